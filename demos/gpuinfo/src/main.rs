@@ -0,0 +1,50 @@
+use chibi_engine::renderer::adapter;
+use chibi_engine::window::WindowSystem;
+
+//
+// gpuinfo - a tiny vulkaninfo-like tool that prints every GPU the engine can see, and why it would (or would not)
+// pick each one. The printed GPU index can be passed to the testbed with `--gpu <index>`.
+//
+// Usage: gpuinfo [--surface] [--extensions] [--gpu <index>]
+//   --surface     create a window so presentation support can be checked
+//   --extensions  list every device extension instead of just the count
+//   --gpu         report which GPU would be selected if <index> was requested
+//
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    let check_surface   = args.iter().any(|arg| arg == "--surface");
+    let show_extensions = args.iter().any(|arg| arg == "--extensions");
+    let gpu_index       = args.iter()
+        .skip_while(|arg| *arg != "--gpu")
+        .nth(1)
+        .and_then(|index| index.parse::<usize>().ok());
+
+    // The window has to outlive adapter enumeration since the surface is created from it.
+    let window_system = if check_surface { Some(WindowSystem::new()) } else { None };
+    let window        = window_system.as_ref().map(|system| system.create_window("gpuinfo", 64, 64));
+
+    let adapters = match adapter::enumerate_adapters(window.as_ref().map(|w| w.get_native_surface())) {
+        Ok(adapters) => adapters,
+        Err(reason)  => {
+            eprintln!("Failed to enumerate GPUs: {}", reason);
+            std::process::exit(1);
+        }
+    };
+
+    let selected = adapter::select_adapter(&adapters, gpu_index);
+    print!("{}", adapter::format_adapter_report(&adapters, selected.as_ref().ok().copied(), show_extensions));
+
+    if !check_surface {
+        println!("Presentation support was not checked. Run with --surface to check it.");
+    }
+
+    match selected {
+        Ok(position) => println!("The engine would select GPU {}: {}", adapters[position].index, adapters[position].name),
+        Err(reason)  => {
+            println!("The engine would fail to select a GPU: {}", reason);
+            std::process::exit(1);
+        }
+    }
+}
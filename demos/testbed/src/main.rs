@@ -11,8 +11,10 @@ use std::str::FromStr;
 use std::io::prelude::*;
 use std::fs::File;
 
+use chibi_engine::animation::{ clip::AnimationClip, gltf::SkinnedModel, pose::AnimationPlayer };
 use chibi_engine::core::engine::*;
 use chibi_engine::core::asset_system::AssetDrive;
+use chibi_engine::font::atlas::{ FontAtlas, FontAtlasSettings };
 use chibi_engine::math::{
     *,
     float2::*,
@@ -25,15 +27,124 @@ use chibi_engine::window::*;
 
 use chibi_engine::renderer::{
     command_buffer::*,
+    culling::GpuCullingSettings,
+    deferred::{ DeferredSettings, RenderPath },
+    environment::SkyboxSettings,
+    lod::{ GeneratedLods, LodGenerationSettings, LodSettings, MeshLod, generate_lods },
     mesh::Vertex,
+    mesh_processing::{ deduplicate_vertices, generate_normals, generate_tangents, optimize_overdraw, optimize_vertex_cache },
+    particles::{ ParticleBlend, ParticleEmitter, ParticleSettings, ParticleSimulation },
+    post_process::{ PostProcessSettings, ColorGradingLut, Tonemapper, DEFAULT_LUT_SIZE },
+    shadows::ShadowSettings,
+    ssao::SsaoSettings,
+    sprite::{ Sprite, SpriteFilter, SpriteSettings, TextureAtlas },
+    text::TextDraw,
+    transparency::{ TransparencyMode, TransparencySettings },
 };
 
+// The font atlas' texture, created in on_init and drawn from every frame
+const FONT_ATLAS_ID: u64 = 1;
+
+// The fence's texture, cut out by its alpha
+const FENCE_ALBEDO_ID: u64 = 2;
+
+// Seconds the tentacle takes to crossfade from one clip to the next
+const CLIP_CROSSFADE_DURATION: f32 = 0.5;
+
 struct Testbed{
-    engine: Rc<Engine>,
-    mesh:   ChibiGeometry,
+    engine:       Rc<Engine>,
+    mesh:         ChibiGeometry,
+    ground_plane: ChibiGeometry, // something for the mesh to cast a shadow onto
+    cube:         ChibiGeometry, // drawn as a field of instances around the mesh
+    fence:        ChibiGeometry, // an alpha-tested panel behind the mesh
+
+    // The renderer copies texture pixels when it processes the command, so they must outlive the submit
+    ground_albedo: Vec<u8>,
+    fence_albedo:  Vec<u8>,
 
     camera: Camera,
 
+    // Debug toggles
+    simulate_device_lost: bool, // F9: ask the renderer to simulate a lost device
+    dump_render_graph:    bool, // F10: write the render graph to render_graph.dot
+    show_debug_lines:     bool, // G: draw a ground grid and the world axes with the DebugDraw API
+
+    // Sprites
+    //   P: toggle a row of pixel-perfect sprites cut from the ground texture
+    sprite_atlas:       TextureAtlas,
+    show_sprites:       bool,
+    show_sprites_dirty: bool,
+
+    // Particles
+    //   K: switch the particle simulation between the GPU and the CPU
+    particle_settings:       ParticleSettings,
+    particle_settings_dirty: bool,
+
+    // Text
+    //   T: toggle a HUD line and a label above the mesh, drawn from an SDF atlas of Roboto
+    font_atlas: Option<FontAtlas>, // None if the font failed to load. The pixels must outlive the texture's submit
+    show_text:  bool,
+
+    // Skeletal animation
+    //   N: crossfade the tentacle to its next clip
+    tentacle:        Option<SkinnedModel>, // None if the model failed to load. The meshes must outlive their submit
+    tentacle_clips:  Vec<Rc<AnimationClip>>,
+    tentacle_clip:   usize, // into tentacle_clips
+    tentacle_player: AnimationPlayer,
+
+    // Transparency
+    //   O: switch the tinted glass panes in front of the mesh between sorted blending and weighted blended OIT
+    transparency_settings:       TransparencySettings,
+    transparency_settings_dirty: bool,
+
+    // Skybox
+    //   B: cycle the skybox blur
+    skybox_settings:       SkyboxSettings,
+    skybox_settings_dirty: bool,
+
+    // Shadow tuning
+    //   F5: cycle the cascade count, F6: cycle the PCF radius,
+    //   [ and ]: lower/raise the cascade split lambda, - and =: lower/raise the depth bias
+    shadow_settings:       ShadowSettings,
+    shadow_settings_dirty: bool,
+
+    // Ambient occlusion
+    //   J: toggle SSAO, H: toggle the AO debug view
+    ssao_settings:       SsaoSettings,
+    ssao_settings_dirty: bool,
+
+    // Deferred shading, with `testbed --deferred`
+    //   V: cycle the G-buffer debug view
+    deferred_settings:       DeferredSettings,
+    deferred_settings_dirty: bool,
+
+    // Mesh LODs, generated when the OBJ files are imported
+    //   L: cycle between picking the LODs by screen size and forcing each LOD
+    lod_settings:       LodSettings,
+    lod_settings_dirty: bool,
+
+    // Point and spot lights
+    //   F7: cycle the number of point lights circling the mesh
+    point_light_count: usize,
+    light_time:        f32,
+
+    // Post-processing
+    //   F1: cycle the tonemapper, F2: bloom, F3: FXAA, F4: vignette, F8: color grading,
+    //   , and .: lower/raise the exposure
+    post_settings:       PostProcessSettings,
+    post_settings_dirty: bool,
+
+    // Anti-aliasing
+    //   F11: cycle the MSAA sample count
+    msaa_samples:       u32,
+    msaa_samples_dirty: bool,
+
+    // GPU-driven geometry
+    //   F12: cycle between GPU frustum + occlusion culling, GPU frustum culling and CPU draws
+    //   Tab: print how many meshes the CPU draws culled last frame
+    culling_settings:       GpuCullingSettings,
+    culling_settings_dirty: bool,
+
     // Event listeners for the window
     //
 
@@ -80,6 +191,7 @@ struct ObjGeometry {
 struct ChibiGeometry {
     vertices: Vec<Vertex>,
     indices:  Vec<u32>,
+    lods:     Vec<MeshLod>, // ranges of indices, empty for a single LOD
 }
 
 fn get_mesh_directory(engine: &Engine) -> PathBuf {
@@ -281,6 +393,7 @@ fn make_vertex_from_face(obj: &ObjGeometry, face: ObjFaceIdx) -> Vertex {
         normal,
         uv_y: tex_coord.y,
         color: Float4::new(0.0, 0.0, 0.0, 1.0),
+        tangent: Float4::zero(),
     };
 }
 
@@ -288,15 +401,15 @@ fn convert_obj_file(obj: &ObjGeometry) -> ChibiGeometry {
     let mut result = ChibiGeometry{
         vertices: Vec::new(),
         indices:  Vec::new(),
+        lods:     Vec::new(),
     };
 
     assert!(obj.positions.len() > 0);
     result.vertices.reserve(obj.positions.len());
 
+    // Every corner gets its own vertex, import_obj_file merges the duplicates
     let mut index_count: u32 = 0;
     for face in &obj.faces {
-        //note: this might result in duplicated geometry
-
         let v0 = make_vertex_from_face(&obj, face.v0);
         let v1 = make_vertex_from_face(&obj, face.v1);
         let v2 = make_vertex_from_face(&obj, face.v2);
@@ -312,15 +425,88 @@ fn convert_obj_file(obj: &ObjGeometry) -> ChibiGeometry {
         index_count += 3;
     }
 
-    for i in 0..obj.positions.len() {
-        let mut vertex = Vertex::new();
-        vertex.position = obj.positions[i];
+    return result;
+}
+
+// A flat, double-sided square facing +y. Both windings are emitted so it is visible from either side.
+fn make_ground_plane(half_size: f32, height: f32) -> ChibiGeometry {
+    let corners: [(f32, f32); 4] = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)];
+
+    let mut result = ChibiGeometry::default();
+    for (x, z) in corners {
+        result.vertices.push(Vertex{
+            position: Float3::new(x * half_size, height, z * half_size),
+            uv_x:     (x + 1.0) * half_size * 0.5,
+            normal:   Float3::new(0.0, 1.0, 0.0),
+            uv_y:     (z + 1.0) * half_size * 0.5,
+            color:    Float4::one(),
+            tangent:  Float4::new(1.0, 0.0, 0.0, 1.0),
+        });
     }
 
+    result.indices = vec![0, 1, 2, 0, 2, 3, 0, 2, 1, 0, 3, 2];
     return result;
 }
 
+// OBJ files without normals get smooth normals across edges flatter than this, in degrees, and flat ones elsewhere
+const OBJ_SMOOTHING_ANGLE: f32 = 60.0;
+
+// How many more vertex cache misses the overdraw order may cost, 1.05 is 5% more
+const OBJ_OVERDRAW_THRESHOLD: f32 = 1.05;
+
+// Meshes whose LODs `testbed --cook-lods` writes next to their OBJ files
+const COOKED_MESHES: [&str; 2] = ["suzanne", "cube"];
+
+// Imports an OBJ file, with the LODs cooked next to it by `testbed --cook-lods`, or generated if they are missing or out of date
 fn import_obj_file(asset_path: &PathBuf, name: &str) -> ChibiGeometry {
+    let mut result = import_obj_geometry(asset_path, name);
+
+    let lods_file = asset_path.join(format!("{}.lods", name));
+    let cooked    = std::fs::read(&lods_file)
+        .ok()
+        .and_then(|bytes| GeneratedLods::from_bytes(&bytes, &result.indices, result.vertices.len()));
+
+    let generated = match cooked {
+        Some(cooked) => cooked,
+        None         => {
+            println!("{} is missing or out of date, generating the LODs, run `testbed --cook-lods` to cook them again", lods_file.display());
+            cook_lods(&result)
+        }
+    };
+
+    result.indices = generated.indices;
+    result.lods    = generated.lods;
+    return result;
+}
+
+// The coarser LODs index the same vertices, after the full mesh's indices
+fn cook_lods(geometry: &ChibiGeometry) -> GeneratedLods {
+    let mut generated = generate_lods(&geometry.vertices, &geometry.indices, &LodGenerationSettings::default());
+
+    for lod in generated.lods.iter().skip(1) {
+        let range = lod.first_index as usize..(lod.first_index + lod.index_count) as usize;
+        optimize_vertex_cache(&mut generated.indices[range], geometry.vertices.len());
+    }
+
+    return generated;
+}
+
+// `testbed --cook-lods` writes the LODs of COOKED_MESHES next to them, and exits without starting the engine
+fn cook_mesh_lods(asset_path: &PathBuf) {
+    for name in COOKED_MESHES {
+        let geometry  = import_obj_geometry(asset_path, name);
+        let generated = cook_lods(&geometry);
+
+        let lods_file = asset_path.join(format!("{}.lods", name));
+        match std::fs::write(&lods_file, generated.to_bytes(geometry.vertices.len())) {
+            Err(why) => panic!("couldn't write {}: {}", lods_file.display(), why),
+            Ok(_)    => println!("Cooked {} LODs into {}", generated.lods.len(), lods_file.display()),
+        }
+    }
+}
+
+// The mesh of an OBJ file, processed for drawing, without LODs
+fn import_obj_geometry(asset_path: &PathBuf, name: &str) -> ChibiGeometry {
     let mut name_str = String::from_str(name).expect("Failed to construct string.");
     name_str.push_str(".obj");
 
@@ -342,7 +528,100 @@ fn import_obj_file(asset_path: &PathBuf, name: &str) -> ChibiGeometry {
     }
 
     let parsed_obj = parse_obj_file(&file_str);
-    return convert_obj_file(&parsed_obj);
+
+    let mut result = convert_obj_file(&parsed_obj);
+    deduplicate_vertices(&mut result.vertices, &mut result.indices);
+
+    if parsed_obj.normals.is_empty() {
+        generate_normals(&mut result.vertices, &mut result.indices, OBJ_SMOOTHING_ANGLE);
+    }
+
+    generate_tangents(&mut result.vertices, &mut result.indices);
+
+    optimize_vertex_cache(&mut result.indices, result.vertices.len());
+    optimize_overdraw(&mut result.indices, &result.vertices, OBJ_OVERDRAW_THRESHOLD);
+
+    return result;
+}
+
+// A grid of small, randomly tinted cubes resting on the ground, leaving the middle free for the mesh
+fn make_cube_field_instances(cubes_per_side: u32, half_size: f32, height: f32) -> Vec<MeshInstance> {
+    const CUBE_SCALE: f32 = 0.12;
+
+    let mut instances = Vec::<MeshInstance>::with_capacity((cubes_per_side * cubes_per_side) as usize);
+    let spacing       = 2.0 * half_size / (cubes_per_side - 1) as f32;
+
+    for z in 0..cubes_per_side {
+        for x in 0..cubes_per_side {
+            let position_x = -half_size + x as f32 * spacing;
+            let position_z = -half_size + z as f32 * spacing;
+
+            if position_x.abs() < 3.0 && position_z.abs() < 3.0 {
+                continue;
+            }
+
+            // A cheap hash of the grid cell, so the tints don't change from run to run
+            let hash = (x.wrapping_mul(73_856_093) ^ z.wrapping_mul(19_349_663)) % 1000;
+            let hue  = hash as f32 / 1000.0 * std::f32::consts::TAU;
+
+            let translate = Float4x4::get_translate_matrix(Float4::new(position_x, height + CUBE_SCALE, position_z, 1.0));
+            let rotate    = Float4x4::get_rotate_y_matrix(hash as f32 * 0.36);
+            let scale     = Float4x4::get_uniform_scale_matrix(CUBE_SCALE);
+
+            instances.push(MeshInstance{
+                transform: mul_rh(translate, mul_rh(rotate, scale)),
+                color:     Float4::new(0.5 + 0.4 * hue.cos(), 0.5 + 0.4 * (hue + 2.094).cos(), 0.5 + 0.4 * (hue + 4.189).cos(), 1.0),
+            });
+        }
+    }
+
+    return instances;
+}
+
+// A grey checkerboard, 8x8 texels per square, stored as sRGB RGBA8
+fn make_checker_texture(size: u32) -> Vec<u8> {
+    let mut pixels = Vec::<u8>::with_capacity((size * size * 4) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let value: u8 = if ((x / 8) + (y / 8)) % 2 == 0 { 180 } else { 110 };
+            pixels.extend_from_slice(&[value, value, value, 255]);
+        }
+    }
+
+    return pixels;
+}
+
+// Wires 2 texels wide every 16 texels, with transparent gaps in between, stored as sRGB RGBA8
+fn make_fence_texture(size: u32) -> Vec<u8> {
+    let mut pixels = Vec::<u8>::with_capacity((size * size * 4) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let is_wire = x % 16 < 2 || y % 16 < 2;
+            pixels.extend_from_slice(if is_wire { &[150, 150, 140, 255] } else { &[0, 0, 0, 0] });
+        }
+    }
+
+    return pixels;
+}
+
+// A warm, slightly punchier grade. Applied to sRGB encoded colors, see ColorGradingLut.
+fn make_warm_color_grading_lut(size: u32) -> ColorGradingLut {
+    let mut lut = ColorGradingLut::identity(size);
+
+    for texel in lut.pixels.chunks_exact_mut(4) {
+        let grade = |value: u8, scale: f32| -> u8 {
+            let x = value as f32 / 255.0;
+            let contrast = x * x * (3.0 - 2.0 * x); // smoothstep, darkens the shadows and lifts the highlights
+            let graded   = (x * 0.6 + contrast * 0.4) * scale;
+            return (graded.clamp(0.0, 1.0) * 255.0).round() as u8;
+        };
+
+        texel[0] = grade(texel[0], 1.06);
+        texel[1] = grade(texel[1], 1.0);
+        texel[2] = grade(texel[2], 0.88);
+    }
+
+    return lut;
 }
 
 impl Game for Testbed {
@@ -365,16 +644,247 @@ impl Game for Testbed {
         //println!("Parsed file:\n{:?}\n\n", self.mesh);
 
         let mut upload_commands = RenderCommandBuffer::default();
+
+        // Materials reference textures, and meshes reference materials, so create them in that order
+        const GROUND_ALBEDO_ID:   u64 = 0;
+        const GOLD_MATERIAL_ID:     u64 = 0;
+        const GROUND_MATERIAL_ID:   u64 = 1;
+        const TENTACLE_MATERIAL_ID: u64 = 2;
+        const FENCE_MATERIAL_ID:    u64 = 3;
+        const GLASS_MATERIAL_ID:    u64 = 4; // one per pane, each with its own tint
+
+        self.ground_albedo = make_checker_texture(64);
+
+        upload_commands.add_command(RenderCommand::CreateTexture(CreateTextureInfo{
+            pixels:    self.ground_albedo.as_ptr(),
+            width:     64,
+            height:    64,
+            format:    TextureFormat::Rgba8Srgb,
+            engine_id: GROUND_ALBEDO_ID,
+        }));
+
+        // 8x8 texel cells of the checker, each one a single square
+        self.sprite_atlas = TextureAtlas::from_grid(GROUND_ALBEDO_ID, 64, 64, 8, 8);
+
+        upload_commands.add_command(RenderCommand::UpdateSpriteSettings(SpriteSettings{ filter: SpriteFilter::Nearest }));
+        upload_commands.add_command(RenderCommand::UpdateSpriteCamera(Float4x4::get_orthographic_2d_matrix(960.0, 540.0, 1920.0, 1080.0)));
+
+        self.font_atlas = match self.engine.load_font(AssetDrive::Res, "fonts/Roboto-Medium.ttf") {
+            Ok(font)   => FontAtlas::new(&font, &FontAtlasSettings::default()).map_err(|error| println!("[WARN] :: Testbed :: {}", error)).ok(),
+            Err(error) => { println!("[WARN] :: Testbed :: {}", error); None },
+        };
+
+        if let Some(atlas) = &self.font_atlas {
+            upload_commands.add_command(RenderCommand::CreateTexture(CreateTextureInfo{
+                pixels:    atlas.pixels.as_ptr(),
+                width:     atlas.width,
+                height:    atlas.height,
+                format:    TextureFormat::Rgba8Unorm,
+                engine_id: FONT_ATLAS_ID,
+            }));
+        }
+
+        upload_commands.add_command(RenderCommand::CreateMaterial(CreateMaterialInfo{
+            base_color_factor: Float4::new(1.0, 0.77, 0.34, 1.0),
+            metallic_factor:   1.0,
+            roughness_factor:  0.35,
+            engine_id:         GOLD_MATERIAL_ID,
+            ..Default::default()
+        }));
+
+        upload_commands.add_command(RenderCommand::CreateMaterial(CreateMaterialInfo{
+            roughness_factor: 0.8,
+            albedo_texture:   Some(GROUND_ALBEDO_ID),
+            engine_id:        GROUND_MATERIAL_ID,
+            ..Default::default()
+        }));
+
         let mesh_info = CreateMeshInfo{
             vertices:     self.mesh.vertices.as_ptr(),
             vertex_count: self.mesh.vertices.len(),
             indices:      self.mesh.indices.as_ptr(),
             index_count:  self.mesh.indices.len(),
+            lods:         self.mesh.lods.clone(),
             transform:    Float4x4::get_rotate_z_matrix(180.0),
+            material_id:  Some(GOLD_MATERIAL_ID),
             engine_id:    0,
         };
 
         upload_commands.add_command(RenderCommand::CreateMesh(mesh_info));
+
+        self.ground_plane = make_ground_plane(10.0, -1.5);
+
+        let ground_info = CreateMeshInfo{
+            vertices:     self.ground_plane.vertices.as_ptr(),
+            vertex_count: self.ground_plane.vertices.len(),
+            indices:      self.ground_plane.indices.as_ptr(),
+            index_count:  self.ground_plane.indices.len(),
+            lods:         self.ground_plane.lods.clone(),
+            transform:    Float4x4::identity(),
+            material_id:  Some(GROUND_MATERIAL_ID),
+            engine_id:    1,
+        };
+
+        upload_commands.add_command(RenderCommand::CreateMesh(ground_info));
+
+        // A field of small cubes, all drawn with a single instanced draw
+        self.cube = import_obj_file(&mesh_dir, "cube");
+
+        let cube_info = CreateInstancedMeshInfo{
+            vertices:     self.cube.vertices.as_ptr(),
+            vertex_count: self.cube.vertices.len(),
+            indices:      self.cube.indices.as_ptr(),
+            index_count:  self.cube.indices.len(),
+            lods:         self.cube.lods.clone(),
+            instances:    make_cube_field_instances(32, 9.0, -1.5),
+            material_id:  None,
+            engine_id:    2,
+        };
+
+        upload_commands.add_command(RenderCommand::CreateInstancedMesh(cube_info));
+
+        // A wire fence behind the mesh, the gaps between the wires are cut out of the texture's alpha
+        self.fence_albedo = make_fence_texture(64);
+        self.fence        = make_ground_plane(1.5, 0.0);
+
+        upload_commands.add_command(RenderCommand::CreateTexture(CreateTextureInfo{
+            pixels:    self.fence_albedo.as_ptr(),
+            width:     64,
+            height:    64,
+            format:    TextureFormat::Rgba8Srgb,
+            engine_id: FENCE_ALBEDO_ID,
+        }));
+
+        upload_commands.add_command(RenderCommand::CreateMaterial(CreateMaterialInfo{
+            metallic_factor:  0.8,
+            roughness_factor: 0.5,
+            albedo_texture:   Some(FENCE_ALBEDO_ID),
+            alpha_mode:       AlphaMode::Mask,
+            alpha_cutoff:     0.5,
+            engine_id:        FENCE_MATERIAL_ID,
+            ..Default::default()
+        }));
+
+        upload_commands.add_command(RenderCommand::CreateMesh(CreateMeshInfo{
+            vertices:     self.fence.vertices.as_ptr(),
+            vertex_count: self.fence.vertices.len(),
+            indices:      self.fence.indices.as_ptr(),
+            index_count:  self.fence.indices.len(),
+            lods:         self.fence.lods.clone(),
+            transform:    mul_rh(Float4x4::get_translate_matrix(Float4::new(0.0, 0.0, -4.5, 1.0)), Float4x4::get_rotate_x_matrix(90.0)),
+            material_id:  Some(FENCE_MATERIAL_ID),
+            engine_id:    3,
+        }));
+
+        // Three overlapping panes of tinted glass in front of the mesh, drawn in the transparent pass
+        let glass_tints = [Float4::new(1.0, 0.2, 0.2, 0.35), Float4::new(0.2, 1.0, 0.2, 0.35), Float4::new(0.2, 0.4, 1.0, 0.35)];
+
+        for (pane, tint) in glass_tints.iter().enumerate() {
+            let offset    = pane as f32 - 1.0;
+            let translate = Float4x4::get_translate_matrix(Float4::new(offset * 0.6, -0.5, 2.0 + offset * 0.4, 1.0));
+            let scale     = Float4x4::get_scale_matrix(0.6, 0.9, 0.02);
+
+            upload_commands.add_command(RenderCommand::CreateMaterial(CreateMaterialInfo{
+                base_color_factor: *tint,
+                roughness_factor:  0.05,
+                alpha_mode:        AlphaMode::Blend,
+                engine_id:         GLASS_MATERIAL_ID + pane as u64,
+                ..Default::default()
+            }));
+
+            upload_commands.add_command(RenderCommand::CreateMesh(CreateMeshInfo{
+                vertices:     self.cube.vertices.as_ptr(),
+                vertex_count: self.cube.vertices.len(),
+                indices:      self.cube.indices.as_ptr(),
+                index_count:  self.cube.indices.len(),
+                lods:         self.cube.lods.clone(),
+                transform:    mul_rh(translate, scale),
+                material_id:  Some(GLASS_MATERIAL_ID + pane as u64),
+                engine_id:    4 + pane as u64,
+            }));
+        }
+
+        // A skinned tentacle behind the mesh, swaying through the clips of its glTF file
+        self.tentacle = match self.engine.load_skinned_model(AssetDrive::Res, "models/tentacle.glb") {
+            Ok(model)  => Some(model),
+            Err(error) => { println!("[WARN] :: Testbed :: {}", error); None },
+        };
+
+        if let Some(tentacle) = &self.tentacle {
+            upload_commands.add_command(RenderCommand::CreateMaterial(CreateMaterialInfo{
+                base_color_factor: Float4::new(0.2, 0.6, 0.55, 1.0),
+                roughness_factor:  0.4,
+                engine_id:         TENTACLE_MATERIAL_ID,
+                ..Default::default()
+            }));
+
+            for (engine_id, mesh) in tentacle.meshes.iter().enumerate() {
+                upload_commands.add_command(RenderCommand::CreateSkinnedMesh(CreateSkinnedMeshInfo{
+                    vertices:     mesh.vertices.as_ptr(),
+                    skin:         mesh.skin.as_ptr(),
+                    vertex_count: mesh.vertices.len(),
+                    indices:      mesh.indices.as_ptr(),
+                    index_count:  mesh.indices.len(),
+                    joint_count:  tentacle.skeleton.get_joint_count(),
+                    transform:    Float4x4::get_translate_matrix(Float4::new(0.0, -1.5, -2.5, 1.0)),
+                    material_id:  Some(TENTACLE_MATERIAL_ID),
+                    engine_id:    engine_id as u64,
+                }));
+            }
+
+            self.tentacle_clips = tentacle.clips.iter().map(|clip| Rc::new(clip.clone())).collect();
+            if let Some(clip) = self.tentacle_clips.first() {
+                self.tentacle_player.play(clip.clone(), true);
+            }
+        }
+
+        // Sparks shooting up on one side of the mesh, smoke drifting up on the other
+        upload_commands.add_command(RenderCommand::CreateParticleEmitter(ParticleEmitter{
+            engine_id:     0,
+            position:      Float3::new(3.0, -1.5, 0.0),
+            max_particles: 2048,
+            spawn_rate:    600.0,
+            lifetime_min:  1.0,
+            lifetime_max:  2.5,
+            cone_angle:    0.35,
+            speed_min:     4.0,
+            speed_max:     6.0,
+            color_start:   Float4::new(8.0, 3.0, 0.6, 1.0),
+            color_end:     Float4::new(2.0, 0.2, 0.0, 0.0),
+            size_start:    0.08,
+            size_end:      0.02,
+            blend:         ParticleBlend::Additive,
+            ..Default::default()
+        }));
+
+        upload_commands.add_command(RenderCommand::CreateParticleEmitter(ParticleEmitter{
+            engine_id:     1,
+            position:      Float3::new(-3.0, -1.5, 0.0),
+            max_particles: 512,
+            spawn_rate:    60.0,
+            lifetime_min:  3.0,
+            lifetime_max:  5.0,
+            cone_angle:    0.25,
+            speed_min:     0.5,
+            speed_max:     1.0,
+            gravity:       Float3::new(0.2, 0.1, 0.0),
+            color_start:   Float4::new(0.4, 0.4, 0.4, 0.6),
+            color_end:     Float4::new(0.6, 0.6, 0.6, 0.0),
+            size_start:    0.3,
+            size_end:      1.5,
+            blend:         ParticleBlend::AlphaBlend,
+            ..Default::default()
+        }));
+
+        // A procedural sky with a bright sun, for the skybox and the image-based lighting
+        match self.engine.load_environment_map(AssetDrive::Res, "environments/sky.hdr") {
+            Ok(environment) => upload_commands.add_command(RenderCommand::UpdateEnvironment(environment)),
+            Err(error)      => println!("[WARN] :: Testbed :: {}", error),
+        }
+
+        // Only used once color grading is enabled with F8
+        upload_commands.add_command(RenderCommand::UpdateColorGradingLut(make_warm_color_grading_lut(DEFAULT_LUT_SIZE)));
+
         self.engine.submit_render_command_buffer(upload_commands);
 
         return true;
@@ -384,6 +894,123 @@ impl Game for Testbed {
         while let Ok(ev) = self.event_reciever.try_recv() {
             match ev {
                 WindowEvent::KeyPress(key_event)         => {
+                    if key_event.key == KeyboardKey::F9 && key_event.state == KeyState::Pressed {
+                        self.simulate_device_lost = true;
+                    }
+
+                    if key_event.key == KeyboardKey::F10 && key_event.state == KeyState::Pressed {
+                        self.dump_render_graph = true;
+                    }
+
+                    if key_event.key == KeyboardKey::F7 && key_event.state == KeyState::Pressed {
+                        self.point_light_count = match self.point_light_count { 0 => 16, 16 => 64, 64 => 256, _ => 0 };
+                        println!("[INFO] :: Testbed :: Point lights: {}", self.point_light_count);
+                    }
+
+                    if key_event.key == KeyboardKey::F11 && key_event.state == KeyState::Pressed {
+                        self.msaa_samples       = match self.msaa_samples { 1 => 2, 2 => 4, 4 => 8, _ => 1 };
+                        self.msaa_samples_dirty = true;
+                        println!("[INFO] :: Testbed :: MSAA samples: {}", self.msaa_samples);
+                    }
+
+                    if key_event.key == KeyboardKey::F12 && key_event.state == KeyState::Pressed {
+                        let settings = &mut self.culling_settings;
+                        (settings.enabled, settings.occlusion_culling) = match (settings.enabled, settings.occlusion_culling) {
+                            (true, true)  => (true, false),
+                            (true, false) => (false, false),
+                            _             => (true, true),
+                        };
+
+                        self.culling_settings_dirty = true;
+                        println!("[INFO] :: Testbed :: GPU culling: {}, occlusion culling: {}", settings.enabled, settings.occlusion_culling);
+                    }
+
+                    if key_event.key == KeyboardKey::G && key_event.state == KeyState::Pressed {
+                        self.show_debug_lines = !self.show_debug_lines;
+                    }
+
+                    if key_event.key == KeyboardKey::P && key_event.state == KeyState::Pressed {
+                        self.show_sprites       = !self.show_sprites;
+                        self.show_sprites_dirty = true;
+                    }
+
+                    if key_event.key == KeyboardKey::K && key_event.state == KeyState::Pressed {
+                        self.particle_settings.simulation = match self.particle_settings.simulation {
+                            ParticleSimulation::Gpu => ParticleSimulation::Cpu,
+                            ParticleSimulation::Cpu => ParticleSimulation::Gpu,
+                        };
+
+                        self.particle_settings_dirty = true;
+                        println!("[INFO] :: Testbed :: Particle simulation: {:?}", self.particle_settings.simulation);
+                    }
+
+                    if key_event.key == KeyboardKey::T && key_event.state == KeyState::Pressed {
+                        self.show_text = !self.show_text;
+                    }
+
+                    if key_event.key == KeyboardKey::O && key_event.state == KeyState::Pressed {
+                        self.transparency_settings.mode = match self.transparency_settings.mode {
+                            TransparencyMode::Sorted          => TransparencyMode::WeightedBlended,
+                            TransparencyMode::WeightedBlended => TransparencyMode::Sorted,
+                        };
+
+                        self.transparency_settings_dirty = true;
+                        println!("[INFO] :: Testbed :: Transparency: {:?}", self.transparency_settings.mode);
+                    }
+
+                    if key_event.key == KeyboardKey::J && key_event.state == KeyState::Pressed {
+                        self.ssao_settings.enabled = !self.ssao_settings.enabled;
+                        self.ssao_settings_dirty   = true;
+                        println!("[INFO] :: Testbed :: SSAO: {}", self.ssao_settings.enabled);
+                    }
+
+                    if key_event.key == KeyboardKey::H && key_event.state == KeyState::Pressed {
+                        self.ssao_settings.debug_view = !self.ssao_settings.debug_view;
+                        self.ssao_settings_dirty      = true;
+                    }
+
+                    if key_event.key == KeyboardKey::V && key_event.state == KeyState::Pressed {
+                        self.deferred_settings.debug_view = self.deferred_settings.debug_view.next();
+                        self.deferred_settings_dirty      = true;
+                        println!("[INFO] :: Testbed :: G-buffer view: {:?}", self.deferred_settings.debug_view);
+                    }
+
+                    if key_event.key == KeyboardKey::L && key_event.state == KeyState::Pressed {
+                        let lod_count = self.mesh.lods.len().max(1) as u32;
+                        self.lod_settings.forced_lod = match self.lod_settings.forced_lod {
+                            None                             => Some(0),
+                            Some(lod) if lod + 1 < lod_count => Some(lod + 1),
+                            Some(_)                          => None,
+                        };
+
+                        self.lod_settings_dirty = true;
+                        println!("[INFO] :: Testbed :: Forced LOD: {:?} (the mesh has {})", self.lod_settings.forced_lod, lod_count);
+                    }
+
+                    if key_event.key == KeyboardKey::B && key_event.state == KeyState::Pressed {
+                        self.skybox_settings.blur  = if self.skybox_settings.blur >= 0.75 { 0.0 } else { self.skybox_settings.blur + 0.25 };
+                        self.skybox_settings_dirty = true;
+                        println!("[INFO] :: Testbed :: Skybox blur: {}", self.skybox_settings.blur);
+                    }
+
+                    if key_event.key == KeyboardKey::N && key_event.state == KeyState::Pressed && !self.tentacle_clips.is_empty() {
+                        self.tentacle_clip = (self.tentacle_clip + 1) % self.tentacle_clips.len();
+
+                        let clip = self.tentacle_clips[self.tentacle_clip].clone();
+                        println!("[INFO] :: Testbed :: Tentacle clip: {}", clip.name);
+                        self.tentacle_player.crossfade(clip, true, CLIP_CROSSFADE_DURATION);
+                    }
+
+                    if key_event.key == KeyboardKey::Tab && key_event.state == KeyState::Pressed {
+                        let stats = self.engine.get_culling_stats();
+                        println!("[INFO] :: Testbed :: Meshes drawn: {}, culled: {}", stats.meshes_drawn, stats.meshes_culled);
+                    }
+
+                    if key_event.state == KeyState::Pressed {
+                        self.on_shadow_tuning_key(key_event.key);
+                        self.on_post_process_key(key_event.key);
+                    }
+
                     self.camera.on_key_event(key_event);
                 },
                 WindowEvent::MousePress(mouse_event)     => {
@@ -406,8 +1033,97 @@ impl Game for Testbed {
 
         let mut render_commands = RenderCommandBuffer::default();
         render_commands.add_command(camera_info);
+
+        if self.simulate_device_lost {
+            self.simulate_device_lost = false;
+            render_commands.add_command(RenderCommand::DebugSimulateDeviceLost);
+        }
+
+        if self.dump_render_graph {
+            self.dump_render_graph = false;
+            render_commands.add_command(RenderCommand::DebugDumpRenderGraph(PathBuf::from("render_graph.dot")));
+        }
+
+        if self.shadow_settings_dirty {
+            self.shadow_settings_dirty = false;
+            render_commands.add_command(RenderCommand::UpdateShadowSettings(self.shadow_settings));
+        }
+
+        if self.post_settings_dirty {
+            self.post_settings_dirty = false;
+            render_commands.add_command(RenderCommand::UpdatePostProcessSettings(self.post_settings));
+        }
+
+        if self.msaa_samples_dirty {
+            self.msaa_samples_dirty = false;
+            render_commands.add_command(RenderCommand::UpdateMsaaSampleCount(self.msaa_samples));
+        }
+
+        if self.culling_settings_dirty {
+            self.culling_settings_dirty = false;
+            render_commands.add_command(RenderCommand::UpdateGpuCullingSettings(self.culling_settings));
+        }
+
+        if self.skybox_settings_dirty {
+            self.skybox_settings_dirty = false;
+            render_commands.add_command(RenderCommand::UpdateSkyboxSettings(self.skybox_settings));
+        }
+
+        if self.ssao_settings_dirty {
+            self.ssao_settings_dirty = false;
+            render_commands.add_command(RenderCommand::UpdateSsaoSettings(self.ssao_settings));
+        }
+
+        if self.deferred_settings_dirty {
+            self.deferred_settings_dirty = false;
+            render_commands.add_command(RenderCommand::UpdateDeferredSettings(self.deferred_settings));
+        }
+
+        if self.lod_settings_dirty {
+            self.lod_settings_dirty = false;
+            render_commands.add_command(RenderCommand::UpdateLodSettings(self.lod_settings));
+        }
+
+        if self.transparency_settings_dirty {
+            self.transparency_settings_dirty = false;
+            render_commands.add_command(RenderCommand::UpdateTransparencySettings(self.transparency_settings));
+        }
+
+        if self.particle_settings_dirty {
+            self.particle_settings_dirty = false;
+            render_commands.add_command(RenderCommand::UpdateParticleSettings(self.particle_settings));
+        }
+
+        if self.show_sprites_dirty {
+            self.show_sprites_dirty = false;
+            render_commands.add_command(RenderCommand::UpdateSprites(if self.show_sprites { self.make_sprites() } else { Vec::new() }));
+        }
+
+        self.light_time += 1.0 / 60.0;
+        render_commands.add_command(RenderCommand::UpdatePointLights(self.make_point_lights()));
+        render_commands.add_command(RenderCommand::UpdateSpotLights(self.make_spot_lights()));
+        render_commands.add_command(RenderCommand::UpdateText(self.make_text()));
+
+        if let Some(tentacle) = &self.tentacle {
+            self.tentacle_player.update(1.0 / 60.0);
+
+            let joint_matrices = self.tentacle_player.evaluate(&tentacle.skeleton).get_joint_matrices(&tentacle.skeleton);
+            for engine_id in 0..tentacle.meshes.len() {
+                render_commands.add_command(RenderCommand::UpdateJointMatrices(engine_id as u64, joint_matrices.clone()));
+            }
+        }
+
         self.engine.submit_render_command_buffer(render_commands);
 
+        if self.show_debug_lines {
+            let mut debug_draw = self.engine.debug_draw();
+            debug_draw.grid(Float3::zero(), 1.0, 20, Float4::new(0.5, 0.5, 0.5, 1.0), None);
+
+            debug_draw.set_depth_test(false);
+            debug_draw.axes(Float4x4::identity(), 2.0, None);
+            debug_draw.set_depth_test(true);
+        }
+
         return true;
     }
 
@@ -415,40 +1131,234 @@ impl Game for Testbed {
     fn on_shutdown(&mut self) -> bool { return true; }
 }
 
+impl Testbed {
+    // A row of squares along the bottom of the screen, alternating between the light and dark checker cells
+    fn make_sprites(&self) -> Vec<Sprite> {
+        let mut sprites = Vec::<Sprite>::new();
+
+        for index in 0..8 {
+            let cell     = if index % 2 == 0 { "0" } else { "1" };
+            let position = Float2::new(96.0 + index as f32 * 80.0, 96.0);
+
+            if let Some(mut sprite) = self.sprite_atlas.make_sprite(cell, position, Float2::new(64.0, 64.0)) {
+                sprite.rotation = index as f32 * 0.2;
+                sprite.layer    = index;
+                sprites.push(sprite);
+            }
+        }
+
+        return sprites;
+    }
+
+    // A HUD line in the top-left corner and a label floating above the mesh
+    fn make_text(&self) -> Vec<TextDraw> {
+        let Some(atlas) = &self.font_atlas else {
+            return Vec::new();
+        };
+
+        if !self.show_text {
+            return Vec::new();
+        }
+
+        let hud = format!("Point lights: {}  MSAA: {}x", self.point_light_count, self.msaa_samples);
+
+        let label        = "Suzanne";
+        let label_height = 0.5;
+        let label_width  = atlas.measure_text(label).x * label_height / atlas.pixel_size;
+
+        return vec![
+            TextDraw::screen(atlas, FONT_ATLAS_ID, &hud, Float2::new(16.0, 16.0), 24.0, Float4::one()),
+            TextDraw::world(atlas, FONT_ATLAS_ID, label, Float3::new(-0.5 * label_width, 1.8, 0.0), Float3::new(1.0, 0.0, 0.0), Float3::new(0.0, 1.0, 0.0), label_height, Float4::new(1.0, 0.85, 0.4, 1.0)),
+        ];
+    }
+
+    fn on_shadow_tuning_key(&mut self, key: KeyboardKey) {
+        let settings = &mut self.shadow_settings;
+
+        match key {
+            KeyboardKey::F5           => settings.cascade_count       = settings.cascade_count % 4 + 1,
+            KeyboardKey::F6           => settings.pcf_radius          = (settings.pcf_radius + 1) % 4,
+            KeyboardKey::LeftBracket  => settings.split_lambda        = (settings.split_lambda - 0.05).max(0.0),
+            KeyboardKey::RightBracket => settings.split_lambda        = (settings.split_lambda + 0.05).min(1.0),
+            KeyboardKey::Minus        => settings.depth_bias_constant = (settings.depth_bias_constant - 0.25).max(0.0),
+            KeyboardKey::Equal        => settings.depth_bias_constant = settings.depth_bias_constant + 0.25,
+            _                         => return,
+        }
+
+        println!("[INFO] :: Testbed :: Shadow settings: {:?}", settings);
+        self.shadow_settings_dirty = true;
+    }
+
+    fn on_post_process_key(&mut self, key: KeyboardKey) {
+        let settings = &mut self.post_settings;
+
+        match key {
+            KeyboardKey::F1     => settings.tonemapper = match settings.tonemapper {
+                Tonemapper::Aces => Tonemapper::AgX,
+                Tonemapper::AgX  => Tonemapper::None,
+                Tonemapper::None => Tonemapper::Aces,
+            },
+            KeyboardKey::F2     => settings.bloom_enabled         = !settings.bloom_enabled,
+            KeyboardKey::F3     => settings.fxaa_enabled          = !settings.fxaa_enabled,
+            KeyboardKey::F4     => settings.vignette_enabled      = !settings.vignette_enabled,
+            KeyboardKey::F8     => settings.color_grading_enabled = !settings.color_grading_enabled,
+            KeyboardKey::Comma  => settings.exposure_ev           = settings.exposure_ev - 0.25,
+            KeyboardKey::Period => settings.exposure_ev           = settings.exposure_ev + 0.25,
+            _                   => return,
+        }
+
+        println!("[INFO] :: Testbed :: Post-process settings: {:?}", settings);
+        self.post_settings_dirty = true;
+    }
+
+    // Rings of colored lights skimming the ground plane, circling the mesh
+    fn make_point_lights(&self) -> Vec<PointLightInfo> {
+        let mut lights = Vec::<PointLightInfo>::with_capacity(self.point_light_count);
+
+        for i in 0..self.point_light_count {
+            let t      = i as f32 / self.point_light_count as f32;
+            let ring   = (i % 4) as f32;
+            let radius = 2.5 + ring * 1.75;
+            let angle  = t * std::f32::consts::TAU + self.light_time * (0.5 - ring * 0.1);
+
+            // Spread the hues around the color wheel
+            let hue   = t * std::f32::consts::TAU;
+            let color = Float3::new(
+                0.5 + 0.5 * hue.cos(),
+                0.5 + 0.5 * (hue + 2.094).cos(),
+                0.5 + 0.5 * (hue + 4.189).cos(),
+            );
+
+            lights.push(PointLightInfo{
+                position:  Float3::new(radius * angle.cos(), -1.2, radius * angle.sin()),
+                range:     2.5,
+                color,
+                intensity: 1.0,
+            });
+        }
+
+        return lights;
+    }
+
+    // A spot light sweeping across the ground in front of the mesh
+    fn make_spot_lights(&self) -> Vec<SpotLightInfo> {
+        let sweep = (self.light_time * 0.7).sin() * 0.6;
+
+        return vec![SpotLightInfo{
+            position:         Float3::new(0.0, 3.0, 3.0),
+            direction:        Float3::new(sweep, -1.0, -0.5),
+            range:            12.0,
+            color:            Float3::new(1.0, 0.9, 0.7),
+            intensity:        20.0,
+            inner_cone_angle: 15.0,
+            outer_cone_angle: 25.0,
+        }];
+    }
+}
+
 impl Default for ChibiGeometry {
     fn default() -> Self {
         Self{
             vertices: Vec::new(),
             indices:  Vec::new(),
+            lods:     Vec::new(),
         }
     }
 }
 
 fn get_info() -> GameInfo {
+    // Allow overriding the GPU from the command line: `testbed --gpu <index>`. Run gpuinfo to list the indices.
+    let gpu_index = std::env::args()
+        .skip_while(|arg| arg != "--gpu")
+        .nth(1)
+        .and_then(|index| index.parse::<usize>().ok());
+
+    // `testbed --hdr` presents in HDR10 when the display supports it
+    let prefer_hdr = std::env::args().any(|arg| arg == "--hdr");
+
+    // `testbed --deferred` shades the opaque geometry with the deferred path instead of the forward one
+    let render_path = if std::env::args().any(|arg| arg == "--deferred") { RenderPath::Deferred } else { RenderPath::Forward };
+
     GameInfo{
         title:         String::from("Chibi EngineTestbed"),
         game_version:  chibi_engine::make_app_version(0, 0, 1),
         window_width:  1920,
         window_height: 1080,
         manifest_dir:  PathBuf::from(env!("CARGO_MANIFEST_DIR")),
+        gpu_index,
+        prefer_hdr,
+        render_path,
     }
 }
 
 fn main() {
-    let chibi_engine = chibi_engine::new_engine(get_info());
+    if std::env::args().any(|arg| arg == "--cook-lods") {
+        cook_mesh_lods(&PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets").join("geometry"));
+        return;
+    }
+
+    let chibi_engine = match chibi_engine::new_engine(get_info()) {
+        Ok(engine) => engine,
+        Err(error) => {
+            eprintln!("Failed to start the engine: {}", error);
+            std::process::exit(1);
+        }
+    };
 
     let (listener, reciever) = chibi_engine::window::make_event_channels();
 
     let testbed = Box::new(Testbed{
-        engine:         chibi_engine.clone(),
-        mesh:           ChibiGeometry::default(),
-        camera:         Camera::default(),
-        event_listener: listener,
-        event_reciever: reciever,
+        engine:                 chibi_engine.clone(),
+        mesh:                   ChibiGeometry::default(),
+        ground_plane:           ChibiGeometry::default(),
+        cube:                   ChibiGeometry::default(),
+        fence:                  ChibiGeometry::default(),
+        ground_albedo:          Vec::new(),
+        fence_albedo:           Vec::new(),
+        camera:                 Camera::default(),
+        simulate_device_lost:   false,
+        dump_render_graph:      false,
+        show_debug_lines:       false,
+        sprite_atlas:           TextureAtlas::new(0, 0, 0), // replaced once the ground texture is created
+        show_sprites:           false,
+        show_sprites_dirty:     false,
+        particle_settings:       ParticleSettings::default(),
+        particle_settings_dirty: false,
+        font_atlas:             None,
+        show_text:              true,
+        tentacle:               None,
+        tentacle_clips:         Vec::new(),
+        tentacle_clip:          0,
+        tentacle_player:        AnimationPlayer::new(),
+        transparency_settings:       TransparencySettings::default(),
+        transparency_settings_dirty: false,
+        skybox_settings:        SkyboxSettings::default(),
+        skybox_settings_dirty:  false,
+        shadow_settings:        ShadowSettings::default(),
+        shadow_settings_dirty:  false,
+        ssao_settings:          SsaoSettings::default(),
+        ssao_settings_dirty:    false,
+        deferred_settings:       DeferredSettings::default(),
+        deferred_settings_dirty: false,
+        lod_settings:           LodSettings::default(),
+        lod_settings_dirty:     false,
+        point_light_count:      64,
+        light_time:             0.0,
+        post_settings:          PostProcessSettings::default(),
+        post_settings_dirty:    false,
+        msaa_samples:           4,
+        msaa_samples_dirty:     false,
+        culling_settings:       GpuCullingSettings::default(),
+        culling_settings_dirty: false,
+        event_listener:         listener,
+        event_reciever:         reciever,
     });
 
     chibi_engine.register_game(testbed);
-    chibi_engine.run();
+    if let Err(error) = chibi_engine.run() {
+        eprintln!("The engine stopped with an error: {}", error);
+        std::process::exit(1);
+    }
 }
 
 struct Camera {
@@ -11,6 +11,7 @@ struct Vertex {
 	vec3 normal;
 	float uv_y;
 	vec4 color;
+	vec4 tangent; // w: handedness of the bitangent
 };
 
 layout(buffer_reference, std430) readonly buffer VertexBuffer{
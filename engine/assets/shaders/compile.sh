@@ -54,10 +54,66 @@ fi
 # Syntax:
 # glslang [option]... [file]...
 
-glslang --target-env vulkan1.3 --glsl-version 460 -o "$outdir/gradient.comp.spv"       "$srcdir/gradient.comp"
-glslang --target-env vulkan1.3 --glsl-version 460 -o "$outdir/gradient_color.comp.spv" "$srcdir/gradient_color.comp"
-glslang --target-env vulkan1.3 --glsl-version 460 -o "$outdir/sky.comp.spv"            "$srcdir/sky.comp"
+# Skybox, drawn behind the geometry from the environment cubemap
+glslang --target-env vulkan1.3 --glsl-version 460 -o "$outdir/skybox.comp.spv" "$srcdir/skybox.comp"
+
+# Assigns point and spot lights to the clusters of the view frustum
+glslang --target-env vulkan1.3 --glsl-version 460 -o "$outdir/light_cull.comp.spv"     "$srcdir/light_cull.comp"
+
+# GPU-driven geometry: frustum and Hi-Z occlusion culling into indirect draws, and the Hi-Z pyramid build
+glslang --target-env vulkan1.3 --glsl-version 460 -o "$outdir/draw_cull.comp.spv"      "$srcdir/draw_cull.comp"
+glslang --target-env vulkan1.3 --glsl-version 460 -o "$outdir/hiz_build.comp.spv"      "$srcdir/hiz_build.comp"
+
+# Screen-space ambient occlusion: the half-resolution AO, its bilateral blur and the pass that applies it
+glslang --target-env vulkan1.3 --glsl-version 460 -o "$outdir/ssao.comp.spv"       "$srcdir/ssao.comp"
+glslang --target-env vulkan1.3 --glsl-version 460 -o "$outdir/ssao_blur.comp.spv"  "$srcdir/ssao_blur.comp"
+glslang --target-env vulkan1.3 --glsl-version 460 -o "$outdir/ssao_apply.comp.spv" "$srcdir/ssao_apply.comp"
+
+# HDR post-processing: bloom, tonemapping and FXAA
+glslang --target-env vulkan1.3 --glsl-version 460 -o "$outdir/bloom_downsample.comp.spv" "$srcdir/bloom_downsample.comp"
+glslang --target-env vulkan1.3 --glsl-version 460 -o "$outdir/bloom_upsample.comp.spv"   "$srcdir/bloom_upsample.comp"
+glslang --target-env vulkan1.3 --glsl-version 460 -o "$outdir/tonemap.comp.spv"          "$srcdir/tonemap.comp"
+glslang --target-env vulkan1.3 --glsl-version 460 -o "$outdir/fxaa.comp.spv"             "$srcdir/fxaa.comp"
+
+# Particles, simulated in a compute pass and drawn into the HDR scene
+glslang --target-env vulkan1.3 --glsl-version 460 -o "$outdir/particle_sim.comp.spv" "$srcdir/particle_sim.comp"
+glslang --target-env vulkan1.3 --glsl-version 460 -o "$outdir/particle.vert.spv"     "$srcdir/particle.vert"
+glslang --target-env vulkan1.3 --glsl-version 460 -o "$outdir/particle.frag.spv"     "$srcdir/particle.frag"
+
+# Sprites, drawn over the tonemapped image
+glslang --target-env vulkan1.3 --glsl-version 460 -o "$outdir/sprite.vert.spv" "$srcdir/sprite.vert"
+glslang --target-env vulkan1.3 --glsl-version 460 -o "$outdir/sprite.frag.spv" "$srcdir/sprite.frag"
+
+# Screen- and world-space text, drawn over the tonemapped image
+glslang --target-env vulkan1.3 --glsl-version 460 -o "$outdir/text.vert.spv" "$srcdir/text.vert"
+glslang --target-env vulkan1.3 --glsl-version 460 -o "$outdir/text.frag.spv" "$srcdir/text.frag"
+
+# Debug lines, drawn over the tonemapped image
+glslang --target-env vulkan1.3 --glsl-version 460 -o "$outdir/debug_line.vert.spv" "$srcdir/debug_line.vert"
+glslang --target-env vulkan1.3 --glsl-version 460 -o "$outdir/debug_line.frag.spv" "$srcdir/debug_line.frag"
 
 # Example colored triangle with hardcoded vertices
 glslang --target-env vulkan1.3 --glsl-version 450 -o "$outdir/colored_triangle.vert.spv" "$srcdir/colored_triangle.vert"
 glslang --target-env vulkan1.3 --glsl-version 450 -o "$outdir/colored_triangle.frag.spv" "$srcdir/colored_triangle.frag"
+
+# Lit mesh shaders, sun lighting with cascaded shadow maps
+glslang --target-env vulkan1.3 --glsl-version 460 -o "$outdir/mesh.vert.spv"         "$srcdir/mesh.vert"
+glslang --target-env vulkan1.3 --glsl-version 460 -o "$outdir/mesh.frag.spv"         "$srcdir/mesh.frag"
+glslang --target-env vulkan1.3 --glsl-version 460 -o "$outdir/shadow_depth.vert.spv" "$srcdir/shadow_depth.vert"
+
+# Skinned variants of the mesh and shadow vertex shaders, see the animation module
+glslang --target-env vulkan1.3 --glsl-version 460 -o "$outdir/skinned_mesh.vert.spv"         "$srcdir/skinned_mesh.vert"
+glslang --target-env vulkan1.3 --glsl-version 460 -o "$outdir/skinned_shadow_depth.vert.spv" "$srcdir/skinned_shadow_depth.vert"
+
+# Alpha tested shadow casters: the shadow vertex shaders with UVs for the fragment shader that discards cut out texels
+glslang --target-env vulkan1.3 --glsl-version 460 -DALPHA_TEST -o "$outdir/shadow_depth_alpha_test.vert.spv"         "$srcdir/shadow_depth.vert"
+glslang --target-env vulkan1.3 --glsl-version 460 -DALPHA_TEST -o "$outdir/skinned_shadow_depth_alpha_test.vert.spv" "$srcdir/skinned_shadow_depth.vert"
+glslang --target-env vulkan1.3 --glsl-version 460 -o "$outdir/shadow_alpha_test.frag.spv"                            "$srcdir/shadow_alpha_test.frag"
+
+# Transparency: the weighted blended OIT variant of the lit mesh shader, and the pass that composites it over the scene
+glslang --target-env vulkan1.3 --glsl-version 460 -DWEIGHTED_OIT -o "$outdir/mesh_oit.frag.spv" "$srcdir/mesh.frag"
+glslang --target-env vulkan1.3 --glsl-version 460 -o "$outdir/oit_composite.comp.spv"           "$srcdir/oit_composite.comp"
+
+# Deferred shading: the G-buffer variant of the lit mesh shader, and the compute pass that lights the G-buffer
+glslang --target-env vulkan1.3 --glsl-version 460 -DGBUFFER -o "$outdir/mesh_gbuffer.frag.spv" "$srcdir/mesh.frag"
+glslang --target-env vulkan1.3 --glsl-version 460 -o "$outdir/deferred_lighting.comp.spv"      "$srcdir/deferred_lighting.comp"
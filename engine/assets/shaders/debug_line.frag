@@ -0,0 +1,64 @@
+#version 450
+#extension GL_EXT_buffer_reference : require
+
+layout (location = 0) in vec4 inColor;
+
+layout (location = 0) out vec4 outFragColor;
+
+#define OUTPUT_SDR    0 // sRGB encoded, debug colors are written as they are
+#define OUTPUT_HDR10  1 // Rec.2020 primaries, PQ encoded
+
+layout(buffer_reference, std430) readonly buffer DebugVertexBuffer {
+	vec4 data[];
+};
+
+// Matches shader::DebugLinePushConstants
+layout(push_constant) uniform constants
+{
+	mat4              viewProj;
+	DebugVertexBuffer vertexBuffer;
+	vec4              outputParams; // x: output mode, y: HDR paper white in nits
+} PushConstants;
+
+vec3 srgb_to_linear(vec3 color)
+{
+	vec3 low  = color / 12.92;
+	vec3 high = pow((color + 0.055) / 1.055, vec3(2.4));
+	return mix(high, low, lessThanEqual(color, vec3(0.04045)));
+}
+
+// Same encoding as tonemap.comp
+vec3 linear_to_pq(vec3 normalizedNits)
+{
+	const float m1 = 0.1593017578125;
+	const float m2 = 78.84375;
+	const float c1 = 0.8359375;
+	const float c2 = 18.8515625;
+	const float c3 = 18.6875;
+
+	vec3 ym1 = pow(clamp(normalizedNits, 0.0, 1.0), vec3(m1));
+	return pow((c1 + c2 * ym1) / (1.0 + c3 * ym1), vec3(m2));
+}
+
+vec3 rec709_to_rec2020(vec3 color)
+{
+	const mat3 conversion = mat3(
+		0.6274, 0.0691, 0.0164,
+		0.3293, 0.9195, 0.0880,
+		0.0433, 0.0114, 0.8956);
+
+	return conversion * color;
+}
+
+void main()
+{
+	vec3 color = clamp(inColor.rgb, 0.0, 1.0);
+
+	// The lines are drawn after tonemapping, so encode them like the tonemapper does. White is paper white.
+	if (int(PushConstants.outputParams.x) == OUTPUT_HDR10) {
+		float paperWhite = PushConstants.outputParams.y;
+		color = linear_to_pq(rec709_to_rec2020(srgb_to_linear(color)) * paperWhite / 10000.0);
+	}
+
+	outFragColor = vec4(color, inColor.a);
+}
@@ -0,0 +1,30 @@
+#version 450
+#extension GL_EXT_buffer_reference : require
+
+layout (location = 0) out vec4 outColor;
+
+// Matches debug_draw::DebugVertex
+struct DebugVertex {
+	vec4 position; // w: unused
+	vec4 color;
+};
+
+layout(buffer_reference, std430) readonly buffer DebugVertexBuffer {
+	DebugVertex vertices[];
+};
+
+// Matches shader::DebugLinePushConstants
+layout(push_constant) uniform constants
+{
+	mat4              viewProj;
+	DebugVertexBuffer vertexBuffer;
+	vec4              outputParams; // x: output mode, y: HDR paper white in nits
+} PushConstants;
+
+void main()
+{
+	DebugVertex v = PushConstants.vertexBuffer.vertices[gl_VertexIndex];
+
+	gl_Position = PushConstants.viewProj * vec4(v.position.xyz, 1.0);
+	outColor    = v.color;
+}
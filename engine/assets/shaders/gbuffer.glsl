@@ -0,0 +1,23 @@
+// The G-buffer of the deferred path, written by mesh.frag with GBUFFER defined and read by deferred_lighting.comp.
+// Matches the GBUFFER_*_FORMAT constants in deferred.rs.
+//   scene    (rgba16f): rgb: emissive, a: coverage, like the forward geometry pass
+//   albedo   (rgba8):   rgb: albedo, a: the material's occlusion
+//   normal   (rgba16f): xy: shading normal, zw: geometric normal, both octahedron encoded
+//   material (rgba8):   r: metallic, g: roughness
+// The world position is reconstructed from the scene depth.
+
+// Octahedron normal encoding (Cigolle et al., "A Survey of Efficient Representations for Independent Unit Vectors")
+vec2 encode_octahedron(vec3 n)
+{
+	n /= abs(n.x) + abs(n.y) + abs(n.z);
+	vec2 signs = vec2(n.x >= 0.0 ? 1.0 : -1.0, n.y >= 0.0 ? 1.0 : -1.0);
+	return n.z >= 0.0 ? n.xy : (1.0 - abs(n.yx)) * signs;
+}
+
+vec3 decode_octahedron(vec2 e)
+{
+	vec3  n = vec3(e, 1.0 - abs(e.x) - abs(e.y));
+	float t = clamp(-n.z, 0.0, 1.0);
+	n.xy += vec2(n.x >= 0.0 ? -t : t, n.y >= 0.0 ? -t : t);
+	return normalize(n);
+}
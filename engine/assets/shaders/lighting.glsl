@@ -0,0 +1,200 @@
+// Shared by the lit shaders: mesh.frag shades each fragment with it, deferred_lighting.comp each pixel of the
+// G-buffer. Include after scene_data.glsl and lights.glsl. The lookups all use an explicit level of detail, so they
+// also work in compute shaders.
+
+#define PI 3.14159265359
+
+// What the lighting needs to know about the surface at a pixel
+struct Surface {
+	vec3  worldPos;
+	vec3  geometricNormal; // the interpolated vertex normal, for the shadow bias
+	vec3  N;               // the shading normal, with the normal map applied
+	vec3  V;               // from the surface to the camera
+	vec3  albedo;
+	float metallic;
+	float roughness;
+	float viewDepth;
+};
+
+// Point and spot lights, assigned to clusters by light_cull.comp
+layout(set = 0, binding = 2) readonly buffer LightBuffer {
+	Light lights[];
+};
+
+layout(set = 0, binding = 3) readonly buffer LightGrid {
+	Cluster clusters[];
+};
+
+// Returns how lit the fragment is by the sun, from 0 (fully shadowed) to 1.
+float sample_sun_shadow(vec3 worldPos, vec3 normal, float viewDepth)
+{
+	int cascadeCount = int(sceneData.shadowParams.x);
+
+	// Pick the first cascade that contains the fragment
+	int cascade = cascadeCount;
+	for (int i = 0; i < cascadeCount; ++i) {
+		if (viewDepth < sceneData.cascadeSplits[i]) {
+			cascade = i;
+			break;
+		}
+	}
+
+	if (cascade == cascadeCount) {
+		return 1.0; // past the last cascade, no shadows
+	}
+
+	// Normal offset, scaled to the cascade's texel size so the bias is the same in every cascade
+	vec3 offsetPos = worldPos + normal * sceneData.shadowParams.y * sceneData.cascadeTexelSizes[cascade];
+
+	vec4 shadowPos = sceneData.cascadeViewProj[cascade] * vec4(offsetPos, 1.0);
+	shadowPos.xyz /= shadowPos.w;
+
+	if (shadowPos.z >= 1.0) {
+		return 1.0;
+	}
+
+	// Move into the cascade's tile of the atlas, and keep the filter from reading neighbouring cascades
+	vec2  tile    = vec2(cascade % 2, cascade / 2);
+	float texel   = sceneData.shadowParams.w;
+	vec2  atlasUV = (tile + (shadowPos.xy * 0.5 + 0.5)) * 0.5;
+	vec2  tileMin = tile * 0.5 + texel;
+	vec2  tileMax = tile * 0.5 + 0.5 - texel;
+
+	// PCF: every tap is already a 2x2 bilinear comparison from the shadow sampler
+	int   radius = int(sceneData.shadowParams.z);
+	float lit    = 0.0;
+
+	for (int y = -radius; y <= radius; ++y) {
+		for (int x = -radius; x <= radius; ++x) {
+			vec2 sampleUV = clamp(atlasUV + vec2(x, y) * texel, tileMin, tileMax);
+			lit += textureLod(shadowMap, vec3(sampleUV, shadowPos.z), 0.0);
+		}
+	}
+
+	float tapCount = float((2 * radius + 1) * (2 * radius + 1));
+	return lit / tapCount;
+}
+
+// GGX / Trowbridge-Reitz normal distribution
+float distribution_ggx(float NdotH, float roughness)
+{
+	float a  = roughness * roughness;
+	float a2 = a * a;
+	float d  = NdotH * NdotH * (a2 - 1.0) + 1.0;
+	return a2 / (PI * d * d);
+}
+
+// Smith-Schlick geometry term, with the k remapping for direct lighting
+float geometry_smith(float NdotV, float NdotL, float roughness)
+{
+	float k  = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+	float gv = NdotV / (NdotV * (1.0 - k) + k);
+	float gl = NdotL / (NdotL * (1.0 - k) + k);
+	return gv * gl;
+}
+
+vec3 fresnel_schlick(float cosTheta, vec3 F0)
+{
+	return F0 + (1.0 - F0) * pow(clamp(1.0 - cosTheta, 0.0, 1.0), 5.0);
+}
+
+// Analytic fit of the split-sum environment BRDF (Karis, "Physically Based Shading on Mobile")
+vec3 env_brdf_approx(vec3 F0, float roughness, float NdotV)
+{
+	const vec4 c0 = vec4(-1.0, -0.0275, -0.572,  0.022);
+	const vec4 c1 = vec4( 1.0,  0.0425,  1.04,  -0.04);
+	vec4  r    = roughness * c0 + c1;
+	float a004 = min(r.x * r.x, exp2(-9.28 * NdotV)) * r.x + r.y;
+	vec2  AB   = vec2(-1.04, 1.04) * a004 + r.zw;
+	return F0 * AB.x + AB.y;
+}
+
+// Cook-Torrance specular + Lambert diffuse, for light arriving from L. Returns the reflected light per unit of
+// incoming radiance.
+vec3 evaluate_brdf(vec3 N, vec3 V, vec3 L, vec3 albedo, vec3 F0, float metallic, float roughness)
+{
+	vec3 H = normalize(V + L);
+
+	float NdotV = max(dot(N, V), 1e-4);
+	float NdotL = max(dot(N, L), 0.0);
+	float NdotH = max(dot(N, H), 0.0);
+	float VdotH = max(dot(V, H), 0.0);
+
+	vec3  F        = fresnel_schlick(VdotH, F0);
+	float D        = distribution_ggx(NdotH, roughness);
+	float G        = geometry_smith(NdotV, NdotL, roughness);
+	vec3  specular = D * G * F / (4.0 * NdotV * max(NdotL, 1e-4));
+	vec3  kD       = (1.0 - F) * (1.0 - metallic);
+
+	return (kD * albedo / PI + specular) * NdotL;
+}
+
+// Inverse square falloff, windowed so it reaches zero at the light's range (Karis, "Real Shading in Unreal
+// Engine 4"). Spot lights also fade out between their inner and outer cones.
+vec3 evaluate_light(Light light, vec3 worldPos, vec3 N, vec3 V, vec3 albedo, vec3 F0, float metallic, float roughness)
+{
+	vec3  toLight = light.positionRange.xyz - worldPos;
+	float dist2   = dot(toLight, toLight);
+	vec3  L       = toLight * inversesqrt(max(dist2, 1e-8));
+
+	float range       = light.positionRange.w;
+	float ratio       = dist2 / max(range * range, 1e-8);
+	float window      = clamp(1.0 - ratio * ratio, 0.0, 1.0);
+	float attenuation = window * window / max(dist2, 1e-4);
+
+	if (int(light.directionType.w) == LIGHT_TYPE_SPOT) {
+		float cosAngle = dot(-L, light.directionType.xyz);
+		attenuation *= smoothstep(light.cone.x, light.cone.y, cosAngle);
+	}
+
+	vec3 radiance = light.colorIntensity.rgb * light.colorIntensity.w * attenuation;
+	return evaluate_brdf(N, V, L, albedo, F0, metallic, roughness) * radiance;
+}
+
+// Finds the cluster the fragment is in. Must match the cluster bounds in light_cull.comp.
+uint get_fragment_cluster(vec2 fragCoord, float viewDepth)
+{
+	uvec2 tile  = uvec2(fragCoord / sceneData.clusterParams.xy);
+	uint  slice = uint(max(log(viewDepth) * sceneData.clusterParams.z + sceneData.clusterParams.w, 0.0));
+
+	tile  = min(tile, uvec2(CLUSTER_GRID_X - 1, CLUSTER_GRID_Y - 1));
+	slice = min(slice, uint(CLUSTER_GRID_Z - 1));
+
+	return get_cluster_index(uvec3(tile, slice));
+}
+
+// The sun and the point and spot lights in the surface's cluster. `fragCoord` is the pixel center.
+vec3 evaluate_direct_light(Surface surface, vec2 fragCoord)
+{
+	vec3 F0 = mix(vec3(0.04), surface.albedo, surface.metallic);
+	vec3 L  = normalize(-sceneData.sunlightDirection.xyz);
+
+	// The shadow lookup offsets along the geometric normal, normal maps would make the bias noisy
+	float shadow   = sample_sun_shadow(surface.worldPos, surface.geometricNormal, surface.viewDepth);
+	vec3  radiance = sceneData.sunlightColor.rgb * sceneData.sunlightColor.w;
+	vec3  direct   = evaluate_brdf(surface.N, surface.V, L, surface.albedo, F0, surface.metallic, surface.roughness) * radiance * shadow;
+
+	uint cluster    = get_fragment_cluster(fragCoord, surface.viewDepth);
+	uint lightCount = clusters[cluster].count;
+	for (uint i = 0; i < lightCount; ++i) {
+		Light light = lights[clusters[cluster].lightIndices[i]];
+		direct += evaluate_light(light, surface.worldPos, surface.N, surface.V, surface.albedo, F0, surface.metallic, surface.roughness);
+	}
+
+	return direct;
+}
+
+// The irradiance map for the diffuse, and the specular map prefiltered for the surface's roughness along the
+// reflection vector. Not occluded, the callers apply the material's and the screen-space occlusion.
+vec3 evaluate_ambient_light(Surface surface)
+{
+	vec3  F0    = mix(vec3(0.04), surface.albedo, surface.metallic);
+	float NdotV = max(dot(surface.N, surface.V), 1e-4);
+
+	vec3  R               = reflect(-surface.V, surface.N);
+	float specularLod     = surface.roughness * float(textureQueryLevels(specularMap) - 1);
+	vec3  ambientDiffuse  = textureLod(irradianceMap, surface.N, 0.0).rgb * surface.albedo * (1.0 - surface.metallic);
+	vec3  ambientSpecular = textureLod(specularMap, R, specularLod).rgb * env_brdf_approx(F0, surface.roughness, NdotV);
+
+	return (ambientDiffuse + ambientSpecular) * sceneData.ambientColor.rgb;
+}
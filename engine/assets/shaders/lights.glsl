@@ -0,0 +1,27 @@
+// Shared by the light cull pass and the lit shaders. Matches lights.rs and shader::GpuLight.
+
+#define CLUSTER_GRID_X         16
+#define CLUSTER_GRID_Y         9
+#define CLUSTER_GRID_Z         24
+#define MAX_LIGHTS_PER_CLUSTER 127
+
+#define LIGHT_TYPE_POINT 0
+#define LIGHT_TYPE_SPOT  1
+
+struct Light {
+	vec4 positionRange;  // xyz: world-space position, w: range
+	vec4 colorIntensity; // rgb: linear color, w: intensity
+	vec4 directionType;  // xyz: direction a spot light points in, w: LIGHT_TYPE_*
+	vec4 cone;           // x: cos(outer cone angle), y: cos(inner cone angle)
+};
+
+// The lights that touch a cluster, as indices into the light buffer
+struct Cluster {
+	uint count;
+	uint lightIndices[MAX_LIGHTS_PER_CLUSTER];
+};
+
+uint get_cluster_index(uvec3 cluster)
+{
+	return cluster.x + cluster.y * CLUSTER_GRID_X + cluster.z * CLUSTER_GRID_X * CLUSTER_GRID_Y;
+}
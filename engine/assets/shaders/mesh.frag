@@ -0,0 +1,159 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+#extension GL_EXT_nonuniform_qualifier : require
+
+#include "scene_data.glsl"
+#include "lights.glsl"
+#include "lighting.glsl"
+
+//shader input
+layout (location = 0) in vec3  inColor;
+layout (location = 1) in vec2  inUV;
+layout (location = 2) in vec3  inNormal;
+layout (location = 3) in vec3  inWorldPos;
+layout (location = 4) in float inViewDepth;
+layout (location = 5) in vec4  inTangent;
+layout (location = 6) in vec4  inInstanceColor;
+
+//output write
+#ifdef WEIGHTED_OIT
+// Weighted blended order-independent transparency, see transparency.rs. Accumulated with additive blending and
+// revealage with multiplicative blending.
+layout (location = 0) out vec4  outAccum;
+layout (location = 1) out float outRevealage;
+#elif defined(GBUFFER)
+// The G-buffer of the deferred path, see deferred.rs. The lighting is done later, in deferred_lighting.comp
+#include "gbuffer.glsl"
+
+layout (location = 0) out vec4 outFragColor;
+layout (location = 1) out vec4 outAlbedo;
+layout (location = 2) out vec4 outNormal;
+layout (location = 3) out vec4 outMaterial;
+#else
+layout (location = 0) out vec4 outFragColor;
+// The ambient part of outFragColor, for the SSAO apply pass to occlude, see ssao.rs. The transparent pass has no
+// attachment for it, so it is dropped there.
+layout (location = 1) out vec4 outAmbient;
+#endif
+
+// Matches material_system::BINDLESS_SAMPLER_COUNT
+#define BINDLESS_SAMPLER_COUNT 2
+
+// Matches material_system::ALPHA_MODE_*
+#define ALPHA_MODE_OPAQUE 0
+#define ALPHA_MODE_MASK   1
+#define ALPHA_MODE_BLEND  2
+
+// Matches shader::MaterialConstants. The textures are indices into the bindless texture array
+struct MaterialConstants {
+	vec4  baseColorFactor;
+	vec4  emissiveFactor;
+	float metallicFactor;
+	float roughnessFactor;
+	float normalScale;
+	float occlusionStrength;
+	uint  albedoTexture;
+	uint  normalTexture;
+	uint  metallicRoughnessTexture; // g: roughness, b: metallic
+	uint  occlusionTexture;         // r: occlusion
+	uint  emissiveTexture;
+	uint  samplerIndex;
+	float alphaCutoff;
+	uint  alphaMode;                // ALPHA_MODE_*
+};
+
+// Bindless set, see material_system.rs
+layout(set = 1, binding = 0) readonly buffer MaterialBuffer {
+	MaterialConstants materials[];
+};
+
+layout(set = 1, binding = 1) uniform sampler   samplers[BINDLESS_SAMPLER_COUNT];
+layout(set = 1, binding = 2) uniform texture2D textures[];
+
+// Matches shader::GpuDrawPushConstants, the vertex shader reads the members before the material index
+layout(push_constant) uniform constants
+{
+	layout(offset = 80) uint materialIndex;
+} PushConstants;
+
+// Loaded from the material buffer at the start of main
+MaterialConstants material;
+
+// The material index comes from the push constants, so every index here is dynamically uniform
+vec4 sample_material_texture(uint textureIndex, vec2 uv)
+{
+	return texture(sampler2D(textures[textureIndex], samplers[material.samplerIndex]), uv);
+}
+
+// Applies the material's normal map. Meshes without tangents keep their vertex normal.
+vec3 get_shading_normal(vec3 normal)
+{
+	if (dot(inTangent.xyz, inTangent.xyz) < 1e-8) {
+		return normal;
+	}
+
+	vec3 T = normalize(inTangent.xyz - normal * dot(normal, inTangent.xyz));
+	vec3 B = cross(normal, T) * inTangent.w;
+
+	vec3 tangentNormal = sample_material_texture(material.normalTexture, inUV).xyz * 2.0 - 1.0;
+	tangentNormal.xy *= material.normalScale;
+
+	return normalize(mat3(T, B, normal) * tangentNormal);
+}
+
+void main()
+{
+	material = materials[PushConstants.materialIndex];
+
+	vec4  baseColor = material.baseColorFactor * inInstanceColor * sample_material_texture(material.albedoTexture, inUV);
+	if (material.alphaMode == ALPHA_MODE_MASK && baseColor.a < material.alphaCutoff) {
+		discard;
+	}
+
+	vec4  mr        = sample_material_texture(material.metallicRoughnessTexture, inUV);
+	float metallic  = clamp(material.metallicFactor * mr.b, 0.0, 1.0);
+	// Very low roughness turns the sun into a sub-pixel highlight
+	float roughness = clamp(material.roughnessFactor * mr.g, 0.045, 1.0);
+	float ao        = mix(1.0, sample_material_texture(material.occlusionTexture, inUV).r, material.occlusionStrength);
+	vec3  emissive  = material.emissiveFactor.rgb * sample_material_texture(material.emissiveTexture, inUV).rgb;
+
+	vec3 geometricNormal = normalize(inNormal);
+	vec3 N = get_shading_normal(geometricNormal);
+
+#ifdef GBUFFER
+	outFragColor = vec4(emissive, 1.0);
+	outAlbedo    = vec4(baseColor.rgb, ao);
+	outNormal    = vec4(encode_octahedron(N), encode_octahedron(geometricNormal));
+	outMaterial  = vec4(metallic, roughness, 0.0, 0.0);
+#else
+	Surface surface;
+	surface.worldPos        = inWorldPos;
+	surface.geometricNormal = geometricNormal;
+	surface.N               = N;
+	surface.V               = normalize(sceneData.cameraPosition.xyz - inWorldPos);
+	surface.albedo          = baseColor.rgb;
+	surface.metallic        = metallic;
+	surface.roughness       = roughness;
+	surface.viewDepth       = inViewDepth;
+
+	vec3 direct  = evaluate_direct_light(surface, gl_FragCoord.xy);
+	vec3 ambient = evaluate_ambient_light(surface) * ao;
+	vec3 color   = direct + ambient + emissive;
+
+#ifdef WEIGHTED_OIT
+	// Weight function from McGuire and Bavoil, "Weighted Blended Order-Independent Transparency", equation 10.
+	// Favors closer and more opaque surfaces.
+	float alpha  = baseColor.a;
+	float weight = clamp(pow(min(1.0, alpha * 10.0) + 0.01, 3.0) * 1e8 * pow(1.0 - gl_FragCoord.z * 0.9, 3.0), 1e-2, 3e3);
+
+	outAccum     = vec4(color * alpha, alpha) * weight;
+	outRevealage = alpha;
+#else
+	// In the geometry pass alpha is the coverage, the background is composited underneath it after the MSAA resolve.
+	// Blended materials are drawn over the background in the transparent pass, with their own alpha.
+	float alpha  = material.alphaMode == ALPHA_MODE_BLEND ? baseColor.a : 1.0;
+	outFragColor = vec4(color, alpha);
+	outAmbient   = vec4(ambient, 0.0);
+#endif
+#endif
+}
@@ -0,0 +1,68 @@
+#version 450
+#extension GL_EXT_buffer_reference : require
+#extension GL_GOOGLE_include_directive : require
+
+#include "scene_data.glsl"
+
+layout (location = 0) out vec3  outColor;
+layout (location = 1) out vec2  outUV;
+layout (location = 2) out vec3  outNormal;
+layout (location = 3) out vec3  outWorldPos;
+layout (location = 4) out float outViewDepth;
+layout (location = 5) out vec4  outTangent;
+layout (location = 6) out vec4  outInstanceColor;
+
+struct Vertex {
+
+	vec3 position;
+	float uv_x;
+	vec3 normal;
+	float uv_y;
+	vec4 color;
+	vec4 tangent; // w: handedness of the bitangent
+};
+
+layout(buffer_reference, std430) readonly buffer VertexBuffer{
+	Vertex vertices[];
+};
+
+// Matches shader::GpuInstance
+struct Instance {
+	mat4 transform;
+	vec4 color;
+};
+
+layout(buffer_reference, std430) readonly buffer InstanceBuffer{
+	Instance instances[];
+};
+
+//push constants block
+layout( push_constant ) uniform constants
+{
+	mat4 world_matrix;
+	VertexBuffer vertexBuffer;
+	InstanceBuffer instanceBuffer;
+	uint materialIndex; // read by mesh.frag
+} PushConstants;
+
+void main()
+{
+	//load vertex data from device adress
+	Vertex   v        = PushConstants.vertexBuffer.vertices[gl_VertexIndex];
+	Instance instance = PushConstants.instanceBuffer.instances[gl_InstanceIndex];
+
+	mat4 worldMatrix = PushConstants.world_matrix * instance.transform;
+	vec4 worldPos    = worldMatrix * vec4(v.position, 1.0f);
+
+	//output data
+	gl_Position  = sceneData.viewproj * worldPos;
+	outColor     = v.color.xyz;
+	outUV.x      = v.uv_x;
+	outUV.y      = v.uv_y;
+	//note: assumes the world matrix doesn't have a non-uniform scale
+	outNormal    = mat3(worldMatrix) * v.normal;
+	outWorldPos  = worldPos.xyz;
+	outViewDepth = -(sceneData.view * worldPos).z;
+	outTangent   = vec4(mat3(worldMatrix) * v.tangent.xyz, v.tangent.w);
+	outInstanceColor = instance.color;
+}
@@ -0,0 +1,56 @@
+#version 450
+#extension GL_EXT_buffer_reference : require
+#extension GL_EXT_nonuniform_qualifier : require
+
+layout (location = 0) in vec2 inUV;
+layout (location = 1) in vec4 inColor;
+layout (location = 2) in vec2 inCorner;
+
+layout (location = 0) out vec4 outFragColor;
+
+// Matches material_system::BINDLESS_SAMPLER_COUNT and BINDLESS_SAMPLER_LINEAR
+#define BINDLESS_SAMPLER_COUNT 2
+#define SAMPLER_LINEAR         0
+
+// Matches particles::NO_PARTICLE_TEXTURE
+#define NO_TEXTURE 0xFFFFFFFFu
+
+// The particle pipelines only bind the bindless set, see material_system.rs
+layout(set = 0, binding = 1) uniform sampler   samplers[BINDLESS_SAMPLER_COUNT];
+layout(set = 0, binding = 2) uniform texture2D textures[];
+
+layout(buffer_reference, std430) readonly buffer ParticleBuffer {
+	vec4 data[];
+};
+
+layout(buffer_reference, std430) readonly buffer EmitterBuffer {
+	vec4 data[];
+};
+
+// Matches shader::ParticleDrawPushConstants
+layout(push_constant) uniform constants
+{
+	mat4           viewProj;
+	vec4           cameraRight;
+	vec4           cameraUp;
+	ParticleBuffer particleBuffer;
+	EmitterBuffer  emitterBuffer;
+	uint           emitterIndex;
+	uint           textureIndex;
+} PushConstants;
+
+void main()
+{
+	vec4 texel;
+	if (PushConstants.textureIndex == NO_TEXTURE) {
+		// A soft round dot, fading out towards the edge of the quad
+		float distance = length(inCorner * 2.0 - 1.0);
+		texel = vec4(1.0, 1.0, 1.0, 1.0 - smoothstep(0.0, 1.0, distance));
+	} else {
+		// The texture comes from the push constants, so the index is dynamically uniform
+		texel = texture(sampler2D(textures[PushConstants.textureIndex], samplers[SAMPLER_LINEAR]), inUV);
+	}
+
+	// Linear HDR, the particles are tonemapped with the scene
+	outFragColor = texel * inColor;
+}
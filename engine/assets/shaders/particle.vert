@@ -0,0 +1,86 @@
+#version 450
+#extension GL_EXT_buffer_reference : require
+
+layout (location = 0) out vec2 outUV;
+layout (location = 1) out vec4 outColor;
+layout (location = 2) out vec2 outCorner; // 0 to 1 across the quad, for the soft dot
+
+// Matches shader::GpuParticle
+struct Particle {
+	vec4 positionAge;      // xyz: position, w: age in seconds
+	vec4 velocityLifetime; // xyz: velocity, w: lifetime in seconds
+};
+
+// Matches shader::GpuParticleEmitter
+struct Emitter {
+	vec4 position;
+	vec4 direction;
+	vec4 speedLifetime;
+	vec4 gravity;
+	vec4 colorStart;
+	vec4 colorEnd;
+	vec4 sizeSheet; // x: start size, y: end size, z: sheet columns, w: sheet rows
+};
+
+layout(buffer_reference, std430) readonly buffer ParticleBuffer {
+	Particle particles[];
+};
+
+layout(buffer_reference, std430) readonly buffer EmitterBuffer {
+	Emitter emitters[];
+};
+
+// Matches shader::ParticleDrawPushConstants
+layout(push_constant) uniform constants
+{
+	mat4           viewProj;
+	vec4           cameraRight;
+	vec4           cameraUp;
+	ParticleBuffer particleBuffer;
+	EmitterBuffer  emitterBuffer;
+	uint           emitterIndex;
+	uint           textureIndex;
+} PushConstants;
+
+// Two triangles, as corners of the quad from the top-left (0, 0) to the bottom-right (1, 1)
+const vec2 corners[6] = vec2[](
+	vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(1.0, 1.0),
+	vec2(0.0, 0.0), vec2(1.0, 1.0), vec2(0.0, 1.0)
+);
+
+void main()
+{
+	// One instance per slot of the emitter's ring
+	Particle particle = PushConstants.particleBuffer.particles[gl_InstanceIndex];
+	Emitter  emitter  = PushConstants.emitterBuffer.emitters[PushConstants.emitterIndex];
+	vec2     corner   = corners[gl_VertexIndex];
+
+	float age      = particle.positionAge.w;
+	float lifetime = particle.velocityLifetime.w;
+
+	// Dead slots collapse to a point outside the clip volume
+	if (age >= lifetime) {
+		gl_Position = vec4(0.0, 0.0, 2.0, 1.0);
+		outUV       = vec2(0.0);
+		outColor    = vec4(0.0);
+		outCorner   = vec2(0.0);
+		return;
+	}
+
+	float life = clamp(age / lifetime, 0.0, 1.0);
+	float size = mix(emitter.sizeSheet.x, emitter.sizeSheet.y, life);
+
+	vec3 offset   = PushConstants.cameraRight.xyz * (corner.x - 0.5) + PushConstants.cameraUp.xyz * (0.5 - corner.y);
+	vec3 position = particle.positionAge.xyz + offset * size;
+
+	// The sheet plays once over the particle's life, left to right then top to bottom
+	vec2  sheetSize  = emitter.sizeSheet.zw;
+	float frameCount = sheetSize.x * sheetSize.y;
+	float frame      = min(floor(life * frameCount), frameCount - 1.0);
+	vec2  cell       = vec2(mod(frame, sheetSize.x), floor(frame / sheetSize.x));
+
+	gl_Position = PushConstants.viewProj * vec4(position, 1.0);
+	outUV       = (cell + corner) / sheetSize;
+	outColor    = mix(emitter.colorStart, emitter.colorEnd, life);
+	outCorner   = corner;
+}
@@ -0,0 +1,26 @@
+// Shared by every shader that binds the GlobalSceneData at set 0. Matches shader::GlobalSceneData.
+
+#define MAX_SHADOW_CASCADES 4
+
+layout(set = 0, binding = 0) uniform SceneData {
+	mat4 view;
+	mat4 proj;
+	mat4 viewproj;
+	vec4 ambientColor;
+	vec4 sunlightDirection; // xyz: direction the light travels in
+	vec4 sunlightColor;     // w: intensity
+	vec4 cameraPosition;
+	mat4 cascadeViewProj[MAX_SHADOW_CASCADES];
+	vec4 cascadeSplits;     // view-space depth where each cascade ends
+	vec4 cascadeTexelSizes; // world-space size of a shadow map texel in each cascade
+	vec4 shadowParams;      // x: cascade count, y: normal bias, z: pcf radius, w: 1 / atlas resolution
+	vec4 lightParams;       // x: light count, y: near plane, z: far plane
+	vec4 clusterParams;     // xy: cluster tile size in pixels, z: depth slice scale, w: depth slice bias
+} sceneData;
+
+// Cascaded shadow map atlas, cascades are laid out in a 2x2 grid
+layout(set = 0, binding = 1) uniform sampler2DShadow shadowMap;
+
+// Image-based lighting, see environment.rs
+layout(set = 0, binding = 4) uniform samplerCube irradianceMap; // diffuse irradiance / PI
+layout(set = 0, binding = 5) uniform samplerCube specularMap;   // GGX prefiltered radiance, mip = roughness * (mip count - 1)
@@ -0,0 +1,55 @@
+#version 450
+#extension GL_EXT_nonuniform_qualifier : require
+
+// Depth only, like the regular shadow pipeline, but discards the texels a Mask material cuts out so foliage and
+// fences cast the same holes in their shadows as they show on screen. The alpha matches mesh.frag.
+
+layout (location = 0) in vec2  inUV;
+layout (location = 1) in float inInstanceAlpha;
+
+// Matches material_system::BINDLESS_SAMPLER_COUNT
+#define BINDLESS_SAMPLER_COUNT 2
+
+// Matches shader::MaterialConstants. The textures are indices into the bindless texture array
+struct MaterialConstants {
+	vec4  baseColorFactor;
+	vec4  emissiveFactor;
+	float metallicFactor;
+	float roughnessFactor;
+	float normalScale;
+	float occlusionStrength;
+	uint  albedoTexture;
+	uint  normalTexture;
+	uint  metallicRoughnessTexture;
+	uint  occlusionTexture;
+	uint  emissiveTexture;
+	uint  samplerIndex;
+	float alphaCutoff;
+	uint  alphaMode;
+};
+
+// Bindless set, see material_system.rs. It is the only set of the shadow pipeline layout
+layout(set = 0, binding = 0) readonly buffer MaterialBuffer {
+	MaterialConstants materials[];
+};
+
+layout(set = 0, binding = 1) uniform sampler   samplers[BINDLESS_SAMPLER_COUNT];
+layout(set = 0, binding = 2) uniform texture2D textures[];
+
+// Matches shader::GpuDrawPushConstants, the vertex shader reads the members before the material index
+layout(push_constant) uniform constants
+{
+	layout(offset = 80) uint materialIndex;
+} PushConstants;
+
+void main()
+{
+	MaterialConstants material = materials[PushConstants.materialIndex];
+
+	float alpha = material.baseColorFactor.a * inInstanceAlpha
+		* texture(sampler2D(textures[material.albedoTexture], samplers[material.samplerIndex]), inUV).a;
+
+	if (alpha < material.alphaCutoff) {
+		discard;
+	}
+}
@@ -0,0 +1,52 @@
+#version 450
+#extension GL_EXT_buffer_reference : require
+
+struct Vertex {
+
+	vec3 position;
+	float uv_x;
+	vec3 normal;
+	float uv_y;
+	vec4 color;
+	vec4 tangent; // w: handedness of the bitangent
+};
+
+layout(buffer_reference, std430) readonly buffer VertexBuffer{
+	Vertex vertices[];
+};
+
+// Matches shader::GpuInstance
+struct Instance {
+	mat4 transform;
+	vec4 color;
+};
+
+layout(buffer_reference, std430) readonly buffer InstanceBuffer{
+	Instance instances[];
+};
+
+//push constants block, render_matrix = cascade view-projection * world
+layout( push_constant ) uniform constants
+{
+	mat4 render_matrix;
+	VertexBuffer vertexBuffer;
+	InstanceBuffer instanceBuffer;
+} PushConstants;
+
+#ifdef ALPHA_TEST
+// The variant for Mask materials, shadow_alpha_test.frag discards the texels the material cuts out
+layout (location = 0) out vec2  outUV;
+layout (location = 1) out float outInstanceAlpha;
+#endif
+
+void main()
+{
+	Vertex   v        = PushConstants.vertexBuffer.vertices[gl_VertexIndex];
+	Instance instance = PushConstants.instanceBuffer.instances[gl_InstanceIndex];
+	gl_Position = PushConstants.render_matrix * instance.transform * vec4(v.position, 1.0f);
+
+#ifdef ALPHA_TEST
+	outUV            = vec2(v.uv_x, v.uv_y);
+	outInstanceAlpha = instance.color.a;
+#endif
+}
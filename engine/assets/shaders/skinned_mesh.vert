@@ -0,0 +1,95 @@
+#version 450
+#extension GL_EXT_buffer_reference : require
+#extension GL_GOOGLE_include_directive : require
+
+#include "scene_data.glsl"
+
+// mesh.vert with linear blend skinning, the outputs match what mesh.frag reads
+
+layout (location = 0) out vec3  outColor;
+layout (location = 1) out vec2  outUV;
+layout (location = 2) out vec3  outNormal;
+layout (location = 3) out vec3  outWorldPos;
+layout (location = 4) out float outViewDepth;
+layout (location = 5) out vec4  outTangent;
+layout (location = 6) out vec4  outInstanceColor;
+
+struct Vertex {
+
+	vec3 position;
+	float uv_x;
+	vec3 normal;
+	float uv_y;
+	vec4 color;
+	vec4 tangent; // w: handedness of the bitangent
+};
+
+layout(buffer_reference, std430) readonly buffer VertexBuffer{
+	Vertex vertices[];
+};
+
+// Matches mesh::SkinVertex, indexed like the vertices
+struct SkinVertex {
+	uvec4 joints;
+	vec4  weights;
+};
+
+layout(buffer_reference, std430) readonly buffer SkinBuffer{
+	SkinVertex skins[];
+};
+
+// The skinned mesh's joint palette, joint world transform * inverse bind matrix
+layout(buffer_reference, std430) readonly buffer JointBuffer{
+	mat4 joints[];
+};
+
+// Matches shader::GpuInstance
+struct Instance {
+	mat4 transform;
+	vec4 color;
+};
+
+layout(buffer_reference, std430) readonly buffer InstanceBuffer{
+	Instance instances[];
+};
+
+//push constants block, matches shader::GpuDrawPushConstants
+layout( push_constant ) uniform constants
+{
+	mat4 world_matrix;
+	VertexBuffer vertexBuffer;
+	InstanceBuffer instanceBuffer;
+	uint materialIndex; // read by mesh.frag
+	uint pad;
+	SkinBuffer skinBuffer;
+	JointBuffer jointBuffer;
+} PushConstants;
+
+void main()
+{
+	//load vertex data from device adress
+	Vertex     v        = PushConstants.vertexBuffer.vertices[gl_VertexIndex];
+	SkinVertex skin     = PushConstants.skinBuffer.skins[gl_VertexIndex];
+	Instance   instance = PushConstants.instanceBuffer.instances[gl_InstanceIndex];
+
+	mat4 skinMatrix =
+		skin.weights.x * PushConstants.jointBuffer.joints[skin.joints.x] +
+		skin.weights.y * PushConstants.jointBuffer.joints[skin.joints.y] +
+		skin.weights.z * PushConstants.jointBuffer.joints[skin.joints.z] +
+		skin.weights.w * PushConstants.jointBuffer.joints[skin.joints.w];
+
+	mat4 worldMatrix = PushConstants.world_matrix * instance.transform * skinMatrix;
+	vec4 worldPos    = worldMatrix * vec4(v.position, 1.0f);
+
+	//output data
+	gl_Position  = sceneData.viewproj * worldPos;
+	outColor     = v.color.xyz;
+	outUV.x      = v.uv_x;
+	outUV.y      = v.uv_y;
+	//note: assumes the joints don't have a non-uniform scale
+	outNormal    = mat3(worldMatrix) * v.normal;
+	outWorldPos  = worldPos.xyz;
+	outViewDepth = -(sceneData.view * worldPos).z;
+	outTangent   = vec4(mat3(worldMatrix) * v.tangent.xyz, v.tangent.w);
+	outInstanceColor = instance.color;
+}
@@ -0,0 +1,51 @@
+#version 450
+#extension GL_EXT_buffer_reference : require
+
+layout (location = 0) out vec2 outUV;
+layout (location = 1) out vec4 outTint;
+
+// Matches shader::GpuSprite
+struct Sprite {
+	vec4 positionSize; // xy: position, zw: size
+	vec4 uvRect;       // xy: min, zw: max
+	vec4 tint;
+	vec4 origin;       // xy: origin, z: cos(rotation), w: sin(rotation)
+};
+
+layout(buffer_reference, std430) readonly buffer SpriteBuffer {
+	Sprite sprites[];
+};
+
+// Matches shader::SpritePushConstants
+layout(push_constant) uniform constants
+{
+	mat4         viewProj;
+	SpriteBuffer spriteBuffer;
+	uint         textureIndex;
+	uint         samplerIndex;
+	vec4         outputParams; // x: output mode, y: HDR paper white in nits
+} PushConstants;
+
+// Two triangles, as corners of the sprite from the top-left (0, 0) to the bottom-right (1, 1)
+const vec2 corners[6] = vec2[](
+	vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(1.0, 1.0),
+	vec2(0.0, 0.0), vec2(1.0, 1.0), vec2(0.0, 1.0)
+);
+
+void main()
+{
+	// The first instance of each batch is its offset into the sprite buffer
+	Sprite sprite = PushConstants.spriteBuffer.sprites[gl_InstanceIndex];
+	vec2   corner = corners[gl_VertexIndex];
+
+	// World +y is up, while the corners go down the sprite
+	vec2 local = vec2(corner.x - sprite.origin.x, sprite.origin.y - corner.y) * sprite.positionSize.zw;
+
+	float c = sprite.origin.z;
+	float s = sprite.origin.w;
+	vec2 world = sprite.positionSize.xy + vec2(local.x * c - local.y * s, local.x * s + local.y * c);
+
+	gl_Position = PushConstants.viewProj * vec4(world, 0.0, 1.0);
+	outUV       = mix(sprite.uvRect.xy, sprite.uvRect.zw, corner);
+	outTint     = sprite.tint;
+}
@@ -0,0 +1,97 @@
+#version 450
+#extension GL_EXT_buffer_reference : require
+#extension GL_EXT_nonuniform_qualifier : require
+
+layout (location = 0) in vec2 inUV;
+layout (location = 1) in vec4 inColor;
+
+layout (location = 0) out vec4 outFragColor;
+
+#define OUTPUT_SDR    0 // sRGB encoded
+#define OUTPUT_HDR10  1 // Rec.2020 primaries, PQ encoded
+
+// Matches material_system::BINDLESS_SAMPLER_COUNT
+#define BINDLESS_SAMPLER_COUNT 2
+
+// The text pipelines only bind the bindless set, see material_system.rs
+layout(set = 0, binding = 1) uniform sampler   samplers[BINDLESS_SAMPLER_COUNT];
+layout(set = 0, binding = 2) uniform texture2D textures[];
+
+// Matches material_system::BINDLESS_SAMPLER_LINEAR
+#define SAMPLER_LINEAR 0
+
+#define MODE_BITMAP 0 // coverage in alpha
+#define MODE_SDF    1 // distance in alpha, 0.5 on the outline
+
+layout(buffer_reference, std430) readonly buffer GlyphBuffer {
+	vec4 data[];
+};
+
+// Matches shader::TextPushConstants
+layout(push_constant) uniform constants
+{
+	mat4        viewProj;
+	GlyphBuffer glyphBuffer;
+	uint        textureIndex;
+	uint        mode;
+	vec4        outputParams; // x: output mode, y: HDR paper white in nits
+} PushConstants;
+
+vec3 linear_to_srgb(vec3 color)
+{
+	vec3 low  = color * 12.92;
+	vec3 high = 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055;
+	return mix(high, low, lessThanEqual(color, vec3(0.0031308)));
+}
+
+// Same encoding as tonemap.comp
+vec3 linear_to_pq(vec3 normalizedNits)
+{
+	const float m1 = 0.1593017578125;
+	const float m2 = 78.84375;
+	const float c1 = 0.8359375;
+	const float c2 = 18.8515625;
+	const float c3 = 18.6875;
+
+	vec3 ym1 = pow(clamp(normalizedNits, 0.0, 1.0), vec3(m1));
+	return pow((c1 + c2 * ym1) / (1.0 + c3 * ym1), vec3(m2));
+}
+
+vec3 rec709_to_rec2020(vec3 color)
+{
+	const mat3 conversion = mat3(
+		0.6274, 0.0691, 0.0164,
+		0.3293, 0.9195, 0.0880,
+		0.0433, 0.0114, 0.8956);
+
+	return conversion * color;
+}
+
+void main()
+{
+	// The texture comes from the push constants, so the index is dynamically uniform
+	float value = texture(sampler2D(textures[PushConstants.textureIndex], samplers[SAMPLER_LINEAR]), inUV).a;
+
+	// Antialias the reconstructed edge over about a pixel on screen, whatever the text's scale
+	float alpha = value;
+	if (PushConstants.mode == MODE_SDF) {
+		float width = max(fwidth(value), 1e-4);
+		alpha = smoothstep(0.5 - width, 0.5 + width, value);
+	}
+
+	vec4 color = vec4(inColor.rgb, inColor.a * alpha);
+	if (color.a <= 0.0) {
+		discard;
+	}
+
+	// The text is drawn after tonemapping, so encode them like the tonemapper does. White is paper white.
+	vec3 encoded;
+	if (int(PushConstants.outputParams.x) == OUTPUT_HDR10) {
+		float paperWhite = PushConstants.outputParams.y;
+		encoded = linear_to_pq(rec709_to_rec2020(clamp(color.rgb, 0.0, 1.0)) * paperWhite / 10000.0);
+	} else {
+		encoded = linear_to_srgb(clamp(color.rgb, 0.0, 1.0));
+	}
+
+	outFragColor = vec4(encoded, color.a);
+}
@@ -0,0 +1,47 @@
+#version 450
+#extension GL_EXT_buffer_reference : require
+
+layout (location = 0) out vec2 outUV;
+layout (location = 1) out vec4 outColor;
+
+// Matches shader::GpuGlyph
+struct Glyph {
+	vec4 corner;  // xyz: top-left corner
+	vec4 axisX;   // xyz: from the top-left to the top-right corner
+	vec4 axisY;   // xyz: from the top-left to the bottom-left corner
+	vec4 uvRect;  // xy: min, zw: max
+	vec4 color;
+};
+
+layout(buffer_reference, std430) readonly buffer GlyphBuffer {
+	Glyph glyphs[];
+};
+
+// Matches shader::TextPushConstants
+layout(push_constant) uniform constants
+{
+	mat4        viewProj;
+	GlyphBuffer glyphBuffer;
+	uint        textureIndex;
+	uint        mode;
+	vec4        outputParams; // x: output mode, y: HDR paper white in nits
+} PushConstants;
+
+// Two triangles, as corners of the glyph from the top-left (0, 0) to the bottom-right (1, 1)
+const vec2 corners[6] = vec2[](
+	vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(1.0, 1.0),
+	vec2(0.0, 0.0), vec2(1.0, 1.0), vec2(0.0, 1.0)
+);
+
+void main()
+{
+	// The first instance of each batch is its offset into the glyph buffer
+	Glyph glyph  = PushConstants.glyphBuffer.glyphs[gl_InstanceIndex];
+	vec2  corner = corners[gl_VertexIndex];
+
+	vec3 position = glyph.corner.xyz + glyph.axisX.xyz * corner.x + glyph.axisY.xyz * corner.y;
+
+	gl_Position = PushConstants.viewProj * vec4(position, 1.0);
+	outUV       = mix(glyph.uvRect.xy, glyph.uvRect.zw, corner);
+	outColor    = glyph.color;
+}
@@ -0,0 +1,220 @@
+use std::ops;
+
+use crate::math::{ float3::*, quaternion::* };
+
+use super::pose::Pose;
+
+//
+// Animation Clips
+//
+// Keyframed joint transforms, following glTF animations. Each channel animates the translation, rotation or scale
+// of one joint, with its own keyframe times and interpolation:
+//   - Step:        holds each keyframe until the next one.
+//   - Linear:      lerps translation and scale, slerps rotation.
+//   - CubicSpline: Hermite spline through the keyframes. Every keyframe stores an in-tangent, the value and an
+//                  out-tangent, in that order.
+//
+// Sampling before the first keyframe holds the first value, and after the last keyframe holds the last value.
+//
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Interpolation {
+    Step,
+    Linear,
+    CubicSpline,
+}
+
+#[derive(Clone, Debug)]
+pub enum ChannelValues {
+    Translation(Vec<Float3>),
+    Rotation(Vec<Quaternion>),
+    Scale(Vec<Float3>),
+}
+
+#[derive(Clone, Debug)]
+pub struct AnimationChannel {
+    pub joint:         usize,         // into Skeleton::joints
+    pub interpolation: Interpolation,
+    pub times:         Vec<f32>,      // seconds, increasing
+    pub values:        ChannelValues, // one per keyframe, three per keyframe for CubicSpline
+}
+
+#[derive(Clone, Debug)]
+pub struct AnimationClip {
+    pub name:     String,
+    pub duration: f32, // seconds, the last keyframe of any channel
+    pub channels: Vec<AnimationChannel>,
+}
+
+impl AnimationClip {
+    pub fn new(name: &str, channels: Vec<AnimationChannel>) -> AnimationClip {
+        let duration = channels.iter()
+            .filter_map(|channel| channel.times.last())
+            .fold(0.0f32, |duration, time| duration.max(*time));
+
+        return AnimationClip{ name: name.to_string(), duration, channels };
+    }
+
+    /// Overwrites the joints the clip animates with their values at `time`, other joints keep their transform.
+    /// Looping clips wrap `time` around the duration.
+    pub fn sample(&self, time: f32, is_looping: bool, pose: &mut Pose) {
+        let time = if is_looping && self.duration > 0.0 { time.rem_euclid(self.duration) } else { time };
+
+        for channel in &self.channels {
+            let Some(joint) = pose.joints.get_mut(channel.joint) else {
+                continue;
+            };
+
+            match &channel.values {
+                ChannelValues::Translation(values) => {
+                    if let Some(value) = sample_channel(&channel.times, values, channel.interpolation, time, |a, b, t| a + (b - a) * t) {
+                        joint.translation = value;
+                    }
+                },
+                ChannelValues::Rotation(values) => {
+                    if let Some(value) = sample_channel(&channel.times, values, channel.interpolation, time, |a, b, t| a.slerp(b, t)) {
+                        joint.rotation = value.normalize();
+                    }
+                },
+                ChannelValues::Scale(values) => {
+                    if let Some(value) = sample_channel(&channel.times, values, channel.interpolation, time, |a, b, t| a + (b - a) * t) {
+                        joint.scale = value;
+                    }
+                },
+            }
+        }
+    }
+}
+
+// Returns None for a channel without keyframes, or with fewer values than its keyframes need
+fn sample_channel<T>(times: &[f32], values: &[T], interpolation: Interpolation, time: f32, lerp: impl Fn(T, T, f32) -> T) -> Option<T>
+    where T: Copy + ops::Add<T, Output = T> + ops::Mul<f32, Output = T>
+{
+    let values_per_key = if interpolation == Interpolation::CubicSpline { 3 } else { 1 };
+    if times.is_empty() || values.len() < times.len() * values_per_key {
+        return None;
+    }
+
+    // The value of a keyframe, skipping the tangents of cubic splines
+    let get_value = |key: usize| -> T { values[key * values_per_key + values_per_key / 2] };
+
+    // A NaN time, from a 0 / 0 playback rate for example, holds the first pose
+    let last = times.len() - 1;
+    if time.is_nan() || time <= times[0] {
+        return Some(get_value(0));
+    }
+    if time >= times[last] {
+        return Some(get_value(last));
+    }
+
+    // The keyframe at or before `time`, there is always one after it
+    let key = times.partition_point(|key_time| *key_time <= time).saturating_sub(1).min(last - 1);
+
+    let delta = times[key + 1] - times[key];
+    let t     = if delta > 0.0 { (time - times[key]) / delta } else { 0.0 };
+
+    return Some(match interpolation {
+        Interpolation::Step        => get_value(key),
+        Interpolation::Linear      => lerp(get_value(key), get_value(key + 1), t),
+        Interpolation::CubicSpline => {
+            let t2 = t * t;
+            let t3 = t2 * t;
+
+            let value_0       = values[key * 3 + 1];
+            let out_tangent_0 = values[key * 3 + 2];
+            let in_tangent_1  = values[key * 3 + 3];
+            let value_1       = values[key * 3 + 4];
+
+            value_0 * (2.0 * t3 - 3.0 * t2 + 1.0)
+                + out_tangent_0 * (delta * (t3 - 2.0 * t2 + t))
+                + value_1       * (-2.0 * t3 + 3.0 * t2)
+                + in_tangent_1  * (delta * (t3 - t2))
+        },
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-4;
+
+    fn make_channel(interpolation: Interpolation, times: &[f32], values: &[f32]) -> AnimationChannel {
+        return AnimationChannel{
+            joint:  0,
+            interpolation,
+            times:  times.to_vec(),
+            values: ChannelValues::Translation(values.iter().map(|x| Float3::new(*x, 0.0, 0.0)).collect()),
+        };
+    }
+
+    // The x translation of joint 0 after sampling the channel at `time`
+    fn sample_x(channel: &AnimationChannel, time: f32, is_looping: bool) -> f32 {
+        let clip = AnimationClip::new("test", vec![channel.clone()]);
+        let mut pose = Pose{ joints: vec![Default::default()] };
+        clip.sample(time, is_looping, &mut pose);
+        return pose.joints[0].translation.x;
+    }
+
+    #[test]
+    fn step_and_linear_hold_the_ends() {
+        let step   = make_channel(Interpolation::Step, &[1.0, 2.0, 4.0], &[10.0, 20.0, 40.0]);
+        let linear = make_channel(Interpolation::Linear, &[1.0, 2.0, 4.0], &[10.0, 20.0, 40.0]);
+
+        assert_eq!(AnimationClip::new("test", vec![step.clone()]).duration, 4.0);
+
+        assert_eq!(sample_x(&step, 0.0, false), 10.0);
+        assert_eq!(sample_x(&step, 1.5, false), 10.0);
+        assert_eq!(sample_x(&step, 2.0, false), 20.0);
+        assert_eq!(sample_x(&step, 3.9, false), 20.0);
+        assert_eq!(sample_x(&step, 9.0, false), 40.0);
+
+        assert_eq!(sample_x(&linear, 0.0, false), 10.0);
+        assert_eq!(sample_x(&linear, 1.5, false), 15.0);
+        assert_eq!(sample_x(&linear, 3.0, false), 30.0);
+        assert_eq!(sample_x(&linear, 9.0, false), 40.0);
+
+        // Looping wraps around the duration, including negative times
+        assert_eq!(sample_x(&linear, 5.5, true), 15.0);
+        assert_eq!(sample_x(&linear, -2.5, true), 15.0);
+    }
+
+    #[test]
+    fn cubic_splines_pass_through_the_keys() {
+        // In-tangent, value and out-tangent per key
+        let spline = make_channel(Interpolation::CubicSpline, &[0.0, 2.0], &[0.0, 1.0, 0.0, 0.0, 3.0, 0.0]);
+
+        assert_eq!(sample_x(&spline, 0.0, false), 1.0);
+        assert_eq!(sample_x(&spline, 2.0, false), 3.0);
+
+        // Flat tangents ease in and out, so the middle is halfway
+        assert!((sample_x(&spline, 1.0, false) - 2.0).abs() < EPSILON);
+        assert!(sample_x(&spline, 0.5, false) < 1.5);
+
+        // Tangents are per second, so they are scaled by the time between the keys: 2 * 2 * (t^3 - 2t^2 + t)
+        let sloped = make_channel(Interpolation::CubicSpline, &[0.0, 2.0], &[0.0, 0.0, 2.0, 0.0, 2.0, 0.0]);
+        assert!((sample_x(&sloped, 1.0, false) - 1.5).abs() < EPSILON);
+    }
+
+    #[test]
+    fn malformed_channels_are_skipped() {
+        // NaN holds the first key rather than indexing with it
+        let linear = make_channel(Interpolation::Linear, &[1.0, 2.0], &[10.0, 20.0]);
+        assert_eq!(sample_x(&linear, f32::NAN, false), 10.0);
+        assert_eq!(sample_x(&linear, f32::NAN, true), 10.0);
+
+        // Fewer values than keys, and cubic splines without their tangents, leave the joint alone
+        assert_eq!(sample_x(&make_channel(Interpolation::Linear, &[0.0, 1.0], &[5.0]), 0.5, false), 0.0);
+        assert_eq!(sample_x(&make_channel(Interpolation::CubicSpline, &[0.0, 1.0], &[5.0, 6.0]), 0.5, false), 0.0);
+        assert_eq!(sample_x(&make_channel(Interpolation::Linear, &[], &[]), 0.5, false), 0.0);
+
+        // Channels for joints the pose doesn't have
+        let mut channel = linear.clone();
+        channel.joint = 3;
+        assert_eq!(sample_x(&channel, 1.5, false), 0.0);
+
+        // Keys at the same time don't divide by zero
+        let repeated = make_channel(Interpolation::Linear, &[0.0, 1.0, 1.0, 2.0], &[0.0, 10.0, 20.0, 30.0]);
+        assert!(sample_x(&repeated, 1.0, false).is_finite());
+    }
+}
@@ -0,0 +1,30 @@
+use std::fmt;
+
+//
+// Animation Errors
+//
+// Errors from importing a skinned model. Like fonts, none of these are fatal to the engine, the game decides whether
+// it can run without the model.
+//
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AnimationError {
+    /// The glTF file, or one of the buffers it refers to, could not be read.
+    FileNotFound { path: String, reason: String },
+    /// The file isn't valid glTF, or an index or accessor points outside of the data.
+    InvalidGltf(String),
+    /// Valid glTF the engine can't import, such as sparse accessors or a model without a skin.
+    UnsupportedGltf(String),
+}
+
+impl fmt::Display for AnimationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            AnimationError::FileNotFound{ path, reason } => write!(f, "Failed to load {}: {}", path, reason),
+            AnimationError::InvalidGltf(reason)          => write!(f, "Invalid glTF: {}", reason),
+            AnimationError::UnsupportedGltf(reason)      => write!(f, "Unsupported glTF: {}", reason),
+        };
+    }
+}
+
+impl std::error::Error for AnimationError {}
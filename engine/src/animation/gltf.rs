@@ -0,0 +1,786 @@
+use std::path::Path;
+
+use crate::math::{ float3::*, float4::*, float4x4::*, quaternion::* };
+use crate::renderer::mesh::{ Vertex, SkinVertex };
+use crate::renderer::mesh_processing::generate_tangents;
+
+use super::clip::*;
+use super::error::AnimationError;
+use super::json::JsonValue;
+use super::skeleton::*;
+
+//
+// glTF Import
+//
+// Reads the skeleton, skinned meshes and animations of a glTF 2.0 model, either a .gltf document with external or
+// embedded (base64 data URI) buffers, or a binary .glb. Only what skinning needs is imported:
+//   - The first skin becomes the skeleton. Its joints keep the skin's order, which is the order JOINTS_0 refers to.
+//   - Every triangle primitive of a node skinned with it becomes a SkinnedMeshData, in the space of the skeleton.
+//   - Every animation becomes a clip, keeping the channels that target a joint. Morph target weights are skipped.
+//
+// Materials aren't imported, and neither are sparse accessors.
+//
+
+const GLB_MAGIC:      u32 = 0x46546C67; // "glTF"
+const GLB_CHUNK_JSON: u32 = 0x4E4F534A; // "JSON"
+const GLB_CHUNK_BIN:  u32 = 0x004E4942; // "BIN\0"
+
+const COMPONENT_BYTE:           u64 = 5120;
+const COMPONENT_UNSIGNED_BYTE:  u64 = 5121;
+const COMPONENT_SHORT:          u64 = 5122;
+const COMPONENT_UNSIGNED_SHORT: u64 = 5123;
+const COMPONENT_UNSIGNED_INT:   u64 = 5125;
+const COMPONENT_FLOAT:          u64 = 5126;
+
+const PRIMITIVE_TRIANGLES: usize = 4;
+
+/// One skinned primitive, ready to be sent with RenderCommand::CreateSkinnedMesh
+pub struct SkinnedMeshData {
+    pub name:     String,
+    pub vertices: Vec<Vertex>,
+    pub skin:     Vec<SkinVertex>, // indexed like the vertices
+    pub indices:  Vec<u32>,
+}
+
+pub struct SkinnedModel {
+    pub skeleton: Skeleton,
+    pub meshes:   Vec<SkinnedMeshData>,
+    pub clips:    Vec<AnimationClip>,
+}
+
+impl SkinnedModel {
+    /// Loads a .gltf or .glb file. External buffers are looked up next to the file.
+    pub fn from_file(path: &Path) -> Result<SkinnedModel, AnimationError> {
+        let data = match std::fs::read(path) {
+            Ok(data)   => data,
+            Err(error) => return Err(AnimationError::FileNotFound{ path: path.display().to_string(), reason: error.to_string() }),
+        };
+
+        let base_dir = path.parent().unwrap_or(Path::new(""));
+        return SkinnedModel::from_bytes(&data, base_dir);
+    }
+
+    /// Reads a .gltf document or a .glb file from memory. `base_dir` is where external buffers are looked up.
+    pub fn from_bytes(data: &[u8], base_dir: &Path) -> Result<SkinnedModel, AnimationError> {
+        let (json, glb_buffer) = if read_u32(data, 0) == Some(GLB_MAGIC) {
+            read_glb(data)?
+        } else {
+            (data, None)
+        };
+
+        let json     = std::str::from_utf8(json).map_err(|_| invalid("the document isn't UTF-8"))?;
+        let document = JsonValue::parse(json).map_err(|reason| invalid(&reason))?;
+
+        let mut buffers = Vec::<Vec<u8>>::new();
+        for (index, buffer) in document.get("buffers").map_or(&[][..], |buffers| buffers.as_array()).iter().enumerate() {
+            buffers.push(load_buffer(buffer, index, glb_buffer, base_dir)?);
+        }
+
+        let gltf = GltfDocument{ document: &document, buffers };
+        return gltf.import();
+    }
+
+    pub fn find_clip(&self, name: &str) -> Option<&AnimationClip> {
+        return self.clips.iter().find(|clip| clip.name == name);
+    }
+}
+
+fn invalid(reason: &str) -> AnimationError {
+    return AnimationError::InvalidGltf(reason.to_string());
+}
+
+fn unsupported(reason: &str) -> AnimationError {
+    return AnimationError::UnsupportedGltf(reason.to_string());
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    return Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]));
+}
+
+// Splits a .glb into its JSON chunk and its optional binary chunk
+fn read_glb(data: &[u8]) -> Result<(&[u8], Option<&[u8]>), AnimationError> {
+    let version = read_u32(data, 4).ok_or_else(|| invalid("truncated .glb header"))?;
+    if version != 2 {
+        return Err(unsupported(&format!("glTF version {}", version)));
+    }
+
+    let length = (read_u32(data, 8).ok_or_else(|| invalid("truncated .glb header"))? as usize).min(data.len());
+
+    let mut json   = None;
+    let mut binary = None;
+    let mut offset = 12;
+
+    while offset + 8 <= length {
+        let chunk_length = read_u32(data, offset).unwrap() as usize;
+        let chunk_type   = read_u32(data, offset + 4).unwrap();
+        let chunk        = data.get(offset + 8..offset + 8 + chunk_length).ok_or_else(|| invalid("truncated .glb chunk"))?;
+
+        match chunk_type {
+            GLB_CHUNK_JSON if json.is_none()   => json   = Some(chunk),
+            GLB_CHUNK_BIN  if binary.is_none() => binary = Some(chunk),
+            _                                  => {}, // unknown chunks are skipped
+        }
+
+        offset += 8 + chunk_length;
+    }
+
+    return Ok((json.ok_or_else(|| invalid("the .glb has no JSON chunk"))?, binary));
+}
+
+// A buffer's bytes: the .glb's binary chunk, a base64 data URI or a file next to the document
+fn load_buffer(buffer: &JsonValue, index: usize, glb_buffer: Option<&[u8]>, base_dir: &Path) -> Result<Vec<u8>, AnimationError> {
+    let byte_length = buffer.get("byteLength").and_then(|length| length.as_usize()).ok_or_else(|| invalid("a buffer has no byteLength"))?;
+
+    let data = match buffer.get("uri").and_then(|uri| uri.as_str()) {
+        None => match glb_buffer {
+            Some(glb_buffer) if index == 0 => glb_buffer.to_vec(),
+            _                              => return Err(invalid(&format!("buffer {} has no uri", index))),
+        },
+        Some(uri) if uri.starts_with("data:") => {
+            let (_, payload) = uri.split_once(";base64,").ok_or_else(|| unsupported("data URIs that aren't base64"))?;
+            decode_base64(payload).ok_or_else(|| invalid(&format!("buffer {} has an invalid data URI", index)))?
+        },
+        Some(uri) => {
+            let path = base_dir.join(uri);
+            match std::fs::read(&path) {
+                Ok(data)   => data,
+                Err(error) => return Err(AnimationError::FileNotFound{ path: path.display().to_string(), reason: error.to_string() }),
+            }
+        },
+    };
+
+    if data.len() < byte_length {
+        return Err(invalid(&format!("buffer {} is shorter than its byteLength", index)));
+    }
+
+    return Ok(data);
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut result = Vec::<u8>::with_capacity(text.len() * 3 / 4);
+    let mut bits   = 0u32;
+    let mut count  = 0;
+
+    for byte in text.bytes() {
+        let value = match byte {
+            b'A'..=b'Z'  => byte - b'A',
+            b'a'..=b'z'  => byte - b'a' + 26,
+            b'0'..=b'9'  => byte - b'0' + 52,
+            b'+' | b'-'  => 62,
+            b'/' | b'_'  => 63,
+            b'='         => break,
+            b' ' | b'\n' | b'\r' | b'\t' => continue,
+            _            => return None,
+        };
+
+        bits   = (bits << 6) | value as u32;
+        count += 6;
+
+        if count >= 8 {
+            count -= 8;
+            result.push((bits >> count) as u8);
+        }
+    }
+
+    return Some(result);
+}
+
+// An accessor resolved to the bytes of its buffer view
+struct Accessor<'a> {
+    data:           &'a [u8],
+    stride:         usize,
+    count:          usize,
+    components:     usize,
+    component_type: u64,
+    component_size: usize,
+    normalized:     bool,
+}
+
+impl<'a> Accessor<'a> {
+    fn get_component_bytes(&self, element: usize, component: usize) -> &'a [u8] {
+        let offset = element * self.stride + component * self.component_size;
+        return &self.data[offset..offset + self.component_size];
+    }
+
+    fn get_float(&self, element: usize, component: usize) -> f32 {
+        let bytes = self.get_component_bytes(element, component);
+
+        // Normalized integers map to [0, 1] or [-1, 1]
+        return match (self.component_type, self.normalized) {
+            (COMPONENT_FLOAT, _)                 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            (COMPONENT_BYTE, true)               => (bytes[0] as i8 as f32 / 127.0).max(-1.0),
+            (COMPONENT_UNSIGNED_BYTE, true)      => bytes[0] as f32 / 255.0,
+            (COMPONENT_SHORT, true)              => (i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32767.0).max(-1.0),
+            (COMPONENT_UNSIGNED_SHORT, true)     => u16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 65535.0,
+            (COMPONENT_BYTE, false)              => bytes[0] as i8 as f32,
+            (COMPONENT_SHORT, false)             => i16::from_le_bytes([bytes[0], bytes[1]]) as f32,
+            _                                    => self.get_uint(element, component) as f32,
+        };
+    }
+
+    fn get_uint(&self, element: usize, component: usize) -> u32 {
+        let bytes = self.get_component_bytes(element, component);
+
+        return match self.component_size {
+            1 => bytes[0] as u32,
+            2 => u16::from_le_bytes([bytes[0], bytes[1]]) as u32,
+            _ => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        };
+    }
+
+    fn read_floats<const N: usize>(&self) -> Vec<[f32; N]> {
+        return (0..self.count).map(|element| std::array::from_fn(|component| {
+            if component < self.components { self.get_float(element, component) } else { 0.0 }
+        })).collect();
+    }
+}
+
+struct GltfDocument<'a> {
+    document: &'a JsonValue,
+    buffers:  Vec<Vec<u8>>,
+}
+
+impl<'a> GltfDocument<'a> {
+    fn get_array(&self, key: &str) -> &'a [JsonValue] {
+        return self.document.get(key).map_or(&[], |values| values.as_array());
+    }
+
+    fn get_accessor(&self, index: usize) -> Result<Accessor<'_>, AnimationError> {
+        let accessor = self.get_array("accessors").get(index).ok_or_else(|| invalid(&format!("accessor {} doesn't exist", index)))?;
+
+        if accessor.get("sparse").is_some() {
+            return Err(unsupported("sparse accessors"));
+        }
+
+        let count          = accessor.get("count").and_then(|count| count.as_usize()).ok_or_else(|| invalid("an accessor has no count"))?;
+        let component_type = accessor.get("componentType").and_then(|value| value.as_usize()).ok_or_else(|| invalid("an accessor has no componentType"))? as u64;
+        let normalized     = accessor.get("normalized").and_then(|value| value.as_bool()).unwrap_or(false);
+        let byte_offset    = accessor.get("byteOffset").and_then(|value| value.as_usize()).unwrap_or(0);
+
+        let components = match accessor.get("type").and_then(|value| value.as_str()) {
+            Some("SCALAR") => 1,
+            Some("VEC2")   => 2,
+            Some("VEC3")   => 3,
+            Some("VEC4")   => 4,
+            Some("MAT4")   => 16,
+            Some(other)    => return Err(unsupported(&format!("accessors of type {}", other))),
+            None           => return Err(invalid("an accessor has no type")),
+        };
+
+        let component_size = match component_type {
+            COMPONENT_BYTE | COMPONENT_UNSIGNED_BYTE   => 1,
+            COMPONENT_SHORT | COMPONENT_UNSIGNED_SHORT => 2,
+            COMPONENT_UNSIGNED_INT | COMPONENT_FLOAT   => 4,
+            _                                          => return Err(invalid(&format!("unknown componentType {}", component_type))),
+        };
+
+        let element_size = components * component_size;
+
+        let Some(view_index) = accessor.get("bufferView").and_then(|view| view.as_usize()) else {
+            return Err(unsupported("accessors without a bufferView"));
+        };
+
+        let view        = self.get_array("bufferViews").get(view_index).ok_or_else(|| invalid(&format!("buffer view {} doesn't exist", view_index)))?;
+        let buffer      = view.get("buffer").and_then(|buffer| buffer.as_usize()).and_then(|buffer| self.buffers.get(buffer)).ok_or_else(|| invalid("a buffer view has no buffer"))?;
+        let view_offset = view.get("byteOffset").and_then(|value| value.as_usize()).unwrap_or(0);
+        let view_length = view.get("byteLength").and_then(|value| value.as_usize()).ok_or_else(|| invalid("a buffer view has no byteLength"))?;
+        let stride      = view.get("byteStride").and_then(|value| value.as_usize()).unwrap_or(element_size);
+
+        let view_end  = view_offset.checked_add(view_length).ok_or_else(|| invalid("a buffer view is outside of its buffer"))?;
+        let view_data = buffer.get(view_offset..view_end).ok_or_else(|| invalid("a buffer view is outside of its buffer"))?;
+        let data      = view_data.get(byte_offset..).ok_or_else(|| invalid("an accessor is outside of its buffer view"))?;
+
+        // The last element ends at (count - 1) * stride + element_size, which a hostile count or stride can overflow
+        let accessor_end = match count {
+            0 => Some(0),
+            _ => (count - 1).checked_mul(stride).and_then(|last| last.checked_add(element_size)),
+        };
+
+        if accessor_end.is_none_or(|end| end > data.len()) {
+            return Err(invalid("an accessor is outside of its buffer view"));
+        }
+
+        return Ok(Accessor{ data, stride, count, components, component_type, component_size, normalized });
+    }
+
+    fn get_node_transform(node: &JsonValue) -> JointTransform {
+        let read = |key: &str| -> Vec<f32> {
+            return node.get(key).map_or(Vec::new(), |values| values.as_array().iter().filter_map(|value| value.as_f64()).map(|value| value as f32).collect());
+        };
+
+        let matrix = read("matrix");
+        if matrix.len() == 16 {
+            let matrix: [f32; 16] = matrix.try_into().unwrap();
+            return decompose_matrix(&matrix);
+        }
+
+        let mut transform = JointTransform::default();
+
+        if let [x, y, z] = read("translation")[..] {
+            transform.translation = Float3::new(x, y, z);
+        }
+        if let [x, y, z, w] = read("rotation")[..] {
+            transform.rotation = Quaternion::new(x, y, z, w).normalize();
+        }
+        if let [x, y, z] = read("scale")[..] {
+            transform.scale = Float3::new(x, y, z);
+        }
+
+        return transform;
+    }
+
+    // The closest ancestor of `node` that is a joint of the skin, and the transform of the nodes on the way to it.
+    // Without a joint above it, the transform of every ancestor.
+    fn find_parent_joint(nodes: &[JsonValue], node_parents: &[Option<usize>], joint_nodes: &[usize], node: usize) -> Result<(Option<usize>, Float4x4), AnimationError> {
+        let mut transform = Float4x4::identity();
+        let mut ancestor  = node_parents[node];
+
+        for _ in 0..nodes.len() {
+            let Some(ancestor_node) = ancestor else {
+                return Ok((None, transform));
+            };
+
+            if let Some(joint) = joint_nodes.iter().position(|joint| *joint == ancestor_node) {
+                return Ok((Some(joint), transform));
+            }
+
+            transform = mul_rh(GltfDocument::get_node_transform(&nodes[ancestor_node]).get_matrix(), transform);
+            ancestor  = node_parents[ancestor_node];
+        }
+
+        return Err(invalid("the node hierarchy has a cycle"));
+    }
+
+    fn import(&self) -> Result<SkinnedModel, AnimationError> {
+        let nodes = self.get_array("nodes");
+        let skins = self.get_array("skins");
+
+        let Some(skin) = skins.first() else {
+            return Err(unsupported("models without a skin"));
+        };
+
+        if skins.len() > 1 {
+            println!("[WARN] :: SkinnedModel :: The model has {} skins, only the first one is imported.", skins.len());
+        }
+
+        let mut node_parents = vec![None::<usize>; nodes.len()];
+        for (index, node) in nodes.iter().enumerate() {
+            for child in node.get("children").map_or(&[][..], |children| children.as_array()) {
+                let child = child.as_usize().filter(|child| *child < nodes.len()).ok_or_else(|| invalid("a node has an invalid child"))?;
+                node_parents[child] = Some(index);
+            }
+        }
+
+        // Skeleton
+        //
+
+        let joint_nodes: Vec<usize> = skin.get("joints").map_or(&[][..], |joints| joints.as_array()).iter()
+            .map(|joint| joint.as_usize().filter(|joint| *joint < nodes.len()).ok_or_else(|| invalid("a skin has an invalid joint")))
+            .collect::<Result<_, _>>()?;
+
+        if joint_nodes.is_empty() {
+            return Err(invalid("the skin has no joints"));
+        }
+
+        let inverse_bind_matrices = match skin.get("inverseBindMatrices").and_then(|accessor| accessor.as_usize()) {
+            Some(accessor) => self.get_accessor(accessor)?.read_floats::<16>().iter().map(Float4x4::from_column_major).collect(),
+            None           => vec![Float4x4::identity(); joint_nodes.len()],
+        };
+
+        if inverse_bind_matrices.len() < joint_nodes.len() {
+            return Err(invalid("the skin has fewer inverse bind matrices than joints"));
+        }
+
+        let mut joints         = Vec::<Joint>::with_capacity(joint_nodes.len());
+        let mut root_transform = None::<Float4x4>;
+
+        for (index, &node) in joint_nodes.iter().enumerate() {
+            let (parent, parent_transform) = GltfDocument::find_parent_joint(nodes, &node_parents, &joint_nodes, node)?;
+
+            // The nodes above the first root joint, usually an armature node
+            let parent_transform = match parent {
+                Some(_) => parent_transform,
+                None    => {
+                    root_transform.get_or_insert(parent_transform);
+                    Float4x4::identity()
+                },
+            };
+
+            joints.push(Joint{
+                name:                nodes[node].get("name").and_then(|name| name.as_str()).map_or(format!("joint_{}", index), |name| name.to_string()),
+                parent,
+                parent_transform,
+                inverse_bind_matrix: inverse_bind_matrices[index],
+                rest:                GltfDocument::get_node_transform(&nodes[node]),
+            });
+        }
+
+        let root_transform = root_transform.unwrap_or(Float4x4::identity());
+
+        let has_cycle = joints.iter().enumerate().any(|(index, _)| {
+            let mut parent = joints[index].parent;
+            for _ in 0..joints.len() {
+                match parent {
+                    Some(next) => parent = joints[next].parent,
+                    None       => return false,
+                }
+            }
+            return true;
+        });
+
+        if has_cycle {
+            return Err(invalid("the joint hierarchy has a cycle"));
+        }
+
+        let skeleton = Skeleton::new(joints, root_transform);
+
+        // Meshes
+        //
+
+        let mut meshes = Vec::<SkinnedMeshData>::new();
+        for node in nodes.iter().filter(|node| node.get("skin").and_then(|skin| skin.as_usize()) == Some(0)) {
+            let Some(mesh) = node.get("mesh").and_then(|mesh| mesh.as_usize()) else {
+                continue;
+            };
+
+            let mesh      = self.get_array("meshes").get(mesh).ok_or_else(|| invalid(&format!("mesh {} doesn't exist", mesh)))?;
+            let mesh_name = mesh.get("name").and_then(|name| name.as_str()).unwrap_or("mesh");
+
+            for (index, primitive) in mesh.get("primitives").map_or(&[][..], |primitives| primitives.as_array()).iter().enumerate() {
+                let mode = primitive.get("mode").and_then(|mode| mode.as_usize()).unwrap_or(PRIMITIVE_TRIANGLES);
+                if mode != PRIMITIVE_TRIANGLES {
+                    println!("[WARN] :: SkinnedModel :: Skipping primitive {} of {}, only triangle lists are imported.", index, mesh_name);
+                    continue;
+                }
+
+                meshes.push(self.import_primitive(primitive, &format!("{}_{}", mesh_name, index), skeleton.get_joint_count())?);
+            }
+        }
+
+        // Animations
+        //
+
+        let mut clips = Vec::<AnimationClip>::new();
+        for (index, animation) in self.get_array("animations").iter().enumerate() {
+            let samplers = animation.get("samplers").map_or(&[][..], |samplers| samplers.as_array());
+
+            let mut channels = Vec::<AnimationChannel>::new();
+            for channel in animation.get("channels").map_or(&[][..], |channels| channels.as_array()) {
+                let target = channel.get("target");
+                let node   = target.and_then(|target| target.get("node")).and_then(|node| node.as_usize());
+                let path   = target.and_then(|target| target.get("path")).and_then(|path| path.as_str()).unwrap_or("");
+
+                // Channels on other nodes don't move the skeleton
+                let Some(joint) = node.and_then(|node| joint_nodes.iter().position(|joint| *joint == node)) else {
+                    continue;
+                };
+
+                let sampler = channel.get("sampler").and_then(|sampler| sampler.as_usize()).and_then(|sampler| samplers.get(sampler))
+                    .ok_or_else(|| invalid("an animation channel has an invalid sampler"))?;
+
+                let interpolation = match sampler.get("interpolation").and_then(|value| value.as_str()).unwrap_or("LINEAR") {
+                    "STEP"        => Interpolation::Step,
+                    "LINEAR"      => Interpolation::Linear,
+                    "CUBICSPLINE" => Interpolation::CubicSpline,
+                    other         => return Err(invalid(&format!("unknown interpolation {}", other))),
+                };
+
+                let input  = sampler.get("input").and_then(|input| input.as_usize()).ok_or_else(|| invalid("an animation sampler has no input"))?;
+                let output = sampler.get("output").and_then(|output| output.as_usize()).ok_or_else(|| invalid("an animation sampler has no output"))?;
+
+                let times: Vec<f32> = self.get_accessor(input)?.read_floats::<1>().iter().map(|time| time[0]).collect();
+                let output          = self.get_accessor(output)?;
+
+                let values = match path {
+                    "translation" => ChannelValues::Translation(output.read_floats::<3>().iter().map(|v| Float3::new(v[0], v[1], v[2])).collect()),
+                    "rotation"    => ChannelValues::Rotation(output.read_floats::<4>().iter().map(|v| Quaternion::new(v[0], v[1], v[2], v[3])).collect()),
+                    "scale"       => ChannelValues::Scale(output.read_floats::<3>().iter().map(|v| Float3::new(v[0], v[1], v[2])).collect()),
+                    _             => continue, // morph target weights
+                };
+
+                channels.push(AnimationChannel{ joint, interpolation, times, values });
+            }
+
+            let name = animation.get("name").and_then(|name| name.as_str()).map_or(format!("animation_{}", index), |name| name.to_string());
+            clips.push(AnimationClip::new(&name, channels));
+        }
+
+        return Ok(SkinnedModel{ skeleton, meshes, clips });
+    }
+
+    fn import_primitive(&self, primitive: &JsonValue, name: &str, joint_count: usize) -> Result<SkinnedMeshData, AnimationError> {
+        let attribute = |key: &str| primitive.get("attributes").and_then(|attributes| attributes.get(key)).and_then(|accessor| accessor.as_usize());
+
+        let positions = self.get_accessor(attribute("POSITION").ok_or_else(|| invalid(&format!("{} has no positions", name)))?)?.read_floats::<3>();
+
+        let (Some(joints_accessor), Some(weights_accessor)) = (attribute("JOINTS_0"), attribute("WEIGHTS_0")) else {
+            return Err(invalid(&format!("{} is skinned but has no JOINTS_0 or WEIGHTS_0", name)));
+        };
+
+        let joints  = self.get_accessor(joints_accessor)?;
+        let weights = self.get_accessor(weights_accessor)?;
+
+        // A vertex's 4 joints are read as 4 components, anything narrower would read the next vertex's joints
+        if joints.components != 4 || weights.components != 4 {
+            return Err(invalid(&format!("{} has JOINTS_0 or WEIGHTS_0 that aren't VEC4", name)));
+        }
+
+        let weights = weights.read_floats::<4>();
+
+        let vertex_count = positions.len();
+        if joints.count < vertex_count || weights.len() < vertex_count {
+            return Err(invalid(&format!("{} has fewer joints or weights than positions", name)));
+        }
+
+        let normals = attribute("NORMAL").map(|accessor| self.get_accessor(accessor)).transpose()?.map(|accessor| accessor.read_floats::<3>());
+        let uvs     = attribute("TEXCOORD_0").map(|accessor| self.get_accessor(accessor)).transpose()?.map(|accessor| accessor.read_floats::<2>());
+        let colors  = attribute("COLOR_0").map(|accessor| self.get_accessor(accessor)).transpose()?.map(|accessor| (accessor.components, accessor.read_floats::<4>()));
+        let tangent = attribute("TANGENT").map(|accessor| self.get_accessor(accessor)).transpose()?.map(|accessor| accessor.read_floats::<4>());
+
+        let mut vertices = Vec::<Vertex>::with_capacity(vertex_count);
+        let mut skin     = Vec::<SkinVertex>::with_capacity(vertex_count);
+
+        for index in 0..vertex_count {
+            let position = positions[index];
+            let normal   = normals.as_ref().and_then(|normals| normals.get(index)).copied().unwrap_or([0.0, 1.0, 0.0]);
+            let uv       = uvs.as_ref().and_then(|uvs| uvs.get(index)).copied().unwrap_or([0.0, 0.0]);
+
+            let color = match &colors {
+                Some((3, colors)) => colors.get(index).map_or(Float4::one(), |c| Float4::new(c[0], c[1], c[2], 1.0)),
+                Some((_, colors)) => colors.get(index).map_or(Float4::one(), |c| Float4::new(c[0], c[1], c[2], c[3])),
+                None              => Float4::one(),
+            };
+
+            vertices.push(Vertex{
+                position: Float3::new(position[0], position[1], position[2]),
+                uv_x:     uv[0],
+                normal:   Float3::new(normal[0], normal[1], normal[2]),
+                uv_y:     uv[1],
+                color,
+                tangent:  tangent.as_ref().and_then(|tangents| tangents.get(index)).map_or(Float4::zero(), |t| Float4::new(t[0], t[1], t[2], t[3])),
+            });
+
+            let mut vertex_joints  = [0u32; 4];
+            let mut vertex_weights = weights[index];
+
+            for slot in 0..4 {
+                vertex_joints[slot] = joints.get_uint(index, slot);
+                if vertex_joints[slot] as usize >= joint_count {
+                    return Err(invalid(&format!("{} refers to joint {}, the skin has {}", name, vertex_joints[slot], joint_count)));
+                }
+            }
+
+            // Exporters don't always normalize the weights exactly
+            let total = vertex_weights.iter().sum::<f32>();
+            if total > 0.0 {
+                vertex_weights = vertex_weights.map(|weight| weight / total);
+            } else {
+                vertex_weights = [1.0, 0.0, 0.0, 0.0];
+            }
+
+            skin.push(SkinVertex{
+                joints:  vertex_joints,
+                weights: Float4::new(vertex_weights[0], vertex_weights[1], vertex_weights[2], vertex_weights[3]),
+            });
+        }
+
+        let mut indices: Vec<u32> = match primitive.get("indices").and_then(|indices| indices.as_usize()) {
+            Some(accessor) => {
+                let accessor = self.get_accessor(accessor)?;
+                (0..accessor.count).map(|index| accessor.get_uint(index, 0)).collect()
+            },
+            None => (0..vertex_count as u32).collect(),
+        };
+
+        if indices.iter().any(|index| *index as usize >= vertex_count) {
+            return Err(invalid(&format!("{} has an index past its vertices", name)));
+        }
+
+        if tangent.is_none() {
+            // Vertices used by mirrored UVs are split, their skin has to follow
+            let sources = generate_tangents(&mut vertices, &mut indices);
+            skin = sources.iter().map(|source| skin[*source as usize]).collect();
+        }
+
+        return Ok(SkinnedMeshData{ name: name.to_string(), vertices, skin, indices });
+    }
+}
+
+// Splits a column-major TRS matrix back into its components. Assumes there is no shear.
+fn decompose_matrix(values: &[f32; 16]) -> JointTransform {
+    let x_axis = Float3::new(values[0], values[1], values[2]);
+    let y_axis = Float3::new(values[4], values[5], values[6]);
+    let z_axis = Float3::new(values[8], values[9], values[10]);
+
+    let mut scale = Float3::new(x_axis.length(), y_axis.length(), z_axis.length());
+
+    // A mirrored basis, flip one axis so the rest is a rotation
+    if x_axis.cross(y_axis).dot(z_axis) < 0.0 {
+        scale.x = -scale.x;
+    }
+
+    let safe_div = |axis: Float3, length: f32| if length != 0.0 { axis / length } else { axis };
+
+    return JointTransform{
+        translation: Float3::new(values[12], values[13], values[14]),
+        rotation:    Quaternion::from_rotation_axes(safe_div(x_axis, scale.x), safe_div(y_axis, scale.y), safe_div(z_axis, scale.z)),
+        scale,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::pose::Pose;
+
+    const EPSILON: f32 = 1e-4;
+
+    fn encode_base64(data: &[u8]) -> String {
+        const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+        let mut text = String::new();
+        for chunk in data.chunks(3) {
+            let bits = (chunk[0] as u32) << 16 | (*chunk.get(1).unwrap_or(&0) as u32) << 8 | *chunk.get(2).unwrap_or(&0) as u32;
+            for sextet in 0..4 {
+                if sextet <= chunk.len() {
+                    text.push(ALPHABET[(bits >> (18 - 6 * sextet) & 0x3F) as usize] as char);
+                } else {
+                    text.push('=');
+                }
+            }
+        }
+
+        return text;
+    }
+
+    /// One embedded buffer, with a buffer view and an accessor per add_accessor
+    #[derive(Default)]
+    struct TestDocument {
+        data:      Vec<u8>,
+        views:     Vec<String>,
+        accessors: Vec<String>,
+    }
+
+    impl TestDocument {
+        fn add_accessor(&mut self, bytes: &[u8], component_type: u64, accessor_type: &str, count: usize) -> usize {
+            self.views.push(format!(r#"{{ "buffer": 0, "byteOffset": {}, "byteLength": {} }}"#, self.data.len(), bytes.len()));
+            self.accessors.push(format!(r#"{{ "bufferView": {}, "componentType": {}, "type": "{}", "count": {} }}"#, self.views.len() - 1, component_type, accessor_type, count));
+
+            self.data.extend_from_slice(bytes);
+            self.data.resize(self.data.len().next_multiple_of(4), 0);
+            return self.accessors.len() - 1;
+        }
+
+        fn add_floats(&mut self, values: &[f32], accessor_type: &str, count: usize) -> usize {
+            let bytes: Vec<u8> = values.iter().flat_map(|value| value.to_le_bytes()).collect();
+            return self.add_accessor(&bytes, COMPONENT_FLOAT, accessor_type, count);
+        }
+
+        /// `members` are the document's other top level members: nodes, skins, meshes and animations
+        fn import(&self, members: &str) -> Result<SkinnedModel, AnimationError> {
+            let json = format!(
+                r#"{{ "asset": {{ "version": "2.0" }}, "buffers": [{{ "byteLength": {}, "uri": "data:application/octet-stream;base64,{}" }}], "bufferViews": [{}], "accessors": [{}], {} }}"#,
+                self.data.len(), encode_base64(&self.data), self.views.join(", "), self.accessors.join(", "), members);
+
+            return SkinnedModel::from_bytes(json.as_bytes(), Path::new(""));
+        }
+    }
+
+    fn get_position(matrix: Float4x4) -> Float3 {
+        let position = matrix.translate_point(Float4::new(0.0, 0.0, 0.0, 1.0));
+        return Float3::new(position.x, position.y, position.z);
+    }
+
+    fn assert_near(actual: Float3, expected: Float3) {
+        assert!((actual - expected).length() < EPSILON, "{:?} isn't {:?}", actual, expected);
+    }
+
+    fn is_invalid(result: Result<SkinnedModel, AnimationError>) -> bool {
+        return matches!(result, Err(AnimationError::InvalidGltf(_)));
+    }
+
+    // A triangle skinned to joint 0 of a one joint skin, with the given JOINTS_0 accessor type
+    fn import_triangle(joints_type: &str) -> Result<SkinnedModel, AnimationError> {
+        let mut document = TestDocument::default();
+        let positions = document.add_floats(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0], "VEC3", 3);
+        let joints    = document.add_accessor(&[0u8; 12], COMPONENT_UNSIGNED_BYTE, joints_type, 3);
+        let weights   = document.add_floats(&[1.0, 0.0, 0.0, 0.0].repeat(3), "VEC4", 3);
+
+        return document.import(&format!(
+            r#""nodes": [{{ "name": "bone" }}, {{ "mesh": 0, "skin": 0 }}], "skins": [{{ "joints": [0] }}],
+               "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": {}, "JOINTS_0": {}, "WEIGHTS_0": {} }} }}] }}]"#,
+            positions, joints, weights));
+    }
+
+    #[test]
+    fn imports_skinned_triangles() {
+        let model = import_triangle("VEC4").unwrap();
+
+        assert_eq!(model.skeleton.get_joint_count(), 1);
+        assert_eq!(model.meshes.len(), 1);
+        assert_eq!(model.meshes[0].indices, [0, 1, 2]);
+        assert!(model.meshes[0].skin.iter().all(|vertex| vertex.joints == [0, 0, 0, 0] && vertex.weights.x == 1.0));
+    }
+
+    #[test]
+    fn nodes_between_joints_move_the_child_joint() {
+        let mut document = TestDocument::default();
+        let times  = document.add_floats(&[0.0, 1.0], "SCALAR", 2);
+        let values = document.add_floats(&[0.0, 0.0, 3.0, 0.0, 0.0, 5.0], "VEC3", 2);
+
+        // armature -> root joint -> offset node -> child joint
+        let model = document.import(&format!(
+            r#""nodes": [
+                 {{ "name": "armature", "children": [1], "translation": [0, 0, -5] }},
+                 {{ "name": "root", "children": [2], "translation": [0, 1, 0] }},
+                 {{ "name": "offset", "children": [3], "translation": [2, 0, 0] }},
+                 {{ "name": "child", "translation": [0, 0, 3] }}
+               ],
+               "skins": [{{ "joints": [1, 3] }}],
+               "animations": [{{ "channels": [{{ "sampler": 0, "target": {{ "node": 3, "path": "translation" }} }}],
+                                 "samplers": [{{ "input": {}, "output": {} }}] }}]"#,
+            times, values)).unwrap();
+
+        let skeleton = &model.skeleton;
+        assert_eq!(skeleton.joints[1].parent, Some(0));
+        assert_near(get_position(skeleton.joints[1].parent_transform), Float3::new(2.0, 0.0, 0.0));
+        assert_near(get_position(skeleton.root_transform), Float3::new(0.0, 0.0, -5.0));
+
+        let rest = Pose::new(skeleton).get_world_matrices(skeleton);
+        assert_near(get_position(rest[0]), Float3::new(0.0, 1.0, -5.0));
+        assert_near(get_position(rest[1]), Float3::new(2.0, 1.0, -2.0));
+
+        // The animation replaces the child's own translation, the offset node still applies
+        let mut pose = Pose::new(skeleton);
+        model.clips[0].sample(1.0, false, &mut pose);
+        assert_near(get_position(pose.get_world_matrices(skeleton)[1]), Float3::new(2.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn rejects_malformed_models() {
+        // Joints narrower than VEC4 would read the next vertex's joints
+        assert!(is_invalid(import_triangle("VEC2")));
+        assert!(is_invalid(import_triangle("SCALAR")));
+
+        // A count or stride whose last element overflows, rather than wrapping back into the buffer
+        let mut document = TestDocument::default();
+        document.add_floats(&[0.0; 16], "MAT4", 1);
+        document.accessors[0] = document.accessors[0].replace(r#""count": 1"#, r#""count": 1e30"#);
+        assert!(is_invalid(document.import(r#""nodes": [{ }], "skins": [{ "joints": [0], "inverseBindMatrices": 0 }]"#)));
+
+        let mut document = TestDocument::default();
+        document.add_floats(&[0.0; 32], "MAT4", 2);
+        document.views[0] = document.views[0].replace(" }", r#", "byteStride": 4611686018427387904 }"#);
+        assert!(is_invalid(document.import(r#""nodes": [{ }, { }], "skins": [{ "joints": [0, 1], "inverseBindMatrices": 0 }]"#)));
+
+        // Nodes that aren't joints looping above a joint
+        let document = TestDocument::default();
+        assert!(is_invalid(document.import(r#""nodes": [{ }, { "children": [0, 2] }, { "children": [1] }], "skins": [{ "joints": [0] }]"#)));
+
+        // Joints and indices past the skin and the vertices
+        assert!(is_invalid(document.import(r#""nodes": [{ }], "skins": [{ "joints": [4] }]"#)));
+        assert!(is_invalid(document.import(r#""nodes": [{ }], "skins": [{ "joints": [] }]"#)));
+        assert!(matches!(document.import(r#""nodes": [{ }]"#), Err(AnimationError::UnsupportedGltf(_))));
+    }
+}
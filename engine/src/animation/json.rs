@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+
+//
+// JSON
+//
+// Just enough JSON to read a glTF document. Numbers are kept as f64 and strings only handle the escapes JSON
+// defines. Objects are unordered, glTF never depends on the order of keys.
+//
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(HashMap<String, JsonValue>),
+}
+
+impl JsonValue {
+    pub fn parse(text: &str) -> Result<JsonValue, String> {
+        let mut parser = JsonParser{ bytes: text.as_bytes(), offset: 0 };
+
+        let value = parser.parse_value()?;
+        parser.skip_whitespace();
+
+        if parser.offset != parser.bytes.len() {
+            return Err(format!("Unexpected data after the document at byte {}", parser.offset));
+        }
+
+        return Ok(value);
+    }
+
+    /// The member of an object, None for anything else or a missing key
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        return match self {
+            JsonValue::Object(members) => members.get(key),
+            _                          => None,
+        };
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        return match self {
+            JsonValue::Number(value) => Some(*value),
+            _                        => None,
+        };
+    }
+
+    pub fn as_usize(&self) -> Option<usize> {
+        return match self {
+            JsonValue::Number(value) if *value >= 0.0 && value.fract() == 0.0 => Some(*value as usize),
+            _                                                                  => None,
+        };
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        return match self {
+            JsonValue::Bool(value) => Some(*value),
+            _                      => None,
+        };
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        return match self {
+            JsonValue::String(value) => Some(value.as_str()),
+            _                        => None,
+        };
+    }
+
+    /// The elements of an array. Anything else is treated as an empty array, which is what glTF means by a
+    /// missing array.
+    pub fn as_array(&self) -> &[JsonValue] {
+        return match self {
+            JsonValue::Array(values) => values.as_slice(),
+            _                        => &[],
+        };
+    }
+}
+
+struct JsonParser<'a> {
+    bytes:  &'a [u8],
+    offset: usize,
+}
+
+impl<'a> JsonParser<'a> {
+    fn skip_whitespace(&mut self) {
+        while self.offset < self.bytes.len() && matches!(self.bytes[self.offset], b' ' | b'\t' | b'\n' | b'\r') {
+            self.offset += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        return self.bytes.get(self.offset).copied();
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), String> {
+        self.skip_whitespace();
+
+        if self.peek() != Some(byte) {
+            return Err(format!("Expected '{}' at byte {}", byte as char, self.offset));
+        }
+
+        self.offset += 1;
+        return Ok(());
+    }
+
+    fn expect_literal(&mut self, literal: &str, value: JsonValue) -> Result<JsonValue, String> {
+        if !self.bytes[self.offset..].starts_with(literal.as_bytes()) {
+            return Err(format!("Unexpected character at byte {}", self.offset));
+        }
+
+        self.offset += literal.len();
+        return Ok(value);
+    }
+
+    fn parse_value(&mut self) -> Result<JsonValue, String> {
+        self.skip_whitespace();
+
+        return match self.peek() {
+            Some(b'{')                        => self.parse_object(),
+            Some(b'[')                        => self.parse_array(),
+            Some(b'"')                        => Ok(JsonValue::String(self.parse_string()?)),
+            Some(b't')                        => self.expect_literal("true",  JsonValue::Bool(true)),
+            Some(b'f')                        => self.expect_literal("false", JsonValue::Bool(false)),
+            Some(b'n')                        => self.expect_literal("null",  JsonValue::Null),
+            Some(b'-') | Some(b'0'..=b'9')    => self.parse_number(),
+            Some(_)                           => Err(format!("Unexpected character at byte {}", self.offset)),
+            None                              => Err("Unexpected end of the document".to_string()),
+        };
+    }
+
+    fn parse_object(&mut self) -> Result<JsonValue, String> {
+        self.expect(b'{')?;
+
+        let mut members = HashMap::<String, JsonValue>::new();
+
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.offset += 1;
+            return Ok(JsonValue::Object(members));
+        }
+
+        loop {
+            self.skip_whitespace();
+            let key = self.parse_string()?;
+
+            self.expect(b':')?;
+            let value = self.parse_value()?;
+            members.insert(key, value);
+
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.offset += 1,
+                Some(b'}') => { self.offset += 1; break; },
+                _          => return Err(format!("Expected ',' or '}}' at byte {}", self.offset)),
+            }
+        }
+
+        return Ok(JsonValue::Object(members));
+    }
+
+    fn parse_array(&mut self) -> Result<JsonValue, String> {
+        self.expect(b'[')?;
+
+        let mut values = Vec::<JsonValue>::new();
+
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.offset += 1;
+            return Ok(JsonValue::Array(values));
+        }
+
+        loop {
+            values.push(self.parse_value()?);
+
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.offset += 1,
+                Some(b']') => { self.offset += 1; break; },
+                _          => return Err(format!("Expected ',' or ']' at byte {}", self.offset)),
+            }
+        }
+
+        return Ok(JsonValue::Array(values));
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        if self.peek() != Some(b'"') {
+            return Err(format!("Expected a string at byte {}", self.offset));
+        }
+        self.offset += 1;
+
+        let mut result = String::new();
+        let mut run    = self.offset; // start of the bytes copied as-is
+
+        loop {
+            match self.peek() {
+                None       => return Err("Unterminated string".to_string()),
+                Some(b'"') => {
+                    result.push_str(self.get_run(run)?);
+                    self.offset += 1;
+                    return Ok(result);
+                },
+                Some(b'\\') => {
+                    result.push_str(self.get_run(run)?);
+                    self.offset += 1;
+
+                    let escape = self.peek().ok_or("Unterminated string")?;
+                    self.offset += 1;
+
+                    match escape {
+                        b'"'  => result.push('"'),
+                        b'\\' => result.push('\\'),
+                        b'/'  => result.push('/'),
+                        b'b'  => result.push('\u{8}'),
+                        b'f'  => result.push('\u{c}'),
+                        b'n'  => result.push('\n'),
+                        b'r'  => result.push('\r'),
+                        b't'  => result.push('\t'),
+                        b'u'  => {
+                            let mut code = self.parse_hex4()?;
+
+                            // A surrogate pair, the low half is another \u escape
+                            if (0xD800..0xDC00).contains(&code) && self.bytes[self.offset..].starts_with(b"\\u") {
+                                self.offset += 2;
+                                let low = self.parse_hex4()?;
+                                code = 0x10000 + ((code - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF);
+                            }
+
+                            result.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                        },
+                        _ => return Err(format!("Invalid escape at byte {}", self.offset - 1)),
+                    }
+
+                    run = self.offset;
+                },
+                Some(_) => self.offset += 1,
+            }
+        }
+    }
+
+    fn get_run(&self, start: usize) -> Result<&'a str, String> {
+        return std::str::from_utf8(&self.bytes[start..self.offset]).map_err(|_| "Invalid UTF-8 in a string".to_string());
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, String> {
+        let digits = self.bytes.get(self.offset..self.offset + 4).ok_or("Truncated \\u escape")?;
+        let digits = std::str::from_utf8(digits).map_err(|_| "Invalid \\u escape".to_string())?;
+        let code   = u32::from_str_radix(digits, 16).map_err(|_| format!("Invalid \\u escape at byte {}", self.offset))?;
+
+        self.offset += 4;
+        return Ok(code);
+    }
+
+    fn parse_number(&mut self) -> Result<JsonValue, String> {
+        let start = self.offset;
+
+        while let Some(byte) = self.peek() {
+            if !matches!(byte, b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') {
+                break;
+            }
+            self.offset += 1;
+        }
+
+        let text = std::str::from_utf8(&self.bytes[start..self.offset]).unwrap_or("");
+        return text.parse::<f64>().map(JsonValue::Number).map_err(|_| format!("Invalid number at byte {}", start));
+    }
+}
@@ -0,0 +1,7 @@
+pub mod clip;
+pub mod error;
+pub mod gltf;
+pub mod pose;
+pub mod skeleton;
+
+mod json;
@@ -0,0 +1,269 @@
+use std::rc::Rc;
+
+use crate::math::float4x4::*;
+
+use super::clip::AnimationClip;
+use super::skeleton::*;
+
+//
+// Poses
+//
+// The local transform of every joint of a skeleton at one point in time. A pose is sampled from clips, blended with
+// other poses, then flattened into the joint palette the skinned vertex shader reads: one matrix per joint, the
+// joint's world transform times its inverse bind matrix.
+//
+// AnimationPlayer drives the poses of one skinned mesh. It plays a clip, and crossfades to the next one by blending
+// the outgoing clip's pose into the incoming clip's pose over the fade.
+//
+
+#[derive(Clone, Debug)]
+pub struct Pose {
+    pub joints: Vec<JointTransform>, // indexed like Skeleton::joints
+}
+
+impl Pose {
+    /// The skeleton's rest pose
+    pub fn new(skeleton: &Skeleton) -> Pose {
+        return Pose{ joints: skeleton.joints.iter().map(|joint| joint.rest).collect() };
+    }
+
+    /// Blends towards `other` joint by joint, a `weight` of 0 is self and 1 is other
+    pub fn blend(&self, other: &Pose, weight: f32) -> Pose {
+        let weight = weight.clamp(0.0, 1.0);
+        return Pose{ joints: self.joints.iter().zip(&other.joints).map(|(a, b)| a.blend(b, weight)).collect() };
+    }
+
+    /// The world transform of every joint, relative to the skinned mesh
+    pub fn get_world_matrices(&self, skeleton: &Skeleton) -> Vec<Float4x4> {
+        let mut world = vec![Float4x4::identity(); skeleton.get_joint_count()];
+
+        for &joint in skeleton.get_evaluation_order() {
+            let local = self.joints.get(joint).map_or(skeleton.joints[joint].rest, |transform| *transform).get_matrix();
+
+            world[joint] = match skeleton.joints[joint].parent {
+                Some(parent) => mul_rh(mul_rh(world[parent], skeleton.joints[joint].parent_transform), local),
+                None         => mul_rh(skeleton.root_transform, local),
+            };
+        }
+
+        return world;
+    }
+
+    /// The joint palette, ready to be sent with RenderCommand::UpdateJointMatrices
+    pub fn get_joint_matrices(&self, skeleton: &Skeleton) -> Vec<Float4x4> {
+        let mut matrices = self.get_world_matrices(skeleton);
+
+        for (matrix, joint) in matrices.iter_mut().zip(&skeleton.joints) {
+            *matrix = mul_rh(*matrix, joint.inverse_bind_matrix);
+        }
+
+        return matrices;
+    }
+}
+
+#[derive(Clone)]
+struct PlayingClip {
+    clip:       Rc<AnimationClip>,
+    time:       f32, // seconds into the clip
+    is_looping: bool,
+}
+
+/// Plays clips on a skeleton, crossfading between them
+#[derive(Clone)]
+pub struct AnimationPlayer {
+    pub speed: f32, // multiplies the time passed to update, 1 plays clips at their own pace
+
+    current:       Option<PlayingClip>,
+    previous:      Option<PlayingClip>, // fading out
+    fade_time:     f32,
+    fade_duration: f32,
+}
+
+impl AnimationPlayer {
+    pub fn new() -> AnimationPlayer {
+        return AnimationPlayer{
+            speed:         1.0,
+            current:       None,
+            previous:      None,
+            fade_time:     0.0,
+            fade_duration: 0.0,
+        };
+    }
+
+    /// Switches to the clip straight away, from its start
+    pub fn play(&mut self, clip: Rc<AnimationClip>, is_looping: bool) {
+        self.current  = Some(PlayingClip{ clip, time: 0.0, is_looping });
+        self.previous = None;
+    }
+
+    /// Starts the clip and fades the current clip out over `duration` seconds. The outgoing clip keeps playing
+    /// while it fades. Crossfading during a crossfade drops the clip that was already fading out.
+    pub fn crossfade(&mut self, clip: Rc<AnimationClip>, is_looping: bool, duration: f32) {
+        if duration <= 0.0 || self.current.is_none() {
+            self.play(clip, is_looping);
+            return;
+        }
+
+        self.previous      = self.current.take();
+        self.current       = Some(PlayingClip{ clip, time: 0.0, is_looping });
+        self.fade_time     = 0.0;
+        self.fade_duration = duration;
+    }
+
+    pub fn get_current_clip(&self) -> Option<&Rc<AnimationClip>> {
+        return self.current.as_ref().map(|playing| &playing.clip);
+    }
+
+    pub fn is_crossfading(&self) -> bool {
+        return self.previous.is_some();
+    }
+
+    /// Advances the clips by `delta_time` seconds
+    pub fn update(&mut self, delta_time: f32) {
+        let delta_time = delta_time * self.speed;
+
+        for playing in [&mut self.current, &mut self.previous].into_iter().flatten() {
+            playing.time += delta_time;
+            if !playing.is_looping {
+                playing.time = playing.time.min(playing.clip.duration);
+            }
+        }
+
+        if self.previous.is_some() {
+            self.fade_time += delta_time;
+            if self.fade_time >= self.fade_duration {
+                self.previous = None;
+            }
+        }
+    }
+
+    /// The blended pose of the clips. Joints the clips don't animate stay in the rest pose.
+    pub fn evaluate(&self, skeleton: &Skeleton) -> Pose {
+        let mut pose = Pose::new(skeleton);
+
+        let Some(current) = &self.current else {
+            return pose;
+        };
+
+        current.clip.sample(current.time, current.is_looping, &mut pose);
+
+        if let Some(previous) = &self.previous {
+            let mut previous_pose = Pose::new(skeleton);
+            previous.clip.sample(previous.time, previous.is_looping, &mut previous_pose);
+
+            pose = previous_pose.blend(&pose, self.fade_time / self.fade_duration);
+        }
+
+        return pose;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::{ float3::*, float4::*, quaternion::* };
+    use super::super::clip::*;
+
+    const EPSILON: f32 = 1e-4;
+
+    fn make_joint(parent: Option<usize>, translation: Float3) -> Joint {
+        return Joint{
+            name:                String::from("joint"),
+            parent,
+            parent_transform:    Float4x4::identity(),
+            inverse_bind_matrix: Float4x4::identity(),
+            rest:                JointTransform{ translation, ..JointTransform::default() },
+        };
+    }
+
+    fn get_position(matrix: Float4x4) -> Float3 {
+        let position = matrix.translate_point(Float4::new(0.0, 0.0, 0.0, 1.0));
+        return Float3::new(position.x, position.y, position.z);
+    }
+
+    fn assert_near(actual: Float3, expected: Float3) {
+        assert!((actual - expected).length() < EPSILON, "{:?} isn't {:?}", actual, expected);
+    }
+
+    // A clip moving joint 0 along x from `from` to `to` over a second
+    fn make_clip(from: f32, to: f32) -> Rc<AnimationClip> {
+        let channel = AnimationChannel{
+            joint:         0,
+            interpolation: Interpolation::Linear,
+            times:         vec![0.0, 1.0],
+            values:        ChannelValues::Translation(vec![Float3::new(from, 0.0, 0.0), Float3::new(to, 0.0, 0.0)]),
+        };
+
+        return Rc::new(AnimationClip::new("test", vec![channel]));
+    }
+
+    #[test]
+    fn world_matrices_follow_the_hierarchy() {
+        // The child comes before its parent, the evaluation order puts the parent first
+        let mut joints = vec![
+            make_joint(Some(1), Float3::new(0.0, 2.0, 0.0)),
+            make_joint(None, Float3::new(1.0, 0.0, 0.0)),
+        ];
+        joints[0].inverse_bind_matrix = Float4x4::get_translate_matrix(Float4::new(-1.0, -2.0, 0.0, 1.0));
+
+        let skeleton = Skeleton::new(joints, Float4x4::get_translate_matrix(Float4::new(0.0, 0.0, 5.0, 1.0)));
+        assert_eq!(skeleton.get_evaluation_order(), [1, 0]);
+
+        let pose  = Pose::new(&skeleton);
+        let world = pose.get_world_matrices(&skeleton);
+        assert_near(get_position(world[1]), Float3::new(1.0, 0.0, 5.0));
+        assert_near(get_position(world[0]), Float3::new(1.0, 2.0, 5.0));
+
+        // The inverse bind matrix takes the vertex into the joint's space first
+        let palette = pose.get_joint_matrices(&skeleton);
+        assert_near(get_position(palette[0]), Float3::new(0.0, 0.0, 5.0));
+    }
+
+    #[test]
+    fn blending_clamps_the_weight() {
+        let skeleton = Skeleton::new(vec![make_joint(None, Float3::zero())], Float4x4::identity());
+
+        let a = Pose::new(&skeleton);
+        let mut b = Pose::new(&skeleton);
+        b.joints[0].translation = Float3::new(4.0, 0.0, 0.0);
+        b.joints[0].rotation    = Quaternion::from_axis_angle(90.0, Float3::new(0.0, 1.0, 0.0));
+
+        assert_near(a.blend(&b, 0.25).joints[0].translation, Float3::new(1.0, 0.0, 0.0));
+        assert_near(a.blend(&b, 2.0).joints[0].translation, Float3::new(4.0, 0.0, 0.0));
+        assert_near(a.blend(&b, -1.0).joints[0].translation, Float3::zero());
+
+        let rotation = a.blend(&b, 1.0).joints[0].rotation;
+        assert!((rotation.dot(b.joints[0].rotation).abs() - 1.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn player_crossfades_between_clips() {
+        let skeleton   = Skeleton::new(vec![make_joint(None, Float3::zero())], Float4x4::identity());
+        let mut player = AnimationPlayer::new();
+
+        // Nothing playing is the rest pose
+        assert_near(player.evaluate(&skeleton).joints[0].translation, Float3::zero());
+
+        // Clips that don't loop stop at their end
+        player.play(make_clip(0.0, 10.0), false);
+        player.update(0.5);
+        assert_near(player.evaluate(&skeleton).joints[0].translation, Float3::new(5.0, 0.0, 0.0));
+        player.update(2.0);
+        assert_near(player.evaluate(&skeleton).joints[0].translation, Float3::new(10.0, 0.0, 0.0));
+
+        // Halfway through the fade, halfway between the outgoing clip's end and the incoming clip's start
+        player.crossfade(make_clip(20.0, 20.0), true, 0.5);
+        assert!(player.is_crossfading());
+        player.update(0.25);
+        assert_near(player.evaluate(&skeleton).joints[0].translation, Float3::new(15.0, 0.0, 0.0));
+
+        player.update(0.25);
+        assert!(!player.is_crossfading());
+        assert_near(player.evaluate(&skeleton).joints[0].translation, Float3::new(20.0, 0.0, 0.0));
+
+        // A zero length fade switches straight away
+        player.crossfade(make_clip(-1.0, -1.0), true, 0.0);
+        assert!(!player.is_crossfading());
+        assert_near(player.evaluate(&skeleton).joints[0].translation, Float3::new(-1.0, 0.0, 0.0));
+    }
+}
@@ -0,0 +1,102 @@
+use crate::math::{ float3::*, float4x4::*, quaternion::* };
+
+//
+// Skeletons
+//
+// A hierarchy of joints, each with a transform relative to its parent. Skinned vertices are bound to up to four
+// joints, and are moved by the joint's current world transform times its inverse bind matrix, which takes the
+// vertex from the mesh's space into the joint's space at bind time.
+//
+// Joints are indexed the way the skin's vertices refer to them, which doesn't have to put parents first. The
+// skeleton keeps an order that does, so a pose can be evaluated in a single pass.
+//
+
+/// A joint's transform relative to its parent, kept as separate components so poses can be blended
+#[derive(Clone, Copy, Debug)]
+pub struct JointTransform {
+    pub translation: Float3,
+    pub rotation:    Quaternion,
+    pub scale:       Float3,
+}
+
+impl Default for JointTransform {
+    fn default() -> Self {
+        Self{
+            translation: Float3::zero(),
+            rotation:    Quaternion::identity(),
+            scale:       Float3::one(),
+        }
+    }
+}
+
+impl JointTransform {
+    pub fn get_matrix(&self) -> Float4x4 {
+        return Float4x4::get_transform_matrix(self.translation, self.rotation, self.scale);
+    }
+
+    /// Blends towards `other`, a `weight` of 0 is self and 1 is other
+    pub fn blend(&self, other: &JointTransform, weight: f32) -> JointTransform {
+        return JointTransform{
+            translation: self.translation + (other.translation - self.translation) * weight,
+            rotation:    self.rotation.nlerp(other.rotation, weight),
+            scale:       self.scale + (other.scale - self.scale) * weight,
+        };
+    }
+}
+
+#[derive(Clone)]
+pub struct Joint {
+    pub name:                String,
+    pub parent:              Option<usize>, // None for the roots of the skeleton
+    pub parent_transform:    Float4x4,      // the nodes between the parent and the joint that aren't joints, usually identity
+    pub inverse_bind_matrix: Float4x4,
+    pub rest:                JointTransform, // the joint's transform when nothing animates it
+}
+
+#[derive(Clone)]
+pub struct Skeleton {
+    pub joints:         Vec<Joint>,
+    pub root_transform: Float4x4, // applied above the root joints, the transform of the nodes the skeleton hangs from
+
+    order: Vec<usize>, // every joint, parents before their children
+}
+
+impl Skeleton {
+    /// Panics if a joint's parent is out of range or the parents form a cycle.
+    pub fn new(joints: Vec<Joint>, root_transform: Float4x4) -> Skeleton {
+        let mut order    = Vec::<usize>::with_capacity(joints.len());
+        let mut children = vec![Vec::<usize>::new(); joints.len()];
+
+        for (index, joint) in joints.iter().enumerate() {
+            match joint.parent {
+                Some(parent) => children[parent].push(index),
+                None         => order.push(index),
+            }
+        }
+
+        // Breadth first from the roots
+        let mut next = 0;
+        while next < order.len() {
+            let joint = order[next];
+            order.extend_from_slice(&children[joint]);
+            next += 1;
+        }
+
+        assert!(order.len() == joints.len(), "The joint hierarchy has a cycle.");
+
+        return Skeleton{ joints, root_transform, order };
+    }
+
+    pub fn get_joint_count(&self) -> usize {
+        return self.joints.len();
+    }
+
+    pub fn find_joint(&self, name: &str) -> Option<usize> {
+        return self.joints.iter().position(|joint| joint.name == name);
+    }
+
+    /// Every joint index, parents before their children
+    pub fn get_evaluation_order(&self) -> &[usize] {
+        return &self.order;
+    }
+}
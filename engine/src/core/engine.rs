@@ -1,12 +1,20 @@
 
 use std::borrow::BorrowMut;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell, RefMut};
 use std::path::PathBuf;
 use std::ptr;
+use std::time::Instant;
 
+use crate::animation::{ error::AnimationError, gltf::SkinnedModel };
+use crate::font::{ error::FontError, truetype::Font };
+use crate::image::{ error::ImageError, hdr::HdrImage };
 use crate::window;
 use crate::renderer::{
     command_buffer::*,
+    culling::CullingStats,
+    debug_draw::DebugDraw,
+    deferred::RenderPath,
+    error::RenderError,
     mesh::Vertex,
     system::{RenderSystem, RendererCreateInfo},
     thread::*,
@@ -29,6 +37,9 @@ pub struct GameInfo {
     pub window_width:  u32,
     pub window_height: u32,
     pub manifest_dir:  std::path::PathBuf,
+    pub gpu_index:     Option<usize>, // if None, the renderer picks the best available GPU
+    pub prefer_hdr:    bool,          // present in HDR10 when the display supports it, otherwise SDR
+    pub render_path:   RenderPath,    // forward or deferred shading, fixed for the lifetime of the renderer
 }
 
 pub struct DefaultGame {}
@@ -45,10 +56,12 @@ pub struct Engine {
     client_window: RefCell<window::Window>,
     render_thread: RenderThread,
     asset_system:  AssetSystem,
+    culling_stats: Cell<CullingStats>, // from the last frame the renderer finished
+    debug_draw:    RefCell<DebugDraw>,
 }
 
 impl Engine {
-    pub fn new(game_info: GameInfo) -> Engine {
+    pub fn new(game_info: GameInfo) -> Result<Engine, RenderError> {
         let game = Box::new(DefaultGame{});
 
         let window_system = window::WindowSystem::new();
@@ -59,37 +72,46 @@ impl Engine {
         );
 
         let render_thread = create_render_thread(RendererCreateInfo{
-            surface: client_window.get_native_surface(),
-        });
+            surface:     client_window.get_native_surface(),
+            gpu_index:   game_info.gpu_index,
+            prefer_hdr:  game_info.prefer_hdr,
+            render_path: game_info.render_path,
+        })?;
 
         let (width, height) = client_window.get_framebuffer_size();
-        render_thread.on_resize(width, height);
+        render_thread.on_resize(width, height)?;
 
         let ig_ctx = unsafe { igCreateContext(std::ptr::null_mut()) };
 
-        return Engine{
+        return Ok(Engine{
             game: RefCell::new(game),
             window_system,
             client_window: RefCell::new(client_window),
             render_thread,
             asset_system: AssetSystem::new(game_info.manifest_dir),
-        }
+            culling_stats: Cell::new(CullingStats::default()),
+            debug_draw: RefCell::new(DebugDraw::new()),
+        });
     }
 
     pub fn register_game(&self, game: Box<dyn Game>) {
         self.game.replace(game);
     }
 
-    pub fn run(&self) {
+    /// Runs the game until the window closes or the game asks to quit. Recoverable render errors are logged and
+    /// the game keeps running, any other render error stops the game and is returned to the caller.
+    pub fn run(&self) -> Result<(), RenderError> {
         let mut game = self.game.borrow_mut();
 
         // run post-game engine setup
         let mut frame_index: usize = 0;
+        let mut render_error: Option<RenderError> = None;
+        let mut renderer_shut_down = false;
 
         // initialize the game
         let mut game_res = game.on_init();
         if !game_res {
-            return;
+            return self.render_thread.destroy();
         }
 
         //use std::time::{Duration, Instant};
@@ -111,14 +133,42 @@ impl Engine {
             // Process any waiting messages from the renderer
             //
             let mut last_frame_rendered = false;
-            while let Some(msg) = self.render_thread.recieve_message(false) {
+            loop {
+                let msg = match self.render_thread.recieve_message(false) {
+                    Ok(Some(msg)) => msg,
+                    Ok(None)      => break,
+                    Err(error)    => { render_error = Some(error); break; },
+                };
+
                 match msg {
-                    RenderThreadResponse::RenderFrameDone   => last_frame_rendered = true,
-                    RenderThreadResponse::RendererShutdown  => todo!(),
-                    RenderThreadResponse::SubmitCommandList => todo!(),
+                    RenderThreadResponse::RendererReady     => {},
+                    RenderThreadResponse::RenderFrameDone(stats) => {
+                        self.culling_stats.set(stats);
+                        last_frame_rendered = true;
+                    },
+                    RenderThreadResponse::RendererShutdown  => {
+                        println!("[WARN] :: Engine::run :: The renderer shut down, stopping the game.");
+                        renderer_shut_down = true;
+                        break;
+                    },
+                    // command lists are submitted without waiting on the renderer, so there is nothing to do here.
+                    RenderThreadResponse::SubmitCommandList => {},
+                    RenderThreadResponse::RendererError(error) => {
+                        if error.is_recoverable() {
+                            println!("[WARN] :: Engine::run :: Recoverable render error: {}", error);
+                        } else {
+                            println!("[ERROR] :: Engine::run :: Render error: {}", error);
+                            render_error = Some(error);
+                            break;
+                        }
+                    },
                 }
             }
 
+            if render_error.is_some() || renderer_shut_down {
+                break;
+            }
+
             game_res = game.on_update();
             if !game_res {
                 break;
@@ -150,7 +200,17 @@ impl Engine {
                 break;
             }
 
-            self.render_thread.render_frame(frame_index);
+            // Send the debug lines every frame, even when there are none, so the last frame's lines are cleared
+            let debug_lines = self.debug_draw.borrow_mut().flush(Instant::now());
+
+            let mut debug_commands = RenderCommandBuffer::default();
+            debug_commands.add_command(RenderCommand::UpdateDebugLines(debug_lines));
+            self.submit_render_command_buffer(debug_commands);
+
+            if let Err(error) = self.render_thread.render_frame(frame_index) {
+                render_error = Some(error);
+                break;
+            }
 
             // this should be the "editor_render_external_windows()" - render imgui viewports
             // unsafe {
@@ -169,15 +229,54 @@ impl Engine {
             frame_index += 1;
         }
 
-        self.render_thread.destroy();
+        // The render thread is gone if it disconnected or already shut down, so there is nothing left to shut down.
+        if !renderer_shut_down && render_error != Some(RenderError::RenderThreadDisconnected) {
+            self.render_thread.destroy()?;
+        }
+
+        return match render_error {
+            Some(error) => Err(error),
+            None        => Ok(()),
+        };
     }
 
     pub fn get_asset_dir(&self, drive: AssetDrive) -> PathBuf {
         return self.asset_system.get_dir(drive);
     }
 
+    /// Loads a TrueType font from `path`, relative to the drive's directory. See font::atlas to draw with it.
+    pub fn load_font(&self, drive: AssetDrive, path: &str) -> Result<Font, FontError> {
+        return Font::from_file(&self.asset_system.get_dir(drive).join(path));
+    }
+
+    /// Loads an equirectangular Radiance HDR image from `path`, relative to the drive's directory, as an environment
+    /// map for RenderCommand::UpdateEnvironment.
+    pub fn load_environment_map(&self, drive: AssetDrive, path: &str) -> Result<EnvironmentMapInfo, ImageError> {
+        let image = HdrImage::from_file(&self.asset_system.get_dir(drive).join(path))?;
+        return Ok(EnvironmentMapInfo::from_equirectangular(image));
+    }
+
+    /// Loads the skeleton, skinned meshes and animations of a glTF model from `path`, relative to the drive's
+    /// directory. See the animation module to pose it.
+    pub fn load_skinned_model(&self, drive: AssetDrive, path: &str) -> Result<SkinnedModel, AnimationError> {
+        return SkinnedModel::from_file(&self.asset_system.get_dir(drive).join(path));
+    }
+
+    /// Meshes drawn and culled in the last frame the renderer finished, see CullingStats.
+    pub fn get_culling_stats(&self) -> CullingStats {
+        return self.culling_stats.get();
+    }
+
+    /// World-space debug lines, drawn in the next frame. See DebugDraw.
+    pub fn debug_draw(&self) -> RefMut<'_, DebugDraw> {
+        return self.debug_draw.borrow_mut();
+    }
+
     pub fn submit_render_command_buffer(&self, cmd: RenderCommandBuffer) {
-        self.render_thread.submit_command_buffer(cmd);
+        // Failures are picked up by run() the next time it checks on the render thread.
+        if let Err(error) = self.render_thread.submit_command_buffer(cmd) {
+            println!("[WARN] :: Engine::submit_render_command_buffer :: {}", error);
+        }
     }
 
     pub fn register_window_event(&self, ev_type: window::WindowEventType, listener: window::EventListener) {
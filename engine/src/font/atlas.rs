@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+
+use crate::math::float2::*;
+
+use super::error::FontError;
+use super::raster::*;
+use super::truetype::Font;
+
+//
+// Glyph Atlases
+//
+// A font's glyphs rasterized once, at a single pixel size, and packed into one RGBA8 texture. The glyphs are white,
+// with either their coverage or a signed distance field in alpha, so the texture is uploaded with
+// TextureFormat::Rgba8Unorm and tinted when drawn.
+//   - Bitmap: sharp at the rasterized size, blurry when scaled up.
+//   - SDF:    stays sharp when scaled up, edges are reconstructed in text.frag from the distance.
+//
+// Text is laid out left to right, one glyph per character, with the font's kerning. Laid out text is in text space:
+// pixels at the atlas' pixel size, from the top-left of the first line, with +y down.
+//
+
+/// Printable ASCII, the default set of characters in an atlas
+pub const ASCII_CHARACTERS: &str = " !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+
+// Empty texels between glyphs, so filtering doesn't bleed one glyph into the next
+const GLYPH_GAP: u32 = 1;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GlyphRasterization {
+    Bitmap,
+    Sdf { spread: f32 }, // pixels from the outline where the distance field saturates
+}
+
+#[derive(Clone, Debug)]
+pub struct FontAtlasSettings {
+    pub pixel_size:    f32, // em size the glyphs are rasterized at
+    pub rasterization: GlyphRasterization,
+    pub width:         u32,
+    pub height:        u32,
+    pub characters:    String, // characters missing from the atlas are drawn with the font's missing glyph
+}
+
+impl Default for FontAtlasSettings {
+    fn default() -> Self {
+        Self{
+            pixel_size:    48.0,
+            rasterization: GlyphRasterization::Sdf{ spread: 6.0 },
+            width:         512,
+            height:        512,
+            characters:    ASCII_CHARACTERS.to_string(),
+        }
+    }
+}
+
+/// A glyph quad of laid out text, in text space
+#[derive(Clone, Copy, Debug)]
+pub struct GlyphQuad {
+    pub min:    Float2,
+    pub max:    Float2,
+    pub uv_min: Float2,
+    pub uv_max: Float2,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct AtlasGlyph {
+    uv_min:  Float2,
+    uv_max:  Float2,
+    offset:  Float2, // from the pen position on the baseline to the top-left of the quad
+    size:    Float2, // zero for glyphs without an outline
+    advance: f32,
+}
+
+pub struct FontAtlas {
+    pub pixels:        Vec<u8>, // width * height RGBA texels, the CreateTextureInfo pixels
+    pub width:         u32,
+    pub height:        u32,
+    pub pixel_size:    f32,
+    pub rasterization: GlyphRasterization,
+
+    ascender:    f32, // pixels
+    line_height: f32, // pixels
+    glyphs:      HashMap<char, AtlasGlyph>,
+    missing:     AtlasGlyph,               // the font's missing glyph, for characters not in the atlas
+    kerning:     HashMap<(char, char), f32>, // pixels, only the pairs with kerning
+}
+
+// A rasterized glyph waiting to be packed
+struct PendingGlyph {
+    character: Option<char>, // None for the missing glyph
+    values:    Vec<f32>,     // coverage or distance, row by row
+    width:     u32,
+    height:    u32,
+    offset:    Float2,
+    advance:   f32,
+}
+
+impl FontAtlas {
+    pub fn new(font: &Font, settings: &FontAtlasSettings) -> Result<FontAtlas, FontError> {
+        let scale   = settings.pixel_size / font.get_units_per_em();
+        let metrics = font.get_line_metrics();
+
+        let padding = match settings.rasterization {
+            GlyphRasterization::Bitmap         => 1,
+            GlyphRasterization::Sdf{ spread }  => spread.ceil() as u32 + 1,
+        };
+
+        let mut characters: Vec<char> = settings.characters.chars().collect();
+        characters.sort_unstable();
+        characters.dedup();
+
+        let mut pending = Vec::<PendingGlyph>::with_capacity(characters.len() + 1);
+        pending.push(rasterize_glyph(font, 0, None, scale, padding, settings.rasterization));
+
+        for character in &characters {
+            match font.get_glyph_index(*character) {
+                Some(glyph) => pending.push(rasterize_glyph(font, glyph, Some(*character), scale, padding, settings.rasterization)),
+                None        => println!("[WARN] :: FontAtlas :: The font has no glyph for {:?}, the missing glyph is drawn instead.", character),
+            }
+        }
+
+        // Shelf packing, tallest glyphs first so each shelf wastes little height
+        let mut order: Vec<usize> = (0..pending.len()).collect();
+        order.sort_by(|a, b| pending[*b].height.cmp(&pending[*a].height));
+
+        let byte_count = (settings.width as usize).checked_mul(settings.height as usize)
+            .and_then(|texel_count| texel_count.checked_mul(4))
+            .ok_or(FontError::AtlasTooLarge{ width: settings.width, height: settings.height })?;
+
+        let mut pixels = vec![0u8; byte_count];
+        for texel in pixels.chunks_exact_mut(4) {
+            texel[0] = 255;
+            texel[1] = 255;
+            texel[2] = 255;
+        }
+
+        let texel_size = Float2::new(1.0 / settings.width as f32, 1.0 / settings.height as f32);
+
+        let mut glyphs       = HashMap::<char, AtlasGlyph>::with_capacity(characters.len());
+        let mut missing      = None;
+        let mut shelf_x      = GLYPH_GAP;
+        let mut shelf_y      = GLYPH_GAP;
+        let mut shelf_height = 0;
+
+        for index in order {
+            let glyph = &pending[index];
+
+            if shelf_x + glyph.width + GLYPH_GAP > settings.width {
+                shelf_x      = GLYPH_GAP;
+                shelf_y     += shelf_height + GLYPH_GAP;
+                shelf_height = 0;
+            }
+
+            if shelf_x + glyph.width + GLYPH_GAP > settings.width || shelf_y + glyph.height + GLYPH_GAP > settings.height {
+                return Err(FontError::AtlasFull{ width: settings.width, height: settings.height });
+            }
+
+            for y in 0..glyph.height {
+                for x in 0..glyph.width {
+                    let value = glyph.values[(y * glyph.width + x) as usize];
+                    let texel = (shelf_y + y) as usize * settings.width as usize + (shelf_x + x) as usize;
+                    pixels[texel * 4 + 3] = (value * 255.0 + 0.5) as u8;
+                }
+            }
+
+            let atlas_glyph = AtlasGlyph{
+                uv_min:  Float2::new(shelf_x as f32 * texel_size.x, shelf_y as f32 * texel_size.y),
+                uv_max:  Float2::new((shelf_x + glyph.width) as f32 * texel_size.x, (shelf_y + glyph.height) as f32 * texel_size.y),
+                offset:  glyph.offset,
+                size:    Float2::new(glyph.width as f32, glyph.height as f32),
+                advance: glyph.advance,
+            };
+
+            match glyph.character {
+                Some(character) => { glyphs.insert(character, atlas_glyph); },
+                None            => missing = Some(atlas_glyph),
+            }
+
+            shelf_x     += glyph.width + GLYPH_GAP;
+            shelf_height = shelf_height.max(glyph.height);
+        }
+
+        let mut kerning = HashMap::<(char, char), f32>::new();
+        for left in &characters {
+            for right in &characters {
+                let (Some(left_glyph), Some(right_glyph)) = (font.get_glyph_index(*left), font.get_glyph_index(*right)) else {
+                    continue;
+                };
+
+                let value = font.get_kerning(left_glyph, right_glyph);
+                if value != 0.0 {
+                    kerning.insert((*left, *right), value * scale);
+                }
+            }
+        }
+
+        return Ok(FontAtlas{
+            pixels,
+            width:         settings.width,
+            height:        settings.height,
+            pixel_size:    settings.pixel_size,
+            rasterization: settings.rasterization,
+            ascender:      metrics.ascender * scale,
+            line_height:   metrics.get_line_height() * scale,
+            glyphs,
+            missing:       missing.expect("the missing glyph is always rasterized"),
+            kerning,
+        });
+    }
+
+    /// Distance between the baselines of two lines, in text space
+    pub fn get_line_height(&self) -> f32 {
+        return self.line_height;
+    }
+
+    /// One quad per visible character. '\n' starts a new line.
+    pub fn layout_text(&self, text: &str) -> Vec<GlyphQuad> {
+        let mut quads = Vec::<GlyphQuad>::with_capacity(text.len());
+
+        self.walk_text(text, |pen, glyph| {
+            if glyph.size.x > 0.0 && glyph.size.y > 0.0 {
+                let min = Float2::new(pen.x + glyph.offset.x, pen.y + glyph.offset.y);
+
+                quads.push(GlyphQuad{
+                    min,
+                    max:    Float2::new(min.x + glyph.size.x, min.y + glyph.size.y),
+                    uv_min: glyph.uv_min,
+                    uv_max: glyph.uv_max,
+                });
+            }
+        });
+
+        return quads;
+    }
+
+    /// The width of the longest line and the height of every line, in text space
+    pub fn measure_text(&self, text: &str) -> Float2 {
+        let mut width = 0.0f32;
+
+        self.walk_text(text, |pen, glyph| {
+            width = width.max(pen.x + glyph.advance);
+        });
+
+        let line_count = text.split('\n').count();
+        return Float2::new(width, line_count as f32 * self.line_height);
+    }
+
+    // Calls `visit` with the pen position on the baseline and the glyph of every character except line breaks
+    fn walk_text(&self, text: &str, mut visit: impl FnMut(Float2, &AtlasGlyph)) {
+        let mut pen_x    = 0.0;
+        let mut pen_y    = self.ascender;
+        let mut previous = None::<char>;
+
+        for character in text.chars() {
+            match character {
+                '\n' => {
+                    pen_x    = 0.0;
+                    pen_y   += self.line_height;
+                    previous = None;
+                    continue;
+                },
+                '\r' => continue,
+                _    => {},
+            }
+
+            if let Some(previous) = previous {
+                pen_x += self.kerning.get(&(previous, character)).copied().unwrap_or(0.0);
+            }
+
+            let glyph = self.glyphs.get(&character).unwrap_or(&self.missing);
+            visit(Float2::new(pen_x, pen_y), glyph);
+
+            pen_x   += glyph.advance;
+            previous = Some(character);
+        }
+    }
+}
+
+// Rasterizes the glyph into its own bitmap, with `padding` empty texels around the outline
+fn rasterize_glyph(font: &Font, glyph: u16, character: Option<char>, scale: f32, padding: u32, rasterization: GlyphRasterization) -> PendingGlyph {
+    let advance = font.get_glyph_advance(glyph) * scale;
+    let outline = font.get_glyph_outline(glyph);
+
+    let points = outline.contours.iter().flatten();
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (f32::MAX, f32::MAX, f32::MIN, f32::MIN);
+    for point in points {
+        min_x = min_x.min(point.x);
+        min_y = min_y.min(point.y);
+        max_x = max_x.max(point.x);
+        max_y = max_y.max(point.y);
+    }
+
+    if outline.is_empty() || min_x >= max_x || min_y >= max_y {
+        return PendingGlyph{ character, values: Vec::new(), width: 0, height: 0, offset: Float2::zero(), advance };
+    }
+
+    let width  = ((max_x - min_x) * scale).ceil() as u32 + 2 * padding;
+    let height = ((max_y - min_y) * scale).ceil() as u32 + 2 * padding;
+
+    // The outline's top-left corner lands at (padding, padding)
+    let origin   = Float2::new(padding as f32 - min_x * scale, padding as f32 + max_y * scale);
+    let segments = flatten_outline(&outline, scale, origin);
+
+    let values = match rasterization {
+        GlyphRasterization::Bitmap        => rasterize_coverage(&segments, width as usize, height as usize),
+        GlyphRasterization::Sdf{ spread } => compute_distance_field(&segments, width as usize, height as usize, spread),
+    };
+
+    return PendingGlyph{
+        character,
+        values,
+        width,
+        height,
+        offset: Float2::new(min_x * scale - padding as f32, -max_y * scale - padding as f32),
+        advance,
+    };
+}
@@ -0,0 +1,36 @@
+use std::fmt;
+
+//
+// Font Errors
+//
+// Errors from loading a TrueType font or building a glyph atlas from it. None of these are fatal to the engine,
+// the game decides whether it can run without the font.
+//
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FontError {
+    /// The font file could not be read.
+    FileNotFound { path: String, reason: String },
+    /// The file isn't a TrueType font, or a table the engine needs is missing or truncated.
+    InvalidFont(String),
+    /// A valid font the engine can't read, such as a font collection or a font with CFF outlines.
+    UnsupportedFont(String),
+    /// The glyphs don't fit in an atlas of this size.
+    AtlasFull { width: u32, height: u32 },
+    /// The atlas' texels can't be addressed in memory.
+    AtlasTooLarge { width: u32, height: u32 },
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            FontError::FileNotFound{ path, reason }   => write!(f, "Failed to load font {}: {}", path, reason),
+            FontError::InvalidFont(reason)            => write!(f, "Invalid font: {}", reason),
+            FontError::UnsupportedFont(reason)        => write!(f, "Unsupported font: {}", reason),
+            FontError::AtlasFull{ width, height }     => write!(f, "The glyphs don't fit in a {}x{} atlas", width, height),
+            FontError::AtlasTooLarge{ width, height } => write!(f, "A {}x{} atlas is too large to allocate", width, height),
+        };
+    }
+}
+
+impl std::error::Error for FontError {}
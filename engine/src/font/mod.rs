@@ -0,0 +1,5 @@
+pub mod atlas;
+pub mod error;
+pub mod truetype;
+
+mod raster;
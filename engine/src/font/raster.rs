@@ -0,0 +1,321 @@
+use crate::math::float2::*;
+
+use super::truetype::{ GlyphOutline, OutlinePoint };
+
+//
+// Glyph Rasterization
+//
+// Outlines are flattened into line segments in pixel space (+y down), then either:
+//   - Coverage: each line adds its signed area to an accumulation buffer, and a running sum along each row gives
+//               the fraction of every pixel inside the glyph. Exact for lines, so curves are only as good as their
+//               flattening.
+//   - Distance: the distance from every pixel center to the nearest segment, negative outside the glyph. Brute
+//               force, which is fine for the handful of glyphs in an atlas.
+//
+
+// Curves are split into at most this many lines
+const MAX_CURVE_SEGMENTS: usize = 16;
+
+/// A line of a flattened outline, in pixels
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Segment {
+    pub p0: Float2,
+    pub p1: Float2,
+}
+
+/// Flattens the outline into lines. Font units map to pixels with `scale`, and the outline's (0, 0) lands at
+/// `origin` with +y flipped to point down.
+pub(crate) fn flatten_outline(outline: &GlyphOutline, scale: f32, origin: Float2) -> Vec<Segment> {
+    let to_pixels = |point: &OutlinePoint| Float2::new(origin.x + point.x * scale, origin.y - point.y * scale);
+
+    let mut segments = Vec::<Segment>::new();
+
+    for contour in &outline.contours {
+        let point_count = contour.len();
+        if point_count < 2 {
+            continue;
+        }
+
+        // Start on an on-curve point, or between the last and first points if they are both off the curve
+        let start_index = contour.iter().position(|point| point.on_curve);
+        let start = match start_index {
+            Some(index) => to_pixels(&contour[index]),
+            None        => get_midpoint(to_pixels(&contour[point_count - 1]), to_pixels(&contour[0])),
+        };
+
+        let (first, remaining) = match start_index {
+            Some(index) => (index + 1, point_count - 1),
+            None        => (0, point_count),
+        };
+
+        let mut current = start;
+        let mut control: Option<Float2> = None;
+
+        for offset in 0..remaining {
+            let point    = &contour[(first + offset) % point_count];
+            let position = to_pixels(point);
+
+            if point.on_curve {
+                match control.take() {
+                    Some(control) => add_curve(&mut segments, current, control, position),
+                    None          => segments.push(Segment{ p0: current, p1: position }),
+                }
+
+                current = position;
+            } else {
+                // Two control points in a row have an on-curve point between them
+                if let Some(previous) = control {
+                    let middle = get_midpoint(previous, position);
+                    add_curve(&mut segments, current, previous, middle);
+                    current = middle;
+                }
+
+                control = Some(position);
+            }
+        }
+
+        match control {
+            Some(control) => add_curve(&mut segments, current, control, start),
+            None          => segments.push(Segment{ p0: current, p1: start }),
+        }
+    }
+
+    return segments;
+}
+
+/// The fraction of each pixel covered by the outline, `width` x `height` row by row. The segments must be inside
+/// the bitmap.
+pub(crate) fn rasterize_coverage(segments: &[Segment], width: usize, height: usize) -> Vec<f32> {
+    // A line can touch the pixel after the end of a row, the extra elements keep the last row in bounds
+    let mut accumulation = vec![0.0f32; width * height + 2];
+
+    for segment in segments {
+        let clamp = |point: Float2| Float2::new(point.x.clamp(0.0, width as f32 - 1.0), point.y.clamp(0.0, height as f32));
+        accumulate_line(&mut accumulation, width, height, clamp(segment.p0), clamp(segment.p1));
+    }
+
+    let mut coverage = Vec::<f32>::with_capacity(width * height);
+    let mut sum = 0.0;
+
+    for area in &accumulation[0..width * height] {
+        sum += area;
+        coverage.push(sum.abs().min(1.0));
+    }
+
+    return coverage;
+}
+
+/// A signed distance field of the outline: 0.5 on the outline, rising to 1 at `spread` pixels inside it and falling
+/// to 0 at `spread` pixels outside it.
+pub(crate) fn compute_distance_field(segments: &[Segment], width: usize, height: usize, spread: f32) -> Vec<f32> {
+    let coverage = rasterize_coverage(segments, width, height);
+
+    let mut distances = Vec::<f32>::with_capacity(width * height);
+
+    for y in 0..height {
+        for x in 0..width {
+            let center = Float2::new(x as f32 + 0.5, y as f32 + 0.5);
+
+            let nearest = segments.iter()
+                .map(|segment| get_distance_squared(center, segment))
+                .fold(f32::MAX, f32::min)
+                .sqrt();
+
+            let is_inside = coverage[y * width + x] >= 0.5;
+            let distance  = if is_inside { nearest } else { -nearest };
+
+            distances.push((0.5 + distance / (2.0 * spread)).clamp(0.0, 1.0));
+        }
+    }
+
+    return distances;
+}
+
+fn get_midpoint(a: Float2, b: Float2) -> Float2 {
+    return Float2::new((a.x + b.x) * 0.5, (a.y + b.y) * 0.5);
+}
+
+// Splits a quadratic curve into lines, more of them the further the control point pulls the curve
+fn add_curve(segments: &mut Vec<Segment>, p0: Float2, p1: Float2, p2: Float2) {
+    let deviation_x = p0.x - 2.0 * p1.x + p2.x;
+    let deviation_y = p0.y - 2.0 * p1.y + p2.y;
+    let deviation   = (deviation_x * deviation_x + deviation_y * deviation_y).sqrt();
+
+    let step_count = (1.0 + (deviation * 3.0).sqrt()).floor().min(MAX_CURVE_SEGMENTS as f32) as usize;
+
+    let mut previous = p0;
+    for step in 1..=step_count {
+        let t  = step as f32 / step_count as f32;
+        let mt = 1.0 - t;
+
+        let point = Float2::new(
+            mt * mt * p0.x + 2.0 * mt * t * p1.x + t * t * p2.x,
+            mt * mt * p0.y + 2.0 * mt * t * p1.y + t * t * p2.y,
+        );
+
+        segments.push(Segment{ p0: previous, p1: point });
+        previous = point;
+    }
+}
+
+// Adds the signed area the line covers in each row it crosses. The area left of the line goes to the pixels the
+// line passes through, and the rest is carried to the pixel after it, so the running sum of a row is the coverage.
+fn accumulate_line(accumulation: &mut [f32], width: usize, height: usize, p0: Float2, p1: Float2) {
+    if (p0.y - p1.y).abs() < 1e-6 {
+        return; // horizontal lines don't cover anything
+    }
+
+    let (direction, top, bottom) = if p0.y < p1.y { (1.0, p0, p1) } else { (-1.0, p1, p0) };
+    let dxdy = (bottom.x - top.x) / (bottom.y - top.y);
+
+    let first_row = top.y as usize;
+    let last_row  = (bottom.y.ceil() as usize).min(height);
+
+    let mut x = top.x;
+    for row in first_row..last_row {
+        let row_start = row * width;
+
+        let dy     = ((row + 1) as f32).min(bottom.y) - (row as f32).max(top.y);
+        let x_next = x + dxdy * dy;
+        let area   = dy * direction;
+
+        let (x0, x1) = if x < x_next { (x, x_next) } else { (x_next, x) };
+        let x0_floor = x0.floor();
+        let x1_ceil  = x1.ceil();
+        let x0_pixel = x0_floor as usize;
+        let x1_pixel = x1_ceil as usize;
+
+        if x1_pixel <= x0_pixel + 1 {
+            // Within a single pixel, split by where the line crosses it on average
+            let x_middle = 0.5 * (x + x_next) - x0_floor;
+            accumulation[row_start + x0_pixel]     += area - area * x_middle;
+            accumulation[row_start + x0_pixel + 1] += area * x_middle;
+        } else {
+            let inverse_width = 1.0 / (x1 - x0);
+            let x0_fraction   = x0 - x0_floor;
+            let x1_fraction   = x1 - x1_ceil + 1.0;
+            let first_area    = 0.5 * inverse_width * (1.0 - x0_fraction) * (1.0 - x0_fraction);
+            let last_area     = 0.5 * inverse_width * x1_fraction * x1_fraction;
+
+            accumulation[row_start + x0_pixel] += area * first_area;
+
+            if x1_pixel == x0_pixel + 2 {
+                accumulation[row_start + x0_pixel + 1] += area * (1.0 - first_area - last_area);
+            } else {
+                let second_area = inverse_width * (1.5 - x0_fraction);
+                accumulation[row_start + x0_pixel + 1] += area * (second_area - first_area);
+
+                for pixel in x0_pixel + 2..x1_pixel - 1 {
+                    accumulation[row_start + pixel] += area * inverse_width;
+                }
+
+                let covered_area = second_area + (x1_pixel - x0_pixel - 3) as f32 * inverse_width;
+                accumulation[row_start + x1_pixel - 1] += area * (1.0 - covered_area - last_area);
+            }
+
+            accumulation[row_start + x1_pixel] += area * last_area;
+        }
+
+        x = x_next;
+    }
+}
+
+// From a point to the closest point on a segment
+fn get_distance_squared(point: Float2, segment: &Segment) -> f32 {
+    let edge_x = segment.p1.x - segment.p0.x;
+    let edge_y = segment.p1.y - segment.p0.y;
+    let to_x   = point.x - segment.p0.x;
+    let to_y   = point.y - segment.p0.y;
+
+    let length_squared = edge_x * edge_x + edge_y * edge_y;
+    let t = if length_squared > 0.0 { ((to_x * edge_x + to_y * edge_y) / length_squared).clamp(0.0, 1.0) } else { 0.0 };
+
+    let dx = to_x - edge_x * t;
+    let dy = to_y - edge_y * t;
+
+    return dx * dx + dy * dy;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-4;
+
+    fn make_outline(points: &[(f32, f32, bool)]) -> GlyphOutline {
+        let contour = points.iter().map(|(x, y, on_curve)| OutlinePoint{ x: *x, y: *y, on_curve: *on_curve }).collect();
+        return GlyphOutline{ contours: vec![contour] };
+    }
+
+    // A counter-clockwise rectangle in font units, +y up
+    fn make_rectangle(min_x: f32, min_y: f32, max_x: f32, max_y: f32) -> GlyphOutline {
+        return make_outline(&[(min_x, min_y, true), (max_x, min_y, true), (max_x, max_y, true), (min_x, max_y, true)]);
+    }
+
+    #[test]
+    fn flattening_flips_y_and_closes_contours() {
+        let segments = flatten_outline(&make_rectangle(0.0, 0.0, 100.0, 200.0), 0.02, Float2::new(1.0, 5.0));
+        assert_eq!(segments.len(), 4);
+
+        let points: Vec<(f32, f32)> = segments.iter().map(|segment| (segment.p0.x, segment.p0.y)).collect();
+        assert_eq!(points, [(1.0, 5.0), (3.0, 5.0), (3.0, 1.0), (1.0, 1.0)]);
+        assert_eq!((segments[3].p1.x, segments[3].p1.y), (1.0, 5.0));
+
+        // Only control points: the contour starts between the last and first point, and every curve joins the next
+        let circle   = make_outline(&[(0.0, 10.0, false), (10.0, 0.0, false), (0.0, -10.0, false), (-10.0, 0.0, false)]);
+        let segments = flatten_outline(&circle, 1.0, Float2::new(16.0, 16.0));
+        assert!(segments.len() > 4);
+
+        for (segment, next) in segments.iter().zip(segments.iter().cycle().skip(1)) {
+            assert!((segment.p1.x - next.p0.x).abs() < EPSILON && (segment.p1.y - next.p0.y).abs() < EPSILON);
+        }
+
+        // The curves pass through the midpoints between the control points
+        assert!(segments.iter().any(|segment| (segment.p0.x - 21.0).abs() < EPSILON && (segment.p0.y - 11.0).abs() < EPSILON));
+    }
+
+    #[test]
+    fn coverage_is_exact_for_lines() {
+        // Pixel aligned, every pixel is fully inside or outside
+        let segments = flatten_outline(&make_rectangle(1.0, 1.0, 3.0, 4.0), 1.0, Float2::new(0.0, 5.0));
+        let coverage = rasterize_coverage(&segments, 5, 5);
+
+        for y in 0..5 {
+            for x in 0..5 {
+                let is_inside = (1..3).contains(&x) && (1..4).contains(&y);
+                assert_eq!(coverage[y * 5 + x], if is_inside { 1.0 } else { 0.0 }, "pixel ({}, {})", x, y);
+            }
+        }
+
+        // Half a pixel off, the edge pixels are half covered
+        let segments = flatten_outline(&make_rectangle(0.5, 1.0, 2.5, 2.0), 1.0, Float2::new(0.0, 3.0));
+        let coverage = rasterize_coverage(&segments, 4, 3);
+        let row: Vec<f32> = coverage[4..8].to_vec();
+        assert_eq!(row, [0.5, 1.0, 0.5, 0.0]);
+
+        // A diagonal edge covers the triangle's area in total, split across the pixels it crosses
+        let triangle = make_outline(&[(0.0, 0.0, true), (4.0, 0.0, true), (0.0, 4.0, true)]);
+        let segments = flatten_outline(&triangle, 1.0, Float2::new(1.0, 5.0));
+        let coverage = rasterize_coverage(&segments, 6, 6);
+
+        assert!((coverage.iter().sum::<f32>() - 8.0).abs() < EPSILON);
+        assert!((coverage[6 + 1] - 0.5).abs() < EPSILON); // the top pixel the edge cuts in half
+        assert!((coverage[4 * 6 + 4] - 0.5).abs() < EPSILON);
+        assert_eq!(coverage[4 * 6 + 1], 1.0);
+    }
+
+    #[test]
+    fn distance_field_is_half_on_the_outline() {
+        let spread   = 2.0;
+        let segments = flatten_outline(&make_rectangle(2.0, 2.0, 10.0, 10.0), 1.0, Float2::new(0.0, 12.0));
+        let field    = compute_distance_field(&segments, 12, 12, spread);
+
+        // Pixel centers half a pixel inside and outside the left edge
+        assert!((field[6 * 12 + 2] - (0.5 + 0.5 / (2.0 * spread))).abs() < EPSILON);
+        assert!((field[6 * 12 + 1] - (0.5 - 0.5 / (2.0 * spread))).abs() < EPSILON);
+
+        // Saturated further than the spread from the outline
+        assert_eq!(field[6 * 12 + 6], 1.0);
+        assert_eq!(field[0], 0.0);
+    }
+}
@@ -0,0 +1,987 @@
+use std::path::Path;
+
+use super::error::FontError;
+
+//
+// TrueType Fonts
+//
+// Reads the tables a simple left-to-right text renderer needs, straight from the font file:
+//   head, maxp - units per em, glyph count and the loca format
+//   hhea, hmtx - line metrics and glyph advances
+//   cmap       - characters to glyphs, from a Unicode subtable in format 4 (BMP) or 12 (full range)
+//   loca, glyf - glyph outlines, simple and composite
+//   kern, GPOS - pair kerning, from the legacy kern table or the pair adjustment lookups of GPOS
+//
+// Hinting, vertical metrics and the rest of GPOS and GSUB (ligatures, mark positioning) are ignored. Fonts with CFF
+// outlines (.otf) and font collections (.ttc) aren't supported.
+//
+// Every read is bounds checked, a truncated or malformed table makes the glyph empty rather than panicking.
+//
+
+// Simple glyph point flags
+const FLAG_ON_CURVE:           u8 = 0x01;
+const FLAG_X_SHORT:            u8 = 0x02;
+const FLAG_Y_SHORT:            u8 = 0x04;
+const FLAG_REPEAT:             u8 = 0x08;
+const FLAG_X_SAME_OR_POSITIVE: u8 = 0x10;
+const FLAG_Y_SAME_OR_POSITIVE: u8 = 0x20;
+
+// Composite glyph component flags
+const ARG_1_AND_2_ARE_WORDS:    u16 = 0x0001;
+const ARGS_ARE_XY_VALUES:       u16 = 0x0002;
+const WE_HAVE_A_SCALE:          u16 = 0x0008;
+const MORE_COMPONENTS:          u16 = 0x0020;
+const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+const WE_HAVE_A_TWO_BY_TWO:     u16 = 0x0080;
+
+// Composite glyphs referencing composite glyphs past this depth are treated as empty
+const MAX_COMPOSITE_DEPTH: u32 = 8;
+
+// GPOS lookups and value records
+const GPOS_LOOKUP_PAIR_ADJUSTMENT: u16 = 2;
+const GPOS_LOOKUP_EXTENSION:       u16 = 9;
+const VALUE_FORMAT_X_ADVANCE:      u16 = 0x0004;
+
+/// A point of a glyph outline, in font units with +y up. Two off-curve points in a row have an implied on-curve
+/// point between them.
+#[derive(Clone, Copy, Debug)]
+pub struct OutlinePoint {
+    pub x:        f32,
+    pub y:        f32,
+    pub on_curve: bool, // off-curve points are quadratic control points
+}
+
+/// The closed contours of a glyph. Empty for glyphs without an outline, like the space.
+#[derive(Clone, Debug, Default)]
+pub struct GlyphOutline {
+    pub contours: Vec<Vec<OutlinePoint>>,
+}
+
+impl GlyphOutline {
+    pub fn is_empty(&self) -> bool {
+        return self.contours.iter().all(|contour| contour.is_empty());
+    }
+}
+
+/// Vertical metrics shared by every line, in font units. The descender is negative.
+#[derive(Clone, Copy, Debug)]
+pub struct LineMetrics {
+    pub ascender:  f32,
+    pub descender: f32,
+    pub line_gap:  f32,
+}
+
+impl LineMetrics {
+    pub fn get_line_height(&self) -> f32 {
+        return self.ascender - self.descender + self.line_gap;
+    }
+}
+
+pub struct Font {
+    data:             Vec<u8>,
+    units_per_em:     f32,
+    glyph_count:      u16,
+    long_loca:        bool,  // loca offsets are u32 rather than u16 / 2
+    line_metrics:     LineMetrics,
+    h_metric_count:   usize, // glyphs past this use the last advance
+    cmap:             usize, // offset of the Unicode cmap subtable
+    cmap_format:      u16,   // 4 or 12
+    loca:             usize,
+    glyf:             usize,
+    hmtx:             usize,
+    kern:             Option<usize>, // offset of a horizontal format 0 kern subtable
+    pair_adjustments: Vec<usize>,    // offsets of the GPOS pair adjustment subtables
+}
+
+impl Font {
+    pub fn from_file(path: &Path) -> Result<Font, FontError> {
+        let data = match std::fs::read(path) {
+            Ok(data)   => data,
+            Err(error) => return Err(FontError::FileNotFound{ path: path.display().to_string(), reason: error.to_string() }),
+        };
+
+        return Font::from_bytes(data);
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<Font, FontError> {
+        let invalid = |reason: &str| FontError::InvalidFont(reason.to_string());
+
+        match read_u32(&data, 0) {
+            Some(0x00010000) | Some(0x74727565) => {}, // 1.0 or 'true'
+            Some(0x4F54544F) => return Err(FontError::UnsupportedFont("CFF outlines ('OTTO') aren't supported".to_string())),
+            Some(0x74746366) => return Err(FontError::UnsupportedFont("font collections ('ttcf') aren't supported".to_string())),
+            _                => return Err(invalid("not a TrueType font")),
+        }
+
+        let head = find_table(&data, b"head").ok_or_else(|| invalid("missing the head table"))?;
+        let maxp = find_table(&data, b"maxp").ok_or_else(|| invalid("missing the maxp table"))?;
+        let hhea = find_table(&data, b"hhea").ok_or_else(|| invalid("missing the hhea table"))?;
+        let hmtx = find_table(&data, b"hmtx").ok_or_else(|| invalid("missing the hmtx table"))?;
+        let cmap = find_table(&data, b"cmap").ok_or_else(|| invalid("missing the cmap table"))?;
+        let loca = find_table(&data, b"loca").ok_or_else(|| invalid("missing the loca table"))?;
+        let glyf = find_table(&data, b"glyf").ok_or_else(|| invalid("missing the glyf table"))?;
+
+        let units_per_em   = read_u16(&data, head + 18).ok_or_else(|| invalid("truncated head table"))?;
+        let loca_format    = read_i16(&data, head + 50).ok_or_else(|| invalid("truncated head table"))?;
+        let glyph_count    = read_u16(&data, maxp + 4).ok_or_else(|| invalid("truncated maxp table"))?;
+        let ascender       = read_i16(&data, hhea + 4).ok_or_else(|| invalid("truncated hhea table"))?;
+        let descender      = read_i16(&data, hhea + 6).ok_or_else(|| invalid("truncated hhea table"))?;
+        let line_gap       = read_i16(&data, hhea + 8).ok_or_else(|| invalid("truncated hhea table"))?;
+        let h_metric_count = read_u16(&data, hhea + 34).ok_or_else(|| invalid("truncated hhea table"))?;
+
+        if units_per_em == 0 || h_metric_count == 0 {
+            return Err(invalid("zero units per em or horizontal metrics"));
+        }
+
+        let (cmap_subtable, cmap_format) = find_unicode_cmap(&data, cmap).ok_or_else(|| FontError::UnsupportedFont("no Unicode cmap in format 4 or 12".to_string()))?;
+
+        let kern             = find_table(&data, b"kern").and_then(|kern| find_kern_subtable(&data, kern));
+        let pair_adjustments = find_table(&data, b"GPOS").and_then(|gpos| find_pair_adjustments(&data, gpos)).unwrap_or_default();
+
+        return Ok(Font{
+            data,
+            units_per_em:   units_per_em as f32,
+            glyph_count,
+            long_loca:      loca_format != 0,
+            line_metrics:   LineMetrics{ ascender: ascender as f32, descender: descender as f32, line_gap: line_gap as f32 },
+            h_metric_count: h_metric_count as usize,
+            cmap:           cmap_subtable,
+            cmap_format,
+            loca,
+            glyf,
+            hmtx,
+            kern,
+            pair_adjustments,
+        });
+    }
+
+    pub fn get_units_per_em(&self) -> f32 {
+        return self.units_per_em;
+    }
+
+    pub fn get_glyph_count(&self) -> u16 {
+        return self.glyph_count;
+    }
+
+    pub fn get_line_metrics(&self) -> LineMetrics {
+        return self.line_metrics;
+    }
+
+    /// The glyph drawn for `character`, None if the font doesn't have one. Glyph 0 is the font's "missing glyph".
+    pub fn get_glyph_index(&self, character: char) -> Option<u16> {
+        let code = character as u32;
+
+        let glyph = match self.cmap_format {
+            4  => self.get_glyph_index_format4(code),
+            12 => self.get_glyph_index_format12(code),
+            _  => None,
+        };
+
+        return glyph.filter(|glyph| *glyph != 0 && *glyph < self.glyph_count);
+    }
+
+    /// How far the pen moves after the glyph, in font units.
+    pub fn get_glyph_advance(&self, glyph: u16) -> f32 {
+        let metric = (glyph as usize).min(self.h_metric_count - 1);
+        return read_u16(&self.data, self.hmtx + 4 * metric).unwrap_or(0) as f32;
+    }
+
+    /// The adjustment to the advance of `left` when `right` follows it, in font units. Usually negative.
+    pub fn get_kerning(&self, left: u16, right: u16) -> f32 {
+        if let Some(kern) = self.kern {
+            if let Some(value) = get_kern_pair(&self.data, kern, left, right) {
+                return value as f32;
+            }
+        }
+
+        for subtable in &self.pair_adjustments {
+            match get_pair_adjustment(&self.data, *subtable, left, right) {
+                Some(value) if value != 0 => return value as f32,
+                _                         => {},
+            }
+        }
+
+        return 0.0;
+    }
+
+    /// The glyph's outline in font units. Composite glyphs are flattened into their components' contours.
+    pub fn get_glyph_outline(&self, glyph: u16) -> GlyphOutline {
+        let mut outline = GlyphOutline::default();
+
+        let identity = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+        if self.append_glyph_outline(glyph, identity, 0, &mut outline).is_none() {
+            println!("[WARN] :: Font :: Glyph {} has a malformed outline, it is drawn empty.", glyph);
+            return GlyphOutline::default();
+        }
+
+        return outline;
+    }
+
+    fn get_glyph_index_format4(&self, code: u32) -> Option<u16> {
+        if code > 0xFFFF {
+            return None;
+        }
+
+        let data      = &self.data;
+        let segments  = (read_u16(data, self.cmap + 6)? / 2) as usize;
+        let end_codes = self.cmap + 14;
+        let starts    = end_codes + segments * 2 + 2; // skips the reserved pad
+        let deltas    = starts + segments * 2;
+        let ranges    = deltas + segments * 2;
+
+        for segment in 0..segments {
+            let end_code = read_u16(data, end_codes + segment * 2)? as u32;
+            if code > end_code {
+                continue;
+            }
+
+            let start_code = read_u16(data, starts + segment * 2)? as u32;
+            if code < start_code {
+                return None;
+            }
+
+            let delta        = read_u16(data, deltas + segment * 2)?;
+            let range_at     = ranges + segment * 2;
+            let range_offset = read_u16(data, range_at)? as usize;
+
+            if range_offset == 0 {
+                return Some((code as u16).wrapping_add(delta));
+            }
+
+            // The offset is relative to its own position in the idRangeOffset array
+            let glyph = read_u16(data, range_at + range_offset + 2 * (code - start_code) as usize)?;
+            return if glyph == 0 { None } else { Some(glyph.wrapping_add(delta)) };
+        }
+
+        return None;
+    }
+
+    fn get_glyph_index_format12(&self, code: u32) -> Option<u16> {
+        let data   = &self.data;
+        let groups = read_u32(data, self.cmap + 12)? as usize;
+
+        for group in 0..groups {
+            let record     = self.cmap + 16 + group * 12;
+            let start_code = read_u32(data, record)?;
+            let end_code   = read_u32(data, record + 4)?;
+
+            if code >= start_code && code <= end_code {
+                let glyph = read_u32(data, record + 8)? + (code - start_code);
+                return u16::try_from(glyph).ok();
+            }
+        }
+
+        return None;
+    }
+
+    // The glyph's byte range in the glyf table, None for glyphs without an outline
+    fn get_glyph_range(&self, glyph: u16) -> Option<(usize, usize)> {
+        if glyph >= self.glyph_count {
+            return None;
+        }
+
+        let index = glyph as usize;
+        let (start, end) = if self.long_loca {
+            (read_u32(&self.data, self.loca + index * 4)? as usize, read_u32(&self.data, self.loca + index * 4 + 4)? as usize)
+        } else {
+            (read_u16(&self.data, self.loca + index * 2)? as usize * 2, read_u16(&self.data, self.loca + index * 2 + 2)? as usize * 2)
+        };
+
+        if start >= end {
+            return None;
+        }
+
+        return Some((self.glyf + start, self.glyf + end));
+    }
+
+    // Appends the glyph's contours, transformed by [a, b, c, d, e, f]: x' = a*x + c*y + e, y' = b*x + d*y + f.
+    // None if the outline is malformed.
+    fn append_glyph_outline(&self, glyph: u16, transform: [f32; 6], depth: u32, outline: &mut GlyphOutline) -> Option<()> {
+        if depth > MAX_COMPOSITE_DEPTH {
+            return None;
+        }
+
+        let Some((start, _)) = self.get_glyph_range(glyph) else {
+            return Some(()); // no outline
+        };
+
+        let contour_count = read_i16(&self.data, start)?;
+        if contour_count >= 0 {
+            return self.append_simple_outline(start, contour_count as usize, transform, outline);
+        }
+
+        return self.append_composite_outline(start, transform, depth, outline);
+    }
+
+    fn append_simple_outline(&self, start: usize, contour_count: usize, transform: [f32; 6], outline: &mut GlyphOutline) -> Option<()> {
+        let data = &self.data;
+
+        let end_points_at = start + 10; // skips the contour count and bounding box
+        let mut end_points = Vec::<usize>::with_capacity(contour_count);
+        for contour in 0..contour_count {
+            end_points.push(read_u16(data, end_points_at + contour * 2)? as usize);
+        }
+
+        let point_count        = end_points.last().map_or(0, |last| last + 1);
+        let instruction_length = read_u16(data, end_points_at + contour_count * 2)? as usize;
+        let mut offset         = end_points_at + contour_count * 2 + 2 + instruction_length;
+
+        let mut flags = Vec::<u8>::with_capacity(point_count);
+        while flags.len() < point_count {
+            let flag = read_u8(data, offset)?;
+            offset += 1;
+            flags.push(flag);
+
+            if flag & FLAG_REPEAT != 0 {
+                let repeat_count = read_u8(data, offset)?;
+                offset += 1;
+                for _ in 0..repeat_count {
+                    flags.push(flag);
+                }
+            }
+        }
+        flags.truncate(point_count);
+
+        // Coordinates are deltas from the previous point, all the x coordinates come before the y coordinates
+        let mut read_coordinates = |short_flag: u8, same_or_positive_flag: u8| -> Option<Vec<i32>> {
+            let mut coordinates = Vec::<i32>::with_capacity(point_count);
+            let mut value: i32  = 0;
+
+            for flag in &flags {
+                if flag & short_flag != 0 {
+                    let delta = read_u8(data, offset)? as i32;
+                    offset += 1;
+                    value += if flag & same_or_positive_flag != 0 { delta } else { -delta };
+                } else if flag & same_or_positive_flag == 0 {
+                    value += read_i16(data, offset)? as i32;
+                    offset += 2;
+                }
+
+                coordinates.push(value);
+            }
+
+            return Some(coordinates);
+        };
+
+        let xs = read_coordinates(FLAG_X_SHORT, FLAG_X_SAME_OR_POSITIVE)?;
+        let ys = read_coordinates(FLAG_Y_SHORT, FLAG_Y_SAME_OR_POSITIVE)?;
+
+        let mut contour_start = 0;
+        for end_point in end_points {
+            if end_point < contour_start || end_point >= point_count {
+                return None;
+            }
+
+            let contour = (contour_start..=end_point)
+                .map(|point| {
+                    let (x, y) = (xs[point] as f32, ys[point] as f32);
+                    OutlinePoint{
+                        x:        transform[0] * x + transform[2] * y + transform[4],
+                        y:        transform[1] * x + transform[3] * y + transform[5],
+                        on_curve: flags[point] & FLAG_ON_CURVE != 0,
+                    }
+                })
+                .collect();
+
+            outline.contours.push(contour);
+            contour_start = end_point + 1;
+        }
+
+        return Some(());
+    }
+
+    fn append_composite_outline(&self, start: usize, transform: [f32; 6], depth: u32, outline: &mut GlyphOutline) -> Option<()> {
+        let data = &self.data;
+        let mut offset = start + 10;
+
+        loop {
+            let flags     = read_u16(data, offset)?;
+            let component = read_u16(data, offset + 2)?;
+            offset += 4;
+
+            let (arg1, arg2) = if flags & ARG_1_AND_2_ARE_WORDS != 0 {
+                offset += 4;
+                (read_i16(data, offset - 4)? as f32, read_i16(data, offset - 2)? as f32)
+            } else {
+                offset += 2;
+                (read_u8(data, offset - 2)? as i8 as f32, read_u8(data, offset - 1)? as i8 as f32)
+            };
+
+            let (mut a, mut b, mut c, mut d) = (1.0, 0.0, 0.0, 1.0);
+            if flags & WE_HAVE_A_SCALE != 0 {
+                a = read_f2dot14(data, offset)?;
+                d = a;
+                offset += 2;
+            } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+                a = read_f2dot14(data, offset)?;
+                d = read_f2dot14(data, offset + 2)?;
+                offset += 4;
+            } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+                a = read_f2dot14(data, offset)?;
+                b = read_f2dot14(data, offset + 2)?;
+                c = read_f2dot14(data, offset + 4)?;
+                d = read_f2dot14(data, offset + 6)?;
+                offset += 8;
+            }
+
+            // Components placed by matching points are rare in Latin fonts, they are placed without an offset
+            let (e, f) = if flags & ARGS_ARE_XY_VALUES != 0 { (arg1, arg2) } else { (0.0, 0.0) };
+
+            // The component's transform is applied first, then the parent's
+            let p = transform;
+            let combined = [
+                p[0] * a + p[2] * b,
+                p[1] * a + p[3] * b,
+                p[0] * c + p[2] * d,
+                p[1] * c + p[3] * d,
+                p[0] * e + p[2] * f + p[4],
+                p[1] * e + p[3] * f + p[5],
+            ];
+
+            self.append_glyph_outline(component, combined, depth + 1, outline)?;
+
+            if flags & MORE_COMPONENTS == 0 {
+                break;
+            }
+        }
+
+        return Some(());
+    }
+}
+
+fn read_u8(data: &[u8], offset: usize) -> Option<u8> {
+    return data.get(offset).copied();
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    return Some(u16::from_be_bytes([bytes[0], bytes[1]]));
+}
+
+fn read_i16(data: &[u8], offset: usize) -> Option<i16> {
+    return read_u16(data, offset).map(|value| value as i16);
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    return Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]));
+}
+
+// A signed 2.14 fixed point number, used for composite glyph scales
+fn read_f2dot14(data: &[u8], offset: usize) -> Option<f32> {
+    return read_i16(data, offset).map(|value| value as f32 / 16384.0);
+}
+
+fn find_table(data: &[u8], tag: &[u8; 4]) -> Option<usize> {
+    let table_count = read_u16(data, 4)? as usize;
+
+    for table in 0..table_count {
+        let record = 12 + table * 16;
+        if data.get(record..record + 4)? == tag {
+            return read_u32(data, record + 8).map(|offset| offset as usize);
+        }
+    }
+
+    return None;
+}
+
+// Prefers a format 12 subtable, which covers characters past the BMP, over format 4
+fn find_unicode_cmap(data: &[u8], cmap: usize) -> Option<(usize, u16)> {
+    let record_count = read_u16(data, cmap + 2)? as usize;
+    let mut result: Option<(usize, u16)> = None;
+
+    for record in 0..record_count {
+        let record_at = cmap + 4 + record * 8;
+        let platform  = read_u16(data, record_at)?;
+        let encoding  = read_u16(data, record_at + 2)?;
+        let subtable  = cmap + read_u32(data, record_at + 4)? as usize;
+
+        let is_unicode = platform == 0 || (platform == 3 && (encoding == 1 || encoding == 10));
+        if !is_unicode {
+            continue;
+        }
+
+        match read_u16(data, subtable)? {
+            12 => return Some((subtable, 12)),
+            4  => if result.is_none() { result = Some((subtable, 4)); },
+            _  => {},
+        }
+    }
+
+    return result;
+}
+
+// The first subtable of a version 0 kern table, if it holds horizontal format 0 pairs
+fn find_kern_subtable(data: &[u8], kern: usize) -> Option<usize> {
+    if read_u16(data, kern)? != 0 || read_u16(data, kern + 2)? == 0 {
+        return None;
+    }
+
+    let subtable = kern + 4;
+    let coverage = read_u16(data, subtable + 4)?;
+
+    let is_format0    = coverage >> 8 == 0;
+    let is_horizontal = coverage & 0x1 != 0;
+    return if is_format0 && is_horizontal { Some(subtable) } else { None };
+}
+
+fn get_kern_pair(data: &[u8], subtable: usize, left: u16, right: u16) -> Option<i16> {
+    let pair_count = read_u16(data, subtable + 6)? as usize;
+    let pairs      = subtable + 14;
+    let key        = (left as u32) << 16 | right as u32;
+
+    // The pairs are sorted by their left and right glyphs
+    let (mut low, mut high) = (0, pair_count);
+    while low < high {
+        let middle   = (low + high) / 2;
+        let pair_key = read_u32(data, pairs + middle * 6)?;
+
+        if pair_key == key {
+            return read_i16(data, pairs + middle * 6 + 4);
+        } else if pair_key < key {
+            low = middle + 1;
+        } else {
+            high = middle;
+        }
+    }
+
+    return None;
+}
+
+// Every pair adjustment subtable of GPOS, in lookup order. The script and feature lists are ignored, so kerning
+// applies to every script.
+fn find_pair_adjustments(data: &[u8], gpos: usize) -> Option<Vec<usize>> {
+    let lookup_list  = gpos + read_u16(data, gpos + 8)? as usize;
+    let lookup_count = read_u16(data, lookup_list)? as usize;
+
+    let mut result = Vec::<usize>::new();
+    for lookup in 0..lookup_count {
+        let lookup_at      = lookup_list + read_u16(data, lookup_list + 2 + lookup * 2)? as usize;
+        let lookup_type    = read_u16(data, lookup_at)?;
+        let subtable_count = read_u16(data, lookup_at + 4)? as usize;
+
+        for subtable in 0..subtable_count {
+            let mut subtable_at   = lookup_at + read_u16(data, lookup_at + 6 + subtable * 2)? as usize;
+            let mut subtable_type = lookup_type;
+
+            // Extension subtables point to a subtable of another lookup type
+            if lookup_type == GPOS_LOOKUP_EXTENSION {
+                subtable_type = read_u16(data, subtable_at + 2)?;
+                subtable_at  += read_u32(data, subtable_at + 4)? as usize;
+            }
+
+            if subtable_type == GPOS_LOOKUP_PAIR_ADJUSTMENT {
+                result.push(subtable_at);
+            }
+        }
+    }
+
+    return Some(result);
+}
+
+// The x advance adjustment of the first glyph, from a pair adjustment subtable in format 1 (glyph pairs) or format 2
+// (class pairs). None if the subtable doesn't cover the pair.
+fn get_pair_adjustment(data: &[u8], subtable: usize, left: u16, right: u16) -> Option<i16> {
+    let format        = read_u16(data, subtable)?;
+    let coverage      = subtable + read_u16(data, subtable + 2)? as usize;
+    let value_format1 = read_u16(data, subtable + 4)?;
+    let value_format2 = read_u16(data, subtable + 6)?;
+
+    if value_format1 & VALUE_FORMAT_X_ADVANCE == 0 {
+        return None;
+    }
+
+    let coverage_index = get_coverage_index(data, coverage, left)?;
+
+    // A value record holds a u16 for each bit of its format, the x advance comes after the x and y placements
+    let record1_size     = 2 * value_format1.count_ones() as usize;
+    let record2_size     = 2 * value_format2.count_ones() as usize;
+    let x_advance_offset = 2 * (value_format1 & 0x3).count_ones() as usize;
+
+    return match format {
+        1 => {
+            let pair_set_count = read_u16(data, subtable + 8)? as usize;
+            if coverage_index >= pair_set_count {
+                return None;
+            }
+
+            let pair_set    = subtable + read_u16(data, subtable + 10 + coverage_index * 2)? as usize;
+            let pair_count  = read_u16(data, pair_set)? as usize;
+            let record_size = 2 + record1_size + record2_size;
+
+            // The pairs are sorted by the second glyph
+            let (mut low, mut high) = (0, pair_count);
+            while low < high {
+                let middle = (low + high) / 2;
+                let record = pair_set + 2 + middle * record_size;
+                let second = read_u16(data, record)?;
+
+                if second == right {
+                    return read_i16(data, record + 2 + x_advance_offset);
+                } else if second < right {
+                    low = middle + 1;
+                } else {
+                    high = middle;
+                }
+            }
+
+            None
+        },
+        2 => {
+            let class_def1   = subtable + read_u16(data, subtable + 8)? as usize;
+            let class_def2   = subtable + read_u16(data, subtable + 10)? as usize;
+            let class1_count = read_u16(data, subtable + 12)? as usize;
+            let class2_count = read_u16(data, subtable + 14)? as usize;
+
+            let class1 = get_glyph_class(data, class_def1, left)?;
+            let class2 = get_glyph_class(data, class_def2, right)?;
+            if class1 >= class1_count || class2 >= class2_count {
+                return None;
+            }
+
+            let record = subtable + 16 + (class1 * class2_count + class2) * (record1_size + record2_size);
+            read_i16(data, record + x_advance_offset)
+        },
+        _ => None,
+    };
+}
+
+fn get_coverage_index(data: &[u8], coverage: usize, glyph: u16) -> Option<usize> {
+    return match read_u16(data, coverage)? {
+        1 => {
+            let glyph_count = read_u16(data, coverage + 2)? as usize;
+
+            let (mut low, mut high) = (0, glyph_count);
+            while low < high {
+                let middle = (low + high) / 2;
+                let value  = read_u16(data, coverage + 4 + middle * 2)?;
+
+                if value == glyph {
+                    return Some(middle);
+                } else if value < glyph {
+                    low = middle + 1;
+                } else {
+                    high = middle;
+                }
+            }
+
+            None
+        },
+        2 => {
+            let range_count = read_u16(data, coverage + 2)? as usize;
+
+            for range in 0..range_count {
+                let record = coverage + 4 + range * 6;
+                let start  = read_u16(data, record)?;
+                let end    = read_u16(data, record + 2)?;
+
+                if glyph >= start && glyph <= end {
+                    let start_index = read_u16(data, record + 4)? as usize;
+                    return Some(start_index + (glyph - start) as usize);
+                }
+            }
+
+            None
+        },
+        _ => None,
+    };
+}
+
+// Glyphs a class definition doesn't list are in class 0
+fn get_glyph_class(data: &[u8], class_def: usize, glyph: u16) -> Option<usize> {
+    return match read_u16(data, class_def)? {
+        1 => {
+            let start_glyph = read_u16(data, class_def + 2)?;
+            let glyph_count = read_u16(data, class_def + 4)?;
+
+            if glyph < start_glyph || glyph - start_glyph >= glyph_count {
+                return Some(0);
+            }
+
+            read_u16(data, class_def + 6 + (glyph - start_glyph) as usize * 2).map(|class| class as usize)
+        },
+        2 => {
+            let range_count = read_u16(data, class_def + 2)? as usize;
+
+            for range in 0..range_count {
+                let record = class_def + 4 + range * 6;
+                let start  = read_u16(data, record)?;
+                let end    = read_u16(data, record + 2)?;
+
+                if glyph >= start && glyph <= end {
+                    return read_u16(data, record + 4).map(|class| class as usize);
+                }
+            }
+
+            Some(0)
+        },
+        _ => Some(0),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Glyphs of the test font
+    const SQUARE:    u16 = 1; // 'A', a 500 x 700 square
+    const COMPOSITE: u16 = 2; // 'B', the square moved 100 units right
+    const SPACE:     u16 = 3; // ' ', no outline
+
+    fn push_u16(data: &mut Vec<u8>, value: u16) {
+        data.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn push_i16(data: &mut Vec<u8>, value: i16) {
+        data.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn push_u32(data: &mut Vec<u8>, value: u32) {
+        data.extend_from_slice(&value.to_be_bytes());
+    }
+
+    /// Lays out the tables after a table directory, each on a 4 byte boundary
+    fn make_font_file(magic: u32, tables: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+        let mut data = Vec::<u8>::new();
+        push_u32(&mut data, magic);
+        push_u16(&mut data, tables.len() as u16);
+        push_u16(&mut data, 0); // searchRange, entrySelector and rangeShift aren't read
+        push_u16(&mut data, 0);
+        push_u16(&mut data, 0);
+
+        let mut offset = 12 + 16 * tables.len();
+        for (tag, table) in tables {
+            data.extend_from_slice(*tag);
+            push_u32(&mut data, 0); // checksum
+            push_u32(&mut data, offset as u32);
+            push_u32(&mut data, table.len() as u32);
+            offset += table.len().next_multiple_of(4);
+        }
+
+        for (_, table) in tables {
+            data.extend_from_slice(table);
+            data.resize(data.len().next_multiple_of(4), 0);
+        }
+
+        return data;
+    }
+
+    fn make_head(units_per_em: u16) -> Vec<u8> {
+        let mut head = vec![0u8; 54];
+        head[18..20].copy_from_slice(&units_per_em.to_be_bytes());
+        return head; // short loca offsets
+    }
+
+    fn make_glyf() -> (Vec<u8>, Vec<u8>) {
+        let mut glyf = Vec::<u8>::new();
+        let mut starts = Vec::<usize>::new();
+
+        // Glyph 0 has no outline
+        starts.push(glyf.len());
+
+        // A square of on-curve points with 16 bit coordinate deltas
+        starts.push(glyf.len());
+        for value in [1, 0, 0, 500, 700] {
+            push_i16(&mut glyf, value); // contour count, then the bounding box
+        }
+        push_u16(&mut glyf, 3); // the contour's last point
+        push_u16(&mut glyf, 0); // no instructions
+        glyf.extend_from_slice(&[FLAG_ON_CURVE; 4]);
+        for delta in [0, 500, 0, -500, 0, 0, 700, 0] {
+            push_i16(&mut glyf, delta);
+        }
+
+        // The square as a component, offset by (100, 0)
+        starts.push(glyf.len());
+        for value in [-1, 100, 0, 600, 700] {
+            push_i16(&mut glyf, value);
+        }
+        push_u16(&mut glyf, ARG_1_AND_2_ARE_WORDS | ARGS_ARE_XY_VALUES);
+        push_u16(&mut glyf, SQUARE);
+        push_i16(&mut glyf, 100);
+        push_i16(&mut glyf, 0);
+
+        // The space has no outline
+        starts.push(glyf.len());
+        starts.push(glyf.len());
+
+        let mut loca = Vec::<u8>::new();
+        for start in starts {
+            push_u16(&mut loca, (start / 2) as u16);
+        }
+
+        return (glyf, loca);
+    }
+
+    // A format 4 cmap mapping ' ' to SPACE and 'A', 'B' to SQUARE, COMPOSITE
+    fn make_cmap() -> Vec<u8> {
+        let segments: [(u16, u16, u16); 3] = [(32, 32, SPACE.wrapping_sub(32)), (65, 66, SQUARE.wrapping_sub(65)), (0xFFFF, 0xFFFF, 1)];
+
+        let mut cmap = Vec::<u8>::new();
+        push_u16(&mut cmap, 0); // version
+        push_u16(&mut cmap, 1); // one encoding record
+        push_u16(&mut cmap, 3); // Windows Unicode BMP
+        push_u16(&mut cmap, 1);
+        push_u32(&mut cmap, 12);
+
+        push_u16(&mut cmap, 4); // format
+        push_u16(&mut cmap, 0); // length and language aren't read
+        push_u16(&mut cmap, 0);
+        push_u16(&mut cmap, segments.len() as u16 * 2);
+        push_u16(&mut cmap, 0); // searchRange, entrySelector and rangeShift
+        push_u16(&mut cmap, 0);
+        push_u16(&mut cmap, 0);
+        segments.iter().for_each(|(_, end, _)| push_u16(&mut cmap, *end));
+        push_u16(&mut cmap, 0); // reserved pad
+        segments.iter().for_each(|(start, _, _)| push_u16(&mut cmap, *start));
+        segments.iter().for_each(|(_, _, delta)| push_u16(&mut cmap, *delta));
+        segments.iter().for_each(|_| push_u16(&mut cmap, 0)); // no range offsets
+
+        return cmap;
+    }
+
+    // One horizontal format 0 subtable kerning SQUARE followed by COMPOSITE
+    fn make_kern() -> Vec<u8> {
+        let mut kern = Vec::<u8>::new();
+        push_u16(&mut kern, 0); // version
+        push_u16(&mut kern, 1); // one subtable
+        push_u16(&mut kern, 0); // subtable version
+        push_u16(&mut kern, 20);
+        push_u16(&mut kern, 0x0001); // horizontal, format 0
+        push_u16(&mut kern, 1); // one pair
+        push_u16(&mut kern, 0); // searchRange, entrySelector and rangeShift
+        push_u16(&mut kern, 0);
+        push_u16(&mut kern, 0);
+        push_u16(&mut kern, SQUARE);
+        push_u16(&mut kern, COMPOSITE);
+        push_i16(&mut kern, -50);
+        return kern;
+    }
+
+    fn make_tables(units_per_em: u16) -> Vec<(&'static [u8; 4], Vec<u8>)> {
+        let mut maxp = vec![0u8; 6];
+        maxp[4..6].copy_from_slice(&4u16.to_be_bytes());
+
+        let mut hhea = vec![0u8; 36];
+        hhea[4..6].copy_from_slice(&800i16.to_be_bytes());
+        hhea[6..8].copy_from_slice(&(-200i16).to_be_bytes());
+        hhea[8..10].copy_from_slice(&100i16.to_be_bytes());
+        hhea[34..36].copy_from_slice(&2u16.to_be_bytes());
+
+        // Two long metrics, the glyphs after them share the last advance
+        let mut hmtx = Vec::<u8>::new();
+        for (advance, bearing) in [(400, 0), (600, 0)] {
+            push_u16(&mut hmtx, advance);
+            push_i16(&mut hmtx, bearing);
+        }
+        push_i16(&mut hmtx, 100);
+        push_i16(&mut hmtx, 0);
+
+        let (glyf, loca) = make_glyf();
+
+        return vec![
+            (b"cmap", make_cmap()),
+            (b"glyf", glyf),
+            (b"head", make_head(units_per_em)),
+            (b"hhea", hhea),
+            (b"hmtx", hmtx),
+            (b"kern", make_kern()),
+            (b"loca", loca),
+            (b"maxp", maxp),
+        ];
+    }
+
+    fn make_font() -> Font {
+        return Font::from_bytes(make_font_file(0x00010000, &make_tables(1000))).unwrap();
+    }
+
+    #[test]
+    fn reads_metrics_characters_and_kerning() {
+        let font = make_font();
+
+        assert_eq!(font.get_units_per_em(), 1000.0);
+        assert_eq!(font.get_glyph_count(), 4);
+        assert_eq!(font.get_line_metrics().get_line_height(), 1100.0);
+
+        assert_eq!(font.get_glyph_index('A'), Some(SQUARE));
+        assert_eq!(font.get_glyph_index('B'), Some(COMPOSITE));
+        assert_eq!(font.get_glyph_index(' '), Some(SPACE));
+        assert_eq!(font.get_glyph_index('C'), None);
+        assert_eq!(font.get_glyph_index('\u{1F600}'), None);
+
+        // Glyphs past the long metrics use the last advance
+        assert_eq!(font.get_glyph_advance(SQUARE), 600.0);
+        assert_eq!(font.get_glyph_advance(SPACE), 600.0);
+
+        assert_eq!(font.get_kerning(SQUARE, COMPOSITE), -50.0);
+        assert_eq!(font.get_kerning(COMPOSITE, SQUARE), 0.0);
+    }
+
+    #[test]
+    fn reads_simple_and_composite_outlines() {
+        let font = make_font();
+
+        let square = font.get_glyph_outline(SQUARE);
+        assert_eq!(square.contours.len(), 1);
+        let points: Vec<(f32, f32, bool)> = square.contours[0].iter().map(|point| (point.x, point.y, point.on_curve)).collect();
+        assert_eq!(points, [(0.0, 0.0, true), (500.0, 0.0, true), (500.0, 700.0, true), (0.0, 700.0, true)]);
+
+        let composite = font.get_glyph_outline(COMPOSITE);
+        assert_eq!(composite.contours.len(), 1);
+        let xs: Vec<f32> = composite.contours[0].iter().map(|point| point.x).collect();
+        assert_eq!(xs, [100.0, 600.0, 600.0, 100.0]);
+
+        assert!(font.get_glyph_outline(SPACE).is_empty());
+        assert!(font.get_glyph_outline(0).is_empty());
+        assert!(font.get_glyph_outline(100).is_empty());
+    }
+
+    #[test]
+    fn rejects_unsupported_and_malformed_fonts() {
+        let is_invalid     = |result: Result<Font, FontError>| matches!(result, Err(FontError::InvalidFont(_)));
+        let is_unsupported = |result: Result<Font, FontError>| matches!(result, Err(FontError::UnsupportedFont(_)));
+
+        assert!(is_unsupported(Font::from_bytes(make_font_file(0x4F54544F, &make_tables(1000)))));
+        assert!(is_unsupported(Font::from_bytes(make_font_file(0x74746366, &make_tables(1000)))));
+        assert!(is_invalid(Font::from_bytes(make_font_file(0x12345678, &make_tables(1000)))));
+        assert!(is_invalid(Font::from_bytes(Vec::new())));
+
+        assert!(is_invalid(Font::from_bytes(make_font_file(0x00010000, &make_tables(0)))));
+
+        let missing_glyf: Vec<_> = make_tables(1000).into_iter().filter(|(tag, _)| *tag != b"glyf").collect();
+        assert!(is_invalid(Font::from_bytes(make_font_file(0x00010000, &missing_glyf))));
+
+        // Cut off inside the table directory, before the tables themselves
+        let mut truncated = make_font_file(0x00010000, &make_tables(1000));
+        truncated.truncate(12 + 16 * 3);
+        assert!(is_invalid(Font::from_bytes(truncated)));
+
+        let no_unicode_cmap: Vec<_> = make_tables(1000).into_iter()
+            .map(|(tag, table)| if tag == b"cmap" { (tag, vec![0, 0, 0, 0]) } else { (tag, table) })
+            .collect();
+        assert!(is_unsupported(Font::from_bytes(make_font_file(0x00010000, &no_unicode_cmap))));
+    }
+
+    #[test]
+    fn malformed_outlines_are_empty() {
+        let mut tables = make_tables(1000);
+        let glyf = &mut tables[1].1;
+
+        // The square's instructions run past the end of the file
+        glyf[12..14].copy_from_slice(&u16::MAX.to_be_bytes());
+
+        let font = Font::from_bytes(make_font_file(0x00010000, &tables)).unwrap();
+        assert!(font.get_glyph_outline(SQUARE).is_empty());
+        assert!(font.get_glyph_outline(COMPOSITE).is_empty());
+
+        // A composite glyph that references itself stops at the depth limit
+        let mut tables = make_tables(1000);
+        let glyf = &mut tables[1].1;
+        let composite = glyf.windows(4).position(|bytes| bytes == [0, 3, 0, 1]).unwrap(); // flags, then SQUARE
+        glyf[composite + 2..composite + 4].copy_from_slice(&COMPOSITE.to_be_bytes());
+
+        let font = Font::from_bytes(make_font_file(0x00010000, &tables)).unwrap();
+        assert!(font.get_glyph_outline(COMPOSITE).is_empty());
+        assert!(!font.get_glyph_outline(SQUARE).is_empty());
+    }
+}
@@ -0,0 +1,30 @@
+use std::fmt;
+
+//
+// Image Errors
+//
+// Errors from loading an image file. None of these are fatal to the engine, the game decides whether it can run
+// without the image.
+//
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ImageError {
+    /// The image file could not be read.
+    FileNotFound { path: String, reason: String },
+    /// The file isn't an image of the expected format, or its pixels are truncated.
+    InvalidImage(String),
+    /// A valid image the engine can't read, such as a Radiance file with flipped or rotated scanlines.
+    UnsupportedImage(String),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            ImageError::FileNotFound{ path, reason } => write!(f, "Failed to load image {}: {}", path, reason),
+            ImageError::InvalidImage(reason)         => write!(f, "Invalid image: {}", reason),
+            ImageError::UnsupportedImage(reason)     => write!(f, "Unsupported image: {}", reason),
+        };
+    }
+}
+
+impl std::error::Error for ImageError {}
@@ -0,0 +1,341 @@
+use std::path::Path;
+
+use crate::math::{ float3::*, float4::* };
+
+use super::error::ImageError;
+
+//
+// Radiance HDR Images
+//
+// Reads .hdr (.pic) files, the usual format for HDR environment maps. Each pixel is stored as RGBE: an 8-bit
+// mantissa per channel sharing an 8-bit exponent. The scanlines are either flat, run-length encoded the old way
+// (a pixel of 1, 1, 1 repeats the previous pixel), or run-length encoded per channel the new way.
+//
+// Only the standard orientation is read, top to bottom and left to right ("-Y height +X width"). The XYZE format
+// and the other orientations aren't supported.
+//
+
+// New-style RLE is only used for scanlines of this width
+const MIN_RLE_WIDTH: usize = 8;
+const MAX_RLE_WIDTH: usize = 0x7fff;
+
+// Larger images are rejected before anything is allocated for them. The largest environment maps are 16K x 8K.
+const MAX_IMAGE_SIZE:   usize = 32768;
+const MAX_IMAGE_TEXELS: usize = 16384 * 8192;
+
+/// Linear RGB pixels, row by row from the top left. Alpha is always 1.
+#[derive(Clone, Debug)]
+pub struct HdrImage {
+    pub width:  u32,
+    pub height: u32,
+    pub pixels: Vec<Float4>,
+}
+
+impl HdrImage {
+    pub fn from_file(path: &Path) -> Result<HdrImage, ImageError> {
+        let data = match std::fs::read(path) {
+            Ok(data)   => data,
+            Err(error) => return Err(ImageError::FileNotFound{ path: path.display().to_string(), reason: error.to_string() }),
+        };
+
+        return HdrImage::from_bytes(&data);
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<HdrImage, ImageError> {
+        let mut cursor = 0;
+
+        let magic = read_line(data, &mut cursor).ok_or_else(|| invalid("the header is truncated"))?;
+        if !magic.starts_with("#?") {
+            return Err(invalid("missing the #? signature"));
+        }
+
+        // Header variables, up to an empty line
+        let mut exposure = 1.0f32;
+        loop {
+            let line = read_line(data, &mut cursor).ok_or_else(|| invalid("the header is truncated"))?;
+            if line.is_empty() {
+                break;
+            }
+
+            if let Some(format) = line.strip_prefix("FORMAT=") {
+                if format.trim() != "32-bit_rle_rgbe" {
+                    return Err(ImageError::UnsupportedImage(format!("pixel format {}", format.trim())));
+                }
+            } else if let Some(value) = line.strip_prefix("EXPOSURE=") {
+                // Pixel values were multiplied by every exposure applied to the file
+                if let Ok(value) = value.trim().parse::<f32>() {
+                    if value > 0.0 {
+                        exposure *= value;
+                    }
+                }
+            }
+        }
+
+        let resolution = read_line(data, &mut cursor).ok_or_else(|| invalid("missing the resolution"))?;
+        let tokens: Vec<&str> = resolution.split_whitespace().collect();
+        if tokens.len() != 4 {
+            return Err(invalid("malformed resolution"));
+        }
+        if tokens[0] != "-Y" || tokens[2] != "+X" {
+            return Err(ImageError::UnsupportedImage(format!("scanline orientation {} {}", tokens[0], tokens[2])));
+        }
+
+        let height = tokens[1].parse::<usize>().map_err(|_| invalid("malformed resolution"))?;
+        let width  = tokens[3].parse::<usize>().map_err(|_| invalid("malformed resolution"))?;
+        if width == 0 || height == 0 {
+            return Err(invalid("the image is empty"));
+        }
+
+        let texel_count = width.checked_mul(height).filter(|count| width <= MAX_IMAGE_SIZE && height <= MAX_IMAGE_SIZE && *count <= MAX_IMAGE_TEXELS);
+        let Some(texel_count) = texel_count else {
+            return Err(ImageError::UnsupportedImage(format!("a {}x{} image is too large", width, height)));
+        };
+
+        let mut rgbe     = vec![[0u8; 4]; texel_count];
+        let mut scanline = vec![[0u8; 4]; width];
+
+        for y in 0..height {
+            read_scanline(data, &mut cursor, &mut scanline)?;
+            rgbe[y * width..(y + 1) * width].copy_from_slice(&scanline);
+        }
+
+        let pixels = rgbe.iter().map(|texel| {
+            let color = decode_rgbe(*texel) * (1.0 / exposure);
+            Float4::new(color.x, color.y, color.z, 1.0)
+        }).collect();
+
+        return Ok(HdrImage{ width: width as u32, height: height as u32, pixels });
+    }
+}
+
+fn invalid(reason: &str) -> ImageError {
+    return ImageError::InvalidImage(reason.to_string());
+}
+
+// Reads up to the next newline, which is skipped. None at the end of the data.
+fn read_line<'a>(data: &'a [u8], cursor: &mut usize) -> Option<&'a str> {
+    let start  = *cursor;
+    let length = data.get(start..)?.iter().position(|byte| *byte == b'\n')?;
+
+    *cursor = start + length + 1;
+    return std::str::from_utf8(&data[start..start + length]).ok().map(|line| line.trim_end_matches('\r'));
+}
+
+fn read_bytes<'a>(data: &'a [u8], cursor: &mut usize, count: usize) -> Result<&'a [u8], ImageError> {
+    let bytes = data.get(*cursor..*cursor + count).ok_or_else(|| invalid("the pixels are truncated"))?;
+    *cursor += count;
+    return Ok(bytes);
+}
+
+fn read_scanline(data: &[u8], cursor: &mut usize, scanline: &mut [[u8; 4]]) -> Result<(), ImageError> {
+    let width = scanline.len();
+
+    // New-style scanlines start with 2, 2 and the width
+    if (MIN_RLE_WIDTH..=MAX_RLE_WIDTH).contains(&width) {
+        if let Some(start) = data.get(*cursor..*cursor + 4) {
+            if start[0] == 2 && start[1] == 2 && start[2] & 0x80 == 0 {
+                if ((start[2] as usize) << 8 | start[3] as usize) != width {
+                    return Err(invalid("a scanline's width doesn't match the image"));
+                }
+
+                *cursor += 4;
+                return read_rle_scanline(data, cursor, scanline);
+            }
+        }
+    }
+
+    // Flat pixels, possibly with old-style runs
+    let mut x     = 0;
+    let mut shift = 0;
+    while x < width {
+        let bytes = read_bytes(data, cursor, 4)?;
+        let texel = [bytes[0], bytes[1], bytes[2], bytes[3]];
+
+        if texel[0] == 1 && texel[1] == 1 && texel[2] == 1 {
+            // Repeats the previous pixel, consecutive runs make up the higher bits of the count
+            if x == 0 {
+                return Err(invalid("a run at the start of a scanline"));
+            }
+            if texel[3] == 0 {
+                return Err(invalid("an empty run"));
+            }
+            if shift > usize::BITS - 8 {
+                return Err(invalid("too many consecutive runs"));
+            }
+
+            let count = (texel[3] as usize) << shift;
+            if count > width - x {
+                return Err(invalid("a run overflows its scanline"));
+            }
+
+            let previous = scanline[x - 1];
+            scanline[x..x + count].fill(previous);
+
+            x     += count;
+            shift += 8;
+        } else {
+            scanline[x] = texel;
+            x          += 1;
+            shift       = 0;
+        }
+    }
+
+    return Ok(());
+}
+
+// Each channel is stored separately, as runs of a repeated byte and runs of literal bytes
+fn read_rle_scanline(data: &[u8], cursor: &mut usize, scanline: &mut [[u8; 4]]) -> Result<(), ImageError> {
+    let width = scanline.len();
+
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = read_bytes(data, cursor, 1)?[0] as usize;
+
+            if count > 128 {
+                let count = count - 128;
+                if x + count > width {
+                    return Err(invalid("a run overflows its scanline"));
+                }
+
+                let value = read_bytes(data, cursor, 1)?[0];
+                for texel in &mut scanline[x..x + count] {
+                    texel[channel] = value;
+                }
+                x += count;
+            } else {
+                if count == 0 || x + count > width {
+                    return Err(invalid("a run overflows its scanline"));
+                }
+
+                let values = read_bytes(data, cursor, count)?;
+                for (texel, value) in scanline[x..x + count].iter_mut().zip(values) {
+                    texel[channel] = *value;
+                }
+                x += count;
+            }
+        }
+    }
+
+    return Ok(());
+}
+
+fn decode_rgbe(texel: [u8; 4]) -> Float3 {
+    if texel[3] == 0 {
+        return Float3::zero();
+    }
+
+    // The exponent is biased by 128, and the mantissas are 8-bit fractions. Sample at the middle of each step.
+    let scale = 2.0f32.powi(texel[3] as i32 - (128 + 8));
+    return Float3::new(texel[0] as f32 + 0.5, texel[1] as f32 + 0.5, texel[2] as f32 + 0.5) * scale;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // About 1.0 and 0.5 in every channel, decoded at the middle of the mantissa's step
+    const ONE:  [u8; 4] = [128, 128, 128, 129];
+    const HALF: [u8; 4] = [128, 128, 128, 128];
+
+    fn make_file(header: &str, width: usize, height: usize, pixels: &[u8]) -> Vec<u8> {
+        let mut data = format!("#?RADIANCE\n{}FORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", header, height, width).into_bytes();
+        data.extend_from_slice(pixels);
+        return data;
+    }
+
+    fn assert_red(data: &[u8], expected: &[f32]) {
+        let image = HdrImage::from_bytes(data).unwrap();
+        assert_eq!(image.pixels.len(), expected.len());
+
+        for (pixel, expected) in image.pixels.iter().zip(expected) {
+            assert!((pixel.x - expected).abs() < 0.01, "{} isn't {}", pixel.x, expected);
+        }
+    }
+
+    fn is_invalid(data: &[u8]) -> bool {
+        return matches!(HdrImage::from_bytes(data), Err(ImageError::InvalidImage(_)));
+    }
+
+    #[test]
+    fn reads_flat_pixels() {
+        let pixels = [ONE, HALF, [0, 0, 0, 0], ONE].concat();
+        let image  = HdrImage::from_bytes(&make_file("", 2, 2, &pixels)).unwrap();
+
+        assert_eq!((image.width, image.height), (2, 2));
+        assert_red(&make_file("", 2, 2, &pixels), &[1.0, 0.5, 0.0, 1.0]);
+        assert!(image.pixels.iter().all(|pixel| pixel.w == 1.0));
+
+        // Exposures multiply, and the pixels are divided by them
+        assert_red(&make_file("EXPOSURE=2\nEXPOSURE=2\n", 2, 2, &pixels), &[0.25, 0.125, 0.0, 0.25]);
+    }
+
+    #[test]
+    fn reads_old_style_runs() {
+        // A run repeats the previous pixel
+        let pixels = [HALF, [1, 1, 1, 2], ONE].concat();
+        assert_red(&make_file("", 4, 1, &pixels), &[0.5, 0.5, 0.5, 1.0]);
+
+        // Consecutive runs are the higher bytes of the count: 3 + 1 * 256
+        let pixels = [HALF, [1, 1, 1, 3], [1, 1, 1, 1]].concat();
+        assert_red(&make_file("", 260, 1, &pixels), &[0.5; 260]);
+    }
+
+    #[test]
+    fn reads_new_style_runs() {
+        // Red and exponent as runs, green and blue as literals
+        let mut pixels = vec![2, 2, 0, 8];
+        pixels.extend_from_slice(&[128 + 8, 128]);
+        pixels.extend_from_slice(&[8, 0, 0, 0, 0, 128, 128, 128, 128]);
+        pixels.extend_from_slice(&[4, 0, 0, 0, 0, 128 + 4, 64]);
+        pixels.extend_from_slice(&[128 + 8, 129]);
+
+        let image = HdrImage::from_bytes(&make_file("", 8, 1, &pixels)).unwrap();
+        assert!(image.pixels.iter().all(|pixel| (pixel.x - 1.0).abs() < 0.01));
+        assert!(image.pixels[..4].iter().all(|pixel| pixel.y < 0.01 && pixel.z < 0.01));
+        assert!(image.pixels[4..].iter().all(|pixel| (pixel.y - 1.0).abs() < 0.01 && (pixel.z - 0.5).abs() < 0.01));
+    }
+
+    #[test]
+    fn rejects_malformed_runs() {
+        // Old style: a run with nothing to repeat, an empty run, and runs past the end of the scanline
+        assert!(is_invalid(&make_file("", 2, 1, &[[1, 1, 1, 1], ONE].concat())));
+        assert!(is_invalid(&make_file("", 2, 1, &[ONE, [1, 1, 1, 0]].concat())));
+        assert!(is_invalid(&make_file("", 4, 1, &[ONE, [1, 1, 1, 4]].concat())));
+        assert!(is_invalid(&make_file("", 4, 1, &[ONE, [1, 1, 1, 1], [1, 1, 1, 1]].concat())));
+
+        // Each consecutive run shifts its count by another byte, they run past the scanline long before overflowing
+        let mut pixels = ONE.to_vec();
+        for _ in 0..16 {
+            pixels.extend_from_slice(&[1, 1, 1, 1]);
+        }
+        assert!(is_invalid(&make_file("", MAX_IMAGE_SIZE, 1, &pixels)));
+
+        // New style: a width that isn't the image's, a run past the scanline, and a zero length literal
+        assert!(is_invalid(&make_file("", 8, 1, &[2, 2, 0, 9, 128 + 8, 0])));
+        assert!(is_invalid(&make_file("", 8, 1, &[2, 2, 0, 8, 128 + 9, 0])));
+        assert!(is_invalid(&make_file("", 8, 1, &[2, 2, 0, 8, 0])));
+        assert!(is_invalid(&make_file("", 8, 1, &[2, 2, 0, 8, 9, 0, 0, 0, 0, 0, 0, 0, 0, 0])));
+
+        // Truncated pixels
+        assert!(is_invalid(&make_file("", 2, 2, &[ONE, ONE, ONE].concat())));
+        assert!(is_invalid(&make_file("", 8, 1, &[2, 2, 0, 8, 128 + 8])));
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        assert!(is_invalid(b"P6\n"));
+        assert!(is_invalid(b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n"));
+        assert!(is_invalid(&make_file("", 0, 4, &[])));
+        assert!(is_invalid(b"#?RADIANCE\n\n-Y two +X 2\n"));
+
+        let unsupported = |data: &[u8]| matches!(HdrImage::from_bytes(data), Err(ImageError::UnsupportedImage(_)));
+        assert!(unsupported(b"#?RADIANCE\nFORMAT=32-bit_rle_xyze\n\n-Y 1 +X 1\n\x80\x80\x80\x81"));
+        assert!(unsupported(b"#?RADIANCE\n\n+Y 1 +X 1\n\x80\x80\x80\x81"));
+
+        // Too large to allocate, rejected before reading any pixels
+        assert!(unsupported(&make_file("", MAX_IMAGE_SIZE + 1, 1, &[])));
+        assert!(unsupported(&make_file("", MAX_IMAGE_SIZE, MAX_IMAGE_SIZE, &[])));
+        assert!(unsupported(&make_file("", usize::MAX, usize::MAX, &[])));
+    }
+}
@@ -8,7 +8,10 @@
 
 extern crate vendor;
 
+pub mod animation;
 pub mod core;
+pub mod font;
+pub mod image;
 pub mod math;
 pub mod renderer;
 pub mod window;
@@ -57,8 +60,8 @@ pub const ENGINE_VERSION_MINOR: u32 = 0;
 pub const ENGINE_VERSION_PATCH: u32 = 1;
 pub const ENGINE_VERSION: u32 = ENGINE_VERSION_MAJOR << 24 | ENGINE_VERSION_MINOR << 16 | ENGINE_VERSION_PATCH << 8;
 
-pub fn new_engine(game_info: core::engine::GameInfo) -> Rc<core::engine::Engine>{
-    return Rc::new(core::engine::Engine::new(game_info));
+pub fn new_engine(game_info: core::engine::GameInfo) -> Result<Rc<core::engine::Engine>, renderer::error::RenderError> {
+    return Ok(Rc::new(core::engine::Engine::new(game_info)?));
 }
 
 pub fn make_app_version(major: u32, minor: u32, patch: u32) -> u32 {
@@ -4,6 +4,7 @@ use crate::math::{
     {Float, float_is_zero, rand_float, rand_float_in_range},
     float4::*,
     float3::*,
+    quaternion::*,
 };
 
 use super::degrees_to_radians;
@@ -227,6 +228,56 @@ impl Float4x4 {
         return result;
     }
 
+    // Get a Matrix that scales, then rotates, then translates. This is how glTF nodes and animation keyframes
+    // describe a local transform.
+    pub fn get_transform_matrix(translation: Float3, rotation: Quaternion, scale: Float3) -> Self {
+        let mut result = Float4x4::default();
+
+        let q  = rotation.normalize();
+        let xx = q.x * q.x; let yy = q.y * q.y; let zz = q.z * q.z;
+        let xy = q.x * q.y; let xz = q.x * q.z; let yz = q.y * q.z;
+        let wx = q.w * q.x; let wy = q.w * q.y; let wz = q.w * q.z;
+
+        unsafe {
+            result._data[0][0] = (1.0 - 2.0 * (yy + zz)) * scale.x;
+            result._data[0][1] = (2.0 * (xy + wz))       * scale.x;
+            result._data[0][2] = (2.0 * (xz - wy))       * scale.x;
+            result._data[0][3] = 0.0;
+
+            result._data[1][0] = (2.0 * (xy - wz))       * scale.y;
+            result._data[1][1] = (1.0 - 2.0 * (xx + zz)) * scale.y;
+            result._data[1][2] = (2.0 * (yz + wx))       * scale.y;
+            result._data[1][3] = 0.0;
+
+            result._data[2][0] = (2.0 * (xz + wy))       * scale.z;
+            result._data[2][1] = (2.0 * (yz - wx))       * scale.z;
+            result._data[2][2] = (1.0 - 2.0 * (xx + yy)) * scale.z;
+            result._data[2][3] = 0.0;
+
+            result._data[3][0] = translation.x;
+            result._data[3][1] = translation.y;
+            result._data[3][2] = translation.z;
+            result._data[3][3] = 1.0;
+        }
+
+        return result;
+    }
+
+    // Builds a Matrix from 16 floats in column-major order, the layout glTF stores matrices in
+    pub fn from_column_major(values: &[f32; 16]) -> Self {
+        let mut result = Float4x4::default();
+
+        unsafe {
+            for col in 0..4 {
+                for row in 0..4 {
+                    result._data[col][row] = values[col * 4 + row];
+                }
+            }
+        }
+
+        return result;
+    }
+
     // get a Right-Handed Look-At Matrix
     pub fn get_look_at_matrix(eye_position: Float3, eye_look_at_point: Float3, mut up_vector: Float3) -> Self {
         let mut result = Float4x4::default();
@@ -285,6 +336,40 @@ impl Float4x4 {
         return result;
     }
 
+    // get a Right-Handed Orthographic Matrix
+    //   Maps view-space z in [-near, -far] to a [0, 1] depth range, so it can be used for Vulkan depth images
+    //   without a depth-range fixup (used for shadow maps).
+    pub fn get_orthographic_matrix(left: f32, right: f32, bottom: f32, top: f32, near_plane: f32, far_plane: f32) -> Self {
+        let mut result = Float4x4::default();
+
+        unsafe {
+            result._data[0][0] =  2.0 / (right - left);
+            result._data[1][1] =  2.0 / (top - bottom);
+            result._data[2][2] = -1.0 / (far_plane - near_plane);
+            result._data[3][0] = -(right + left) / (right - left);
+            result._data[3][1] = -(top + bottom) / (top - bottom);
+            result._data[3][2] = -near_plane / (far_plane - near_plane);
+            result._data[3][3] =  1.0;
+        }
+
+        return result;
+    }
+
+    // get an Orthographic Camera Matrix for 2D
+    //   Views `width` x `height` world units of the XY plane around (center_x, center_y), with +y up on screen. Use a
+    //   width and height in pixels for pixel-perfect sprites. Only z = 0 is used, sprites are ordered by layer.
+    pub fn get_orthographic_2d_matrix(center_x: f32, center_y: f32, width: f32, height: f32) -> Self {
+        let half_width  = width  * 0.5;
+        let half_height = height * 0.5;
+
+        // Vulkan's clip space has +y down, so the top of the view goes to the bottom of the clip volume
+        return Float4x4::get_orthographic_matrix(
+            center_x - half_width,  center_x + half_width,
+            center_y + half_height, center_y - half_height,
+            -1.0, 1.0,
+        );
+    }
+
     pub fn transpose(&self) -> Self {
         let mut result = Float4x4::default();
 
@@ -313,6 +398,37 @@ impl Float4x4 {
         return result;
     }
 
+    /// Extracts the six planes of the clip volume of a view-projection matrix (Gribb-Hartmann): left, right, bottom,
+    /// top, near, far. xyz is the plane normal pointing into the volume and w the distance, normalized so that
+    /// `plane.dot(point)` is the signed world-space distance of a point with w = 1.
+    ///   @assume: the Vulkan clip volume, -w <= x,y <= w and 0 <= z <= w.
+    pub fn get_frustum_planes(&self) -> [Float4; 6] {
+        let mut planes = [Float4::zero(); 6];
+
+        unsafe {
+            let r0 = Float4{x: self._data[0][0], y: self._data[1][0], z: self._data[2][0], w: self._data[3][0] };
+            let r1 = Float4{x: self._data[0][1], y: self._data[1][1], z: self._data[2][1], w: self._data[3][1] };
+            let r2 = Float4{x: self._data[0][2], y: self._data[1][2], z: self._data[2][2], w: self._data[3][2] };
+            let r3 = Float4{x: self._data[0][3], y: self._data[1][3], z: self._data[2][3], w: self._data[3][3] };
+
+            planes[0] = Float4::new(r3.x + r0.x, r3.y + r0.y, r3.z + r0.z, r3.w + r0.w); // left
+            planes[1] = Float4::new(r3.x - r0.x, r3.y - r0.y, r3.z - r0.z, r3.w - r0.w); // right
+            planes[2] = Float4::new(r3.x + r1.x, r3.y + r1.y, r3.z + r1.z, r3.w + r1.w); // bottom
+            planes[3] = Float4::new(r3.x - r1.x, r3.y - r1.y, r3.z - r1.z, r3.w - r1.w); // top
+            planes[4] = r2;                                                              // near
+            planes[5] = Float4::new(r3.x - r2.x, r3.y - r2.y, r3.z - r2.z, r3.w - r2.w); // far
+        }
+
+        for plane in &mut planes {
+            let length = (plane.x * plane.x + plane.y * plane.y + plane.z * plane.z).sqrt();
+            if !float_is_zero(length) {
+                *plane = Float4::new(plane.x / length, plane.y / length, plane.z / length, plane.w / length);
+            }
+        }
+
+        return planes;
+    }
+
     pub fn translate_point(&self, point: Float4) -> Float4 {
         let mut result = Float4::zero();
 
@@ -4,6 +4,7 @@ pub mod float2;
 pub mod float3;
 pub mod float4;
 pub mod float4x4;
+pub mod quaternion;
 
 use float3::Float3;
 
@@ -0,0 +1,222 @@
+use std::ops;
+
+use crate::math::{Float, float_is_zero, float3::*, degrees_to_radians};
+
+// A rotation, stored as (x, y, z) = axis * sin(angle / 2) and w = cos(angle / 2). Follows the glTF conventions,
+// so keyframes can be read as-is.
+#[derive(Copy, Clone, Debug)]
+pub struct Quaternion {
+    pub x: Float,
+    pub y: Float,
+    pub z: Float,
+    pub w: Float,
+}
+
+impl Default for Quaternion {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Quaternion {
+    pub fn new(x: Float, y: Float, z: Float, w: Float) -> Quaternion {
+        Quaternion { x, y, z, w }
+    }
+
+    pub fn identity() -> Quaternion {
+        return Self::new(0.0, 0.0, 0.0, 1.0);
+    }
+
+    pub fn from_axis_angle(theta_degrees: Float, axis: Float3) -> Quaternion {
+        let half_angle = degrees_to_radians(theta_degrees) * 0.5;
+        let axis       = axis.unit() * half_angle.sin();
+
+        return Self::new(axis.x, axis.y, axis.z, half_angle.cos());
+    }
+
+    // The rotation of an orthonormal basis, given as the columns of a rotation matrix
+    pub fn from_rotation_axes(x_axis: Float3, y_axis: Float3, z_axis: Float3) -> Quaternion {
+        // Shepperd's method, divides by the largest of the four components to stay accurate
+        let trace = x_axis.x + y_axis.y + z_axis.z;
+
+        let q = if trace > 0.0 {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            Self::new((y_axis.z - z_axis.y) / s, (z_axis.x - x_axis.z) / s, (x_axis.y - y_axis.x) / s, 0.25 * s)
+        } else if x_axis.x > y_axis.y && x_axis.x > z_axis.z {
+            let s = (1.0 + x_axis.x - y_axis.y - z_axis.z).sqrt() * 2.0;
+            Self::new(0.25 * s, (y_axis.x + x_axis.y) / s, (z_axis.x + x_axis.z) / s, (y_axis.z - z_axis.y) / s)
+        } else if y_axis.y > z_axis.z {
+            let s = (1.0 + y_axis.y - x_axis.x - z_axis.z).sqrt() * 2.0;
+            Self::new((y_axis.x + x_axis.y) / s, 0.25 * s, (z_axis.y + y_axis.z) / s, (z_axis.x - x_axis.z) / s)
+        } else {
+            let s = (1.0 + z_axis.z - x_axis.x - y_axis.y).sqrt() * 2.0;
+            Self::new((z_axis.x + x_axis.z) / s, (z_axis.y + y_axis.z) / s, 0.25 * s, (x_axis.y - y_axis.x) / s)
+        };
+
+        return q.normalize();
+    }
+
+    pub fn dot(&self, q: Quaternion) -> Float {
+        self.x * q.x + self.y * q.y + self.z * q.z + self.w * q.w
+    }
+
+    pub fn length(self) -> Float {
+        self.dot(self).sqrt()
+    }
+
+    pub fn normalize(self) -> Quaternion {
+        let len = self.length();
+        if !float_is_zero(len) {
+            self * (1.0 / len)
+        } else {
+            Quaternion::identity()
+        }
+    }
+
+    // The inverse rotation, assumes a unit quaternion
+    pub fn conjugate(self) -> Quaternion {
+        return Self::new(-self.x, -self.y, -self.z, self.w);
+    }
+
+    pub fn rotate(self, v: Float3) -> Float3 {
+        // v' = v + 2w(q x v) + 2(q x (q x v))
+        let q = Float3::new(self.x, self.y, self.z);
+        let t = q.cross(v) * 2.0;
+        return v + t * self.w + q.cross(t);
+    }
+
+    // Normalized linear interpolation along the shortest path. Cheaper than slerp and close enough for the small
+    // angles between keyframes and when blending poses.
+    pub fn nlerp(self, q: Quaternion, t: Float) -> Quaternion {
+        let q = if self.dot(q) < 0.0 { -q } else { q };
+        return (self * (1.0 - t) + q * t).normalize();
+    }
+
+    // Spherical linear interpolation along the shortest path, constant angular velocity.
+    pub fn slerp(self, q: Quaternion, t: Float) -> Quaternion {
+        let mut cos_theta = self.dot(q);
+        let mut q         = q;
+        if cos_theta < 0.0 {
+            q         = -q;
+            cos_theta = -cos_theta;
+        }
+
+        // Nearly the same rotation, sin(theta) is too small to divide by
+        if cos_theta > 0.9995 {
+            return self.nlerp(q, t);
+        }
+
+        let theta     = cos_theta.acos();
+        let sin_theta = theta.sin();
+        let a         = ((1.0 - t) * theta).sin() / sin_theta;
+        let b         = (t * theta).sin() / sin_theta;
+
+        return (self * a + q * b).normalize();
+    }
+}
+
+impl ops::Add<Quaternion> for Quaternion {
+    type Output = Quaternion;
+    fn add(self, q: Quaternion) -> Quaternion {
+        Quaternion::new(self.x + q.x, self.y + q.y, self.z + q.z, self.w + q.w)
+    }
+}
+
+impl ops::Mul<Float> for Quaternion {
+    type Output = Quaternion;
+    fn mul(self, s: Float) -> Quaternion {
+        Quaternion::new(self.x * s, self.y * s, self.z * s, self.w * s)
+    }
+}
+
+// Hamilton product, `a * b` rotates by b and then by a
+impl ops::Mul<Quaternion> for Quaternion {
+    type Output = Quaternion;
+    fn mul(self, q: Quaternion) -> Quaternion {
+        Quaternion::new(
+            self.w * q.x + self.x * q.w + self.y * q.z - self.z * q.y,
+            self.w * q.y - self.x * q.z + self.y * q.w + self.z * q.x,
+            self.w * q.z + self.x * q.y - self.y * q.x + self.z * q.w,
+            self.w * q.w - self.x * q.x - self.y * q.y - self.z * q.z,
+        )
+    }
+}
+
+impl ops::Neg for Quaternion {
+    type Output = Quaternion;
+    fn neg(self) -> Quaternion {
+        Quaternion::new(-self.x, -self.y, -self.z, -self.w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: Float = 1e-4;
+
+    fn assert_near(actual: Float3, expected: Float3) {
+        assert!((actual - expected).length() < EPSILON, "{:?} isn't {:?}", actual, expected);
+    }
+
+    // q and -q are the same rotation
+    fn assert_same_rotation(actual: Quaternion, expected: Quaternion) {
+        assert!((actual.dot(expected).abs() - 1.0).abs() < EPSILON, "{:?} isn't {:?}", actual, expected);
+    }
+
+    #[test]
+    fn rotates_and_composes() {
+        let yaw = Quaternion::from_axis_angle(90.0, Float3::new(0.0, 1.0, 0.0));
+        assert_near(yaw.rotate(Float3::new(1.0, 0.0, 0.0)), Float3::new(0.0, 0.0, -1.0));
+        assert_near(yaw.conjugate().rotate(yaw.rotate(Float3::new(1.0, 2.0, 3.0))), Float3::new(1.0, 2.0, 3.0));
+
+        // a * b rotates by b first
+        let pitch = Quaternion::from_axis_angle(90.0, Float3::new(1.0, 0.0, 0.0));
+        let v     = Float3::new(0.0, 0.0, 1.0);
+        assert_near((yaw * pitch).rotate(v), yaw.rotate(pitch.rotate(v)));
+        assert_near((yaw * pitch).rotate(v), Float3::new(0.0, -1.0, 0.0));
+
+        assert_same_rotation(Quaternion::new(0.0, 0.0, 0.0, 0.0).normalize(), Quaternion::identity());
+    }
+
+    #[test]
+    fn rotation_axes_round_trip() {
+        // Angles that exercise each branch of Shepperd's method: a positive trace, then the largest diagonal on x, y and z
+        let rotations = [
+            Quaternion::from_axis_angle(30.0, Float3::new(1.0, 2.0, 3.0)),
+            Quaternion::from_axis_angle(170.0, Float3::new(1.0, 0.1, 0.0)),
+            Quaternion::from_axis_angle(170.0, Float3::new(0.1, 1.0, 0.0)),
+            Quaternion::from_axis_angle(170.0, Float3::new(0.0, 0.1, 1.0)),
+        ];
+
+        for rotation in rotations {
+            let x_axis = rotation.rotate(Float3::new(1.0, 0.0, 0.0));
+            let y_axis = rotation.rotate(Float3::new(0.0, 1.0, 0.0));
+            let z_axis = rotation.rotate(Float3::new(0.0, 0.0, 1.0));
+
+            assert_same_rotation(Quaternion::from_rotation_axes(x_axis, y_axis, z_axis), rotation);
+        }
+    }
+
+    #[test]
+    fn interpolation_takes_the_shortest_path() {
+        let start = Quaternion::identity();
+        let end   = Quaternion::from_axis_angle(90.0, Float3::new(0.0, 0.0, 1.0));
+        let half  = Quaternion::from_axis_angle(45.0, Float3::new(0.0, 0.0, 1.0));
+
+        assert_same_rotation(start.slerp(end, 0.5), half);
+        assert_same_rotation(start.nlerp(end, 0.5), half);
+        assert_same_rotation(start.slerp(end, 0.0), start);
+        assert_same_rotation(start.slerp(end, 1.0), end);
+
+        // The negated end is the same rotation, and must not send the interpolation the long way around
+        assert_same_rotation(start.slerp(-end, 0.5), half);
+        assert_same_rotation(start.nlerp(-end, 0.5), half);
+
+        // Nearly equal rotations fall back to nlerp instead of dividing by sin(theta)
+        let close = Quaternion::from_axis_angle(0.01, Float3::new(0.0, 0.0, 1.0));
+        let mid   = start.slerp(close, 0.5);
+        assert!(mid.x.is_finite() && mid.y.is_finite() && mid.z.is_finite() && mid.w.is_finite());
+        assert_same_rotation(mid, Quaternion::from_axis_angle(0.005, Float3::new(0.0, 0.0, 1.0)));
+    }
+}
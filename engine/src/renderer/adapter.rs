@@ -0,0 +1,293 @@
+use crate::window::NativeSurface;
+
+use super::error::RenderError;
+use super::graphics::gpu_device::{Instance, Surface, Gpu};
+
+//
+// GPU Adapters
+//
+// A Vulkan-free description of the physical devices found on the system. The renderer scores these to pick
+// a GPU when the game doesn't request one, and tools (see demos/gpuinfo) use them to print a capability report.
+//
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AdapterType {
+    Discrete,
+    Integrated,
+    Virtual,
+    Cpu,
+    Other,
+}
+
+#[derive(Clone, Debug)]
+pub struct MemoryHeapInfo {
+    pub size:         u64, // in bytes
+    pub device_local: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct AdapterFeature {
+    pub name:      &'static str,
+    pub supported: bool,
+    pub required:  bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct AdapterInfo {
+    pub index:             usize,  // index of the adapter in Vulkan's enumeration order
+    pub name:              String,
+    pub adapter_type:      AdapterType,
+    pub vendor_id:         u32,
+    pub device_id:         u32,
+    pub driver_version:    String,
+    pub api_version:       String,
+    pub memory_heaps:      Vec<MemoryHeapInfo>,
+    pub features:          Vec<AdapterFeature>,
+    pub extensions:        Vec<String>,
+    /// Human readable reasons the engine can't render with this adapter. Empty if the adapter is suitable.
+    pub rejection_reasons: Vec<String>,
+}
+
+impl AdapterInfo {
+    pub fn is_suitable(&self) -> bool {
+        return self.rejection_reasons.is_empty();
+    }
+
+    pub fn get_device_local_memory(&self) -> u64 {
+        return self.memory_heaps.iter()
+            .filter(|heap| heap.device_local)
+            .map(|heap| heap.size)
+            .sum();
+    }
+
+    pub fn get_vendor_name(&self) -> &'static str {
+        return match self.vendor_id {
+            0x1002  => "AMD",
+            0x10DE  => "NVIDIA",
+            0x8086  => "Intel",
+            0x13B5  => "ARM",
+            0x5143  => "Qualcomm",
+            0x106B  => "Apple",
+            0x10005 => "Mesa",
+            _       => "Unknown",
+        };
+    }
+
+    pub fn has_extension(&self, extension: &str) -> bool {
+        return self.extensions.iter().any(|ext| ext == extension);
+    }
+
+    pub fn has_feature(&self, feature: &str) -> bool {
+        return self.features.iter().any(|supported| supported.name == feature && supported.supported);
+    }
+}
+
+/// Scores an adapter for automatic selection. Discrete GPUs always win over integrated GPUs, which win over
+/// virtual and software adapters. Ties within a type are broken by the amount of device local memory.
+/// Returns None if the adapter does not meet the engine's requirements.
+pub fn score_adapter(adapter: &AdapterInfo) -> Option<u64> {
+    if !adapter.is_suitable() {
+        return None;
+    }
+
+    let type_rank: u64 = match adapter.adapter_type {
+        AdapterType::Discrete   => 4,
+        AdapterType::Integrated => 3,
+        AdapterType::Virtual    => 2,
+        AdapterType::Cpu        => 1,
+        AdapterType::Other      => 0,
+    };
+
+    // Memory is measured in MiB so it can never overflow into the type rank.
+    let memory_mib = adapter.get_device_local_memory() >> 20;
+    return Some((type_rank << 48) | memory_mib.min((1 << 48) - 1));
+}
+
+/// Picks the adapter to render with. If the caller requested a specific adapter, it is used as long as it is
+/// suitable, otherwise the highest scoring adapter is chosen. Returns the position of the adapter in the list.
+pub fn select_adapter(adapters: &[AdapterInfo], requested: Option<usize>) -> Result<usize, String> {
+    if let Some(index) = requested {
+        let Some(position) = adapters.iter().position(|adapter| adapter.index == index) else {
+            return Err(format!("Requested GPU {} does not exist. Found {} GPU(s).", index, adapters.len()));
+        };
+
+        let adapter = &adapters[position];
+        if !adapter.is_suitable() {
+            return Err(format!("Requested GPU {} ({}) is not suitable: {}", index, adapter.name, adapter.rejection_reasons.join("; ")));
+        }
+
+        return Ok(position);
+    }
+
+    let mut best: Option<(usize, u64)> = None;
+    for (position, adapter) in adapters.iter().enumerate() {
+        if let Some(score) = score_adapter(adapter) {
+            if best.is_none() || score > best.unwrap().1 {
+                best = Some((position, score));
+            }
+        }
+    }
+
+    if let Some((position, _)) = best {
+        return Ok(position);
+    }
+
+    return Err("No suitable GPU found.".to_string());
+}
+
+/// Builds a human readable report of every adapter, including why rejected adapters can't be used.
+pub fn format_adapter_report(adapters: &[AdapterInfo], selected: Option<usize>, show_extensions: bool) -> String {
+    use std::fmt::Write;
+
+    let mut report = String::new();
+
+    if adapters.is_empty() {
+        report.push_str("No Vulkan capable GPUs found.\n");
+        return report;
+    }
+
+    for (position, adapter) in adapters.iter().enumerate() {
+        let selected_str = if selected == Some(position) { " (selected)" } else { "" };
+        let _ = writeln!(report, "GPU {}: {} [{:?}]{}", adapter.index, adapter.name, adapter.adapter_type, selected_str);
+        let _ = writeln!(report, "    Vendor:      {} (0x{:04x}), Device: 0x{:04x}", adapter.get_vendor_name(), adapter.vendor_id, adapter.device_id);
+        let _ = writeln!(report, "    Driver:      {}", adapter.driver_version);
+        let _ = writeln!(report, "    Vulkan API:  {}", adapter.api_version);
+
+        let _ = writeln!(report, "    Memory Heaps:");
+        for (heap_index, heap) in adapter.memory_heaps.iter().enumerate() {
+            let local_str = if heap.device_local { " (device local)" } else { "" };
+            let _ = writeln!(report, "        Heap {}: {} MiB{}", heap_index, heap.size >> 20, local_str);
+        }
+
+        let _ = writeln!(report, "    Features:");
+        for feature in &adapter.features {
+            let supported_str = if feature.supported { "x" } else { " " };
+            let required_str  = if feature.required  { " (required)" } else { "" };
+            let _ = writeln!(report, "        [{}] {}{}", supported_str, feature.name, required_str);
+        }
+
+        if show_extensions {
+            let _ = writeln!(report, "    Extensions ({}):", adapter.extensions.len());
+            for extension in &adapter.extensions {
+                let _ = writeln!(report, "        {}", extension);
+            }
+        } else {
+            let _ = writeln!(report, "    Extensions:  {} available", adapter.extensions.len());
+        }
+
+        if let Some(score) = score_adapter(adapter) {
+            let _ = writeln!(report, "    Status:      Suitable (score {})", score);
+        } else {
+            let _ = writeln!(report, "    Status:      Rejected");
+            for reason in &adapter.rejection_reasons {
+                let _ = writeln!(report, "        - {}", reason);
+            }
+        }
+
+        report.push('\n');
+    }
+
+    return report;
+}
+
+/// Enumerates every GPU visible to Vulkan. When a surface is provided, adapters are also checked for
+/// presentation support, otherwise presentation is assumed to be available.
+pub fn enumerate_adapters(surface: Option<NativeSurface>) -> Result<Vec<AdapterInfo>, RenderError> {
+    let mut instance = Instance::new(crate::make_app_version(0, 0, 1), "Adapter Report")?;
+
+    let mut vk_surface = match surface.map(|native| Surface::new(&instance, native)) {
+        Some(Ok(surf))   => Some(surf),
+        Some(Err(error)) => {
+            instance.destroy();
+            return Err(error);
+        },
+        None             => None,
+    };
+
+    let gpus = Gpu::enumerate_gpus(&instance, vk_surface.as_ref());
+
+    if let Some(ref mut surf) = vk_surface {
+        surf.destroy(&instance);
+    }
+    instance.destroy();
+
+    return Ok(gpus?.iter().map(|gpu| gpu.info.clone()).collect());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_adapter(index: usize, adapter_type: AdapterType, memory_mib: u64) -> AdapterInfo {
+        return AdapterInfo {
+            index,
+            name:              format!("GPU {}", index),
+            adapter_type,
+            vendor_id:         0x10DE,
+            device_id:         0,
+            driver_version:    "1.0.0".to_string(),
+            api_version:       "1.3.0".to_string(),
+            memory_heaps:      vec![MemoryHeapInfo { size: memory_mib << 20, device_local: true }],
+            features:          vec![AdapterFeature { name: "dynamicRendering", supported: true, required: true }],
+            extensions:        Vec::new(),
+            rejection_reasons: Vec::new(),
+        };
+    }
+
+    fn make_unsuitable(mut adapter: AdapterInfo) -> AdapterInfo {
+        adapter.features[0].supported = false;
+        adapter.rejection_reasons.push("Missing required feature: dynamicRendering".to_string());
+        return adapter;
+    }
+
+    #[test]
+    fn discrete_gpus_outrank_integrated_gpus_with_more_memory() {
+        let discrete   = make_adapter(0, AdapterType::Discrete, 2048);
+        let integrated = make_adapter(1, AdapterType::Integrated, 16384);
+        assert!(score_adapter(&discrete).unwrap() > score_adapter(&integrated).unwrap());
+
+        let adapters = vec![integrated, discrete];
+        assert_eq!(select_adapter(&adapters, None), Ok(1));
+    }
+
+    #[test]
+    fn memory_breaks_ties_within_a_type() {
+        let adapters = vec![
+            make_adapter(0, AdapterType::Discrete, 4096),
+            make_adapter(1, AdapterType::Discrete, 8192),
+        ];
+        assert_eq!(select_adapter(&adapters, None), Ok(1));
+    }
+
+    #[test]
+    fn requested_index_overrides_the_ranking() {
+        // positions and indices differ so the lookup by index is exercised
+        let adapters = vec![
+            make_adapter(3, AdapterType::Discrete, 8192),
+            make_adapter(5, AdapterType::Integrated, 1024),
+        ];
+        assert_eq!(select_adapter(&adapters, Some(5)), Ok(1));
+    }
+
+    #[test]
+    fn invalid_requested_index_is_rejected() {
+        let adapters = vec![make_adapter(0, AdapterType::Discrete, 8192)];
+
+        let error = select_adapter(&adapters, Some(7)).unwrap_err();
+        assert!(error.contains("Requested GPU 7 does not exist"), "{}", error);
+    }
+
+    #[test]
+    fn unsuitable_gpus_are_never_selected() {
+        let missing_feature = make_unsuitable(make_adapter(0, AdapterType::Discrete, 8192));
+        assert_eq!(score_adapter(&missing_feature), None);
+
+        let adapters = vec![missing_feature.clone(), make_adapter(1, AdapterType::Cpu, 0)];
+        assert_eq!(select_adapter(&adapters, None), Ok(1));
+
+        let error = select_adapter(&adapters, Some(0)).unwrap_err();
+        assert!(error.contains("Missing required feature: dynamicRendering"), "{}", error);
+
+        assert_eq!(select_adapter(&[missing_feature], None), Err("No suitable GPU found.".to_string()));
+    }
+}
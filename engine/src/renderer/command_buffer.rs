@@ -1,7 +1,21 @@
 use std::collections::VecDeque;
+use std::path::PathBuf;
 
-use crate::math::float4x4::*;
-use super::mesh::Vertex;
+use crate::image::{ error::ImageError, hdr::HdrImage };
+use crate::math::{ float3::*, float4::*, float4x4::* };
+use super::environment::SkyboxSettings;
+use super::lod::{ LodSettings, MeshLod };
+use super::mesh::{ Vertex, SkinVertex };
+use super::shadows::ShadowSettings;
+use super::ssao::SsaoSettings;
+use super::post_process::{ PostProcessSettings, ColorGradingLut };
+use super::culling::GpuCullingSettings;
+use super::deferred::DeferredSettings;
+use super::debug_draw::DebugLines;
+use super::particles::{ ParticleEmitter, ParticleSettings };
+use super::sprite::{ Sprite, SpriteSettings };
+use super::text::TextDraw;
+use super::transparency::TransparencySettings;
 
 pub struct CreateMeshInfo {
     pub vertices:     *const Vertex,
@@ -9,9 +23,11 @@ pub struct CreateMeshInfo {
 
     pub indices:      *const u32,
     pub index_count:  usize,
+    pub lods:         Vec<MeshLod>, // index ranges of the LODs, see lod.rs, in any order. Empty draws every index as LOD 0
 
     //todo: other mesh properties
     pub transform:    Float4x4,
+    pub material_id:  Option<u64>, // CreateMaterialInfo::engine_id, or None for the default material
 
     // Some engine-id so that we can tell the engine the mesh has uploaded
     pub engine_id:    u64,
@@ -20,6 +36,198 @@ pub struct CreateMeshInfo {
 // Required for sending a *const Vertex
 unsafe impl Send for CreateMeshInfo {}
 
+/// One copy of an instanced mesh. The transform places the copy in the world, and the color multiplies the
+/// material's base color.
+#[derive(Clone, Copy)]
+pub struct MeshInstance {
+    pub transform: Float4x4,
+    pub color:     Float4, // linear RGBA
+}
+
+impl Default for MeshInstance {
+    fn default() -> Self {
+        Self{
+            transform: Float4x4::identity(),
+            color:     Float4::one(),
+        }
+    }
+}
+
+/// A mesh drawn once per instance with a single draw call, for foliage, crowds and other repeated objects.
+pub struct CreateInstancedMeshInfo {
+    pub vertices:     *const Vertex,
+    pub vertex_count: usize,
+
+    pub indices:      *const u32,
+    pub index_count:  usize,
+    pub lods:         Vec<MeshLod>, // index ranges of the LODs, see lod.rs, in any order. Empty draws every index as LOD 0
+
+    pub instances:    Vec<MeshInstance>,
+    pub material_id:  Option<u64>, // CreateMaterialInfo::engine_id, or None for the default material
+
+    // Chosen by the engine, UpdateMeshInstances refers to the mesh with it
+    pub engine_id:    u64,
+}
+
+// Required for sending a *const Vertex
+unsafe impl Send for CreateInstancedMeshInfo {}
+
+/// A mesh deformed by a skeleton in the vertex shader, see the animation module. Each vertex follows up to four
+/// joints of the palette sent with UpdateJointMatrices, and the mesh starts out in its bind pose.
+pub struct CreateSkinnedMeshInfo {
+    pub vertices:     *const Vertex,
+    pub skin:         *const SkinVertex, // one per vertex
+    pub vertex_count: usize,
+
+    pub indices:      *const u32,
+    pub index_count:  usize,
+
+    pub joint_count:  usize, // size of the joint palette
+    pub transform:    Float4x4,
+    pub material_id:  Option<u64>, // CreateMaterialInfo::engine_id, or None for the default material
+
+    // Chosen by the engine, UpdateJointMatrices refers to the mesh with it
+    pub engine_id:    u64,
+}
+
+// Required for sending a *const Vertex
+unsafe impl Send for CreateSkinnedMeshInfo {}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TextureFormat {
+    Rgba8Srgb,  // color data: albedo and emissive textures
+    Rgba8Unorm, // everything else: normal, metallic-roughness and occlusion textures
+}
+
+pub struct CreateTextureInfo {
+    pub pixels:    *const u8, // width * height RGBA texels, 4 bytes each
+    pub width:     u32,
+    pub height:    u32,
+    pub format:    TextureFormat,
+
+    // Chosen by the engine, materials refer to the texture with it
+    pub engine_id: u64,
+}
+
+// Required for sending a *const u8
+unsafe impl Send for CreateTextureInfo {}
+
+/// How a material's alpha is used, following the glTF conventions. The alpha is the base color factor's alpha times
+/// the albedo texture's alpha.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AlphaMode {
+    Opaque, // alpha is ignored
+    Mask,   // cutout: pixels with an alpha below the material's alpha_cutoff are discarded, drawn in the geometry pass
+    Blend,  // drawn in the transparent pass, see transparency.rs
+}
+
+/// A metallic-roughness material, following the glTF conventions. Textures are referred to by their
+/// CreateTextureInfo::engine_id and are multiplied with the factors. A material without a texture uses a neutral
+/// default (white, a flat normal or no emission).
+#[derive(Clone, Copy, Debug)]
+pub struct CreateMaterialInfo {
+    pub base_color_factor:          Float4,
+    pub emissive_factor:            Float3,
+    pub metallic_factor:            f32,
+    pub roughness_factor:           f32,
+    pub normal_scale:               f32,
+    pub occlusion_strength:         f32,
+    pub alpha_mode:                 AlphaMode,
+    pub alpha_cutoff:               f32, // only used by AlphaMode::Mask
+
+    pub albedo_texture:             Option<u64>,
+    pub normal_texture:             Option<u64>, // tangent space
+    pub metallic_roughness_texture: Option<u64>, // roughness in G, metallic in B
+    pub occlusion_texture:          Option<u64>, // occlusion in R
+    pub emissive_texture:           Option<u64>,
+
+    // Chosen by the engine, meshes refer to the material with it
+    pub engine_id:                  u64,
+}
+
+impl Default for CreateMaterialInfo {
+    fn default() -> Self {
+        Self{
+            base_color_factor:          Float4::one(),
+            emissive_factor:            Float3::zero(),
+            metallic_factor:            0.0,
+            roughness_factor:           0.5,
+            normal_scale:               1.0,
+            occlusion_strength:         1.0,
+            alpha_mode:                 AlphaMode::Opaque,
+            alpha_cutoff:               0.5,
+            albedo_texture:             None,
+            normal_texture:             None,
+            metallic_roughness_texture: None,
+            occlusion_texture:          None,
+            emissive_texture:           None,
+            engine_id:                  0,
+        }
+    }
+}
+
+/// The environment's radiance in linear RGB, drawn as the skybox and used for the image-based lighting. +y is up.
+#[derive(Clone)]
+pub enum EnvironmentMapInfo {
+    /// A latitude-longitude map, the left edge faces +x.
+    Equirectangular{ width: u32, height: u32, pixels: Vec<Float4> },
+    /// Six square faces in +x, -x, +y, -y, +z, -z order, oriented like Vulkan cubemap faces. Each face is
+    /// `face_size` texels across, stored row by row from the top left, and the faces are stored back to back.
+    Cube{ face_size: u32, pixels: Vec<Float4> },
+}
+
+impl EnvironmentMapInfo {
+    pub fn from_equirectangular(image: HdrImage) -> EnvironmentMapInfo {
+        return EnvironmentMapInfo::Equirectangular{ width: image.width, height: image.height, pixels: image.pixels };
+    }
+
+    /// Builds a cubemap from six square faces of the same size, in +x, -x, +y, -y, +z, -z order.
+    pub fn from_faces(faces: [HdrImage; 6]) -> Result<EnvironmentMapInfo, ImageError> {
+        let face_size = faces[0].width;
+
+        let mut pixels = Vec::<Float4>::with_capacity((face_size * face_size * 6) as usize);
+        for face in faces {
+            if face.width != face_size || face.height != face_size {
+                return Err(ImageError::InvalidImage(format!("cubemap faces must be square and the same size, expected {}x{} but found {}x{}",
+                    face_size, face_size, face.width, face.height)));
+            }
+
+            pixels.extend(face.pixels);
+        }
+
+        return Ok(EnvironmentMapInfo::Cube{ face_size, pixels });
+    }
+
+    /// False if the pixels don't cover the map, the renderer ignores those maps.
+    pub fn is_valid(&self) -> bool {
+        return match self {
+            EnvironmentMapInfo::Equirectangular{ width, height, pixels } => *width > 0 && *height > 0 && pixels.len() == (*width * *height) as usize,
+            EnvironmentMapInfo::Cube{ face_size, pixels }                => *face_size > 0 && pixels.len() == (*face_size * *face_size * 6) as usize,
+        };
+    }
+}
+
+/// A light that shines in every direction. Its light fades out smoothly and reaches zero at the range.
+#[derive(Clone, Copy, Debug)]
+pub struct PointLightInfo {
+    pub position:  Float3,
+    pub range:     f32,
+    pub color:     Float3, // linear RGB
+    pub intensity: f32,
+}
+
+/// A light that shines in a cone. The light fades out between the inner and outer cone angles, in degrees.
+#[derive(Clone, Copy, Debug)]
+pub struct SpotLightInfo {
+    pub position:         Float3,
+    pub direction:        Float3,
+    pub range:            f32,
+    pub color:            Float3, // linear RGB
+    pub intensity:        f32,
+    pub inner_cone_angle: f32,
+    pub outer_cone_angle: f32,
+}
+
 pub struct ReadyMeshInfo {
     pub engine_id:      u64,
     pub render_mesh_id: u64,
@@ -37,19 +245,63 @@ pub enum RenderCommand{
     // Camera-related commands
     UpdateCamera(CameraStateInfo),
 
+    // Lighting-related commands
+    UpdateShadowSettings(ShadowSettings),
+    UpdatePointLights(Vec<PointLightInfo>), // replaces every point light, usually sent each frame
+    UpdateSpotLights(Vec<SpotLightInfo>),   // replaces every spot light, usually sent each frame
+
     // Mesh-related commands
     CreateMesh(CreateMeshInfo),
+    CreateInstancedMesh(CreateInstancedMeshInfo),
+    UpdateMeshInstances(u64, Vec<MeshInstance>), // replaces the instances of the instanced mesh with the engine id
+    CreateSkinnedMesh(CreateSkinnedMeshInfo),
+    UpdateJointMatrices(u64, Vec<Float4x4>),      // replaces the joint palette of the skinned mesh with the engine id, see animation::pose
     DestroyMesh,
     HideMesh,
     ShowMesh,
 
     // Texture-related commands
-    CreateTexture,
+    CreateTexture(CreateTextureInfo),
     DestroyTexture,
 
     // Material-related commands
-    CreateMaterial,
+    CreateMaterial(CreateMaterialInfo),
     DestroyMaterial,
+    UpdateTransparencySettings(TransparencySettings), // how meshes with AlphaMode::Blend materials are drawn
+
+    // Environment-related commands
+    UpdateEnvironment(EnvironmentMapInfo), // replaces the skybox and the image-based lighting once built, see environment.rs
+    UpdateSkyboxSettings(SkyboxSettings),
+
+    // Post-processing commands
+    UpdatePostProcessSettings(PostProcessSettings),
+    UpdateSsaoSettings(SsaoSettings),
+    UpdateColorGradingLut(ColorGradingLut), // replaces the LUT used when color grading is enabled
+
+    // Particle commands
+    CreateParticleEmitter(ParticleEmitter),  // starts emitting, see particles.rs
+    UpdateParticleEmitter(ParticleEmitter),  // replaces the settings of the emitter with the same engine_id
+    DestroyParticleEmitter(u64),             // engine_id of the emitter, its particles disappear with it
+    UpdateParticleSettings(ParticleSettings),
+
+    // Sprite commands
+    UpdateSprites(Vec<Sprite>),           // replaces the sprites drawn every frame, see sprite.rs
+    UpdateSpriteCamera(Float4x4),         // view-projection of the sprites, usually a Float4x4::get_orthographic_2d_matrix
+    UpdateSpriteSettings(SpriteSettings),
+
+    // Text commands
+    UpdateText(Vec<TextDraw>), // replaces the text drawn every frame, see text.rs
+
+    // Render settings commands
+    UpdateMsaaSampleCount(u32),                     // samples per pixel in the geometry pass (1, 2, 4 or 8), clamped to what the GPU supports
+    UpdateGpuCullingSettings(GpuCullingSettings),   // switches between GPU-driven and CPU geometry draws, see culling.rs
+    UpdateDeferredSettings(DeferredSettings),       // the G-buffer debug view, only used on the deferred path
+    UpdateLodSettings(LodSettings),                 // how the mesh LODs are picked, see lod.rs
+
+    // Debug commands
+    DebugSimulateDeviceLost,       // the next frame fails with VK_ERROR_DEVICE_LOST, exercising device recovery
+    DebugDumpRenderGraph(PathBuf), // writes the next frame's render graph to a Graphviz .dot file
+    UpdateDebugLines(DebugLines),  // replaces the lines drawn this frame, see debug_draw.rs
 
     // Renderer -> Engine Commands
     //
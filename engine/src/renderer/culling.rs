@@ -0,0 +1,628 @@
+use crate::math::{ float4::*, float4x4::* };
+
+use super::graphics::*;
+use super::graphics::gpu_device::Device;
+use super::error::RenderError;
+use super::mesh::{ BoundingBox, GpuMeshBuffers, GpuInstancedMesh, RetainedInstancedMesh };
+use super::command_buffer::MeshInstance;
+use super::material_system::GpuMaterial;
+use super::shader::{ GpuInstance, GpuDrawObject, GpuDrawBatch };
+
+use vendor::vulkan::*;
+
+//
+// GPU-Driven Geometry
+//
+// Every drawable object - each regular mesh, and each instance of an instanced mesh - is packed into a storage
+// buffer every frame, with its world transform and world-space bounding sphere. Objects are grouped into batches,
+// one per mesh, and each batch owns a range of draw command slots the size of its object count.
+//   1. Cull - a compute pass (draw_cull.comp) tests every object's bounding sphere against the camera frustum and
+//             against the Hi-Z pyramid built from the previous frame's depth. Visible objects claim a slot in their
+//             batch with an atomic counter, and write a VkDrawIndexedIndirectCommand that draws the object as a
+//             single instance (firstInstance is the object index).
+//   2. Draw - the geometry pass binds each batch's material and index buffer, then issues one
+//             vkCmdDrawIndexedIndirectCount that reads the batch's visible count from the GPU.
+//   3. Hi-Z - after the geometry pass, hiz_build.comp reduces the scene depth into a mip chain where each texel
+//             holds the farthest depth underneath it. Next frame's cull pass reads it.
+//
+// The occlusion test uses last frame's depth and camera, so an object that was hidden last frame and comes into
+// view can be missing for a frame. The pyramid is dropped whenever it can't be trusted (resize, device loss).
+//
+// Devices without multiDrawIndirect, drawIndirectFirstInstance or drawIndirectCount use the CPU draw loop. It tests
+// each mesh's world-space bounding sphere, then its world-space box, against the planes of the camera frustum and
+// skips the meshes outside of it. Instanced meshes are tested with a box around all of their instances.
+//
+
+// Must match draw_cull.comp and hiz_build.comp
+pub(crate) const DRAW_CULL_GROUP_SIZE: u32 = 64;
+pub(crate) const HIZ_GROUP_SIZE:       u32 = 16;
+
+pub(crate) const DRAW_COMMAND_STRIDE: usize = std::mem::size_of::<VkDrawIndexedIndirectCommand>();
+
+/// Runtime switches for the GPU-driven path. Can be changed with RenderCommand::UpdateGpuCullingSettings.
+#[derive(Clone, Copy, Debug)]
+pub struct GpuCullingSettings {
+    pub enabled:           bool, // cull and draw on the GPU when the device supports it
+    pub occlusion_culling: bool, // test against the Hi-Z pyramid, frustum culling is always on
+}
+
+impl Default for GpuCullingSettings {
+    fn default() -> Self {
+        Self{
+            enabled:           true,
+            occlusion_culling: true,
+        }
+    }
+}
+
+/// Opaque meshes drawn and culled in the last frame. The CPU draw loop counts them as it records. GPU-driven frames
+/// copy each batch's visible count back from the draw cull pass, and count them once the GPU is done with the frame,
+/// so their stats are a few frames late. An instanced mesh is drawn while any of its instances is.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CullingStats {
+    pub meshes_drawn:  u32,
+    pub meshes_culled: u32,
+}
+
+/// Tests a world-space bounding sphere (xyz: center, w: radius) against planes from Float4x4::get_frustum_planes.
+pub(crate) fn is_sphere_in_frustum(planes: &[Float4; 6], sphere: Float4) -> bool {
+    let center = Float4::new(sphere.x, sphere.y, sphere.z, 1.0);
+    return planes.iter().all(|plane| plane.dot(center) >= -sphere.w);
+}
+
+/// Tests a world-space box against planes from Float4x4::get_frustum_planes. Only rejects boxes that are fully
+/// behind a single plane, so a few boxes near the frustum's corners pass without being visible.
+pub(crate) fn is_box_in_frustum(planes: &[Float4; 6], aabb: &BoundingBox) -> bool {
+    return planes.iter().all(|plane| {
+        // The corner furthest along the plane normal
+        let corner = Float4::new(
+            if plane.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+            if plane.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+            if plane.z >= 0.0 { aabb.max.z } else { aabb.min.z },
+            1.0,
+        );
+
+        plane.dot(corner) >= 0.0
+    });
+}
+
+/// Frustum culls the CPU draw list, every mesh followed by every instanced mesh. Returns the indices of the draws
+/// that can be seen, and how many were drawn and culled. Meshes with blended materials are left to the transparent
+/// pass and aren't counted.
+pub(crate) fn cull_draw_list(view_proj: Float4x4, materials: &[GpuMaterial], meshes: &[GpuMeshBuffers], instanced_meshes: &[GpuInstancedMesh]) -> (Vec<usize>, CullingStats) {
+    let planes = view_proj.get_frustum_planes();
+
+    let mut visible = Vec::<usize>::with_capacity(meshes.len() + instanced_meshes.len());
+    let mut stats   = CullingStats::default();
+    let mut opaque  = 0;
+
+    for (index, mesh) in meshes.iter().enumerate() {
+        if materials[mesh.material_index].is_blended() {
+            continue;
+        }
+
+        opaque += 1;
+
+        // The sphere is cheaper to move into world space, the box is tighter
+        let is_visible = is_sphere_in_frustum(&planes, transform_bounding_sphere(mesh.transform, mesh.bounds))
+            && is_box_in_frustum(&planes, &mesh.aabb.transform(mesh.transform));
+
+        if is_visible {
+            visible.push(index);
+        }
+    }
+
+    for (index, instanced) in instanced_meshes.iter().enumerate() {
+        if materials[instanced.mesh.material_index].is_blended() {
+            continue;
+        }
+
+        opaque += 1;
+
+        if instanced.instance_count > 0 && is_box_in_frustum(&planes, &instanced.instance_bounds) {
+            visible.push(meshes.len() + index);
+        }
+    }
+
+    stats.meshes_drawn  = visible.len() as u32;
+    stats.meshes_culled = (opaque - visible.len()) as u32;
+
+    return (visible, stats);
+}
+
+/// Stats of a GPU-driven frame, from the visible object count of each of its batches (one batch per mesh).
+pub(crate) fn count_visible_batches(visible_counts: &[u32]) -> CullingStats {
+    let drawn = visible_counts.iter().filter(|count| **count > 0).count() as u32;
+
+    return CullingStats{
+        meshes_drawn:  drawn,
+        meshes_culled: visible_counts.len() as u32 - drawn,
+    };
+}
+
+/// A batch as the geometry pass sees it: the mesh to bind and the batch's range of draw command slots.
+pub(crate) struct DrawBatch {
+    pub mesh:          GpuMeshBuffers,
+    pub first_command: u32,
+    pub max_commands:  u32,
+}
+
+/// This frame's objects, in the layouts the cull pass and the vertex shader read them in.
+pub(crate) struct DrawList {
+    pub instances:   Vec<GpuInstance>,   // indexed by object, read by mesh.vert through gl_InstanceIndex
+    pub objects:     Vec<GpuDrawObject>, // indexed by object, read by draw_cull.comp
+    pub gpu_batches: Vec<GpuDrawBatch>,
+    pub batches:     Vec<DrawBatch>,
+}
+
+impl DrawList {
+    pub fn get_object_count(&self) -> usize {
+        return self.objects.len();
+    }
+}
+
+/// Moves a local-space bounding sphere into world space. The radius is scaled by the largest axis scale of the
+/// transform, so the sphere stays conservative under non-uniform scales.
+pub(crate) fn transform_bounding_sphere(transform: Float4x4, sphere: Float4) -> Float4 {
+    let center = transform.translate_point(Float4::new(sphere.x, sphere.y, sphere.z, 1.0));
+
+    let axis_x = transform.translate_point(Float4::new(1.0, 0.0, 0.0, 0.0));
+    let axis_y = transform.translate_point(Float4::new(0.0, 1.0, 0.0, 0.0));
+    let axis_z = transform.translate_point(Float4::new(0.0, 0.0, 1.0, 0.0));
+
+    let max_scale_sq = axis_x.dot(axis_x).max(axis_y.dot(axis_y)).max(axis_z.dot(axis_z));
+
+    return Float4::new(center.x, center.y, center.z, sphere.w * max_scale_sq.sqrt());
+}
+
+/// Packs every mesh and every instance into this frame's draw list. Regular meshes are objects with the mesh's
+/// transform, instanced meshes contribute one object per instance. Empty batches, and meshes with blended materials,
+/// are left out.
+///   @assume: `retained_instanced_meshes` is indexed like `instanced_meshes`.
+pub(crate) fn build_draw_list(materials: &[GpuMaterial], meshes: &[GpuMeshBuffers], instanced_meshes: &[GpuInstancedMesh], retained_instanced_meshes: &[RetainedInstancedMesh]) -> DrawList {
+    let default_instance = [MeshInstance::default()];
+
+    let regular   = meshes.iter().map(|mesh| (mesh, &default_instance[..]));
+    let instanced = instanced_meshes.iter().zip(retained_instanced_meshes.iter()).map(|(instanced, retained)| (&instanced.mesh, retained.instances.as_slice()));
+
+    let mut result = DrawList{
+        instances:   Vec::new(),
+        objects:     Vec::new(),
+        gpu_batches: Vec::new(),
+        batches:     Vec::new(),
+    };
+
+    for (mesh, instances) in regular.chain(instanced) {
+        if instances.is_empty() || mesh.get_lod().index_count == 0 || materials[mesh.material_index].is_blended() {
+            continue;
+        }
+
+        let batch_index = result.batches.len() as u32;
+        let first       = result.objects.len() as u32;
+
+        for instance in instances {
+            let transform = mul_rh(mesh.transform, instance.transform);
+
+            result.instances.push(GpuInstance{ transform, color: instance.color });
+            result.objects.push(GpuDrawObject{
+                bounds: transform_bounding_sphere(transform, mesh.bounds),
+                batch:  batch_index,
+                _pad:   [0; 3],
+            });
+        }
+
+        let lod = mesh.get_lod();
+        result.gpu_batches.push(GpuDrawBatch{
+            index_count:   lod.index_count,
+            first_command: first,
+            first_index:   lod.first_index,
+            _pad:          0,
+        });
+
+        result.batches.push(DrawBatch{
+            mesh:          *mesh,
+            first_command: first,
+            max_commands:  instances.len() as u32,
+        });
+    }
+
+    return result;
+}
+
+/// Hierarchical depth pyramid, built from the scene depth at half resolution. Each mip holds the farthest depth
+/// of the 2x2 (or 3x3, along odd edges) texels underneath it in the previous mip.
+pub(crate) struct HiZPyramid {
+    pub image:     AllocatedImage,
+    pub mip_views: Vec<VkImageView>, // one storage view per mip, the image's own view covers every mip
+    pub view_proj: Float4x4,         // camera the pyramid was built with
+    pub is_valid:  bool,             // set once a frame has built the pyramid
+}
+
+impl HiZPyramid {
+    pub fn new(device: &Device, screen_extent: VkExtent3D) -> Result<HiZPyramid, RenderError> {
+        let extent = get_hiz_extent(screen_extent);
+
+        let mut image = device.allocate_image_memory(
+            extent, VK_FORMAT_R32_SFLOAT, VK_IMAGE_USAGE_STORAGE_BIT | VK_IMAGE_USAGE_SAMPLED_BIT,
+            VMA_MEMORY_USAGE_GPU_ONLY, VK_MEMORY_PROPERTY_DEVICE_LOCAL_BIT, true, VK_SAMPLE_COUNT_1_BIT)?;
+
+        let mut mip_views = Vec::<VkImageView>::new();
+        for mip in 0..get_mip_count(extent) {
+            match device.create_image_mip_view(&image, mip) {
+                Ok(view)   => mip_views.push(view),
+                Err(error) => {
+                    for view in mip_views {
+                        device.destroy_image_view(view);
+                    }
+                    device.destroy_image_memory(&mut image);
+                    return Err(error);
+                },
+            }
+        }
+
+        return Ok(HiZPyramid{
+            image,
+            mip_views,
+            view_proj: Float4x4::identity(),
+            is_valid:  false,
+        });
+    }
+
+    pub fn get_mip_count(&self) -> u32 {
+        return self.mip_views.len() as u32;
+    }
+
+    pub fn destroy(&mut self, device: &Device) {
+        for view in self.mip_views.drain(..) {
+            device.destroy_image_view(view);
+        }
+
+        device.destroy_image_memory(&mut self.image);
+        self.is_valid = false;
+    }
+}
+
+/// The pyramid's first mip is half the screen size, rounded down.
+pub(crate) fn get_hiz_extent(screen_extent: VkExtent3D) -> VkExtent3D {
+    return VkExtent3D{ width: (screen_extent.width / 2).max(1), height: (screen_extent.height / 2).max(1), depth: 1 };
+}
+
+/// Size of a mip of the pyramid, each mip is half the previous one rounded down.
+pub(crate) fn get_hiz_mip_extent(hiz_extent: VkExtent3D, mip: u32) -> VkExtent2D {
+    return VkExtent2D{ width: (hiz_extent.width >> mip).max(1), height: (hiz_extent.height >> mip).max(1) };
+}
+
+// Same mip count Device::allocate_image_memory gives a mipmapped image
+fn get_mip_count(extent: VkExtent3D) -> u32 {
+    return ((extent.width.max(extent.height) as f32).log2().floor() as u32) + 1;
+}
+
+/// This frame's draw list on the GPU. Every buffer is transient, and released with the frame's deletion queue.
+pub(crate) struct GpuDrawBuffers {
+    pub list:             DrawList,
+    pub instances:        AllocatedBuffer, // GpuInstance per object
+    pub instance_address: VkDeviceAddress,
+    pub objects:          AllocatedBuffer, // GpuDrawObject per object
+    pub batches:          AllocatedBuffer, // GpuDrawBatch per batch
+    pub commands:         AllocatedBuffer, // VkDrawIndexedIndirectCommand slots, one per object
+    pub counts:           AllocatedBuffer, // visible object count per batch
+    pub counts_readback:  AllocatedBuffer, // host-visible copy of the counts, for the culling stats
+    pub cull_data:        AllocatedBuffer, // DrawCullData
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::float3::Float3;
+    use super::super::command_buffer::AlphaMode;
+    use super::super::lod::{ MeshLod, MAX_MESH_LODS };
+
+    const EPSILON: f32 = 1e-4;
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < EPSILON, "expected {}, got {}", expected, actual);
+    }
+
+    /// A unit cube around the origin, drawn with `index_count` indices at LOD 0.
+    fn make_mesh(transform: Float4x4, material_index: usize, index_count: u32) -> GpuMeshBuffers {
+        let mut lods = [MeshLod::default(); MAX_MESH_LODS];
+        lods[0].index_count = index_count;
+
+        return GpuMeshBuffers{
+            index_buffer:          AllocatedBuffer::default(),
+            vertex_buffer:         AllocatedBuffer::default(),
+            vertex_buffer_address: 0,
+            lods,
+            lod_count:             1,
+            lod:                   0,
+            transform,
+            bounds:                Float4::new(0.0, 0.0, 0.0, 3.0f32.sqrt()),
+            aabb:                  BoundingBox{ min: Float3::new(-1.0, -1.0, -1.0), max: Float3::new(1.0, 1.0, 1.0) },
+            material_index,
+        };
+    }
+
+    fn make_materials() -> Vec<GpuMaterial> {
+        return vec![
+            GpuMaterial{ engine_id: 0, alpha_mode: AlphaMode::Opaque },
+            GpuMaterial{ engine_id: 1, alpha_mode: AlphaMode::Blend },
+        ];
+    }
+
+    fn translation(x: f32, y: f32, z: f32) -> Float4x4 {
+        return Float4x4::get_translate_matrix(Float4::new(x, y, z, 1.0));
+    }
+
+    #[test]
+    fn bounding_spheres_follow_the_transform() {
+        let transform = mul_rh(translation(5.0, 0.0, -2.0), Float4x4::get_scale_matrix(1.0, 4.0, 2.0));
+        let sphere    = transform_bounding_sphere(transform, Float4::new(1.0, 1.0, 0.0, 0.5));
+
+        assert_near(sphere.x, 6.0);
+        assert_near(sphere.y, 4.0);
+        assert_near(sphere.z, -2.0);
+
+        // Scaled by the largest axis, so the sphere still covers the stretched mesh
+        assert_near(sphere.w, 2.0);
+
+        // Rotations keep the radius
+        let rotated = transform_bounding_sphere(Float4x4::get_rotate_y_matrix(37.0), Float4::new(0.0, 0.0, 0.0, 1.5));
+        assert_near(rotated.w, 1.5);
+    }
+
+    #[test]
+    fn draw_lists_have_one_object_per_instance() {
+        let materials = make_materials();
+        let meshes    = vec![
+            make_mesh(translation(1.0, 0.0, 0.0), 0, 36),
+            make_mesh(Float4x4::identity(), 1, 36), // blended, left to the transparent pass
+            make_mesh(Float4x4::identity(), 0, 0),  // nothing to draw
+            make_mesh(translation(0.0, 2.0, 0.0), 0, 12),
+        ];
+
+        let instances = vec![
+            MeshInstance{ transform: translation(0.0, 0.0, -4.0), color: Float4::new(1.0, 0.0, 0.0, 1.0) },
+            MeshInstance{ transform: translation(0.0, 0.0, -8.0), color: Float4::new(0.0, 1.0, 0.0, 1.0) },
+            MeshInstance{ transform: translation(0.0, 0.0, -12.0), color: Float4::new(0.0, 0.0, 1.0, 1.0) },
+        ];
+
+        let instanced = vec![
+            GpuInstancedMesh{
+                mesh:                    make_mesh(Float4x4::identity(), 0, 24),
+                instance_buffer:         AllocatedBuffer::default(),
+                instance_buffer_address: 0,
+                instance_count:          instances.len() as u32,
+                instance_bounds:         BoundingBox::default(),
+                engine_id:               7,
+            },
+        ];
+
+        let retained = vec![
+            RetainedInstancedMesh{
+                vertices:    Vec::new(),
+                indices:     Vec::new(),
+                lods:        Vec::new(),
+                instances:   instances.clone(),
+                material_id: None,
+                engine_id:   7,
+            },
+        ];
+
+        let list = build_draw_list(&materials, &meshes, &instanced, &retained);
+
+        // Two regular meshes and three instances, in three batches
+        assert_eq!(list.get_object_count(), 5);
+        assert_eq!(list.instances.len(), 5);
+        assert_eq!(list.batches.len(), 3);
+        assert_eq!(list.gpu_batches.len(), 3);
+
+        let batch_of: Vec<u32> = list.objects.iter().map(|object| object.batch).collect();
+        assert_eq!(batch_of, [0, 1, 2, 2, 2]);
+
+        let first_commands: Vec<u32> = list.gpu_batches.iter().map(|batch| batch.first_command).collect();
+        let max_commands:   Vec<u32> = list.batches.iter().map(|batch| batch.max_commands).collect();
+        let index_counts:   Vec<u32> = list.gpu_batches.iter().map(|batch| batch.index_count).collect();
+        assert_eq!(first_commands, [0, 1, 2]);
+        assert_eq!(max_commands,   [1, 1, 3]);
+        assert_eq!(index_counts,   [36, 12, 24]);
+
+        // Objects carry their world-space spheres, instances their color
+        assert_near(list.objects[0].bounds.x, 1.0);
+        assert_near(list.objects[1].bounds.y, 2.0);
+        assert_near(list.objects[3].bounds.z, -8.0);
+        assert_near(list.instances[4].color.z, 1.0);
+    }
+
+    #[test]
+    fn hiz_pyramids_halve_down_to_one_texel() {
+        let hiz_extent = get_hiz_extent(VkExtent3D{ width: 1920, height: 1080, depth: 1 });
+        assert_eq!((hiz_extent.width, hiz_extent.height), (960, 540));
+
+        // log2(960) rounded down, plus the first mip
+        assert_eq!(get_mip_count(hiz_extent), 10);
+
+        let last = get_hiz_mip_extent(hiz_extent, 9);
+        assert_eq!((last.width, last.height), (1, 1));
+
+        let odd = get_hiz_mip_extent(hiz_extent, 2);
+        assert_eq!((odd.width, odd.height), (240, 135));
+
+        // A one pixel tall window still gets a pyramid
+        let thin = get_hiz_extent(VkExtent3D{ width: 8, height: 1, depth: 1 });
+        assert_eq!((thin.width, thin.height), (4, 1));
+    }
+
+    /// Looks down -z from the origin, at x in [-10, 10], y in [-5, 5] and z in [-1, -101].
+    fn make_box_frustum() -> [Float4; 6] {
+        return Float4x4::get_orthographic_matrix(-10.0, 10.0, -5.0, 5.0, 1.0, 101.0).get_frustum_planes();
+    }
+
+    #[test]
+    fn frustum_planes_measure_world_space_distances() {
+        let planes = make_box_frustum();
+        let point  = Float4::new(4.0, -2.0, -31.0, 1.0);
+
+        // left, right, bottom, top, near, far
+        let distances: Vec<f32> = planes.iter().map(|plane| plane.dot(point)).collect();
+        let expected = [14.0, 6.0, 3.0, 7.0, 30.0, 70.0];
+        for (distance, expected) in distances.iter().zip(expected) {
+            assert_near(*distance, expected);
+        }
+
+        // The normals are unit length and point into the volume
+        for plane in &planes {
+            assert_near(plane.x * plane.x + plane.y * plane.y + plane.z * plane.z, 1.0);
+        }
+        assert!(planes[0].dot(Float4::new(-11.0, 0.0, -50.0, 1.0)) < 0.0);
+        assert!(planes[5].dot(Float4::new(0.0, 0.0, -102.0, 1.0)) < 0.0);
+    }
+
+    #[test]
+    fn spheres_are_culled_once_fully_outside_a_plane() {
+        let planes = make_box_frustum();
+
+        assert!(is_sphere_in_frustum(&planes, Float4::new(0.0, 0.0, -50.0, 1.0)));
+
+        // Center outside, but the sphere reaches in
+        assert!(is_sphere_in_frustum(&planes, Float4::new(11.0, 0.0, -50.0, 1.5)));
+        assert!(is_sphere_in_frustum(&planes, Float4::new(0.0, 0.0, 0.5, 2.0)));
+
+        assert!(!is_sphere_in_frustum(&planes, Float4::new(11.0, 0.0, -50.0, 0.5)));
+        assert!(!is_sphere_in_frustum(&planes, Float4::new(0.0, -8.0, -50.0, 2.0)));
+        assert!(!is_sphere_in_frustum(&planes, Float4::new(0.0, 0.0, -110.0, 5.0)));
+        assert!(!is_sphere_in_frustum(&planes, Float4::new(0.0, 0.0, 10.0, 5.0)));
+    }
+
+    #[test]
+    fn boxes_are_culled_once_fully_outside_a_plane() {
+        let planes = make_box_frustum();
+        let make_box = |min: (f32, f32, f32), max: (f32, f32, f32)| BoundingBox{
+            min: Float3::new(min.0, min.1, min.2),
+            max: Float3::new(max.0, max.1, max.2),
+        };
+
+        assert!(is_box_in_frustum(&planes, &make_box((-1.0, -1.0, -51.0), (1.0, 1.0, -49.0))));
+
+        // Straddling a plane, or surrounding the whole frustum
+        assert!(is_box_in_frustum(&planes, &make_box((9.0, -1.0, -51.0), (12.0, 1.0, -49.0))));
+        assert!(is_box_in_frustum(&planes, &make_box((-100.0, -100.0, -200.0), (100.0, 100.0, 100.0))));
+
+        assert!(!is_box_in_frustum(&planes, &make_box((10.5, -1.0, -51.0), (12.0, 1.0, -49.0))));
+        assert!(!is_box_in_frustum(&planes, &make_box((-1.0, 5.5, -51.0), (1.0, 6.0, -49.0))));
+        assert!(!is_box_in_frustum(&planes, &make_box((-1.0, -1.0, 0.0), (1.0, 1.0, 4.0))));
+    }
+
+    #[test]
+    fn transformed_boxes_contain_the_rotated_box() {
+        let aabb = BoundingBox{ min: Float3::new(-1.0, -2.0, -3.0), max: Float3::new(1.0, 2.0, 3.0) };
+
+        // A quarter turn around y swaps the x and z extents
+        let turned = aabb.transform(mul_rh(translation(10.0, 0.0, 0.0), Float4x4::get_rotate_y_matrix(90.0)));
+        assert_near(turned.min.x, 7.0);
+        assert_near(turned.max.x, 13.0);
+        assert_near(turned.min.y, -2.0);
+        assert_near(turned.max.z, 1.0);
+
+        // An eighth turn grows the box to hold the rotated corners
+        let diagonal = aabb.transform(Float4x4::get_rotate_y_matrix(45.0));
+        let extent   = (1.0 + 3.0) * std::f32::consts::FRAC_1_SQRT_2;
+        assert_near(diagonal.max.x, extent);
+        assert_near(diagonal.max.z, extent);
+        assert_near(diagonal.max.y, 2.0);
+    }
+
+    #[test]
+    fn draw_lists_are_culled_against_the_camera() {
+        let view_proj = Float4x4::get_orthographic_matrix(-10.0, 10.0, -5.0, 5.0, 1.0, 101.0);
+        let materials = make_materials();
+
+        let meshes = vec![
+            make_mesh(translation(0.0, 0.0, -20.0), 0, 36),
+            make_mesh(translation(30.0, 0.0, -20.0), 0, 36), // off to the right
+            make_mesh(translation(30.0, 0.0, -20.0), 1, 36), // blended, not counted
+            make_mesh(mul_rh(translation(11.5, 0.0, -20.0), Float4x4::get_uniform_scale_matrix(2.0)), 0, 36), // scaled into view
+        ];
+
+        let make_instanced = |instance_count: u32, bounds: BoundingBox| GpuInstancedMesh{
+            mesh:                    make_mesh(Float4x4::identity(), 0, 36),
+            instance_buffer:         AllocatedBuffer::default(),
+            instance_buffer_address: 0,
+            instance_count,
+            instance_bounds:         bounds,
+            engine_id:               0,
+        };
+
+        let instanced = vec![
+            make_instanced(4, BoundingBox{ min: Float3::new(-2.0, -2.0, -40.0), max: Float3::new(2.0, 2.0, -30.0) }),
+            make_instanced(4, BoundingBox{ min: Float3::new(-2.0, -2.0, 10.0),  max: Float3::new(2.0, 2.0, 20.0) }), // behind the camera
+            make_instanced(0, BoundingBox{ min: Float3::new(-2.0, -2.0, -40.0), max: Float3::new(2.0, 2.0, -30.0) }), // no instances
+        ];
+
+        let (visible, stats) = cull_draw_list(view_proj, &materials, &meshes, &instanced);
+
+        // Instanced draws come after the meshes in the draw list
+        assert_eq!(visible, [0, 3, meshes.len()]);
+        assert_eq!(stats, CullingStats{ meshes_drawn: 3, meshes_culled: 3 });
+    }
+
+    #[test]
+    fn gpu_driven_stats_count_the_batches_with_visible_objects() {
+        let view_proj = Float4x4::get_orthographic_matrix(-10.0, 10.0, -5.0, 5.0, 1.0, 101.0);
+        let planes    = view_proj.get_frustum_planes();
+        let materials = make_materials();
+
+        let meshes = vec![
+            make_mesh(translation(0.0, 0.0, -20.0), 0, 36),
+            make_mesh(translation(30.0, 0.0, -20.0), 0, 36), // off to the right
+            make_mesh(translation(30.0, 0.0, -20.0), 1, 36), // blended, not in the draw list
+        ];
+
+        let instanced = vec![
+            GpuInstancedMesh{
+                mesh:                    make_mesh(Float4x4::identity(), 0, 36),
+                instance_buffer:         AllocatedBuffer::default(),
+                instance_buffer_address: 0,
+                instance_count:          2,
+                instance_bounds:         BoundingBox::default(),
+                engine_id:               0,
+            },
+        ];
+
+        let make_retained = |instances: Vec<MeshInstance>| RetainedInstancedMesh{
+            vertices:    Vec::new(),
+            indices:     Vec::new(),
+            lods:        Vec::new(),
+            instances,
+            material_id: None,
+            engine_id:   0,
+        };
+
+        // One instance in view is enough to draw the instanced mesh
+        let partly_visible = vec![make_retained(vec![
+            MeshInstance{ transform: translation(0.0, 0.0, -30.0), color: Float4::one() },
+            MeshInstance{ transform: translation(0.0, 0.0, 30.0),  color: Float4::one() }, // behind the camera
+        ])];
+
+        let hidden = vec![make_retained(vec![
+            MeshInstance{ transform: translation(-30.0, 0.0, -30.0), color: Float4::one() },
+            MeshInstance{ transform: translation(0.0, 0.0, 30.0),    color: Float4::one() },
+        ])];
+
+        for (retained, expected) in [(partly_visible, CullingStats{ meshes_drawn: 2, meshes_culled: 1 }), (hidden, CullingStats{ meshes_drawn: 1, meshes_culled: 2 })] {
+            let list = build_draw_list(&materials, &meshes, &instanced, &retained);
+
+            // What the draw cull pass writes: every object in the frustum claims a slot in its batch
+            let mut visible_counts = vec![0u32; list.batches.len()];
+            for object in &list.objects {
+                if is_sphere_in_frustum(&planes, object.bounds) {
+                    visible_counts[object.batch as usize] += 1;
+                }
+            }
+
+            assert_eq!(count_visible_batches(&visible_counts), expected);
+        }
+
+        // Nothing to draw, nothing culled
+        assert_eq!(count_visible_batches(&[]), CullingStats::default());
+    }
+}
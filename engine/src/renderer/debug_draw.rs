@@ -0,0 +1,295 @@
+use crate::math::{ float3::*, float4::*, float4x4::* };
+
+use std::time::{ Duration, Instant };
+
+//
+// Debug Drawing
+//
+// Immediate-mode lines for gameplay and tooling. The game calls DebugDraw from Game::on_update or Game::on_render,
+// and the Engine sends the frame's lines to the renderer with RenderCommand::UpdateDebugLines before the frame is
+// rendered. The renderer copies them into a transient vertex buffer and draws them as a line list on top of the
+// tonemapped image, so the colors are the colors on screen. Lines are either depth tested against the scene or
+// drawn over it.
+//
+// A line without a duration is drawn for a single frame. A line with a duration is drawn every frame until it
+// expires. Durations too long to represent as an Instant never expire, those lines are drawn until clear().
+//
+
+/// Lines past this count are dropped by the renderer
+pub const MAX_DEBUG_LINES: usize = 65536;
+
+// Segments in a circle of a debug sphere
+const SPHERE_SEGMENTS: usize = 32;
+
+/// A line end as the renderer draws it. Matches debug_line.vert
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct DebugVertex {
+    pub position: Float4, // w: unused
+    pub color:    Float4, // display color, not affected by exposure or tonemapping
+}
+
+/// A frame's debug lines, two vertices per line.
+#[derive(Clone, Default)]
+pub struct DebugLines {
+    pub depth_tested: Vec<DebugVertex>, // hidden behind the scene's geometry
+    pub overlay:      Vec<DebugVertex>, // drawn over everything
+}
+
+impl DebugLines {
+    pub fn is_empty(&self) -> bool {
+        return self.depth_tested.is_empty() && self.overlay.is_empty();
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum LineLifetime {
+    SingleFrame,
+    Until(Instant),
+    Forever,
+}
+
+struct DebugLine {
+    start:      Float3,
+    end:        Float3,
+    color:      Float4,
+    depth_test: bool,
+    lifetime:   LineLifetime,
+}
+
+pub struct DebugDraw {
+    lines:      Vec<DebugLine>,
+    depth_test: bool,
+}
+
+impl Default for DebugDraw {
+    fn default() -> Self {
+        Self{
+            lines:      Vec::new(),
+            depth_test: true,
+        }
+    }
+}
+
+impl DebugDraw {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// Whether the lines added after this call are hidden behind the scene. On by default.
+    pub fn set_depth_test(&mut self, enabled: bool) {
+        self.depth_test = enabled;
+    }
+
+    /// A line from `start` to `end`. `duration` is in seconds, None draws the line for the next frame only.
+    pub fn line(&mut self, start: Float3, end: Float3, color: Float4, duration: Option<f32>) {
+        let lifetime = match duration {
+            Some(seconds) => {
+                let expires_at = Duration::try_from_secs_f32(seconds.max(0.0))
+                    .ok()
+                    .and_then(|duration| Instant::now().checked_add(duration));
+
+                expires_at.map_or(LineLifetime::Forever, LineLifetime::Until)
+            },
+            None => LineLifetime::SingleFrame,
+        };
+
+        self.lines.push(DebugLine{ start, end, color, depth_test: self.depth_test, lifetime });
+    }
+
+    /// A line from `start` to `end` with an arrow head at `end`.
+    pub fn arrow(&mut self, start: Float3, end: Float3, color: Float4, duration: Option<f32>) {
+        self.line(start, end, color, duration);
+
+        let direction = end - start;
+        let length    = direction.length();
+        if length < 1e-6 {
+            return;
+        }
+
+        let forward     = direction / length;
+        let (side, up)  = get_perpendicular_axes(forward);
+        let head_length = length * 0.2;
+        let head_width  = head_length * 0.4;
+        let head_base   = end - forward * head_length;
+
+        self.line(end, head_base + side * head_width, color, duration);
+        self.line(end, head_base - side * head_width, color, duration);
+        self.line(end, head_base + up   * head_width, color, duration);
+        self.line(end, head_base - up   * head_width, color, duration);
+    }
+
+    /// The edges of the axis-aligned box from `min` to `max`.
+    pub fn wire_box(&mut self, min: Float3, max: Float3, color: Float4, duration: Option<f32>) {
+        let corners = [
+            Float3::new(min.x, min.y, min.z), Float3::new(max.x, min.y, min.z),
+            Float3::new(max.x, max.y, min.z), Float3::new(min.x, max.y, min.z),
+            Float3::new(min.x, min.y, max.z), Float3::new(max.x, min.y, max.z),
+            Float3::new(max.x, max.y, max.z), Float3::new(min.x, max.y, max.z),
+        ];
+
+        self.box_edges(&corners, color, duration);
+    }
+
+    /// A sphere drawn as three circles, one around each axis.
+    pub fn sphere(&mut self, center: Float3, radius: f32, color: Float4, duration: Option<f32>) {
+        let axes = [
+            (Float3::new(1.0, 0.0, 0.0), Float3::new(0.0, 1.0, 0.0)),
+            (Float3::new(0.0, 1.0, 0.0), Float3::new(0.0, 0.0, 1.0)),
+            (Float3::new(0.0, 0.0, 1.0), Float3::new(1.0, 0.0, 0.0)),
+        ];
+
+        for (axis_u, axis_v) in axes {
+            let point_at = |segment: usize| -> Float3 {
+                let angle = (segment as f32 / SPHERE_SEGMENTS as f32) * std::f32::consts::TAU;
+                return center + axis_u * (angle.cos() * radius) + axis_v * (angle.sin() * radius);
+            };
+
+            for segment in 0..SPHERE_SEGMENTS {
+                self.line(point_at(segment), point_at(segment + 1), color, duration);
+            }
+        }
+    }
+
+    /// The edges of the volume a view-projection matrix sees, for example a camera or a shadow cascade.
+    ///   @assume: the Vulkan clip volume, 0 <= z <= w.
+    pub fn frustum(&mut self, view_proj: Float4x4, color: Float4, duration: Option<f32>) {
+        let inverse = view_proj.invert();
+
+        let mut corners = [Float3::zero(); 8];
+        for (index, corner) in corners.iter_mut().enumerate() {
+            // Same corner order as wire_box, x and y from -1 to 1 and z from 0 to 1
+            let x = if index % 4 == 1 || index % 4 == 2 { 1.0 } else { -1.0 };
+            let y = if index % 4 >= 2 { 1.0 } else { -1.0 };
+            let z = if index >= 4 { 1.0 } else { 0.0 };
+
+            let point = inverse.translate_point(Float4::new(x, y, z, 1.0));
+            *corner = Float3::new(point.x / point.w, point.y / point.w, point.z / point.w);
+        }
+
+        self.box_edges(&corners, color, duration);
+    }
+
+    /// A grid on the XZ plane around `center`, `cell_count` cells of `cell_size` along each side.
+    pub fn grid(&mut self, center: Float3, cell_size: f32, cell_count: u32, color: Float4, duration: Option<f32>) {
+        let half_size = cell_size * cell_count as f32 * 0.5;
+
+        for line in 0..=cell_count {
+            let offset = -half_size + line as f32 * cell_size;
+
+            self.line(center + Float3::new(offset, 0.0, -half_size), center + Float3::new(offset, 0.0, half_size), color, duration);
+            self.line(center + Float3::new(-half_size, 0.0, offset), center + Float3::new(half_size, 0.0, offset), color, duration);
+        }
+    }
+
+    /// The X (red), Y (green) and Z (blue) axes of a transform, `size` units long.
+    pub fn axes(&mut self, transform: Float4x4, size: f32, duration: Option<f32>) {
+        let origin = transform.translate_point(Float4::new(0.0, 0.0, 0.0, 1.0));
+        let origin = Float3::new(origin.x, origin.y, origin.z);
+
+        let axes = [
+            (Float4::new(size, 0.0, 0.0, 0.0), Float4::new(1.0, 0.0, 0.0, 1.0)),
+            (Float4::new(0.0, size, 0.0, 0.0), Float4::new(0.0, 1.0, 0.0, 1.0)),
+            (Float4::new(0.0, 0.0, size, 0.0), Float4::new(0.0, 0.0, 1.0, 1.0)),
+        ];
+
+        for (axis, color) in axes {
+            let axis = transform.translate_point(axis);
+            self.arrow(origin, origin + Float3::new(axis.x, axis.y, axis.z), color, duration);
+        }
+    }
+
+    /// Removes every line, including the ones that haven't expired.
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+
+    /// Collects the lines to draw this frame, and drops the single-frame and expired lines.
+    pub fn flush(&mut self, now: Instant) -> DebugLines {
+        let mut result = DebugLines::default();
+
+        self.lines.retain(|line| match line.lifetime {
+            LineLifetime::Until(expires_at) => now < expires_at,
+            _                               => true,
+        });
+
+        for line in &self.lines {
+            let vertices = if line.depth_test { &mut result.depth_tested } else { &mut result.overlay };
+            vertices.push(DebugVertex{ position: Float4::new(line.start.x, line.start.y, line.start.z, 1.0), color: line.color });
+            vertices.push(DebugVertex{ position: Float4::new(line.end.x,   line.end.y,   line.end.z,   1.0), color: line.color });
+        }
+
+        self.lines.retain(|line| line.lifetime != LineLifetime::SingleFrame);
+
+        return result;
+    }
+
+    // The 12 edges of a box, from corners 0-3 on one face and 4-7 on the opposite face, in the same winding.
+    fn box_edges(&mut self, corners: &[Float3; 8], color: Float4, duration: Option<f32>) {
+        for index in 0..4 {
+            let next = (index + 1) % 4;
+
+            self.line(corners[index],     corners[next],     color, duration);
+            self.line(corners[index + 4], corners[next + 4], color, duration);
+            self.line(corners[index],     corners[index + 4], color, duration);
+        }
+    }
+}
+
+// Two unit axes perpendicular to `forward` and to each other
+fn get_perpendicular_axes(forward: Float3) -> (Float3, Float3) {
+    let reference = if forward.y.abs() < 0.99 { Float3::new(0.0, 1.0, 0.0) } else { Float3::new(1.0, 0.0, 0.0) };
+
+    let side = forward.cross(reference).unit();
+    let up   = side.cross(forward);
+
+    return (side, up);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_line_count(lines: &DebugLines) -> usize {
+        return (lines.depth_tested.len() + lines.overlay.len()) / 2;
+    }
+
+    #[test]
+    fn lines_last_for_their_duration() {
+        let mut debug_draw = DebugDraw::new();
+        let color          = Float4::one();
+
+        debug_draw.line(Float3::zero(), Float3::new(1.0, 0.0, 0.0), color, None);
+        debug_draw.line(Float3::zero(), Float3::new(0.0, 1.0, 0.0), color, Some(10.0));
+        debug_draw.set_depth_test(false);
+        debug_draw.line(Float3::zero(), Float3::new(0.0, 0.0, 1.0), color, Some(-1.0));
+
+        let now   = Instant::now();
+        let first = debug_draw.flush(now);
+        assert_eq!(first.depth_tested.len(), 4);
+        assert_eq!(first.overlay.len(), 0);
+
+        // The single-frame line is gone, the timed one stays until it expires
+        assert_eq!(get_line_count(&debug_draw.flush(now)), 1);
+        assert_eq!(get_line_count(&debug_draw.flush(now + Duration::from_secs(11))), 0);
+    }
+
+    #[test]
+    fn unrepresentable_durations_never_expire() {
+        let mut debug_draw = DebugDraw::new();
+        let color          = Float4::one();
+
+        debug_draw.line(Float3::zero(), Float3::new(1.0, 0.0, 0.0), color, Some(f32::INFINITY));
+        debug_draw.line(Float3::zero(), Float3::new(0.0, 1.0, 0.0), color, Some(f32::MAX));
+        debug_draw.line(Float3::zero(), Float3::new(0.0, 0.0, 1.0), color, Some(f32::NAN));
+
+        // NaN is no time at all, like a negative duration, and has expired by the time the lines are flushed
+        assert_eq!(get_line_count(&debug_draw.flush(Instant::now())), 2);
+
+        let far_future = Instant::now() + Duration::from_secs(100 * 365 * 24 * 60 * 60);
+        assert_eq!(get_line_count(&debug_draw.flush(far_future)), 2);
+
+        debug_draw.clear();
+        assert!(debug_draw.flush(far_future).is_empty());
+    }
+}
@@ -0,0 +1,87 @@
+use vendor::vulkan::*;
+
+//
+// Deferred Shading
+//
+// The renderer draws the opaque geometry down one of two paths, picked at startup with RendererCreateInfo::render_path:
+//   1. Forward  - the geometry pass shades every fragment as it is drawn, with MSAA. Simple, but every overdrawn
+//                 fragment pays for all of the lights of its cluster.
+//   2. Deferred - the geometry pass only writes the surface of every pixel to the G-buffer (mesh.frag built with
+//                 GBUFFER, see gbuffer.glsl for the layout), and a compute pass (deferred_lighting.comp) lights each
+//                 pixel once. The lighting is the same as the forward path's, from the same clustered lights, shadow
+//                 cascades and IBL maps (lighting.glsl).
+//
+// The deferred geometry pass is always drawn at one sample per pixel, the G-buffer can't be averaged the way a lit
+// color can, so RenderCommand::UpdateMsaaSampleCount only affects the forward path. SSAO runs between the two passes
+// and the lighting pass reads it directly, rather than taking the occluded ambient back out of the scene afterwards.
+// The later passes don't change: the skybox goes underneath the coverage the geometry pass wrote to the scene, and
+// meshes with blended materials are still drawn forward in the transparent pass.
+//
+// The G-buffer can be shown instead of the lit scene with RenderCommand::UpdateDeferredSettings.
+//
+
+// Must match deferred_lighting.comp
+pub(crate) const DEFERRED_LIGHTING_GROUP_SIZE: u32 = 16;
+
+// Must match gbuffer.glsl
+pub(crate) const GBUFFER_ALBEDO_FORMAT:   VkFormat = VK_FORMAT_R8G8B8A8_UNORM;      // rgb: albedo, a: occlusion
+pub(crate) const GBUFFER_NORMAL_FORMAT:   VkFormat = VK_FORMAT_R16G16B16A16_SFLOAT; // octahedron encoded shading and geometric normals
+pub(crate) const GBUFFER_MATERIAL_FORMAT: VkFormat = VK_FORMAT_R8G8B8A8_UNORM;      // r: metallic, g: roughness
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RenderPath {
+    Forward,  // lit while drawing the geometry
+    Deferred, // the geometry writes a G-buffer, lit by a compute pass
+}
+
+/// What the deferred lighting pass shows. Anything but Lit replaces the scene with a G-buffer channel.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GBufferDebugView {
+    Lit,
+    Albedo,
+    Normal,   // world-space shading normal, mapped to [0, 1]
+    Material, // r: metallic, g: roughness, b: the material's occlusion
+    Depth,    // view depth on a logarithmic scale, white is near
+}
+
+impl GBufferDebugView {
+    /// The view after this one, cycles back to Lit.
+    pub fn next(self) -> GBufferDebugView {
+        return match self {
+            GBufferDebugView::Lit      => GBufferDebugView::Albedo,
+            GBufferDebugView::Albedo   => GBufferDebugView::Normal,
+            GBufferDebugView::Normal   => GBufferDebugView::Material,
+            GBufferDebugView::Material => GBufferDebugView::Depth,
+            GBufferDebugView::Depth    => GBufferDebugView::Lit,
+        };
+    }
+
+    // Matches the DEBUG_VIEW_* defines in deferred_lighting.comp
+    pub(crate) fn get_shader_value(self) -> u32 {
+        return match self {
+            GBufferDebugView::Lit      => 0,
+            GBufferDebugView::Albedo   => 1,
+            GBufferDebugView::Normal   => 2,
+            GBufferDebugView::Material => 3,
+            GBufferDebugView::Depth    => 4,
+        };
+    }
+}
+
+/// deferred_lighting.comp's DEBUG_VIEW_AO, shown for the SSAO debug view on the deferred path.
+pub(crate) const SSAO_DEBUG_VIEW: u32 = 5;
+
+/// Settings of the deferred path, ignored on the forward path. Can be changed at runtime with
+/// RenderCommand::UpdateDeferredSettings.
+#[derive(Clone, Copy, Debug)]
+pub struct DeferredSettings {
+    pub debug_view: GBufferDebugView,
+}
+
+impl Default for DeferredSettings {
+    fn default() -> Self {
+        Self{
+            debug_view: GBufferDebugView::Lit,
+        }
+    }
+}
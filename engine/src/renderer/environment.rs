@@ -0,0 +1,640 @@
+use std::f32::consts::PI;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::thread;
+
+use crate::math::{ float3::*, float4::* };
+
+use super::command_buffer::EnvironmentMapInfo;
+use super::graphics::{ gpu_device::Device, AllocatedImage };
+
+//
+// Environment Maps
+//
+// The environment is kept as three cubemaps, built on the CPU by the EnvironmentLoader's thread when the engine sends
+// a new environment, so the frames keep going while it is prefiltered. The maps are swapped in once they are built:
+//   1. Skybox     - the environment's radiance, drawn behind the geometry by the background pass. Its mips are box
+//                   filtered, so the skybox can be blurred (SkyboxSettings::blur).
+//   2. Irradiance - the diffuse irradiance / PI for every normal. The radiance is projected onto 2nd order
+//                   spherical harmonics, which represent the irradiance of any environment to within a few percent
+//                   (Ramamoorthi and Hanrahan, "An Efficient Representation for Irradiance Environment Maps"), and
+//                   the harmonics are evaluated into a small cubemap.
+//   3. Specular   - the radiance prefiltered with the GGX lobe, one roughness per mip from 0 to 1 (Karis, "Real
+//                   Shading in Unreal Engine 4"). The lit shader picks the mip from the surface's roughness, looks it
+//                   up along the reflection vector and scales it by the split-sum environment BRDF.
+//
+// Equirectangular maps are resampled into a cubemap first. The faces follow the Vulkan cubemap conventions, so a
+// world-space direction can be looked up in any of the maps as-is. Until the first environment is built, the scene is
+// lit by a placeholder, a cubemap of a single texel per face (make_placeholder_environment), which is built in place.
+//
+
+// Workgroup size of skybox.comp, in both dimensions
+pub(crate) const SKYBOX_GROUP_SIZE: u32 = 16;
+
+const SH_COEFFICIENT_COUNT: usize = 9;
+
+// Larger environments are downsampled to this face size for the skybox
+const SKYBOX_MAX_FACE_SIZE: u32 = 512;
+
+// The specular map is prefiltered from the first skybox mip no larger than this
+const SPECULAR_FACE_SIZE: u32 = 128;
+const SPECULAR_MIP_COUNT: usize = 6;
+
+// GGX samples per texel of the specular map. Each sample reads a mip of the skybox matching the sample's footprint
+// (filtered importance sampling), so a few samples are enough to avoid fireflies.
+const SPECULAR_SAMPLE_COUNT: u32 = 64;
+
+const IRRADIANCE_FACE_SIZE: u32 = 32;
+
+// Size of the procedural sky used until the engine sends an environment map
+const DEFAULT_ENVIRONMENT_WIDTH:  u32 = 256;
+const DEFAULT_ENVIRONMENT_HEIGHT: u32 = 128;
+
+/// How the environment is drawn behind the scene. Can be changed at runtime with RenderCommand::UpdateSkyboxSettings.
+#[derive(Clone, Copy, Debug)]
+pub struct SkyboxSettings {
+    pub intensity: f32, // scales the skybox's radiance, 0 leaves the background black. The lighting isn't affected.
+    pub blur:      f32, // 0 = sharp, 1 = the skybox's smallest mip
+}
+
+impl Default for SkyboxSettings {
+    fn default() -> Self {
+        Self{
+            intensity: 1.0,
+            blur:      0.0,
+        }
+    }
+}
+
+impl SkyboxSettings {
+    /// Clamps the settings to values the renderer can use.
+    pub fn sanitize(&self) -> SkyboxSettings {
+        return SkyboxSettings{
+            intensity: self.intensity.max(0.0),
+            blur:      self.blur.clamp(0.0, 1.0),
+        };
+    }
+}
+
+/// Six square faces in +x, -x, +y, -y, +z, -z order, each `size` texels across and stored row by row.
+#[derive(Clone, Default)]
+pub(crate) struct CubeMap {
+    pub size:   u32,
+    pub texels: Vec<Float3>,
+}
+
+impl CubeMap {
+    /// Fills every texel with `f` of the direction through its center.
+    pub fn from_fn(size: u32, f: impl Fn(Float3) -> Float3) -> CubeMap {
+        let mut texels = Vec::<Float3>::with_capacity((size * size * 6) as usize);
+        for face in 0..6 {
+            for y in 0..size {
+                for x in 0..size {
+                    texels.push(f(get_cube_direction(face, x, y, size)));
+                }
+            }
+        }
+
+        return CubeMap{ size, texels };
+    }
+
+    /// Bilinear lookup along a direction. The filter is clamped to the edges of the face the direction falls in.
+    pub fn sample(&self, dir: Float3) -> Float3 {
+        let (face, s, t) = get_cube_face_coords(dir);
+
+        let size = self.size as f32;
+        let u    = (s * size - 0.5).clamp(0.0, size - 1.0);
+        let v    = (t * size - 0.5).clamp(0.0, size - 1.0);
+
+        let x0 = u.floor() as u32;
+        let y0 = v.floor() as u32;
+        let x1 = (x0 + 1).min(self.size - 1);
+        let y1 = (y0 + 1).min(self.size - 1);
+        let fx = u - x0 as f32;
+        let fy = v - y0 as f32;
+
+        let texel = |x: u32, y: u32| -> Float3 { self.texels[((face * self.size + y) * self.size + x) as usize] };
+
+        let top    = texel(x0, y0) * (1.0 - fx) + texel(x1, y0) * fx;
+        let bottom = texel(x0, y1) * (1.0 - fx) + texel(x1, y1) * fx;
+        return top * (1.0 - fy) + bottom * fy;
+    }
+
+    /// Half the size, every texel is the average of the 2x2 texels it covers.
+    pub fn downsample(&self) -> CubeMap {
+        let size = (self.size / 2).max(1);
+
+        let mut texels = Vec::<Float3>::with_capacity((size * size * 6) as usize);
+        for face in 0..6 {
+            for y in 0..size {
+                for x in 0..size {
+                    let mut sum = Float3::zero();
+                    for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                        let sx = (x * 2 + dx).min(self.size - 1);
+                        let sy = (y * 2 + dy).min(self.size - 1);
+                        sum = sum + self.texels[((face * self.size + sy) * self.size + sx) as usize];
+                    }
+
+                    texels.push(sum * 0.25);
+                }
+            }
+        }
+
+        return CubeMap{ size, texels };
+    }
+}
+
+/// The CPU side of the environment maps, each map as a list of mips
+#[derive(Clone, Default)]
+pub(crate) struct EnvironmentCubes {
+    pub skybox:     Vec<CubeMap>,
+    pub irradiance: Vec<CubeMap>, // a single mip
+    pub specular:   Vec<CubeMap>, // mip m is prefiltered for a roughness of m / (mip count - 1)
+}
+
+/// The environment maps on the GPU, as RGBA16F cubemaps
+#[derive(Default)]
+pub(crate) struct GpuEnvironment {
+    pub skybox:     AllocatedImage,
+    pub irradiance: AllocatedImage,
+    pub specular:   AllocatedImage,
+}
+
+impl GpuEnvironment {
+    pub fn destroy(&mut self, device: &Device) {
+        device.destroy_image_memory(&mut self.skybox);
+        device.destroy_image_memory(&mut self.irradiance);
+        device.destroy_image_memory(&mut self.specular);
+    }
+}
+
+/// Builds environments on a thread of its own. Only the latest request matters: requests waiting behind the one being
+/// built are skipped for the newest, and the result of a request that was replaced while it was built is dropped.
+pub(crate) struct EnvironmentLoader {
+    requests:       Option<mpsc::Sender<(u64, EnvironmentMapInfo)>>, // taken on drop, which tells the thread to exit
+    results:        mpsc::Receiver<(u64, Option<EnvironmentCubes>)>, // None if building the environment panicked
+    latest_request: u64,
+    handle:         Option<thread::JoinHandle<()>>,
+}
+
+impl EnvironmentLoader {
+    pub fn new() -> std::io::Result<EnvironmentLoader> {
+        let (request_sender, request_receiver) = mpsc::channel::<(u64, EnvironmentMapInfo)>();
+        let (result_sender, result_receiver)   = mpsc::channel::<(u64, Option<EnvironmentCubes>)>();
+
+        let handle = thread::Builder::new()
+            .name(String::from("Environment Loader"))
+            .spawn(move || {
+                while let Ok(mut request) = request_receiver.recv() {
+                    while let Ok(newer) = request_receiver.try_recv() {
+                        request = newer;
+                    }
+
+                    let (id, environment) = request;
+                    let cubes = panic::catch_unwind(AssertUnwindSafe(|| build_environment(&environment))).ok();
+
+                    if result_sender.send((id, cubes)).is_err() {
+                        break;
+                    }
+                }
+            })?;
+
+        return Ok(EnvironmentLoader{
+            requests:       Some(request_sender),
+            results:        result_receiver,
+            latest_request: 0,
+            handle:         Some(handle),
+        });
+    }
+
+    /// Starts building an environment, replacing any request that hasn't finished.
+    /// @assume: the environment is valid, see EnvironmentMapInfo::is_valid
+    pub fn request(&mut self, environment: EnvironmentMapInfo) {
+        self.latest_request += 1;
+
+        if let Some(sender) = &self.requests {
+            let _ = sender.send((self.latest_request, environment));
+        }
+    }
+
+    /// The maps of the latest request, once they are built. None while they are being built, or once they were taken.
+    pub fn take_built(&self) -> Option<EnvironmentCubes> {
+        let mut built = None;
+        while let Ok((id, cubes)) = self.results.try_recv() {
+            if id != self.latest_request {
+                continue;
+            }
+
+            match cubes {
+                Some(cubes) => built = Some(cubes),
+                None        => println!("[WARN] :: RenderSystem :: Building the environment maps panicked, keeping the previous environment."),
+            }
+        }
+
+        return built;
+    }
+}
+
+impl Drop for EnvironmentLoader {
+    fn drop(&mut self) {
+        // Waits for the environment being built, if there is one
+        self.requests = None;
+
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Builds the skybox, irradiance and specular maps of an environment.
+/// @assume: the environment is valid, see EnvironmentMapInfo::is_valid
+pub(crate) fn build_environment(environment: &EnvironmentMapInfo) -> EnvironmentCubes {
+    let mut base = match environment {
+        EnvironmentMapInfo::Equirectangular{ width, height, pixels } => {
+            // A quarter of the width keeps the texel density of the equator
+            let size = (*width / 4).clamp(1, SKYBOX_MAX_FACE_SIZE);
+            CubeMap::from_fn(size, |dir| sample_equirect(*width, *height, pixels, dir))
+        },
+        EnvironmentMapInfo::Cube{ face_size, pixels } => {
+            CubeMap{ size: *face_size, texels: pixels.iter().map(|pixel| Float3::new(pixel.x, pixel.y, pixel.z)).collect() }
+        },
+    };
+
+    while base.size > SKYBOX_MAX_FACE_SIZE {
+        base = base.downsample();
+    }
+
+    let mut skybox = vec![base];
+    while skybox.last().unwrap().size > 1 {
+        let next = skybox.last().unwrap().downsample();
+        skybox.push(next);
+    }
+
+    let specular_source = skybox.iter().position(|mip| mip.size <= SPECULAR_FACE_SIZE).unwrap_or(0);
+    let specular        = prefilter_specular(&skybox[specular_source..]);
+
+    let sh         = compute_sh_irradiance(&specular[0]);
+    let irradiance = CubeMap::from_fn(IRRADIANCE_FACE_SIZE, |dir| {
+        let value = evaluate_sh(&sh, dir);
+        Float3::new(value.x.max(0.0), value.y.max(0.0), value.z.max(0.0))
+    });
+
+    return EnvironmentCubes{ skybox, irradiance: vec![irradiance], specular };
+}
+
+// Direction through the center of a cubemap texel. Follows the face orientations of the Vulkan spec, "Cube Map
+// Face Selection".
+fn get_cube_direction(face: u32, x: u32, y: u32, size: u32) -> Float3 {
+    let s = (x as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+    let t = (y as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+
+    let dir = match face {
+        0 => Float3::new( 1.0,   -t,   -s),
+        1 => Float3::new(-1.0,   -t,    s),
+        2 => Float3::new(   s,  1.0,    t),
+        3 => Float3::new(   s, -1.0,   -t),
+        4 => Float3::new(   s,   -t,  1.0),
+        _ => Float3::new(  -s,   -t, -1.0),
+    };
+
+    return dir.unit();
+}
+
+// The face a direction falls in and its texture coordinates on that face, in [0, 1]. The inverse of
+// get_cube_direction().
+fn get_cube_face_coords(dir: Float3) -> (u32, f32, f32) {
+    let (ax, ay, az) = (dir.x.abs(), dir.y.abs(), dir.z.abs());
+
+    let (face, sc, tc, ma) = if ax >= ay && ax >= az {
+        if dir.x > 0.0 { (0, -dir.z, -dir.y, ax) } else { (1, dir.z, -dir.y, ax) }
+    } else if ay >= az {
+        if dir.y > 0.0 { (2, dir.x, dir.z, ay) } else { (3, dir.x, -dir.z, ay) }
+    } else {
+        if dir.z > 0.0 { (4, dir.x, -dir.y, az) } else { (5, -dir.x, -dir.y, az) }
+    };
+
+    let ma = ma.max(1e-8);
+    return (face, (sc / ma + 1.0) * 0.5, (tc / ma + 1.0) * 0.5);
+}
+
+// Solid angle of a cubemap texel, from the area of its projection onto the unit sphere
+fn get_texel_solid_angle(x: u32, y: u32, size: u32) -> f32 {
+    let area = |x: f32, y: f32| -> f32 { (x * y).atan2((x * x + y * y + 1.0).sqrt()) };
+
+    let texel = 2.0 / size as f32;
+    let x0    = x as f32 * texel - 1.0;
+    let y0    = y as f32 * texel - 1.0;
+    let x1    = x0 + texel;
+    let y1    = y0 + texel;
+
+    return area(x0, y0) - area(x0, y1) - area(x1, y0) + area(x1, y1);
+}
+
+// Direction through the center of an equirectangular texel
+fn get_equirect_direction(x: u32, y: u32, width: u32, height: u32) -> Float3 {
+    let theta = (y as f32 + 0.5) / height as f32 * PI;
+    let phi   = (x as f32 + 0.5) / width  as f32 * 2.0 * PI;
+
+    return Float3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin());
+}
+
+// Bilinear lookup of an equirectangular map, wrapping around horizontally. The inverse of get_equirect_direction().
+fn sample_equirect(width: u32, height: u32, pixels: &[Float4], dir: Float3) -> Float3 {
+    let theta = dir.y.clamp(-1.0, 1.0).acos();
+    let phi   = dir.z.atan2(dir.x).rem_euclid(2.0 * PI);
+
+    let u = phi / (2.0 * PI) * width as f32 - 0.5;
+    let v = (theta / PI * height as f32 - 0.5).clamp(0.0, (height - 1) as f32);
+
+    let x0 = u.floor();
+    let y0 = v.floor() as u32;
+    let y1 = (y0 + 1).min(height - 1);
+    let fx = u - x0;
+    let fy = v - y0 as f32;
+
+    let x0 = (x0 as i64).rem_euclid(width as i64) as u32;
+    let x1 = (x0 + 1) % width;
+
+    let texel = |x: u32, y: u32| -> Float3 {
+        let pixel = pixels[(y * width + x) as usize];
+        Float3::new(pixel.x, pixel.y, pixel.z)
+    };
+
+    let top    = texel(x0, y0) * (1.0 - fx) + texel(x1, y0) * fx;
+    let bottom = texel(x0, y1) * (1.0 - fx) + texel(x1, y1) * fx;
+    return top * (1.0 - fy) + bottom * fy;
+}
+
+// Real spherical harmonics basis, bands 0 to 2
+fn get_sh_basis(dir: Float3) -> [f32; SH_COEFFICIENT_COUNT] {
+    return [
+        0.282095,
+        0.488603 * dir.y,
+        0.488603 * dir.z,
+        0.488603 * dir.x,
+        1.092548 * dir.x * dir.y,
+        1.092548 * dir.y * dir.z,
+        0.315392 * (3.0 * dir.z * dir.z - 1.0),
+        1.092548 * dir.x * dir.z,
+        0.546274 * (dir.x * dir.x - dir.y * dir.y),
+    ];
+}
+
+fn evaluate_sh(coefficients: &[Float3; SH_COEFFICIENT_COUNT], dir: Float3) -> Float3 {
+    let basis = get_sh_basis(dir);
+
+    let mut result = Float3::zero();
+    for i in 0..SH_COEFFICIENT_COUNT {
+        result = result + coefficients[i] * basis[i];
+    }
+
+    return result;
+}
+
+/// Projects a cubemap of radiance onto the SH basis and convolves it into diffuse irradiance / PI.
+fn compute_sh_irradiance(cube: &CubeMap) -> [Float3; SH_COEFFICIENT_COUNT] {
+    let mut coefficients = [Float3::zero(); SH_COEFFICIENT_COUNT];
+
+    for face in 0..6 {
+        for y in 0..cube.size {
+            for x in 0..cube.size {
+                let radiance    = cube.texels[((face * cube.size + y) * cube.size + x) as usize];
+                let solid_angle = get_texel_solid_angle(x, y, cube.size);
+                let basis       = get_sh_basis(get_cube_direction(face, x, y, cube.size));
+
+                for i in 0..SH_COEFFICIENT_COUNT {
+                    coefficients[i] = coefficients[i] + radiance * (basis[i] * solid_angle);
+                }
+            }
+        }
+    }
+
+    // Clamped cosine convolution per band (PI, 2PI/3, PI/4), divided by PI
+    let band_scale: [f32; SH_COEFFICIENT_COUNT] = [1.0, 2.0 / 3.0, 2.0 / 3.0, 2.0 / 3.0, 0.25, 0.25, 0.25, 0.25, 0.25];
+    for i in 0..SH_COEFFICIENT_COUNT {
+        coefficients[i] = coefficients[i] * band_scale[i];
+    }
+
+    return coefficients;
+}
+
+// Low-discrepancy point set over [0, 1)^2
+fn hammersley(i: u32, count: u32) -> (f32, f32) {
+    return (i as f32 / count as f32, i.reverse_bits() as f32 * (1.0 / 4294967296.0));
+}
+
+// Trilinear lookup of a cubemap's mips
+fn sample_mips(mips: &[CubeMap], dir: Float3, lod: f32) -> Float3 {
+    let lod  = lod.clamp(0.0, (mips.len() - 1) as f32);
+    let mip  = lod.floor() as usize;
+    let next = (mip + 1).min(mips.len() - 1);
+    let t    = lod - mip as f32;
+
+    return mips[mip].sample(dir) * (1.0 - t) + mips[next].sample(dir) * t;
+}
+
+/// Prefilters the radiance with the GGX lobe, assuming the view direction is the normal. `source` is the radiance
+/// and its mips, the first mip of the result is a copy of the radiance.
+fn prefilter_specular(source: &[CubeMap]) -> Vec<CubeMap> {
+    let mip_count = SPECULAR_MIP_COUNT.min(source.len());
+
+    // Solid angle of a texel of the source's first mip
+    let texel_solid_angle = 4.0 * PI / (6.0 * (source[0].size * source[0].size) as f32);
+
+    let mut mips = vec![source[0].clone()];
+
+    for mip in 1..mip_count {
+        let roughness = mip as f32 / (mip_count - 1).max(1) as f32;
+        let alpha     = roughness * roughness;
+        let alpha2    = alpha * alpha;
+
+        mips.push(CubeMap::from_fn(source[mip].size, |n| {
+            let up        = if n.y.abs() < 0.999 { Float3::new(0.0, 1.0, 0.0) } else { Float3::new(1.0, 0.0, 0.0) };
+            let tangent   = up.cross(n).unit();
+            let bitangent = n.cross(tangent);
+
+            let mut sum    = Float3::zero();
+            let mut weight = 0.0;
+
+            for i in 0..SPECULAR_SAMPLE_COUNT {
+                // GGX importance sample of the half vector
+                let (u1, u2)  = hammersley(i, SPECULAR_SAMPLE_COUNT);
+                let phi       = 2.0 * PI * u1;
+                let cos_theta = ((1.0 - u2) / (1.0 + (alpha2 - 1.0) * u2)).sqrt();
+                let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+
+                let h = tangent * (sin_theta * phi.cos()) + bitangent * (sin_theta * phi.sin()) + n * cos_theta;
+                let l = h * (2.0 * n.dot(h)) - n;
+
+                let n_dot_l = n.dot(l);
+                if n_dot_l <= 0.0 {
+                    continue;
+                }
+
+                // With the view along the normal, the pdf of l is D / 4. Read the mip whose texels cover about as
+                // much of the sphere as the sample does.
+                let d                  = (cos_theta * cos_theta * (alpha2 - 1.0) + 1.0).max(1e-6);
+                let pdf                = alpha2 / (PI * d * d) * 0.25;
+                let sample_solid_angle = 1.0 / (SPECULAR_SAMPLE_COUNT as f32 * pdf + 1e-6);
+                let lod                = 0.5 * (sample_solid_angle / texel_solid_angle).log2() + 1.0;
+
+                sum    = sum + sample_mips(source, l, lod) * n_dot_l;
+                weight += n_dot_l;
+            }
+
+            if weight > 0.0 { sum * (1.0 / weight) } else { source[mip].sample(n) }
+        }));
+    }
+
+    return mips;
+}
+
+/// Packs the mips back to back, face by face, as RGBA16F texels ready to be copied into a cubemap.
+pub(crate) fn pack_cube_mips(mips: &[CubeMap]) -> Vec<[u16; 4]> {
+    let texel_count = mips.iter().map(|mip| mip.texels.len()).sum();
+
+    let mut packed = Vec::<[u16; 4]>::with_capacity(texel_count);
+    for mip in mips {
+        for texel in &mip.texels {
+            packed.push([f32_to_f16(texel.x), f32_to_f16(texel.y), f32_to_f16(texel.z), f32_to_f16(1.0)]);
+        }
+    }
+
+    return packed;
+}
+
+// Rounds to the nearest half float. Values past the half float range are clamped to the largest half float, so a
+// very bright sun doesn't turn into infinity.
+fn f32_to_f16(value: f32) -> u16 {
+    let bits     = value.to_bits();
+    let sign     = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+    let mantissa = bits & 0x7f_ffff;
+
+    if value.is_nan() {
+        return sign | 0x7e00;
+    }
+
+    if exponent >= 31 {
+        return sign | 0x7bff;
+    }
+
+    if exponent <= 0 {
+        // Subnormal, or too small for a half float
+        if exponent < -10 {
+            return sign;
+        }
+
+        let mantissa = mantissa | 0x80_0000;
+        let shift    = (14 - exponent) as u32;
+        let rounding = (mantissa >> (shift - 1)) & 1;
+        return sign | ((mantissa >> shift) + rounding) as u16;
+    }
+
+    let half     = sign as u32 | (exponent as u32) << 10 | mantissa >> 13;
+    let rounding = (mantissa >> 12) & 1;
+    return (half + rounding).min(sign as u32 | 0x7bff) as u16;
+}
+
+// Radiance of the default sky, a gradient over a dark ground
+fn get_default_sky(dir: Float3) -> Float3 {
+    let zenith  = Float3::new(0.25, 0.45, 0.85);
+    let horizon = Float3::new(0.70, 0.80, 0.95);
+    let ground  = Float3::new(0.20, 0.18, 0.15);
+
+    if dir.y >= 0.0 {
+        let t = dir.y.sqrt();
+        return horizon * (1.0 - t) + zenith * t;
+    }
+
+    let t = (-dir.y).sqrt();
+    return horizon * (1.0 - t) + ground * t;
+}
+
+/// A simple sky gradient over a dark ground, the skybox and ambient lighting until the engine sends an environment.
+pub(crate) fn make_default_environment() -> EnvironmentMapInfo {
+    let width  = DEFAULT_ENVIRONMENT_WIDTH;
+    let height = DEFAULT_ENVIRONMENT_HEIGHT;
+
+    let mut pixels = Vec::<Float4>::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let color = get_default_sky(get_equirect_direction(x, y, width, height));
+            pixels.push(Float4::new(color.x, color.y, color.z, 1.0));
+        }
+    }
+
+    return EnvironmentMapInfo::Equirectangular{ width, height, pixels };
+}
+
+/// The default sky at a single texel per face, cheap enough to build on the render thread. Lights the scene while the
+/// loader builds the default environment.
+pub(crate) fn make_placeholder_environment() -> EnvironmentMapInfo {
+    let face = CubeMap::from_fn(1, get_default_sky);
+    return EnvironmentMapInfo::Cube{ face_size: 1, pixels: face.texels.iter().map(|texel| Float4::new(texel.x, texel.y, texel.z, 1.0)).collect() };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{ Duration, Instant };
+
+    /// A cubemap of a single color, `size` texels across.
+    fn make_flat_environment(size: u32, color: Float3) -> EnvironmentMapInfo {
+        return EnvironmentMapInfo::Cube{ face_size: size, pixels: vec![Float4::new(color.x, color.y, color.z, 1.0); (size * size * 6) as usize] };
+    }
+
+    fn wait_for_built(loader: &EnvironmentLoader) -> EnvironmentCubes {
+        let start = Instant::now();
+        loop {
+            if let Some(cubes) = loader.take_built() {
+                return cubes;
+            }
+
+            assert!(start.elapsed() < Duration::from_secs(30), "the environment was never built");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn loader_builds_the_latest_request() {
+        let mut loader = EnvironmentLoader::new().unwrap();
+        assert!(loader.take_built().is_none());
+
+        let red  = Float3::new(1.0, 0.0, 0.0);
+        let blue = Float3::new(0.0, 0.0, 1.0);
+
+        // The red environment is replaced before anything is taken, only the blue one comes out
+        loader.request(make_flat_environment(8, red));
+        loader.request(make_flat_environment(8, blue));
+
+        let cubes = wait_for_built(&loader);
+        assert_eq!(cubes.skybox[0].size, 8);
+        assert!(cubes.skybox.iter().all(|mip| mip.texels.iter().all(|texel| *texel == blue)));
+        assert!(cubes.specular.iter().all(|mip| mip.texels.iter().all(|texel| (*texel - blue).length() < 1e-4)));
+
+        // A flat environment lights every normal the same, irradiance / PI is the radiance
+        assert!(cubes.irradiance[0].texels.iter().all(|texel| (*texel - blue).length() < 1e-2));
+
+        // Nothing new until the next request
+        thread::sleep(Duration::from_millis(10));
+        assert!(loader.take_built().is_none());
+
+        loader.request(make_flat_environment(4, red));
+        assert!(wait_for_built(&loader).skybox[0].texels.iter().all(|texel| *texel == red));
+    }
+
+    #[test]
+    fn placeholder_follows_the_default_sky() {
+        let cubes = build_environment(&make_placeholder_environment());
+
+        assert_eq!(cubes.skybox.len(), 1);
+        assert_eq!(cubes.specular.len(), 1);
+
+        // Brighter sky above than ground below, the +y face is the third
+        let up   = cubes.skybox[0].texels[2];
+        let down = cubes.skybox[0].texels[3];
+        assert!(up.z > down.z);
+        assert!(cubes.irradiance[0].sample(Float3::new(0.0, 1.0, 0.0)).z > cubes.irradiance[0].sample(Float3::new(0.0, -1.0, 0.0)).z);
+    }
+}
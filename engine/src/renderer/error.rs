@@ -0,0 +1,100 @@
+use std::fmt;
+
+use vendor::vulkan::*;
+
+//
+// Render Errors
+//
+// Errors surfaced by the graphics layer. The render thread forwards these to the Engine, which decides whether
+// the game can keep going. Recoverable errors are ones the renderer can fix by recreating some of its state.
+//
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RenderError {
+    /// Failed to load Vulkan, or to create the instance or window surface.
+    Initialization(String),
+    /// No GPU meets the engine's requirements, or the requested GPU can't be used.
+    NoSuitableGpu(String),
+    /// A Vulkan call failed with an error the renderer can't recover from.
+    Vulkan { call: &'static str, result: VkResult },
+    /// VK_ERROR_DEVICE_LOST. The device, and everything created from it, must be recreated. By the time this
+    /// reaches the Engine, the RenderSystem has already recreated the device and re-uploaded its resources.
+    DeviceLost,
+    /// The device was lost and recreating it failed. The renderer has no device left to render with.
+    DeviceRecoveryFailed(String),
+    /// VK_ERROR_OUT_OF_DATE_KHR. The swapchain must be recreated before the next frame.
+    SwapchainOutOfDate,
+    /// A compiled shader could not be found or read from the shader cache.
+    ShaderNotFound { path: String, reason: String },
+    /// The shader was read from disk, but the driver rejected it.
+    InvalidShader(String),
+    /// The render thread has gone away. Usually this means it panicked.
+    RenderThreadDisconnected,
+    /// A worker recording part of a draw list panicked. Holds the panic message.
+    RecordingWorkerPanicked(String),
+}
+
+impl RenderError {
+    pub fn from_vk_result(call: &'static str, result: VkResult) -> RenderError {
+        return match result {
+            VK_ERROR_DEVICE_LOST     => RenderError::DeviceLost,
+            VK_ERROR_OUT_OF_DATE_KHR => RenderError::SwapchainOutOfDate,
+            _                        => RenderError::Vulkan{ call, result },
+        };
+    }
+
+    pub fn is_recoverable(&self) -> bool {
+        return match self {
+            RenderError::DeviceLost                 => true,
+            RenderError::SwapchainOutOfDate         => true,
+            RenderError::ShaderNotFound{ .. }       => true,
+            RenderError::InvalidShader(_)           => true,
+            RenderError::Initialization(_)          => false,
+            RenderError::NoSuitableGpu(_)           => false,
+            RenderError::DeviceRecoveryFailed(_)    => false,
+            RenderError::Vulkan{ .. }               => false,
+            RenderError::RenderThreadDisconnected   => false,
+            RenderError::RecordingWorkerPanicked(_) => false,
+        };
+    }
+}
+
+fn get_vk_result_name(result: VkResult) -> &'static str {
+    return match result {
+        VK_ERROR_OUT_OF_HOST_MEMORY    => "VK_ERROR_OUT_OF_HOST_MEMORY",
+        VK_ERROR_OUT_OF_DEVICE_MEMORY  => "VK_ERROR_OUT_OF_DEVICE_MEMORY",
+        VK_ERROR_INITIALIZATION_FAILED => "VK_ERROR_INITIALIZATION_FAILED",
+        VK_ERROR_DEVICE_LOST           => "VK_ERROR_DEVICE_LOST",
+        VK_ERROR_MEMORY_MAP_FAILED     => "VK_ERROR_MEMORY_MAP_FAILED",
+        VK_ERROR_LAYER_NOT_PRESENT     => "VK_ERROR_LAYER_NOT_PRESENT",
+        VK_ERROR_EXTENSION_NOT_PRESENT => "VK_ERROR_EXTENSION_NOT_PRESENT",
+        VK_ERROR_FEATURE_NOT_PRESENT   => "VK_ERROR_FEATURE_NOT_PRESENT",
+        VK_ERROR_INCOMPATIBLE_DRIVER   => "VK_ERROR_INCOMPATIBLE_DRIVER",
+        VK_ERROR_TOO_MANY_OBJECTS      => "VK_ERROR_TOO_MANY_OBJECTS",
+        VK_ERROR_FORMAT_NOT_SUPPORTED  => "VK_ERROR_FORMAT_NOT_SUPPORTED",
+        VK_ERROR_FRAGMENTED_POOL       => "VK_ERROR_FRAGMENTED_POOL",
+        VK_ERROR_OUT_OF_POOL_MEMORY    => "VK_ERROR_OUT_OF_POOL_MEMORY",
+        VK_ERROR_SURFACE_LOST_KHR      => "VK_ERROR_SURFACE_LOST_KHR",
+        VK_ERROR_OUT_OF_DATE_KHR       => "VK_ERROR_OUT_OF_DATE_KHR",
+        _                              => "Unknown VkResult",
+    };
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            RenderError::Initialization(reason)          => write!(f, "Failed to initialize the renderer: {}", reason),
+            RenderError::NoSuitableGpu(reason)           => write!(f, "Failed to select a GPU: {}", reason),
+            RenderError::Vulkan{ call, result }          => write!(f, "{} failed: {} ({})", call, get_vk_result_name(*result), result),
+            RenderError::DeviceLost                      => write!(f, "The GPU device was lost"),
+            RenderError::DeviceRecoveryFailed(reason)    => write!(f, "Failed to recover from a lost GPU device: {}", reason),
+            RenderError::SwapchainOutOfDate              => write!(f, "The swapchain is out of date"),
+            RenderError::ShaderNotFound{ path, reason }  => write!(f, "Failed to load shader {}: {}", path, reason),
+            RenderError::InvalidShader(name)             => write!(f, "Failed to create a shader module from {}", name),
+            RenderError::RenderThreadDisconnected        => write!(f, "The render thread is no longer running"),
+            RenderError::RecordingWorkerPanicked(reason) => write!(f, "A command recording worker panicked: {}", reason),
+        };
+    }
+}
+
+impl std::error::Error for RenderError {}
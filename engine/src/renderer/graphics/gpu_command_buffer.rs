@@ -3,29 +3,35 @@ use std::ffi::c_void;
 use crate::util::ffi::call;
 
 use super::{gpu_utils::*, AllocatedBuffer, AllocatedImage};
+use crate::renderer::error::RenderError;
 
 use vendor::vulkan::*;
 
 pub struct CommandBufferFnTable {
-    pub begin_command_buffer:     FN_vkBeginCommandBuffer,
-    pub end_command_buffer:       FN_vkEndCommandBuffer,
-    pub reset_command_buffer:     FN_vkResetCommandBuffer,
-    pub cmd_pipeline_barrier2:    FN_vkCmdPipelineBarrier2,
-    pub cmd_clear_color_image:    FN_vkCmdClearColorImage,
-    pub cmd_blit_image2:          FN_vkCmdBlitImage2,
-    pub cmd_bind_pipeline:        FN_vkCmdBindPipeline,
-    pub cmd_bind_descriptor_sets: FN_vkCmdBindDescriptorSets,
-    pub cmd_dispatch:             FN_vkCmdDispatch,
-    pub cmd_begin_rendering:      FN_vkCmdBeginRendering,
-    pub cmd_end_rendering:        FN_vkCmdEndRendering,
-    pub cmd_set_scissor:          FN_vkCmdSetScissor,
-    pub cmd_set_viewport:         FN_vkCmdSetViewport,
-    pub cmd_draw:                 FN_vkCmdDraw,
-    pub cmd_push_constants:       FN_vkCmdPushConstants,
-    pub cmd_copy_buffer:          FN_vkCmdCopyBuffer,
-    pub cmd_bind_index_buffer:    FN_vkCmdBindIndexBuffer,
-    pub cmd_draw_indexed:         FN_vkCmdDrawIndexed,
-    pub cmd_copy_buffer_to_image: FN_vkCmdCopyBufferToImage,
+    pub begin_command_buffer:            FN_vkBeginCommandBuffer,
+    pub end_command_buffer:              FN_vkEndCommandBuffer,
+    pub reset_command_buffer:            FN_vkResetCommandBuffer,
+    pub cmd_pipeline_barrier2:           FN_vkCmdPipelineBarrier2,
+    pub cmd_clear_color_image:           FN_vkCmdClearColorImage,
+    pub cmd_blit_image2:                 FN_vkCmdBlitImage2,
+    pub cmd_bind_pipeline:               FN_vkCmdBindPipeline,
+    pub cmd_bind_descriptor_sets:        FN_vkCmdBindDescriptorSets,
+    pub cmd_dispatch:                    FN_vkCmdDispatch,
+    pub cmd_begin_rendering:             FN_vkCmdBeginRendering,
+    pub cmd_end_rendering:               FN_vkCmdEndRendering,
+    pub cmd_set_scissor:                 FN_vkCmdSetScissor,
+    pub cmd_set_viewport:                FN_vkCmdSetViewport,
+    pub cmd_draw:                        FN_vkCmdDraw,
+    pub cmd_push_constants:              FN_vkCmdPushConstants,
+    pub cmd_copy_buffer:                 FN_vkCmdCopyBuffer,
+    pub cmd_bind_index_buffer:           FN_vkCmdBindIndexBuffer,
+    pub cmd_draw_indexed:                FN_vkCmdDrawIndexed,
+    pub cmd_copy_buffer_to_image:        FN_vkCmdCopyBufferToImage,
+    pub cmd_execute_commands:            FN_vkCmdExecuteCommands,
+    pub cmd_set_depth_bias:              FN_vkCmdSetDepthBias,
+    pub cmd_draw_indexed_indirect:       FN_vkCmdDrawIndexedIndirect,
+    pub cmd_draw_indexed_indirect_count: FN_vkCmdDrawIndexedIndirectCount,
+    pub cmd_fill_buffer:                 FN_vkCmdFillBuffer,
 }
 
 #[derive(PartialEq)]
@@ -44,6 +50,18 @@ pub struct CommandBuffer {
     pub bound_pipeline: VkPipeline,
 }
 
+// Command buffers can be recorded on any thread. Recording still needs exclusive access to the command buffer
+// and its pool, so each worker thread records into command buffers from its own pool.
+unsafe impl Send for CommandBuffer {}
+
+/// The dynamic rendering state a secondary command buffer executes in. The formats and sample count must match
+/// the vkCmdBeginRendering call on the primary command buffer.
+pub struct RenderingInheritance<'a> {
+    pub color_formats: &'a [VkFormat],
+    pub depth_format:  VkFormat,
+    pub samples:       VkSampleCountFlagBits,
+}
+
 impl CommandBuffer {
     pub fn new(fns: CommandBufferFnTable, handle: VkCommandBuffer) -> Self {
         Self{
@@ -51,29 +69,69 @@ impl CommandBuffer {
         }
     }
 
-    pub fn begin_recording(&mut self) {
+    pub fn begin_recording(&mut self) -> Result<(), RenderError> {
         assert!(self.state == CommandBufferState::Reset);
 
         let cmd_begin_info = make_command_buffer_begin_info(VK_COMMAND_BUFFER_USAGE_ONE_TIME_SUBMIT_BIT);
         call_throw!(self.fns.begin_command_buffer, self.handle, &cmd_begin_info);
 
         self.state = CommandBufferState::Open;
+        return Ok(());
     }
 
-    pub fn end_recording(&mut self) {
+    /// Begins recording a secondary command buffer that continues a dynamic rendering scope. The primary command
+    /// buffer must begin rendering with VK_RENDERING_CONTENTS_SECONDARY_COMMAND_BUFFERS_BIT.
+    pub fn begin_secondary_recording(&mut self, inheritance: &RenderingInheritance) -> Result<(), RenderError> {
+        assert!(self.state == CommandBufferState::Reset);
+
+        let rendering_info = VkCommandBufferInheritanceRenderingInfo{
+            sType:                   VK_STRUCTURE_TYPE_COMMAND_BUFFER_INHERITANCE_RENDERING_INFO,
+            pNext:                   std::ptr::null(),
+            flags:                   0,
+            viewMask:                0,
+            colorAttachmentCount:    inheritance.color_formats.len() as u32,
+            pColorAttachmentFormats: inheritance.color_formats.as_ptr(),
+            depthAttachmentFormat:   inheritance.depth_format,
+            stencilAttachmentFormat: VK_FORMAT_UNDEFINED,
+            rasterizationSamples:    inheritance.samples,
+        };
+
+        let inheritance_info = VkCommandBufferInheritanceInfo{
+            sType:                VK_STRUCTURE_TYPE_COMMAND_BUFFER_INHERITANCE_INFO,
+            pNext:                &rendering_info as *const VkCommandBufferInheritanceRenderingInfo as *const c_void,
+            renderPass:           std::ptr::null_mut(),
+            subpass:              0,
+            framebuffer:          std::ptr::null_mut(),
+            occlusionQueryEnable: VK_FALSE,
+            queryFlags:           0,
+            pipelineStatistics:   0,
+        };
+
+        let mut cmd_begin_info = make_command_buffer_begin_info(VK_COMMAND_BUFFER_USAGE_ONE_TIME_SUBMIT_BIT | VK_COMMAND_BUFFER_USAGE_RENDER_PASS_CONTINUE_BIT);
+        cmd_begin_info.pInheritanceInfo = &inheritance_info;
+
+        call_throw!(self.fns.begin_command_buffer, self.handle, &cmd_begin_info);
+
+        self.state = CommandBufferState::Open;
+        return Ok(());
+    }
+
+    pub fn end_recording(&mut self) -> Result<(), RenderError> {
         assert!(self.state == CommandBufferState::Open);
 
         call_throw!(self.fns.end_command_buffer, self.handle);
         self.state = CommandBufferState::Closed;
+        return Ok(());
     }
 
-    pub fn reset(&mut self) {
+    pub fn reset(&mut self) -> Result<(), RenderError> {
         assert!(self.state == CommandBufferState::Closed);
 
         call_throw!(self.fns.reset_command_buffer, self.handle, 0);
 
         self.state          = CommandBufferState::Reset;
         self.bound_pipeline = std::ptr::null_mut();
+        return Ok(());
     }
 
     pub fn get_submit_info(&self) -> VkCommandBufferSubmitInfo {
@@ -117,6 +175,29 @@ impl CommandBuffer {
         call!(self.fns.cmd_pipeline_barrier2, self.handle, &dep_info);
     }
 
+    /// Records a batch of image and buffer barriers with a single vkCmdPipelineBarrier2.
+    pub fn pipeline_barrier(&self, image_barriers: &[VkImageMemoryBarrier2], buffer_barriers: &[VkBufferMemoryBarrier2]) {
+        assert!(self.state == CommandBufferState::Open);
+
+        if image_barriers.is_empty() && buffer_barriers.is_empty() {
+            return;
+        }
+
+        let dep_info = VkDependencyInfo{
+            sType:                    VK_STRUCTURE_TYPE_DEPENDENCY_INFO,
+            pNext:                    std::ptr::null(),
+            dependencyFlags:          0,
+            memoryBarrierCount:       0,
+            pMemoryBarriers:          std::ptr::null(),
+            bufferMemoryBarrierCount: buffer_barriers.len() as u32,
+            pBufferMemoryBarriers:    buffer_barriers.as_ptr(),
+            imageMemoryBarrierCount:  image_barriers.len() as u32,
+            pImageMemoryBarriers:     image_barriers.as_ptr(),
+        };
+
+        call!(self.fns.cmd_pipeline_barrier2, self.handle, &dep_info);
+    }
+
     pub fn clear_color_image(&self, image: VkImage, clear_value: &VkClearColorValue) {
         assert!(self.state == CommandBufferState::Open);
 
@@ -211,6 +292,24 @@ impl CommandBuffer {
     	call!(self.fns.cmd_set_scissor, self.handle, 0, 1, &scissor);
     }
 
+    pub fn set_scissor_rect(&self, offset_x: i32, offset_y: i32, width: u32, height: u32) {
+        assert!(self.state == CommandBufferState::Open);
+
+        let mut scissor = VkRect2D::default();
+        scissor.offset.x      = offset_x;
+        scissor.offset.y      = offset_y;
+        scissor.extent.width  = width;
+        scissor.extent.height = height;
+
+        call!(self.fns.cmd_set_scissor, self.handle, 0, 1, &scissor);
+    }
+
+    // Requires a pipeline built with GraphicsPipelineBuilder::enable_dynamic_depth_bias()
+    pub fn set_depth_bias(&self, constant_factor: f32, clamp: f32, slope_factor: f32) {
+        assert!(self.state == CommandBufferState::Open);
+        call!(self.fns.cmd_set_depth_bias, self.handle, constant_factor, clamp, slope_factor);
+    }
+
     pub fn draw(&self, vertex_count: u32, instance_count: u32, first_vertex: u32, first_instance: u32) {
         assert!(self.state == CommandBufferState::Open);
         call!(self.fns.cmd_draw, self.handle, vertex_count, instance_count, first_vertex, first_instance);
@@ -302,6 +401,38 @@ impl CommandBuffer {
 		call!(self.fns.cmd_copy_buffer_to_image, self.handle, upload_buffer.buffer, dst_image.image, VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL, 1, &copy_region);
     }
 
+    /// Copies tightly packed texels at `buffer_offset` into one mip of the first `layer_count` layers of an image,
+    /// such as the six faces of a cubemap. `size` is the size of the mip.
+    pub fn copy_buffer_to_image_mip(&self, upload_buffer: &AllocatedBuffer, buffer_offset: VkDeviceSize, dst_image: &AllocatedImage, mip_level: u32, layer_count: u32, size: VkExtent3D) {
+        assert!(self.state == CommandBufferState::Open);
+
+        let copy_region = VkBufferImageCopy{
+            bufferOffset:      buffer_offset,
+            bufferRowLength:   0,
+            bufferImageHeight: 0,
+            imageSubresource:  VkImageSubresourceLayers{
+                aspectMask:     VK_IMAGE_ASPECT_COLOR_BIT,
+                mipLevel:       mip_level,
+                baseArrayLayer: 0,
+                layerCount:     layer_count,
+            },
+            imageOffset:       VkOffset3D::default(),
+            imageExtent:       size,
+        };
+
+        call!(self.fns.cmd_copy_buffer_to_image, self.handle, upload_buffer.buffer, dst_image.image, VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL, 1, &copy_region);
+    }
+
+    pub fn execute_commands(&self, secondary_command_buffers: &[VkCommandBuffer]) {
+        assert!(self.state == CommandBufferState::Open);
+
+        if secondary_command_buffers.is_empty() {
+            return;
+        }
+
+        call!(self.fns.cmd_execute_commands, self.handle, secondary_command_buffers.len() as u32, secondary_command_buffers.as_ptr());
+    }
+
     pub fn bind_index_buffer(&self, index_buffer: &AllocatedBuffer) {
         call!(self.fns.cmd_bind_index_buffer, self.handle, index_buffer.buffer, 0, VK_INDEX_TYPE_UINT32);
     }
@@ -310,6 +441,68 @@ impl CommandBuffer {
         call!(self.fns.cmd_draw_indexed, self.handle, index_count, instance_count, first_index, vertex_offset, first_instance);
     }
 
+    /// Draws `draw_count` VkDrawIndexedIndirectCommands read from `buffer`, starting at `offset`.
+    pub fn draw_indexed_indirect(&self, buffer: VkBuffer, offset: VkDeviceSize, draw_count: u32, stride: u32) {
+        call!(self.fns.cmd_draw_indexed_indirect, self.handle, buffer, offset, draw_count, stride);
+    }
+
+    /// Like draw_indexed_indirect, but the number of draws is read from a u32 in `count_buffer` when the command
+    /// executes, up to `max_draw_count`. Requires Device::supports_gpu_driven_rendering.
+    pub fn draw_indexed_indirect_count(&self, buffer: VkBuffer, offset: VkDeviceSize, count_buffer: VkBuffer, count_offset: VkDeviceSize, max_draw_count: u32, stride: u32) {
+        call!(self.fns.cmd_draw_indexed_indirect_count, self.handle, buffer, offset, count_buffer, count_offset, max_draw_count, stride);
+    }
+
+    /// Fills `size` bytes of `buffer` with the repeated u32 `data`. Both `offset` and `size` must be multiples of 4.
+    pub fn fill_buffer(&self, buffer: VkBuffer, offset: VkDeviceSize, size: VkDeviceSize, data: u32) {
+        assert!(self.state == CommandBufferState::Open);
+        call!(self.fns.cmd_fill_buffer, self.handle, buffer, offset, size, data);
+    }
+
+    /// Makes the writes to the whole buffer from `src_stages` visible to `dst_stages`. The render graph places the
+    /// barriers between passes, this is for dependencies within a pass.
+    pub fn buffer_barrier(&self, buffer: VkBuffer, src_stages: VkPipelineStageFlags2, src_access: VkAccessFlags2, dst_stages: VkPipelineStageFlags2, dst_access: VkAccessFlags2) {
+        let barrier = VkBufferMemoryBarrier2{
+            sType:               VK_STRUCTURE_TYPE_BUFFER_MEMORY_BARRIER_2,
+            pNext:               std::ptr::null(),
+            srcStageMask:        src_stages,
+            srcAccessMask:       src_access,
+            dstStageMask:        dst_stages,
+            dstAccessMask:       dst_access,
+            srcQueueFamilyIndex: VK_QUEUE_FAMILY_IGNORED as u32,
+            dstQueueFamilyIndex: VK_QUEUE_FAMILY_IGNORED as u32,
+            buffer,
+            offset:              0,
+            size:                VK_WHOLE_SIZE as VkDeviceSize,
+        };
+
+        self.pipeline_barrier(&[], &[barrier]);
+    }
+
+    /// Makes the writes to one mip level of a color image from `src_stages` visible to `dst_stages`, keeping the
+    /// image in `layout`. Used between the passes of a compute shader that reads the previous mip it wrote.
+    pub fn image_mip_barrier(&self, image: VkImage, mip_level: u32, layout: VkImageLayout, src_stages: VkPipelineStageFlags2, src_access: VkAccessFlags2, dst_stages: VkPipelineStageFlags2, dst_access: VkAccessFlags2) {
+        let mut subresource_range = make_image_subresource_range(VK_IMAGE_ASPECT_COLOR_BIT);
+        subresource_range.baseMipLevel = mip_level;
+        subresource_range.levelCount   = 1;
+
+        let barrier = VkImageMemoryBarrier2{
+            sType:               VK_STRUCTURE_TYPE_IMAGE_MEMORY_BARRIER_2,
+            pNext:               std::ptr::null(),
+            srcStageMask:        src_stages,
+            srcAccessMask:       src_access,
+            dstStageMask:        dst_stages,
+            dstAccessMask:       dst_access,
+            oldLayout:           layout,
+            newLayout:           layout,
+            srcQueueFamilyIndex: VK_QUEUE_FAMILY_IGNORED as u32,
+            dstQueueFamilyIndex: VK_QUEUE_FAMILY_IGNORED as u32,
+            image,
+            subresourceRange:    subresource_range,
+        };
+
+        self.pipeline_barrier(&[barrier], &[]);
+    }
+
     pub fn generate_mipmaps(&self, image: &AllocatedImage) {
         assert!(self.state == CommandBufferState::Open);
 
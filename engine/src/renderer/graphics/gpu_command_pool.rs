@@ -9,3 +9,6 @@ pub struct CommandPool {
     pub fns:    CommandPoolFnTable,
     pub handle: VkCommandPool,
 }
+
+// A command pool may be used from any thread, as long as only one thread uses it at a time.
+unsafe impl Send for CommandPool {}
@@ -1,15 +1,18 @@
 use vendor::vulkan::*;
 use super::gpu_device::Device;
+use crate::renderer::error::RenderError;
 
 use std::{collections::VecDeque, ptr};
 
 pub struct DescriptorLayoutBuilder {
-    bindings: Vec<VkDescriptorSetLayoutBinding>,
+    bindings:      Vec<VkDescriptorSetLayoutBinding>,
+    binding_flags: Vec<VkDescriptorBindingFlags>, // indexed like bindings
 }
 
 pub enum DescriptorAllocatorFlags {
     None,
     AllowFree,
+    UpdateAfterBind, // for sets with update-after-bind bindings, see DescriptorLayoutBuilder::add_array_binding
 }
 
 #[derive(Copy, Clone)]
@@ -31,35 +34,53 @@ pub struct DescriptorAllocatorGrowable {
 
 impl DescriptorLayoutBuilder {
     pub fn new() -> Self{
-        Self { bindings: Vec::new() }
+        Self { bindings: Vec::new(), binding_flags: Vec::new() }
     }
 
     pub fn add_binding(&mut self, binding: u32, descriptor_type: VkDescriptorType) -> &mut Self {
+        self.add_array_binding(binding, descriptor_type, 1, 0)
+    }
+
+    /// Adds an array of `count` descriptors. Binding flags (partially bound, update-after-bind, ...) need the
+    /// descriptor indexing features, and a layout built with VK_DESCRIPTOR_SET_LAYOUT_CREATE_UPDATE_AFTER_BIND_POOL_BIT
+    /// if any binding can be updated after it is bound.
+    pub fn add_array_binding(&mut self, binding: u32, descriptor_type: VkDescriptorType, count: u32, flags: VkDescriptorBindingFlags) -> &mut Self {
         let binding = VkDescriptorSetLayoutBinding{
             binding,
             descriptorType:     descriptor_type,
-            descriptorCount:    1,
+            descriptorCount:    count,
             stageFlags:         0,
             pImmutableSamplers: std::ptr::null(),
         };
 
         self.bindings.push(binding);
+        self.binding_flags.push(flags);
         self
     }
 
-    pub fn clear(&mut self) { self.bindings.clear(); }
+    pub fn clear(&mut self) {
+        self.bindings.clear();
+        self.binding_flags.clear();
+    }
 
-    pub fn build(&mut self, device: &Device, stages: VkShaderStageFlags, flags: VkDescriptorSetLayoutCreateFlags) -> VkDescriptorSetLayout {
+    pub fn build(&mut self, device: &Device, stages: VkShaderStageFlags, flags: VkDescriptorSetLayoutCreateFlags) -> Result<VkDescriptorSetLayout, RenderError> {
         for binding in &mut self.bindings {
             binding.stageFlags |= stages;
         }
 
-        return device.create_descriptor_set_layout(self.bindings.as_slice(), flags);
+        // Only chain the binding flags when there are some, so layouts keep working without descriptor indexing
+        let binding_flags: &[VkDescriptorBindingFlags] = if self.binding_flags.iter().any(|flags| *flags != 0) {
+            self.binding_flags.as_slice()
+        } else {
+            &[]
+        };
+
+        return device.create_descriptor_set_layout(self.bindings.as_slice(), binding_flags, flags);
     }
 }
 
 impl DescriptorAllocatorGrowable {
-    pub fn new(device: &Device, ratios: &[PoolSizeRatio], sets_per_pool: u32) -> Self{
+    pub fn new(device: &Device, ratios: &[PoolSizeRatio], sets_per_pool: u32) -> Result<Self, RenderError> {
         let mut result = Self{
             ratios:      Vec::new(),
             sets_per_pool,
@@ -69,19 +90,19 @@ impl DescriptorAllocatorGrowable {
 
         result.ratios.extend_from_slice(ratios);
 
-        let pool = result.get_pool(&device);
+        let pool = result.get_pool(&device)?;
         result.ready_pools.push_back(pool);
 
-        result
+        Ok(result)
     }
 
-    fn get_pool(&mut self, device: &Device) -> DescriptorAllocator {
+    fn get_pool(&mut self, device: &Device) -> Result<DescriptorAllocator, RenderError> {
         if let Some(pool) = self.ready_pools.pop_back() {
-            return pool;
+            return Ok(pool);
         } else {
-            let result = device.create_descriptor_allocator(self.sets_per_pool, DescriptorAllocatorFlags::None, self.ratios.as_slice());
+            let result = device.create_descriptor_allocator(self.sets_per_pool, DescriptorAllocatorFlags::None, self.ratios.as_slice())?;
             self.sets_per_pool = ((1.5 * self.sets_per_pool as f32) as u32).min(4092);
-            return result;
+            return Ok(result);
         }
     }
 
@@ -115,10 +136,10 @@ impl DescriptorAllocatorGrowable {
         self.destroy_pools(device);
     }
 
-    pub fn allocate(&mut self, device: &Device, layout: VkDescriptorSetLayout) -> VkDescriptorSet {
+    pub fn allocate(&mut self, device: &Device, layout: VkDescriptorSetLayout) -> Result<VkDescriptorSet, RenderError> {
         let mut result: VkDescriptorSet = std::ptr::null_mut();
 
-        let mut pool = self.get_pool(device);
+        let mut pool = self.get_pool(device)?;
 
         let ds = device.allocate_descriptors(&pool, layout);
         if let Some(set) = ds {
@@ -127,12 +148,18 @@ impl DescriptorAllocatorGrowable {
             // current pool is full, so let's a get a new and try again.
             self.full_pools.push_back(pool);
 
-            pool = self.get_pool(device);
-            result = device.allocate_descriptors(&pool, layout).expect("Failed to allocate a descriptor set from the growable pool.");
+            pool = self.get_pool(device)?;
+            result = match device.allocate_descriptors(&pool, layout) {
+                Some(set) => set,
+                None => {
+                    self.ready_pools.push_back(pool);
+                    return Err(RenderError::Vulkan{ call: "vkAllocateDescriptorSets", result: VK_ERROR_OUT_OF_POOL_MEMORY });
+                }
+            };
         }
 
         self.ready_pools.push_back(pool);
-        return result;
+        return Ok(result);
     }
 }
 
@@ -166,6 +193,18 @@ impl DescriptorWriter {
         self.write_image(binding, image, std::ptr::null_mut(), layout, VK_DESCRIPTOR_TYPE_STORAGE_IMAGE);
     }
 
+    /// Writes a single element of a sampler array.
+    pub fn write_sampler_element(&mut self, binding: u32, element: u32, sampler: VkSampler) {
+        self.write_sampler(binding, sampler);
+        self.writes.last_mut().expect("write_sampler always adds a write").dstArrayElement = element;
+    }
+
+    /// Writes a single element of a sampled image array.
+    pub fn write_sampled_image_element(&mut self, binding: u32, element: u32, image: VkImageView, layout: VkImageLayout) {
+        self.write_sampled_image(binding, image, layout);
+        self.writes.last_mut().expect("write_sampled_image always adds a write").dstArrayElement = element;
+    }
+
     #[inline(always)]
     pub fn write_combined_image_sampler(&mut self, binding: u32, image: VkImageView, sampler: VkSampler, layout: VkImageLayout) {
         self.write_image(binding, image, sampler, layout, VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER);
@@ -191,7 +230,7 @@ impl DescriptorWriter {
         draw_write.dstBinding      = binding;
     	draw_write.descriptorCount = 1;
     	draw_write.descriptorType  = descriptor_type;
-    	draw_write.pImageInfo      = self.image_infos.back().expect("There should be an element here -_-");
+    	// pImageInfo is filled in by update_set(), see the note there
 
         self.writes.push(draw_write);
     }
@@ -216,7 +255,7 @@ impl DescriptorWriter {
         draw_write.dstBinding      = binding;
         draw_write.descriptorCount = 1;
         draw_write.descriptorType  = descriptor_type;
-        draw_write.pBufferInfo     = self.buffer_infos.back().expect("There should be an element here -_-");
+        // pBufferInfo is filled in by update_set(), see the note there
 
         self.writes.push(draw_write);
     }
@@ -228,8 +267,24 @@ impl DescriptorWriter {
     }
 
     pub fn update_set(&mut self, device: &Device, set: VkDescriptorSet) {
+        // The info pointers are resolved here rather than when the write is recorded. Growing a VecDeque moves
+        // its elements, so pointers taken while writing more than a handful of descriptors would dangle.
+        let mut image_infos  = self.image_infos.iter();
+        let mut buffer_infos = self.buffer_infos.iter();
+
         for write in &mut self.writes {
             write.dstSet = set;
+
+            let is_buffer = write.descriptorType == VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER
+                || write.descriptorType == VK_DESCRIPTOR_TYPE_STORAGE_BUFFER
+                || write.descriptorType == VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER_DYNAMIC
+                || write.descriptorType == VK_DESCRIPTOR_TYPE_STORAGE_BUFFER_DYNAMIC;
+
+            if is_buffer {
+                write.pBufferInfo = buffer_infos.next().expect("There should be an element here -_-");
+            } else {
+                write.pImageInfo  = image_infos.next().expect("There should be an element here -_-");
+            }
         }
 
         device.update_descriptor_sets(&self.writes);
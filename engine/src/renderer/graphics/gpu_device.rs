@@ -1,5 +1,7 @@
 use crate::util::ffi::*;
 use crate::window::NativeSurface;
+use crate::renderer::adapter::{self, AdapterInfo, AdapterType, AdapterFeature, MemoryHeapInfo};
+use crate::renderer::error::RenderError;
 
 use vendor::vulkan::*;
 use super::consts;
@@ -21,11 +23,11 @@ use std::rc::Rc;
 //
 // TODO:
 // - Device Extensions require modifying 2 locations (is device valid, and device creation), which is not great. centralize this into an array.
-// - HDR Support (select_surface_format)
 //
 
+#[derive(Clone, Copy, Debug)]
 pub struct Features {
-    pub prefer_hdr: bool,
+    pub prefer_hdr: bool, // present to an HDR10 swapchain when the surface supports one
 }
 
 impl Default for Features {
@@ -41,6 +43,7 @@ pub struct CreateInfo {
     pub surface:          NativeSurface,
     pub software_version: u32,
     pub software_name:    String,
+    pub gpu_index:        Option<usize>, // if None, the highest scoring GPU is chosen
 }
 
 pub struct Instance {
@@ -48,6 +51,7 @@ pub struct Instance {
     glb_fns:              util::GlobalFnTable,
     inst_fns:             util::InstanceFnTable,
     handle:               VkInstance,
+    debug_messenger:      Option<VkDebugUtilsMessengerEXT>,
     // meta information about the VkInstance
     requested_layers:     Vec<CString>,
     requested_extensions: Vec<CString>,
@@ -79,6 +83,7 @@ struct GpuFnTable {
 pub struct Gpu {
     fns:                                    GpuFnTable,
     pub handle:                             VkPhysicalDevice,
+    pub info:                               AdapterInfo,
     pub properties:                         VkPhysicalDeviceProperties,
     pub features:                           VkPhysicalDeviceFeatures,
     pub memory_properties:                  VkPhysicalDeviceMemoryProperties,
@@ -106,6 +111,21 @@ pub struct Device {
     //displays: Vec<Rc<Display>>,
     gpu:      Rc<Gpu>,
     //display: Rc<Display>,
+
+    features: Features,
+
+    supports_gpu_driven_rendering: bool,
+}
+
+// The parts of a Device created after its instance and surface, see Device::create_logical_device.
+struct LogicalDevice {
+    fns:       util::DeviceFnTable,
+    handle:    VkDevice,
+    allocator: VmaAllocator,
+    gpus:      Vec<Rc<Gpu>>,
+    gpu:       Rc<Gpu>,
+
+    supports_gpu_driven_rendering: bool,
 }
 
 unsafe extern "C" fn debug_callback(
@@ -127,13 +147,30 @@ unsafe extern "C" fn debug_callback(
     VK_FALSE
 }
 
+fn format_api_version(version: u32) -> String {
+    return format!("{}.{}.{}", (version >> 22) & 0x7F, (version >> 12) & 0x3FF, version & 0xFFF);
+}
+
+// Vendors are free to pack the driver version however they like, so decode the known exceptions.
+fn format_driver_version(vendor_id: u32, version: u32) -> String {
+    if vendor_id == 0x10DE { // NVIDIA
+        return format!("{}.{}.{}.{}", (version >> 22) & 0x3FF, (version >> 14) & 0xFF, (version >> 6) & 0xFF, version & 0x3F);
+    }
+
+    if cfg!(target_os = "windows") && vendor_id == 0x8086 { // Intel
+        return format!("{}.{}", version >> 14, version & 0x3FFF);
+    }
+
+    return format!("{}.{}.{}", version >> 22, (version >> 12) & 0x3FF, version & 0xFFF);
+}
+
 impl Instance {
-    pub fn new(software_version: u32, software_name: &str) -> Result<Instance, String> {
+    pub fn new(software_version: u32, software_name: &str) -> Result<Instance, RenderError> {
         // load vulkan functions
         //
         let global_fns: util::GlobalFnTable = match util::load_vulkan_proc_addr() {
             Ok(fns) => fns,
-            Err(reason) => panic!("Failed to load vulkan library: {}", reason),
+            Err(reason) => return Err(RenderError::Initialization(format!("Failed to load vulkan library: {}", reason))),
         };
 
         // Build list of validation layers, if enabled and available
@@ -145,7 +182,7 @@ impl Instance {
         let mut instance_layers        = Vec::<*const std::os::raw::c_char>::new();
 
         if consts::ENABLE_DEBUG_LAYER {
-            let validation_layers = global_fns.enumerate_instance_layers();
+            let validation_layers = global_fns.enumerate_instance_layers()?;
 
             for layer in validation_layers.iter() {
                 if char_array_as_cstr!(layer.layerName) == desired_validation_layer {
@@ -189,12 +226,13 @@ impl Instance {
         let mut instance_ext_strings = Vec::<CString>::new();
         let mut instance_exts        = Vec::<*const std::os::raw::c_char>::new();
 
-        let available_extensions = global_fns.enumerate_instance_extensions();
+        let available_extensions = global_fns.enumerate_instance_extensions()?;
 
         // We want 2 specific platform extensions:
         // 1. Surface KHR extension
         // 2. Platform Surface KHR extension
         // 3. Physical Device Properties 2
+        // And the swapchain color space extension when it is available, the HDR10 color space needs it.
         let mut surface_ext_found          = false;
         let mut platform_surface_ext_found = false;
         let mut device_props2_ext_found    = false;
@@ -205,13 +243,13 @@ impl Instance {
                 match session_type.as_str() {
                     "x11"     => byte_array_as_cstr!(VK_KHR_XLIB_SURFACE_EXTENSION_NAME),
                     "wayland" => byte_array_as_cstr!(VK_KHR_WAYLAND_SURFACE_EXTENSION_NAME),
-                    _         => return Err("Unsupported window manager".to_string()),
+                    _         => return Err(RenderError::Initialization("Unsupported window manager".to_string())),
                 }
             } else {
-                return Err("Unsupported window manager".to_string());
+                return Err(RenderError::Initialization("Unsupported window manager".to_string()));
             }
         } else {
-            return Err("Unsupported operating system".to_string());
+            return Err(RenderError::Initialization("Unsupported operating system".to_string()));
         };
 
         // Verify the extensions are available
@@ -239,6 +277,11 @@ impl Instance {
                 instance_ext_strings.push(string);
 
                 platform_surface_ext_found = true;
+            } else if ext_c_str == byte_array_as_cstr!(VK_EXT_SWAPCHAIN_COLOR_SPACE_EXTENSION_NAME) {
+                let string: CString = ext_c_str.into();
+
+                instance_exts.push(string.as_ptr());
+                instance_ext_strings.push(string);
             } else if consts::ENABLE_DEBUG_LAYER {
                 if ext_c_str == byte_array_as_cstr!(VK_EXT_DEBUG_UTILS_EXTENSION_NAME) {
                     let string: CString = ext_c_str.into();
@@ -251,15 +294,15 @@ impl Instance {
 
         // These extensions are required for rendering to the Swapchain, so failing to find them is a fatal error.
         if !surface_ext_found {
-            return Err("Surface extension for Vulkan not found".to_string());
+            return Err(RenderError::Initialization("Surface extension for Vulkan not found".to_string()));
         }
 
         if !device_props2_ext_found {
-            return Err("Failed to find instance extension: VK_KHR_GET_PHYSICAL_DEVICE_PROPERTIES_2_EXTENSION_NAME".to_string());
+            return Err(RenderError::Initialization("Failed to find instance extension: VK_KHR_GET_PHYSICAL_DEVICE_PROPERTIES_2_EXTENSION_NAME".to_string()));
         }
 
         if !platform_surface_ext_found {
-            return Err("Platform surface extension for Vulkan not found".to_string());
+            return Err(RenderError::Initialization("Platform surface extension for Vulkan not found".to_string()));
         }
 
         // Create the VkInstance
@@ -295,7 +338,7 @@ impl Instance {
         let instance_fns: util::InstanceFnTable =
             match util::load_instance_functions(&global_fns, instance) {
                 Ok(fns) => fns,
-                Err(reason) => panic!("Failed to load vulkan library: {}", reason),
+                Err(reason) => return Err(RenderError::Initialization(format!("Failed to load vulkan instance functions: {}", reason))),
             };
 
         // Create the debug messenger
@@ -330,16 +373,28 @@ impl Instance {
             glb_fns: global_fns,
             inst_fns: instance_fns,
             handle: instance,
+            debug_messenger,
             requested_layers: instance_layer_strings,
             requested_extensions: instance_ext_strings,
             software_name,
             engine_name,
         })
     }
+
+    pub fn destroy(&mut self) {
+        if let Some(messenger) = self.debug_messenger.take() {
+            if let Some(destroy_debug_messenger) = self.inst_fns.destroy_debug_messenger {
+                call!(destroy_debug_messenger, self.handle, messenger, ptr::null());
+            }
+        }
+
+        call!(self.inst_fns.destroy_instance, self.handle, ptr::null());
+        self.handle = ptr::null_mut();
+    }
 }
 
 impl Surface {
-    pub fn new(instance: &Instance, native_surface: NativeSurface) -> Result<Surface, String> {
+    pub fn new(instance: &Instance, native_surface: NativeSurface) -> Result<Surface, RenderError> {
         let result = if cfg!(target_os = "linux") {
             if let NativeSurface::Wayland(native) = native_surface {
                 let info = VkWaylandSurfaceCreateInfoKHR {
@@ -382,22 +437,27 @@ impl Surface {
                     handle: unsafe { surf.assume_init() },
                 })
             } else {
-                panic!("Invalid native surface for linux.");
+                Err(RenderError::Initialization("Invalid native surface for linux".to_string()))
             }
         } else {
-            Err("unsupported operating system".to_string())
+            Err(RenderError::Initialization("Unsupported operating system".to_string()))
         };
 
         result
     }
+
+    pub fn destroy(&mut self, instance: &Instance) {
+        call!(instance.inst_fns.destroy_surface, instance.handle, self.handle, ptr::null());
+        self.handle = ptr::null_mut();
+    }
 }
 
 impl Gpu {
     fn get_queue_families(
         instance: &Instance,
-        surface: &Surface,
-        gpu: VkPhysicalDevice,
-    ) -> GpuQueueFamilies {
+        surface:  Option<&Surface>,
+        gpu:      VkPhysicalDevice,
+    ) -> Result<GpuQueueFamilies, RenderError> {
         let mut result = GpuQueueFamilies::default();
 
         let queue_properties = instance.inst_fns.enumerate_gpu_queue_family_properties(gpu);
@@ -422,30 +482,32 @@ impl Gpu {
             }
 
             // Does this queue family support the present queue? If so, yoink it.
-            let mut supports_present: VkBool32 = VK_FALSE;
-            util::call_throw!(
-                instance.inst_fns.get_gpu_surface_support,
-                gpu,
-                queue_family_index,
-                surface.handle,
-                &mut supports_present
-            );
+            if let Some(surface) = surface {
+                let mut supports_present: VkBool32 = VK_FALSE;
+                util::call_throw!(
+                    instance.inst_fns.get_gpu_surface_support,
+                    gpu,
+                    queue_family_index,
+                    surface.handle,
+                    &mut supports_present
+                );
 
-            if supports_present == VK_TRUE {
-                result.present = Some(queue_family_index);
+                if supports_present == VK_TRUE {
+                    result.present = Some(queue_family_index);
+                }
             }
 
             queue_family_index += 1;
         }
 
-        return result;
+        return Ok(result);
     }
 
     pub fn query_swapchain_capabilities(
         instance: &Instance,
         surface:  &Surface,
         gpu:       VkPhysicalDevice,
-    ) -> SwapchainSupportInfo {
+    ) -> Result<SwapchainSupportInfo, RenderError> {
         // Surface capabilities
         let mut capabilities_unsafe = MaybeUninit::<VkSurfaceCapabilitiesKHR>::uninit();
         util::call_throw!(
@@ -458,83 +520,162 @@ impl Gpu {
         // Surface formats
         let formats = instance
             .inst_fns
-            .enumerate_gpu_surface_formats(gpu, surface.handle);
+            .enumerate_gpu_surface_formats(gpu, surface.handle)?;
 
         // Present modes
         let present_modes = instance
             .inst_fns
-            .enumerate_gpu_present_modes(gpu, surface.handle);
+            .enumerate_gpu_present_modes(gpu, surface.handle)?;
 
-        return SwapchainSupportInfo {
+        return Ok(SwapchainSupportInfo {
             capabilities: unsafe { capabilities_unsafe.assume_init() },
             formats,
             present_modes,
-        };
+        });
     }
 
-    fn does_gpu_meet_requirements(
-        instance:     &Instance,
-        surface:      &Surface,
-        gpu:           VkPhysicalDevice,
+    fn query_adapter_info(
+        instance:    &Instance,
+        surface:      Option<&Surface>,
+        index:        usize,
+        gpu:          VkPhysicalDevice,
+        properties:  &VkPhysicalDeviceProperties,
         gpu_features: &VkPhysicalDeviceFeatures,
-    ) -> bool {
-        let queue_families = Self::get_queue_families(instance, surface, gpu);
-
-        let has_present = queue_families.present.is_some();
-        let has_graphics = queue_families.graphics.is_some();
-        let has_transfer = queue_families.transfer.is_some();
-        let has_compute = queue_families.compute.is_some();
-        if !has_present || !has_graphics {
-            println!("Missing required queues");
-            return false;
+        memory:      &VkPhysicalDeviceMemoryProperties,
+    ) -> Result<AdapterInfo, RenderError> {
+        let mut rejection_reasons = Vec::<String>::new();
+
+        if properties.apiVersion < consts::VK_API_VERSION {
+            rejection_reasons.push(format!("Requires Vulkan 1.3, but the driver only supports {}", format_api_version(properties.apiVersion)));
         }
 
-        let swapchain_info = Self::query_swapchain_capabilities(instance, surface, gpu);
-        if swapchain_info.formats.is_empty() || swapchain_info.present_modes.is_empty() {
-            // missing presentable surface
-            println!("Missing presentation surface");
-            return false;
+        // Make sure the gpu has the queues we need
+        let queue_families = Self::get_queue_families(instance, surface, gpu)?;
+        if queue_families.graphics.is_none() {
+            rejection_reasons.push("Missing a graphics queue".to_string());
         }
 
-        // Check for sampler anisotropy
-        const REQUIRE_ANISOTROPY: bool = true;
-        if REQUIRE_ANISOTROPY && gpu_features.samplerAnisotropy != VK_TRUE {
-            println!("Requested anisotropy, but not found.");
-            return false;
+        // Presentation can only be checked when we have a surface to present to
+        if let Some(surface) = surface {
+            if queue_families.present.is_none() {
+                rejection_reasons.push("Missing a queue that can present to the window surface".to_string());
+            }
+
+            let swapchain_info = Self::query_swapchain_capabilities(instance, surface, gpu)?;
+            if swapchain_info.formats.is_empty() || swapchain_info.present_modes.is_empty() {
+                rejection_reasons.push("Missing a presentable surface format or present mode".to_string());
+            }
         }
 
-        // Make sure the gpu supports all required extensions
-        let mut swapchain_extension_found = false;
-        let mut semaphore_timelines_found = false;
-        let mut dynamic_rendering_found   = false;
+        // Query the features we enable at device creation. Same chain as Device::new.
+        use core::ffi::c_void;
 
-        let extensions = instance.inst_fns.enumerate_device_extensions(gpu);
-        for extension in extensions {
-            let ext_c_str = char_array_as_cstr!(extension.extensionName);
+        let mut feature_dyn_rendering = VkPhysicalDeviceDynamicRenderingFeatures::default();
+
+        let mut feature_vulkan12 = VkPhysicalDeviceVulkan12Features::default();
+        feature_vulkan12.pNext = &mut feature_dyn_rendering as *mut _ as *mut c_void;
+
+        let mut feature_sync2 = VkPhysicalDeviceSynchronization2Features::default();
+        feature_sync2.pNext = &mut feature_vulkan12 as *mut _ as *mut c_void;
+
+        let mut features2 = VkPhysicalDeviceFeatures2 {
+            pNext: &mut feature_sync2 as *mut _ as *mut c_void,
+            ..Default::default()
+        };
+
+        call!(instance.inst_fns.get_gpu_features2, gpu, &mut features2);
+
+        let make_feature = |name: &'static str, supported: VkBool32, required: bool| -> AdapterFeature {
+            return AdapterFeature{ name, supported: supported == VK_TRUE, required };
+        };
+
+        let features = vec![
+            make_feature("samplerAnisotropy",                            gpu_features.samplerAnisotropy,                                true),
+            make_feature("dynamicRendering",                             feature_dyn_rendering.dynamicRendering,                        true),
+            make_feature("synchronization2",                             feature_sync2.synchronization2,                                true),
+            make_feature("bufferDeviceAddress",                          feature_vulkan12.bufferDeviceAddress,                          true),
+            make_feature("timelineSemaphore",                            feature_vulkan12.timelineSemaphore,                            true),
+            make_feature("runtimeDescriptorArray",                       feature_vulkan12.runtimeDescriptorArray,                       true),
+            make_feature("descriptorBindingPartiallyBound",              feature_vulkan12.descriptorBindingPartiallyBound,              true),
+            make_feature("descriptorBindingSampledImageUpdateAfterBind", feature_vulkan12.descriptorBindingSampledImageUpdateAfterBind, true),
+            make_feature("descriptorBindingUpdateUnusedWhilePending",    feature_vulkan12.descriptorBindingUpdateUnusedWhilePending,    true),
+            make_feature("shaderSampledImageArrayDynamicIndexing",       gpu_features.shaderSampledImageArrayDynamicIndexing,           true),
+            make_feature("fillModeNonSolid",                             gpu_features.fillModeNonSolid,                                 false),
+            make_feature("wideLines",                                    gpu_features.wideLines,                                        false),
+            make_feature("depthClamp",                                   gpu_features.depthClamp,                                       false),
+            make_feature("sampleRateShading",                            gpu_features.sampleRateShading,                                false),
+            make_feature("multiDrawIndirect",                            gpu_features.multiDrawIndirect,                                false),
+            make_feature("drawIndirectFirstInstance",                    gpu_features.drawIndirectFirstInstance,                        false),
+            make_feature("drawIndirectCount",                            feature_vulkan12.drawIndirectCount,                            false),
+            make_feature("shaderInt64",                                  gpu_features.shaderInt64,                                      false),
+            make_feature("textureCompressionBC",                         gpu_features.textureCompressionBC,                             false),
+        ];
 
-            if ext_c_str == byte_array_as_cstr!(VK_KHR_SWAPCHAIN_EXTENSION_NAME) {
-                swapchain_extension_found = true;
-            } else if ext_c_str == byte_array_as_cstr!(VK_KHR_TIMELINE_SEMAPHORE_EXTENSION_NAME) {
-                semaphore_timelines_found = true;
-            } else if ext_c_str == byte_array_as_cstr!(VK_KHR_DYNAMIC_RENDERING_EXTENSION_NAME) {
-                dynamic_rendering_found = true;
+        for feature in &features {
+            if feature.required && !feature.supported {
+                rejection_reasons.push(format!("Missing required feature: {}", feature.name));
             }
         }
 
-        if !swapchain_extension_found || !semaphore_timelines_found || !dynamic_rendering_found {
-            // could not find required extensions
-            println!("Could not find required extensions");
-            return false;
+        // Make sure the gpu supports all required extensions
+        let extensions: Vec<String> = instance.inst_fns.enumerate_device_extensions(gpu)?
+            .iter()
+            .map(|extension| char_array_as_cstr!(extension.extensionName).to_string_lossy().into_owned())
+            .collect();
+
+        let required_extensions = [
+            byte_array_as_cstr!(VK_KHR_SWAPCHAIN_EXTENSION_NAME),
+            byte_array_as_cstr!(VK_KHR_TIMELINE_SEMAPHORE_EXTENSION_NAME),
+            byte_array_as_cstr!(VK_KHR_DYNAMIC_RENDERING_EXTENSION_NAME),
+        ];
+
+        for required in required_extensions {
+            let required_str = required.to_string_lossy();
+            if !extensions.iter().any(|ext| *ext == required_str) {
+                rejection_reasons.push(format!("Missing required extension: {}", required_str));
+            }
+        }
+
+        // Memory heaps
+        let mut memory_heaps = Vec::<MemoryHeapInfo>::with_capacity(memory.memoryHeapCount as usize);
+        for i in 0..memory.memoryHeapCount {
+            let heap = &memory.memoryHeaps[i as usize];
+            memory_heaps.push(MemoryHeapInfo{
+                size:         heap.size,
+                device_local: (heap.flags & VK_MEMORY_HEAP_DEVICE_LOCAL_BIT) != 0,
+            });
         }
 
-        true
+        let adapter_type = match properties.deviceType {
+            VK_PHYSICAL_DEVICE_TYPE_DISCRETE_GPU   => AdapterType::Discrete,
+            VK_PHYSICAL_DEVICE_TYPE_INTEGRATED_GPU => AdapterType::Integrated,
+            VK_PHYSICAL_DEVICE_TYPE_VIRTUAL_GPU    => AdapterType::Virtual,
+            VK_PHYSICAL_DEVICE_TYPE_CPU            => AdapterType::Cpu,
+            _                                      => AdapterType::Other,
+        };
+
+        return Ok(AdapterInfo{
+            index,
+            name:              char_array_as_cstr!(properties.deviceName).to_string_lossy().into_owned(),
+            adapter_type,
+            vendor_id:         properties.vendorID,
+            device_id:         properties.deviceID,
+            driver_version:    format_driver_version(properties.vendorID, properties.driverVersion),
+            api_version:       format_api_version(properties.apiVersion),
+            memory_heaps,
+            features,
+            extensions,
+            rejection_reasons,
+        });
     }
 
-    fn enumerate_gpus(instance: &Instance, surface: &Surface) -> Vec<Rc<Gpu>> {
+    /// Enumerates every GPU on the system, including the ones that don't meet the engine's requirements.
+    /// Check gpu.info.rejection_reasons to see if a gpu can be used.
+    pub fn enumerate_gpus(instance: &Instance, surface: Option<&Surface>) -> Result<Vec<Rc<Gpu>>, RenderError> {
         let mut result = Vec::<Rc<Gpu>>::new();
 
-        let vk_gpus = instance.inst_fns.enumerate_gpus(instance.handle);
-        for gpu in vk_gpus.into_iter() {
+        let vk_gpus = instance.inst_fns.enumerate_gpus(instance.handle)?;
+        for (index, gpu) in vk_gpus.into_iter().enumerate() {
             let mut properties_unsafe = MaybeUninit::<VkPhysicalDeviceProperties>::uninit();
             call!(
                 instance.inst_fns.get_gpu_properties,
@@ -576,9 +717,13 @@ impl Gpu {
                 }
             }
 
-            if !Gpu::does_gpu_meet_requirements(instance, surface, gpu, &features) {
-                continue;
-            }
+            let info = Gpu::query_adapter_info(instance, surface, index, gpu, &properties, &features, &memory)?;
+
+            let swapchain_support_info = if let (Some(surf), true) = (surface, info.is_suitable()) {
+                Gpu::query_swapchain_capabilities(instance, surf, gpu)?
+            } else {
+                SwapchainSupportInfo::default()
+            };
 
             let gpu_fns = GpuFnTable{
                 get_gpu_format_properties: instance.inst_fns.get_gpu_format_properties,
@@ -587,29 +732,30 @@ impl Gpu {
             let adapter = Rc::new(Gpu {
                 fns: gpu_fns,
                 handle: gpu,
+                info,
                 properties,
                 features,
                 memory_properties: memory,
-                queue_infos: Gpu::get_queue_families(instance, surface, gpu),
-                swapchain_support_info: Gpu::query_swapchain_capabilities(instance, surface, gpu),
+                queue_infos: Gpu::get_queue_families(instance, surface, gpu)?,
+                swapchain_support_info,
                 supports_device_local_host_visible,
             });
 
             result.push(adapter);
         }
 
-        result
+        Ok(result)
     }
 
-    pub fn require_portability_subset(&self, instance: &Instance) -> bool {
-        let extensions = instance.inst_fns.enumerate_device_extensions(self.handle);
+    pub fn require_portability_subset(&self, instance: &Instance) -> Result<bool, RenderError> {
+        let extensions = instance.inst_fns.enumerate_device_extensions(self.handle)?;
         for extension in extensions {
             let ext_c_str = char_array_as_cstr!(extension.extensionName);
             if ext_c_str == byte_array_as_cstr!(consts::VK_KHR_PORTABILITY_SUBSET_EXTENSION_NAME) {
-                return true;
+                return Ok(true);
             }
         }
-        return false;
+        return Ok(false);
     }
 
     pub fn select_surface_format(&self, prefer_hdr: bool) -> VkSurfaceFormatKHR {
@@ -623,8 +769,8 @@ impl Gpu {
             return false;
         };
 
-        const SDR_FORMAT: VkSurfaceFormatKHR = VkSurfaceFormatKHR{ format: VK_FORMAT_B8G8R8A8_UNORM, colorSpace: VK_COLOR_SPACE_SRGB_NONLINEAR_KHR };
-        const HDR_FORMAT: VkSurfaceFormatKHR = VkSurfaceFormatKHR{ format: VK_FORMAT_UNDEFINED,      colorSpace: VK_COLOR_SPACE_MAX_ENUM_KHR       };
+        const SDR_FORMAT: VkSurfaceFormatKHR = VkSurfaceFormatKHR{ format: VK_FORMAT_B8G8R8A8_UNORM,           colorSpace: VK_COLOR_SPACE_SRGB_NONLINEAR_KHR };
+        const HDR_FORMAT: VkSurfaceFormatKHR = VkSurfaceFormatKHR{ format: VK_FORMAT_A2B10G10R10_UNORM_PACK32, colorSpace: VK_COLOR_SPACE_HDR10_ST2084_EXT };
 
         if prefer_hdr && has_format(&self.swapchain_support_info.formats, HDR_FORMAT) {
             return HDR_FORMAT;
@@ -667,27 +813,78 @@ impl Gpu {
 
         return depth_format;
     }
+
+    /// The highest sample count, up to `requested`, that both color and depth attachments support.
+    pub fn select_sample_count(&self, requested: u32) -> VkSampleCountFlagBits {
+        let limits    = &self.properties.limits;
+        let supported = limits.framebufferColorSampleCounts & limits.framebufferDepthSampleCounts;
+
+        const SAMPLE_CANDIDATES: [VkSampleCountFlagBits; 4] = [
+            VK_SAMPLE_COUNT_8_BIT,
+            VK_SAMPLE_COUNT_4_BIT,
+            VK_SAMPLE_COUNT_2_BIT,
+            VK_SAMPLE_COUNT_1_BIT,
+        ];
+
+        for candidate in SAMPLE_CANDIDATES {
+            if candidate <= requested && (supported & candidate) != 0 {
+                return candidate;
+            }
+        }
+
+        return VK_SAMPLE_COUNT_1_BIT;
+    }
 }
 
 impl Device {
-    pub fn new(create_info: CreateInfo) -> Device {
-        let instance = match Instance::new(
+    pub fn new(create_info: CreateInfo) -> Result<Device, RenderError> {
+        let mut instance = Instance::new(
             create_info.software_version,
             create_info.software_name.as_str(),
-        ) {
-            Ok(inst) => inst,
-            Err(reason) => panic!("Failed to create vulkan instance: {}", reason),
+        )?;
+
+        let mut surface = match Surface::new(&instance, create_info.surface) {
+            Ok(surface) => surface,
+            Err(error)  => {
+                instance.destroy();
+                return Err(error);
+            },
         };
 
-        let surface = match Surface::new(&instance, create_info.surface) {
-            Ok(surf) => surf,
-            Err(reason) => panic!("Failed to create vulkan surface: {}", reason),
+        // create_logical_device releases whatever it created itself when it fails, the instance and surface are
+        // released here.
+        let logical_device = match Self::create_logical_device(&instance, &surface, create_info.gpu_index) {
+            Ok(logical_device) => logical_device,
+            Err(error)         => {
+                surface.destroy(&instance);
+                instance.destroy();
+                return Err(error);
+            },
         };
 
-        let gpus = Gpu::enumerate_gpus(&instance, &surface);
-        assert!(gpus.len() > 0);
+        println!("Finished created Vulkan Device.");
+
+        return Ok(Device {
+            fns:       logical_device.fns,
+            handle:    logical_device.handle,
+            allocator: logical_device.allocator,
+            instance,
+            surface,
+            gpus:      logical_device.gpus,
+            gpu:       logical_device.gpu,
+            features:  create_info.features,
+            supports_gpu_driven_rendering: logical_device.supports_gpu_driven_rendering,
+        });
+    }
+
+    fn create_logical_device(instance: &Instance, surface: &Surface, gpu_index: Option<usize>) -> Result<LogicalDevice, RenderError> {
+        let gpus = Gpu::enumerate_gpus(instance, Some(surface))?;
+        let chosen_gpu: Rc<Gpu> = match Self::select_gpu(&gpus, gpu_index) {
+            Ok(gpu) => gpu,
+            Err(reason) => return Err(RenderError::NoSuitableGpu(reason)),
+        };
 
-        let chosen_gpu: Rc<Gpu> = Self::select_gpu(&gpus, None);
+        println!("Selected GPU {}: {} ({:?}, driver {})", chosen_gpu.info.index, chosen_gpu.info.name, chosen_gpu.info.adapter_type, chosen_gpu.info.driver_version);
 
         //---------------------------------------------------------------------------------------//
         // Create Logical Device
@@ -735,41 +932,52 @@ impl Device {
         //   - Synchronization2
         //   - Timeline Semaphores
         //   - Dynamic Rendering
+        //   - Descriptor indexing, for the bindless texture array (see material_system.rs)
+        //   - Indirect draws with a GPU written draw count, when supported (see supports_gpu_driven_rendering)
         use core::ffi::c_void;
 
+        let supports_gpu_driven_rendering = chosen_gpu.info.has_feature("multiDrawIndirect")
+            && chosen_gpu.info.has_feature("drawIndirectFirstInstance")
+            && chosen_gpu.info.has_feature("drawIndirectCount");
+
         let mut feature_dyn_rendering = VkPhysicalDeviceDynamicRenderingFeatures::default(); //VK_KHR_dynamic_rendering
         feature_dyn_rendering.dynamicRendering = VK_TRUE;
 
         let feature_dyn_rendering_ptr: *mut VkPhysicalDeviceDynamicRenderingFeatures = &mut feature_dyn_rendering;
 
-        let mut feature_device_addr = VkPhysicalDeviceBufferDeviceAddressFeatures::default();
-        feature_device_addr.bufferDeviceAddress = VK_TRUE;
-        feature_device_addr.pNext = feature_dyn_rendering_ptr as *mut c_void;
+        // The Vulkan 1.2 features can't be chained next to the individual structs they replace (device address,
+        // timeline semaphores), so they are all enabled through this one.
+        let mut feature_vulkan12 = VkPhysicalDeviceVulkan12Features::default();
+        feature_vulkan12.bufferDeviceAddress                          = VK_TRUE;
+        feature_vulkan12.timelineSemaphore                            = VK_TRUE;
+        feature_vulkan12.drawIndirectCount                            = if supports_gpu_driven_rendering { VK_TRUE } else { VK_FALSE };
+        feature_vulkan12.runtimeDescriptorArray                       = VK_TRUE;
+        feature_vulkan12.descriptorBindingPartiallyBound              = VK_TRUE;
+        feature_vulkan12.descriptorBindingSampledImageUpdateAfterBind = VK_TRUE;
+        feature_vulkan12.descriptorBindingUpdateUnusedWhilePending    = VK_TRUE;
+        feature_vulkan12.pNext = feature_dyn_rendering_ptr as *mut c_void;
 
-        let feature_device_addr_ptr: *mut VkPhysicalDeviceBufferDeviceAddressFeatures = &mut feature_device_addr;
+        let feature_vulkan12_ptr: *mut VkPhysicalDeviceVulkan12Features = &mut feature_vulkan12;
 
         let mut feature_sync2 = VkPhysicalDeviceSynchronization2Features::default();
         feature_sync2.synchronization2 = VK_TRUE;
-        feature_sync2.pNext = feature_device_addr_ptr as *mut c_void;
+        feature_sync2.pNext = feature_vulkan12_ptr as *mut c_void;
 
         let feature_sync2_ptr: *mut VkPhysicalDeviceSynchronization2Features =
             &mut feature_sync2;
 
-        let mut feature_timeline = VkPhysicalDeviceTimelineSemaphoreFeatures::default();
-        feature_timeline.timelineSemaphore = VK_TRUE;
-        feature_timeline.pNext = feature_sync2_ptr as *mut c_void;
-
-        let feature_timeline_ptr: *mut VkPhysicalDeviceTimelineSemaphoreFeatures =
-            &mut feature_timeline;
-
-        //let enabled_features = VkPhysicalDeviceFeatures::default();
-        // left here in case I want to override the defaults in the future
-
         let mut enabled_features2 = VkPhysicalDeviceFeatures2 {
-            pNext: feature_timeline_ptr as *mut c_void,
+            pNext: feature_sync2_ptr as *mut c_void,
             ..Default::default()
         };
 
+        enabled_features2.features.shaderSampledImageArrayDynamicIndexing = VK_TRUE;
+
+        if supports_gpu_driven_rendering {
+            enabled_features2.features.multiDrawIndirect         = VK_TRUE;
+            enabled_features2.features.drawIndirectFirstInstance = VK_TRUE;
+        }
+
         let enabled_features2_ptr: *mut VkPhysicalDeviceFeatures2 = &mut enabled_features2;
 
         // 3. Build the list of device extensions
@@ -788,7 +996,7 @@ impl Device {
         extension_list_strings.push(swapchain_ext_string);
         extension_list_strings.push(semaphore_ext_string);
 
-        if chosen_gpu.require_portability_subset(&instance) {
+        if chosen_gpu.require_portability_subset(instance)? {
             let portability_ext_string: CString =
                 byte_array_as_cstr!(consts::VK_KHR_PORTABILITY_SUBSET_EXTENSION_NAME).into();
             extension_list.push(portability_ext_string.as_ptr());
@@ -817,8 +1025,19 @@ impl Device {
         // Load Device Functions
 
         let device_fns =
-            util::load_device_functions(&instance.glb_fns, instance.handle, device_handle)
-                .expect("Failed to load Vulkan Device level functions");
+            match util::load_device_functions(&instance.glb_fns, instance.handle, device_handle) {
+                Ok(fns) => fns,
+                Err(reason) => {
+                    // Without the device table, vkDestroyDevice has to be looked up on its own to release the device.
+                    let destroy_device = unsafe { (instance.inst_fns.get_device_procaddr)(device_handle, c"vkDestroyDevice".as_ptr()) };
+                    if destroy_device.is_some() {
+                        let destroy_device: FN_vkDestroyDevice = unsafe { std::mem::transmute_copy(&destroy_device) };
+                        call!(destroy_device, device_handle, ptr::null());
+                    }
+
+                    return Err(RenderError::Initialization(format!("Failed to load vulkan device functions: {}", reason)));
+                },
+            };
 
         //---------------------------------------------------------------------------------------//
         // Load the Vulkan Memory Allocator
@@ -864,53 +1083,60 @@ impl Device {
         vma_ci.pVulkanFunctions = &vma_fns;
 
         let mut vma_allocator: MaybeUninit<_> = MaybeUninit::<VmaAllocator>::uninit();
-        call_throw!(vmaCreateAllocator, &vma_ci, vma_allocator.as_mut_ptr());
+        let vma_result = call_nothrow!(vmaCreateAllocator, &vma_ci, vma_allocator.as_mut_ptr());
+        if vma_result < 0 {
+            call!(device_fns.destroy_device, device_handle, ptr::null());
+            return Err(RenderError::from_vk_result("vmaCreateAllocator", vma_result));
+        }
 
         //---------------------------------------------------------------------------------------//
         // (Finally) Return the Device
-        println!("Finished created Vulkan Device.");
 
-        return Device {
+        return Ok(LogicalDevice {
             fns:       device_fns,
             handle:    device_handle,
             allocator: unsafe { vma_allocator.assume_init() },
-            instance,
-            surface,
             gpus,
-            gpu: chosen_gpu,
-        };
+            gpu:       chosen_gpu,
+            supports_gpu_driven_rendering,
+        });
     }
 
     pub fn destroy(&mut self) {
         call!(vmaDestroyAllocator, self.allocator);
         call!(self.fns.destroy_device, self.handle, ptr::null());
+
+        // The surface and instance are owned by the device, so a new device can be created for the same window.
+        self.surface.destroy(&self.instance);
+        self.instance.destroy();
     }
 
-    /// If None is passed as gpu_index, then the first available Discrete GPU is chosen.
-    pub fn select_gpu(gpu_list: &Vec<Rc<Gpu>>, gpu_index: Option<usize>) -> Rc<Gpu> {
-        assert!(gpu_list.len() > 0);
+    /// If None is passed as gpu_index, then the highest scoring GPU is chosen (see adapter::score_adapter).
+    /// gpu_index refers to the adapter index reported by adapter::enumerate_adapters.
+    pub fn select_gpu(gpu_list: &Vec<Rc<Gpu>>, gpu_index: Option<usize>) -> Result<Rc<Gpu>, String> {
+        let adapters: Vec<AdapterInfo> = gpu_list.iter().map(|gpu| gpu.info.clone()).collect();
 
-        if let Some(index) = gpu_index {
-            if index < gpu_list.len() {
-                return gpu_list[index].clone();
-            }
-        } else {
-            for gpu in gpu_list {
-                if (gpu.properties.deviceType & VK_PHYSICAL_DEVICE_TYPE_DISCRETE_GPU) != 0 {
-                    return gpu.clone();
-                }
-            }
-        }
+        return match adapter::select_adapter(&adapters, gpu_index) {
+            Ok(position) => Ok(gpu_list[position].clone()),
+            Err(reason)  => Err(format!("{}\n{}", reason, adapter::format_adapter_report(&adapters, None, false))),
+        };
+    }
 
-        println!("[WARN] Device::select_gpu :: Failed to find a discrete gpu. Falling back to the first available gpu.");
-        return gpu_list[0].clone();
+    pub fn get_adapter_info(&self) -> &AdapterInfo {
+        return &self.gpu.info;
+    }
+
+    /// Multi-draw indirect with a first instance and a GPU written draw count were all enabled, so draws can be
+    /// culled and issued from a compute shader.
+    pub fn supports_gpu_driven_rendering(&self) -> bool {
+        return self.supports_gpu_driven_rendering;
     }
 
     pub fn create_device_context(device: Rc<Self>) -> context::DeviceContext {
         return context::DeviceContext::new(device);
     }
 
-    pub fn create_imgui_editor(&self, min_image_count: u32) -> super::EditorRenderData {
+    pub fn create_imgui_editor(&self, min_image_count: u32) -> Result<super::EditorRenderData, RenderError> {
         // 1: create descriptor pool for IMGUI
         //  the size of the pool is very oversized, but it's copied from imgui demo.
         let sizes: [PoolSizeRatio; 11] = [
@@ -927,12 +1153,12 @@ impl Device {
             PoolSizeRatio{descriptor_type: VK_DESCRIPTOR_TYPE_INPUT_ATTACHMENT,       ratio: 1000.0 },
         ];
 
-        let descriptor_alloc = self.create_descriptor_allocator(1000, DescriptorAllocatorFlags::AllowFree, sizes.as_slice());
+        let descriptor_alloc = self.create_descriptor_allocator(1000, DescriptorAllocatorFlags::AllowFree, sizes.as_slice())?;
 
         // 2: initialize imgui library for vulkan
         use vendor::imgui::{ImGuiVulkanInitInfo, ig_load_vulkan_functions, ig_vulkan_init, ig_vulkan_create_fonts_texture};
 
-        let surface_format = self.gpu.select_surface_format(consts::DEVICE_FEATURES.prefer_hdr);
+        let surface_format = self.gpu.select_surface_format(self.features.prefer_hdr);
 
         let mut dyn_render_info = VkPipelineRenderingCreateInfo::default();
         dyn_render_info.colorAttachmentCount    = 1;
@@ -969,7 +1195,7 @@ impl Device {
         ig_vulkan_init(init_info);
         ig_vulkan_create_fonts_texture();
 
-        return super::EditorRenderData{ allocator: descriptor_alloc };
+        return Ok(super::EditorRenderData{ allocator: descriptor_alloc });
     }
 
     pub fn destroy_imgui_editor(&self, editor: &mut super::EditorRenderData) {
@@ -992,7 +1218,7 @@ impl Device {
         return unsafe { queue.assume_init() };
     }
 
-    pub fn create_swapchain(&self, old_swapchain: Option<&Swapchain>) -> Swapchain {
+    pub fn create_swapchain(&self, old_swapchain: Option<&Swapchain>) -> Result<Swapchain, RenderError> {
         let present_queue = self.get_queue(util::QueueType::Present);
 
         // Let's grab some data from the old swapchain
@@ -1018,8 +1244,8 @@ impl Device {
         cached_width  = std::cmp::max(cached_width,  MIN_SIZE);
         cached_height = std::cmp::max(cached_height, MIN_SIZE);
 
-        let surface_format = self.gpu.select_surface_format(consts::DEVICE_FEATURES.prefer_hdr);
-        let swapchain_caps = Gpu::query_swapchain_capabilities(&self.instance, &self.surface, self.gpu.handle);
+        let surface_format = self.gpu.select_surface_format(self.features.prefer_hdr);
+        let swapchain_caps = Gpu::query_swapchain_capabilities(&self.instance, &self.surface, self.gpu.handle)?;
 
         // Select the present mode
         let mut present_mode: VkPresentModeKHR = VK_PRESENT_MODE_FIFO_KHR; //worst-case fallback if mailbox is not present
@@ -1133,7 +1359,7 @@ impl Device {
         } else {
             let mut sems = Vec::<super::Semaphore>::with_capacity(swapchain_images.len());
             for i in 0..swapchain_images.len() {
-                sems.push(self.create_semaphore());
+                sems.push(self.create_semaphore()?);
             }
             sems
         };
@@ -1143,7 +1369,7 @@ impl Device {
         } else {
             let mut sems = Vec::<super::Semaphore>::with_capacity(swapchain_images.len());
             for i in 0..swapchain_images.len() {
-                sems.push(self.create_semaphore());
+                sems.push(self.create_semaphore()?);
             }
             sems
         };
@@ -1156,7 +1382,7 @@ impl Device {
                 // Create the fence in a signaled state, indicating that the first frame has already been "rendered".
                 // This will prevent the application from waiting indefinitely for the first frame to render since it
                 // cannot be rendered until a frame is "rendered" before it.
-                sems.push(self.create_fence(true));
+                sems.push(self.create_fence(true)?);
             }
             sems
         };
@@ -1179,6 +1405,7 @@ impl Device {
             present_queue,
             image_views:        swapchain_image_views,
             images:             swapchain_images,
+            surface_format,
             present_semaphores,
             render_semaphores,
             render_fences,
@@ -1190,7 +1417,7 @@ impl Device {
             current_generation: if let Some(old) = old_swapchain { old.current_generation } else { 0 },
         };
 
-        return result;
+        return Ok(result);
     }
 
     pub fn destroy_swapchain(&self, swapchain: &mut Swapchain) {
@@ -1261,7 +1488,7 @@ impl Device {
         return queue_index;
     }
 
-    pub fn create_command_pool(&self, queue_type: util::QueueType) -> CommandPool {
+    pub fn create_command_pool(&self, queue_type: util::QueueType) -> Result<CommandPool, RenderError> {
         let queue_index = self.get_queue_index(queue_type);
 
         let mut command_pool_ci = VkCommandPoolCreateInfo::default();
@@ -1272,59 +1499,74 @@ impl Device {
 
         let fn_table = CommandPoolFnTable{};
 
-        return CommandPool{
+        return Ok(CommandPool{
             fns:    fn_table,
             handle: unsafe { pool.assume_init() },
-        };
+        });
     }
 
     pub fn destroy_command_pool(&self, command_pool: &mut CommandPool) {
         call!(self.fns.destroy_command_pool, self.handle, command_pool.handle, ptr::null_mut());
     }
 
-    pub fn create_command_buffer(&self, command_pool: &CommandPool) -> CommandBuffer {
+    pub fn create_command_buffer(&self, command_pool: &CommandPool) -> Result<CommandBuffer, RenderError> {
+        return self.allocate_command_buffer(command_pool, VK_COMMAND_BUFFER_LEVEL_PRIMARY);
+    }
+
+    /// Secondary command buffers are recorded on worker threads and executed from a primary command buffer.
+    pub fn create_secondary_command_buffer(&self, command_pool: &CommandPool) -> Result<CommandBuffer, RenderError> {
+        return self.allocate_command_buffer(command_pool, VK_COMMAND_BUFFER_LEVEL_SECONDARY);
+    }
+
+    fn allocate_command_buffer(&self, command_pool: &CommandPool, level: VkCommandBufferLevel) -> Result<CommandBuffer, RenderError> {
         let mut command_buffer_ci = VkCommandBufferAllocateInfo::default();
         command_buffer_ci.commandPool        = command_pool.handle;
         command_buffer_ci.commandBufferCount = 1;
+        command_buffer_ci.level              = level;
 
         let mut buffer: MaybeUninit<_> = MaybeUninit::<VkCommandBuffer>::uninit();
         call_throw!(self.fns.alloc_command_buffers, self.handle, &command_buffer_ci, buffer.as_mut_ptr());
 
         let fn_table = CommandBufferFnTable{
-            begin_command_buffer:     self.fns.begin_command_buffer,
-            end_command_buffer:       self.fns.end_command_buffer,
-            reset_command_buffer:     self.fns.reset_command_buffer,
-            cmd_pipeline_barrier2:    self.fns.cmd_pipeline_barrier2,
-            cmd_clear_color_image:    self.fns.cmd_clear_color_image,
-            cmd_blit_image2:          self.fns.cmd_blit_image2,
-            cmd_bind_pipeline:        self.fns.cmd_bind_pipeline,
-            cmd_bind_descriptor_sets: self.fns.cmd_bind_descriptor_sets,
-            cmd_dispatch:             self.fns.cmd_dispatch,
-            cmd_begin_rendering:      self.fns.cmd_begin_rendering,
-            cmd_end_rendering:        self.fns.cmd_end_rendering,
-            cmd_set_scissor:          self.fns.cmd_set_scissor,
-            cmd_set_viewport:         self.fns.cmd_set_viewport,
-            cmd_draw:                 self.fns.cmd_draw,
-            cmd_push_constants:       self.fns.cmd_push_constants,
-            cmd_copy_buffer:          self.fns.cmd_copy_buffer,
-            cmd_bind_index_buffer:    self.fns.cmd_bind_index_buffer,
-            cmd_draw_indexed:         self.fns.cmd_draw_indexed,
-            cmd_copy_buffer_to_image: self.fns.cmd_copy_buffer_to_image,
+            begin_command_buffer:            self.fns.begin_command_buffer,
+            end_command_buffer:              self.fns.end_command_buffer,
+            reset_command_buffer:            self.fns.reset_command_buffer,
+            cmd_pipeline_barrier2:           self.fns.cmd_pipeline_barrier2,
+            cmd_clear_color_image:           self.fns.cmd_clear_color_image,
+            cmd_blit_image2:                 self.fns.cmd_blit_image2,
+            cmd_bind_pipeline:               self.fns.cmd_bind_pipeline,
+            cmd_bind_descriptor_sets:        self.fns.cmd_bind_descriptor_sets,
+            cmd_dispatch:                    self.fns.cmd_dispatch,
+            cmd_begin_rendering:             self.fns.cmd_begin_rendering,
+            cmd_end_rendering:               self.fns.cmd_end_rendering,
+            cmd_set_scissor:                 self.fns.cmd_set_scissor,
+            cmd_set_viewport:                self.fns.cmd_set_viewport,
+            cmd_draw:                        self.fns.cmd_draw,
+            cmd_push_constants:              self.fns.cmd_push_constants,
+            cmd_copy_buffer:                 self.fns.cmd_copy_buffer,
+            cmd_bind_index_buffer:           self.fns.cmd_bind_index_buffer,
+            cmd_draw_indexed:                self.fns.cmd_draw_indexed,
+            cmd_copy_buffer_to_image:        self.fns.cmd_copy_buffer_to_image,
+            cmd_execute_commands:            self.fns.cmd_execute_commands,
+            cmd_set_depth_bias:              self.fns.cmd_set_depth_bias,
+            cmd_draw_indexed_indirect:       self.fns.cmd_draw_indexed_indirect,
+            cmd_draw_indexed_indirect_count: self.fns.cmd_draw_indexed_indirect_count,
+            cmd_fill_buffer:                 self.fns.cmd_fill_buffer,
         };
 
-        return CommandBuffer::new(fn_table, unsafe { buffer.assume_init() });
+        return Ok(CommandBuffer::new(fn_table, unsafe { buffer.assume_init() }));
     }
 
-    pub fn create_semaphore(&self) -> super::Semaphore {
+    pub fn create_semaphore(&self) -> Result<super::Semaphore, RenderError> {
         let semaphore_ci = VkSemaphoreCreateInfo::default();
 
         let mut semaphore: MaybeUninit<_> = MaybeUninit::<VkSemaphore>::uninit();
         call_throw!(self.fns.create_semaphore, self.handle, &semaphore_ci, ptr::null(), semaphore.as_mut_ptr());
 
-        return unsafe { semaphore.assume_init() };
+        return Ok(unsafe { semaphore.assume_init() });
     }
 
-    pub fn create_timeline_semaphore(&self, initial_value: u64) -> super::TimelineSemaphore {
+    pub fn create_timeline_semaphore(&self, initial_value: u64) -> Result<super::TimelineSemaphore, RenderError> {
         let mut timeline_ci = VkSemaphoreTypeCreateInfo::default();
         timeline_ci.initialValue = initial_value;
 
@@ -1336,7 +1578,7 @@ impl Device {
         let mut semaphore: MaybeUninit<_> = MaybeUninit::<VkSemaphore>::uninit();
         call_throw!(self.fns.create_semaphore, self.handle, &semaphore_ci, ptr::null(), semaphore.as_mut_ptr());
 
-        return unsafe { semaphore.assume_init() };
+        return Ok(unsafe { semaphore.assume_init() });
     }
 
     pub fn destroy_semaphore(&self, semaphore: &super::Semaphore) {
@@ -1347,14 +1589,14 @@ impl Device {
         self.destroy_semaphore(semaphore);
     }
 
-    pub fn create_fence(&self, set_signaled: bool) -> super::Fence {
+    pub fn create_fence(&self, set_signaled: bool) -> Result<super::Fence, RenderError> {
         let mut fence_ci = VkFenceCreateInfo::default();
         fence_ci.flags = if set_signaled { VK_FENCE_CREATE_SIGNALED_BIT } else { 0 };
 
         let mut fence: MaybeUninit<_> = MaybeUninit::<VkFence>::uninit();
         call_throw!(self.fns.create_fence, self.handle, &fence_ci, ptr::null(), fence.as_mut_ptr());
 
-        return unsafe { fence.assume_init() };
+        return Ok(unsafe { fence.assume_init() });
     }
 
     pub fn destroy_fence(&self, fence: &super::Fence) {
@@ -1372,8 +1614,12 @@ impl Device {
         image_usage:        VkImageUsageFlags,
         memory_usage:       VmaMemoryUsage,
         memory_props:       VkMemoryPropertyFlagBits,
-        mipmapped:          bool)    -> super::AllocatedImage
+        mipmapped:          bool,
+        samples:            VkSampleCountFlagBits) -> Result<super::AllocatedImage, RenderError>
     {
+        // Multisampled images can't have mips
+        assert!(!mipmapped || samples == VK_SAMPLE_COUNT_1_BIT);
+
         let mut result = super::AllocatedImage::default();
 
         // select the aspect flags
@@ -1387,6 +1633,7 @@ impl Device {
         result.dims   = extent;
 
         let mut image_ci = util::make_image_ci(format, image_usage, extent);
+        image_ci.samples = samples;
         if mipmapped {
             image_ci.mipLevels = ((extent.width.max(extent.height) as f32).log2().floor() as u32) + 1;
         }
@@ -1405,7 +1652,51 @@ impl Device {
 
         call_throw!(self.fns.create_image_view, self.handle, &image_view_ci, ptr::null_mut(), &mut result.view);
 
-        return result;
+        return Ok(result);
+    }
+
+    /// Allocates a color cubemap, six square faces of `face_size` texels with `mip_count` mips each. The view covers
+    /// every face and mip, for samplerCube lookups.
+    pub fn allocate_cube_image_memory(&self, face_size: u32, format: VkFormat, image_usage: VkImageUsageFlags, mip_count: u32) -> Result<super::AllocatedImage, RenderError> {
+        let mut result = super::AllocatedImage::default();
+        result.format = format;
+        result.dims   = VkExtent3D{ width: face_size, height: face_size, depth: 1 };
+
+        let mut image_ci = util::make_image_ci(format, image_usage, result.dims);
+        image_ci.mipLevels   = mip_count;
+        image_ci.arrayLayers = 6;
+        image_ci.flags       = VK_IMAGE_CREATE_CUBE_COMPATIBLE_BIT as VkImageCreateFlags;
+
+        let mut image_alloc_info = VmaAllocationCreateInfo::default();
+        image_alloc_info.usage          = VMA_MEMORY_USAGE_GPU_ONLY;
+        image_alloc_info.preferredFlags = VK_MEMORY_PROPERTY_DEVICE_LOCAL_BIT;
+
+        call_throw!(vmaCreateImage, self.allocator, &image_ci, &image_alloc_info, &mut result.image, &mut result.memory, ptr::null_mut());
+
+        let mut image_view_ci = util::make_image_view_ci(result.format, result.image, VK_IMAGE_ASPECT_COLOR_BIT);
+        image_view_ci.viewType                    = VK_IMAGE_VIEW_TYPE_CUBE;
+        image_view_ci.subresourceRange.levelCount = mip_count;
+        image_view_ci.subresourceRange.layerCount = 6;
+
+        call_throw!(self.fns.create_image_view, self.handle, &image_view_ci, ptr::null_mut(), &mut result.view);
+
+        return Ok(result);
+    }
+
+    /// Creates a view of a single mip level of a mipmapped color image, for compute shaders that write one mip
+    /// at a time. The view is owned by the caller, see destroy_image_view.
+    pub fn create_image_mip_view(&self, image: &super::AllocatedImage, mip_level: u32) -> Result<VkImageView, RenderError> {
+        let mut image_view_ci = util::make_image_view_ci(image.format, image.image, VK_IMAGE_ASPECT_COLOR_BIT);
+        image_view_ci.subresourceRange.baseMipLevel = mip_level;
+
+        let mut view: MaybeUninit<_> = MaybeUninit::<VkImageView>::uninit();
+        call_throw!(self.fns.create_image_view, self.handle, &image_view_ci, ptr::null(), view.as_mut_ptr());
+
+        return Ok(unsafe { view.assume_init() });
+    }
+
+    pub fn destroy_image_view(&self, view: VkImageView) {
+        call!(self.fns.destroy_image_view, self.handle, view, ptr::null_mut());
     }
 
     pub fn destroy_image_memory(&self, image: &mut super::AllocatedImage) {
@@ -1418,12 +1709,22 @@ impl Device {
 
     pub fn create_descriptor_set_layout(&self,
         layout_bindings: &[VkDescriptorSetLayoutBinding],
+        binding_flags:   &[VkDescriptorBindingFlags], // empty, or one per binding
         flags:            VkDescriptorSetLayoutCreateFlags
-    ) -> VkDescriptorSetLayout
+    ) -> Result<VkDescriptorSetLayout, RenderError>
     {
+        assert!(binding_flags.is_empty() || binding_flags.len() == layout_bindings.len());
+
+        let binding_flags_ci = VkDescriptorSetLayoutBindingFlagsCreateInfo{
+            sType:         VK_STRUCTURE_TYPE_DESCRIPTOR_SET_LAYOUT_BINDING_FLAGS_CREATE_INFO,
+            pNext:         ptr::null(),
+            bindingCount:  binding_flags.len() as u32,
+            pBindingFlags: binding_flags.as_ptr(),
+        };
+
         let descriptor_layout_ci = VkDescriptorSetLayoutCreateInfo{
             sType:        VK_STRUCTURE_TYPE_DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
-            pNext:        ptr::null(),
+            pNext:        if binding_flags.is_empty() { ptr::null() } else { &binding_flags_ci as *const _ as *const core::ffi::c_void },
             flags,
             bindingCount: layout_bindings.len() as u32,
             pBindings:    layout_bindings.as_ptr(),
@@ -1432,14 +1733,14 @@ impl Device {
         let mut layout: MaybeUninit<_> = MaybeUninit::<VkDescriptorSetLayout>::uninit();
         call_throw!(self.fns.create_descriptor_set_layout, self.handle, &descriptor_layout_ci, ptr::null(), layout.as_mut_ptr());
 
-        return unsafe { layout.assume_init() };
+        return Ok(unsafe { layout.assume_init() });
     }
 
     pub fn destroy_descriptor_set_layout(&self, layout: VkDescriptorSetLayout) {
         call!(self.fns.destroy_descriptor_set_layout, self.handle, layout, ptr::null());
     }
 
-    pub fn create_descriptor_allocator(&self, max_sets: u32, flags: DescriptorAllocatorFlags, pool_ratios: &[PoolSizeRatio]) -> DescriptorAllocator {
+    pub fn create_descriptor_allocator(&self, max_sets: u32, flags: DescriptorAllocatorFlags, pool_ratios: &[PoolSizeRatio]) -> Result<DescriptorAllocator, RenderError> {
         let mut pool_sizes = Vec::<VkDescriptorPoolSize>::with_capacity(pool_ratios.len());
         for ratio in pool_ratios {
             pool_sizes.push(VkDescriptorPoolSize{
@@ -1449,8 +1750,9 @@ impl Device {
         }
 
         let vk_flags = match (flags) {
-            DescriptorAllocatorFlags::None      => 0,
-            DescriptorAllocatorFlags::AllowFree => VK_DESCRIPTOR_POOL_CREATE_FREE_DESCRIPTOR_SET_BIT,
+            DescriptorAllocatorFlags::None            => 0,
+            DescriptorAllocatorFlags::AllowFree       => VK_DESCRIPTOR_POOL_CREATE_FREE_DESCRIPTOR_SET_BIT,
+            DescriptorAllocatorFlags::UpdateAfterBind => VK_DESCRIPTOR_POOL_CREATE_UPDATE_AFTER_BIND_BIT,
         };
 
         let pool_info = VkDescriptorPoolCreateInfo{
@@ -1465,7 +1767,7 @@ impl Device {
         let mut pool: MaybeUninit<_> = MaybeUninit::<VkDescriptorPool>::uninit();
         call_throw!(self.fns.create_descriptor_pool, self.handle, &pool_info, ptr::null(), pool.as_mut_ptr());
 
-        return DescriptorAllocator{ pool: unsafe{ pool.assume_init() } };
+        return Ok(DescriptorAllocator{ pool: unsafe{ pool.assume_init() } });
     }
 
     pub fn clear_descriptor_allocator(&self, allocator: &DescriptorAllocator) {
@@ -1502,7 +1804,7 @@ impl Device {
         call!(self.fns.update_descriptor_sets, self.handle, write_infos.len() as u32, write_infos.as_ptr(), descriptor_copy_count, ptr::null());
     }
 
-    pub fn create_shader_module(&self, shader_code: &[u8]) -> Option<VkShaderModule> {
+    pub fn create_shader_module(&self, shader_code: &[u8]) -> Result<VkShaderModule, RenderError> {
         let module_ci = VkShaderModuleCreateInfo{
             sType:    VK_STRUCTURE_TYPE_SHADER_MODULE_CREATE_INFO,
             pNext:    ptr::null(),
@@ -1512,12 +1814,9 @@ impl Device {
         };
 
         let mut module: MaybeUninit<_> = MaybeUninit::<VkShaderModule>::uninit();
-        let result = call_nothrow!(self.fns.create_shader_module, self.handle, &module_ci, ptr::null(), module.as_mut_ptr());
-        if result != VK_SUCCESS {
-            return None;
-        }
+        call_throw!(self.fns.create_shader_module, self.handle, &module_ci, ptr::null(), module.as_mut_ptr());
 
-        return Some(unsafe{ module.assume_init() });
+        return Ok(unsafe{ module.assume_init() });
     }
 
     pub fn destroy_shader_module(&self, module: VkShaderModule) {
@@ -1525,7 +1824,7 @@ impl Device {
         call!(self.fns.destroy_shader_module, self.handle, module, ptr::null());
     }
 
-    pub fn create_pipeline_layout(&self, descriptor_sets: &[VkDescriptorSetLayout], push_constants: &[VkPushConstantRange]) -> VkPipelineLayout {
+    pub fn create_pipeline_layout(&self, descriptor_sets: &[VkDescriptorSetLayout], push_constants: &[VkPushConstantRange]) -> Result<VkPipelineLayout, RenderError> {
         let layout_ci = VkPipelineLayoutCreateInfo{
             sType:                  VK_STRUCTURE_TYPE_PIPELINE_LAYOUT_CREATE_INFO,
             pNext:                  ptr::null(),
@@ -1539,14 +1838,14 @@ impl Device {
         let mut layout: MaybeUninit<_> = MaybeUninit::<VkPipelineLayout>::uninit();
         call_throw!(self.fns.create_pipeline_layout, self.handle, &layout_ci, ptr::null(), layout.as_mut_ptr());
 
-        return unsafe{ layout.assume_init() };
+        return Ok(unsafe{ layout.assume_init() });
     }
 
     pub fn destroy_pipeline_layout(&self, layout: VkPipelineLayout) {
         call!(self.fns.destroy_pipeline_layout, self.handle, layout, ptr::null());
     }
 
-    pub fn create_compute_pipeline(&self, compute_module: VkShaderModule, pipeline_layout: VkPipelineLayout) -> VkPipeline {
+    pub fn create_compute_pipeline(&self, compute_module: VkShaderModule, pipeline_layout: VkPipelineLayout) -> Result<VkPipeline, RenderError> {
         let entry_point = CString::new("main").unwrap();
 
         let stage_info = VkPipelineShaderStageCreateInfo{
@@ -1574,37 +1873,40 @@ impl Device {
         let mut pipeline: MaybeUninit<_> = MaybeUninit::<VkPipeline>::uninit();
         call_throw!(self.fns.create_compute_pipeline, self.handle, pipeline_cache, 1, &pipeline_ci, ptr::null(), pipeline.as_mut_ptr());
 
-        return unsafe { pipeline.assume_init() };
+        return Ok(unsafe { pipeline.assume_init() });
     }
 
     // the graphics pipeline just requires so many inputs, so pass in the create info struct instead of trying to pass fields as parameters
-    pub fn create_graphics_pipeline(&self, pipeline_ci: VkGraphicsPipelineCreateInfo) -> VkPipeline {
+    pub fn create_graphics_pipeline(&self, pipeline_ci: VkGraphicsPipelineCreateInfo) -> Result<VkPipeline, RenderError> {
         let pipeline_cache: VkPipelineCache = ptr::null_mut(); //todo: support pipeline caches
 
         let mut pipeline: MaybeUninit<_> = MaybeUninit::<VkPipeline>::uninit();
         call_throw!(self.fns.create_graphics_pipeline, self.handle, pipeline_cache, 1, &pipeline_ci, ptr::null(), pipeline.as_mut_ptr());
 
-        return unsafe { pipeline.assume_init() };
+        return Ok(unsafe { pipeline.assume_init() });
     }
 
     pub fn destroy_pipeline(&self, pipeline: VkPipeline) {
         call!(self.fns.destroy_pipeline, self.handle, pipeline, ptr::null());
     }
 
-    pub fn reset_fences(&self, fence: &super::Fence) {
+    pub fn reset_fences(&self, fence: &super::Fence) -> Result<(), RenderError> {
         call_throw!(self.fns.reset_fences, self.handle, 1, fence);
+        return Ok(());
     }
 
-    pub fn queue_submit(&self, queue_type: util::QueueType, info: VkSubmitInfo2, fence: super::Fence) {
+    pub fn queue_submit(&self, queue_type: util::QueueType, info: VkSubmitInfo2, fence: super::Fence) -> Result<(), RenderError> {
         let graphics_queue = self.get_queue(queue_type);
         call_throw!(self.fns.queue_submit2, graphics_queue, 1, &info, fence);
+        return Ok(());
     }
 
-    pub fn wait_for_fences(&self, fence: super::Fence) {
+    pub fn wait_for_fences(&self, fence: super::Fence) -> Result<(), RenderError> {
         call_throw!(self.fns.wait_for_fences, self.handle, 1, &fence, VK_TRUE, 1000000000);
+        return Ok(());
     }
 
-    pub fn create_buffer(&self, alloc_size: usize, buffer_usage: VkBufferUsageFlags, memory_usage: VmaMemoryUsage) -> AllocatedBuffer {
+    pub fn create_buffer(&self, alloc_size: usize, buffer_usage: VkBufferUsageFlags, memory_usage: VmaMemoryUsage) -> Result<AllocatedBuffer, RenderError> {
         let mut buffer_ci = VkBufferCreateInfo::default();
         buffer_ci.size  = alloc_size as u64;
         buffer_ci.usage = buffer_usage;
@@ -1616,7 +1918,13 @@ impl Device {
         let mut result = AllocatedBuffer::default();
         call_throw!(vmaCreateBuffer, self.allocator, &buffer_ci, &vma_alloc_info, &mut result.buffer, &mut result.memory, &mut result.info);
 
-        return result;
+        return Ok(result);
+    }
+
+    /// Makes the GPU's writes to a mapped buffer visible to the CPU, for memory that isn't host coherent.
+    pub fn invalidate_buffer(&self, buffer: &AllocatedBuffer) -> Result<(), RenderError> {
+        call_throw!(vmaInvalidateAllocation, self.allocator, buffer.memory, 0, VK_WHOLE_SIZE as VkDeviceSize);
+        return Ok(());
     }
 
     pub fn destroy_buffer(&self, buffer: &mut AllocatedBuffer) {
@@ -1638,7 +1946,12 @@ impl Device {
         self.gpu.select_depth_format()
     }
 
-    pub fn create_sampler(&self, mag_filter: VkFilter, min_filter: VkFilter) -> VkSampler {
+    /// Clamps a requested MSAA sample count to what the GPU supports for render targets.
+    pub fn get_sample_count(&self, requested: u32) -> VkSampleCountFlagBits {
+        self.gpu.select_sample_count(requested)
+    }
+
+    pub fn create_sampler(&self, mag_filter: VkFilter, min_filter: VkFilter) -> Result<VkSampler, RenderError> {
         let sampler_ci = VkSamplerCreateInfo{
             sType:                   VK_STRUCTURE_TYPE_SAMPLER_CREATE_INFO,
             pNext:                   ptr::null(),
@@ -1663,7 +1976,97 @@ impl Device {
         let mut sampler: MaybeUninit<_> = MaybeUninit::<VkSampler>::uninit();
         call_throw!(self.fns.create_sampler, self.handle, &sampler_ci, ptr::null(), sampler.as_mut_ptr());
 
-        return unsafe { sampler.assume_init() };
+        return Ok(unsafe { sampler.assume_init() });
+    }
+
+    // A sampler that clamps to the edge of the image, for post-processing where repeating would bleed the opposite
+    // edge of the screen into the result.
+    pub fn create_clamped_sampler(&self, filter: VkFilter) -> Result<VkSampler, RenderError> {
+        let sampler_ci = VkSamplerCreateInfo{
+            sType:                   VK_STRUCTURE_TYPE_SAMPLER_CREATE_INFO,
+            pNext:                   ptr::null(),
+            flags:                   0,
+            magFilter:               filter,
+            minFilter:               filter,
+            mipmapMode:              VK_SAMPLER_MIPMAP_MODE_NEAREST,
+            addressModeU:            VK_SAMPLER_ADDRESS_MODE_CLAMP_TO_EDGE,
+            addressModeV:            VK_SAMPLER_ADDRESS_MODE_CLAMP_TO_EDGE,
+            addressModeW:            VK_SAMPLER_ADDRESS_MODE_CLAMP_TO_EDGE,
+            mipLodBias:              0.0,
+            anisotropyEnable:        VK_FALSE,
+            maxAnisotropy:           0.0,
+            compareEnable:           VK_FALSE,
+            compareOp:               VK_COMPARE_OP_NEVER,
+            minLod:                  0.0,
+            maxLod:                  0.0,
+            borderColor:             VK_BORDER_COLOR_FLOAT_TRANSPARENT_BLACK,
+            unnormalizedCoordinates: VK_FALSE,
+        };
+
+        let mut sampler: MaybeUninit<_> = MaybeUninit::<VkSampler>::uninit();
+        call_throw!(self.fns.create_sampler, self.handle, &sampler_ci, ptr::null(), sampler.as_mut_ptr());
+
+        return Ok(unsafe { sampler.assume_init() });
+    }
+
+    // A depth comparison sampler for sampler2DShadow lookups. Linear filtering makes the hardware blend the four
+    // nearest comparison results (2x2 PCF), and anything outside the shadow map compares as lit.
+    pub fn create_shadow_sampler(&self) -> Result<VkSampler, RenderError> {
+        let sampler_ci = VkSamplerCreateInfo{
+            sType:                   VK_STRUCTURE_TYPE_SAMPLER_CREATE_INFO,
+            pNext:                   ptr::null(),
+            flags:                   0,
+            magFilter:               VK_FILTER_LINEAR,
+            minFilter:               VK_FILTER_LINEAR,
+            mipmapMode:              VK_SAMPLER_MIPMAP_MODE_NEAREST,
+            addressModeU:            VK_SAMPLER_ADDRESS_MODE_CLAMP_TO_BORDER,
+            addressModeV:            VK_SAMPLER_ADDRESS_MODE_CLAMP_TO_BORDER,
+            addressModeW:            VK_SAMPLER_ADDRESS_MODE_CLAMP_TO_BORDER,
+            mipLodBias:              0.0,
+            anisotropyEnable:        VK_FALSE,
+            maxAnisotropy:           0.0,
+            compareEnable:           VK_TRUE,
+            compareOp:               VK_COMPARE_OP_LESS_OR_EQUAL,
+            minLod:                  0.0,
+            maxLod:                  0.0,
+            borderColor:             VK_BORDER_COLOR_FLOAT_OPAQUE_WHITE,
+            unnormalizedCoordinates: VK_FALSE,
+        };
+
+        let mut sampler: MaybeUninit<_> = MaybeUninit::<VkSampler>::uninit();
+        call_throw!(self.fns.create_sampler, self.handle, &sampler_ci, ptr::null(), sampler.as_mut_ptr());
+
+        return Ok(unsafe { sampler.assume_init() });
+    }
+
+    // A trilinear sampler for cubemaps. Cube lookups filter across the face edges on their own, the clamp only keeps
+    // the filter from wrapping around a single face.
+    pub fn create_cube_sampler(&self) -> Result<VkSampler, RenderError> {
+        let sampler_ci = VkSamplerCreateInfo{
+            sType:                   VK_STRUCTURE_TYPE_SAMPLER_CREATE_INFO,
+            pNext:                   ptr::null(),
+            flags:                   0,
+            magFilter:               VK_FILTER_LINEAR,
+            minFilter:               VK_FILTER_LINEAR,
+            mipmapMode:              VK_SAMPLER_MIPMAP_MODE_LINEAR,
+            addressModeU:            VK_SAMPLER_ADDRESS_MODE_CLAMP_TO_EDGE,
+            addressModeV:            VK_SAMPLER_ADDRESS_MODE_CLAMP_TO_EDGE,
+            addressModeW:            VK_SAMPLER_ADDRESS_MODE_CLAMP_TO_EDGE,
+            mipLodBias:              0.0,
+            anisotropyEnable:        VK_FALSE,
+            maxAnisotropy:           0.0,
+            compareEnable:           VK_FALSE,
+            compareOp:               VK_COMPARE_OP_NEVER,
+            minLod:                  0.0,
+            maxLod:                  VK_LOD_CLAMP_NONE as f32,
+            borderColor:             VK_BORDER_COLOR_FLOAT_TRANSPARENT_BLACK,
+            unnormalizedCoordinates: VK_FALSE,
+        };
+
+        let mut sampler: MaybeUninit<_> = MaybeUninit::<VkSampler>::uninit();
+        call_throw!(self.fns.create_sampler, self.handle, &sampler_ci, ptr::null(), sampler.as_mut_ptr());
+
+        return Ok(unsafe { sampler.assume_init() });
     }
 
     pub fn destroy_sampler(&self, sampler: VkSampler) {
@@ -3,9 +3,12 @@ use crate::util::ffi::call;
 use vendor::vulkan::*;
 use super::gpu_utils::*;
 use super::gpu_device::Device;
+use crate::renderer::error::RenderError;
 
 use std::ptr;
 
+pub const MAX_COLOR_ATTACHMENTS: usize = 4;
+
 pub struct GraphicsPipelineBuilder {
     shader_stages:           Vec<VkPipelineShaderStageCreateInfo>,
     input_assembly:          VkPipelineInputAssemblyStateCreateInfo,
@@ -16,6 +19,13 @@ pub struct GraphicsPipelineBuilder {
     depth_stencil:           VkPipelineDepthStencilStateCreateInfo,
     render_info:             VkPipelineRenderingCreateInfo,
     color_attachment_format: VkFormat,
+    dynamic_depth_bias:      bool,
+
+    // Pipelines with more than one color attachment, see set_color_attachment_formats(). The attachments share
+    // color_blend_attachment unless separate_blending is set, see set_weighted_oit_attachments()
+    color_attachment_formats: [VkFormat; MAX_COLOR_ATTACHMENTS],
+    blend_attachments:        [VkPipelineColorBlendAttachmentState; MAX_COLOR_ATTACHMENTS],
+    separate_blending:        bool,
 }
 
 impl Default for GraphicsPipelineBuilder{
@@ -30,6 +40,10 @@ impl Default for GraphicsPipelineBuilder{
             depth_stencil:           VkPipelineDepthStencilStateCreateInfo::default(),
             render_info:             VkPipelineRenderingCreateInfo::default(),
             color_attachment_format: VkFormat::default(),
+            dynamic_depth_bias:      false,
+            color_attachment_formats: [VkFormat::default(); MAX_COLOR_ATTACHMENTS],
+            blend_attachments:        [VkPipelineColorBlendAttachmentState::default(); MAX_COLOR_ATTACHMENTS],
+            separate_blending:        false,
         }
     }
 }
@@ -43,7 +57,7 @@ impl GraphicsPipelineBuilder {
         *self = GraphicsPipelineBuilder::default();
     }
 
-    pub fn build(&mut self, device: &Device) -> VkPipeline {
+    pub fn build(&mut self, device: &Device) -> Result<VkPipeline, RenderError> {
         // Setup shader stage entry points
         let entry_point = std::ffi::CString::new("main").unwrap();
         for stage in &mut self.shader_stages {
@@ -57,11 +71,14 @@ impl GraphicsPipelineBuilder {
         viewport_state.scissorCount  = 1;
 
         // setup dummy color blending. We arent using transparent objects yet
-        // the blending is just "no blend", but we do write to the color attachment
+        // the blending is just "no blend", but we do write to the color attachment.
+        // depth-only pipelines don't have a color attachment to blend.
+        let blend_attachments = if self.separate_blending { self.blend_attachments } else { [self.color_blend_attachment; MAX_COLOR_ATTACHMENTS] };
+
         let mut color_blending = VkPipelineColorBlendStateCreateInfo::default();
         color_blending.logicOp         = VK_LOGIC_OP_COPY;
-        color_blending.attachmentCount = 1;
-        color_blending.pAttachments    = &self.color_blend_attachment;
+        color_blending.attachmentCount = self.render_info.colorAttachmentCount;
+        color_blending.pAttachments    = blend_attachments.as_ptr();
 
         // completely clear VertexInputStateCreateInfo, as we have no need for it (for now)
         let vertex_input_info = VkPipelineVertexInputStateCreateInfo::default();
@@ -82,7 +99,10 @@ impl GraphicsPipelineBuilder {
         pipeline_ci.pDepthStencilState  = &self.depth_stencil;
         pipeline_ci.layout              = self.pipeline_layout;
 
-        let state: [VkDynamicState; 2] = [ VK_DYNAMIC_STATE_VIEWPORT, VK_DYNAMIC_STATE_SCISSOR ];
+        let mut state: Vec<VkDynamicState> = vec![ VK_DYNAMIC_STATE_VIEWPORT, VK_DYNAMIC_STATE_SCISSOR ];
+        if self.dynamic_depth_bias {
+            state.push(VK_DYNAMIC_STATE_DEPTH_BIAS);
+        }
 
         let dynamic_ci = VkPipelineDynamicStateCreateInfo{
             sType:             VK_STRUCTURE_TYPE_PIPELINE_DYNAMIC_STATE_CREATE_INFO,
@@ -133,6 +153,25 @@ impl GraphicsPipelineBuilder {
         self
     }
 
+    // For depth-only pipelines (shadow maps, depth prepass), where there is nothing for a fragment shader to write.
+    pub fn set_vertex_shader(&mut self, vertex: VkShaderModule) -> &mut Self {
+        self.shader_stages.clear();
+
+        let vertex_stage_info = VkPipelineShaderStageCreateInfo{
+            sType:               VK_STRUCTURE_TYPE_PIPELINE_SHADER_STAGE_CREATE_INFO,
+            pNext:               ptr::null(),
+            flags:               0,
+            stage:               VK_SHADER_STAGE_VERTEX_BIT,
+            module:              vertex,
+            pName:               ptr::null(),
+            pSpecializationInfo: ptr::null(), //todo: specialization info
+        };
+
+        self.shader_stages.push(vertex_stage_info);
+
+        self
+    }
+
     pub fn set_input_topology(&mut self, topology: VkPrimitiveTopology) -> &mut Self{
         self.input_assembly.topology = topology;
         // not going to use primitive restart so leave it on false
@@ -155,6 +194,15 @@ impl GraphicsPipelineBuilder {
         self
     }
 
+    // Depth bias is set with CommandBuffer::set_depth_bias() before drawing, so it can be tuned without
+    // rebuilding the pipeline.
+    pub fn enable_dynamic_depth_bias(&mut self) -> &mut Self {
+        self.rasterizer.depthBiasEnable = VK_TRUE;
+        self.dynamic_depth_bias         = true;
+
+        self
+    }
+
     pub fn set_multisampling_none(&mut self) -> &mut Self{
         self.multisampling.sampleShadingEnable   = VK_FALSE;
         // multisampling defaulted to no multisampling (1 sample per pixel)
@@ -168,6 +216,14 @@ impl GraphicsPipelineBuilder {
         self
     }
 
+    /// Rasterizes with `samples` samples per pixel. Must match the sample count of the attachments.
+    pub fn set_multisampling(&mut self, samples: VkSampleCountFlagBits) -> &mut Self{
+        self.set_multisampling_none();
+        self.multisampling.rasterizationSamples = samples;
+
+        self
+    }
+
     //
     // Blending
     //   outColor = srcColor * srcColorBlendFactor <op> dstColor * dstColorBlendFactor;
@@ -227,6 +283,59 @@ impl GraphicsPipelineBuilder {
         self
     }
 
+    // Weighted blended order-independent transparency (McGuire and Bavoil) renders into two attachments:
+    //   0. accumulation - outColor = srcColor + dstColor, the weighted premultiplied color and alpha
+    //   1. revealage    - outColor = dstColor * (1.0 - srcColor), how much of the scene behind shows through
+    // Replaces the color attachment formats and the blending.
+    pub fn set_weighted_oit_attachments(&mut self, accum_format: VkFormat, revealage_format: VkFormat) -> &mut Self {
+        let mut accum = VkPipelineColorBlendAttachmentState::default();
+        accum.colorWriteMask      = VK_COLOR_COMPONENT_R_BIT | VK_COLOR_COMPONENT_G_BIT | VK_COLOR_COMPONENT_B_BIT | VK_COLOR_COMPONENT_A_BIT;
+        accum.blendEnable         = VK_TRUE;
+        accum.srcColorBlendFactor = VK_BLEND_FACTOR_ONE;
+        accum.dstColorBlendFactor = VK_BLEND_FACTOR_ONE;
+        accum.colorBlendOp        = VK_BLEND_OP_ADD;
+        accum.srcAlphaBlendFactor = VK_BLEND_FACTOR_ONE;
+        accum.dstAlphaBlendFactor = VK_BLEND_FACTOR_ONE;
+        accum.alphaBlendOp        = VK_BLEND_OP_ADD;
+
+        let mut revealage = VkPipelineColorBlendAttachmentState::default();
+        revealage.colorWriteMask      = VK_COLOR_COMPONENT_R_BIT;
+        revealage.blendEnable         = VK_TRUE;
+        revealage.srcColorBlendFactor = VK_BLEND_FACTOR_ZERO;
+        revealage.dstColorBlendFactor = VK_BLEND_FACTOR_ONE_MINUS_SRC_COLOR;
+        revealage.colorBlendOp        = VK_BLEND_OP_ADD;
+        revealage.srcAlphaBlendFactor = VK_BLEND_FACTOR_ZERO;
+        revealage.dstAlphaBlendFactor = VK_BLEND_FACTOR_ONE;
+        revealage.alphaBlendOp        = VK_BLEND_OP_ADD;
+
+        self.set_color_attachment_formats(&[accum_format, revealage_format]);
+        self.blend_attachments[0] = accum;
+        self.blend_attachments[1] = revealage;
+        self.separate_blending    = true;
+
+        self
+    }
+
+    // Multiple render targets, every attachment is blended the same way.
+    pub fn set_color_attachment_formats(&mut self, formats: &[VkFormat]) -> &mut Self {
+        assert!(formats.len() <= MAX_COLOR_ATTACHMENTS);
+
+        self.color_attachment_formats[..formats.len()].copy_from_slice(formats);
+
+        self.render_info.colorAttachmentCount    = formats.len() as u32;
+        self.render_info.pColorAttachmentFormats = self.color_attachment_formats.as_ptr();
+
+        self
+    }
+
+    // Pipelines that only write depth (shadow maps) have no color attachments.
+    pub fn set_no_color_attachment(&mut self) -> &mut Self {
+        self.render_info.colorAttachmentCount    = 0;
+        self.render_info.pColorAttachmentFormats = ptr::null();
+
+        self
+    }
+
     pub fn set_depth_format(&mut self, format: VkFormat) -> &mut Self {
         self.render_info.depthAttachmentFormat = format;
         self
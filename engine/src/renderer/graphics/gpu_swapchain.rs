@@ -2,6 +2,7 @@ use vendor::vulkan::*;
 use super::consts;
 use super::gpu_utils::*;
 use super::gpu_device::Device;
+use crate::renderer::error::RenderError;
 
 use std::ptr;
 use std::rc::Rc;
@@ -17,6 +18,7 @@ pub struct Swapchain {
     // swapchain images
     pub image_views:        Vec<VkImageView>,
     pub images:             Vec<VkImage>,
+    pub surface_format:     VkSurfaceFormatKHR,
 
     // synchronization state
     pub present_semaphores:     Vec<super::Semaphore>,
@@ -40,6 +42,7 @@ impl Default for Swapchain {
             present_queue:      ptr::null_mut(),
             image_views:        Vec::<VkImageView>::new(),
             images:             Vec::<VkImage>::new(),
+            surface_format:     VkSurfaceFormatKHR{ format: VK_FORMAT_UNDEFINED, colorSpace: VK_COLOR_SPACE_SRGB_NONLINEAR_KHR },
             present_semaphores: Vec::<super::Semaphore>::new(),
             render_semaphores:  Vec::<super::Semaphore>::new(),
             render_fences:      Vec::<super::Fence>::new(),
@@ -88,6 +91,10 @@ impl Swapchain {
         self.image_views[self.swapchain_index as usize]
     }
 
+    pub fn get_format(&self) -> VkFormat {
+        self.surface_format.format
+    }
+
     pub fn get_render_semaphore(&self) -> super::Semaphore {
         self.render_semaphores[self.frame_index as usize]
     }
@@ -104,13 +111,17 @@ impl Swapchain {
         self.images.len()
     }
 
-    pub fn acquire_frame(&mut self, device: &Device) -> bool {
+    /// Returns Ok(false) if the frame should be skipped. An out of date swapchain is invalidated and returned as
+    /// RenderError::SwapchainOutOfDate, the swapchain will be recreated on the next frame.
+    pub fn acquire_frame(&mut self, device: &Device) -> Result<bool, RenderError> {
         // Wait for the execution of the current frame to complete. The fence being free will allow this one to move on.
         //   Timeout of 1s
         let result = call_nothrow!(device.fns.wait_for_fences, device.handle, 1, &self.render_fences[self.frame_index], VK_TRUE, 1000000000);
-        if result != VK_SUCCESS {
+        if result < 0 {
+            return Err(RenderError::from_vk_result("vkWaitForFences", result));
+        } else if result != VK_SUCCESS {
             println!("WARN :: begin_frame :: In-flight fence wait failure!");
-            return false;
+            return Ok(false);
         }
 
         // Reset the fence for use on the next frame
@@ -127,16 +138,18 @@ impl Swapchain {
 
         if result == VK_ERROR_OUT_OF_DATE_KHR {
             self.invalidate();
-            return false;
+            return Err(RenderError::SwapchainOutOfDate);
+        } else if result < 0 {
+            return Err(RenderError::from_vk_result("vkAcquireNextImageKHR", result));
         } else if result != VK_SUCCESS && result != VK_SUBOPTIMAL_KHR {
             println!("ERROR :: begin_frame :: Failed to acquire swapchain image!");
-            return false;
+            return Ok(false);
         }
 
-        true
+        Ok(true)
     }
 
-    pub fn present_frame(&mut self, device: &Device) {
+    pub fn present_frame(&mut self, device: &Device) -> Result<(), RenderError> {
         // Return the image to the swapchain for presentation.
         let present_info = VkPresentInfoKHR{
             sType:              VK_STRUCTURE_TYPE_PRESENT_INFO_KHR,
@@ -150,6 +163,10 @@ impl Swapchain {
         };
 
         let result = call_nothrow!(device.fns.queue_present, self.present_queue, &present_info);
+
+        // The frame was submitted either way, so move on to the next frame's sync objects before reporting errors.
+        self.frame_index = (self.frame_index + 1) % self.render_fences.len();
+
         if result == VK_ERROR_OUT_OF_DATE_KHR || result == VK_SUBOPTIMAL_KHR {
             // Swapchain is out of date, suboptimal or a framebuffer resize has occurred. Trigger swapchain recreation.
             println!("WARN :: present_frame :: vkQueuePresentKHR returned out of date or suboptimal.");
@@ -158,9 +175,9 @@ impl Swapchain {
         }
         else if (result != VK_SUCCESS)
         {
-            panic!("Failed to present swap chain image!");
+            return Err(RenderError::from_vk_result("vkQueuePresentKHR", result));
         }
 
-        self.frame_index = (self.frame_index + 1) % self.render_fences.len();
+        Ok(())
     }
 }
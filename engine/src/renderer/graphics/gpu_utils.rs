@@ -5,15 +5,18 @@ use crate::util::ffi::*;
 
 use vendor::vulkan::*;
 use super::consts;
+use crate::renderer::error::RenderError;
 
 /* ======================================================================== */
 /* Helpful macros                                                           */
 
+// Returns a RenderError from the calling function if the Vulkan call fails, so the caller must return a
+// Result<_, RenderError>. VK_ERROR_DEVICE_LOST and VK_ERROR_OUT_OF_DATE_KHR map to their own variants.
 macro_rules! call_throw {
     ($call:expr, $($arg:expr),*) => {{
         let result = unsafe { ($call)($($arg,)*) };
         if result < 0 {
-            panic!("{} failed: {}", stringify!(call), result);
+            return Err(crate::renderer::error::RenderError::from_vk_result(stringify!($call), result));
         }
     }};
 }
@@ -73,7 +76,7 @@ pub struct GlobalFnTable {
 }
 
 impl GlobalFnTable {
-    pub fn enumerate_instance_extensions(&self) -> Vec<VkExtensionProperties> {
+    pub fn enumerate_instance_extensions(&self) -> Result<Vec<VkExtensionProperties>, RenderError> {
         let mut extension_count: u32 = 0;
         call_throw!(self.enumerate_instance_extension_properties, ptr::null(), &mut extension_count as *mut u32, ptr::null_mut());
 
@@ -82,15 +85,15 @@ impl GlobalFnTable {
             extensions.resize(extension_count as usize, VkExtensionProperties::default());
 
             call_throw!(self.enumerate_instance_extension_properties, ptr::null(), &mut extension_count as *mut u32, extensions.as_mut_ptr());
-            return extensions;
+            return Ok(extensions);
         }
         else
         {
-            return Vec::<VkExtensionProperties>::with_capacity(0);
+            return Ok(Vec::<VkExtensionProperties>::with_capacity(0));
         }
     }
 
-    pub fn enumerate_instance_layers(&self) -> Vec<VkLayerProperties> {
+    pub fn enumerate_instance_layers(&self) -> Result<Vec<VkLayerProperties>, RenderError> {
         let mut layer_count: u32 = 0;
         call_throw!(self.enumerate_instance_layer_properties, &mut layer_count as *mut u32, ptr::null_mut());
 
@@ -99,11 +102,11 @@ impl GlobalFnTable {
             layers.resize(layer_count as usize, VkLayerProperties::default());
 
             call_throw!(self.enumerate_instance_layer_properties, &mut layer_count as *mut u32, layers.as_mut_ptr());
-            return layers;
+            return Ok(layers);
         }
         else
         {
-            return Vec::<VkLayerProperties>::with_capacity(0);
+            return Ok(Vec::<VkLayerProperties>::with_capacity(0));
         }
     }
 }
@@ -122,6 +125,7 @@ pub struct InstanceFnTable
     pub(crate) get_gpu_memory_properties2:   FN_vkGetPhysicalDeviceMemoryProperties2,
     pub(crate) get_gpu_properties:           FN_vkGetPhysicalDeviceProperties,
     pub(crate) get_gpu_features:             FN_vkGetPhysicalDeviceFeatures,
+    pub(crate) get_gpu_features2:            FN_vkGetPhysicalDeviceFeatures2,
 
     pub(crate) get_gpu_surface_capabilities: FN_vkGetPhysicalDeviceSurfaceCapabilitiesKHR,
     pub(crate) get_gpu_surface_support:      FN_vkGetPhysicalDeviceSurfaceSupportKHR,
@@ -143,7 +147,7 @@ pub struct InstanceFnTable
 }
 
 impl InstanceFnTable {
-    pub fn enumerate_gpu_present_modes(&self, gpu: VkPhysicalDevice, surface: VkSurfaceKHR) -> Vec<VkPresentModeKHR> {
+    pub fn enumerate_gpu_present_modes(&self, gpu: VkPhysicalDevice, surface: VkSurfaceKHR) -> Result<Vec<VkPresentModeKHR>, RenderError> {
         let mut present_mode_count: u32 = 0;
         call_throw!(self.get_gpu_surface_present_modes, gpu, surface, &mut present_mode_count, ptr::null_mut());
 
@@ -152,13 +156,13 @@ impl InstanceFnTable {
             present_mdoes.resize(present_mode_count as usize, VkPresentModeKHR::default());
             call_throw!(self.get_gpu_surface_present_modes, gpu, surface, &mut present_mode_count, present_mdoes.as_mut_ptr());
 
-            return present_mdoes;
+            return Ok(present_mdoes);
         } else {
-            return Vec::with_capacity(0);
+            return Ok(Vec::with_capacity(0));
         }
     }
 
-    pub fn enumerate_gpu_surface_formats(&self, gpu: VkPhysicalDevice, surface: VkSurfaceKHR) -> Vec<VkSurfaceFormatKHR> {
+    pub fn enumerate_gpu_surface_formats(&self, gpu: VkPhysicalDevice, surface: VkSurfaceKHR) -> Result<Vec<VkSurfaceFormatKHR>, RenderError> {
         let mut format_count: u32 = 0;
         call_throw!(self.get_gpu_surface_formats, gpu, surface, &mut format_count, ptr::null_mut());
 
@@ -167,9 +171,9 @@ impl InstanceFnTable {
             formats.resize(format_count as usize, VkSurfaceFormatKHR::default());
             call_throw!(self.get_gpu_surface_formats, gpu, surface, &mut format_count, formats.as_mut_ptr());
 
-            return formats;
+            return Ok(formats);
         } else {
-            return Vec::with_capacity(0);
+            return Ok(Vec::with_capacity(0));
         }
     }
 
@@ -188,7 +192,7 @@ impl InstanceFnTable {
         }
     }
 
-    pub fn enumerate_device_extensions(&self, gpu: VkPhysicalDevice) -> Vec<VkExtensionProperties> {
+    pub fn enumerate_device_extensions(&self, gpu: VkPhysicalDevice) -> Result<Vec<VkExtensionProperties>, RenderError> {
         let mut ext_count: u32 = 0;
         call_throw!(self.enum_gpu_ext_props, gpu, ptr::null_mut(), &mut ext_count as *mut u32, ptr::null_mut());
 
@@ -197,13 +201,13 @@ impl InstanceFnTable {
             extensions.resize(ext_count as usize, VkExtensionProperties::default());
             call_throw!(self.enum_gpu_ext_props, gpu, ptr::null_mut(), &mut ext_count as *mut u32, extensions.as_mut_ptr());
 
-            return extensions;
+            return Ok(extensions);
         } else {
-            return Vec::with_capacity(0);
+            return Ok(Vec::with_capacity(0));
         }
     }
 
-    pub fn enumerate_gpus(&self, instance: VkInstance) -> Vec<VkPhysicalDevice> {
+    pub fn enumerate_gpus(&self, instance: VkInstance) -> Result<Vec<VkPhysicalDevice>, RenderError> {
         let mut device_count: u32 = 0;
         call_throw!(self.enum_physical_devices, instance, &mut device_count as *mut u32, ptr::null_mut());
 
@@ -213,9 +217,9 @@ impl InstanceFnTable {
 
             call_throw!(self.enum_physical_devices, instance, &mut device_count as *mut u32, gpus.as_mut_ptr());
 
-            return gpus;
+            return Ok(gpus);
         } else {
-            return Vec::with_capacity(0);
+            return Ok(Vec::with_capacity(0));
         }
     }
 }
@@ -312,6 +316,11 @@ pub struct DeviceFnTable
     pub cmd_draw_indexed:                FN_vkCmdDrawIndexed,
     pub cmd_copy_buffer_to_image:        FN_vkCmdCopyBufferToImage,
     pub destroy_sampler:                 FN_vkDestroySampler,
+    pub cmd_execute_commands:            FN_vkCmdExecuteCommands,
+    pub cmd_set_depth_bias:              FN_vkCmdSetDepthBias,
+    pub cmd_draw_indexed_indirect:       FN_vkCmdDrawIndexedIndirect,
+    pub cmd_draw_indexed_indirect_count: FN_vkCmdDrawIndexedIndirectCount,
+    pub cmd_fill_buffer:                 FN_vkCmdFillBuffer,
 }
 
 /* ======================================================================== */
@@ -397,6 +406,7 @@ pub fn load_instance_functions(gbl: &GlobalFnTable, inst: VkInstance) -> Result<
         get_gpu_memory_properties2:      get_inst_procaddr!(inst, vkGetPhysicalDeviceMemoryProperties2),
         get_gpu_properties:              get_inst_procaddr!(inst, vkGetPhysicalDeviceProperties),
         get_gpu_features:                get_inst_procaddr!(inst, vkGetPhysicalDeviceFeatures),
+        get_gpu_features2:               get_inst_procaddr!(inst, vkGetPhysicalDeviceFeatures2),
         get_gpu_queue_family_properties: get_inst_procaddr!(inst, vkGetPhysicalDeviceQueueFamilyProperties),
         get_gpu_surface_present_modes:   get_inst_procaddr!(inst, vkGetPhysicalDeviceSurfacePresentModesKHR),
         get_gpu_surface_capabilities:    get_inst_procaddr!(inst, vkGetPhysicalDeviceSurfaceCapabilitiesKHR),
@@ -520,6 +530,11 @@ pub fn load_device_functions(gbl: &GlobalFnTable, inst: VkInstance, device: VkDe
         cmd_draw_indexed:                get_device_procaddr!(vkCmdDrawIndexed),
         cmd_copy_buffer_to_image:        get_device_procaddr!(vkCmdCopyBufferToImage),
         destroy_sampler:                 get_device_procaddr!(vkDestroySampler),
+        cmd_execute_commands:            get_device_procaddr!(vkCmdExecuteCommands),
+        cmd_set_depth_bias:              get_device_procaddr!(vkCmdSetDepthBias),
+        cmd_draw_indexed_indirect:       get_device_procaddr!(vkCmdDrawIndexedIndirect),
+        cmd_draw_indexed_indirect_count: get_device_procaddr!(vkCmdDrawIndexedIndirectCount),
+        cmd_fill_buffer:                 get_device_procaddr!(vkCmdFillBuffer),
     };
 
     Ok(funcs)
@@ -689,12 +704,25 @@ pub fn make_depth_attachment_info(view: VkImageView, layout: VkImageLayout) -> V
     return attachment_info;
 }
 
+/// Resolves a multisampled attachment into `resolve_view` when rendering ends. Only the resolved image is kept, the
+/// multisampled contents are discarded.
+#[inline(always)]
+pub fn make_resolve_attachment_info(attachment: VkRenderingAttachmentInfo, resolve_view: VkImageView, resolve_layout: VkImageLayout, resolve_mode: VkResolveModeFlagBits) -> VkRenderingAttachmentInfo {
+    let mut attachment_info = attachment;
+    attachment_info.resolveMode        = resolve_mode;
+    attachment_info.resolveImageView   = resolve_view;
+    attachment_info.resolveImageLayout = resolve_layout;
+    attachment_info.storeOp            = VK_ATTACHMENT_STORE_OP_DONT_CARE;
+
+    return attachment_info;
+}
+
 #[inline(always)]
 pub fn make_rendering_info(render_extent: VkExtent2D, color_attachment: *const VkRenderingAttachmentInfo, depth_attachment: *const VkRenderingAttachmentInfo) -> VkRenderingInfo {
     let mut render_info = VkRenderingInfo::default();
     render_info.renderArea           = VkRect2D{ offset: VkOffset2D::default(), extent: render_extent };
     render_info.layerCount           = 1;
-    render_info.colorAttachmentCount = 1;
+    render_info.colorAttachmentCount = if color_attachment.is_null() { 0 } else { 1 };
     render_info.pColorAttachments    = color_attachment;
     render_info.pDepthAttachment     = depth_attachment;;
 
@@ -0,0 +1,98 @@
+use crate::math::{ float4::*, float4x4::* };
+
+use super::command_buffer::{ PointLightInfo, SpotLightInfo };
+use super::shader::{ GpuLight, LightCullPushConstants };
+
+//
+// Clustered Point and Spot Lights
+//
+// The view frustum is split into a grid of clusters: CLUSTER_GRID_X x CLUSTER_GRID_Y screen-space tiles, each
+// sliced into CLUSTER_GRID_Z depth slices. The depth slices are spaced exponentially, so clusters far from the
+// camera are about as deep as they are wide.
+//   1. Upload - the engine's lights are packed into a storage buffer every frame.
+//   2. Cull   - a compute pass (light_cull.comp) tests every light's bounding sphere against every cluster and
+//               writes the indices of the lights that touch the cluster into the light grid.
+//   3. Shade  - the lit shader finds its cluster from the fragment position and view depth, and only evaluates
+//               the lights listed in the cluster.
+//
+// A cluster holds at most MAX_LIGHTS_PER_CLUSTER lights, any more are dropped from that cluster.
+//
+
+// Must match lights.glsl
+pub(crate) const CLUSTER_GRID_X:         u32   = 16;
+pub(crate) const CLUSTER_GRID_Y:         u32   = 9;
+pub(crate) const CLUSTER_GRID_Z:         u32   = 24;
+pub(crate) const MAX_LIGHTS_PER_CLUSTER: usize = 127;
+
+pub(crate) const CLUSTER_COUNT: usize = (CLUSTER_GRID_X * CLUSTER_GRID_Y * CLUSTER_GRID_Z) as usize;
+
+/// Lights past this count are ignored
+pub const MAX_LIGHTS: usize = 1024;
+
+// A cluster is a light count followed by its light indices
+pub(crate) const CLUSTER_SIZE: usize = (1 + MAX_LIGHTS_PER_CLUSTER) * std::mem::size_of::<u32>();
+
+pub(crate) const LIGHT_TYPE_POINT: f32 = 0.0;
+pub(crate) const LIGHT_TYPE_SPOT:  f32 = 1.0;
+
+/// Packs the engine's lights into the layout the shaders expect. Point lights come first, then spot lights.
+pub(crate) fn pack_lights(point_lights: &[PointLightInfo], spot_lights: &[SpotLightInfo]) -> Vec<GpuLight> {
+    let total_count = point_lights.len() + spot_lights.len();
+    if total_count > MAX_LIGHTS {
+        println!("[WARN] :: Lights :: {} lights were submitted, only the first {} are rendered.", total_count, MAX_LIGHTS);
+    }
+
+    let mut result = Vec::<GpuLight>::with_capacity(total_count.min(MAX_LIGHTS));
+
+    for light in point_lights.iter().take(MAX_LIGHTS) {
+        result.push(GpuLight{
+            position_range:  Float4::new(light.position.x, light.position.y, light.position.z, light.range.max(0.0)),
+            color_intensity: Float4::new(light.color.x, light.color.y, light.color.z, light.intensity),
+            direction_type:  Float4::new(0.0, 0.0, 0.0, LIGHT_TYPE_POINT),
+            cone:            Float4::zero(),
+        });
+    }
+
+    let remaining = MAX_LIGHTS - result.len();
+    for light in spot_lights.iter().take(remaining) {
+        let direction = light.direction.unit();
+
+        // Keep the inner cone inside the outer cone, so the falloff never divides by zero
+        let outer_cos = light.outer_cone_angle.clamp(0.0, 89.0).to_radians().cos();
+        let inner_cos = light.inner_cone_angle.clamp(0.0, 89.0).to_radians().cos().max(outer_cos + 1e-4);
+
+        result.push(GpuLight{
+            position_range:  Float4::new(light.position.x, light.position.y, light.position.z, light.range.max(0.0)),
+            color_intensity: Float4::new(light.color.x, light.color.y, light.color.z, light.intensity),
+            direction_type:  Float4::new(direction.x, direction.y, direction.z, LIGHT_TYPE_SPOT),
+            cone:            Float4::new(outer_cos, inner_cos, 0.0, 0.0),
+        });
+    }
+
+    return result;
+}
+
+/// Push constants for the light cull pass.
+///   @assume: the projection is a symmetric perspective projection, as made by Float4x4::get_perspective_matrix.
+pub(crate) fn make_light_cull_constants(view: Float4x4, proj: Float4x4, light_count: usize) -> LightCullPushConstants {
+    let (near, far) = get_clip_planes(proj);
+
+    // A point one unit in front of the camera lands on the x and y scales of the projection
+    let scale = proj.translate_point(Float4::new(1.0, 1.0, -1.0, 1.0));
+
+    return LightCullPushConstants{
+        view,
+        projection:  Float4::new(scale.x / scale.w, scale.y / scale.w, near, far),
+        light_count: Float4::new(light_count as f32, 0.0, 0.0, 0.0),
+    };
+}
+
+/// Recovers the near and far plane distances of a perspective projection with a [-1, 1] clip depth.
+pub(crate) fn get_clip_planes(proj: Float4x4) -> (f32, f32) {
+    let inv_proj = proj.invert();
+
+    let near = inv_proj.translate_point(Float4::new(0.0, 0.0, -1.0, 1.0));
+    let far  = inv_proj.translate_point(Float4::new(0.0, 0.0,  1.0, 1.0));
+
+    return (-near.z / near.w, -far.z / far.w);
+}
@@ -0,0 +1,827 @@
+use std::collections::HashMap;
+
+use crate::math::{ float3::*, float4::* };
+
+use super::mesh::{ Vertex, compute_bounding_box };
+
+//
+// Mesh Levels of Detail
+//
+// A mesh can have up to MAX_MESH_LODS levels of detail. They share the mesh's vertex buffer, each LOD is a range of
+// its index buffer, LOD 0 being the full mesh. The coarser LODs are made ahead of time by an asset cooker, with
+// generate_lods(), and saved with GeneratedLods::to_bytes() next to the mesh (`testbed --cook-lods` cooks the
+// testbed's meshes). Importers load them back with GeneratedLods::from_bytes(), which rejects files cooked from a
+// different mesh, and only generate them on load when the file is missing or stale:
+//   1. Simplify - quadric edge collapse (Garland and Heckbert, "Surface Simplification Using Quadric Error Metrics").
+//                 Every vertex accumulates the planes of the triangles around it, and the edges whose collapse moves
+//                 the surface the least are collapsed first. A vertex can only collapse onto the position of one of
+//                 its neighbours, so no new vertices are made. Collapses happen between positions, vertices with the
+//                 same position but different normals or UVs (seams) follow along, each corner picking the vertex
+//                 with the closest attributes at its new position. Vertices on open borders, or on non-manifold
+//                 edges, never move, and collapses that would fold triangles over are skipped.
+//   2. Select   - every frame the RenderSystem measures the screen size of each mesh, the fraction of the screen's
+//                 height its bounding sphere covers, and picks the coarsest LOD whose screen size it is under. A mesh
+//                 only switches once it is a margin past the threshold (the hysteresis), so a mesh sitting right on
+//                 a threshold doesn't pop back and forth between two LODs.
+//
+// Instanced meshes are drawn with the LOD of their largest instance on screen, and skinned meshes always use LOD 0.
+// Selection can be tuned, or a LOD forced, at runtime with RenderCommand::UpdateLodSettings.
+//
+
+pub const MAX_MESH_LODS: usize = 4;
+
+/// A LOD is only kept if it has at most this fraction of the previous LOD's indices.
+const MIN_LOD_REDUCTION: f32 = 0.9;
+
+/// Collapses that turn a triangle's normal by more than ~75 degrees (the cosine is under this) are skipped.
+const MIN_NORMAL_COSINE: f64 = 0.25;
+
+/// Simplification passes before giving up on reaching the target.
+const MAX_SIMPLIFY_PASSES: usize = 64;
+
+/// One level of detail of a mesh, a range of its index buffer.
+#[derive(Clone, Copy, Debug)]
+pub struct MeshLod {
+    pub first_index: u32,
+    pub index_count: u32,
+    pub screen_size: f32, // drawn while the mesh covers less than this fraction of the screen height, f32::MAX for LOD 0
+}
+
+impl Default for MeshLod {
+    fn default() -> Self {
+        Self{
+            first_index: 0,
+            index_count: 0,
+            screen_size: f32::MAX,
+        }
+    }
+}
+
+/// How generate_lods() builds the LODs of a mesh.
+#[derive(Clone, Copy, Debug)]
+pub struct LodGenerationSettings {
+    pub max_lods:    usize, // including LOD 0, 1 to MAX_MESH_LODS
+    pub reduction:   f32,   // each LOD aims for this fraction of the previous LOD's triangles
+    pub max_error:   f32,   // quadric error LOD 1 may have, relative to the size of the mesh's bounds, see simplify()
+    pub screen_size: f32,   // screen size LOD 1 is drawn below, halved for every further LOD
+}
+
+impl Default for LodGenerationSettings {
+    fn default() -> Self {
+        Self{
+            max_lods:    MAX_MESH_LODS,
+            reduction:   0.5,
+            max_error:   0.02,
+            screen_size: 0.5,
+        }
+    }
+}
+
+/// Cooked LOD files start with this, followed by the format version.
+const COOKED_LODS_MAGIC: [u8; 4] = *b"CLOD";
+const COOKED_LODS_VERSION: u32   = 1;
+
+/// The index buffer of a mesh with every LOD, and the range of each LOD in it.
+pub struct GeneratedLods {
+    pub indices: Vec<u32>,
+    pub lods:    Vec<MeshLod>,
+}
+
+impl GeneratedLods {
+    /// Serializes the LODs for an asset cooker, little endian: the magic and version, the mesh's vertex count, the
+    /// LOD count and each LOD's range and screen size, then the index count and the indices.
+    pub fn to_bytes(&self, vertex_count: usize) -> Vec<u8> {
+        let mut bytes = Vec::<u8>::with_capacity(20 + self.lods.len() * 12 + self.indices.len() * 4);
+        bytes.extend_from_slice(&COOKED_LODS_MAGIC);
+        bytes.extend_from_slice(&COOKED_LODS_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(vertex_count as u32).to_le_bytes());
+
+        bytes.extend_from_slice(&(self.lods.len() as u32).to_le_bytes());
+        for lod in &self.lods {
+            bytes.extend_from_slice(&lod.first_index.to_le_bytes());
+            bytes.extend_from_slice(&lod.index_count.to_le_bytes());
+            bytes.extend_from_slice(&lod.screen_size.to_le_bytes());
+        }
+
+        bytes.extend_from_slice(&(self.indices.len() as u32).to_le_bytes());
+        for index in &self.indices {
+            bytes.extend_from_slice(&index.to_le_bytes());
+        }
+
+        return bytes;
+    }
+
+    /// Reads LODs written by to_bytes() for the mesh with these indices and vertex count. Returns None if the bytes
+    /// are malformed, or were cooked from a different mesh (the vertex count or LOD 0's indices don't match), so the
+    /// importer can generate the LODs again instead.
+    pub fn from_bytes(bytes: &[u8], indices: &[u32], vertex_count: usize) -> Option<GeneratedLods> {
+        if bytes.get(0..4)? != COOKED_LODS_MAGIC
+            || read_u32(bytes, 4)? != COOKED_LODS_VERSION
+            || read_u32(bytes, 8)? as usize != vertex_count {
+            return None;
+        }
+
+        let lod_count = read_u32(bytes, 12)? as usize;
+        if lod_count == 0 || lod_count > MAX_MESH_LODS {
+            return None;
+        }
+
+        let mut lods = Vec::<MeshLod>::with_capacity(lod_count);
+        for lod in 0..lod_count {
+            let offset = 16 + lod * 12;
+            lods.push(MeshLod{
+                first_index: read_u32(bytes, offset)?,
+                index_count: read_u32(bytes, offset + 4)?,
+                screen_size: f32::from_bits(read_u32(bytes, offset + 8)?),
+            });
+        }
+
+        let indices_offset = 16 + lod_count * 12;
+        let index_count    = read_u32(bytes, indices_offset)? as usize;
+        let index_bytes    = bytes.get(indices_offset + 4..)?;
+        if index_bytes.len() != index_count.checked_mul(4)? {
+            return None;
+        }
+
+        let result = GeneratedLods{
+            indices: index_bytes.chunks_exact(4).map(|index| u32::from_le_bytes([index[0], index[1], index[2], index[3]])).collect(),
+            lods,
+        };
+
+        let is_lod_valid = |lod: &MeshLod| {
+            let end = lod.first_index as usize + lod.index_count as usize;
+            return lod.index_count > 0 && lod.index_count % 3 == 0 && end <= result.indices.len() && lod.screen_size > 0.0;
+        };
+
+        if !result.lods.iter().all(is_lod_valid)
+            || result.lods[0].first_index != 0
+            || result.lods[0].index_count as usize != indices.len()
+            || result.indices[..indices.len()] != *indices
+            || result.indices.iter().any(|&index| index as usize >= vertex_count) {
+            return None;
+        }
+
+        return Some(result);
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let bytes = bytes.get(offset..offset.checked_add(4)?)?;
+    return Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]));
+}
+
+/// Builds the coarser LODs of a mesh, appended after its own indices, which become LOD 0. Every LOD is simplified
+/// from the full mesh. Each LOD is drawn at half the screen size of the previous one, so it may deviate twice as far
+/// for the same error in pixels. Stops early once simplifying no longer removes enough triangles, a mesh that can't
+/// be simplified only gets LOD 0.
+pub fn generate_lods(vertices: &[Vertex], indices: &[u32], settings: &LodGenerationSettings) -> GeneratedLods {
+    let mut result = GeneratedLods{
+        indices: indices.to_vec(),
+        lods:    vec![MeshLod{ first_index: 0, index_count: indices.len() as u32, screen_size: f32::MAX }],
+    };
+
+    let max_lods = settings.max_lods.clamp(1, MAX_MESH_LODS);
+
+    let mut previous_count = indices.len();
+    let mut max_error      = settings.max_error;
+    let mut screen_size    = settings.screen_size;
+
+    for level in 1..max_lods {
+        let target_count = (indices.len() as f32 * settings.reduction.powi(level as i32)) as usize;
+
+        let lod_indices = simplify(vertices, indices, target_count, max_error);
+        if lod_indices.is_empty() || lod_indices.len() as f32 > previous_count as f32 * MIN_LOD_REDUCTION {
+            break;
+        }
+
+        result.lods.push(MeshLod{
+            first_index: result.indices.len() as u32,
+            index_count: lod_indices.len() as u32,
+            screen_size,
+        });
+        result.indices.extend_from_slice(&lod_indices);
+
+        previous_count = lod_indices.len();
+        max_error     *= 2.0;
+        screen_size   *= 0.5;
+    }
+
+    return result;
+}
+
+/// Error quadric of a vertex, the sum of the squared distances to the planes of its triangles. Each plane is
+/// weighted by its triangle's area, the error is divided by the total weight to get a distance back out.
+#[derive(Clone, Copy, Default)]
+struct Quadric {
+    a00: f64, a01: f64, a02: f64, // symmetric 3x3 matrix, the sum of n * n^T
+    a11: f64, a12: f64,
+    a22: f64,
+    b0:  f64, b1: f64, b2: f64,   // sum of d * n
+    c:   f64,                     // sum of d * d
+    weight: f64,
+}
+
+impl Quadric {
+    /// The plane dot(normal, p) + d = 0, `normal` is unit length.
+    fn from_plane(normal: [f64; 3], d: f64, weight: f64) -> Quadric {
+        let [x, y, z] = normal;
+        return Quadric{
+            a00: weight * x * x, a01: weight * x * y, a02: weight * x * z,
+            a11: weight * y * y, a12: weight * y * z,
+            a22: weight * z * z,
+            b0:  weight * d * x, b1: weight * d * y, b2: weight * d * z,
+            c:   weight * d * d,
+            weight,
+        };
+    }
+
+    fn add(&self, other: &Quadric) -> Quadric {
+        return Quadric{
+            a00: self.a00 + other.a00, a01: self.a01 + other.a01, a02: self.a02 + other.a02,
+            a11: self.a11 + other.a11, a12: self.a12 + other.a12,
+            a22: self.a22 + other.a22,
+            b0:  self.b0 + other.b0, b1: self.b1 + other.b1, b2: self.b2 + other.b2,
+            c:   self.c + other.c,
+            weight: self.weight + other.weight,
+        };
+    }
+
+    /// Weighted RMS distance from `p` to the planes.
+    fn get_error(&self, p: [f64; 3]) -> f64 {
+        let [x, y, z] = p;
+
+        let squared = self.a00 * x * x + self.a11 * y * y + self.a22 * z * z
+            + 2.0 * (self.a01 * x * y + self.a02 * x * z + self.a12 * y * z)
+            + 2.0 * (self.b0 * x + self.b1 * y + self.b2 * z)
+            + self.c;
+
+        return (squared.max(0.0) / self.weight.max(1e-20)).sqrt();
+    }
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    return [a[0] - b[0], a[1] - b[1], a[2] - b[2]];
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    return [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]];
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    return a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+}
+
+/// Unnormalized triangle normal, its length is twice the triangle's area.
+fn triangle_normal(p0: [f64; 3], p1: [f64; 3], p2: [f64; 3]) -> [f64; 3] {
+    return cross(sub(p1, p0), sub(p2, p0));
+}
+
+/// Maps every vertex to the first vertex with the same position, so the simplifier can work on the surface's
+/// topology rather than on the attribute seams of the vertex buffer.
+fn weld_positions(vertices: &[Vertex]) -> Vec<u32> {
+    let mut first_at = HashMap::<[u32; 3], u32>::with_capacity(vertices.len());
+
+    return vertices.iter().enumerate().map(|(index, vertex)| {
+        // + 0.0 turns -0.0 into 0.0, so both hash the same
+        let p   = vertex.position;
+        let key = [(p.x + 0.0).to_bits(), (p.y + 0.0).to_bits(), (p.z + 0.0).to_bits()];
+        *first_at.entry(key).or_insert(index as u32)
+    }).collect();
+}
+
+/// Positions that must not move: the ends of open border edges, and of edges shared by more than two triangles.
+fn find_locked_positions(position_of: &[u32], indices: &[u32]) -> Vec<bool> {
+    let mut edge_counts = HashMap::<(u32, u32), u32>::new();
+
+    for triangle in indices.chunks_exact(3) {
+        let corners = [position_of[triangle[0] as usize], position_of[triangle[1] as usize], position_of[triangle[2] as usize]];
+        for edge in 0..3 {
+            let (a, b) = (corners[edge], corners[(edge + 1) % 3]);
+            if a != b {
+                *edge_counts.entry((a, b)).or_insert(0) += 1;
+            }
+        }
+    }
+
+    let mut locked = vec![false; position_of.len()];
+    for (&(a, b), &count) in &edge_counts {
+        // A border edge has no twin going the other way
+        if count > 1 || !edge_counts.contains_key(&(b, a)) {
+            locked[a as usize] = true;
+            locked[b as usize] = true;
+        }
+    }
+
+    return locked;
+}
+
+/// The vertex of `wedges`, the vertices at one position, whose attributes are the closest to `vertex`'s.
+fn find_closest_wedge(vertices: &[Vertex], wedges: &[u32], vertex: &Vertex) -> u32 {
+    let distance = |candidate: &Vertex| -> f32 {
+        let du = candidate.uv_x - vertex.uv_x;
+        let dv = candidate.uv_y - vertex.uv_y;
+        return (candidate.normal - vertex.normal).squared_length() + du * du + dv * dv;
+    };
+
+    return *wedges.iter().min_by(|a, b| distance(&vertices[**a as usize]).total_cmp(&distance(&vertices[**b as usize]))).expect("Every position has a vertex.");
+}
+
+struct Collapse {
+    from: u32, // positions, see weld_positions()
+    to:   u32,
+    cost: f64,
+}
+
+/// Simplifies a triangle list towards `target_index_count` indices with quadric edge collapses. A collapse is only
+/// made while its quadric error, the RMS distance to the original planes around the collapsed vertices, is under
+/// `max_error`, relative to the size of the mesh's bounds. The RMS hides the occasional plane that moves further, the
+/// farthest the surface moves is usually 2 to 3 times `max_error`. The result indexes the same vertices, and can end
+/// up with more indices than the target if the error or the mesh's borders don't allow more collapses.
+pub fn simplify(vertices: &[Vertex], indices: &[u32], target_index_count: usize, max_error: f32) -> Vec<u32> {
+    let mut result = indices.to_vec();
+    if indices.len() < 3 || target_index_count >= indices.len() {
+        return result;
+    }
+
+    let bounds = compute_bounding_box(vertices);
+    let extent = (bounds.max - bounds.min).length() as f64;
+    if extent <= 0.0 {
+        return result;
+    }
+
+    let error_limit = max_error as f64 * extent;
+    let position    = |vertex: u32| -> [f64; 3] {
+        let p = vertices[vertex as usize].position;
+        [p.x as f64, p.y as f64, p.z as f64]
+    };
+
+    let position_of = weld_positions(vertices);
+    let locked      = find_locked_positions(&position_of, indices);
+
+    let mut wedges = vec![Vec::<u32>::new(); vertices.len()];
+    for (vertex, &at) in position_of.iter().enumerate() {
+        wedges[at as usize].push(vertex as u32);
+    }
+
+    let mut quadrics = vec![Quadric::default(); vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        let corners = [position_of[triangle[0] as usize], position_of[triangle[1] as usize], position_of[triangle[2] as usize]];
+        let normal  = triangle_normal(position(corners[0]), position(corners[1]), position(corners[2]));
+
+        let length = dot(normal, normal).sqrt();
+        if length < 1e-20 {
+            continue;
+        }
+
+        let unit  = [normal[0] / length, normal[1] / length, normal[2] / length];
+        let plane = Quadric::from_plane(unit, -dot(unit, position(corners[0])), length * 0.5);
+        for corner in corners {
+            quadrics[corner as usize] = quadrics[corner as usize].add(&plane);
+        }
+    }
+
+    for _ in 0..MAX_SIMPLIFY_PASSES {
+        if result.len() <= target_index_count {
+            break;
+        }
+
+        let triangle_count = result.len() / 3;
+        let corners_of     = |triangle: usize| -> [u32; 3] {
+            [position_of[result[triangle * 3] as usize], position_of[result[triangle * 3 + 1] as usize], position_of[result[triangle * 3 + 2] as usize]]
+        };
+
+        let mut triangles_at = vec![Vec::<u32>::new(); vertices.len()];
+        let mut edges        = Vec::<(u32, u32)>::new();
+
+        for triangle in 0..triangle_count {
+            let corners = corners_of(triangle);
+            for edge in 0..3 {
+                triangles_at[corners[edge] as usize].push(triangle as u32);
+
+                let (a, b) = (corners[edge], corners[(edge + 1) % 3]);
+                edges.push((a.min(b), a.max(b)));
+            }
+        }
+
+        edges.sort_unstable();
+        edges.dedup();
+
+        // The cheaper direction of every edge that can collapse
+        let mut collapses = Vec::<Collapse>::with_capacity(edges.len());
+        for (a, b) in edges {
+            let quadric = quadrics[a as usize].add(&quadrics[b as usize]);
+
+            let a_to_b = if locked[a as usize] { f64::MAX } else { quadric.get_error(position(b)) };
+            let b_to_a = if locked[b as usize] { f64::MAX } else { quadric.get_error(position(a)) };
+
+            if a_to_b <= b_to_a && a_to_b <= error_limit {
+                collapses.push(Collapse{ from: a, to: b, cost: a_to_b });
+            } else if b_to_a <= error_limit {
+                collapses.push(Collapse{ from: b, to: a, cost: b_to_a });
+            }
+        }
+
+        collapses.sort_by(|a, b| a.cost.total_cmp(&b.cost));
+
+        // Both ends of a collapse are left alone for the rest of the pass, so every moved position is one lookup away
+        let mut collapsed_to = (0..vertices.len() as u32).collect::<Vec<u32>>();
+        let mut touched      = vec![false; vertices.len()];
+
+        let triangles_to_remove   = (result.len() - target_index_count).div_ceil(3);
+        let mut triangles_removed = 0;
+
+        for collapse in &collapses {
+            let (from, to) = (collapse.from as usize, collapse.to as usize);
+            if touched[from] || touched[to] {
+                continue;
+            }
+
+            let mut removed   = 0;
+            let mut is_valid  = true;
+            let mut opposite  = Vec::<u32>::new(); // third corners of the triangles on the edge
+            let mut neighbors = Vec::<u32>::new(); // corners of from's other triangles
+
+            for &triangle in &triangles_at[from] {
+                let corners = corners_of(triangle as usize).map(|corner| collapsed_to[corner as usize]);
+                if corners[0] == corners[1] || corners[1] == corners[2] || corners[0] == corners[2] {
+                    continue; // already collapsed this pass
+                }
+
+                if corners.contains(&collapse.to) {
+                    removed += 1;
+                    opposite.extend(corners.iter().filter(|&&corner| corner != collapse.from && corner != collapse.to));
+                    continue;
+                }
+
+                let before = triangle_normal(position(corners[0]), position(corners[1]), position(corners[2]));
+                let moved  = corners.map(|corner| if corner == collapse.from { collapse.to } else { corner });
+                let after  = triangle_normal(position(moved[0]), position(moved[1]), position(moved[2]));
+
+                if dot(before, after) <= MIN_NORMAL_COSINE * (dot(before, before) * dot(after, after)).sqrt() {
+                    is_valid = false;
+                    break;
+                }
+
+                neighbors.extend(corners.iter().filter(|&&corner| corner != collapse.from));
+            }
+
+            if !is_valid || removed == 0 {
+                continue;
+            }
+
+            // Link condition: the ends may only share the neighbours across the edge, or the collapse pinches the
+            // surface into a non-manifold edge
+            let is_shared = |corner: &u32| -> bool {
+                triangles_at[to].iter().any(|&triangle| {
+                    let corners = corners_of(triangle as usize).map(|other| collapsed_to[other as usize]);
+                    corners.contains(&collapse.to) && corners.contains(corner)
+                })
+            };
+
+            if neighbors.iter().any(|corner| !opposite.contains(corner) && is_shared(corner)) {
+                continue;
+            }
+
+            collapsed_to[from] = collapse.to;
+            quadrics[to]       = quadrics[to].add(&quadrics[from]);
+            touched[from]      = true;
+            touched[to]        = true;
+
+            triangles_removed += removed;
+            if triangles_removed >= triangles_to_remove {
+                break;
+            }
+        }
+
+        if triangles_removed == 0 {
+            break;
+        }
+
+        // Move the collapsed corners to their new position's closest vertex, and drop the degenerate triangles
+        let mut simplified = Vec::<u32>::with_capacity(result.len());
+        for triangle in result.chunks_exact(3) {
+            let moved = [triangle[0], triangle[1], triangle[2]].map(|vertex| {
+                let to = collapsed_to[position_of[vertex as usize] as usize];
+                if to == position_of[vertex as usize] { vertex } else { find_closest_wedge(vertices, &wedges[to as usize], &vertices[vertex as usize]) }
+            });
+
+            let corners = moved.map(|vertex| position_of[vertex as usize]);
+            if corners[0] != corners[1] && corners[1] != corners[2] && corners[0] != corners[2] {
+                simplified.extend_from_slice(&moved);
+            }
+        }
+
+        result = simplified;
+    }
+
+    return result;
+}
+
+/// Fraction of the screen height covered by a world-space bounding sphere (xyz: center, w: radius), seen from
+/// `camera_pos` through a projection with the vertical scale `projection_scale` (proj[1][1]).
+pub(crate) fn get_screen_size(sphere: Float4, camera_pos: Float3, projection_scale: f32) -> f32 {
+    let distance = (Float3::new(sphere.x, sphere.y, sphere.z) - camera_pos).length();
+    if distance <= sphere.w {
+        return f32::MAX; // the camera is inside the bounds
+    }
+
+    return sphere.w * projection_scale.abs() / distance;
+}
+
+/// Picks the LOD of a mesh covering `screen_size` of the screen height, currently drawn with LOD `current`. The mesh
+/// switches to a coarser LOD once it is `hysteresis` (a fraction) below its screen size, and back to a finer LOD
+/// once it is `hysteresis` above the screen size of the current one.
+pub fn select_lod(lods: &[MeshLod], screen_size: f32, current: usize, hysteresis: f32) -> usize {
+    // The screen sizes shrink with every LOD, so the LODs the mesh is under are always the first ones
+    let coarsest_under = |scale: f32| -> usize {
+        return lods.iter().rposition(|lod| screen_size < lod.screen_size * scale).unwrap_or(0);
+    };
+
+    let finest   = coarsest_under(1.0 - hysteresis);
+    let coarsest = coarsest_under(1.0 + hysteresis);
+
+    return current.clamp(finest, coarsest);
+}
+
+/// How the RenderSystem picks the LOD of each mesh. Can be changed at runtime with RenderCommand::UpdateLodSettings.
+#[derive(Clone, Copy, Debug)]
+pub struct LodSettings {
+    pub enabled:    bool,        // off draws every mesh with LOD 0
+    pub hysteresis: f32,         // how far past a threshold a mesh must go before it switches, 0 to 0.9
+    pub lod_bias:   f32,         // scales the screen size of every mesh, above 1 keeps the finer LODs for longer
+    pub forced_lod: Option<u32>, // draws every mesh with this LOD, or its coarsest one if it has fewer
+}
+
+impl Default for LodSettings {
+    fn default() -> Self {
+        Self{
+            enabled:    true,
+            hysteresis: 0.1,
+            lod_bias:   1.0,
+            forced_lod: None,
+        }
+    }
+}
+
+impl LodSettings {
+    /// Clamps the settings to values the renderer can use.
+    pub fn sanitize(&self) -> LodSettings {
+        return LodSettings{
+            enabled:    self.enabled,
+            hysteresis: self.hysteresis.clamp(0.0, 0.9),
+            lod_bias:   self.lod_bias.max(0.01),
+            forced_lod: self.forced_lod,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::test_meshes::load_indexed_obj;
+
+    /// Closest point to `p` on the triangle a, b, c (Ericson, "Real-Time Collision Detection", 5.1.5).
+    fn closest_point_on_triangle(p: Float3, a: Float3, b: Float3, c: Float3) -> Float3 {
+        let ab = b - a;
+        let ac = c - a;
+        let ap = p - a;
+
+        let d1 = ab.dot(ap);
+        let d2 = ac.dot(ap);
+        if d1 <= 0.0 && d2 <= 0.0 {
+            return a;
+        }
+
+        let bp = p - b;
+        let d3 = ab.dot(bp);
+        let d4 = ac.dot(bp);
+        if d3 >= 0.0 && d4 <= d3 {
+            return b;
+        }
+
+        let vc = d1 * d4 - d3 * d2;
+        if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+            return a + ab * (d1 / (d1 - d3));
+        }
+
+        let cp = p - c;
+        let d5 = ab.dot(cp);
+        let d6 = ac.dot(cp);
+        if d6 >= 0.0 && d5 <= d6 {
+            return c;
+        }
+
+        let vb = d5 * d2 - d1 * d6;
+        if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+            return a + ac * (d2 / (d2 - d6));
+        }
+
+        let va = d3 * d6 - d5 * d4;
+        if va <= 0.0 && d4 - d3 >= 0.0 && d5 - d6 >= 0.0 {
+            return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+        }
+
+        let denominator = 1.0 / (va + vb + vc);
+        return a + ab * (vb * denominator) + ac * (vc * denominator);
+    }
+
+    /// Farthest any vertex of the mesh is from the simplified surface, relative to the size of the mesh's bounds.
+    fn get_relative_deviation(vertices: &[Vertex], simplified: &[u32]) -> f32 {
+        let bounds = compute_bounding_box(vertices);
+        let extent = (bounds.max - bounds.min).length();
+
+        let distance_to_surface = |p: Float3| -> f32 {
+            return simplified.chunks_exact(3).map(|triangle| {
+                let [a, b, c] = [0, 1, 2].map(|corner| vertices[triangle[corner] as usize].position);
+                (closest_point_on_triangle(p, a, b, c) - p).length()
+            }).fold(f32::MAX, f32::min);
+        };
+
+        return vertices.iter().map(|vertex| distance_to_surface(vertex.position)).fold(0.0, f32::max) / extent;
+    }
+
+    fn assert_valid_triangles(vertices: &[Vertex], indices: &[u32]) {
+        assert_eq!(indices.len() % 3, 0);
+        for triangle in indices.chunks_exact(3) {
+            assert!(triangle.iter().all(|index| (*index as usize) < vertices.len()));
+
+            let [a, b, c] = [0, 1, 2].map(|corner| vertices[triangle[corner] as usize].position);
+            assert!(!(a == b) && !(b == c) && !(a == c), "degenerate triangle {:?}", triangle);
+        }
+    }
+
+    fn get_sorted(indices: &[u32]) -> Vec<u32> {
+        let mut sorted = indices.to_vec();
+        sorted.sort();
+        return sorted;
+    }
+
+    #[test]
+    fn simplify_reaches_the_target() {
+        let (vertices, indices) = load_indexed_obj("suzanne.obj");
+
+        for fraction in [0.5, 0.25, 0.1] {
+            let target     = (indices.len() as f32 * fraction) as usize;
+            let simplified = simplify(&vertices, &indices, target, 1.0);
+
+            assert_valid_triangles(&vertices, &simplified);
+
+            // Every collapse removes the two triangles on its edge, so the target can be overshot by a collapse
+            assert!(simplified.len() <= target, "{} indices for a target of {}", simplified.len(), target);
+            assert!(simplified.len() + 4 * 3 >= target, "{} indices for a target of {}", simplified.len(), target);
+        }
+    }
+
+    #[test]
+    fn simplify_respects_the_error() {
+        let (vertices, indices) = load_indexed_obj("suzanne.obj");
+
+        // Suzanne's eyes and ears are open borders, which never move
+        let mut previous_count = 0;
+        for max_error in [0.005, 0.01, 0.02, 0.04] {
+            let simplified = simplify(&vertices, &indices, 0, max_error);
+            assert_valid_triangles(&vertices, &simplified);
+
+            // The quadric error is an RMS distance, the surface can move a few times further in places
+            let deviation = get_relative_deviation(&vertices, &simplified);
+            assert!(deviation <= max_error * 3.0, "deviated {} with a max error of {}", deviation, max_error);
+
+            // A larger error allows more collapses
+            assert!(previous_count == 0 || simplified.len() <= previous_count);
+            previous_count = simplified.len();
+        }
+
+        // Without any error allowed, only collapses within flat areas are possible
+        let simplified = simplify(&vertices, &indices, 0, 0.0);
+        assert!(get_relative_deviation(&vertices, &simplified) < 1e-4);
+    }
+
+    #[test]
+    fn simplify_keeps_a_cube() {
+        let (vertices, indices) = load_indexed_obj("cube.obj");
+
+        // Every collapse of a cube moves a corner off one of its faces
+        let simplified = simplify(&vertices, &indices, 0, 0.01);
+        assert_eq!(get_sorted(&simplified), get_sorted(&indices));
+    }
+
+    #[test]
+    fn generated_lods_shrink() {
+        let (vertices, indices) = load_indexed_obj("suzanne.obj");
+
+        let generated = generate_lods(&vertices, &indices, &LodGenerationSettings::default());
+        assert!(generated.lods.len() > 1 && generated.lods.len() <= MAX_MESH_LODS);
+
+        // LOD 0 is the mesh as it was, the others follow it in the index buffer
+        assert_eq!(&generated.indices[0..indices.len()], &indices[..]);
+        assert_eq!(generated.lods[0].index_count as usize, indices.len());
+
+        let mut end = 0;
+        for pair in generated.lods.windows(2) {
+            assert!(pair[1].index_count as f32 <= pair[0].index_count as f32 * MIN_LOD_REDUCTION);
+            assert!(pair[1].screen_size < pair[0].screen_size);
+        }
+        for lod in &generated.lods {
+            assert_eq!(lod.first_index as usize, end);
+            end += lod.index_count as usize;
+
+            assert_valid_triangles(&vertices, &generated.indices[lod.first_index as usize..end]);
+        }
+        assert_eq!(end, generated.indices.len());
+
+        // A mesh that can't be simplified only has LOD 0
+        let (vertices, indices) = load_indexed_obj("cube.obj");
+        let generated           = generate_lods(&vertices, &indices, &LodGenerationSettings::default());
+        assert_eq!(generated.lods.len(), 1);
+        assert_eq!(generated.indices, indices);
+    }
+
+    #[test]
+    fn cooked_lods_round_trip() {
+        let (vertices, indices) = load_indexed_obj("suzanne.obj");
+
+        let generated = generate_lods(&vertices, &indices, &LodGenerationSettings::default());
+        let bytes     = generated.to_bytes(vertices.len());
+
+        let loaded = GeneratedLods::from_bytes(&bytes, &indices, vertices.len()).expect("the cooked LODs should load");
+        assert_eq!(loaded.indices, generated.indices);
+        assert_eq!(loaded.lods.len(), generated.lods.len());
+        for (loaded, generated) in loaded.lods.iter().zip(&generated.lods) {
+            assert_eq!(loaded.first_index, generated.first_index);
+            assert_eq!(loaded.index_count, generated.index_count);
+            assert_eq!(loaded.screen_size, generated.screen_size);
+        }
+
+        // Cooked from a different mesh
+        assert!(GeneratedLods::from_bytes(&bytes, &indices, vertices.len() + 1).is_none());
+        assert!(GeneratedLods::from_bytes(&bytes, &indices[3..], vertices.len()).is_none());
+
+        let mut reordered = indices.clone();
+        reordered.swap(0, 3);
+        assert!(GeneratedLods::from_bytes(&bytes, &reordered, vertices.len()).is_none());
+
+        // Malformed files
+        assert!(GeneratedLods::from_bytes(&bytes[..bytes.len() - 4], &indices, vertices.len()).is_none());
+        assert!(GeneratedLods::from_bytes(&[], &indices, vertices.len()).is_none());
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert!(GeneratedLods::from_bytes(&bad_magic, &indices, vertices.len()).is_none());
+
+        let mut bad_range = bytes.clone();
+        bad_range[16 + 12 + 4..16 + 12 + 8].copy_from_slice(&u32::MAX.to_le_bytes()); // LOD 1's index count
+        assert!(GeneratedLods::from_bytes(&bad_range, &indices, vertices.len()).is_none());
+    }
+
+    fn make_lods() -> Vec<MeshLod> {
+        return [f32::MAX, 0.5, 0.25, 0.125].iter().map(|screen_size| MeshLod{ screen_size: *screen_size, ..MeshLod::default() }).collect();
+    }
+
+    #[test]
+    fn select_lod_picks_the_coarsest_lod_under_the_screen_size() {
+        let lods = make_lods();
+
+        assert_eq!(select_lod(&lods, 2.0,  0, 0.0), 0);
+        assert_eq!(select_lod(&lods, 0.4,  0, 0.0), 1);
+        assert_eq!(select_lod(&lods, 0.2,  0, 0.0), 2);
+        assert_eq!(select_lod(&lods, 0.01, 0, 0.0), 3);
+        assert_eq!(select_lod(&lods, 2.0,  3, 0.0), 0);
+
+        // Far past every threshold, the hysteresis doesn't hold a mesh back
+        assert_eq!(select_lod(&lods, 0.01, 0, 0.1), 3);
+        assert_eq!(select_lod(&lods, 2.0,  3, 0.1), 0);
+    }
+
+    #[test]
+    fn select_lod_holds_within_the_hysteresis() {
+        const HYSTERESIS: f32 = 0.1;
+
+        let lods = make_lods();
+
+        // A mesh hovering around LOD 1's threshold of 0.5 keeps the LOD it has, either way
+        let hover = [0.52, 0.48, 0.53, 0.47, 0.5, 0.46, 0.54];
+        for current in [0, 1] {
+            let mut lod = current;
+            for screen_size in hover {
+                lod = select_lod(&lods, screen_size, lod, HYSTERESIS);
+                assert_eq!(lod, current, "switched at a screen size of {}", screen_size);
+            }
+        }
+
+        // Shrinking switches past the band, and growing back only switches back past the band on the other side
+        let mut lod = 0;
+        let mut switches = Vec::<(f32, usize)>::new();
+        for step in (0..=60).rev().chain(0..=60) {
+            let screen_size = 0.4 + step as f32 * 0.0035;
+            let next        = select_lod(&lods, screen_size, lod, HYSTERESIS);
+            if next != lod {
+                switches.push((screen_size, next));
+            }
+            lod = next;
+        }
+
+        assert_eq!(switches.len(), 2, "switched at {:?}", switches);
+        assert!(switches[0].1 == 1 && switches[0].0 < 0.5 * (1.0 - HYSTERESIS));
+        assert!(switches[1].1 == 0 && switches[1].0 >= 0.5 * (1.0 + HYSTERESIS));
+    }
+}
@@ -12,14 +12,203 @@ use super::graphics::{
     gpu_descriptors::*,
 };
 
+use super::error::RenderError;
+use super::command_buffer::{ AlphaMode, TextureFormat };
+use super::shader::MaterialConstants;
 use crate::util::id::*;
 use crate::math::float4::*;
 
 use std::rc::Rc;
 
+// Textures and PBR materials created with RenderCommand::CreateTexture and RenderCommand::CreateMaterial. The
+// engine picks the ids, so materials can refer to textures (and meshes to materials) before they're uploaded.
+
+//
+// Bindless Textures
+//
+// Every texture is written once into a global array of sampled images when it is uploaded, and a TextureId is its
+// index into that array. Materials are packed into a single storage buffer, with the TextureIds of their textures,
+// so the lit mesh pipeline binds one descriptor set for the whole frame:
+//   set 1, binding 0 - MaterialConstants[MAX_BINDLESS_MATERIALS], indexed by the material index in the push constants
+//   set 1, binding 1 - sampler[BINDLESS_SAMPLER_COUNT]
+//   set 1, binding 2 - texture2D[MAX_BINDLESS_TEXTURES], partially bound and update-after-bind
+//
+// Update-after-bind lets a texture be added while frames that bound the set are still in flight, as long as they
+// don't read the new slot. Slots are handed out in upload order and never reused, a lost device starts over.
+//
+
+// Must match mesh.frag
+pub(crate) const MAX_BINDLESS_TEXTURES:  u32 = 4096;
+pub(crate) const MAX_BINDLESS_MATERIALS: u32 = 1024;
+pub(crate) const BINDLESS_SAMPLER_COUNT: u32 = 2;
+
+pub(crate) const BINDLESS_SAMPLER_LINEAR:  u32 = 0;
+pub(crate) const BINDLESS_SAMPLER_NEAREST: u32 = 1;
+
+pub(crate) const ALPHA_MODE_OPAQUE: u32 = 0;
+pub(crate) const ALPHA_MODE_MASK:   u32 = 1;
+pub(crate) const ALPHA_MODE_BLEND:  u32 = 2;
+
+/// Index of a texture in the bindless texture array.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct TextureId(u32);
+
+impl TextureId {
+    pub fn get_index(self) -> u32 {
+        return self.0;
+    }
+}
+
+pub(crate) struct BindlessSet {
+    pub layout:    VkDescriptorSetLayout,
+    pub set:       VkDescriptorSet,
+    pool:          DescriptorAllocator,
+    materials:     AllocatedBuffer, // MaterialConstants per material, persistently mapped
+    texture_count: u32,             // the next free slot of the texture array
+}
+
+impl BindlessSet {
+    pub fn new(device: &Device) -> Result<BindlessSet, RenderError> {
+        let layout = {
+            let texture_flags = VK_DESCRIPTOR_BINDING_PARTIALLY_BOUND_BIT
+                | VK_DESCRIPTOR_BINDING_UPDATE_AFTER_BIND_BIT
+                | VK_DESCRIPTOR_BINDING_UPDATE_UNUSED_WHILE_PENDING_BIT;
+
+            let mut builder = DescriptorLayoutBuilder::new();
+            builder.add_binding(0, VK_DESCRIPTOR_TYPE_STORAGE_BUFFER);
+            builder.add_array_binding(1, VK_DESCRIPTOR_TYPE_SAMPLER,       BINDLESS_SAMPLER_COUNT, 0);
+            builder.add_array_binding(2, VK_DESCRIPTOR_TYPE_SAMPLED_IMAGE, MAX_BINDLESS_TEXTURES,  texture_flags);
+            builder.build(device, VK_SHADER_STAGE_FRAGMENT_BIT, VK_DESCRIPTOR_SET_LAYOUT_CREATE_UPDATE_AFTER_BIND_POOL_BIT)?
+        };
+
+        // A pool with room for exactly the one set
+        let sizes: [PoolSizeRatio; 3] = [
+            PoolSizeRatio{descriptor_type: VK_DESCRIPTOR_TYPE_STORAGE_BUFFER, ratio: 1.0 },
+            PoolSizeRatio{descriptor_type: VK_DESCRIPTOR_TYPE_SAMPLER,        ratio: BINDLESS_SAMPLER_COUNT as f32 },
+            PoolSizeRatio{descriptor_type: VK_DESCRIPTOR_TYPE_SAMPLED_IMAGE,  ratio: MAX_BINDLESS_TEXTURES as f32 },
+        ];
+
+        let mut pool = device.create_descriptor_allocator(1, DescriptorAllocatorFlags::UpdateAfterBind, &sizes)?;
+
+        let Some(set) = device.allocate_descriptors(&pool, layout) else {
+            device.destroy_descriptor_allocator(&mut pool);
+            device.destroy_descriptor_set_layout(layout);
+            return Err(RenderError::Vulkan{ call: "vkAllocateDescriptorSets", result: VK_ERROR_OUT_OF_POOL_MEMORY });
+        };
+
+        let materials_size = MAX_BINDLESS_MATERIALS as usize * std::mem::size_of::<MaterialConstants>();
+        let materials = match device.create_buffer(materials_size, VK_BUFFER_USAGE_STORAGE_BUFFER_BIT, VMA_MEMORY_USAGE_CPU_TO_GPU) {
+            Ok(buffer) => buffer,
+            Err(error) => {
+                device.destroy_descriptor_allocator(&mut pool);
+                device.destroy_descriptor_set_layout(layout);
+                return Err(error);
+            },
+        };
+
+        let mut writer = DescriptorWriter::new();
+        writer.write_buffer(0, materials.buffer, materials_size as u64, 0, VK_DESCRIPTOR_TYPE_STORAGE_BUFFER);
+        writer.update_set(device, set);
+
+        return Ok(BindlessSet{ layout, set, pool, materials, texture_count: 0 });
+    }
+
+    /// Writes the samplers materials pick from, in BINDLESS_SAMPLER_* order.
+    pub fn write_samplers(&mut self, device: &Device, samplers: [VkSampler; BINDLESS_SAMPLER_COUNT as usize]) {
+        let mut writer = DescriptorWriter::new();
+        for (index, sampler) in samplers.iter().enumerate() {
+            writer.write_sampler_element(1, index as u32, *sampler);
+        }
+
+        writer.update_set(device, self.set);
+    }
+
+    /// Gives the texture the next free slot of the texture array. Returns None when the array is full.
+    pub fn add_texture(&mut self, device: &Device, view: VkImageView) -> Option<TextureId> {
+        if self.texture_count >= MAX_BINDLESS_TEXTURES {
+            return None;
+        }
+
+        let id = TextureId(self.texture_count);
+        self.texture_count += 1;
+
+        let mut writer = DescriptorWriter::new();
+        writer.write_sampled_image_element(2, id.get_index(), view, VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL);
+        writer.update_set(device, self.set);
+
+        return Some(id);
+    }
+
+    /// Copies the material's constants into its slot of the material buffer. The slot must not be in use by a frame
+    /// in flight.
+    pub fn write_material(&mut self, index: u32, constants: &MaterialConstants) {
+        assert!(index < MAX_BINDLESS_MATERIALS);
+
+        let memory = self.materials.get_allocation();
+        assert!(memory != std::ptr::null_mut());
+        unsafe { std::ptr::copy(constants, (memory as *mut MaterialConstants).add(index as usize), 1) };
+    }
+
+    pub fn destroy(&mut self, device: &Device) {
+        device.destroy_buffer(&mut self.materials);
+        device.destroy_descriptor_allocator(&mut self.pool);
+        device.destroy_descriptor_set_layout(self.layout);
+    }
+}
+
+pub(crate) struct GpuTexture {
+    pub image:     AllocatedImage,
+    pub id:        TextureId,
+    pub engine_id: u64,
+}
+
+// CPU copy of an uploaded texture, kept around so textures can be re-uploaded if the device is lost.
+pub(crate) struct RetainedTexture {
+    pub pixels:    Vec<u8>,
+    pub width:     u32,
+    pub height:    u32,
+    pub format:    TextureFormat,
+    pub engine_id: u64,
+}
+
+// A material's constants live in the bindless material buffer, at the material's index in RenderSystem::materials
+pub(crate) struct GpuMaterial {
+    pub engine_id:  u64,
+    pub alpha_mode: AlphaMode, // meshes with blended materials are drawn in the transparent pass
+}
+
+impl GpuMaterial {
+    pub fn is_blended(&self) -> bool {
+        return self.alpha_mode == AlphaMode::Blend;
+    }
+
+    /// Masked materials cast shadows with the alpha tested shadow pipelines
+    pub fn is_alpha_tested(&self) -> bool {
+        return self.alpha_mode == AlphaMode::Mask;
+    }
+}
+
+impl AlphaMode {
+    pub(crate) fn get_shader_value(self) -> u32 {
+        return match self {
+            AlphaMode::Opaque => ALPHA_MODE_OPAQUE,
+            AlphaMode::Mask   => ALPHA_MODE_MASK,
+            AlphaMode::Blend  => ALPHA_MODE_BLEND,
+        };
+    }
+}
+
+impl TextureFormat {
+    pub(crate) fn get_vk_format(self) -> VkFormat {
+        return match self {
+            TextureFormat::Rgba8Srgb  => VK_FORMAT_R8G8B8A8_SRGB,
+            TextureFormat::Rgba8Unorm => VK_FORMAT_R8G8B8A8_UNORM,
+        };
+    }
+}
+
 struct MaterialId(Id);
 struct MaterialInstanceId(Id);
-struct TextureId(Id);
 
 struct Texture2D {
     image:   AllocatedImage,
@@ -191,15 +380,15 @@ struct MaterialSystem {
 }
 
 impl MaterialDataBuffer {
-    pub fn new(device: &Device, max_size: usize) -> Self {
+    pub fn new(device: &Device, max_size: usize) -> Result<Self, RenderError> {
         let buffer_flags = VK_BUFFER_USAGE_STORAGE_BUFFER_BIT | VK_BUFFER_USAGE_SHADER_DEVICE_ADDRESS_BIT;
         let memory_flags = VMA_MEMORY_USAGE_CPU_TO_GPU;
 
         //todo: are there any alignment concerns?
-        let buffer  = device.create_buffer(max_size, buffer_flags, memory_flags);
+        let buffer  = device.create_buffer(max_size, buffer_flags, memory_flags)?;
         let address = device.get_buffer_device_address(&buffer);
 
-        return Self{ buffer, address, next_offset: 0 };
+        return Ok(Self{ buffer, address, next_offset: 0 });
     }
 
     pub fn destroy(&mut self, device: &Device) {
@@ -221,7 +410,7 @@ impl MaterialDataBuffer {
 }
 
 impl MaterialSystem {
-    pub fn new(device: Rc<Device>, info: MaterialSystemCreateInfo) -> MaterialSystem {
+    pub fn new(device: Rc<Device>, info: MaterialSystemCreateInfo) -> Result<MaterialSystem, RenderError> {
         // arbritrarily chosen - todo: fine tune
         let ratios: [PoolSizeRatio; 4] = [
             PoolSizeRatio{descriptor_type: VK_DESCRIPTOR_TYPE_STORAGE_IMAGE,          ratio: 3.0 },
@@ -230,16 +419,16 @@ impl MaterialSystem {
             PoolSizeRatio{descriptor_type: VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER, ratio: 4.0 },
         ];
 
-        let descriptor_allocator = DescriptorAllocatorGrowable::new(&device, &ratios, 1000);
+        let descriptor_allocator = DescriptorAllocatorGrowable::new(&device, &ratios, 1000)?;
 
-        Self{
+        Ok(Self{
             device,
             descriptor_allocator,
             textures:     Vec::new(),
             texture_ids:  IdSystem::new(info.max_textures),
             materials:    Vec::new(),
             material_ids: IdSystem::new(info.max_materials),
-        }
+        })
     }
 
     pub fn register_material(&mut self, material: Box<dyn Material>) -> MaterialId {
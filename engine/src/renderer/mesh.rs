@@ -1,6 +1,9 @@
 use crate::math::{ float3::*, float4::*, float4x4::*};
 
+use super::command_buffer::MeshInstance;
 use super::graphics::*;
+use super::lod::{ MeshLod, MAX_MESH_LODS };
+use super::shader::GpuInstance;
 
 use vendor::vulkan::*;
 
@@ -19,6 +22,8 @@ pub struct Vertex {
     //----------------- 16-byte boundary
     pub color:    Float4,
     //----------------- 16-byte boundary
+    pub tangent:  Float4, // xyz: tangent, w: bitangent handedness (+1 or -1)
+    //----------------- 16-byte boundary
 }
 
 impl Vertex {
@@ -29,19 +34,198 @@ impl Vertex {
             normal:   Float3::zero(),
             uv_y:     0.0,
             color:    Float4::zero(),
+            tangent:  Float4::zero(),
+        }
+    }
+}
+
+/// The joints a skinned vertex follows, a second vertex stream indexed like the mesh's vertices. The weights sum
+/// to one, unused slots have a weight of zero.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SkinVertex {
+    pub joints:  [u32; 4], // into the skeleton's joints
+    //----------------- 16-byte boundary
+    pub weights: Float4,
+    //----------------- 16-byte boundary
+}
+
+impl Default for SkinVertex {
+    fn default() -> Self {
+        // Follows the first joint
+        Self{
+            joints:  [0; 4],
+            weights: Float4::new(1.0, 0.0, 0.0, 0.0),
         }
     }
 }
 
+/// Axis-aligned bounding box.
+#[derive(Clone, Copy, Debug)]
+pub struct BoundingBox {
+    pub min: Float3,
+    pub max: Float3,
+}
+
+impl Default for BoundingBox {
+    fn default() -> Self {
+        Self{ min: Float3::zero(), max: Float3::zero() }
+    }
+}
+
+impl BoundingBox {
+    pub fn get_center(&self) -> Float3 {
+        return (self.min + self.max) * 0.5;
+    }
+
+    pub fn get_extents(&self) -> Float3 {
+        return (self.max - self.min) * 0.5;
+    }
+
+    /// Smallest box containing both boxes.
+    pub fn union(&self, other: &BoundingBox) -> BoundingBox {
+        return BoundingBox{
+            min: Float3::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y), self.min.z.min(other.min.z)),
+            max: Float3::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y), self.max.z.max(other.max.z)),
+        };
+    }
+
+    /// Box containing the transformed box (Arvo). The result is axis-aligned again, so it can be larger than the
+    /// transformed box under rotations.
+    pub fn transform(&self, transform: Float4x4) -> BoundingBox {
+        let center  = self.get_center();
+        let extents = self.get_extents();
+
+        let new_center = transform.translate_point(Float4::new(center.x, center.y, center.z, 1.0));
+
+        let axis_x = transform.translate_point(Float4::new(extents.x, 0.0, 0.0, 0.0));
+        let axis_y = transform.translate_point(Float4::new(0.0, extents.y, 0.0, 0.0));
+        let axis_z = transform.translate_point(Float4::new(0.0, 0.0, extents.z, 0.0));
+
+        let new_extents = Float3::new(
+            axis_x.x.abs() + axis_y.x.abs() + axis_z.x.abs(),
+            axis_x.y.abs() + axis_y.y.abs() + axis_z.y.abs(),
+            axis_x.z.abs() + axis_y.z.abs() + axis_z.z.abs(),
+        );
+
+        let new_center = Float3::new(new_center.x, new_center.y, new_center.z);
+        return BoundingBox{ min: new_center - new_extents, max: new_center + new_extents };
+    }
+}
+
+/// Bounding box of the vertices. An empty mesh gets an empty box at the origin.
+pub fn compute_bounding_box(vertices: &[Vertex]) -> BoundingBox {
+    if vertices.is_empty() {
+        return BoundingBox::default();
+    }
+
+    let mut min = vertices[0].position;
+    let mut max = vertices[0].position;
+    for vertex in vertices {
+        min = Float3::new(min.x.min(vertex.position.x), min.y.min(vertex.position.y), min.z.min(vertex.position.z));
+        max = Float3::new(max.x.max(vertex.position.x), max.y.max(vertex.position.y), max.z.max(vertex.position.z));
+    }
+
+    return BoundingBox{ min, max };
+}
+
+// CPU copy of an uploaded mesh. The RenderSystem keeps these around so meshes can be re-uploaded if the
+// device is lost.
+pub(crate) struct RetainedMesh {
+    pub vertices:    Vec<Vertex>,
+    pub indices:     Vec<u32>,
+    pub lods:        Vec<MeshLod>,
+    pub transform:   Float4x4,
+    pub material_id: Option<u64>,
+    pub engine_id:   u64,
+}
+
+// CPU copy of an uploaded instanced mesh, kept for the same reason as RetainedMesh.
+pub(crate) struct RetainedInstancedMesh {
+    pub vertices:    Vec<Vertex>,
+    pub indices:     Vec<u32>,
+    pub lods:        Vec<MeshLod>,
+    pub instances:   Vec<MeshInstance>,
+    pub material_id: Option<u64>,
+    pub engine_id:   u64,
+}
+
+// CPU copy of an uploaded skinned mesh, kept for the same reason as RetainedMesh. The joint matrices are the last
+// palette the engine sent.
+pub(crate) struct RetainedSkinnedMesh {
+    pub vertices:       Vec<Vertex>,
+    pub skin:           Vec<SkinVertex>,
+    pub indices:        Vec<u32>,
+    pub joint_matrices: Vec<Float4x4>,
+    pub transform:      Float4x4,
+    pub material_id:    Option<u64>,
+    pub engine_id:      u64,
+}
+
 #[derive(Clone, Copy)]
 pub(crate) struct GpuMeshBuffers {
     pub index_buffer:          AllocatedBuffer,
     pub vertex_buffer:         AllocatedBuffer,
     pub vertex_buffer_address: VkDeviceAddress,
-    pub index_count:           u32,
+    pub lods:                  [MeshLod; MAX_MESH_LODS], // index ranges, LOD 0 is the whole mesh
+    pub lod_count:             u32,
+    pub lod:                   u32,                      // the LOD drawn this frame, picked by the RenderSystem
     pub transform:             Float4x4,
+    pub bounds:                Float4,      // local-space bounding sphere, xyz: center, w: radius
+    pub aabb:                  BoundingBox, // local-space bounding box
+    pub material_index:        usize,       // index into the RenderSystem's materials, 0 is the default material
+}
+
+impl GpuMeshBuffers {
+    pub fn get_lods(&self) -> &[MeshLod] {
+        return &self.lods[0..self.lod_count as usize];
+    }
+
+    /// The index range drawn this frame.
+    pub fn get_lod(&self) -> MeshLod {
+        return self.lods[self.lod as usize];
+    }
 }
 
+// A mesh drawn with one instanced draw call. The mesh's transform is the identity, each instance carries its own.
+#[derive(Clone, Copy)]
+pub(crate) struct GpuInstancedMesh {
+    pub mesh:                    GpuMeshBuffers,
+    pub instance_buffer:         AllocatedBuffer,
+    pub instance_buffer_address: VkDeviceAddress,
+    pub instance_count:          u32,
+    pub instance_bounds:         BoundingBox, // world-space box around every instance, for CPU culling
+    pub engine_id:               u64,
+}
+
+// A mesh skinned in the vertex shader. The joint buffer is host visible and replaced, not overwritten, whenever the
+// engine sends a new palette, so frames in flight keep reading the palette they were recorded with.
+#[derive(Clone, Copy)]
+pub(crate) struct GpuSkinnedMesh {
+    pub mesh:                 GpuMeshBuffers,
+    pub skin_buffer:          AllocatedBuffer, // SkinVertex array
+    pub skin_buffer_address:  VkDeviceAddress,
+    pub joint_buffer:         AllocatedBuffer, // Float4x4 palette
+    pub joint_buffer_address: VkDeviceAddress,
+    pub joint_count:          u32,
+    pub engine_id:            u64,
+}
+
+/// World-space box around every instance of a mesh with the local-space box `aabb`.
+pub(crate) fn compute_instance_bounds(aabb: &BoundingBox, instances: &[MeshInstance]) -> BoundingBox {
+    let mut instance_boxes = instances.iter().map(|instance| aabb.transform(instance.transform));
+
+    let Some(first) = instance_boxes.next() else {
+        return BoundingBox::default();
+    };
+
+    return instance_boxes.fold(first, |bounds, instance_box| bounds.union(&instance_box));
+}
+
+/// Packs the instances in the layout the vertex shaders read them in.
+pub(crate) fn pack_instances(instances: &[MeshInstance]) -> Vec<GpuInstance> {
+    return instances.iter().map(|instance| GpuInstance{ transform: instance.transform, color: instance.color }).collect();
+}
 
 impl Default for Vertex {
     fn default() -> Self {
@@ -51,6 +235,7 @@ impl Default for Vertex {
             normal:   Float3::zero(),
             uv_y:     0.0,
             color:    Float4::zero(),
+            tangent:  Float4::zero(),
         }
     }
 }
@@ -61,8 +246,13 @@ impl Default for GpuMeshBuffers {
             index_buffer:          AllocatedBuffer::default(),
             vertex_buffer:         AllocatedBuffer::default(),
             vertex_buffer_address: 0,
-            index_count:           0,
+            lods:                  [MeshLod::default(); MAX_MESH_LODS],
+            lod_count:             1,
+            lod:                   0,
             transform:             Float4x4::identity(),
+            bounds:                Float4::zero(),
+            aabb:                  BoundingBox::default(),
+            material_index:        0,
         }
     }
 }
@@ -0,0 +1,1163 @@
+use std::collections::HashMap;
+
+use crate::math::{ float3::*, float4::* };
+
+use super::mesh::{ BoundingBox, Vertex };
+
+//
+// Mesh Processing
+//
+// Offline operations on indexed triangle lists, for importers to run before a mesh is handed to the renderer. An
+// import usually runs them in this order (see the testbed's OBJ converter):
+//   1. deduplicate_vertices / weld_vertices - index the mesh, merging identical (or nearly identical) vertices
+//   2. generate_normals                     - for meshes without normals, smooth up to an angle and flat beyond it
+//   3. generate_tangents                    - MikkTSpace-compatible tangents for the normal maps
+//   4. optimize_vertex_cache                - reorders the triangles so their vertices are still in the post-transform
+//                                             cache when they are used again
+//   5. optimize_overdraw                    - reorders clusters of triangles so the parts of the mesh facing outwards
+//                                             are drawn first, without undoing the cache order
+//   6. compute_bounds                       - the box and sphere the renderer culls the mesh with
+//
+// Operations that merge or split vertices rewrite both the vertices and the indices, and return the vertex of the
+// input each vertex of the output was made from, so streams kept next to the vertices (like the SkinVertex stream of
+// a skinned mesh) can be rebuilt to match.
+//
+
+/// Size of the FIFO post-transform cache the triangle orders are tuned for. GPUs don't have a fixed cache like this
+/// anymore, but a small cache is still a good model of how they reuse vertices within a batch.
+pub const VERTEX_CACHE_SIZE: usize = 32;
+
+// Vertex scores of optimize_vertex_cache(), from Tom Forsyth's "Linear-Speed Vertex Cache Optimisation"
+const CACHE_DECAY_POWER:   f32 = 1.5;
+const LAST_TRIANGLE_SCORE: f32 = 0.75;
+const VALENCE_BOOST_SCALE: f32 = 2.0;
+const VALENCE_BOOST_POWER: f32 = 0.5;
+
+/// Bit pattern of every attribute of a vertex, for hashing. -0.0 is folded into 0.0 so both hash the same.
+fn get_vertex_key(vertex: &Vertex) -> [u32; 16] {
+    let values = [
+        vertex.position.x, vertex.position.y, vertex.position.z, vertex.uv_x,
+        vertex.normal.x,   vertex.normal.y,   vertex.normal.z,   vertex.uv_y,
+        vertex.color.x,    vertex.color.y,    vertex.color.z,    vertex.color.w,
+        vertex.tangent.x,  vertex.tangent.y,  vertex.tangent.z,  vertex.tangent.w,
+    ];
+
+    return values.map(|value| (value + 0.0).to_bits());
+}
+
+fn get_position_key(position: Float3) -> [u32; 3] {
+    return [(position.x + 0.0).to_bits(), (position.y + 0.0).to_bits(), (position.z + 0.0).to_bits()];
+}
+
+/// Replaces the vertices with the vertices the indices refer to, merged with `find_vertex`. It is given the vertex to
+/// place and the vertices placed so far, and returns the one to merge it with. Vertices are placed in the order the
+/// indices first use them, which also keeps the vertex fetches of the mesh close together.
+fn rebuild_vertices<F>(vertices: &mut Vec<Vertex>, indices: &mut [u32], mut find_vertex: F) -> Vec<u32> where
+    F: FnMut(&Vertex, &[Vertex]) -> Option<u32>
+{
+    let mut remap   = vec![u32::MAX; vertices.len()];
+    let mut sources = Vec::<u32>::new();
+    let mut result  = Vec::<Vertex>::new();
+
+    for index in indices.iter_mut() {
+        let old = *index as usize;
+        if remap[old] == u32::MAX {
+            remap[old] = match find_vertex(&vertices[old], &result) {
+                Some(existing) => existing,
+                None           => {
+                    result.push(vertices[old]);
+                    sources.push(old as u32);
+                    (result.len() - 1) as u32
+                },
+            };
+        }
+
+        *index = remap[old];
+    }
+
+    *vertices = result;
+    return sources;
+}
+
+/// Merges the vertices whose attributes are bitwise identical, and drops the vertices no index refers to. Returns the
+/// vertex of the input each remaining vertex was made from.
+pub fn deduplicate_vertices(vertices: &mut Vec<Vertex>, indices: &mut [u32]) -> Vec<u32> {
+    let mut placed = HashMap::<[u32; 16], u32>::with_capacity(vertices.len());
+
+    return rebuild_vertices(vertices, indices, |vertex, result| {
+        let key = get_vertex_key(vertex);
+        if let Some(existing) = placed.get(&key) {
+            return Some(*existing);
+        }
+
+        placed.insert(key, result.len() as u32);
+        return None;
+    });
+}
+
+/// How close two vertices must be for weld_vertices() to merge them.
+#[derive(Clone, Copy, Debug)]
+pub struct WeldTolerance {
+    pub position: f32, // largest distance between the positions
+    pub normal:   f32, // largest angle between the normals, in degrees
+    pub uv:       f32, // largest difference of either texture coordinate
+    pub color:    f32, // largest difference of any color channel
+}
+
+impl Default for WeldTolerance {
+    fn default() -> Self {
+        Self{
+            position: 1e-5,
+            normal:   1.0,
+            uv:       1e-4,
+            color:    1.0 / 255.0,
+        }
+    }
+}
+
+/// Merges the vertices that are within `tolerance` of each other, and drops the vertices no index refers to. Each
+/// merged vertex keeps the attributes of the first vertex the indices use. Tangents aren't compared, weld before
+/// generating them. Returns the vertex of the input each remaining vertex was made from.
+pub fn weld_vertices(vertices: &mut Vec<Vertex>, indices: &mut [u32], tolerance: &WeldTolerance) -> Vec<u32> {
+    // Nearby positions are found with a grid of cells as large as the tolerance, a match is always in a neighbouring cell
+    let cell_size = tolerance.position.max(1e-12);
+    let cell_of   = |position: Float3| -> [i64; 3] {
+        [(position.x / cell_size).floor() as i64, (position.y / cell_size).floor() as i64, (position.z / cell_size).floor() as i64]
+    };
+
+    let min_normal_cosine = tolerance.normal.clamp(0.0, 180.0).to_radians().cos();
+
+    let is_close = |a: &Vertex, b: &Vertex| -> bool {
+        let normal_cosine = if a.normal.is_zero() || b.normal.is_zero() {
+            if a.normal.is_zero() && b.normal.is_zero() { 1.0 } else { -1.0 }
+        } else {
+            a.normal.unit().dot(b.normal.unit())
+        };
+
+        let color_difference = (a.color.x - b.color.x).abs().max((a.color.y - b.color.y).abs())
+            .max((a.color.z - b.color.z).abs()).max((a.color.w - b.color.w).abs());
+
+        return (a.position - b.position).length() <= tolerance.position
+            && normal_cosine >= min_normal_cosine - 1e-6
+            && (a.uv_x - b.uv_x).abs() <= tolerance.uv
+            && (a.uv_y - b.uv_y).abs() <= tolerance.uv
+            && color_difference <= tolerance.color;
+    };
+
+    let mut cells = HashMap::<[i64; 3], Vec<u32>>::new();
+
+    return rebuild_vertices(vertices, indices, |vertex, result| {
+        let cell = cell_of(vertex.position);
+
+        for x in -1..=1 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    let Some(candidates) = cells.get(&[cell[0] + x, cell[1] + y, cell[2] + z]) else {
+                        continue;
+                    };
+
+                    if let Some(existing) = candidates.iter().find(|candidate| is_close(&result[**candidate as usize], vertex)) {
+                        return Some(*existing);
+                    }
+                }
+            }
+        }
+
+        cells.entry(cell).or_default().push(result.len() as u32);
+        return None;
+    });
+}
+
+/// Angle of a triangle at `corner`, between the edges to `next` and `previous`.
+fn get_corner_angle(corner: Float3, next: Float3, previous: Float3) -> f32 {
+    let a = (next - corner).unit();
+    let b = (previous - corner).unit();
+
+    return a.dot(b).clamp(-1.0, 1.0).acos();
+}
+
+/// Generates the normals of a triangle list, replacing the normals it has. The corners of the triangles meeting at a
+/// position share their normal when the triangles are at most `smoothing_angle` degrees apart, so 0 gives flat normals
+/// (except across coplanar triangles) and 180 smooths across every edge. Normals are averaged weighted by the angle of
+/// each triangle at the corner, so they don't depend on how the surface was triangulated.
+///
+/// Corners of one vertex that end up with different normals are split into several vertices. Returns the vertex of
+/// the input each vertex of the output was made from.
+pub fn generate_normals(vertices: &mut Vec<Vertex>, indices: &mut [u32], smoothing_angle: f32) -> Vec<u32> {
+    let triangle_count = indices.len() / 3;
+
+    // A little slack, so coplanar triangles stay smooth when their normals are a rounding error apart
+    let min_cosine = smoothing_angle.clamp(0.0, 180.0).to_radians().cos() - 1e-4;
+
+    let mut face_normals = Vec::<Float3>::with_capacity(triangle_count);
+    let mut weights      = Vec::<f32>::with_capacity(triangle_count * 3);
+    let mut corners_at   = HashMap::<[u32; 3], Vec<usize>>::new();
+
+    for triangle in 0..triangle_count {
+        let p = [0, 1, 2].map(|corner| vertices[indices[triangle * 3 + corner] as usize].position);
+        face_normals.push((p[1] - p[0]).cross(p[2] - p[0]).unit());
+
+        for corner in 0..3 {
+            weights.push(get_corner_angle(p[corner], p[(corner + 1) % 3], p[(corner + 2) % 3]));
+            corners_at.entry(get_position_key(p[corner])).or_default().push(triangle * 3 + corner);
+        }
+    }
+
+    let mut corner_normals = vec![Float3::zero(); triangle_count * 3];
+    for corners in corners_at.values() {
+        for &corner in corners {
+            let face_normal = face_normals[corner / 3];
+
+            let mut normal = Float3::zero();
+            for &other in corners {
+                if face_normal.dot(face_normals[other / 3]) >= min_cosine {
+                    normal = normal + face_normals[other / 3] * weights[other];
+                }
+            }
+
+            // Degenerate triangles have no normal of their own, they take the smooth normal of the position
+            if normal.is_zero() {
+                normal = corners.iter().fold(Float3::zero(), |sum, other| sum + face_normals[other / 3] * weights[*other]);
+            }
+
+            corner_normals[corner] = if normal.is_zero() { Float3::new(0.0, 1.0, 0.0) } else { normal.unit() };
+        }
+    }
+
+    // One vertex per distinct (vertex, normal) pair
+    let mut placed  = HashMap::<(u32, [u32; 3]), u32>::new();
+    let mut sources = Vec::<u32>::new();
+    let mut result  = Vec::<Vertex>::new();
+
+    for (corner, index) in indices.iter_mut().enumerate().take(triangle_count * 3) {
+        let normal = corner_normals[corner];
+
+        *index = *placed.entry((*index, get_position_key(normal))).or_insert_with(|| {
+            let mut vertex = vertices[*index as usize];
+            vertex.normal  = normal;
+
+            result.push(vertex);
+            sources.push(*index);
+            (result.len() - 1) as u32
+        });
+    }
+
+    *vertices = result;
+    return sources;
+}
+
+/// Generates MikkTSpace-compatible tangents (Morten Mikkelsen, "Simulation of Wrinkled Surfaces Revisited"), the
+/// tangent space Blender, Substance and most bakers bake normal maps in, so baked normal maps shade without seams:
+///   - every triangle's tangent follows its UVs' u direction, and its handedness is the sign of its UV area
+///   - at each corner, the tangent is projected onto the plane of the vertex normal and weighted by the corner angle
+///   - the corners of a vertex are grouped into fans, the corners with the same handedness that are connected through
+///     the edges of the triangles around the vertex, and each fan averages only its own corners
+///
+/// Every fan of a vertex after the first gets a vertex of its own, so a vertex shared by mirrored and unmirrored
+/// triangles, or by fans that only touch at the vertex, is split. Triangles without usable UVs take the tangent of
+/// their vertices' first fan. The bitangent is rebuilt in the shader as cross(normal, tangent.xyz) * tangent.w.
+///
+/// Returns the vertex of the input each vertex of the output was made from, the vertices of the input keep their
+/// indices and the split vertices are added after them.
+pub fn generate_tangents(vertices: &mut Vec<Vertex>, indices: &mut [u32]) -> Vec<u32> {
+    let triangle_count = indices.len() / 3;
+    let corner_count   = triangle_count * 3;
+    let vertex_count   = vertices.len();
+
+    // Angle weighted tangent of each corner, and the handedness of each triangle, None without usable UVs
+    let mut corner_tangents = vec![Float3::zero(); corner_count];
+    let mut handedness      = Vec::<Option<usize>>::with_capacity(triangle_count);
+
+    for triangle in 0..triangle_count {
+        let v = [0, 1, 2].map(|corner| vertices[indices[triangle * 3 + corner] as usize]);
+
+        let edge1 = v[1].position - v[0].position;
+        let edge2 = v[2].position - v[0].position;
+
+        let du1 = v[1].uv_x - v[0].uv_x;
+        let dv1 = v[1].uv_y - v[0].uv_y;
+        let du2 = v[2].uv_x - v[0].uv_x;
+        let dv2 = v[2].uv_y - v[0].uv_y;
+
+        let uv_area = du1 * dv2 - du2 * dv1;
+        let tangent = (edge1 * dv2 - edge2 * dv1) * uv_area.signum();
+
+        if uv_area.abs() < 1e-12 || tangent.is_zero() {
+            handedness.push(None);
+            continue;
+        }
+
+        handedness.push(Some(if uv_area > 0.0 { 0 } else { 1 }));
+
+        for corner in 0..3 {
+            let normal = v[corner].normal.unit();
+
+            // The corner angle is measured in the tangent plane too
+            let project = |vector: Float3| -> Float3 { (vector - normal * normal.dot(vector)).unit() };
+
+            let next     = project(v[(corner + 1) % 3].position - v[corner].position);
+            let previous = project(v[(corner + 2) % 3].position - v[corner].position);
+            let angle    = next.dot(previous).clamp(-1.0, 1.0).acos();
+
+            corner_tangents[triangle * 3 + corner] = project(tangent) * angle;
+        }
+    }
+
+    // Fans: corners of a vertex whose triangles share an edge out of the vertex, and have the same handedness, are
+    // joined, each fan ends up as a tree of corners
+    let mut parents     = (0..corner_count).collect::<Vec<usize>>();
+    let mut edge_corner = HashMap::<(u32, u32, usize), usize>::new(); // (vertex, other end of the edge, handedness)
+
+    for corner in 0..corner_count {
+        let Some(side) = handedness[corner / 3] else { continue };
+
+        let first = corner - corner % 3;
+        for other in [first + (corner + 1) % 3, first + (corner + 2) % 3] {
+            let edge = (indices[corner], indices[other], side);
+            match edge_corner.get(&edge) {
+                Some(&joined) => join_fans(&mut parents, corner, joined),
+                None          => { edge_corner.insert(edge, corner); },
+            }
+        }
+    }
+
+    let mut fan_sums = vec![Float3::zero(); corner_count];
+    for corner in 0..corner_count {
+        if handedness[corner / 3].is_some() {
+            let fan = find_fan(&mut parents, corner);
+            fan_sums[fan] = fan_sums[fan] + corner_tangents[corner];
+        }
+    }
+
+    let make_tangent = |vertex: &Vertex, sum: Float3, side: usize| -> Float4 {
+        let normal      = vertex.normal.unit();
+        let mut tangent = (sum - normal * normal.dot(sum)).unit();
+        if tangent.is_zero() {
+            // No usable UVs, any direction perpendicular to the normal will do
+            let axis = if normal.x.abs() < 0.9 { Float3::new(1.0, 0.0, 0.0) } else { Float3::new(0.0, 1.0, 0.0) };
+            tangent  = normal.cross(axis).unit();
+        }
+
+        return Float4::new(tangent.x, tangent.y, tangent.z, if side == 0 { 1.0 } else { -1.0 });
+    };
+
+    // The first fan of a vertex keeps its index, the others get new vertices
+    let mut sources    = (0..vertex_count as u32).collect::<Vec<u32>>();
+    let mut is_claimed = vec![false; vertex_count];
+    let mut fan_vertex = vec![u32::MAX; corner_count];
+
+    for corner in 0..corner_count {
+        let Some(side) = handedness[corner / 3] else { continue };
+
+        let fan = find_fan(&mut parents, corner);
+        if fan_vertex[fan] == u32::MAX {
+            let index   = indices[corner] as usize;
+            let tangent = make_tangent(&vertices[index], fan_sums[fan], side);
+
+            if !is_claimed[index] {
+                is_claimed[index]       = true;
+                vertices[index].tangent = tangent;
+                fan_vertex[fan]         = index as u32;
+            } else {
+                let mut vertex = vertices[index];
+                vertex.tangent = tangent;
+
+                fan_vertex[fan] = vertices.len() as u32;
+                vertices.push(vertex);
+                sources.push(index as u32);
+            }
+        }
+
+        indices[corner] = fan_vertex[fan];
+    }
+
+    // Vertices only used by triangles without usable UVs, or not used at all
+    for index in (0..vertex_count).filter(|index| !is_claimed[*index]) {
+        vertices[index].tangent = make_tangent(&vertices[index], Float3::zero(), 0);
+    }
+
+    return sources;
+}
+
+/// The corner at the root of a fan's tree of corners, see generate_tangents().
+fn find_fan(parents: &mut [usize], mut corner: usize) -> usize {
+    while parents[corner] != corner {
+        parents[corner] = parents[parents[corner]]; // path halving
+        corner          = parents[corner];
+    }
+
+    return corner;
+}
+
+fn join_fans(parents: &mut [usize], a: usize, b: usize) {
+    let root_a = find_fan(parents, a);
+    let root_b = find_fan(parents, b);
+    parents[root_a.max(root_b)] = root_a.min(root_b);
+}
+
+/// Score of a vertex for optimize_vertex_cache(). Vertices still in the cache score higher, and so do vertices with
+/// few triangles left, so they are finished off rather than left behind.
+fn get_vertex_score(cache_position: Option<usize>, remaining_triangles: u32) -> f32 {
+    if remaining_triangles == 0 {
+        return -1.0;
+    }
+
+    let mut score = 0.0;
+    if let Some(position) = cache_position {
+        if position < 3 {
+            // Used by the last triangle, a fixed score so the next triangle doesn't just repeat its edge
+            score = LAST_TRIANGLE_SCORE;
+        } else {
+            let scale = 1.0 / (VERTEX_CACHE_SIZE - 3) as f32;
+            score = (1.0 - (position - 3) as f32 * scale).powf(CACHE_DECAY_POWER);
+        }
+    }
+
+    return score + VALENCE_BOOST_SCALE * (remaining_triangles as f32).powf(-VALENCE_BOOST_POWER);
+}
+
+/// Reorders the triangles so each vertex is likely to still be in the post-transform cache the next time it is used,
+/// with Tom Forsyth's "Linear-Speed Vertex Cache Optimisation". Triangles keep their winding, only their order
+/// changes. `vertex_count` is the size of the vertex buffer the indices refer to.
+pub fn optimize_vertex_cache(indices: &mut [u32], vertex_count: usize) {
+    let triangle_count = indices.len() / 3;
+    if triangle_count == 0 {
+        return;
+    }
+
+    // Triangles of each vertex, packed one vertex after the other
+    let mut remaining = vec![0u32; vertex_count];
+    for index in &indices[0..triangle_count * 3] {
+        remaining[*index as usize] += 1;
+    }
+
+    let mut offsets = vec![0usize; vertex_count + 1];
+    for vertex in 0..vertex_count {
+        offsets[vertex + 1] = offsets[vertex] + remaining[vertex] as usize;
+    }
+
+    let mut triangles_of = vec![0u32; triangle_count * 3];
+    let mut filled       = offsets.clone();
+    for (corner, index) in indices[0..triangle_count * 3].iter().enumerate() {
+        triangles_of[filled[*index as usize]] = (corner / 3) as u32;
+        filled[*index as usize] += 1;
+    }
+
+    let mut cache_position = vec![None::<usize>; vertex_count];
+    let mut vertex_scores  = (0..vertex_count).map(|vertex| get_vertex_score(None, remaining[vertex])).collect::<Vec<f32>>();
+
+    let triangle_score = |triangle: usize, vertex_scores: &[f32]| -> f32 {
+        return (0..3).map(|corner| vertex_scores[indices[triangle * 3 + corner] as usize]).sum();
+    };
+
+    let mut is_emitted = vec![false; triangle_count];
+    let mut cache      = Vec::<u32>::with_capacity(VERTEX_CACHE_SIZE + 3);
+    let mut result     = Vec::<u32>::with_capacity(triangle_count * 3);
+
+    let mut best_triangle = (0..triangle_count).max_by(|a, b| triangle_score(*a, &vertex_scores).total_cmp(&triangle_score(*b, &vertex_scores)));
+    let mut next_unemitted = 0;
+
+    while result.len() < triangle_count * 3 {
+        // Nothing in the cache has triangles left, start over from the next triangle in the input order
+        let triangle = match best_triangle {
+            Some(triangle) => triangle,
+            None           => {
+                while is_emitted[next_unemitted] {
+                    next_unemitted += 1;
+                }
+                next_unemitted
+            },
+        };
+
+        is_emitted[triangle] = true;
+
+        let corners = [indices[triangle * 3], indices[triangle * 3 + 1], indices[triangle * 3 + 2]];
+        result.extend_from_slice(&corners);
+
+        // The triangle's vertices move to the front of the cache, the ones pushed out the back are evicted
+        for corner in corners {
+            remaining[corner as usize] -= 1;
+            if let Some(position) = cache.iter().position(|cached| *cached == corner) {
+                cache.remove(position);
+            }
+        }
+        cache.splice(0..0, corners);
+
+        for (position, vertex) in cache.iter().enumerate() {
+            cache_position[*vertex as usize] = if position < VERTEX_CACHE_SIZE { Some(position) } else { None };
+            vertex_scores[*vertex as usize]  = get_vertex_score(cache_position[*vertex as usize], remaining[*vertex as usize]);
+        }
+        cache.truncate(VERTEX_CACHE_SIZE);
+
+        // Only the triangles of cached vertices changed their score
+        best_triangle = None;
+        let mut best_score = 0.0;
+        for vertex in &cache {
+            let vertex = *vertex as usize;
+            for &candidate in &triangles_of[offsets[vertex]..offsets[vertex + 1]] {
+                let candidate = candidate as usize;
+                if is_emitted[candidate] {
+                    continue;
+                }
+
+                let score = triangle_score(candidate, &vertex_scores);
+                if score > best_score {
+                    best_score    = score;
+                    best_triangle = Some(candidate);
+                }
+            }
+        }
+    }
+
+    indices[0..triangle_count * 3].copy_from_slice(&result);
+}
+
+/// Average cache miss ratio of a triangle order: how many vertices a FIFO post-transform cache of `cache_size` has to
+/// transform per triangle. 3 is the worst, around 0.6 is the best a regular mesh can do.
+pub fn compute_acmr(indices: &[u32], vertex_count: usize, cache_size: usize) -> f32 {
+    let triangle_count = indices.len() / 3;
+    if triangle_count == 0 {
+        return 0.0;
+    }
+
+    let misses = count_cache_misses(indices, vertex_count, cache_size);
+    return misses.iter().sum::<u32>() as f32 / triangle_count as f32;
+}
+
+/// Vertices transformed by each triangle with a FIFO cache of `cache_size`, starting out empty.
+fn count_cache_misses(indices: &[u32], vertex_count: usize, cache_size: usize) -> Vec<u32> {
+    let mut cache = CacheSimulation::new(vertex_count, cache_size);
+    return indices.chunks_exact(3).map(|triangle| cache.add_triangle(triangle)).collect();
+}
+
+/// A FIFO post-transform cache, fed one triangle at a time. Emptying it is free, so a single simulation (and its one
+/// buffer) can walk every cluster of a mesh.
+struct CacheSimulation {
+    transformed_at: Vec<usize>, // when each vertex was last transformed, usize::MAX if never
+    transformed:    usize,      // vertices transformed so far, across every restart
+    restarted_at:   usize,      // vertices transformed before the cache was last emptied
+    cache_size:     usize,
+}
+
+impl CacheSimulation {
+    fn new(vertex_count: usize, cache_size: usize) -> Self {
+        Self{
+            transformed_at: vec![usize::MAX; vertex_count],
+            transformed:    0,
+            restarted_at:   0,
+            cache_size,
+        }
+    }
+
+    /// Empties the cache, the vertices transformed before no longer count as cached.
+    fn restart(&mut self) {
+        self.restarted_at = self.transformed;
+    }
+
+    /// Vertices the triangle transforms, the ones that miss the cache.
+    fn add_triangle(&mut self, triangle: &[u32]) -> u32 {
+        // A vertex is cached while fewer than cache_size vertices were transformed after it
+        let mut misses = 0;
+        for index in triangle {
+            let at        = self.transformed_at[*index as usize];
+            let is_cached = at != usize::MAX && at >= self.restarted_at && self.transformed - at < self.cache_size;
+            if !is_cached {
+                self.transformed_at[*index as usize] = self.transformed;
+                self.transformed += 1;
+                misses           += 1;
+            }
+        }
+
+        return misses;
+    }
+}
+
+/// Reorders the triangles so the parts of the mesh facing outwards are drawn first, and hide what is behind them from
+/// the fragment shader (Sander, Nehab and Barczak, "Fast Triangle Reordering for Vertex Locality and Reduced
+/// Overdraw"). Run after optimize_vertex_cache(), the cache order is split into clusters where the cache starts over,
+/// and into smaller clusters as long as their ACMR stays within `threshold` of the cluster they were split from (1.05
+/// allows 5% more cache misses). The clusters are then sorted by how much they face away from the mesh's center.
+pub fn optimize_overdraw(indices: &mut [u32], vertices: &[Vertex], threshold: f32) {
+    let triangle_count = indices.len() / 3;
+    if triangle_count == 0 {
+        return;
+    }
+
+    let triangle = |index: usize| -> &[u32] { &indices[index * 3..index * 3 + 3] };
+    let mut cache = CacheSimulation::new(vertices.len(), VERTEX_CACHE_SIZE);
+
+    // Hard boundaries: triangles whose vertices all miss the cache, nothing before them helps
+    let mut hard_boundaries = Vec::<usize>::new();
+    for index in 0..triangle_count {
+        if cache.add_triangle(triangle(index)) == 3 || index == 0 {
+            hard_boundaries.push(index);
+        }
+    }
+    hard_boundaries.push(triangle_count);
+
+    // Soft boundaries: split each cluster as soon as the piece so far is within the threshold of the cluster's ACMR.
+    // Every piece starts with an empty cache, like its cluster did.
+    let mut clusters = Vec::<(usize, usize)>::new();
+    for range in hard_boundaries.windows(2) {
+        let (start, end) = (range[0], range[1]);
+
+        cache.restart();
+        let cluster_misses    = (start..end).map(|index| cache.add_triangle(triangle(index))).sum::<u32>();
+        let cluster_threshold = threshold * cluster_misses as f32 / (end - start) as f32;
+
+        cache.restart();
+        let mut piece_start = start;
+        let mut miss_count  = 0;
+        for index in start..end {
+            miss_count += cache.add_triangle(triangle(index));
+            if miss_count as f32 / (index + 1 - piece_start) as f32 <= cluster_threshold {
+                clusters.push((piece_start, index + 1));
+                piece_start = index + 1;
+                miss_count  = 0;
+                cache.restart();
+            }
+        }
+
+        if piece_start < end {
+            clusters.push((piece_start, end));
+        }
+    }
+
+    // Area weighted centroid and normal of every cluster, and of the whole mesh
+    let position = |corner: usize| -> Float3 { vertices[indices[corner] as usize].position };
+
+    let mut mesh_centroid = Float3::zero();
+    let mut mesh_area     = 0.0;
+
+    let mut cluster_keys = Vec::<(Float3, Float3)>::with_capacity(clusters.len());
+    for (start, end) in &clusters {
+        let mut centroid = Float3::zero();
+        let mut normal   = Float3::zero();
+        let mut area     = 0.0;
+
+        for triangle in *start..*end {
+            let p0 = position(triangle * 3);
+            let p1 = position(triangle * 3 + 1);
+            let p2 = position(triangle * 3 + 2);
+
+            let triangle_normal = (p1 - p0).cross(p2 - p0);
+            let triangle_area   = triangle_normal.length() * 0.5;
+
+            centroid = centroid + (p0 + p1 + p2) * (triangle_area / 3.0);
+            normal   = normal + triangle_normal;
+            area    += triangle_area;
+        }
+
+        mesh_centroid = mesh_centroid + centroid;
+        mesh_area    += area;
+
+        cluster_keys.push((if area > 0.0 { centroid / area } else { position(start * 3) }, normal.unit()));
+    }
+
+    if mesh_area > 0.0 {
+        mesh_centroid = mesh_centroid / mesh_area;
+    }
+
+    let sort_keys = cluster_keys.iter().map(|(centroid, normal)| (*centroid - mesh_centroid).dot(*normal)).collect::<Vec<f32>>();
+
+    // Most outward facing first
+    let mut order = (0..clusters.len()).collect::<Vec<usize>>();
+    order.sort_by(|a, b| sort_keys[*b].total_cmp(&sort_keys[*a]));
+
+    let mut result = Vec::<u32>::with_capacity(triangle_count * 3);
+    for cluster in order {
+        let (start, end) = clusters[cluster];
+        result.extend_from_slice(&indices[start * 3..end * 3]);
+    }
+
+    indices[0..triangle_count * 3].copy_from_slice(&result);
+}
+
+/// The bounds the renderer culls a mesh with.
+#[derive(Clone, Copy, Debug)]
+pub struct MeshBounds {
+    pub aabb:   BoundingBox,
+    pub sphere: Float4, // xyz: center, w: radius
+}
+
+/// Bounds of the vertices the indices refer to. The sphere is the smaller of the sphere around the bounding box and
+/// Ritter's sphere ("An Efficient Bounding Sphere"), which is tighter for most meshes that aren't boxes. An empty mesh
+/// gets empty bounds at the origin.
+pub fn compute_bounds(vertices: &[Vertex], indices: &[u32]) -> MeshBounds {
+    let mut is_used = vec![false; vertices.len()];
+    for index in indices {
+        is_used[*index as usize] = true;
+    }
+
+    let points = vertices.iter().zip(is_used.iter()).filter(|(_, used)| **used).map(|(vertex, _)| vertex.position).collect::<Vec<Float3>>();
+    if points.is_empty() {
+        return MeshBounds{ aabb: BoundingBox::default(), sphere: Float4::zero() };
+    }
+
+    let mut aabb = BoundingBox{ min: points[0], max: points[0] };
+    for point in &points {
+        aabb.min = Float3::new(aabb.min.x.min(point.x), aabb.min.y.min(point.y), aabb.min.z.min(point.z));
+        aabb.max = Float3::new(aabb.max.x.max(point.x), aabb.max.y.max(point.y), aabb.max.z.max(point.z));
+    }
+
+    let box_center = aabb.get_center();
+    let box_radius = points.iter().fold(0.0f32, |radius, point| radius.max((*point - box_center).length()));
+
+    // Ritter: start from two points far apart, then grow the sphere just enough to take in every point outside it
+    let farthest_from = |from: Float3| -> Float3 {
+        return *points.iter().max_by(|a, b| (**a - from).squared_length().total_cmp(&(**b - from).squared_length())).expect("There is at least one point.");
+    };
+
+    let a = farthest_from(points[0]);
+    let b = farthest_from(a);
+
+    let mut center = (a + b) * 0.5;
+    let mut radius = (b - a).length() * 0.5;
+
+    for point in &points {
+        let distance = (*point - center).length();
+        if distance > radius {
+            let new_radius = (radius + distance) * 0.5;
+            center = center + (*point - center) * ((new_radius - radius) / distance);
+            radius = new_radius;
+        }
+    }
+
+    // Rounding can leave the last points a hair outside
+    radius = points.iter().fold(radius, |radius, point| radius.max((*point - center).length()));
+
+    let sphere = if radius < box_radius {
+        Float4::new(center.x, center.y, center.z, radius)
+    } else {
+        Float4::new(box_center.x, box_center.y, box_center.z, box_radius)
+    };
+
+    return MeshBounds{ aabb, sphere };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::test_meshes::{ load_obj, load_indexed_obj };
+
+    fn get_face_normal(vertices: &[Vertex], triangle: &[u32]) -> Float3 {
+        let p = [0, 1, 2].map(|corner| vertices[triangle[corner] as usize].position);
+        return (p[1] - p[0]).cross(p[2] - p[0]).unit();
+    }
+
+    /// The triangles of a triangle list, each rotated to start at its smallest index so the winding is kept, sorted.
+    fn get_sorted_triangles(indices: &[u32]) -> Vec<[u32; 3]> {
+        let mut triangles = indices.chunks_exact(3).map(|t| {
+            let first = (0..3).min_by_key(|corner| t[*corner]).unwrap();
+            [t[first], t[(first + 1) % 3], t[(first + 2) % 3]]
+        }).collect::<Vec<[u32; 3]>>();
+
+        triangles.sort();
+        return triangles;
+    }
+
+    fn is_close(a: Float3, b: Float3, tolerance: f32) -> bool {
+        return (a - b).length() <= tolerance;
+    }
+
+    #[test]
+    fn deduplicate_indexes_the_cube() {
+        let (original, _) = load_obj("cube_pos.obj");
+        let (vertices, indices) = load_indexed_obj("cube_pos.obj");
+
+        assert_eq!(vertices.len(), 8);
+        assert_eq!(indices.len(), 36);
+
+        // Every corner still has the same vertex
+        for (corner, index) in indices.iter().enumerate() {
+            assert_eq!(get_vertex_key(&vertices[*index as usize]), get_vertex_key(&original[corner]));
+        }
+
+        // The vertices of cube.obj differ in their normals and UVs, a face's corners are the only duplicates
+        let (vertices, _) = load_indexed_obj("cube.obj");
+        assert_eq!(vertices.len(), 24);
+    }
+
+    #[test]
+    fn deduplicate_drops_unused_vertices() {
+        let (mut vertices, mut indices) = load_obj("cube_pos.obj");
+        vertices.push(Vertex{ position: Float3::fill(100.0), ..Vertex::new() });
+
+        let sources = deduplicate_vertices(&mut vertices, &mut indices);
+
+        assert_eq!(vertices.len(), 8);
+        assert_eq!(sources.len(), 8);
+        assert!(vertices.iter().all(|vertex| vertex.position.x.abs() == 1.0));
+    }
+
+    #[test]
+    fn weld_merges_nearby_vertices() {
+        let (mut vertices, mut indices) = load_obj("cube_pos.obj");
+
+        // Exports often round the same position differently in different faces
+        for (corner, vertex) in vertices.iter_mut().enumerate() {
+            let jitter = ((corner % 7) as f32 - 3.0) * 1e-6;
+            vertex.position = vertex.position + Float3::new(jitter, -jitter, jitter * 0.5);
+        }
+
+        let jittered = vertices.clone();
+        let sources  = weld_vertices(&mut vertices, &mut indices, &WeldTolerance::default());
+
+        // Each corner's vertex is one of the jittered copies of its position
+        assert_eq!(vertices.len(), 8);
+        for (corner, index) in indices.iter().enumerate() {
+            assert!(is_close(vertices[*index as usize].position, jittered[corner].position, 1e-5));
+            assert!(vertices[*index as usize].position == jittered[sources[*index as usize] as usize].position);
+        }
+
+        // A tolerance smaller than the jitter leaves the corners apart
+        let (mut vertices, mut indices) = load_obj("cube_pos.obj");
+        for (corner, vertex) in vertices.iter_mut().enumerate() {
+            vertex.position.x += (corner % 2) as f32 * 1e-3;
+        }
+
+        weld_vertices(&mut vertices, &mut indices, &WeldTolerance::default());
+        assert_eq!(vertices.len(), 16);
+    }
+
+    #[test]
+    fn weld_keeps_different_attributes_apart() {
+        let (mut vertices, mut indices) = load_obj("cube.obj");
+        weld_vertices(&mut vertices, &mut indices, &WeldTolerance::default());
+        assert_eq!(vertices.len(), 24);
+
+        // With every attribute ignored but the position, only the 8 corners of the cube are left
+        let (mut vertices, mut indices) = load_obj("cube.obj");
+        let tolerance = WeldTolerance{ normal: 180.0, uv: 1.0, ..WeldTolerance::default() };
+
+        weld_vertices(&mut vertices, &mut indices, &tolerance);
+        assert_eq!(vertices.len(), 8);
+    }
+
+    #[test]
+    fn flat_normals_follow_the_faces() {
+        let (mut vertices, mut indices) = load_indexed_obj("cube_pos.obj");
+
+        let sources = generate_normals(&mut vertices, &mut indices, 0.0);
+
+        // Every corner of the cube is split into the 3 faces meeting there
+        assert_eq!(vertices.len(), 24);
+        assert_eq!(sources.len(), 24);
+
+        for triangle in indices.chunks_exact(3) {
+            let face_normal = get_face_normal(&vertices, triangle);
+            for index in triangle {
+                assert!(is_close(vertices[*index as usize].normal, face_normal, 1e-5));
+            }
+        }
+
+        // The cube's faces are 90 degrees apart, a 60 degree threshold doesn't smooth across them either
+        let (mut vertices, mut indices) = load_indexed_obj("cube_pos.obj");
+        generate_normals(&mut vertices, &mut indices, 60.0);
+        assert_eq!(vertices.len(), 24);
+    }
+
+    #[test]
+    fn smooth_normals_average_the_faces() {
+        let (mut vertices, mut indices) = load_indexed_obj("cube_pos.obj");
+
+        generate_normals(&mut vertices, &mut indices, 180.0);
+
+        // Each corner is shared by 3 faces at right angles, weighted by their corner angles, so the normals point
+        // away from the center no matter how the faces were triangulated
+        assert_eq!(vertices.len(), 8);
+        for vertex in &vertices {
+            assert!(is_close(vertex.normal, vertex.position.unit(), 1e-5));
+        }
+    }
+
+    #[test]
+    fn normals_stay_within_the_smoothing_angle() {
+        let mut previous_count = usize::MAX;
+
+        for angle in [0.0f32, 30.0, 60.0, 90.0, 180.0] {
+            let (mut vertices, mut indices) = load_indexed_obj("suzanne.obj");
+            let original = vertices.clone();
+
+            let sources = generate_normals(&mut vertices, &mut indices, angle);
+
+            // The average of normals within the angle of the face's normal is within the angle too
+            let min_cosine = angle.to_radians().cos() - 1e-3;
+            for triangle in indices.chunks_exact(3) {
+                let face_normal = get_face_normal(&vertices, triangle);
+                for index in triangle {
+                    let normal = vertices[*index as usize].normal;
+                    assert!((normal.length() - 1.0).abs() < 1e-4);
+                    assert!(face_normal.is_zero() || normal.dot(face_normal) >= min_cosine);
+                }
+            }
+
+            for (vertex, source) in vertices.iter().zip(sources.iter()) {
+                assert!(vertex.position == original[*source as usize].position);
+            }
+
+            // Smoothing across more edges only ever merges normals
+            assert!(vertices.len() <= previous_count);
+            previous_count = vertices.len();
+        }
+    }
+
+    #[test]
+    fn tangents_follow_the_uvs() {
+        let (mut vertices, mut indices) = load_indexed_obj("cube.obj");
+
+        generate_tangents(&mut vertices, &mut indices);
+
+        for triangle in indices.chunks_exact(3) {
+            let v = [0, 1, 2].map(|corner| vertices[triangle[corner] as usize]);
+
+            // The direction u increases in along the face, solved from the triangle's edges and UVs
+            let edge1 = v[1].position - v[0].position;
+            let edge2 = v[2].position - v[0].position;
+            let (du1, dv1) = (v[1].uv_x - v[0].uv_x, v[1].uv_y - v[0].uv_y);
+            let (du2, dv2) = (v[2].uv_x - v[0].uv_x, v[2].uv_y - v[0].uv_y);
+
+            let uv_area = du1 * dv2 - du2 * dv1;
+            let dp_du   = ((edge1 * dv2 - edge2 * dv1) / uv_area).unit();
+            let dp_dv   = ((edge2 * du1 - edge1 * du2) / uv_area).unit();
+
+            for vertex in &v {
+                let tangent = Float3::new(vertex.tangent.x, vertex.tangent.y, vertex.tangent.z);
+
+                assert!((tangent.length() - 1.0).abs() < 1e-5);
+                assert!(tangent.dot(vertex.normal).abs() < 1e-5);
+                assert!(tangent.dot(dp_du) > 0.999);
+
+                // The shader's bitangent points where v increases
+                let bitangent = vertex.normal.cross(tangent) * vertex.tangent.w;
+                assert!(bitangent.dot(dp_dv) > 0.999);
+            }
+        }
+    }
+
+    #[test]
+    fn tangents_split_mirrored_vertices() {
+        let (mut vertices, mut indices) = load_indexed_obj("suzanne.obj");
+        let original       = vertices.clone();
+        let original_count = vertices.len();
+
+        // Mirror the UVs of every other triangle, so vertices are shared by both handednesses
+        let mut mirrored_indices = indices.clone();
+        for triangle in (0..indices.len() / 3).filter(|triangle| triangle % 2 == 1) {
+            mirrored_indices[triangle * 3..triangle * 3 + 3].swap(1, 2);
+        }
+
+        let sources = generate_tangents(&mut vertices, &mut indices);
+
+        assert_eq!(sources.len(), vertices.len());
+        assert!(sources[0..original_count].iter().enumerate().all(|(vertex, source)| *source as usize == vertex));
+
+        for (vertex, source) in vertices.iter().zip(sources.iter()) {
+            let source = &original[*source as usize];
+            assert!(vertex.position == source.position && vertex.normal == source.normal);
+            assert!(vertex.uv_x == source.uv_x && vertex.uv_y == source.uv_y);
+            assert!(vertex.tangent.w == 1.0 || vertex.tangent.w == -1.0);
+        }
+
+        // Every corner of a triangle with UVs has the triangle's handedness
+        for triangle in indices.chunks_exact(3) {
+            let v = [0, 1, 2].map(|corner| vertices[triangle[corner] as usize]);
+            let uv_area = (v[1].uv_x - v[0].uv_x) * (v[2].uv_y - v[0].uv_y) - (v[2].uv_x - v[0].uv_x) * (v[1].uv_y - v[0].uv_y);
+            if uv_area.abs() < 1e-12 {
+                continue;
+            }
+
+            for vertex in &v {
+                assert_eq!(vertex.tangent.w, uv_area.signum());
+            }
+        }
+
+        // Flipped triangles have mirrored UVs, their vertices are split off
+        let mut vertices = original.clone();
+        let sources      = generate_tangents(&mut vertices, &mut mirrored_indices);
+        assert!(vertices.len() > original_count);
+        assert_eq!(sources.len(), vertices.len());
+    }
+
+    /// Suzanne smooth shaded, with its triangles in a random order, which a vertex cache handles about as badly as any
+    /// order. The file's flat normals would leave no vertices shared between triangles to reuse.
+    fn load_shuffled_suzanne() -> (Vec<Vertex>, Vec<u32>) {
+        let (mut vertices, mut indices) = load_obj("suzanne.obj");
+        for vertex in &mut vertices {
+            vertex.normal = Float3::zero();
+        }
+
+        deduplicate_vertices(&mut vertices, &mut indices);
+        generate_normals(&mut vertices, &mut indices, 180.0);
+
+        let mut triangles = indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect::<Vec<[u32; 3]>>();
+        let mut state     = 0x2545_f491u32;
+        for triangle in (1..triangles.len()).rev() {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            triangles.swap(triangle, (state >> 8) as usize % (triangle + 1));
+        }
+
+        return (vertices, triangles.concat());
+    }
+
+    #[test]
+    fn vertex_cache_order_reduces_misses() {
+        let (vertices, mut indices) = load_shuffled_suzanne();
+        let original = indices.clone();
+
+        let shuffled_acmr = compute_acmr(&indices, vertices.len(), VERTEX_CACHE_SIZE);
+        optimize_vertex_cache(&mut indices, vertices.len());
+        let optimized_acmr = compute_acmr(&indices, vertices.len(), VERTEX_CACHE_SIZE);
+
+        assert!(optimized_acmr < shuffled_acmr * 0.5, "ACMR went from {} to {}", shuffled_acmr, optimized_acmr);
+        assert!(optimized_acmr < 1.0, "ACMR is {}", optimized_acmr);
+
+        // Only the order of the triangles changed
+        assert_eq!(get_sorted_triangles(&indices), get_sorted_triangles(&original));
+    }
+
+    #[test]
+    fn tangents_average_each_fan_on_its_own() {
+        let make_vertex = |x: f32, y: f32, u: f32, v: f32| Vertex{
+            position: Float3::new(x, y, 0.0),
+            normal:   Float3::new(0.0, 0.0, 1.0),
+            uv_x:     u,
+            uv_y:     v,
+            ..Vertex::new()
+        };
+
+        // Vertex 0 is shared by two fans that only touch there: triangles 0 and 1 share the edge 0-1 and have their
+        // u along +x, triangle 2 only has the vertex and has its u along +y
+        let mut vertices = vec![
+            make_vertex(0.0, 0.0, 0.0, 0.0),
+            make_vertex(1.0, 0.0, 1.0, 0.0),
+            make_vertex(1.0, 1.0, 1.0, 1.0),
+            make_vertex(0.0, -1.0, 0.0, -1.0),
+            make_vertex(-1.0, 0.0, 0.0, 1.0),
+            make_vertex(-1.0, -1.0, -1.0, 1.0),
+        ];
+        let mut indices = vec![0, 1, 2, 0, 3, 1, 0, 4, 5];
+
+        let sources = generate_tangents(&mut vertices, &mut indices);
+
+        // The second fan is split off into a vertex of its own, the connected triangles share theirs
+        assert_eq!(vertices.len(), 7);
+        assert_eq!(sources, [0, 1, 2, 3, 4, 5, 0]);
+        assert_eq!(indices, [0, 1, 2, 0, 3, 1, 6, 4, 5]);
+
+        let tangent = |vertex: &Vertex| Float3::new(vertex.tangent.x, vertex.tangent.y, vertex.tangent.z);
+        assert!(is_close(tangent(&vertices[0]), Float3::new(1.0, 0.0, 0.0), 1e-5));
+        assert!(is_close(tangent(&vertices[6]), Float3::new(0.0, 1.0, 0.0), 1e-5));
+        assert!(vertices[0].tangent.w == 1.0 && vertices[6].tangent.w == 1.0);
+    }
+
+    #[test]
+    fn acmr_counts_cache_misses() {
+        // A strip of quads, every triangle after the first reuses 2 vertices
+        let indices = [0, 1, 2, 2, 1, 3, 2, 3, 4, 4, 3, 5];
+        assert_eq!(compute_acmr(&indices, 6, VERTEX_CACHE_SIZE), 6.0 / 4.0);
+
+        // A cache of 3 forgets vertex 1 by the time the last triangle needs it again
+        let indices = [0, 1, 2, 3, 4, 5, 0, 1, 2];
+        assert_eq!(compute_acmr(&indices, 6, VERTEX_CACHE_SIZE), 6.0 / 3.0);
+        assert_eq!(compute_acmr(&indices, 6, 3), 3.0);
+    }
+
+    #[test]
+    fn overdraw_order_keeps_the_cache_order() {
+        const THRESHOLD: f32 = 1.05;
+
+        let (vertices, mut indices) = load_shuffled_suzanne();
+        let original = indices.clone();
+
+        optimize_vertex_cache(&mut indices, vertices.len());
+        let cache_acmr = compute_acmr(&indices, vertices.len(), VERTEX_CACHE_SIZE);
+
+        optimize_overdraw(&mut indices, &vertices, THRESHOLD);
+        let overdraw_acmr = compute_acmr(&indices, vertices.len(), VERTEX_CACHE_SIZE);
+
+        // Clusters are only split where they are about as cache friendly as before, reordering them can cost a few
+        // more misses where they meet
+        assert!(overdraw_acmr <= cache_acmr * THRESHOLD + 0.1, "ACMR went from {} to {}", cache_acmr, overdraw_acmr);
+        assert_eq!(get_sorted_triangles(&indices), get_sorted_triangles(&original));
+    }
+
+    /// How much a triangle faces away from the origin, the sort key of a cluster of one triangle.
+    fn get_outward_key(vertices: &[Vertex], triangle: &[u32]) -> f32 {
+        let p0 = vertices[triangle[0] as usize].position;
+        let p1 = vertices[triangle[1] as usize].position;
+        let p2 = vertices[triangle[2] as usize].position;
+
+        return ((p0 + p1 + p2) / 3.0).dot((p1 - p0).cross(p2 - p0).unit());
+    }
+
+    #[test]
+    fn overdraw_order_draws_outward_faces_first() {
+        let (cube_vertices, cube_indices) = load_indexed_obj("cube_pos.obj");
+
+        // Three nested cubes around the origin, the inner one facing inwards. None of the triangles share vertices, so
+        // every triangle misses the cache and is a cluster of its own, drawn innermost first.
+        let mut vertices = Vec::<Vertex>::new();
+        for triangle in cube_indices.chunks_exact(3) {
+            for (scale, corners) in [(0.5, [0, 2, 1]), (0.75, [0, 1, 2]), (1.0, [0, 1, 2])] {
+                for corner in corners {
+                    let position = cube_vertices[triangle[corner] as usize].position * scale;
+                    vertices.push(Vertex{ position, ..Vertex::new() });
+                }
+            }
+        }
+
+        let mut indices = (0..vertices.len() as u32).collect::<Vec<u32>>();
+        let original    = indices.clone();
+
+        optimize_overdraw(&mut indices, &vertices, 1.05);
+        assert_eq!(get_sorted_triangles(&indices), get_sorted_triangles(&original));
+
+        // The outer cube first, then the middle one, the inward facing cube last
+        let keys = indices.chunks_exact(3).map(|triangle| get_outward_key(&vertices, triangle)).collect::<Vec<f32>>();
+        for pair in keys.windows(2) {
+            assert!(pair[0] >= pair[1] - 1e-5, "clusters are out of order: {:?}", keys);
+        }
+
+        let face_count = cube_indices.len() / 3;
+        assert!(keys[0..face_count].iter().all(|key| (key - 1.0).abs() < 1e-5));
+        assert!(keys[face_count..face_count * 2].iter().all(|key| (key - 0.75).abs() < 1e-5));
+        assert!(keys[face_count * 2..].iter().all(|key| (key + 0.5).abs() < 1e-5));
+    }
+
+    #[test]
+    fn bounds_of_the_cube() {
+        let (vertices, indices) = load_indexed_obj("cube.obj");
+
+        let bounds = compute_bounds(&vertices, &indices);
+
+        assert!(bounds.aabb.min == Float3::fill(-1.0));
+        assert!(bounds.aabb.max == Float3::fill(1.0));
+        assert!(is_close(Float3::new(bounds.sphere.x, bounds.sphere.y, bounds.sphere.z), Float3::zero(), 1e-5));
+        assert!((bounds.sphere.w - 3f32.sqrt()).abs() < 1e-5);
+    }
+
+    #[test]
+    fn bounds_contain_the_mesh() {
+        let (mut vertices, indices) = load_indexed_obj("suzanne.obj");
+
+        // Vertices no index refers to aren't part of the mesh
+        vertices.push(Vertex{ position: Float3::fill(100.0), ..Vertex::new() });
+
+        let bounds = compute_bounds(&vertices, &indices);
+        let center = Float3::new(bounds.sphere.x, bounds.sphere.y, bounds.sphere.z);
+
+        for vertex in &vertices[0..vertices.len() - 1] {
+            let p = vertex.position;
+            assert!(p.x >= bounds.aabb.min.x && p.y >= bounds.aabb.min.y && p.z >= bounds.aabb.min.z);
+            assert!(p.x <= bounds.aabb.max.x && p.y <= bounds.aabb.max.y && p.z <= bounds.aabb.max.z);
+            assert!((p - center).length() <= bounds.sphere.w * (1.0 + 1e-6));
+        }
+
+        assert!(bounds.aabb.max.x < 100.0);
+
+        // Never larger than the sphere around the box
+        let box_radius = vertices[0..vertices.len() - 1].iter().fold(0.0f32, |radius, vertex| radius.max((vertex.position - bounds.aabb.get_center()).length()));
+        assert!(bounds.sphere.w <= box_radius);
+    }
+}
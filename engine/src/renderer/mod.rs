@@ -1,8 +1,29 @@
+pub mod adapter;
 pub mod command_buffer;
+pub mod culling;
+pub mod debug_draw;
+pub mod deferred;
+pub mod environment;
+pub mod error;
+pub mod lod;
 pub mod mesh;
+pub mod mesh_processing;
+pub mod particles;
+pub mod post_process;
+pub mod shadows;
+pub mod sprite;
+pub mod ssao;
 pub mod system;
+pub mod text;
 pub mod thread;
+pub mod transparency;
 
 mod graphics;
+mod lights;
+mod render_graph;
 mod shader;
 mod material_system;
+mod worker_pool;
+
+#[cfg(test)]
+mod test_meshes;
@@ -0,0 +1,591 @@
+use crate::math::{ float3::*, float4::* };
+
+use super::graphics::*;
+use super::shader::{ GpuParticle, GpuParticleEmitter };
+
+use vendor::vulkan::VkDeviceAddress;
+
+//
+// Particles
+//
+// Emitters spawn particles at a steady rate, and each particle lives for a random lifetime, moving in a straight
+// line bent by gravity. The engine creates emitters with RenderCommand::CreateParticleEmitter and they keep emitting
+// until they are destroyed, the renderer advances them every frame.
+//   1. Spawn    - each emitter owns a ring of `max_particles` slots. Every frame the spawner turns the spawn rate
+//                 into a number of new particles, which take over the next slots in the ring. An emitter that spawns
+//                 more particles than it has slots recycles its oldest particles early.
+//   2. Simulate - particle_sim.comp runs one thread per slot on a storage buffer the emitter owns. Threads in the
+//                 spawn range start a new particle, with a random lifetime and velocity in the emitter's cone, the
+//                 others integrate their particle if it's still alive. simulate_particles() is the same simulation
+//                 on the CPU, used with ParticleSimulation::Cpu and by CpuParticleSimulation.
+//   3. Draw     - particle.vert expands every slot into a camera-facing quad, dead slots are degenerate. Size, color
+//                 and texture sheet frame are interpolated over the particle's life. Particles are drawn into the
+//                 HDR scene after the background, tested against the scene depth without writing to it, so they
+//                 are tonemapped and bloom like the rest of the scene.
+//
+// The spawn positions and velocities come from a hash of the slot index and a per-frame seed, so the CPU and GPU
+// simulations make the same particles, up to floating point differences. Alpha blended particles are not sorted,
+// they are drawn in slot order.
+//
+
+/// Emitters can't have more particles than this
+pub const MAX_PARTICLES_PER_EMITTER: u32 = 65536;
+
+// Frames longer than this are simulated as this long, so a hitch doesn't fling particles across the scene
+const MAX_PARTICLE_TIME_STEP: f32 = 0.1;
+
+// Spawn rates are clamped to this, which refills the largest ring every frame while the spawner stays exact
+const MAX_PARTICLE_SPAWN_RATE: f32 = 1.0e8;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ParticleBlend {
+    Additive,   // particles add light, for fire, sparks and magic
+    AlphaBlend, // particles cover what's behind them, for smoke and dust
+}
+
+/// Where the particles are simulated.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ParticleSimulation {
+    Gpu, // in a compute pass, the particles never leave the GPU
+    Cpu, // on the render thread, the particles are uploaded every frame
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ParticleSettings {
+    pub simulation: ParticleSimulation,
+}
+
+impl Default for ParticleSettings {
+    fn default() -> Self {
+        Self{
+            simulation: ParticleSimulation::Gpu,
+        }
+    }
+}
+
+/// A texture of equally sized frames, played from the top-left frame to the bottom-right frame over a particle's
+/// life. A single frame sheet is a plain texture.
+#[derive(Clone, Copy, Debug)]
+pub struct TextureSheet {
+    pub texture: u64, // CreateTextureInfo::engine_id
+    pub columns: u32,
+    pub rows:    u32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ParticleEmitter {
+    pub engine_id:     u64,
+    pub position:      Float3, // world units, where particles spawn
+    pub max_particles: u32,    // slots in the emitter's ring, clamped to MAX_PARTICLES_PER_EMITTER
+    pub spawn_rate:    f32,    // particles per second
+    pub lifetime_min:  f32,    // seconds
+    pub lifetime_max:  f32,
+    pub direction:     Float3, // center of the cone particles are launched in
+    pub cone_angle:    f32,    // radians from the direction to the edge of the cone
+    pub speed_min:     f32,    // world units per second
+    pub speed_max:     f32,
+    pub gravity:       Float3, // world units per second squared
+    pub color_start:   Float4, // linear HDR color at birth, interpolated to color_end at death
+    pub color_end:     Float4,
+    pub size_start:    f32,    // world units, interpolated to size_end at death
+    pub size_end:      f32,
+    pub texture_sheet: Option<TextureSheet>, // None draws a soft round dot
+    pub blend:         ParticleBlend,
+}
+
+impl Default for ParticleEmitter {
+    fn default() -> Self {
+        Self{
+            engine_id:     0,
+            position:      Float3::zero(),
+            max_particles: 1024,
+            spawn_rate:    100.0,
+            lifetime_min:  1.0,
+            lifetime_max:  2.0,
+            direction:     Float3::new(0.0, 1.0, 0.0),
+            cone_angle:    0.3,
+            speed_min:     1.0,
+            speed_max:     2.0,
+            gravity:       Float3::new(0.0, -9.8, 0.0),
+            color_start:   Float4::one(),
+            color_end:     Float4::new(1.0, 1.0, 1.0, 0.0),
+            size_start:    0.1,
+            size_end:      0.1,
+            texture_sheet: None,
+            blend:         ParticleBlend::Additive,
+        }
+    }
+}
+
+impl ParticleEmitter {
+    pub fn get_capacity(&self) -> u32 {
+        return self.max_particles.clamp(1, MAX_PARTICLES_PER_EMITTER);
+    }
+
+    /// The emitter with its spawn rate and lifetimes made finite and non-negative. NaN becomes zero, so a bad value
+    /// stops the emitter instead of poisoning its spawner, and infinity is clamped to the largest usable value.
+    pub fn get_validated(&self) -> ParticleEmitter {
+        return ParticleEmitter{
+            spawn_rate:   clamp_finite(self.spawn_rate, MAX_PARTICLE_SPAWN_RATE),
+            lifetime_min: clamp_finite(self.lifetime_min, f32::MAX),
+            lifetime_max: clamp_finite(self.lifetime_max, f32::MAX),
+            ..*self
+        };
+    }
+}
+
+fn clamp_finite(value: f32, max: f32) -> f32 {
+    if value.is_nan() {
+        return 0.0;
+    }
+
+    return value.clamp(0.0, max);
+}
+
+/// A particle slot. The slot is dead once the particle's age reaches its lifetime, and empty slots have a lifetime
+/// of zero.
+#[derive(Clone, Copy, Debug)]
+pub struct Particle {
+    pub position: Float3,
+    pub velocity: Float3,
+    pub age:      f32,
+    pub lifetime: f32,
+}
+
+impl Default for Particle {
+    fn default() -> Self {
+        return Self{ position: Float3::zero(), velocity: Float3::zero(), age: 0.0, lifetime: 0.0 };
+    }
+}
+
+impl Particle {
+    pub fn is_alive(&self) -> bool {
+        return self.age < self.lifetime;
+    }
+}
+
+/// One frame of an emitter: which slots spawn a particle, and how far the rest move.
+#[derive(Clone, Copy, Default, Debug)]
+pub(crate) struct ParticleStep {
+    pub spawn_start: u32, // first slot of the spawn range, which wraps around the ring
+    pub spawn_count: u32,
+    pub seed:        u32, // varies the spawned particles from frame to frame
+    pub delta_time:  f32, // seconds
+}
+
+/// Turns an emitter's spawn rate into spawn ranges in its ring, carrying fractional particles between frames.
+#[derive(Clone, Copy, Default, Debug)]
+pub(crate) struct ParticleSpawner {
+    accumulator: f32,
+    next_slot:   u32,
+    frame:       u32,
+}
+
+impl ParticleSpawner {
+    pub fn advance(&mut self, emitter: &ParticleEmitter, delta_time: f32) -> ParticleStep {
+        let capacity   = emitter.get_capacity();
+        let delta_time = delta_time.clamp(0.0, MAX_PARTICLE_TIME_STEP);
+
+        self.accumulator += clamp_finite(emitter.spawn_rate, MAX_PARTICLE_SPAWN_RATE) * delta_time;
+
+        let spawn_count = (self.accumulator.floor() as u32).min(capacity);
+        self.accumulator -= self.accumulator.floor();
+
+        let step = ParticleStep{
+            spawn_start: self.next_slot % capacity,
+            spawn_count,
+            seed:        pcg_hash(self.frame),
+            delta_time,
+        };
+
+        self.next_slot = (self.next_slot + spawn_count) % capacity;
+        self.frame     = self.frame.wrapping_add(1);
+
+        return step;
+    }
+}
+
+/// Advances the particles one step. Matches particle_sim.comp, `particles` holds the emitter's whole ring.
+pub(crate) fn simulate_particles(particles: &mut [Particle], emitter: &ParticleEmitter, step: &ParticleStep) {
+    let capacity = particles.len() as u32;
+
+    for (index, particle) in particles.iter_mut().enumerate() {
+        let index = index as u32;
+
+        // Distance from the start of the spawn range, around the ring
+        let spawn_offset = (index + capacity - step.spawn_start) % capacity;
+
+        if spawn_offset < step.spawn_count {
+            *particle = spawn_particle(emitter, index, step.seed);
+        } else if particle.is_alive() {
+            particle.velocity = particle.velocity + emitter.gravity * step.delta_time;
+            particle.position = particle.position + particle.velocity * step.delta_time;
+            particle.age     += step.delta_time;
+        }
+    }
+}
+
+/// An emitter simulated on the CPU, without a renderer. Runs the same simulation as the GPU, for tests and tools.
+pub struct CpuParticleSimulation {
+    pub emitter: ParticleEmitter,
+    particles:   Vec<Particle>,
+    spawner:     ParticleSpawner,
+}
+
+impl CpuParticleSimulation {
+    pub fn new(emitter: ParticleEmitter) -> Self {
+        return Self{
+            emitter:   emitter.get_validated(),
+            particles: vec![Particle::default(); emitter.get_capacity() as usize],
+            spawner:   ParticleSpawner::default(),
+        };
+    }
+
+    /// Spawns and moves the particles `delta_time` seconds forward.
+    pub fn update(&mut self, delta_time: f32) {
+        // The emitter can be changed between updates
+        self.emitter = self.emitter.get_validated();
+
+        let capacity = self.emitter.get_capacity() as usize;
+        if self.particles.len() != capacity {
+            self.reset();
+        }
+
+        let step = self.spawner.advance(&self.emitter, delta_time);
+        simulate_particles(&mut self.particles, &self.emitter, &step);
+    }
+
+    /// Kills every particle.
+    pub fn reset(&mut self) {
+        self.particles = vec![Particle::default(); self.emitter.get_capacity() as usize];
+        self.spawner   = ParticleSpawner::default();
+    }
+
+    /// Every slot of the emitter's ring, alive or not.
+    pub fn get_particles(&self) -> &[Particle] {
+        return &self.particles;
+    }
+
+    pub fn get_alive_count(&self) -> usize {
+        return self.particles.iter().filter(|particle| particle.is_alive()).count();
+    }
+}
+
+/// An emitter on the renderer. The particles live in `buffer` with GPU simulation, or in `cpu_particles` with CPU
+/// simulation.
+pub(crate) struct ParticleEmitterState {
+    pub emitter:        ParticleEmitter,
+    pub spawner:        ParticleSpawner,
+    pub buffer:         AllocatedBuffer, // GpuParticle ring
+    pub buffer_address: VkDeviceAddress,
+    pub needs_reset:    bool,            // the buffer holds garbage, kill every particle in the next simulation pass
+    pub cpu_particles:  Vec<Particle>,
+}
+
+impl ParticleEmitterState {
+    pub fn new(emitter: ParticleEmitter, buffer: AllocatedBuffer, buffer_address: VkDeviceAddress) -> Self {
+        return Self{
+            emitter:       emitter.get_validated(),
+            spawner:       ParticleSpawner::default(),
+            buffer,
+            buffer_address,
+            needs_reset:   true,
+            cpu_particles: vec![Particle::default(); emitter.get_capacity() as usize],
+        };
+    }
+
+    /// Kills every particle, in both simulations.
+    pub fn reset(&mut self) {
+        self.spawner       = ParticleSpawner::default();
+        self.needs_reset   = true;
+        self.cpu_particles = vec![Particle::default(); self.emitter.get_capacity() as usize];
+    }
+}
+
+/// One emitter's draw, and its simulation dispatch when it is simulated on the GPU
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ParticleDraw {
+    pub emitter_index:   u32,             // into the GpuParticleEmitter buffer, and the renderer's emitters
+    pub particle_buffer: VkDeviceAddress, // GpuParticle ring
+    pub capacity:        u32,
+    pub texture_index:   u32,             // into the bindless texture array, NO_PARTICLE_TEXTURE for a soft dot
+    pub blend:           ParticleBlend,
+    pub gpu_step:        Option<(ParticleStep, bool)>, // the step and whether to reset, for GPU simulated emitters
+}
+
+/// A frame's emitters, simulated on the CPU or ready to simulate on the GPU, and ready to draw
+pub(crate) struct ParticleDrawList {
+    pub emitter_buffer: VkDeviceAddress, // GpuParticleEmitter array
+    pub draws:          Vec<ParticleDraw>,
+}
+
+/// particle.frag draws a soft round dot for emitters without a texture sheet
+pub(crate) const NO_PARTICLE_TEXTURE: u32 = u32::MAX;
+
+/// Packs an emitter's settings for particle_sim.comp and particle.vert.
+pub(crate) fn pack_particle_emitter(emitter: &ParticleEmitter) -> GpuParticleEmitter {
+    let direction = if emitter.direction.is_zero() { Float3::new(0.0, 1.0, 0.0) } else { emitter.direction.unit() };
+    let (columns, rows) = match emitter.texture_sheet {
+        Some(sheet) => (sheet.columns.max(1), sheet.rows.max(1)),
+        None        => (1, 1),
+    };
+
+    return GpuParticleEmitter{
+        position:       Float4::new(emitter.position.x, emitter.position.y, emitter.position.z, emitter.cone_angle),
+        direction:      Float4::new(direction.x, direction.y, direction.z, 0.0),
+        speed_lifetime: Float4::new(emitter.speed_min, emitter.speed_max, emitter.lifetime_min, emitter.lifetime_max),
+        gravity:        Float4::new(emitter.gravity.x, emitter.gravity.y, emitter.gravity.z, 0.0),
+        color_start:    emitter.color_start,
+        color_end:      emitter.color_end,
+        size_sheet:     Float4::new(emitter.size_start, emitter.size_end, columns as f32, rows as f32),
+    };
+}
+
+/// Packs CPU simulated particles for particle.vert.
+pub(crate) fn pack_particles(particles: &[Particle]) -> Vec<GpuParticle> {
+    return particles.iter()
+        .map(|particle| GpuParticle{
+            position_age:      Float4::new(particle.position.x, particle.position.y, particle.position.z, particle.age),
+            velocity_lifetime: Float4::new(particle.velocity.x, particle.velocity.y, particle.velocity.z, particle.lifetime),
+        })
+        .collect();
+}
+
+// Matches spawn_particle() in particle_sim.comp
+fn spawn_particle(emitter: &ParticleEmitter, index: u32, seed: u32) -> Particle {
+    let mut rng = pcg_hash(seed ^ pcg_hash(index));
+
+    let lifetime_t = next_random(&mut rng);
+    let cone_t     = next_random(&mut rng);
+    let angle_t    = next_random(&mut rng);
+    let speed_t    = next_random(&mut rng);
+
+    let direction = if emitter.direction.is_zero() { Float3::new(0.0, 1.0, 0.0) } else { emitter.direction.unit() };
+
+    // A uniformly distributed direction in the cone, around +z, then turned to the emitter's direction
+    let cos_theta = 1.0 - cone_t * (1.0 - emitter.cone_angle.cos());
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi       = angle_t * 2.0 * std::f32::consts::PI;
+
+    let reference = if direction.y.abs() < 0.999 { Float3::new(0.0, 1.0, 0.0) } else { Float3::new(1.0, 0.0, 0.0) };
+    let tangent   = reference.cross(direction).unit();
+    let bitangent = direction.cross(tangent);
+
+    let launch = tangent * (sin_theta * phi.cos()) + bitangent * (sin_theta * phi.sin()) + direction * cos_theta;
+    let speed  = emitter.speed_min + (emitter.speed_max - emitter.speed_min) * speed_t;
+
+    return Particle{
+        position: emitter.position,
+        velocity: launch * speed,
+        age:      0.0,
+        lifetime: emitter.lifetime_min + (emitter.lifetime_max - emitter.lifetime_min) * lifetime_t,
+    };
+}
+
+// Matches pcg_hash() in particle_sim.comp
+fn pcg_hash(input: u32) -> u32 {
+    let state = input.wrapping_mul(747796405).wrapping_add(2891336453);
+    let word  = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    return (word >> 22) ^ word;
+}
+
+// A float in [0, 1), advancing the state. Matches next_random() in particle_sim.comp
+fn next_random(state: &mut u32) -> f32 {
+    *state = pcg_hash(*state);
+    return (*state >> 8) as f32 / 16777216.0;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Time steps that are exact in binary, so ages and spawn counts can be compared exactly
+    const STEP: f32 = 0.0625;
+
+    /// Emits `per_step` particles every STEP, straight along `direction` at `speed`, living `lifetime` seconds.
+    fn make_emitter(per_step: f32, lifetime: f32) -> ParticleEmitter {
+        return ParticleEmitter{
+            spawn_rate:   per_step / STEP,
+            lifetime_min: lifetime,
+            lifetime_max: lifetime,
+            cone_angle:   0.0,
+            speed_min:    2.0,
+            speed_max:    2.0,
+            direction:    Float3::new(1.0, 0.0, 0.0),
+            gravity:      Float3::new(0.0, -8.0, 0.0),
+            ..ParticleEmitter::default()
+        };
+    }
+
+    #[test]
+    fn spawn_rate_carries_fractional_particles() {
+        let mut simulation = CpuParticleSimulation::new(make_emitter(0.5, 100.0));
+
+        // Half a particle per step, one particle every other step
+        let mut alive_counts = Vec::<usize>::new();
+        for _ in 0..6 {
+            simulation.update(STEP);
+            alive_counts.push(simulation.get_alive_count());
+        }
+        assert_eq!(alive_counts, [0, 1, 1, 2, 2, 3]);
+
+        // A rate that doesn't divide the frame time still spawns the right number of particles over a second
+        let emitter     = ParticleEmitter{ spawn_rate: 25.0, ..ParticleEmitter::default() };
+        let mut spawner = ParticleSpawner::default();
+
+        let spawned = (0..60).map(|_| spawner.advance(&emitter, 1.0 / 60.0).spawn_count).sum::<u32>();
+        assert!(spawned == 24 || spawned == 25, "spawned {} particles", spawned);
+
+        // A hitch is simulated as MAX_PARTICLE_TIME_STEP
+        let mut spawner = ParticleSpawner::default();
+        let step        = spawner.advance(&ParticleEmitter{ spawn_rate: 100.0, ..ParticleEmitter::default() }, 1.0);
+        assert_eq!(step.delta_time, MAX_PARTICLE_TIME_STEP);
+        assert_eq!(step.spawn_count, 10);
+    }
+
+    #[test]
+    fn particles_die_at_the_end_of_their_lifetime() {
+        let mut simulation = CpuParticleSimulation::new(make_emitter(1.0, 4.0 * STEP));
+
+        // One particle per step, each alive for 4 steps
+        for _ in 0..10 {
+            simulation.update(STEP);
+            assert!(simulation.get_alive_count() <= 4);
+        }
+        assert_eq!(simulation.get_alive_count(), 4);
+
+        simulation.emitter.spawn_rate = 0.0;
+
+        let mut alive_counts = Vec::<usize>::new();
+        for _ in 0..5 {
+            simulation.update(STEP);
+            alive_counts.push(simulation.get_alive_count());
+        }
+        assert_eq!(alive_counts, [3, 2, 1, 0, 0]);
+
+        // Dead particles don't move
+        let dead = simulation.get_particles()[0];
+        simulation.update(STEP);
+        assert!(simulation.get_particles()[0].position == dead.position);
+        assert_eq!(simulation.get_particles()[0].age, dead.age);
+    }
+
+    #[test]
+    fn particles_integrate_velocity_and_gravity() {
+        let emitter        = make_emitter(1.0, 100.0);
+        let mut simulation = CpuParticleSimulation::new(emitter);
+
+        simulation.update(STEP);
+        simulation.emitter.spawn_rate = 0.0;
+
+        // A cone of zero launches along the direction, at the speed
+        let spawned = simulation.get_particles()[0];
+        assert!(spawned.position == emitter.position);
+        assert!((spawned.velocity - Float3::new(2.0, 0.0, 0.0)).length() < 1e-6);
+        assert_eq!(spawned.age, 0.0);
+
+        // particle_sim.comp: the velocity takes the gravity first, then the position takes the new velocity
+        let mut position = spawned.position;
+        let mut velocity = spawned.velocity;
+        for frame in 1..=8 {
+            simulation.update(STEP);
+
+            velocity = velocity + emitter.gravity * STEP;
+            position = position + velocity * STEP;
+
+            let particle = simulation.get_particles()[0];
+            assert!((particle.velocity - velocity).length() < 1e-5);
+            assert!((particle.position - position).length() < 1e-5);
+            assert_eq!(particle.age, frame as f32 * STEP);
+        }
+
+        // Which is the closed form of the same semi-implicit Euler steps
+        let time     = 8.0 * STEP;
+        let expected = spawned.velocity * time + emitter.gravity * (STEP * STEP * (8.0 * 9.0 / 2.0));
+        assert!((simulation.get_particles()[0].position - expected).length() < 1e-4);
+        assert!((simulation.get_particles()[0].velocity - (spawned.velocity + emitter.gravity * time)).length() < 1e-5);
+    }
+
+    #[test]
+    fn spawned_particles_stay_in_the_emitter_ranges() {
+        let emitter = ParticleEmitter{
+            max_particles: 256,
+            cone_angle:    0.5,
+            direction:     Float3::new(0.0, 0.0, 2.0),
+            ..ParticleEmitter::default()
+        };
+
+        let step          = ParticleStep{ spawn_start: 0, spawn_count: 256, seed: pcg_hash(7), delta_time: STEP };
+        let mut particles = vec![Particle::default(); 256];
+        simulate_particles(&mut particles, &emitter, &step);
+
+        for particle in &particles {
+            let speed = particle.velocity.length();
+            assert!(speed >= emitter.speed_min - 1e-5 && speed <= emitter.speed_max + 1e-5);
+            assert!(particle.velocity.unit().dot(Float3::new(0.0, 0.0, 1.0)) >= emitter.cone_angle.cos() - 1e-5);
+            assert!(particle.lifetime >= emitter.lifetime_min && particle.lifetime <= emitter.lifetime_max);
+        }
+
+        // The same slot and seed always make the same particle
+        let again = spawn_particle(&emitter, 17, step.seed);
+        assert!(again.velocity == particles[17].velocity && again.lifetime == particles[17].lifetime);
+    }
+
+    #[test]
+    fn ring_recycles_the_oldest_particles() {
+        let emitter        = ParticleEmitter{ max_particles: 4, ..make_emitter(1.0, 100.0) };
+        let mut simulation = CpuParticleSimulation::new(emitter);
+
+        // Slots 0 to 3 fill up, then slots 0 and 1 are taken over by new particles
+        for _ in 0..6 {
+            simulation.update(STEP);
+        }
+
+        let ages = simulation.get_particles().iter().map(|particle| particle.age).collect::<Vec<f32>>();
+        assert_eq!(ages, [1.0 * STEP, 0.0, 3.0 * STEP, 2.0 * STEP]);
+
+        // A spawn range wraps around the end of the ring
+        let mut particles = vec![Particle::default(); 4];
+        let step          = ParticleStep{ spawn_start: 3, spawn_count: 2, seed: 1, delta_time: STEP };
+        simulate_particles(&mut particles, &emitter, &step);
+
+        let alive = particles.iter().map(|particle| particle.is_alive()).collect::<Vec<bool>>();
+        assert_eq!(alive, [true, false, false, true]);
+
+        // Bursts larger than the ring are cut down to one particle per slot
+        let mut spawner = ParticleSpawner::default();
+        let step        = spawner.advance(&ParticleEmitter{ spawn_rate: 1000.0, ..emitter }, STEP);
+        assert_eq!(step.spawn_count, 4);
+        assert_eq!(spawner.advance(&emitter, STEP).spawn_start, 0);
+    }
+
+    #[test]
+    fn invalid_spawn_rates_and_lifetimes_are_clamped() {
+        let mut simulation = CpuParticleSimulation::new(ParticleEmitter{
+            spawn_rate:   f32::INFINITY,
+            lifetime_min: f32::NAN,
+            lifetime_max: f32::INFINITY,
+            ..make_emitter(1.0, 1.0)
+        });
+        assert_eq!(simulation.emitter.spawn_rate, MAX_PARTICLE_SPAWN_RATE);
+        assert_eq!(simulation.emitter.lifetime_min, 0.0);
+        assert_eq!(simulation.emitter.lifetime_max, f32::MAX);
+
+        // An infinite rate fills the ring every step, and the spawner keeps going
+        simulation.update(STEP);
+        simulation.update(STEP);
+        assert_eq!(simulation.spawner.accumulator, 0.0);
+        assert!(simulation.get_particles().iter().all(|particle| particle.lifetime.is_finite()));
+
+        // A NaN rate set between updates stops the emitter, and it spawns again once the rate is valid
+        simulation.reset();
+        simulation.emitter.spawn_rate = f32::NAN;
+        simulation.update(STEP);
+        assert_eq!(simulation.get_alive_count(), 0);
+
+        simulation.emitter.spawn_rate = 1.0 / STEP;
+        simulation.update(STEP);
+        assert!(simulation.spawner.accumulator.is_finite());
+        assert_eq!(simulation.get_alive_count(), 1);
+    }
+}
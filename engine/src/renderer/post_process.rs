@@ -0,0 +1,178 @@
+use vendor::vulkan::*;
+
+//
+// Post-Processing
+//
+// The scene is rendered into an HDR R16G16B16A16_SFLOAT image, and a chain of compute passes turns it into the
+// image that is presented:
+//   1. Bloom     - the bright parts of the scene are downsampled into a chain of half-resolution mips (the first
+//                  downsample applies a soft threshold), then upsampled back with a tent filter, accumulating every
+//                  mip on the way up.
+//   2. Tonemap   - exposure, bloom, the tonemapper (ACES or AgX), the color grading LUT and the vignette, then the
+//                  encoding for the swapchain: sRGB for SDR swapchains, or PQ in the Rec.2020 primaries for HDR10.
+//   3. FXAA      - anti-aliasing on the encoded image. The tonemap pass stores the luma in alpha for it.
+//   4. Present   - the result is blitted into the swapchain.
+//
+// Every effect can be turned off and tuned at runtime with RenderCommand::UpdatePostProcessSettings.
+//
+
+pub const MAX_BLOOM_MIPS: usize = 8;
+
+/// Width, height and depth of the identity color grading LUT
+pub const DEFAULT_LUT_SIZE: u32 = 16;
+
+/// Largest color grading LUT the renderer accepts, its strip is MAX_LUT_SIZE^2 texels wide
+pub const MAX_LUT_SIZE: u32 = 64;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Tonemapper {
+    None, // clamps to the display range
+    Aces, // Stephen Hill's fit of the ACES reference rendering and output transforms
+    AgX,  // Troy Sobotka's AgX, with the default look
+}
+
+impl Tonemapper {
+    // Must match tonemap.comp
+    pub(crate) fn get_shader_id(self) -> f32 {
+        return match self {
+            Tonemapper::None => 0.0,
+            Tonemapper::Aces => 1.0,
+            Tonemapper::AgX  => 2.0,
+        };
+    }
+}
+
+/// Post-processing settings. Can be changed at runtime with RenderCommand::UpdatePostProcessSettings.
+#[derive(Clone, Copy, Debug)]
+pub struct PostProcessSettings {
+    pub exposure_enabled:       bool,
+    pub exposure_ev:            f32, // the scene is scaled by 2^exposure_ev
+
+    pub bloom_enabled:          bool,
+    pub bloom_threshold:        f32, // brightness where bloom starts
+    pub bloom_knee:             f32, // width of the soft transition around the threshold
+    pub bloom_intensity:        f32,
+    pub bloom_mip_count:        u32, // 1 to MAX_BLOOM_MIPS, more mips spread the bloom further
+
+    pub tonemapper:             Tonemapper,
+
+    pub color_grading_enabled:  bool,
+    pub color_grading_strength: f32, // 0 = no grading, 1 = the LUT's colors
+
+    pub fxaa_enabled:           bool,
+
+    pub vignette_enabled:       bool,
+    pub vignette_intensity:     f32, // how dark the corners get, 0 to 1
+    pub vignette_radius:        f32, // distance from the center where the darkening starts, 1 is a corner
+
+    // HDR10 swapchains only
+    pub hdr_paper_white_nits:   f32, // brightness of scene white
+    pub hdr_peak_nits:          f32, // brightest value sent to the display
+}
+
+impl Default for PostProcessSettings {
+    fn default() -> Self {
+        Self{
+            exposure_enabled:       true,
+            exposure_ev:            0.0,
+            bloom_enabled:          true,
+            bloom_threshold:        1.0,
+            bloom_knee:             0.5,
+            bloom_intensity:        0.05,
+            bloom_mip_count:        6,
+            tonemapper:             Tonemapper::Aces,
+            color_grading_enabled:  false,
+            color_grading_strength: 1.0,
+            fxaa_enabled:           true,
+            vignette_enabled:       true,
+            vignette_intensity:     0.3,
+            vignette_radius:        0.5,
+            hdr_paper_white_nits:   200.0,
+            hdr_peak_nits:          1000.0,
+        }
+    }
+}
+
+impl PostProcessSettings {
+    /// Clamps the settings to values the renderer can use.
+    pub fn sanitize(&self) -> PostProcessSettings {
+        let paper_white = self.hdr_paper_white_nits.clamp(80.0, 10000.0);
+
+        return PostProcessSettings{
+            exposure_enabled:       self.exposure_enabled,
+            exposure_ev:            self.exposure_ev.clamp(-16.0, 16.0),
+            bloom_enabled:          self.bloom_enabled,
+            bloom_threshold:        self.bloom_threshold.max(0.0),
+            bloom_knee:             self.bloom_knee.max(0.0),
+            bloom_intensity:        self.bloom_intensity.max(0.0),
+            bloom_mip_count:        self.bloom_mip_count.clamp(1, MAX_BLOOM_MIPS as u32),
+            tonemapper:             self.tonemapper,
+            color_grading_enabled:  self.color_grading_enabled,
+            color_grading_strength: self.color_grading_strength.clamp(0.0, 1.0),
+            fxaa_enabled:           self.fxaa_enabled,
+            vignette_enabled:       self.vignette_enabled,
+            vignette_intensity:     self.vignette_intensity.clamp(0.0, 1.0),
+            vignette_radius:        self.vignette_radius.clamp(0.0, 0.95),
+            hdr_paper_white_nits:   paper_white,
+            hdr_peak_nits:          self.hdr_peak_nits.clamp(paper_white, 10000.0),
+        };
+    }
+
+    pub(crate) fn get_exposure(&self) -> f32 {
+        return if self.exposure_enabled { self.exposure_ev.exp2() } else { 1.0 };
+    }
+}
+
+/// A 3D color grading LUT, unwrapped into a 2D strip of `size` slices. Slice b is the square of texels where blue
+/// is b / (size - 1), red increases to the right and green increases downwards. The strip is size * size texels
+/// wide and size texels tall, 4 bytes (RGBA8) per texel. The LUT is applied to sRGB encoded colors.
+pub struct ColorGradingLut {
+    pub size:   u32,
+    pub pixels: Vec<u8>,
+}
+
+impl ColorGradingLut {
+    /// A LUT that leaves every color unchanged. The size is clamped to 2..=MAX_LUT_SIZE.
+    pub fn identity(size: u32) -> ColorGradingLut {
+        let size      = size.clamp(2, MAX_LUT_SIZE);
+        let max_value = (size - 1) as f32;
+
+        let mut pixels = Vec::<u8>::with_capacity((size * size * size * 4) as usize);
+        for green in 0..size {
+            for blue in 0..size {
+                for red in 0..size {
+                    pixels.push((red   as f32 / max_value * 255.0).round() as u8);
+                    pixels.push((green as f32 / max_value * 255.0).round() as u8);
+                    pixels.push((blue  as f32 / max_value * 255.0).round() as u8);
+                    pixels.push(255);
+                }
+            }
+        }
+
+        return ColorGradingLut{ size, pixels };
+    }
+
+    pub fn is_valid(&self) -> bool {
+        return self.size >= 2 && self.size <= MAX_LUT_SIZE && self.pixels.len() == (self.size * self.size * self.size * 4) as usize;
+    }
+
+    pub(crate) fn get_extent(&self) -> VkExtent3D {
+        return VkExtent3D{ width: self.size * self.size, height: self.size, depth: 1 };
+    }
+}
+
+impl Clone for ColorGradingLut {
+    fn clone(&self) -> Self {
+        return ColorGradingLut{ size: self.size, pixels: self.pixels.clone() };
+    }
+}
+
+/// Extent of a bloom mip. The first mip is half the size of the scene.
+pub(crate) fn get_bloom_extent(scene_extent: VkExtent3D, mip: usize) -> VkExtent3D {
+    let shift = (mip + 1) as u32;
+    return VkExtent3D{
+        width:  (scene_extent.width  >> shift).max(1),
+        height: (scene_extent.height >> shift).max(1),
+        depth:  1,
+    };
+}
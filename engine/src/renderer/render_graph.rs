@@ -0,0 +1,1159 @@
+use std::fmt::Write;
+
+use super::graphics::{
+    AllocatedImage,
+    AllocatedBuffer,
+    gpu_device::Device,
+    gpu_command_buffer::CommandBuffer,
+    gpu_utils::make_image_subresource_range,
+};
+use super::error::RenderError;
+
+use vendor::vulkan::*;
+
+//
+// Render Graph
+//
+// The frame is described as a list of passes, where each pass declares the images and buffers it reads and
+// writes. The graph is rebuilt every frame, then compiled and executed:
+//   1. Culling  - passes that don't contribute to an imported resource (and aren't marked with side effects)
+//                 are skipped. Imported resources outlive the frame, so they are the graph's outputs.
+//   2. Aliasing - transient images are only alive between their first and last use. Transient images with
+//                 disjoint lifetimes and the same extent and format share a physical image from the
+//                 TransientImagePool.
+//   3. Barriers - layout transitions and memory dependencies are derived from the declared accesses. All
+//                 barriers needed by a pass are batched into one vkCmdPipelineBarrier2 before it runs.
+//
+// The compiled graph can be written to a Graphviz .dot file with RenderGraph::to_dot().
+//
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct ImageHandle(usize);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct BufferHandle(usize);
+
+/// How a pass uses an image. Determines the layout, pipeline stages and access flags of the barriers.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ImageAccess {
+    ColorAttachment,      // rendered to as a color attachment, may load the previous contents
+    DepthAttachment,      // depth tested and written
+    ColorResolve,         // a multisampled color attachment is resolved into it
+    DepthResolve,         // a multisampled depth attachment is resolved into it
+    DepthAttachmentRead,  // depth tested, but not written
+    ComputeStorageWrite,  // imageStore from a compute shader, may also imageLoad
+    ComputeStorageRead,   // imageLoad from a compute shader
+    ComputeSampled,       // sampled from a compute shader
+    FragmentSampled,      // sampled from a fragment shader
+    TransferSrc,          // source of a copy or blit
+    TransferDst,          // destination of a copy, blit or clear
+}
+
+/// How a pass uses a buffer. Determines the pipeline stages and access flags of the barriers.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BufferAccess {
+    VertexShaderRead,    // storage buffer read from a vertex shader, usually through a buffer device address
+    FragmentShaderRead,  // storage buffer read from a fragment shader
+    ComputeShaderRead,   // storage buffer read from a compute shader
+    ComputeShaderWrite,  // storage buffer written from a compute shader
+    UniformRead,         // uniform buffer read from any graphics or compute shader
+    IndexRead,           // bound as an index buffer
+    IndirectRead,        // draw or dispatch arguments
+    TransferSrc,         // source of a copy
+    TransferDst,         // destination of a copy or fill
+}
+
+impl ImageAccess {
+    pub fn is_write(self) -> bool {
+        return match self {
+            ImageAccess::ColorAttachment     => true,
+            ImageAccess::DepthAttachment     => true,
+            ImageAccess::ColorResolve        => true,
+            ImageAccess::DepthResolve        => true,
+            ImageAccess::ComputeStorageWrite => true,
+            ImageAccess::TransferDst         => true,
+            _                                => false,
+        };
+    }
+
+    /// Whether the access can observe the previous contents of the image. Attachments may be loaded and storage
+    /// images may be read back, so only resolves and transfer destinations are guaranteed to overwrite everything.
+    fn reads_contents(self) -> bool {
+        return !matches!(self, ImageAccess::ColorResolve | ImageAccess::DepthResolve | ImageAccess::TransferDst);
+    }
+
+    fn get_layout(self) -> VkImageLayout {
+        return match self {
+            ImageAccess::ColorAttachment     => VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL,
+            ImageAccess::DepthAttachment     => VK_IMAGE_LAYOUT_DEPTH_ATTACHMENT_OPTIMAL,
+            ImageAccess::ColorResolve        => VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL,
+            ImageAccess::DepthResolve        => VK_IMAGE_LAYOUT_DEPTH_ATTACHMENT_OPTIMAL,
+            ImageAccess::DepthAttachmentRead => VK_IMAGE_LAYOUT_DEPTH_READ_ONLY_OPTIMAL,
+            ImageAccess::ComputeStorageWrite => VK_IMAGE_LAYOUT_GENERAL,
+            ImageAccess::ComputeStorageRead  => VK_IMAGE_LAYOUT_GENERAL,
+            ImageAccess::ComputeSampled      => VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL,
+            ImageAccess::FragmentSampled     => VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL,
+            ImageAccess::TransferSrc         => VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL,
+            ImageAccess::TransferDst         => VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
+        };
+    }
+
+    fn get_stages(self) -> VkPipelineStageFlags2 {
+        return match self {
+            ImageAccess::ColorAttachment     => VK_PIPELINE_STAGE_2_COLOR_ATTACHMENT_OUTPUT_BIT,
+            ImageAccess::DepthAttachment     => VK_PIPELINE_STAGE_2_EARLY_FRAGMENT_TESTS_BIT | VK_PIPELINE_STAGE_2_LATE_FRAGMENT_TESTS_BIT,
+            ImageAccess::ColorResolve        => VK_PIPELINE_STAGE_2_COLOR_ATTACHMENT_OUTPUT_BIT,
+            ImageAccess::DepthResolve        => VK_PIPELINE_STAGE_2_COLOR_ATTACHMENT_OUTPUT_BIT, // resolves run in this stage, even for depth
+            ImageAccess::DepthAttachmentRead => VK_PIPELINE_STAGE_2_EARLY_FRAGMENT_TESTS_BIT | VK_PIPELINE_STAGE_2_LATE_FRAGMENT_TESTS_BIT,
+            ImageAccess::ComputeStorageWrite => VK_PIPELINE_STAGE_2_COMPUTE_SHADER_BIT,
+            ImageAccess::ComputeStorageRead  => VK_PIPELINE_STAGE_2_COMPUTE_SHADER_BIT,
+            ImageAccess::ComputeSampled      => VK_PIPELINE_STAGE_2_COMPUTE_SHADER_BIT,
+            ImageAccess::FragmentSampled     => VK_PIPELINE_STAGE_2_FRAGMENT_SHADER_BIT,
+            ImageAccess::TransferSrc         => VK_PIPELINE_STAGE_2_TRANSFER_BIT,
+            ImageAccess::TransferDst         => VK_PIPELINE_STAGE_2_TRANSFER_BIT,
+        };
+    }
+
+    fn get_access_mask(self) -> VkAccessFlags2 {
+        return match self {
+            ImageAccess::ColorAttachment     => VK_ACCESS_2_COLOR_ATTACHMENT_READ_BIT | VK_ACCESS_2_COLOR_ATTACHMENT_WRITE_BIT,
+            ImageAccess::DepthAttachment     => VK_ACCESS_2_DEPTH_STENCIL_ATTACHMENT_READ_BIT | VK_ACCESS_2_DEPTH_STENCIL_ATTACHMENT_WRITE_BIT,
+            ImageAccess::ColorResolve        => VK_ACCESS_2_COLOR_ATTACHMENT_WRITE_BIT,
+            ImageAccess::DepthResolve        => VK_ACCESS_2_COLOR_ATTACHMENT_WRITE_BIT,
+            ImageAccess::DepthAttachmentRead => VK_ACCESS_2_DEPTH_STENCIL_ATTACHMENT_READ_BIT,
+            ImageAccess::ComputeStorageWrite => VK_ACCESS_2_SHADER_STORAGE_READ_BIT | VK_ACCESS_2_SHADER_STORAGE_WRITE_BIT,
+            ImageAccess::ComputeStorageRead  => VK_ACCESS_2_SHADER_STORAGE_READ_BIT,
+            ImageAccess::ComputeSampled      => VK_ACCESS_2_SHADER_SAMPLED_READ_BIT,
+            ImageAccess::FragmentSampled     => VK_ACCESS_2_SHADER_SAMPLED_READ_BIT,
+            ImageAccess::TransferSrc         => VK_ACCESS_2_TRANSFER_READ_BIT,
+            ImageAccess::TransferDst         => VK_ACCESS_2_TRANSFER_WRITE_BIT,
+        };
+    }
+
+    /// The image usage a transient image needs to support this access.
+    fn get_usage(self) -> VkImageUsageFlags {
+        return match self {
+            ImageAccess::ColorAttachment     => VK_IMAGE_USAGE_COLOR_ATTACHMENT_BIT,
+            ImageAccess::DepthAttachment     => VK_IMAGE_USAGE_DEPTH_STENCIL_ATTACHMENT_BIT,
+            ImageAccess::ColorResolve        => VK_IMAGE_USAGE_COLOR_ATTACHMENT_BIT,
+            ImageAccess::DepthResolve        => VK_IMAGE_USAGE_DEPTH_STENCIL_ATTACHMENT_BIT,
+            ImageAccess::DepthAttachmentRead => VK_IMAGE_USAGE_DEPTH_STENCIL_ATTACHMENT_BIT,
+            ImageAccess::ComputeStorageWrite => VK_IMAGE_USAGE_STORAGE_BIT,
+            ImageAccess::ComputeStorageRead  => VK_IMAGE_USAGE_STORAGE_BIT,
+            ImageAccess::ComputeSampled      => VK_IMAGE_USAGE_SAMPLED_BIT,
+            ImageAccess::FragmentSampled     => VK_IMAGE_USAGE_SAMPLED_BIT,
+            ImageAccess::TransferSrc         => VK_IMAGE_USAGE_TRANSFER_SRC_BIT,
+            ImageAccess::TransferDst         => VK_IMAGE_USAGE_TRANSFER_DST_BIT,
+        };
+    }
+}
+
+impl BufferAccess {
+    pub fn is_write(self) -> bool {
+        return match self {
+            BufferAccess::ComputeShaderWrite => true,
+            BufferAccess::TransferDst        => true,
+            _                                => false,
+        };
+    }
+
+    fn get_stages(self) -> VkPipelineStageFlags2 {
+        return match self {
+            BufferAccess::VertexShaderRead   => VK_PIPELINE_STAGE_2_VERTEX_SHADER_BIT,
+            BufferAccess::FragmentShaderRead => VK_PIPELINE_STAGE_2_FRAGMENT_SHADER_BIT,
+            BufferAccess::ComputeShaderRead  => VK_PIPELINE_STAGE_2_COMPUTE_SHADER_BIT,
+            BufferAccess::ComputeShaderWrite => VK_PIPELINE_STAGE_2_COMPUTE_SHADER_BIT,
+            BufferAccess::UniformRead        => VK_PIPELINE_STAGE_2_VERTEX_SHADER_BIT | VK_PIPELINE_STAGE_2_FRAGMENT_SHADER_BIT | VK_PIPELINE_STAGE_2_COMPUTE_SHADER_BIT,
+            BufferAccess::IndexRead          => VK_PIPELINE_STAGE_2_INDEX_INPUT_BIT,
+            BufferAccess::IndirectRead       => VK_PIPELINE_STAGE_2_DRAW_INDIRECT_BIT,
+            BufferAccess::TransferSrc        => VK_PIPELINE_STAGE_2_TRANSFER_BIT,
+            BufferAccess::TransferDst        => VK_PIPELINE_STAGE_2_TRANSFER_BIT,
+        };
+    }
+
+    fn get_access_mask(self) -> VkAccessFlags2 {
+        return match self {
+            BufferAccess::VertexShaderRead   => VK_ACCESS_2_SHADER_STORAGE_READ_BIT,
+            BufferAccess::FragmentShaderRead => VK_ACCESS_2_SHADER_STORAGE_READ_BIT,
+            BufferAccess::ComputeShaderRead  => VK_ACCESS_2_SHADER_STORAGE_READ_BIT,
+            BufferAccess::ComputeShaderWrite => VK_ACCESS_2_SHADER_STORAGE_READ_BIT | VK_ACCESS_2_SHADER_STORAGE_WRITE_BIT,
+            BufferAccess::UniformRead        => VK_ACCESS_2_UNIFORM_READ_BIT,
+            BufferAccess::IndexRead          => VK_ACCESS_2_INDEX_READ_BIT,
+            BufferAccess::IndirectRead       => VK_ACCESS_2_INDIRECT_COMMAND_READ_BIT,
+            BufferAccess::TransferSrc        => VK_ACCESS_2_TRANSFER_READ_BIT,
+            BufferAccess::TransferDst        => VK_ACCESS_2_TRANSFER_WRITE_BIT,
+        };
+    }
+}
+
+fn is_depth_format(format: VkFormat) -> bool {
+    return format == VK_FORMAT_D16_UNORM
+        || format == VK_FORMAT_D32_SFLOAT
+        || format == VK_FORMAT_D16_UNORM_S8_UINT
+        || format == VK_FORMAT_D24_UNORM_S8_UINT
+        || format == VK_FORMAT_D32_SFLOAT_S8_UINT;
+}
+
+/// The physical image behind an ImageHandle. Valid once the graph has been compiled.
+#[derive(Clone, Copy)]
+pub struct GraphImage {
+    pub image:  VkImage,
+    pub view:   VkImageView,
+    pub extent: VkExtent3D,
+    pub format: VkFormat,
+}
+
+impl GraphImage {
+    pub fn from_allocated(image: &AllocatedImage) -> GraphImage {
+        return GraphImage{
+            image:  image.image,
+            view:   image.view,
+            extent: image.dims,
+            format: image.format,
+        };
+    }
+
+    pub fn get_extent_2d(&self) -> VkExtent2D {
+        return VkExtent2D{ width: self.extent.width, height: self.extent.height };
+    }
+}
+
+/// Description of a transient image. The usage flags are derived from the accesses declared by each pass.
+#[derive(Clone, Copy)]
+pub struct ImageDesc {
+    pub extent:  VkExtent3D,
+    pub format:  VkFormat,
+    pub samples: VkSampleCountFlagBits,
+}
+
+impl ImageDesc {
+    fn is_compatible(&self, other: &ImageDesc) -> bool {
+        return self.format        == other.format
+            && self.samples       == other.samples
+            && self.extent.width  == other.extent.width
+            && self.extent.height == other.extent.height
+            && self.extent.depth  == other.extent.depth;
+    }
+}
+
+//
+// Transient Image Pool
+//
+// Owns the physical images behind the graph's transient images. Images persist across frames so the graph
+// doesn't allocate every frame, and are destroyed once they have gone unused for longer than the number of
+// frames in flight.
+//
+
+struct PooledImage {
+    image:         AllocatedImage,
+    desc:          ImageDesc,
+    usage:         VkImageUsageFlags,
+    unused_frames: usize,
+    busy_until:    Option<usize>, // index of the last pass using the image this frame
+}
+
+pub struct TransientImagePool {
+    images: Vec<PooledImage>,
+}
+
+impl Default for TransientImagePool {
+    fn default() -> Self {
+        Self{
+            images: Vec::new(),
+        }
+    }
+}
+
+impl TransientImagePool {
+    fn begin_frame(&mut self) {
+        for pooled in &mut self.images {
+            pooled.busy_until = None;
+        }
+    }
+
+    /// Finds an image that is free from pass `first_pass` onwards, or creates one. Returns the index of the image.
+    fn acquire(&mut self, device: &Device, desc: &ImageDesc, usage: VkImageUsageFlags, first_pass: usize, last_pass: usize) -> Result<usize, RenderError> {
+        if let Some(index) = self.reuse(desc, usage, first_pass, last_pass) {
+            return Ok(index);
+        }
+
+        let image = device.allocate_image_memory(
+            desc.extent,
+            desc.format,
+            usage,
+            VMA_MEMORY_USAGE_GPU_ONLY,
+            VK_MEMORY_PROPERTY_DEVICE_LOCAL_BIT,
+            false,
+            desc.samples,
+        )?;
+
+        self.images.push(PooledImage{
+            image,
+            desc:          *desc,
+            usage,
+            unused_frames: 0,
+            busy_until:    Some(last_pass),
+        });
+
+        return Ok(self.images.len() - 1);
+    }
+
+    /// Finds an existing image that is free from pass `first_pass` onwards and marks it busy until `last_pass`.
+    fn reuse(&mut self, desc: &ImageDesc, usage: VkImageUsageFlags, first_pass: usize, last_pass: usize) -> Option<usize> {
+        for (index, pooled) in self.images.iter_mut().enumerate() {
+            let is_free = match pooled.busy_until {
+                Some(busy_until) => busy_until < first_pass,
+                None             => true,
+            };
+
+            if is_free && pooled.desc.is_compatible(desc) && (pooled.usage & usage) == usage {
+                pooled.busy_until    = Some(last_pass);
+                pooled.unused_frames = 0;
+                return Some(index);
+            }
+        }
+
+        return None;
+    }
+
+    /// Destroys images that haven't been used for more than `frames_in_flight` frames. Those frames have
+    /// finished on the GPU, so nothing can reference the images anymore.
+    fn end_frame(&mut self, device: &Device, frames_in_flight: usize) {
+        for pooled in &mut self.images {
+            if pooled.busy_until.is_none() {
+                pooled.unused_frames += 1;
+            }
+        }
+
+        self.images.retain_mut(|pooled| {
+            if pooled.unused_frames > frames_in_flight {
+                device.destroy_image_memory(&mut pooled.image);
+                return false;
+            }
+
+            return true;
+        });
+    }
+
+    pub fn get_image_count(&self) -> usize {
+        return self.images.len();
+    }
+
+    /// @assume: the device is idle.
+    pub fn destroy(&mut self, device: &Device) {
+        for pooled in &mut self.images {
+            device.destroy_image_memory(&mut pooled.image);
+        }
+
+        self.images.clear();
+    }
+}
+
+//
+// Graph Resources
+//
+
+enum ImageSource {
+    Imported{ initial_layout: VkImageLayout, final_layout: Option<VkImageLayout> },
+    Transient{ desc: ImageDesc },
+}
+
+struct ImageResource {
+    name:     String,
+    source:   ImageSource,
+    usage:    VkImageUsageFlags,  // union of the usages of every declared access
+    physical: Option<GraphImage>, // set when imported, or when compiled for transients
+    pool_idx: Option<usize>,      // transient images only, the pooled image it was aliased to
+}
+
+struct BufferResource {
+    name:   String,
+    buffer: VkBuffer,
+}
+
+/// The resolved images and buffers, handed to each pass when it executes.
+pub struct RenderGraphResources {
+    images:  Vec<ImageResource>,
+    buffers: Vec<BufferResource>,
+}
+
+impl RenderGraphResources {
+    pub fn get_image(&self, handle: ImageHandle) -> GraphImage {
+        return self.images[handle.0].physical.expect("Render graph image was used before the graph was compiled.");
+    }
+
+    pub fn get_buffer(&self, handle: BufferHandle) -> VkBuffer {
+        return self.buffers[handle.0].buffer;
+    }
+}
+
+//
+// Passes
+//
+
+pub type PassExecuteFn<'a> = Box<dyn FnOnce(&mut CommandBuffer, &RenderGraphResources) -> Result<(), RenderError> + 'a>;
+
+struct Pass<'a> {
+    name:             String,
+    image_accesses:   Vec<(ImageHandle, ImageAccess)>,
+    buffer_accesses:  Vec<(BufferHandle, BufferAccess)>,
+    has_side_effects: bool,
+    execute:          Option<PassExecuteFn<'a>>,
+
+    // Set when compiled
+    is_culled:        bool,
+    image_barriers:   Vec<VkImageMemoryBarrier2>,
+    buffer_barriers:  Vec<VkBufferMemoryBarrier2>,
+}
+
+pub struct PassBuilder<'g, 'a> {
+    graph: &'g mut RenderGraph<'a>,
+    pass:  usize,
+}
+
+impl<'g, 'a> PassBuilder<'g, 'a> {
+    pub fn read_image(self, image: ImageHandle, access: ImageAccess) -> Self {
+        assert!(!access.is_write(), "Use write_image() to declare a write to an image.");
+        return self.access_image(image, access);
+    }
+
+    pub fn write_image(self, image: ImageHandle, access: ImageAccess) -> Self {
+        assert!(access.is_write(), "Use read_image() to declare a read from an image.");
+        return self.access_image(image, access);
+    }
+
+    pub fn read_buffer(self, buffer: BufferHandle, access: BufferAccess) -> Self {
+        assert!(!access.is_write(), "Use write_buffer() to declare a write to a buffer.");
+        self.graph.passes[self.pass].buffer_accesses.push((buffer, access));
+        return self;
+    }
+
+    pub fn write_buffer(self, buffer: BufferHandle, access: BufferAccess) -> Self {
+        assert!(access.is_write(), "Use read_buffer() to declare a read from a buffer.");
+        self.graph.passes[self.pass].buffer_accesses.push((buffer, access));
+        return self;
+    }
+
+    /// The pass does something outside the graph (readback, timestamps, ...) and is never culled.
+    pub fn side_effects(self) -> Self {
+        self.graph.passes[self.pass].has_side_effects = true;
+        return self;
+    }
+
+    pub fn execute<F>(self, func: F)
+        where F: FnOnce(&mut CommandBuffer, &RenderGraphResources) -> Result<(), RenderError> + 'a
+    {
+        self.graph.passes[self.pass].execute = Some(Box::new(func));
+    }
+
+    fn access_image(self, image: ImageHandle, access: ImageAccess) -> Self {
+        self.graph.resources.images[image.0].usage |= access.get_usage();
+        self.graph.passes[self.pass].image_accesses.push((image, access));
+        return self;
+    }
+}
+
+//
+// Render Graph
+//
+
+// Synchronization state of an image or buffer while the graph is compiled
+#[derive(Clone, Copy)]
+struct AccessState {
+    layout:       VkImageLayout,
+    write_stages: VkPipelineStageFlags2, // stages of the last write
+    write_access: VkAccessFlags2,
+    read_stages:  VkPipelineStageFlags2, // stages that have read the resource since the last write
+}
+
+impl AccessState {
+    // Nothing is known about prior use, which is the case for any resource at the start of the frame. Wait
+    // on all previous work so work from the last frame is finished before the resource is touched.
+    fn unknown(layout: VkImageLayout) -> AccessState {
+        return AccessState{
+            layout,
+            write_stages: VK_PIPELINE_STAGE_2_ALL_COMMANDS_BIT,
+            write_access: VK_ACCESS_2_MEMORY_WRITE_BIT,
+            read_stages:  VK_PIPELINE_STAGE_2_NONE,
+        };
+    }
+
+    /// Applies an access and returns the source and destination scopes of the required barrier, if any.
+    fn apply(&mut self, layout: VkImageLayout, stages: VkPipelineStageFlags2, access: VkAccessFlags2, is_write: bool)
+        -> Option<(VkPipelineStageFlags2, VkAccessFlags2)>
+    {
+        let layout_changed = layout != self.layout;
+
+        if is_write || layout_changed {
+            // Write after read/write, or a layout transition, which is also a write.
+            let src = (self.write_stages | self.read_stages, self.write_access);
+
+            self.layout       = layout;
+            self.write_stages = stages;
+            self.write_access = if is_write { access } else { VK_ACCESS_2_NONE };
+            self.read_stages  = if is_write { VK_PIPELINE_STAGE_2_NONE } else { stages };
+
+            return Some(src);
+        }
+
+        // Read after read, nothing to wait on if these stages already saw the last write.
+        if (self.read_stages & stages) == stages || self.write_stages == VK_PIPELINE_STAGE_2_NONE {
+            self.read_stages |= stages;
+            return None;
+        }
+
+        let src = (self.write_stages, self.write_access);
+        self.read_stages |= stages;
+
+        return Some(src);
+    }
+}
+
+pub struct RenderGraph<'a> {
+    passes:      Vec<Pass<'a>>,
+    resources:   RenderGraphResources,
+    is_compiled: bool,
+
+    // Barriers run after the last pass, to move imported images into their final layout
+    final_image_barriers: Vec<VkImageMemoryBarrier2>,
+}
+
+impl<'a> RenderGraph<'a> {
+    pub fn new() -> Self {
+        Self{
+            passes:               Vec::new(),
+            resources:            RenderGraphResources{ images: Vec::new(), buffers: Vec::new() },
+            is_compiled:          false,
+            final_image_barriers: Vec::new(),
+        }
+    }
+
+    /// Imports an image that lives outside the graph. If a final layout is given, the image is transitioned to it
+    /// after the last pass.
+    pub fn import_image(&mut self, name: &str, image: GraphImage, initial_layout: VkImageLayout, final_layout: Option<VkImageLayout>) -> ImageHandle {
+        self.resources.images.push(ImageResource{
+            name:     String::from(name),
+            source:   ImageSource::Imported{ initial_layout, final_layout },
+            usage:    0,
+            physical: Some(image),
+            pool_idx: None,
+        });
+
+        return ImageHandle(self.resources.images.len() - 1);
+    }
+
+    /// Declares an image that only lives for this frame. Its contents are undefined before the first write.
+    pub fn create_image(&mut self, name: &str, desc: ImageDesc) -> ImageHandle {
+        self.resources.images.push(ImageResource{
+            name:     String::from(name),
+            source:   ImageSource::Transient{ desc },
+            usage:    0,
+            physical: None,
+            pool_idx: None,
+        });
+
+        return ImageHandle(self.resources.images.len() - 1);
+    }
+
+    pub fn import_buffer(&mut self, name: &str, buffer: &AllocatedBuffer) -> BufferHandle {
+        self.resources.buffers.push(BufferResource{
+            name:   String::from(name),
+            buffer: buffer.buffer,
+        });
+
+        return BufferHandle(self.resources.buffers.len() - 1);
+    }
+
+    /// Adds a pass. Passes run in the order they are added.
+    pub fn add_pass<'g>(&'g mut self, name: &str) -> PassBuilder<'g, 'a> {
+        self.passes.push(Pass{
+            name:             String::from(name),
+            image_accesses:   Vec::new(),
+            buffer_accesses:  Vec::new(),
+            has_side_effects: false,
+            execute:          None,
+            is_culled:        false,
+            image_barriers:   Vec::new(),
+            buffer_barriers:  Vec::new(),
+        });
+
+        return PassBuilder{ pass: self.passes.len() - 1, graph: self };
+    }
+
+    pub fn get_pass_count(&self) -> usize {
+        return self.passes.len();
+    }
+
+    pub fn get_culled_pass_count(&self) -> usize {
+        return self.passes.iter().filter(|pass| pass.is_culled).count();
+    }
+
+    fn is_imported(&self, image: ImageHandle) -> bool {
+        return matches!(self.resources.images[image.0].source, ImageSource::Imported{ .. });
+    }
+
+    fn cull_passes(&mut self) {
+        // Walk the passes backwards. A pass is needed if it writes a resource that a later needed pass reads, or
+        // a resource that outlives the frame.
+        let mut image_needed:  Vec<bool> = (0..self.resources.images.len()).map(|i| self.is_imported(ImageHandle(i))).collect();
+        let mut buffer_needed: Vec<bool> = vec![true; self.resources.buffers.len()]; // buffers are always imported
+
+        for pass in self.passes.iter_mut().rev() {
+            let writes_needed_image  = pass.image_accesses.iter().any(|(image, access)|   access.is_write() && image_needed[image.0]);
+            let writes_needed_buffer = pass.buffer_accesses.iter().any(|(buffer, access)| access.is_write() && buffer_needed[buffer.0]);
+
+            pass.is_culled = !(pass.has_side_effects || writes_needed_image || writes_needed_buffer);
+            if pass.is_culled {
+                continue;
+            }
+
+            for (image, access) in &pass.image_accesses {
+                if access.reads_contents() {
+                    image_needed[image.0] = true;
+                }
+            }
+        }
+    }
+
+    /// The transient images used by live passes as (image, first pass, last pass), in order of first use.
+    fn get_transient_lifetimes(&self) -> Vec<(usize, usize, usize)> {
+        // Find the lifetime of each transient image, in terms of live passes.
+        let mut lifetimes: Vec<Option<(usize, usize)>> = vec![None; self.resources.images.len()];
+
+        for (pass_index, pass) in self.passes.iter().enumerate() {
+            if pass.is_culled {
+                continue;
+            }
+
+            for (image, _) in &pass.image_accesses {
+                lifetimes[image.0] = match lifetimes[image.0] {
+                    Some((first, _)) => Some((first, pass_index)),
+                    None             => Some((pass_index, pass_index)),
+                };
+            }
+        }
+
+        // Sorted by first use, so a pooled image can be picked up again once its last user ran.
+        let mut order: Vec<usize> = (0..self.resources.images.len())
+            .filter(|index| !self.is_imported(ImageHandle(*index)) && lifetimes[*index].is_some())
+            .collect();
+        order.sort_by_key(|index| lifetimes[*index].unwrap().0);
+
+        return order.into_iter()
+            .map(|index| {
+                let (first, last) = lifetimes[index].unwrap();
+                (index, first, last)
+            })
+            .collect();
+    }
+
+    fn alias_transient_images(&mut self, device: &Device, pool: &mut TransientImagePool) -> Result<(), RenderError> {
+        pool.begin_frame();
+
+        for (index, first, last) in self.get_transient_lifetimes() {
+            let resource = &mut self.resources.images[index];
+
+            let ImageSource::Transient{ desc } = resource.source else { continue; };
+
+            let pool_idx = pool.acquire(device, &desc, resource.usage, first, last)?;
+
+            resource.pool_idx = Some(pool_idx);
+            resource.physical = Some(GraphImage::from_allocated(&pool.images[pool_idx].image));
+        }
+
+        return Ok(());
+    }
+
+    fn build_barriers(&mut self, pool: &TransientImagePool) {
+        // Track state per physical image, so aliased transient images synchronize against each other.
+        let mut imported_states: Vec<Option<AccessState>> = vec![None; self.resources.images.len()];
+        let mut pooled_states:   Vec<Option<AccessState>> = vec![None; pool.images.len()];
+        let mut pooled_owner:    Vec<Option<usize>>       = vec![None; pool.images.len()];
+        let mut buffer_states:   Vec<AccessState>         = vec![AccessState::unknown(VK_IMAGE_LAYOUT_UNDEFINED); self.resources.buffers.len()];
+
+        for pass in self.passes.iter_mut() {
+            if pass.is_culled {
+                continue;
+            }
+
+            for (image, access) in &pass.image_accesses {
+                let resource = &self.resources.images[image.0];
+
+                let state = match resource.source {
+                    ImageSource::Imported{ initial_layout, .. } => {
+                        imported_states[image.0].get_or_insert(AccessState::unknown(initial_layout))
+                    },
+                    ImageSource::Transient{ .. } => {
+                        let pool_idx = resource.pool_idx.unwrap();
+                        let state    = pooled_states[pool_idx].get_or_insert(AccessState::unknown(VK_IMAGE_LAYOUT_UNDEFINED));
+
+                        // First use of this transient image, the previous contents belong to whatever was
+                        // aliased to it before. Discard them.
+                        if pooled_owner[pool_idx] != Some(image.0) {
+                            pooled_owner[pool_idx] = Some(image.0);
+                            state.layout = VK_IMAGE_LAYOUT_UNDEFINED;
+                        }
+
+                        state
+                    },
+                };
+
+                let old_layout = state.layout;
+                let new_layout = access.get_layout();
+
+                if let Some((src_stages, src_access)) = state.apply(new_layout, access.get_stages(), access.get_access_mask(), access.is_write()) {
+                    let physical    = resource.physical.unwrap();
+                    let aspect_mask = if is_depth_format(physical.format) { VK_IMAGE_ASPECT_DEPTH_BIT } else { VK_IMAGE_ASPECT_COLOR_BIT };
+
+                    pass.image_barriers.push(VkImageMemoryBarrier2{
+                        sType:               VK_STRUCTURE_TYPE_IMAGE_MEMORY_BARRIER_2,
+                        pNext:               std::ptr::null(),
+                        srcStageMask:        src_stages,
+                        srcAccessMask:       src_access,
+                        dstStageMask:        access.get_stages(),
+                        dstAccessMask:       access.get_access_mask(),
+                        oldLayout:           old_layout,
+                        newLayout:           new_layout,
+                        srcQueueFamilyIndex: VK_QUEUE_FAMILY_IGNORED as u32,
+                        dstQueueFamilyIndex: VK_QUEUE_FAMILY_IGNORED as u32,
+                        image:               physical.image,
+                        subresourceRange:    make_image_subresource_range(aspect_mask),
+                    });
+                }
+            }
+
+            for (buffer, access) in &pass.buffer_accesses {
+                let state = &mut buffer_states[buffer.0];
+
+                if let Some((src_stages, src_access)) = state.apply(VK_IMAGE_LAYOUT_UNDEFINED, access.get_stages(), access.get_access_mask(), access.is_write()) {
+                    pass.buffer_barriers.push(VkBufferMemoryBarrier2{
+                        sType:               VK_STRUCTURE_TYPE_BUFFER_MEMORY_BARRIER_2,
+                        pNext:               std::ptr::null(),
+                        srcStageMask:        src_stages,
+                        srcAccessMask:       src_access,
+                        dstStageMask:        access.get_stages(),
+                        dstAccessMask:       access.get_access_mask(),
+                        srcQueueFamilyIndex: VK_QUEUE_FAMILY_IGNORED as u32,
+                        dstQueueFamilyIndex: VK_QUEUE_FAMILY_IGNORED as u32,
+                        buffer:              self.resources.buffers[buffer.0].buffer,
+                        offset:              0,
+                        size:                VK_WHOLE_SIZE as VkDeviceSize,
+                    });
+                }
+            }
+        }
+
+        // Move imported images into their final layout
+        for (index, resource) in self.resources.images.iter().enumerate() {
+            let ImageSource::Imported{ initial_layout, final_layout: Some(final_layout) } = resource.source else { continue; };
+
+            let state = imported_states[index].unwrap_or(AccessState::unknown(initial_layout));
+            if state.layout == final_layout {
+                continue;
+            }
+
+            let physical    = resource.physical.unwrap();
+            let aspect_mask = if is_depth_format(physical.format) { VK_IMAGE_ASPECT_DEPTH_BIT } else { VK_IMAGE_ASPECT_COLOR_BIT };
+
+            self.final_image_barriers.push(VkImageMemoryBarrier2{
+                sType:               VK_STRUCTURE_TYPE_IMAGE_MEMORY_BARRIER_2,
+                pNext:               std::ptr::null(),
+                srcStageMask:        state.write_stages | state.read_stages,
+                srcAccessMask:       state.write_access,
+                dstStageMask:        VK_PIPELINE_STAGE_2_ALL_COMMANDS_BIT,
+                dstAccessMask:       VK_ACCESS_2_NONE,
+                oldLayout:           state.layout,
+                newLayout:           final_layout,
+                srcQueueFamilyIndex: VK_QUEUE_FAMILY_IGNORED as u32,
+                dstQueueFamilyIndex: VK_QUEUE_FAMILY_IGNORED as u32,
+                image:               physical.image,
+                subresourceRange:    make_image_subresource_range(aspect_mask),
+            });
+        }
+    }
+
+    /// Culls unused passes, assigns physical images to the transient images and builds the barriers for every pass.
+    /// `frames_in_flight` is used to know when unused pooled images can be safely destroyed.
+    pub fn compile(&mut self, device: &Device, pool: &mut TransientImagePool, frames_in_flight: usize) -> Result<(), RenderError> {
+        assert!(!self.is_compiled);
+
+        self.cull_passes();
+        self.alias_transient_images(device, pool)?;
+        self.build_barriers(pool);
+
+        pool.end_frame(device, frames_in_flight);
+
+        self.is_compiled = true;
+        return Ok(());
+    }
+
+    /// Records every live pass into the command buffer, with the barriers each pass needs.
+    pub fn execute(&mut self, command_buffer: &mut CommandBuffer) -> Result<(), RenderError> {
+        assert!(self.is_compiled);
+
+        for pass in self.passes.iter_mut() {
+            if pass.is_culled {
+                continue;
+            }
+
+            command_buffer.pipeline_barrier(&pass.image_barriers, &pass.buffer_barriers);
+
+            if let Some(execute) = pass.execute.take() {
+                execute(command_buffer, &self.resources)?;
+            }
+        }
+
+        command_buffer.pipeline_barrier(&self.final_image_barriers, &[]);
+
+        return Ok(());
+    }
+
+    /// Writes the graph in Graphviz dot format. Passes and resources are nodes, edges are the declared accesses.
+    /// Culled passes are dashed, and transient images list the pooled image they were aliased to.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+
+        let _ = writeln!(dot, "digraph render_graph {{");
+        let _ = writeln!(dot, "    rankdir=LR;");
+        let _ = writeln!(dot, "    node [fontname=\"Helvetica\", fontsize=10];");
+        let _ = writeln!(dot, "    edge [fontname=\"Helvetica\", fontsize=8];");
+
+        for (index, pass) in self.passes.iter().enumerate() {
+            let barrier_count = pass.image_barriers.len() + pass.buffer_barriers.len();
+
+            if pass.is_culled {
+                let _ = writeln!(dot, "    pass_{} [label=\"{}\\n(culled)\", shape=box, style=dashed, fontcolor=gray];", index, pass.name);
+            } else {
+                let _ = writeln!(dot, "    pass_{} [label=\"{}\\n{} barrier(s)\", shape=box, style=filled, fillcolor=lightblue];", index, pass.name, barrier_count);
+            }
+        }
+
+        for (index, image) in self.resources.images.iter().enumerate() {
+            let (kind, color) = match image.source {
+                ImageSource::Imported{ .. }  => (String::from("imported"), "lightgoldenrod"),
+                ImageSource::Transient{ .. } => match image.pool_idx {
+                    Some(pool_idx) => (format!("transient, pool #{}", pool_idx), "palegreen"),
+                    None           => (String::from("transient, unused"),        "lightgray"),
+                },
+            };
+
+            let extent = match (image.physical, &image.source) {
+                (Some(physical), _)                      => physical.extent,
+                (None, ImageSource::Transient{ desc })   => desc.extent,
+                (None, ImageSource::Imported{ .. })      => VkExtent3D{ width: 0, height: 0, depth: 0 },
+            };
+
+            let _ = writeln!(dot, "    image_{} [label=\"{}\\n{}x{}\\n{}\", shape=ellipse, style=filled, fillcolor={}];",
+                index, image.name, extent.width, extent.height, kind, color);
+        }
+
+        for (index, buffer) in self.resources.buffers.iter().enumerate() {
+            let _ = writeln!(dot, "    buffer_{} [label=\"{}\", shape=cylinder, style=filled, fillcolor=lightgoldenrod];", index, buffer.name);
+        }
+
+        for (pass_index, pass) in self.passes.iter().enumerate() {
+            let style = if pass.is_culled { ", style=dashed, color=gray" } else { "" };
+
+            for (image, access) in &pass.image_accesses {
+                if access.is_write() {
+                    let _ = writeln!(dot, "    pass_{} -> image_{} [label=\"{:?}\"{}];", pass_index, image.0, access, style);
+                } else {
+                    let _ = writeln!(dot, "    image_{} -> pass_{} [label=\"{:?}\"{}];", image.0, pass_index, access, style);
+                }
+            }
+
+            for (buffer, access) in &pass.buffer_accesses {
+                if access.is_write() {
+                    let _ = writeln!(dot, "    pass_{} -> buffer_{} [label=\"{:?}\"{}];", pass_index, buffer.0, access, style);
+                } else {
+                    let _ = writeln!(dot, "    buffer_{} -> pass_{} [label=\"{:?}\"{}];", buffer.0, pass_index, access, style);
+                }
+            }
+        }
+
+        let _ = writeln!(dot, "}}");
+        return dot;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXTENT: VkExtent3D = VkExtent3D{ width: 64, height: 64, depth: 1 };
+
+    fn color_desc() -> ImageDesc {
+        return ImageDesc{ extent: EXTENT, format: VK_FORMAT_R16G16B16A16_SFLOAT, samples: VK_SAMPLE_COUNT_1_BIT };
+    }
+
+    /// A stand-in for an image owned by the renderer, with a unique non-null handle.
+    fn fake_image(id: usize, format: VkFormat) -> GraphImage {
+        return GraphImage{
+            image:  id as VkImage,
+            view:   id as VkImageView,
+            extent: EXTENT,
+            format,
+        };
+    }
+
+    /// A pool holding `count` images compatible with color_desc(), so the graph never has to allocate.
+    fn make_pool(count: usize) -> TransientImagePool {
+        let mut pool = TransientImagePool::default();
+
+        for index in 0..count {
+            let image = fake_image(100 + index, VK_FORMAT_R16G16B16A16_SFLOAT);
+
+            pool.images.push(PooledImage{
+                image:         AllocatedImage{ image: image.image, view: image.view, memory: std::ptr::null_mut(), dims: EXTENT, format: image.format },
+                desc:          color_desc(),
+                usage:         !0,
+                unused_frames: 0,
+                busy_until:    None,
+            });
+        }
+
+        return pool;
+    }
+
+    /// RenderGraph::compile without a device, transient images must fit in the pool's existing images.
+    fn compile_with_pool(graph: &mut RenderGraph, pool: &mut TransientImagePool) {
+        graph.cull_passes();
+
+        pool.begin_frame();
+        for (index, first, last) in graph.get_transient_lifetimes() {
+            let resource = &mut graph.resources.images[index];
+            let ImageSource::Transient{ desc } = resource.source else { continue; };
+
+            let pool_idx = pool.reuse(&desc, resource.usage, first, last).expect("The test pool is too small.");
+
+            resource.pool_idx = Some(pool_idx);
+            resource.physical = Some(GraphImage::from_allocated(&pool.images[pool_idx].image));
+        }
+
+        graph.build_barriers(pool);
+        graph.is_compiled = true;
+    }
+
+    fn is_culled(graph: &RenderGraph, pass: usize) -> bool {
+        return graph.passes[pass].is_culled;
+    }
+
+    #[test]
+    fn passes_that_feed_no_output_are_culled() {
+        let mut graph = RenderGraph::new();
+        let target    = graph.import_image("target", fake_image(1, VK_FORMAT_R8G8B8A8_UNORM), VK_IMAGE_LAYOUT_UNDEFINED, None);
+        let used      = graph.create_image("used",   color_desc());
+        let unused    = graph.create_image("unused", color_desc());
+
+        graph.add_pass("draw used").write_image(used, ImageAccess::ColorAttachment);
+        graph.add_pass("draw unused").write_image(unused, ImageAccess::ColorAttachment);
+        graph.add_pass("readback").read_image(unused, ImageAccess::TransferSrc).side_effects();
+        graph.add_pass("composite")
+            .read_image(used, ImageAccess::ComputeSampled)
+            .write_image(target, ImageAccess::ComputeStorageWrite);
+        graph.add_pass("dead end").write_image(unused, ImageAccess::TransferDst);
+
+        graph.cull_passes();
+
+        // The readback keeps its input alive, the last write to `unused` has no reader
+        assert!(!is_culled(&graph, 0));
+        assert!(!is_culled(&graph, 1));
+        assert!(!is_culled(&graph, 2));
+        assert!(!is_culled(&graph, 3));
+        assert!(is_culled(&graph, 4));
+        assert_eq!(graph.get_culled_pass_count(), 1);
+    }
+
+    #[test]
+    fn attachment_writes_keep_earlier_writers() {
+        let mut graph = RenderGraph::new();
+        let target    = graph.import_image("target", fake_image(1, VK_FORMAT_R8G8B8A8_UNORM), VK_IMAGE_LAYOUT_UNDEFINED, None);
+        let scene     = graph.create_image("scene", color_desc());
+
+        graph.add_pass("opaque").write_image(scene, ImageAccess::ColorAttachment);
+        graph.add_pass("transparent").write_image(scene, ImageAccess::ColorAttachment);
+        graph.add_pass("copy").read_image(scene, ImageAccess::TransferSrc).write_image(target, ImageAccess::TransferDst);
+        graph.add_pass("orphan").write_image(scene, ImageAccess::ColorAttachment);
+
+        graph.cull_passes();
+
+        // An attachment may load what's already there, so the opaque pass is still observed through the copy
+        assert!(!is_culled(&graph, 0));
+        assert!(!is_culled(&graph, 1));
+        assert!(!is_culled(&graph, 2));
+        assert!(is_culled(&graph, 3));
+    }
+
+    #[test]
+    fn transient_lifetimes_follow_pass_order() {
+        let mut graph = RenderGraph::new();
+        let target    = graph.import_image("target", fake_image(1, VK_FORMAT_R8G8B8A8_UNORM), VK_IMAGE_LAYOUT_UNDEFINED, None);
+        let late      = graph.create_image("late",  color_desc());
+        let early     = graph.create_image("early", color_desc());
+        let unused    = graph.create_image("unused", color_desc());
+
+        graph.add_pass("a").write_image(early, ImageAccess::ColorAttachment);
+        graph.add_pass("b").read_image(early, ImageAccess::FragmentSampled).write_image(late, ImageAccess::ColorAttachment);
+        graph.add_pass("c").write_image(unused, ImageAccess::ColorAttachment);
+        graph.add_pass("d").read_image(late, ImageAccess::ComputeSampled).write_image(target, ImageAccess::ComputeStorageWrite);
+
+        graph.cull_passes();
+
+        // Sorted by first use rather than declaration order, culled passes don't extend a lifetime
+        assert_eq!(graph.get_transient_lifetimes(), vec![(early.0, 0, 1), (late.0, 1, 3)]);
+    }
+
+    #[test]
+    fn transient_images_with_disjoint_lifetimes_share_a_pooled_image() {
+        let mut graph = RenderGraph::new();
+        let target    = graph.import_image("target", fake_image(1, VK_FORMAT_R8G8B8A8_UNORM), VK_IMAGE_LAYOUT_UNDEFINED, None);
+        let first     = graph.create_image("first",  color_desc());
+        let second    = graph.create_image("second", color_desc());
+        let third     = graph.create_image("third",  color_desc());
+
+        graph.add_pass("draw first").write_image(first, ImageAccess::ColorAttachment);
+        graph.add_pass("blur first").read_image(first, ImageAccess::ComputeSampled).write_image(second, ImageAccess::ComputeStorageWrite);
+        graph.add_pass("blur second").read_image(second, ImageAccess::ComputeSampled).write_image(third, ImageAccess::ComputeStorageWrite);
+        graph.add_pass("resolve").read_image(third, ImageAccess::ComputeSampled).write_image(target, ImageAccess::ComputeStorageWrite);
+
+        let mut pool = make_pool(2);
+        compile_with_pool(&mut graph, &mut pool);
+
+        let pool_idx = |image: ImageHandle| graph.resources.images[image.0].pool_idx.unwrap();
+
+        // `first` and `second` overlap in "blur first", `third` starts after `first` is done
+        assert_ne!(pool_idx(first), pool_idx(second));
+        assert_ne!(pool_idx(second), pool_idx(third));
+        assert_eq!(pool_idx(first), pool_idx(third));
+        assert_eq!(graph.resources.get_image(third).image, pool.images[pool_idx(first)].image.image);
+    }
+
+    #[test]
+    fn pool_only_reuses_free_compatible_images() {
+        let mut pool = make_pool(1);
+        let usage    = VK_IMAGE_USAGE_COLOR_ATTACHMENT_BIT;
+
+        assert_eq!(pool.reuse(&color_desc(), usage, 0, 2), Some(0));
+
+        // Busy until pass 2
+        assert_eq!(pool.reuse(&color_desc(), usage, 2, 3), None);
+        assert_eq!(pool.reuse(&color_desc(), usage, 3, 4), Some(0));
+
+        pool.begin_frame();
+        let smaller = ImageDesc{ extent: VkExtent3D{ width: 32, height: 32, depth: 1 }, ..color_desc() };
+        let depth   = ImageDesc{ format: VK_FORMAT_D32_SFLOAT, ..color_desc() };
+        let msaa    = ImageDesc{ samples: VK_SAMPLE_COUNT_4_BIT, ..color_desc() };
+        assert_eq!(pool.reuse(&smaller, usage, 0, 0), None);
+        assert_eq!(pool.reuse(&depth,   usage, 0, 0), None);
+        assert_eq!(pool.reuse(&msaa,    usage, 0, 0), None);
+
+        pool.images[0].usage = VK_IMAGE_USAGE_SAMPLED_BIT;
+        assert_eq!(pool.reuse(&color_desc(), usage, 0, 0), None);
+    }
+
+    #[test]
+    fn barriers_follow_the_declared_accesses() {
+        let mut graph = RenderGraph::new();
+        let target    = graph.import_image("target", fake_image(1, VK_FORMAT_R8G8B8A8_UNORM), VK_IMAGE_LAYOUT_UNDEFINED, Some(VK_IMAGE_LAYOUT_PRESENT_SRC_KHR));
+        let scene     = graph.create_image("scene", color_desc());
+
+        graph.add_pass("draw").write_image(scene, ImageAccess::ColorAttachment);
+        graph.add_pass("sample").read_image(scene, ImageAccess::FragmentSampled).write_image(target, ImageAccess::ColorAttachment);
+        graph.add_pass("sample again").read_image(scene, ImageAccess::FragmentSampled).write_image(target, ImageAccess::ColorAttachment);
+        graph.add_pass("sample in compute").read_image(scene, ImageAccess::ComputeSampled).write_image(target, ImageAccess::ComputeStorageWrite);
+
+        let mut pool = make_pool(1);
+        compile_with_pool(&mut graph, &mut pool);
+
+        let scene_image  = graph.resources.get_image(scene).image;
+        let target_image = graph.resources.get_image(target).image;
+        let barrier_for  = |pass: usize, image: VkImage| -> Option<VkImageMemoryBarrier2> {
+            return graph.passes[pass].image_barriers.iter().find(|barrier| barrier.image == image).copied();
+        };
+
+        // The first use of a transient image discards its contents
+        let draw = barrier_for(0, scene_image).unwrap();
+        assert_eq!(draw.oldLayout, VK_IMAGE_LAYOUT_UNDEFINED);
+        assert_eq!(draw.newLayout, VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL);
+
+        // Read after write waits on the attachment write and transitions for sampling
+        let sample = barrier_for(1, scene_image).unwrap();
+        assert_eq!(sample.oldLayout,     VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL);
+        assert_eq!(sample.newLayout,     VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL);
+        assert_eq!(sample.srcStageMask,  VK_PIPELINE_STAGE_2_COLOR_ATTACHMENT_OUTPUT_BIT);
+        assert_eq!(sample.srcAccessMask, VK_ACCESS_2_COLOR_ATTACHMENT_READ_BIT | VK_ACCESS_2_COLOR_ATTACHMENT_WRITE_BIT);
+        assert_eq!(sample.dstStageMask,  VK_PIPELINE_STAGE_2_FRAGMENT_SHADER_BIT);
+
+        // A second read from the same stage needs nothing. A read from a new stage waits on the layout
+        // transition, which counts as a write at the stage that first sampled the image.
+        assert!(barrier_for(2, scene_image).is_none());
+        let compute = barrier_for(3, scene_image).unwrap();
+        assert_eq!(compute.oldLayout,     VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL);
+        assert_eq!(compute.newLayout,     VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL);
+        assert_eq!(compute.srcStageMask,  VK_PIPELINE_STAGE_2_FRAGMENT_SHADER_BIT);
+        assert_eq!(compute.srcAccessMask, VK_ACCESS_2_NONE);
+        assert_eq!(compute.dstStageMask,  VK_PIPELINE_STAGE_2_COMPUTE_SHADER_BIT);
+
+        // Write after write to the imported target, even without a layout change
+        let rewrite = barrier_for(2, target_image).unwrap();
+        assert_eq!(rewrite.oldLayout,    VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL);
+        assert_eq!(rewrite.newLayout,    VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL);
+        assert_eq!(rewrite.srcStageMask, VK_PIPELINE_STAGE_2_COLOR_ATTACHMENT_OUTPUT_BIT);
+
+        // The imported image ends the frame in its final layout
+        assert_eq!(graph.final_image_barriers.len(), 1);
+        assert_eq!(graph.final_image_barriers[0].image,        target_image);
+        assert_eq!(graph.final_image_barriers[0].oldLayout,    VK_IMAGE_LAYOUT_GENERAL);
+        assert_eq!(graph.final_image_barriers[0].newLayout,    VK_IMAGE_LAYOUT_PRESENT_SRC_KHR);
+        assert_eq!(graph.final_image_barriers[0].srcStageMask, VK_PIPELINE_STAGE_2_COMPUTE_SHADER_BIT);
+    }
+
+    #[test]
+    fn aliased_images_discard_the_previous_owner() {
+        let mut graph = RenderGraph::new();
+        let target    = graph.import_image("target", fake_image(1, VK_FORMAT_R8G8B8A8_UNORM), VK_IMAGE_LAYOUT_UNDEFINED, None);
+        let first     = graph.create_image("first",  color_desc());
+        let second    = graph.create_image("second", color_desc());
+
+        graph.add_pass("fill first").write_image(first, ImageAccess::ComputeStorageWrite);
+        graph.add_pass("read first").read_image(first, ImageAccess::ComputeSampled).write_image(target, ImageAccess::ComputeStorageWrite);
+        graph.add_pass("fill second").write_image(second, ImageAccess::ComputeStorageWrite);
+        graph.add_pass("read second").read_image(second, ImageAccess::ComputeSampled).write_image(target, ImageAccess::ComputeStorageWrite);
+
+        let mut pool = make_pool(1);
+        compile_with_pool(&mut graph, &mut pool);
+
+        // Same physical image, but `second` starts from UNDEFINED and waits on the reads of `first`
+        let image   = graph.resources.get_image(second).image;
+        let barrier = graph.passes[2].image_barriers.iter().find(|barrier| barrier.image == image).unwrap();
+        assert_eq!(graph.resources.get_image(first).image, image);
+        assert_eq!(barrier.oldLayout,    VK_IMAGE_LAYOUT_UNDEFINED);
+        assert_eq!(barrier.newLayout,    VK_IMAGE_LAYOUT_GENERAL);
+        assert_eq!(barrier.srcStageMask, VK_PIPELINE_STAGE_2_COMPUTE_SHADER_BIT);
+    }
+
+    #[test]
+    fn buffer_reads_wait_on_the_last_write_once() {
+        let buffer_handle = 7usize as VkBuffer;
+
+        let mut graph = RenderGraph::new();
+        graph.resources.buffers.push(BufferResource{ name: String::from("draws"), buffer: buffer_handle });
+        let draws = BufferHandle(0);
+
+        graph.add_pass("cull").write_buffer(draws, BufferAccess::ComputeShaderWrite);
+        graph.add_pass("draw").read_buffer(draws, BufferAccess::IndirectRead).side_effects();
+        graph.add_pass("draw again").read_buffer(draws, BufferAccess::IndirectRead).side_effects();
+
+        let mut pool = make_pool(0);
+        compile_with_pool(&mut graph, &mut pool);
+
+        let draw = &graph.passes[1].buffer_barriers;
+        assert_eq!(draw.len(), 1);
+        assert_eq!(draw[0].buffer,        buffer_handle);
+        assert_eq!(draw[0].srcStageMask,  VK_PIPELINE_STAGE_2_COMPUTE_SHADER_BIT);
+        assert_eq!(draw[0].srcAccessMask, VK_ACCESS_2_SHADER_STORAGE_READ_BIT | VK_ACCESS_2_SHADER_STORAGE_WRITE_BIT);
+        assert_eq!(draw[0].dstStageMask,  VK_PIPELINE_STAGE_2_DRAW_INDIRECT_BIT);
+        assert_eq!(draw[0].dstAccessMask, VK_ACCESS_2_INDIRECT_COMMAND_READ_BIT);
+        assert!(graph.passes[2].buffer_barriers.is_empty());
+    }
+}
@@ -9,6 +9,8 @@ use super::graphics::{
     *,
     gpu_device::*,
 };
+use super::error::RenderError;
+use super::shadows::MAX_SHADOW_CASCADES;
 
 use vendor::vulkan::*;
 
@@ -33,15 +35,236 @@ pub(crate) struct GlobalSceneData {
     pub view_proj:      Float4x4,
     //----------------- 16-byte boundary
     pub ambient_color:  Float4,
-    pub sunlight_dir:   Float4,
-    pub sunlight_color: Float4,
-    padding0:           Float4,
+    pub sunlight_dir:   Float4, // xyz: direction the light travels in
+    pub sunlight_color: Float4, // w: intensity
+    pub camera_pos:     Float4, // xyz: world-space camera position
+    //----------------- 16-byte boundary
+    pub cascade_view_proj:   [Float4x4; MAX_SHADOW_CASCADES],
+    //----------------- 16-byte boundary
+    pub cascade_splits:      Float4, // view-space depth where each cascade ends
+    pub cascade_texel_sizes: Float4, // world-space size of a shadow map texel in each cascade
+    pub shadow_params:       Float4, // x: cascade count, y: normal bias, z: pcf radius, w: 1 / atlas resolution
+    //----------------- 16-byte boundary
+    pub light_params:        Float4, // x: light count, y: near plane, z: far plane
+    pub cluster_params:      Float4, // xy: cluster tile size in pixels, z: depth slice scale, w: depth slice bias
+    //----------------- 16-byte boundary
+}
+
+// A point or spot light, as stored in the light buffer. Matches lights.glsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct GpuLight {
+    pub position_range:  Float4, // xyz: world-space position, w: range
+    pub color_intensity: Float4, // rgb: linear color, w: intensity
+    pub direction_type:  Float4, // xyz: direction a spot light points in, w: lights::LIGHT_TYPE_*
+    pub cone:            Float4, // x: cos(outer cone angle), y: cos(inner cone angle)
+    //----------------- 16-byte boundary
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct LightCullPushConstants {
+    pub view:        Float4x4,
+    pub projection:  Float4, // x: projection x scale, y: projection y scale, z: near plane, w: far plane
+    pub light_count: Float4, // x: light count
+}
+
+// Per-material constants, an element of the bindless material buffer (set 1 binding 0 of the lit mesh pipeline).
+// The textures are indices into the bindless texture array, see material_system.rs
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct MaterialConstants {
+    pub base_color_factor:          Float4,
+    pub emissive_factor:            Float4, // w: unused
+    //----------------- 16-byte boundary
+    pub metallic_factor:            f32,
+    pub roughness_factor:           f32,
+    pub normal_scale:               f32,
+    pub occlusion_strength:         f32,
+    //----------------- 16-byte boundary
+    pub albedo_texture:             u32,
+    pub normal_texture:             u32,
+    pub metallic_roughness_texture: u32,
+    pub occlusion_texture:          u32,
+    //----------------- 16-byte boundary
+    pub emissive_texture:           u32,
+    pub sampler:                    u32, // BINDLESS_SAMPLER_*
+    pub alpha_cutoff:               f32,
+    pub alpha_mode:                 u32, // ALPHA_MODE_*
     //----------------- 16-byte boundary
 }
 
+// Per-instance data, read by the vertex shaders with gl_InstanceIndex
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct GpuInstance {
+    pub transform: Float4x4,
+    pub color:     Float4,
+    //----------------- 16-byte boundary
+}
+
+#[repr(C)]
 pub(crate) struct GpuDrawPushConstants {
-    pub world_matrix:  Float4x4,
-    pub vertex_buffer: VkDeviceAddress,
+    pub world_matrix:    Float4x4,
+    pub vertex_buffer:   VkDeviceAddress,
+    pub instance_buffer: VkDeviceAddress, // GpuInstance array, a single identity instance for regular meshes, or every object for GPU-driven draws
+    pub material_index:  u32,             // into the bindless material buffer, read by mesh.frag
+    pub _pad:            u32,
+    pub skin_buffer:     VkDeviceAddress, // SkinVertex array, only read by the skinned vertex shaders
+    pub joint_buffer:    VkDeviceAddress, // joint palette, only read by the skinned vertex shaders
+}
+
+// A sprite as sprite.vert expands it into a quad, see sprite.rs
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct GpuSprite {
+    pub position_size: Float4, // xy: position, zw: size
+    pub uv_rect:       Float4, // xy: min, zw: max
+    pub tint:          Float4,
+    pub origin:        Float4, // xy: origin, z: cos(rotation), w: sin(rotation)
+    //----------------- 16-byte boundary
+}
+
+// Matches sprite.vert and sprite.frag
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct SpritePushConstants {
+    pub view_proj:     Float4x4,
+    pub sprite_buffer: VkDeviceAddress, // GpuSprite array, every batch of the frame
+    pub texture_index: u32,             // into the bindless texture array
+    pub sampler_index: u32,             // BINDLESS_SAMPLER_*
+    pub output:        Float4,          // x: output mode (0: SDR, 1: HDR10), y: HDR paper white in nits
+}
+
+// A glyph as text.vert expands it into a quad, see text.rs
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct GpuGlyph {
+    pub corner:  Float4, // xyz: top-left corner, screen pixels or world units
+    pub axis_x:  Float4, // xyz: from the top-left to the top-right corner
+    pub axis_y:  Float4, // xyz: from the top-left to the bottom-left corner
+    pub uv_rect: Float4, // xy: min, zw: max
+    pub color:   Float4,
+    //----------------- 16-byte boundary
+}
+
+// Matches text.vert and text.frag
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct TextPushConstants {
+    pub view_proj:     Float4x4,
+    pub glyph_buffer:  VkDeviceAddress, // GpuGlyph array, every batch of the frame
+    pub texture_index: u32,             // into the bindless texture array
+    pub mode:          u32,             // 0: bitmap, 1: SDF
+    pub output:        Float4,          // x: output mode (0: SDR, 1: HDR10), y: HDR paper white in nits
+}
+
+// A particle slot, see particles.rs. Matches particle_sim.comp and particle.vert
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct GpuParticle {
+    pub position_age:      Float4, // xyz: position, w: age in seconds
+    pub velocity_lifetime: Float4, // xyz: velocity, w: lifetime in seconds, dead once the age reaches it
+    //----------------- 16-byte boundary
+}
+
+// An emitter's settings, see particles.rs. Matches particle_sim.comp and particle.vert
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct GpuParticleEmitter {
+    pub position:       Float4, // xyz: spawn position, w: cone angle in radians
+    pub direction:      Float4, // xyz: center of the cone, unit length
+    pub speed_lifetime: Float4, // x: min speed, y: max speed, z: min lifetime, w: max lifetime
+    pub gravity:        Float4, // xyz: acceleration
+    pub color_start:    Float4,
+    pub color_end:      Float4,
+    pub size_sheet:     Float4, // x: start size, y: end size, z: texture sheet columns, w: texture sheet rows
+    //----------------- 16-byte boundary
+}
+
+// Matches particle_sim.comp
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct ParticleSimPushConstants {
+    pub particle_buffer: VkDeviceAddress, // GpuParticle ring of the emitter
+    pub emitter_buffer:  VkDeviceAddress, // GpuParticleEmitter array, every emitter of the frame
+    pub emitter_index:   u32,
+    pub capacity:        u32,             // slots in the ring
+    pub spawn_start:     u32,
+    pub spawn_count:     u32,
+    pub seed:            u32,
+    pub reset:           u32,             // 1: kill every particle before spawning
+    pub delta_time:      f32,
+    pub _pad:            u32,
+}
+
+// Matches particle.vert and particle.frag
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct ParticleDrawPushConstants {
+    pub view_proj:       Float4x4,
+    pub camera_right:    Float4,          // xyz: world-space right of the camera, the billboards face the camera
+    pub camera_up:       Float4,          // xyz: world-space up of the camera
+    pub particle_buffer: VkDeviceAddress, // GpuParticle ring of the emitter
+    pub emitter_buffer:  VkDeviceAddress, // GpuParticleEmitter array, every emitter of the frame
+    pub emitter_index:   u32,
+    pub texture_index:   u32,             // into the bindless texture array, or 0xFFFFFFFF for a soft dot
+}
+
+// Matches skybox.comp
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct SkyboxPushConstants {
+    pub inverse_view_proj: Float4x4,
+    pub camera_pos:        Float4, // xyz: world-space camera position
+    pub params:            Float4, // x: intensity, y: mip to sample
+}
+
+// Matches deferred_lighting.comp
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct DeferredLightingPushConstants {
+    pub inverse_view_proj: Float4x4,
+    pub params:            Float4, // x: deferred::GBufferDebugView or deferred::SSAO_DEBUG_VIEW
+}
+
+// Matches debug_line.vert and debug_line.frag
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct DebugLinePushConstants {
+    pub view_proj:     Float4x4,
+    pub vertex_buffer: VkDeviceAddress, // DebugVertex array, the depth tested lines followed by the overlay lines
+    pub _pad:          u64,
+    pub output:        Float4,          // x: output mode (0: SDR, 1: HDR10), y: HDR paper white in nits
+}
+
+// A drawable object for the draw cull pass, see culling.rs. Matches draw_cull.comp
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct GpuDrawObject {
+    pub bounds: Float4, // xyz: world-space bounding sphere center, w: radius
+    pub batch:  u32,
+    pub _pad:   [u32; 3],
+    //----------------- 16-byte boundary
+}
+
+// A batch owns the draw command slots [first_command, first_command + object count). Matches draw_cull.comp
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct GpuDrawBatch {
+    pub index_count:   u32, // of the mesh's current LOD
+    pub first_command: u32,
+    pub first_index:   u32,
+    pub _pad:          u32,
+    //----------------- 16-byte boundary
+}
+
+// Uniforms of the draw cull pass
+#[repr(C)]
+pub(crate) struct DrawCullData {
+    pub view_proj:     Float4x4, // camera the objects are culled against
+    pub hiz_view_proj: Float4x4, // camera the Hi-Z pyramid was built with
+    pub params:        Float4,   // x: object count, yz: Hi-Z mip 0 size, w: Hi-Z mip count, 0 skips the occlusion test
 }
 
 pub(crate) enum ShaderStage {
@@ -62,12 +285,18 @@ impl Default for GlobalSceneData {
             ambient_color:  Float4::zero(),
             sunlight_dir:   Float4::zero(),
             sunlight_color: Float4::zero(),
-            padding0:       Float4::zero(),
+            camera_pos:     Float4::zero(),
+            cascade_view_proj:   [Float4x4::identity(); MAX_SHADOW_CASCADES],
+            cascade_splits:      Float4::zero(),
+            cascade_texel_sizes: Float4::zero(),
+            shadow_params:       Float4::zero(),
+            light_params:        Float4::zero(),
+            cluster_params:      Float4::zero(),
         }
     }
 }
 
-pub fn load_shader_module(device: &Device, shader_name: &str, stage: ShaderStage) -> VkShaderModule {
+pub fn load_shader_module(device: &Device, shader_name: &str, stage: ShaderStage) -> Result<VkShaderModule, RenderError> {
     use crate::core::asset_system::{AssetDrive, AssetSystem};
     use std::io::prelude::*;
     use std::fs::File;
@@ -91,16 +320,21 @@ pub fn load_shader_module(device: &Device, shader_name: &str, stage: ShaderStage
 
     // let's read the file
     let mut file = match File::open(&shader_file) {
-        Err(why) => panic!("couldn't open {}: {}", display, why),
+        Err(why) => return Err(RenderError::ShaderNotFound{ path: display.to_string(), reason: why.to_string() }),
         Ok(file) => file,
     };
 
     // Read the file contents into a string, returns `io::Result<usize>`
     let mut file_data = Vec::<u8>::new();
     match file.read_to_end(&mut file_data) {
-        Err(why) => panic!("couldn't read {}: {}", display, why),
+        Err(why) => return Err(RenderError::ShaderNotFound{ path: display.to_string(), reason: why.to_string() }),
         Ok(_)    => {},
     }
 
-    device.create_shader_module(file_data.as_slice()).expect("Failed to create VkShaderModule from gradient.spv")
+    // A lost device is still a lost device, anything else means the driver didn't like the SPIR-V.
+    match device.create_shader_module(file_data.as_slice()) {
+        Ok(module)                   => Ok(module),
+        Err(RenderError::DeviceLost) => Err(RenderError::DeviceLost),
+        Err(_)                       => Err(RenderError::InvalidShader(display.to_string())),
+    }
 }
@@ -0,0 +1,193 @@
+use crate::math::{ float3::*, float4::*, float4x4::* };
+
+use vendor::vulkan::*;
+
+//
+// Cascaded Shadow Maps
+//
+// The sun's shadows are rendered into a single depth atlas, split into a 2x2 grid of cascades. Each cascade
+// covers a slice of the camera frustum, and the slices get longer the further they are from the camera:
+//   1. Splits  - the slice distances blend a logarithmic and a uniform split scheme, see split_lambda.
+//   2. Fitting - each slice is wrapped in a bounding sphere, so the cascade keeps its size as the camera rotates.
+//                The projection is then snapped to whole shadow map texels, so shadow edges don't shimmer as the
+//                camera moves.
+//   3. Lookup  - the lit shader picks the first cascade whose split contains the fragment, and filters the
+//                shadow map with PCF.
+//
+
+pub const MAX_SHADOW_CASCADES: usize = 4;
+
+// Number of cascades along each side of the shadow atlas
+const ATLAS_GRID_SIZE: u32 = 2;
+
+// Casters between the sun and a cascade's slice still need to land in the shadow map, so each cascade's depth
+// range is extended this far towards the sun.
+const SHADOW_CASTER_DISTANCE: f32 = 50.0;
+
+/// Shadow settings for the sun. Can be changed at runtime with RenderCommand::UpdateShadowSettings.
+#[derive(Clone, Copy, Debug)]
+pub struct ShadowSettings {
+    pub cascade_count:       u32, // 1 to MAX_SHADOW_CASCADES
+    pub cascade_resolution:  u32, // width and height of a single cascade, in texels
+    pub max_distance:        f32, // shadows end this far from the camera, or at the far plane if it is closer
+    pub split_lambda:        f32, // 0 = uniform splits, 1 = logarithmic splits
+    pub depth_bias_constant: f32, // rasterizer depth bias, applied when rendering the shadow map
+    pub depth_bias_slope:    f32, // rasterizer depth bias, scaled by the slope of the triangle
+    pub normal_bias:         f32, // lookups are offset along the surface normal by this many shadow map texels
+    pub pcf_radius:          u32, // 0 = a single hardware filtered tap, N = a (2N+1)x(2N+1) kernel
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self{
+            cascade_count:       4,
+            cascade_resolution:  1024,
+            max_distance:        100.0,
+            split_lambda:        0.75,
+            depth_bias_constant: 1.25,
+            depth_bias_slope:    1.75,
+            normal_bias:         1.0,
+            pcf_radius:          1,
+        }
+    }
+}
+
+impl ShadowSettings {
+    /// Clamps the settings to values the renderer can use.
+    pub fn sanitize(&self) -> ShadowSettings {
+        return ShadowSettings{
+            cascade_count:       self.cascade_count.clamp(1, MAX_SHADOW_CASCADES as u32),
+            cascade_resolution:  self.cascade_resolution.clamp(128, 4096),
+            max_distance:        self.max_distance.max(1.0),
+            split_lambda:        self.split_lambda.clamp(0.0, 1.0),
+            depth_bias_constant: self.depth_bias_constant,
+            depth_bias_slope:    self.depth_bias_slope,
+            normal_bias:         self.normal_bias.max(0.0),
+            pcf_radius:          self.pcf_radius.min(4),
+        };
+    }
+
+    /// The atlas always has room for MAX_SHADOW_CASCADES, so changing the cascade count doesn't reallocate it.
+    pub(crate) fn get_atlas_extent(&self) -> VkExtent3D {
+        let size = self.cascade_resolution * ATLAS_GRID_SIZE;
+        return VkExtent3D{ width: size, height: size, depth: 1 };
+    }
+
+    /// Top-left corner of the cascade in the atlas, in texels.
+    pub(crate) fn get_cascade_offset(&self, cascade: usize) -> (u32, u32) {
+        let column = cascade as u32 % ATLAS_GRID_SIZE;
+        let row    = cascade as u32 / ATLAS_GRID_SIZE;
+        return (column * self.cascade_resolution, row * self.cascade_resolution);
+    }
+}
+
+pub(crate) struct ShadowCascades {
+    pub view_proj:    [Float4x4; MAX_SHADOW_CASCADES],
+    pub split_depths: [f32; MAX_SHADOW_CASCADES], // view-space depth where each cascade ends
+    pub texel_sizes:  [f32; MAX_SHADOW_CASCADES], // world-space size of a shadow map texel
+    pub count:        usize,
+}
+
+/// Practical split scheme: a blend of logarithmic splits, which match the perspective projection's resolution
+/// falloff, and uniform splits, which stop the nearest cascade from becoming too small.
+pub(crate) fn compute_split_depths(near: f32, far: f32, cascade_count: usize, lambda: f32) -> [f32; MAX_SHADOW_CASCADES] {
+    let mut splits = [far; MAX_SHADOW_CASCADES];
+
+    for i in 0..cascade_count {
+        let p             = (i + 1) as f32 / cascade_count as f32;
+        let log_split     = near * (far / near).powf(p);
+        let uniform_split = near + (far - near) * p;
+
+        splits[i] = lambda * log_split + (1.0 - lambda) * uniform_split;
+    }
+
+    return splits;
+}
+
+fn to_float3(point: Float4) -> Float3 {
+    return Float3::new(point.x / point.w, point.y / point.w, point.z / point.w);
+}
+
+/// Fits one orthographic projection per cascade around the camera frustum.
+///   @assume: the projection uses the [-1, 1] clip depth produced by Float4x4::get_perspective_matrix.
+///   light_dir is the direction the sunlight travels in.
+pub(crate) fn compute_shadow_cascades(settings: &ShadowSettings, view: Float4x4, proj: Float4x4, light_dir: Float3) -> ShadowCascades {
+    let cascade_count = settings.cascade_count as usize;
+
+    // Recover the camera's clip planes from the projection
+    let inv_proj = proj.invert();
+    let near     = -to_float3(inv_proj.translate_point(Float4::new(0.0, 0.0, -1.0, 1.0))).z;
+    let far      = -to_float3(inv_proj.translate_point(Float4::new(0.0, 0.0,  1.0, 1.0))).z;
+
+    let shadow_far = far.min(settings.max_distance);
+    let splits     = compute_split_depths(near, shadow_far, cascade_count, settings.split_lambda);
+
+    // World-space corners of the camera frustum, paired up along each edge from the near to the far plane
+    let inv_view_proj = mul_rh(proj, view).invert();
+    let ndc_xy: [(f32, f32); 4] = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)];
+
+    let mut near_corners = [Float3::zero(); 4];
+    let mut far_corners  = [Float3::zero(); 4];
+    for (i, (x, y)) in ndc_xy.iter().enumerate() {
+        near_corners[i] = to_float3(inv_view_proj.translate_point(Float4::new(*x, *y, -1.0, 1.0)));
+        far_corners[i]  = to_float3(inv_view_proj.translate_point(Float4::new(*x, *y,  1.0, 1.0)));
+    }
+
+    let light_dir = light_dir.unit();
+    let light_up  = if light_dir.y.abs() > 0.99 { Float3::new(0.0, 0.0, 1.0) } else { Float3::new(0.0, 1.0, 0.0) };
+
+    let mut result = ShadowCascades{
+        view_proj:    [Float4x4::identity(); MAX_SHADOW_CASCADES],
+        split_depths: splits,
+        texel_sizes:  [0.0; MAX_SHADOW_CASCADES],
+        count:        cascade_count,
+    };
+
+    let mut slice_near = near;
+    for cascade in 0..cascade_count {
+        let slice_far = splits[cascade];
+
+        // View depth is linear along each frustum edge, so the slice corners are a lerp between the planes
+        let t_near = (slice_near - near) / (far - near);
+        let t_far  = (slice_far  - near) / (far - near);
+
+        let mut corners = [Float3::zero(); 8];
+        for i in 0..4 {
+            let edge = far_corners[i] - near_corners[i];
+            corners[i]     = near_corners[i] + edge * t_near;
+            corners[i + 4] = near_corners[i] + edge * t_far;
+        }
+
+        let mut center = Float3::zero();
+        for corner in &corners {
+            center = center + *corner;
+        }
+        center = center / 8.0;
+
+        let mut radius: f32 = 0.0;
+        for corner in &corners {
+            radius = radius.max((*corner - center).length());
+        }
+        // Round the radius up so floating point noise doesn't change the cascade's size from frame to frame
+        radius = (radius * 16.0).ceil() / 16.0;
+
+        let eye        = center - light_dir * (radius + SHADOW_CASTER_DISTANCE);
+        let light_view = Float4x4::get_look_at_matrix(eye, center, light_up);
+        let light_proj = Float4x4::get_orthographic_matrix(-radius, radius, -radius, radius, 0.0, 2.0 * radius + SHADOW_CASTER_DISTANCE);
+        let view_proj  = mul_rh(light_proj, light_view);
+
+        // Snap the projection to whole texels
+        let half_resolution = settings.cascade_resolution as f32 * 0.5;
+        let origin   = view_proj.translate_point(Float4::new(0.0, 0.0, 0.0, 1.0));
+        let origin_x = origin.x * half_resolution;
+        let origin_y = origin.y * half_resolution;
+        let offset   = Float4::new((origin_x.round() - origin_x) / half_resolution, (origin_y.round() - origin_y) / half_resolution, 0.0, 0.0);
+
+        result.view_proj[cascade]   = mul_rh(Float4x4::get_translate_matrix(offset), view_proj);
+        result.texel_sizes[cascade] = 2.0 * radius / settings.cascade_resolution as f32;
+
+        slice_near = slice_far;
+    }
+
+    return result;
+}
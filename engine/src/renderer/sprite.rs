@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+
+use crate::math::{ float2::*, float4::* };
+
+use super::shader::GpuSprite;
+
+use vendor::vulkan::VkDeviceAddress;
+
+//
+// 2D Sprites
+//
+// Textured quads for 2D games and overlays. The engine sends the frame's sprites with RenderCommand::UpdateSprites,
+// and the camera they are seen from with RenderCommand::UpdateSpriteCamera, usually a
+// Float4x4::get_orthographic_2d_matrix.
+//   1. Sort  - sprites are sorted by layer, lower layers are drawn first. Within a layer, sprites are grouped by
+//              texture.
+//   2. Batch - consecutive sprites with the same texture become a single instanced draw. The quads are expanded
+//              from a storage buffer of GpuSprites in sprite.vert, so there is no vertex buffer.
+//   3. Draw  - the batches are drawn over the tonemapped image, before the debug lines, so the sprite colors are
+//              the colors on screen. Textures come from the bindless texture array, see material_system.rs.
+//
+// Sprites in the same layer that overlap are drawn in an unspecified order, put them in different layers when the
+// order matters.
+//
+
+/// Sprites past this count are dropped
+pub const MAX_SPRITES: usize = 65536;
+
+/// How sprite textures are sampled.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SpriteFilter {
+    Nearest, // pixel-perfect, for pixel art
+    Linear,  // filtered, for scaled or rotated sprites
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SpriteSettings {
+    pub filter: SpriteFilter,
+}
+
+impl Default for SpriteSettings {
+    fn default() -> Self {
+        Self{
+            filter: SpriteFilter::Linear,
+        }
+    }
+}
+
+/// A rectangle of a texture, in normalized texture coordinates.
+#[derive(Clone, Copy, Debug)]
+pub struct UvRect {
+    pub min: Float2,
+    pub max: Float2,
+}
+
+impl Default for UvRect {
+    fn default() -> Self {
+        return Self::full();
+    }
+}
+
+impl UvRect {
+    /// The whole texture
+    pub fn full() -> Self {
+        return Self{ min: Float2::zero(), max: Float2::one() };
+    }
+
+    /// A rectangle of `width` by `height` texels at (`x`, `y`), from the top-left of a texture that is
+    /// `texture_width` by `texture_height` texels.
+    pub fn from_texels(x: u32, y: u32, width: u32, height: u32, texture_width: u32, texture_height: u32) -> Self {
+        let texel_width  = 1.0 / texture_width.max(1)  as f32;
+        let texel_height = 1.0 / texture_height.max(1) as f32;
+
+        return Self{
+            min: Float2::new(x as f32 * texel_width, y as f32 * texel_height),
+            max: Float2::new((x + width) as f32 * texel_width, (y + height) as f32 * texel_height),
+        };
+    }
+
+    /// The same rectangle, mirrored horizontally
+    pub fn flip_x(self) -> Self {
+        return Self{ min: Float2::new(self.max.x, self.min.y), max: Float2::new(self.min.x, self.max.y) };
+    }
+
+    /// The same rectangle, mirrored vertically
+    pub fn flip_y(self) -> Self {
+        return Self{ min: Float2::new(self.min.x, self.max.y), max: Float2::new(self.max.x, self.min.y) };
+    }
+}
+
+/// A textured quad. `position` is where the sprite's `origin` ends up, and the sprite rotates around it.
+#[derive(Clone, Copy, Debug)]
+pub struct Sprite {
+    pub texture:  u64,    // CreateTextureInfo::engine_id, sprites with a missing texture use the error texture
+    pub position: Float2, // world units
+    pub size:     Float2, // world units
+    pub origin:   Float2, // from (0, 0), the top-left corner, to (1, 1), the bottom-right corner
+    pub rotation: f32,    // radians, counter-clockwise
+    pub uv_rect:  UvRect,
+    pub tint:     Float4, // multiplied with the texture, alpha blended
+    pub layer:    i32,    // higher layers are drawn over lower layers
+}
+
+impl Sprite {
+    /// A sprite showing the whole texture, centered on `position`.
+    pub fn new(texture: u64, position: Float2, size: Float2) -> Self {
+        return Self{
+            texture,
+            position,
+            size,
+            origin:   Float2::new(0.5, 0.5),
+            rotation: 0.0,
+            uv_rect:  UvRect::full(),
+            tint:     Float4::one(),
+            layer:    0,
+        };
+    }
+}
+
+/// Named regions of a single texture, so many sprites can share a texture and be drawn in the same batch.
+pub struct TextureAtlas {
+    pub texture: u64, // CreateTextureInfo::engine_id
+    pub width:   u32, // texels
+    pub height:  u32,
+    regions:     HashMap<String, UvRect>,
+}
+
+impl TextureAtlas {
+    pub fn new(texture: u64, width: u32, height: u32) -> Self {
+        return Self{ texture, width, height, regions: HashMap::new() };
+    }
+
+    /// An atlas of equally sized cells, named "0", "1", ... from left to right, then top to bottom.
+    pub fn from_grid(texture: u64, width: u32, height: u32, cell_width: u32, cell_height: u32) -> Self {
+        let mut atlas = Self::new(texture, width, height);
+
+        let columns = width  / cell_width.max(1);
+        let rows    = height / cell_height.max(1);
+
+        for row in 0..rows {
+            for column in 0..columns {
+                let index = row * columns + column;
+                atlas.add_region(&index.to_string(), column * cell_width, row * cell_height, cell_width, cell_height);
+            }
+        }
+
+        return atlas;
+    }
+
+    /// Adds a region of `width` by `height` texels at (`x`, `y`), replacing a region with the same name.
+    pub fn add_region(&mut self, name: &str, x: u32, y: u32, width: u32, height: u32) {
+        let uv_rect = UvRect::from_texels(x, y, width, height, self.width, self.height);
+        self.regions.insert(name.to_string(), uv_rect);
+    }
+
+    pub fn get_region(&self, name: &str) -> Option<UvRect> {
+        return self.regions.get(name).copied();
+    }
+
+    /// A sprite showing the named region, centered on `position`. None if the atlas has no such region.
+    pub fn make_sprite(&self, name: &str, position: Float2, size: Float2) -> Option<Sprite> {
+        let uv_rect = self.get_region(name)?;
+
+        let mut sprite = Sprite::new(self.texture, position, size);
+        sprite.uv_rect = uv_rect;
+
+        return Some(sprite);
+    }
+}
+
+/// One instanced draw of sprites sharing a texture
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) struct SpriteBatch {
+    pub texture_index:  u32, // into the bindless texture array
+    pub first_instance: u32, // into the GpuSprite buffer
+    pub instance_count: u32,
+}
+
+/// A frame's sprites, uploaded and ready to draw
+pub(crate) struct SpriteDrawList {
+    pub sprite_buffer: VkDeviceAddress, // GpuSprite array
+    pub batches:       Vec<SpriteBatch>,
+}
+
+/// Sorts the sprites by layer and texture and packs them for sprite.vert. `get_texture_index` maps a sprite's
+/// texture to its slot in the bindless texture array. Returns the packed sprites and the draws that cover them.
+pub(crate) fn build_sprite_batches(sprites: &[Sprite], get_texture_index: impl Fn(u64) -> u32) -> (Vec<GpuSprite>, Vec<SpriteBatch>) {
+    if sprites.len() > MAX_SPRITES {
+        println!("[WARN] :: Sprites :: {} sprites were submitted, only the first {} are rendered.", sprites.len(), MAX_SPRITES);
+    }
+
+    let sprites = &sprites[0..sprites.len().min(MAX_SPRITES)];
+
+    let mut sorted: Vec<(i32, u32, usize)> = sprites.iter()
+        .enumerate()
+        .map(|(index, sprite)| (sprite.layer, get_texture_index(sprite.texture), index))
+        .collect();
+
+    // The sprite index keeps the order stable within a layer and texture
+    sorted.sort_unstable();
+
+    let mut instances = Vec::<GpuSprite>::with_capacity(sorted.len());
+    let mut batches   = Vec::<SpriteBatch>::new();
+
+    for (_, texture_index, sprite_index) in sorted {
+        let sprite = &sprites[sprite_index];
+
+        instances.push(GpuSprite{
+            position_size: Float4::new(sprite.position.x, sprite.position.y, sprite.size.x, sprite.size.y),
+            uv_rect:       Float4::new(sprite.uv_rect.min.x, sprite.uv_rect.min.y, sprite.uv_rect.max.x, sprite.uv_rect.max.y),
+            tint:          sprite.tint,
+            origin:        Float4::new(sprite.origin.x, sprite.origin.y, sprite.rotation.cos(), sprite.rotation.sin()),
+        });
+
+        match batches.last_mut() {
+            Some(batch) if batch.texture_index == texture_index => batch.instance_count += 1,
+            _ => batches.push(SpriteBatch{
+                texture_index,
+                first_instance: (instances.len() - 1) as u32,
+                instance_count: 1,
+            }),
+        }
+    }
+
+    return (instances, batches);
+}
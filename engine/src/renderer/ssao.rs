@@ -0,0 +1,83 @@
+use crate::math::{ float4::*, float4x4::* };
+
+use vendor::vulkan::*;
+
+//
+// Screen-Space Ambient Occlusion
+//
+// The lighting is forward, so the ambient light is already in the scene by the time the depth can be read. The
+// geometry pass writes its ambient term to a second color attachment, and the occlusion is applied afterwards by
+// taking the occluded part of it back out of the scene:
+//   1. SSAO  - at half resolution. View-space positions are reconstructed from the resolved scene depth, and the
+//              normals from the differences between neighbouring depths. Samples in a normal-oriented hemisphere,
+//              rotated per pixel, are projected back onto the depth buffer to test whether something is in front
+//              of them. The AO is stored with the view depth of its texel, for the blur.
+//   2. Blur  - a separable bilateral blur, horizontal then vertical. Texels at a different depth get little weight,
+//              so the occlusion of an object doesn't bleed onto the background.
+//   3. Apply - scene -= ambient * (1 - ao), at full resolution. Runs before the skybox, so the background and the
+//              transparent meshes drawn later aren't occluded.
+//
+// Every parameter can be changed at runtime with RenderCommand::UpdateSsaoSettings.
+//
+
+// Must match ssao.comp, ssao_blur.comp and ssao_apply.comp
+pub(crate) const SSAO_GROUP_SIZE: u32 = 16;
+
+/// x: AO, y: view depth of the texel. RGBA16F rather than RG16F, which isn't guaranteed as a storage image.
+pub(crate) const SSAO_IMAGE_FORMAT: VkFormat = VK_FORMAT_R16G16B16A16_SFLOAT;
+
+/// The geometry pass' ambient term, which the apply pass scales by the occlusion.
+pub(crate) const AMBIENT_IMAGE_FORMAT: VkFormat = VK_FORMAT_R16G16B16A16_SFLOAT;
+
+pub const MAX_SSAO_SAMPLES: u32 = 64;
+
+/// Ambient occlusion settings. Can be changed at runtime with RenderCommand::UpdateSsaoSettings.
+#[derive(Clone, Copy, Debug)]
+pub struct SsaoSettings {
+    pub enabled:      bool,
+    pub radius:       f32,  // world-space radius of the sampled hemisphere
+    pub intensity:    f32,  // the AO is raised to this power, 0 turns it off
+    pub sample_count: u32,  // samples per pixel, 1 to MAX_SSAO_SAMPLES
+    pub debug_view:   bool, // replaces the scene with the blurred AO buffer
+}
+
+impl Default for SsaoSettings {
+    fn default() -> Self {
+        Self{
+            enabled:      true,
+            radius:       0.5,
+            intensity:    1.5,
+            sample_count: 16,
+            debug_view:   false,
+        }
+    }
+}
+
+impl SsaoSettings {
+    /// Clamps the settings to values the renderer can use.
+    pub fn sanitize(&self) -> SsaoSettings {
+        return SsaoSettings{
+            enabled:      self.enabled,
+            radius:       self.radius.max(0.01),
+            intensity:    self.intensity.clamp(0.0, 8.0),
+            sample_count: self.sample_count.clamp(1, MAX_SSAO_SAMPLES),
+            debug_view:   self.debug_view,
+        };
+    }
+}
+
+/// The AO buffer is half the screen size, rounded up so every screen pixel has a texel.
+pub(crate) fn get_ssao_extent(screen_extent: VkExtent3D) -> VkExtent3D {
+    return VkExtent3D{ width: screen_extent.width.div_ceil(2).max(1), height: screen_extent.height.div_ceil(2).max(1), depth: 1 };
+}
+
+/// The terms of a perspective projection the SSAO shaders reconstruct view-space positions from, the other terms
+/// are assumed to be zero. x: proj[0][0], y: proj[1][1], z: proj[2][2], w: proj[3][2], indexed [column][row].
+pub(crate) fn get_projection_terms(proj: Float4x4) -> Float4 {
+    let column0 = proj.translate_point(Float4::new(1.0, 0.0, 0.0, 0.0));
+    let column1 = proj.translate_point(Float4::new(0.0, 1.0, 0.0, 0.0));
+    let column2 = proj.translate_point(Float4::new(0.0, 0.0, 1.0, 0.0));
+    let column3 = proj.translate_point(Float4::new(0.0, 0.0, 0.0, 1.0));
+
+    return Float4::new(column0.x, column1.y, column2.z, column3.z);
+}
@@ -1,9 +1,11 @@
 use std::borrow::BorrowMut;
 use std::ptr;
 use std::rc::Rc;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::str::FromStr;
 use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::time::Instant;
 
 use crate::math::{ float3::*, float4::*, float4x4::* };
 use crate::window::NativeSurface;
@@ -22,53 +24,259 @@ use super::graphics::{
 };
 
 use super::command_buffer::*;
+use super::culling::*;
+use super::debug_draw::*;
+use super::deferred::*;
+use super::environment::*;
+use super::error::RenderError;
+use super::lights::*;
+use super::lod::*;
+use super::material_system::*;
 use super::mesh::*;
+use super::mesh_processing::compute_bounds;
+use super::particles::*;
+use super::post_process::*;
+use super::render_graph::*;
 use super::shader::*;
+use super::shadows::*;
+use super::sprite::*;
+use super::ssao::*;
+use super::text::*;
+use super::transparency::*;
+use super::worker_pool::WorkerPool;
 
 use vendor::vulkan::*;
 use vendor::imgui::*;
 
+const SCENE_IMAGE_FORMAT: VkFormat = VK_FORMAT_R16G16B16A16_SFLOAT;
+const SHADOW_MAP_FORMAT:  VkFormat = VK_FORMAT_D32_SFLOAT;
+
+// Multithreaded recording
+//   Draw lists are only split across workers when each worker gets at least MIN_DRAWS_PER_WORKER draws, below
+//   that the cost of handing the draws to the workers outweighs recording on the render thread.
+const MAX_RECORDING_WORKERS: usize = 8;
+const MIN_DRAWS_PER_WORKER:  usize = 256;
+
+// Samples per pixel of the geometry pass, until the engine asks for something else. Clamped to what the GPU supports.
+const DEFAULT_MSAA_SAMPLES: u32 = 4;
+
+// Workgroup size of the post-processing compute shaders, in both dimensions
+const POST_PROCESS_GROUP_SIZE: u32 = 16;
+
 struct PerFrameCommandBuffer {
     pool:   CommandPool,
     handle: CommandBuffer,
 }
 
+// A command pool owned by a single recording worker. Secondary command buffers are handed out in order each frame
+// and reused once the frame's fence has been waited on.
+struct WorkerCommandPool {
+    pool:            CommandPool,
+    command_buffers: Vec<CommandBuffer>,
+    used:            usize,
+}
+
+impl WorkerCommandPool {
+    fn new(device: &Device) -> Result<WorkerCommandPool, RenderError> {
+        return Ok(WorkerCommandPool{
+            pool:            device.create_command_pool(QueueType::Graphics)?,
+            command_buffers: Vec::new(),
+            used:            0,
+        });
+    }
+
+    // @assume: the GPU has finished the frame that last used these command buffers.
+    fn begin_frame(&mut self) {
+        self.used = 0;
+    }
+
+    fn acquire(&mut self, device: &Device) -> Result<&mut CommandBuffer, RenderError> {
+        if self.used == self.command_buffers.len() {
+            self.command_buffers.push(device.create_secondary_command_buffer(&self.pool)?);
+        }
+
+        let command_buffer = &mut self.command_buffers[self.used];
+        self.used += 1;
+
+        if command_buffer.state == CommandBufferState::Closed {
+            command_buffer.reset()?;
+        }
+
+        return Ok(command_buffer);
+    }
+}
+
+// Everything needed to record a range of the geometry draw list, shared with the recording workers. The draw list
+// is every mesh followed by every instanced mesh, `draws` holds the indices that survived frustum culling. The
+// skinned meshes aren't part of the draw list, they are recorded after its last draw.
+struct GeometryDrawContext<'a> {
+    pipeline:          VkPipeline,
+    skinned_pipeline:  VkPipeline,
+    layout:            VkPipelineLayout,
+    scene_set:         VkDescriptorSet,
+    draw_extent:       VkExtent2D,
+    meshes:            &'a [GpuMeshBuffers],
+    instanced_meshes:  &'a [GpuInstancedMesh],
+    skinned_meshes:    &'a [GpuSkinnedMesh],
+    draws:             &'a [usize],     // visible draws, indices into the draw list
+    default_instances: VkDeviceAddress, // the single identity instance regular meshes are drawn with
+    bindless_set:      VkDescriptorSet, // every material and texture, see material_system.rs
+    materials:         &'a [GpuMaterial],
+}
+
+// The handles and meshes are only read while recording, so the context can be shared between workers.
+unsafe impl<'a> Sync for GeometryDrawContext<'a> {}
+
+impl<'a> GeometryDrawContext<'a> {
+    fn get_draw_count(&self) -> usize {
+        return self.draws.len();
+    }
+
+    fn bind_state(&self, cmd_buffer: &mut CommandBuffer) {
+        cmd_buffer.bind_graphics_pipeline(self.pipeline);
+        cmd_buffer.set_viewport(self.draw_extent.width as i32, self.draw_extent.height as i32, 0, 0);
+        cmd_buffer.set_scissor(self.draw_extent.width, self.draw_extent.height);
+
+        let sets: [VkDescriptorSet; 2] = [self.scene_set, self.bindless_set];
+        cmd_buffer.bind_graphics_descriptor_sets(self.layout, 0, &sets);
+    }
+
+    fn record(&self, cmd_buffer: &mut CommandBuffer, draws: std::ops::Range<usize>) {
+        // Secondary command buffers don't inherit any state, so everything is bound per command buffer.
+        self.bind_state(cmd_buffer);
+
+        let is_last_range = draws.end == self.draws.len();
+
+        for &draw in &self.draws[draws] {
+            let (mesh, instance_buffer, instance_count) = if draw < self.meshes.len() {
+                (&self.meshes[draw], self.default_instances, 1)
+            } else {
+                let instanced = &self.instanced_meshes[draw - self.meshes.len()];
+                (&instanced.mesh, instanced.instance_buffer_address, instanced.instance_count)
+            };
+
+            if instance_count == 0 {
+                continue;
+            }
+
+            let push_consts = GpuDrawPushConstants {
+                world_matrix:   mesh.transform,
+                vertex_buffer:  mesh.vertex_buffer_address,
+                instance_buffer,
+                material_index: mesh.material_index as u32,
+                _pad:           0,
+                skin_buffer:    0,
+                joint_buffer:   0,
+            };
+
+            cmd_buffer.bind_push_constants(self.layout, VK_SHADER_STAGE_VERTEX_BIT | VK_SHADER_STAGE_FRAGMENT_BIT, push_consts, 0);
+            cmd_buffer.bind_index_buffer(&mesh.index_buffer);
+            let lod = mesh.get_lod();
+            cmd_buffer.draw_indexed(lod.index_count, instance_count, lod.first_index, 0, 0);
+        }
+
+        if is_last_range {
+            self.record_skinned(cmd_buffer);
+        }
+    }
+
+    /// Records every opaque skinned mesh with the skinned pipeline, leaving it bound.
+    fn record_skinned(&self, cmd_buffer: &mut CommandBuffer) {
+        if self.skinned_meshes.is_empty() {
+            return;
+        }
+
+        cmd_buffer.bind_graphics_pipeline(self.skinned_pipeline);
+
+        for skinned in self.skinned_meshes {
+            let mesh = &skinned.mesh;
+            if self.materials[mesh.material_index].is_blended() {
+                continue;
+            }
+
+            let push_consts = GpuDrawPushConstants {
+                world_matrix:    mesh.transform,
+                vertex_buffer:   mesh.vertex_buffer_address,
+                instance_buffer: self.default_instances,
+                material_index:  mesh.material_index as u32,
+                _pad:            0,
+                skin_buffer:     skinned.skin_buffer_address,
+                joint_buffer:    skinned.joint_buffer_address,
+            };
+
+            cmd_buffer.bind_push_constants(self.layout, VK_SHADER_STAGE_VERTEX_BIT | VK_SHADER_STAGE_FRAGMENT_BIT, push_consts, 0);
+            cmd_buffer.bind_index_buffer(&mesh.index_buffer);
+            let lod = mesh.get_lod();
+            cmd_buffer.draw_indexed(lod.index_count, 1, lod.first_index, 0, 0);
+        }
+    }
+
+    /// Records one indirect count draw per batch of the GPU draw list. The draw commands and counts were written by
+    /// the draw cull pass, and the object transforms are baked into the frame's instance buffer.
+    fn record_indirect(&self, cmd_buffer: &mut CommandBuffer, draw_buffers: &GpuDrawBuffers) {
+        self.bind_state(cmd_buffer);
+
+        for (batch_index, batch) in draw_buffers.list.batches.iter().enumerate() {
+            let mesh = &batch.mesh;
+
+            let push_consts = GpuDrawPushConstants {
+                world_matrix:    Float4x4::identity(),
+                vertex_buffer:   mesh.vertex_buffer_address,
+                instance_buffer: draw_buffers.instance_address,
+                material_index:  mesh.material_index as u32,
+                _pad:            0,
+                skin_buffer:     0,
+                joint_buffer:    0,
+            };
+
+            cmd_buffer.bind_push_constants(self.layout, VK_SHADER_STAGE_VERTEX_BIT | VK_SHADER_STAGE_FRAGMENT_BIT, push_consts, 0);
+            cmd_buffer.bind_index_buffer(&mesh.index_buffer);
+            cmd_buffer.draw_indexed_indirect_count(
+                draw_buffers.commands.buffer, (batch.first_command as usize * DRAW_COMMAND_STRIDE) as VkDeviceSize,
+                draw_buffers.counts.buffer,   (batch_index * std::mem::size_of::<u32>()) as VkDeviceSize,
+                batch.max_commands, DRAW_COMMAND_STRIDE as u32);
+        }
+
+        self.record_skinned(cmd_buffer);
+    }
+}
+
 struct PerFrameDeletionQueues {
     buffer_deletion_queue: VecDeque<AllocatedBuffer>,
     image_deletion_queue:  VecDeque<AllocatedImage>,
 }
 
 struct PerFrameData {
-    command_buffer:      RefCell<PerFrameCommandBuffer>, // i don't like this one bit...
-    dynamic_descriptors: RefCell<DescriptorAllocatorGrowable>,
-    deletion_queues:     RefCell<PerFrameDeletionQueues>,
-}
-
-struct ComputeEffect {
-    pub name:      String,
-	pub pipeline:  VkPipeline,
-	pub layout:    VkPipelineLayout,
-	pub push_data: ComputePushConstants,
+    command_buffer:       RefCell<PerFrameCommandBuffer>, // i don't like this one bit...
+    worker_command_pools: RefCell<Vec<WorkerCommandPool>>, // one per recording worker
+    dynamic_descriptors:  RefCell<DescriptorAllocatorGrowable>,
+    deletion_queues:      RefCell<PerFrameDeletionQueues>,
+    culling_readback:     Cell<Option<(AllocatedBuffer, usize)>>, // the draw counts and batch count of a GPU-driven frame
 }
 
+#[derive(Clone, Copy)]
 pub struct RendererCreateInfo {
-    pub surface: NativeSurface,
+    pub surface:     NativeSurface,
+    pub gpu_index:   Option<usize>, // explicit gpu selection, see adapter::enumerate_adapters
+    pub prefer_hdr:  bool,          // present to an HDR10 swapchain when the display supports one
+    pub render_path: RenderPath,    // forward or deferred shading of the opaque geometry, see deferred.rs
 }
 
 pub struct RenderSystem{
+    create_info: RendererCreateInfo, // kept around to recreate the device if it is lost
     device:      Device,
     swapchain:   Swapchain,
-    scene_image: AllocatedImage,
-    depth_image: AllocatedImage,
 
     // todo: look into using get_mut() when there is a single reference to the Rc. This might
     //       allow me to avoid using RefCell.
     frame_data:  Vec<Rc<PerFrameData>>,
     frame_index: usize,
 
-    global_da:     DescriptorAllocator,
-    draw_image_dl: VkDescriptorSetLayout,
-    draw_image_ds: VkDescriptorSet,
+    // Threads the geometry pass can split its draws across, each one records with its own WorkerCommandPool
+    recording_workers: WorkerPool,
+
+    // Physical images behind the render graph's transient images (scene color, depth, ...)
+    transient_images: RefCell<TransientImagePool>,
 
     scene_data:      GlobalSceneData,
     global_scene_dl: VkDescriptorSetLayout,
@@ -84,31 +292,159 @@ pub struct RenderSystem{
     // IMGUI Editor Data
     //editor_data:        EditorRenderData,
 
-	// for the background
-	gradient_pl:   VkPipelineLayout,
-	gradient_p:    VkPipeline,
+	// Skybox and image-based lighting, see environment.rs
+	environment:          GpuEnvironment,
+	retained_environment: EnvironmentCubes, // CPU copy of environment
+	environment_loader:   EnvironmentLoader, // builds the environments the engine sends
+	skybox_settings:      SkyboxSettings,
+	skybox_dl:            VkDescriptorSetLayout,
+	skybox_pl:            VkPipelineLayout,
+	skybox_p:             VkPipeline, // draws the skybox behind the geometry, in the background pass
+	cube_sampler:         VkSampler,  // trilinear, for the environment cubemaps
+
+	// for the lit meshes
+	mesh_pl:         VkPipelineLayout,
+	mesh_p:          VkPipeline,
+	skinned_mesh_p:  VkPipeline, // mesh_p with skinned_mesh.vert, shares mesh_pl
+
+	// Deferred shading, see deferred.rs. Only used when create_info.render_path is RenderPath::Deferred
+	deferred_settings:      DeferredSettings,
+	gbuffer_mesh_p:         VkPipeline, // mesh_p that writes the G-buffer instead of lighting, shares mesh_pl
+	gbuffer_skinned_mesh_p: VkPipeline,
+	deferred_lighting_dl:   VkDescriptorSetLayout,
+	deferred_lighting_pl:   VkPipelineLayout,
+	deferred_lighting_p:    VkPipeline, // lights the G-buffer into the scene
+
+	// Meshes with blended materials, see transparency.rs. All of the mesh pipelines share mesh_pl
+	transparency_settings:      TransparencySettings,
+	transparent_mesh_p:         VkPipeline,
+	transparent_skinned_mesh_p: VkPipeline,
+	oit_mesh_p:                 VkPipeline, // weighted blended OIT, renders into the accumulation and revealage targets
+	oit_skinned_mesh_p:         VkPipeline,
+	oit_composite_dl:           VkDescriptorSetLayout,
+	oit_composite_pl:           VkPipelineLayout,
+	oit_composite_p:            VkPipeline, // blends the accumulated transparent surfaces over the scene
+
+	// GPU-driven geometry, see culling.rs
+	culling_settings: GpuCullingSettings,
+	culling_stats:    Cell<CullingStats>, // written while recording the geometry pass, or once a GPU-driven frame is done
+	draw_cull_dl:     VkDescriptorSetLayout,
+	draw_cull_pl:     VkPipelineLayout,
+	draw_cull_p:      VkPipeline,
+	hiz_build_p:      VkPipeline, // uses the post-processing layout, a sampled source and a storage target
+	hiz:              HiZPyramid, // built from the scene depth, sized to the swapchain
+
+	// MSAA for the geometry pass
+	msaa_samples:           VkSampleCountFlagBits, // sample count of the geometry targets, mesh_p and skinned_mesh_p
+	requested_msaa_samples: u32,                   // set by RenderCommand::UpdateMsaaSampleCount, applied on resize
+
+	// Screen-space ambient occlusion, see ssao.rs
+	ssao_settings: SsaoSettings,
+	ssao_p:        VkPipeline, // uses the post-processing layout, the scene depth and the AO target
+	ssao_blur_p:   VkPipeline, // uses the post-processing layout
+	ssao_apply_dl: VkDescriptorSetLayout,
+	ssao_apply_pl: VkPipelineLayout,
+	ssao_apply_p:  VkPipeline,
+
+	// Sun shadows
+	shadow_settings: ShadowSettings,
+	shadow_depth_pl: VkPipelineLayout,
+	shadow_depth_p:  VkPipeline,
+	skinned_shadow_depth_p: VkPipeline, // shadow_depth_p with skinned_shadow_depth.vert
+	shadow_depth_alpha_test_p:         VkPipeline, // shadow_depth_p for Mask materials, discards the texels they cut out
+	skinned_shadow_depth_alpha_test_p: VkPipeline,
+	shadow_sampler:  VkSampler,
+
+	// Clustered point and spot lights
+	point_lights:  Vec<PointLightInfo>,
+	spot_lights:   Vec<SpotLightInfo>,
+	light_cull_dl: VkDescriptorSetLayout,
+	light_cull_pl: VkPipelineLayout,
+	light_cull_p:  VkPipeline,
+	light_grid:    AllocatedBuffer, // the lights in each cluster, written by the light cull pass every frame
+
+	// HDR post-processing, see post_process.rs
+	post_settings:      PostProcessSettings,
+	post_process_dl:    VkDescriptorSetLayout, // a sampled source and a storage target, for bloom and FXAA
+	tonemap_dl:         VkDescriptorSetLayout,
+	post_process_pl:    VkPipelineLayout,
+	tonemap_pl:         VkPipelineLayout,
+	bloom_downsample_p: VkPipeline,
+	bloom_upsample_p:   VkPipeline,
+	tonemap_p:          VkPipeline,
+	fxaa_p:             VkPipeline,
+	post_sampler:       VkSampler,        // linear, clamped to the edge of the screen
+	color_grading_lut:  AllocatedImage,
+	retained_lut:       ColorGradingLut,  // CPU copy of color_grading_lut
+
+	// Particle emitters, see particles.rs
+	particle_emitters:   Vec<ParticleEmitterState>,
+	particle_settings:   ParticleSettings,
+	particle_clock:      Option<Instant>, // when the particles were last simulated
+	particle_sim_pl:     VkPipelineLayout,
+	particle_sim_p:      VkPipeline,
+	particle_pl:         VkPipelineLayout,
+	particle_additive_p: VkPipeline,
+	particle_alpha_p:    VkPipeline,
+
+	// 2D sprites, see sprite.rs
+	sprites:         Vec<Sprite>,    // set by RenderCommand::UpdateSprites
+	sprite_camera:   Float4x4,       // set by RenderCommand::UpdateSpriteCamera
+	sprite_settings: SpriteSettings,
+	sprite_pl:       VkPipelineLayout,
+	sprite_p:        VkPipeline,
+
+	// Screen- and world-space text, see text.rs
+	text:         Vec<TextDraw>, // set by RenderCommand::UpdateText
+	text_pl:      VkPipelineLayout,
+	text_p:       VkPipeline, // screen text, drawn over the scene
+	text_world_p: VkPipeline, // world text, depth tested against the scene
+
+	// Debug lines, see debug_draw.rs
+	debug_lines:          DebugLines, // set by RenderCommand::UpdateDebugLines
+	debug_line_pl:        VkPipelineLayout,
+	debug_line_p:         VkPipeline, // depth tested against the scene
+	debug_line_overlay_p: VkPipeline, // drawn over the scene
 
-	compute_effects:        Vec<ComputeEffect>,
-	current_compute_effect: usize,
+	// Mesh "System"
+	meshes:          [GpuMeshBuffers; MAX_LOADED_MESHES],
+	mesh_count:      usize,
+	retained_meshes: Vec<RetainedMesh>, // CPU copies of each mesh, indexed by mesh id
+	lod_settings:    LodSettings,       // how each mesh's LOD is picked every frame, see lod.rs
 
-	// for the triangle
-	single_image_dl: VkDescriptorSetLayout,
-	triangle_pl:     VkPipelineLayout,
-	triangle_p:      VkPipeline,
+	// Instanced meshes, drawn after the regular meshes
+	instanced_meshes:          Vec<GpuInstancedMesh>,
+	retained_instanced_meshes: Vec<RetainedInstancedMesh>, // CPU copies, indexed like instanced_meshes
+	default_instance_buffer:   AllocatedBuffer,             // a single identity instance, bound for regular meshes
+	default_instance_address:  VkDeviceAddress,
 
-	// Mesh "System"
-	meshes:        [GpuMeshBuffers; MAX_LOADED_MESHES],
-	mesh_count:    usize,
+	// Skinned meshes, drawn after the instanced meshes. They aren't culled, their bounds change with the pose.
+	skinned_meshes:          Vec<GpuSkinnedMesh>,
+	retained_skinned_meshes: Vec<RetainedSkinnedMesh>, // CPU copies, indexed like skinned_meshes
 
 	// Texture "System"
 	white_image:              AllocatedImage,
 	black_image:              AllocatedImage,
 	grey_image:               AllocatedImage,
 	error_checkerboard_image: AllocatedImage,
+	flat_normal_image:        AllocatedImage,
+
+	textures:          Vec<GpuTexture>,      // created with RenderCommand::CreateTexture
+	retained_textures: Vec<RetainedTexture>, // CPU copies of each texture, indexed like textures
+
+	// Bindless slots of the default images materials fall back to
+	white_texture:       TextureId,
+	flat_normal_texture: TextureId,
+	error_texture:       TextureId,
 
 	default_sampler_linear:   VkSampler,
 	default_sampler_nearest:  VkSampler,
 
+	// Material "System"
+	bindless:           BindlessSet,             // the material buffer and texture array, see material_system.rs
+	materials:          Vec<GpuMaterial>,        // index 0 is the default material
+	retained_materials: Vec<CreateMaterialInfo>, // every material after the default one, in creation order
+
 	// Camera data
 	view_matrix:        Float4x4,
 	perspective_matrix: Float4x4,
@@ -116,83 +452,216 @@ pub struct RenderSystem{
 	// Outgoing Commands to the engine
 	//   Will probably want this as a mpsc::Sender once the Renderer gets put on its own thread.
 	outgoing_commands: RenderCommandBuffer,
+
+	// Device loss
+	simulate_device_lost: bool, // set by RenderCommand::DebugSimulateDeviceLost
+	is_destroyed:         bool, // all device resources have been released, see destroy()
+
+	// Debug output
+	render_graph_dump_path: Option<PathBuf>, // set by RenderCommand::DebugDumpRenderGraph
 }
 
 impl RenderSystem {
-    fn create_scene_images(device: &Device, extent: VkExtent3D) -> AllocatedImage {
-        let image_usages: VkImageUsageFlags =
-            VK_IMAGE_USAGE_TRANSFER_SRC_BIT |
-            VK_IMAGE_USAGE_TRANSFER_DST_BIT |
-            VK_IMAGE_USAGE_STORAGE_BIT      |
-            VK_IMAGE_USAGE_COLOR_ATTACHMENT_BIT;
-
-        device.allocate_image_memory(
-            extent,
-            VK_FORMAT_R16G16B16A16_SFLOAT,
-            image_usages,
-            VMA_MEMORY_USAGE_GPU_ONLY,
-            VK_MEMORY_PROPERTY_DEVICE_LOCAL_BIT,
-            false,
-        )
-    }
-
-    fn create_depth_image(device: &Device, extent: VkExtent3D) -> AllocatedImage {
-        let depth_format = device.get_depth_format();
-        let image_usages = VK_IMAGE_USAGE_DEPTH_STENCIL_ATTACHMENT_BIT;
-
-        device.allocate_image_memory(
-            extent,
-            depth_format,
-            image_usages,
-            VMA_MEMORY_USAGE_GPU_ONLY,
-            VK_MEMORY_PROPERTY_DEVICE_LOCAL_BIT,
-            false,
-        )
-    }
-
-    fn resize_device_resources(&mut self) {
+    fn resize_device_resources(&mut self) -> Result<(), RenderError> {
         self.device.wait_idle();
 
-        self.swapchain = self.device.create_swapchain(Some(&self.swapchain));
+        self.swapchain = self.device.create_swapchain(Some(&self.swapchain))?;
         self.swapchain.validate();
 
-        self.device.destroy_image_memory(&mut self.scene_image);
-        self.device.destroy_image_memory(&mut self.depth_image);
+        // The geometry targets and the pipelines rendering into them share a sample count, so a new sample count
+        // rebuilds the pipelines here. The targets are recreated by the render graph with the transient images below.
+        let msaa_samples = self.device.get_sample_count(self.requested_msaa_samples);
+        if msaa_samples != self.msaa_samples {
+            let mesh_p         = RenderSystem::create_mesh_pipeline(&self.device, self.mesh_pl, "mesh", msaa_samples)?;
+            let skinned_mesh_p = match RenderSystem::create_mesh_pipeline(&self.device, self.mesh_pl, "skinned_mesh", msaa_samples) {
+                Ok(pipeline) => pipeline,
+                Err(error)   => {
+                    self.device.destroy_pipeline(mesh_p);
+                    return Err(error);
+                },
+            };
 
-        self.scene_image = RenderSystem::create_scene_images(&self.device, self.swapchain.get_extent());
-        self.depth_image = RenderSystem::create_depth_image(&self.device,  self.swapchain.get_extent());
+            self.device.destroy_pipeline(self.mesh_p);
+            self.device.destroy_pipeline(self.skinned_mesh_p);
+            self.mesh_p         = mesh_p;
+            self.skinned_mesh_p = skinned_mesh_p;
+            self.msaa_samples   = msaa_samples;
 
-        self.device.clear_descriptor_allocator(&self.global_da);
-        self.draw_image_ds = {
-            let ds = self.device.allocate_descriptors(&self.global_da, self.draw_image_dl).expect("Failed to alloc descriptor set");
+            println!("[INFO] :: RenderSystem :: MSAA set to {}x.", msaa_samples);
+        }
 
-            let mut writer = DescriptorWriter::new();
-            writer.write_storage_image(0, self.scene_image.view, VK_IMAGE_LAYOUT_GENERAL);
-            writer.update_set(&self.device, ds);
+        // The transient images are sized to the swapchain, release them now rather than waiting for them to age out.
+        self.transient_images.borrow_mut().destroy(&self.device);
 
-            ds
-        };
+        // So is the Hi-Z pyramid, the new one has no depth in it until the next frame builds it
+        let hiz = HiZPyramid::new(&self.device, self.swapchain.get_extent())?;
+        self.hiz.destroy(&self.device);
+        self.hiz = hiz;
 
         //vendor::imgui::ig_vulkan_set_min_image_count(self.swapchain.get_image_count() as u32);
         //ImGui_ImplVulkanH_CreateOrResizeWindow(g_Instance, g_PhysicalDevice, g_Device, &g_MainWindowData, g_QueueFamily, g_Allocator, fb_width, fb_height, g_MinImageCount);
+
+        return Ok(());
+    }
+
+    /// The lit mesh pipeline, with mesh.frag and the `vertex_shader` that places the vertices. Renders into the scene
+    /// and the ambient target, see ssao.rs
+    fn create_mesh_pipeline(device: &Device, mesh_pl: VkPipelineLayout, vertex_shader: &str, samples: VkSampleCountFlagBits) -> Result<VkPipeline, RenderError> {
+        let mesh_vert_sm = load_shader_module(device, vertex_shader, ShaderStage::Vertex)?;
+        let mesh_frag_sm = match load_shader_module(device, "mesh", ShaderStage::Fragment) {
+            Ok(module) => module,
+            Err(error) => {
+                device.destroy_shader_module(mesh_vert_sm);
+                return Err(error);
+            },
+        };
+
+        let mut builder = GraphicsPipelineBuilder::new();
+
+        //use the mesh layout we created
+        builder
+            .set_pipeline_layout(mesh_pl)
+        //connecting the vertex and pixel shaders to the pipeline
+            .set_shaders(mesh_vert_sm, mesh_frag_sm)
+        //it will draw triangles
+            .set_input_topology(VK_PRIMITIVE_TOPOLOGY_TRIANGLE_LIST)
+        //filled triangles
+            .set_polygon_mode(VK_POLYGON_MODE_FILL)
+        //no backface culling
+            .set_cull_mode(VK_CULL_MODE_BACK_BIT, VK_FRONT_FACE_CLOCKWISE)
+        //match the sample count of the geometry targets
+            .set_multisampling(samples)
+        //no blending
+            .disable_blending()
+        // additive blending
+            //.enabled_blending_additive()
+        // alpha blending
+            //.enabled_blending_alphablend()
+        //no depth testing
+            //.disable_depth_test()
+        // enabled depth testing
+            .enable_depth_test(true, VK_COMPARE_OP_LESS_OR_EQUAL)
+        //connect the image formats we will draw into, the scene and its ambient light for SSAO
+            .set_color_attachment_formats(&[SCENE_IMAGE_FORMAT, AMBIENT_IMAGE_FORMAT])
+            .set_depth_format(device.get_depth_format());
+
+        //finally build the pipeline
+        let pipeline = builder.build(device);
+
+        device.destroy_shader_module(mesh_vert_sm);
+        device.destroy_shader_module(mesh_frag_sm);
+
+        return pipeline;
+    }
+
+    /// The lit mesh pipeline of the transparent pass. Draws one sample per pixel into the resolved scene, tested
+    /// against the resolved depth without writing it. With `weighted_oit` it uses mesh_oit.frag and renders into the
+    /// accumulation and revealage targets instead of the scene, see transparency.rs.
+    fn create_transparent_mesh_pipeline(device: &Device, mesh_pl: VkPipelineLayout, vertex_shader: &str, weighted_oit: bool) -> Result<VkPipeline, RenderError> {
+        let mesh_vert_sm = load_shader_module(device, vertex_shader, ShaderStage::Vertex)?;
+        let mesh_frag_sm = match load_shader_module(device, if weighted_oit { "mesh_oit" } else { "mesh" }, ShaderStage::Fragment) {
+            Ok(module) => module,
+            Err(error) => {
+                device.destroy_shader_module(mesh_vert_sm);
+                return Err(error);
+            },
+        };
+
+        let mut builder = GraphicsPipelineBuilder::new();
+        builder
+            .set_pipeline_layout(mesh_pl)
+            .set_shaders(mesh_vert_sm, mesh_frag_sm)
+            .set_input_topology(VK_PRIMITIVE_TOPOLOGY_TRIANGLE_LIST)
+            .set_polygon_mode(VK_POLYGON_MODE_FILL)
+            .set_cull_mode(VK_CULL_MODE_BACK_BIT, VK_FRONT_FACE_CLOCKWISE)
+            .set_multisampling_none()
+            .enabled_blending_alphablend()
+        // test against the opaque geometry, but don't hide the transparent surfaces behind
+            .enable_depth_test(false, VK_COMPARE_OP_LESS_OR_EQUAL)
+            .set_depth_format(device.get_depth_format());
+
+        if weighted_oit {
+            builder.set_weighted_oit_attachments(OIT_ACCUM_FORMAT, OIT_REVEALAGE_FORMAT);
+        } else {
+            builder.set_color_attachment_format(SCENE_IMAGE_FORMAT);
+        }
+
+        let pipeline = builder.build(device);
+
+        device.destroy_shader_module(mesh_vert_sm);
+        device.destroy_shader_module(mesh_frag_sm);
+
+        return pipeline;
     }
 
-    pub fn new(create_info: RendererCreateInfo) -> RenderSystem {
+    /// The mesh pipeline of the deferred geometry pass, with the G-buffer variant of mesh.frag. Renders into the
+    /// scene, for the emissive light and the coverage, and the three G-buffer targets at one sample per pixel, see
+    /// deferred.rs
+    fn create_gbuffer_mesh_pipeline(device: &Device, mesh_pl: VkPipelineLayout, vertex_shader: &str) -> Result<VkPipeline, RenderError> {
+        let mesh_vert_sm = load_shader_module(device, vertex_shader, ShaderStage::Vertex)?;
+        let mesh_frag_sm = match load_shader_module(device, "mesh_gbuffer", ShaderStage::Fragment) {
+            Ok(module) => module,
+            Err(error) => {
+                device.destroy_shader_module(mesh_vert_sm);
+                return Err(error);
+            },
+        };
+
+        let mut builder = GraphicsPipelineBuilder::new();
+        builder
+            .set_pipeline_layout(mesh_pl)
+            .set_shaders(mesh_vert_sm, mesh_frag_sm)
+            .set_input_topology(VK_PRIMITIVE_TOPOLOGY_TRIANGLE_LIST)
+            .set_polygon_mode(VK_POLYGON_MODE_FILL)
+            .set_cull_mode(VK_CULL_MODE_BACK_BIT, VK_FRONT_FACE_CLOCKWISE)
+            .set_multisampling_none()
+            .disable_blending()
+            .enable_depth_test(true, VK_COMPARE_OP_LESS_OR_EQUAL)
+            .set_color_attachment_formats(&[SCENE_IMAGE_FORMAT, GBUFFER_ALBEDO_FORMAT, GBUFFER_NORMAL_FORMAT, GBUFFER_MATERIAL_FORMAT])
+            .set_depth_format(device.get_depth_format());
+
+        let pipeline = builder.build(device);
+
+        device.destroy_shader_module(mesh_vert_sm);
+        device.destroy_shader_module(mesh_frag_sm);
+
+        return pipeline;
+    }
+
+    pub fn new(create_info: RendererCreateInfo) -> Result<RenderSystem, RenderError> {
         let device = Device::new(gpu_device::CreateInfo{
-            features:         gpu_device::Features::default(),  //todo: make configurable
+            features:         gpu_device::Features{ prefer_hdr: create_info.prefer_hdr },
             surface:          create_info.surface,
             software_version: crate::make_app_version(0, 0, 1), //todo: make configurable
             software_name:    String::from("Testbed"),          //todo: make configurable
-        });
+            gpu_index:        create_info.gpu_index,
+        })?;
+
+        let swapchain = device.create_swapchain(None)?;
+
+        let recording_worker_count = std::thread::available_parallelism()
+            .map(|count| count.get())
+            .unwrap_or(1)
+            .min(MAX_RECORDING_WORKERS);
+
+        let recording_workers = match WorkerPool::new("Command Recording", recording_worker_count) {
+            Ok(workers) => workers,
+            Err(error)  => return Err(RenderError::Initialization(format!("Failed to start the command recording workers: {}", error))),
+        };
 
-        let swapchain = device.create_swapchain(None);
+        let environment_loader = match EnvironmentLoader::new() {
+            Ok(loader) => loader,
+            Err(error) => return Err(RenderError::Initialization(format!("Failed to start the environment loader: {}", error))),
+        };
 
-        let scene_image = RenderSystem::create_scene_images(&device, swapchain.get_extent());
-        let depth_image = RenderSystem::create_depth_image(&device,  swapchain.get_extent());
+        let init_frame_data = |device: &Device| -> Result<PerFrameData, RenderError> {
+            let pool =   device.create_command_pool(QueueType::Graphics)?;
+            let buffer = device.create_command_buffer(&pool)?;
 
-        let init_frame_data = |device: &Device| -> PerFrameData {
-            let pool =   device.create_command_pool(QueueType::Graphics);
-            let buffer = device.create_command_buffer(&pool);
+            let mut worker_command_pools = Vec::<WorkerCommandPool>::with_capacity(recording_worker_count);
+            for _ in 0..recording_worker_count {
+                worker_command_pools.push(WorkerCommandPool::new(device)?);
+            }
 
             let sizes: [PoolSizeRatio; 4] = [
                 PoolSizeRatio{descriptor_type: VK_DESCRIPTOR_TYPE_STORAGE_IMAGE,          ratio: 3.0 },
@@ -201,490 +670,3503 @@ impl RenderSystem {
                 PoolSizeRatio{descriptor_type: VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER, ratio: 4.0 },
             ];
 
-            PerFrameData{
-                command_buffer:      RefCell::new(PerFrameCommandBuffer {pool, handle: buffer}),
-                dynamic_descriptors: RefCell::new(DescriptorAllocatorGrowable::new(device, &sizes, 1000)),
-                deletion_queues:     RefCell::new(PerFrameDeletionQueues{
+            Ok(PerFrameData{
+                command_buffer:       RefCell::new(PerFrameCommandBuffer {pool, handle: buffer}),
+                worker_command_pools: RefCell::new(worker_command_pools),
+                dynamic_descriptors:  RefCell::new(DescriptorAllocatorGrowable::new(device, &sizes, 1000)?),
+                deletion_queues:      RefCell::new(PerFrameDeletionQueues{
                     buffer_deletion_queue: VecDeque::new(),
                     image_deletion_queue:  VecDeque::new(),
                 }),
-            }
+                culling_readback:     Cell::new(None),
+            })
         };
 
         let mut frame_data = Vec::<Rc<PerFrameData>>::with_capacity(swapchain.images.len());
         for i in 0..swapchain.images.len() {
-            frame_data.push(Rc::new(init_frame_data(&device)));
+            frame_data.push(Rc::new(init_frame_data(&device)?));
         }
 
         // Create immediate submission context
         //
 
-        let imm_fence:          VkFence       = device.create_fence(true);
-        let imm_command_pool:   CommandPool   = device.create_command_pool(QueueType::Graphics);
-        let imm_command_buffer: CommandBuffer = device.create_command_buffer(&imm_command_pool);
+        let imm_fence:          VkFence       = device.create_fence(true)?;
+        let imm_command_pool:   CommandPool   = device.create_command_pool(QueueType::Graphics)?;
+        let imm_command_buffer: CommandBuffer = device.create_command_buffer(&imm_command_pool)?;
 
         // Create descriptors
         //
 
-        let global_da = {
-            let sizes: [PoolSizeRatio; 1] = [
-                PoolSizeRatio{descriptor_type: VK_DESCRIPTOR_TYPE_STORAGE_IMAGE, ratio: 1.0 },
-            ];
-
-            device.create_descriptor_allocator(10, DescriptorAllocatorFlags::None, sizes.as_slice())
+        let gpu_global_scene_dl = {
+            let mut build = DescriptorLayoutBuilder::new();
+            build.add_binding(0, VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER);
+            build.add_binding(1, VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER); // sun shadow map
+            build.add_binding(2, VK_DESCRIPTOR_TYPE_STORAGE_BUFFER);         // point and spot lights
+            build.add_binding(3, VK_DESCRIPTOR_TYPE_STORAGE_BUFFER);         // light grid
+            build.add_binding(4, VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER); // irradiance cubemap
+            build.add_binding(5, VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER); // specular cubemap
+            // The compute stage is the deferred lighting pass
+            build.build(&device, VK_SHADER_STAGE_VERTEX_BIT | VK_SHADER_STAGE_FRAGMENT_BIT | VK_SHADER_STAGE_COMPUTE_BIT, 0)?
         };
 
-        let draw_image_dl = {
+        // Skybox Pipeline
+        //   Draws the environment behind the geometry, see environment.rs
+
+        let skybox_sm = load_shader_module(&device, "skybox", ShaderStage::Compute)?;
+
+        let skybox_dl = {
             let mut builder = DescriptorLayoutBuilder::new();
-            builder.add_binding(0, VK_DESCRIPTOR_TYPE_STORAGE_IMAGE);
+            builder.add_binding(0, VK_DESCRIPTOR_TYPE_STORAGE_IMAGE);          // scene color
+            builder.add_binding(1, VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER); // skybox cubemap
+            builder.build(&device, VK_SHADER_STAGE_COMPUTE_BIT, 0)?
+        };
 
-            builder.build(&device, VK_SHADER_STAGE_COMPUTE_BIT, 0)
+        let skybox_pl = {
+            let descriptors:    [VkDescriptorSetLayout; 1] = [ skybox_dl ];
+            let push_constants: [VkPushConstantRange; 1]   = [
+                make_push_constant_range(0, std::mem::size_of::<SkyboxPushConstants>() as u32, VK_SHADER_STAGE_COMPUTE_BIT),
+            ];
+
+            device.create_pipeline_layout(descriptors.as_slice(), push_constants.as_slice())?
         };
 
-        let draw_image_ds = {
-            let ds = device.allocate_descriptors(&global_da, draw_image_dl).expect("Failed to alloc descriptor set");
+        let skybox_p = device.create_compute_pipeline(skybox_sm, skybox_pl)?;
 
-            let mut writer = DescriptorWriter::new();
-            writer.write_storage_image(0, scene_image.view, VK_IMAGE_LAYOUT_GENERAL);
-            writer.update_set(&device, ds);
+        device.destroy_shader_module(skybox_sm);
 
-            ds
+        let cube_sampler = device.create_cube_sampler()?;
+
+        // Light Cull Pipeline
+        //   Assigns the point and spot lights to the clusters of the view frustum, see lights.rs
+
+        let light_cull_sm = load_shader_module(&device, "light_cull", ShaderStage::Compute)?;
+
+        let light_cull_dl = {
+            let mut builder = DescriptorLayoutBuilder::new();
+            builder.add_binding(0, VK_DESCRIPTOR_TYPE_STORAGE_BUFFER); // point and spot lights
+            builder.add_binding(1, VK_DESCRIPTOR_TYPE_STORAGE_BUFFER); // light grid
+            builder.build(&device, VK_SHADER_STAGE_COMPUTE_BIT, 0)?
         };
 
-        let gpu_global_scene_dl = {
-            let mut build = DescriptorLayoutBuilder::new();
-            build.add_binding(0, VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER);
-            build.build(&device, VK_SHADER_STAGE_VERTEX_BIT | VK_SHADER_STAGE_FRAGMENT_BIT, 0)
+        let light_cull_pl = {
+            let descriptors:    [VkDescriptorSetLayout; 1] = [ light_cull_dl ];
+            let push_constants: [VkPushConstantRange; 1]   = [
+                make_push_constant_range(0, std::mem::size_of::<LightCullPushConstants>() as u32, VK_SHADER_STAGE_COMPUTE_BIT),
+            ];
+
+            device.create_pipeline_layout(descriptors.as_slice(), push_constants.as_slice())?
         };
 
-        // The Compute Pipeline
-        //
+        let light_cull_p = device.create_compute_pipeline(light_cull_sm, light_cull_pl)?;
 
-        let gradient_sm = load_shader_module(&device, "gradient", ShaderStage::Compute);
+        device.destroy_shader_module(light_cull_sm);
 
-        let gradient_pl = {
-            let descriptors:    [VkDescriptorSetLayout; 1] = [ draw_image_dl ];
-            let push_constants: [VkPushConstantRange; 0]   = [];
+        // The grid doesn't depend on the screen size, so it is allocated once
+        let light_grid = device.create_buffer(CLUSTER_COUNT * CLUSTER_SIZE, VK_BUFFER_USAGE_STORAGE_BUFFER_BIT, VMA_MEMORY_USAGE_GPU_ONLY)?;
+
+        // Post-Processing Pipelines
+        //   Bloom, tonemapping and FXAA, see post_process.rs
 
-            device.create_pipeline_layout(descriptors.as_slice(), push_constants.as_slice())
+        let post_process_dl = {
+            let mut builder = DescriptorLayoutBuilder::new();
+            builder.add_binding(0, VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER); // source
+            builder.add_binding(1, VK_DESCRIPTOR_TYPE_STORAGE_IMAGE);          // target
+            builder.build(&device, VK_SHADER_STAGE_COMPUTE_BIT, 0)?
         };
 
-        let gradient_p = device.create_compute_pipeline(gradient_sm, gradient_pl);
+        let tonemap_dl = {
+            let mut builder = DescriptorLayoutBuilder::new();
+            builder.add_binding(0, VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER); // scene
+            builder.add_binding(1, VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER); // bloom
+            builder.add_binding(2, VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER); // color grading LUT
+            builder.add_binding(3, VK_DESCRIPTOR_TYPE_STORAGE_IMAGE);          // target
+            builder.build(&device, VK_SHADER_STAGE_COMPUTE_BIT, 0)?
+        };
 
-        device.destroy_shader_module(gradient_sm);
+        let create_post_process_layout = |descriptor_layout: VkDescriptorSetLayout| -> Result<VkPipelineLayout, RenderError> {
+            let descriptors:    [VkDescriptorSetLayout; 1] = [ descriptor_layout ];
+            let push_constants: [VkPushConstantRange; 1]   = [
+                make_push_constant_range(0, std::mem::size_of::<ComputePushConstants>() as u32, VK_SHADER_STAGE_COMPUTE_BIT),
+            ];
 
-        // Gradient Color Compute Effect
-        //
+            return device.create_pipeline_layout(descriptors.as_slice(), push_constants.as_slice());
+        };
 
-        let compute_effect_gradient = {
-            let gradient_color_sm = load_shader_module(&device, "gradient_color", ShaderStage::Compute);
+        let post_process_pl = create_post_process_layout(post_process_dl)?;
+        let tonemap_pl      = create_post_process_layout(tonemap_dl)?;
 
-            let gradient_color_pl = {
-                let descriptors:    [VkDescriptorSetLayout; 1] = [ draw_image_dl ];
-                let push_constants: [VkPushConstantRange; 1]   = [
-                    make_push_constant_range(0, std::mem::size_of::<ComputePushConstants>() as u32, VK_SHADER_STAGE_COMPUTE_BIT),
-                ];
+        let create_post_process_pipeline = |name: &str, layout: VkPipelineLayout| -> Result<VkPipeline, RenderError> {
+            let shader_module = load_shader_module(&device, name, ShaderStage::Compute)?;
+            let pipeline      = device.create_compute_pipeline(shader_module, layout);
 
-                device.create_pipeline_layout(descriptors.as_slice(), push_constants.as_slice())
-            };
+            device.destroy_shader_module(shader_module);
+            return pipeline;
+        };
 
-            let gradient_color_p = device.create_compute_pipeline(gradient_color_sm, gradient_color_pl);
+        let bloom_downsample_p = create_post_process_pipeline("bloom_downsample", post_process_pl)?;
+        let bloom_upsample_p   = create_post_process_pipeline("bloom_upsample",   post_process_pl)?;
+        let tonemap_p          = create_post_process_pipeline("tonemap",          tonemap_pl)?;
+        let fxaa_p             = create_post_process_pipeline("fxaa",             post_process_pl)?;
 
-            device.destroy_shader_module(gradient_color_sm);
+        // SSAO Pipelines
+        //   The AO and its blur read one image and write another, like the post-processing passes. Applying it
+        //   reads the ambient target, the AO and the scene depth, and writes the scene. See ssao.rs
 
-            ComputeEffect{
-                name:      String::from("Gradient Effect"),
-               	pipeline:  gradient_color_p,
-               	layout:    gradient_color_pl,
-               	push_data: ComputePushConstants{
-                    data1: Float4::new(1.0, 0.0, 0.0, 1.0),
-                    data2: Float4::new(0.0, 0.0, 1.0, 1.0),
-                    data3: Float4::zero(),
-                    data4: Float4::zero(),
-                },
-            }
+        let ssao_apply_dl = {
+            let mut builder = DescriptorLayoutBuilder::new();
+            builder.add_binding(0, VK_DESCRIPTOR_TYPE_STORAGE_IMAGE);          // scene color
+            builder.add_binding(1, VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER); // ambient
+            builder.add_binding(2, VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER); // AO
+            builder.add_binding(3, VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER); // scene depth
+            builder.build(&device, VK_SHADER_STAGE_COMPUTE_BIT, 0)?
         };
 
-        // Sky Compute Effect
-        //
+        let ssao_apply_pl = create_post_process_layout(ssao_apply_dl)?;
 
-        let sky_effect = {
-            let sky_sm = load_shader_module(&device, "sky", ShaderStage::Compute);
+        let ssao_p       = create_post_process_pipeline("ssao",       post_process_pl)?;
+        let ssao_blur_p  = create_post_process_pipeline("ssao_blur",  post_process_pl)?;
+        let ssao_apply_p = create_post_process_pipeline("ssao_apply", ssao_apply_pl)?;
 
-            let sky_pl = {
-                let descriptors:    [VkDescriptorSetLayout; 1] = [ draw_image_dl ];
-                let push_constants: [VkPushConstantRange; 1]   = [
-                    make_push_constant_range(0, std::mem::size_of::<ComputePushConstants>() as u32, VK_SHADER_STAGE_COMPUTE_BIT),
-                ];
+        // Debug Line Pipelines
+        //   Lines from the DebugDraw API, drawn over the tonemapped image. The vertices are read through a buffer
+        //   device address, so there's no vertex input or descriptor set.
 
-                device.create_pipeline_layout(descriptors.as_slice(), push_constants.as_slice())
-            };
+        let debug_line_vert_sm = load_shader_module(&device, "debug_line", ShaderStage::Vertex)?;
+        let debug_line_frag_sm = load_shader_module(&device, "debug_line", ShaderStage::Fragment)?;
 
-            let sky_p = device.create_compute_pipeline(sky_sm, sky_pl);
+        let debug_line_pl = {
+            let descriptors:    [VkDescriptorSetLayout; 0] = [];
+            let push_constants: [VkPushConstantRange;   1] = [
+                make_push_constant_range(0, std::mem::size_of::<DebugLinePushConstants>() as u32, VK_SHADER_STAGE_VERTEX_BIT | VK_SHADER_STAGE_FRAGMENT_BIT),
+            ];
 
-            device.destroy_shader_module(sky_sm);
+            device.create_pipeline_layout(descriptors.as_slice(), push_constants.as_slice())?
+        };
 
-            ComputeEffect{
-                name:      String::from("Sky"),
-               	pipeline:  sky_p,
-               	layout:    sky_pl,
-               	push_data: ComputePushConstants{
-                    data1: Float4::new(0.1, 0.2, 0.4 ,0.97),
-                    data2: Float4::zero(),
-                    data3: Float4::zero(),
-                    data4: Float4::zero(),
-                },
+        let create_debug_line_pipeline = |depth_test: bool| -> Result<VkPipeline, RenderError> {
+            let mut builder = GraphicsPipelineBuilder::new();
+            builder
+                .set_pipeline_layout(debug_line_pl)
+                .set_shaders(debug_line_vert_sm, debug_line_frag_sm)
+                .set_input_topology(VK_PRIMITIVE_TOPOLOGY_LINE_LIST)
+                .set_polygon_mode(VK_POLYGON_MODE_FILL)
+                .set_cull_mode(VK_CULL_MODE_NONE, VK_FRONT_FACE_CLOCKWISE)
+                .set_multisampling_none()
+                .enabled_blending_alphablend()
+                .set_color_attachment_format(SCENE_IMAGE_FORMAT)
+                .set_depth_format(device.get_depth_format());
+
+            if depth_test {
+                builder.enable_depth_test(false, VK_COMPARE_OP_LESS_OR_EQUAL);
+            } else {
+                builder.disable_depth_test();
             }
+
+            return builder.build(&device);
         };
 
-        // Colored Triangle Pipeline
-        //
+        let debug_line_p         = create_debug_line_pipeline(true)?;
+        let debug_line_overlay_p = create_debug_line_pipeline(false)?;
+
+        device.destroy_shader_module(debug_line_vert_sm);
+        device.destroy_shader_module(debug_line_frag_sm);
 
-        let colored_tri_vert_sm = load_shader_module(&device, "colored_triangle", ShaderStage::Vertex);
-        let colored_tri_frag_sm = load_shader_module(&device, "colored_triangle", ShaderStage::Fragment);
+        // GPU-Driven Geometry Pipelines
+        //   Culls the draw list into indirect draw commands, and builds the Hi-Z pyramid the culling reads. See
+        //   culling.rs
 
-        //todo:VkDescriptorSetLayout _singleImageDescriptorLayout;
-        let single_image_dl = {
+        let draw_cull_sm = load_shader_module(&device, "draw_cull", ShaderStage::Compute)?;
+
+        let draw_cull_dl = {
             let mut builder = DescriptorLayoutBuilder::new();
-            builder.add_binding(0, VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER);
-            builder.build(&device, VK_SHADER_STAGE_FRAGMENT_BIT, 0)
+            builder.add_binding(0, VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER);         // DrawCullData
+            builder.add_binding(1, VK_DESCRIPTOR_TYPE_STORAGE_BUFFER);         // objects
+            builder.add_binding(2, VK_DESCRIPTOR_TYPE_STORAGE_BUFFER);         // batches
+            builder.add_binding(3, VK_DESCRIPTOR_TYPE_STORAGE_BUFFER);         // draw commands
+            builder.add_binding(4, VK_DESCRIPTOR_TYPE_STORAGE_BUFFER);         // draw counts
+            builder.add_binding(5, VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER); // Hi-Z pyramid
+            builder.build(&device, VK_SHADER_STAGE_COMPUTE_BIT, 0)?
+        };
+
+        let draw_cull_pl = {
+            let descriptors:    [VkDescriptorSetLayout; 1] = [ draw_cull_dl ];
+            let push_constants: [VkPushConstantRange; 0]   = [];
+
+            device.create_pipeline_layout(descriptors.as_slice(), push_constants.as_slice())?
         };
 
-        let triangle_pl = {
-            let descriptors:    [VkDescriptorSetLayout; 1] = [ single_image_dl ];
+        let draw_cull_p = device.create_compute_pipeline(draw_cull_sm, draw_cull_pl)?;
+
+        device.destroy_shader_module(draw_cull_sm);
+
+        let hiz_build_p = create_post_process_pipeline("hiz_build", post_process_pl)?;
+        let hiz         = HiZPyramid::new(&device, swapchain.get_extent())?;
+
+        if !device.supports_gpu_driven_rendering() {
+            println!("[INFO] :: RenderSystem :: The GPU can't draw indirect with a count, geometry is culled and drawn on the CPU.");
+        }
+
+        // Lit Mesh Pipeline
+        //
+
+        // One set for every material and texture, bound once per frame
+        let mut bindless = BindlessSet::new(&device)?;
+
+        let mesh_pl = {
+            let descriptors:    [VkDescriptorSetLayout; 2] = [ gpu_global_scene_dl, bindless.layout ];
             let push_constants: [VkPushConstantRange;   1] = [
                 VkPushConstantRange{
-                    stageFlags: VK_SHADER_STAGE_VERTEX_BIT,
+                    stageFlags: VK_SHADER_STAGE_VERTEX_BIT | VK_SHADER_STAGE_FRAGMENT_BIT, // the fragment stage reads the material index
                     offset:     0,
                     size:       std::mem::size_of::<GpuDrawPushConstants>() as u32,
                 },
             ];
 
-            device.create_pipeline_layout(descriptors.as_slice(), push_constants.as_slice())
-        };
+            device.create_pipeline_layout(descriptors.as_slice(), push_constants.as_slice())?
+        };
+
+        let msaa_samples = device.get_sample_count(DEFAULT_MSAA_SAMPLES);
+        let mesh_p       = RenderSystem::create_mesh_pipeline(&device, mesh_pl, "mesh", msaa_samples)?;
+
+        let skinned_mesh_p = RenderSystem::create_mesh_pipeline(&device, mesh_pl, "skinned_mesh", msaa_samples)?;
+
+        // Transparency Pipelines
+        //   Meshes with blended materials, drawn after the skybox either sorted and alpha blended or with weighted
+        //   blended OIT, which the composite pass then blends over the scene. See transparency.rs
+
+        let transparent_mesh_p         = RenderSystem::create_transparent_mesh_pipeline(&device, mesh_pl, "mesh", false)?;
+        let transparent_skinned_mesh_p = RenderSystem::create_transparent_mesh_pipeline(&device, mesh_pl, "skinned_mesh", false)?;
+        let oit_mesh_p                 = RenderSystem::create_transparent_mesh_pipeline(&device, mesh_pl, "mesh", true)?;
+        let oit_skinned_mesh_p         = RenderSystem::create_transparent_mesh_pipeline(&device, mesh_pl, "skinned_mesh", true)?;
+
+        let oit_composite_sm = load_shader_module(&device, "oit_composite", ShaderStage::Compute)?;
+
+        let oit_composite_dl = {
+            let mut builder = DescriptorLayoutBuilder::new();
+            builder.add_binding(0, VK_DESCRIPTOR_TYPE_STORAGE_IMAGE);          // scene color
+            builder.add_binding(1, VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER); // accumulation
+            builder.add_binding(2, VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER); // revealage
+            builder.build(&device, VK_SHADER_STAGE_COMPUTE_BIT, 0)?
+        };
+
+        let oit_composite_pl = {
+            let descriptors:    [VkDescriptorSetLayout; 1] = [ oit_composite_dl ];
+            let push_constants: [VkPushConstantRange;   0] = [];
+
+            device.create_pipeline_layout(descriptors.as_slice(), push_constants.as_slice())?
+        };
+
+        let oit_composite_p = device.create_compute_pipeline(oit_composite_sm, oit_composite_pl)?;
+
+        device.destroy_shader_module(oit_composite_sm);
+
+        // Deferred Shading Pipelines
+        //   The G-buffer variants of the mesh pipelines, and the compute pass that lights the G-buffer. The lighting
+        //   reads the lit meshes' scene set, with the G-buffer in a second set. See deferred.rs
+
+        let gbuffer_mesh_p         = RenderSystem::create_gbuffer_mesh_pipeline(&device, mesh_pl, "mesh")?;
+        let gbuffer_skinned_mesh_p = RenderSystem::create_gbuffer_mesh_pipeline(&device, mesh_pl, "skinned_mesh")?;
+
+        let deferred_lighting_sm = load_shader_module(&device, "deferred_lighting", ShaderStage::Compute)?;
+
+        let deferred_lighting_dl = {
+            let mut builder = DescriptorLayoutBuilder::new();
+            builder.add_binding(0, VK_DESCRIPTOR_TYPE_STORAGE_IMAGE);          // scene color
+            builder.add_binding(1, VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER); // albedo
+            builder.add_binding(2, VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER); // normals
+            builder.add_binding(3, VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER); // material
+            builder.add_binding(4, VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER); // scene depth
+            builder.add_binding(5, VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER); // SSAO
+            builder.build(&device, VK_SHADER_STAGE_COMPUTE_BIT, 0)?
+        };
+
+        let deferred_lighting_pl = {
+            let descriptors:    [VkDescriptorSetLayout; 2] = [ gpu_global_scene_dl, deferred_lighting_dl ];
+            let push_constants: [VkPushConstantRange;   1] = [
+                make_push_constant_range(0, std::mem::size_of::<DeferredLightingPushConstants>() as u32, VK_SHADER_STAGE_COMPUTE_BIT),
+            ];
+
+            device.create_pipeline_layout(descriptors.as_slice(), push_constants.as_slice())?
+        };
+
+        let deferred_lighting_p = device.create_compute_pipeline(deferred_lighting_sm, deferred_lighting_pl)?;
+
+        device.destroy_shader_module(deferred_lighting_sm);
+
+        if create_info.render_path == RenderPath::Deferred {
+            println!("[INFO] :: RenderSystem :: Using deferred shading, the geometry pass doesn't use MSAA.");
+        }
+
+        // Particle Pipelines
+        //   The simulation is a compute pass over each emitter's particle buffer, and the draw expands every particle
+        //   into a billboard in the HDR scene. Both read the particles and emitters through buffer device addresses,
+        //   and the draw reads the texture sheets from the bindless set. See particles.rs
+
+        let particle_sim_sm = load_shader_module(&device, "particle_sim", ShaderStage::Compute)?;
+
+        let particle_sim_pl = {
+            let descriptors:    [VkDescriptorSetLayout; 0] = [];
+            let push_constants: [VkPushConstantRange;   1] = [
+                make_push_constant_range(0, std::mem::size_of::<ParticleSimPushConstants>() as u32, VK_SHADER_STAGE_COMPUTE_BIT),
+            ];
+
+            device.create_pipeline_layout(descriptors.as_slice(), push_constants.as_slice())?
+        };
+
+        let particle_sim_p = device.create_compute_pipeline(particle_sim_sm, particle_sim_pl)?;
+
+        device.destroy_shader_module(particle_sim_sm);
+
+        let particle_vert_sm = load_shader_module(&device, "particle", ShaderStage::Vertex)?;
+        let particle_frag_sm = load_shader_module(&device, "particle", ShaderStage::Fragment)?;
+
+        let particle_pl = {
+            let descriptors:    [VkDescriptorSetLayout; 1] = [ bindless.layout ];
+            let push_constants: [VkPushConstantRange;   1] = [
+                make_push_constant_range(0, std::mem::size_of::<ParticleDrawPushConstants>() as u32, VK_SHADER_STAGE_VERTEX_BIT | VK_SHADER_STAGE_FRAGMENT_BIT),
+            ];
+
+            device.create_pipeline_layout(descriptors.as_slice(), push_constants.as_slice())?
+        };
+
+        let create_particle_pipeline = |blend: ParticleBlend| -> Result<VkPipeline, RenderError> {
+            let mut builder = GraphicsPipelineBuilder::new();
+            builder
+                .set_pipeline_layout(particle_pl)
+                .set_shaders(particle_vert_sm, particle_frag_sm)
+                .set_input_topology(VK_PRIMITIVE_TOPOLOGY_TRIANGLE_LIST)
+                .set_polygon_mode(VK_POLYGON_MODE_FILL)
+                .set_cull_mode(VK_CULL_MODE_NONE, VK_FRONT_FACE_CLOCKWISE)
+                .set_multisampling_none()
+                .enable_depth_test(false, VK_COMPARE_OP_LESS_OR_EQUAL)
+                .set_color_attachment_format(SCENE_IMAGE_FORMAT)
+                .set_depth_format(device.get_depth_format());
+
+            match blend {
+                ParticleBlend::Additive   => builder.enabled_blending_additive(),
+                ParticleBlend::AlphaBlend => builder.enabled_blending_alphablend(),
+            };
+
+            return builder.build(&device);
+        };
+
+        let particle_additive_p = create_particle_pipeline(ParticleBlend::Additive)?;
+        let particle_alpha_p    = create_particle_pipeline(ParticleBlend::AlphaBlend)?;
+
+        device.destroy_shader_module(particle_vert_sm);
+        device.destroy_shader_module(particle_frag_sm);
+
+        // Sprite Pipeline
+        //   Alpha blended quads over the tonemapped image. The sprites are read through a buffer device address and
+        //   the textures from the bindless set, which is the only set.
+
+        let sprite_vert_sm = load_shader_module(&device, "sprite", ShaderStage::Vertex)?;
+        let sprite_frag_sm = load_shader_module(&device, "sprite", ShaderStage::Fragment)?;
+
+        let sprite_pl = {
+            let descriptors:    [VkDescriptorSetLayout; 1] = [ bindless.layout ];
+            let push_constants: [VkPushConstantRange;   1] = [
+                make_push_constant_range(0, std::mem::size_of::<SpritePushConstants>() as u32, VK_SHADER_STAGE_VERTEX_BIT | VK_SHADER_STAGE_FRAGMENT_BIT),
+            ];
+
+            device.create_pipeline_layout(descriptors.as_slice(), push_constants.as_slice())?
+        };
+
+        let sprite_p = {
+            let mut builder = GraphicsPipelineBuilder::new();
+            builder
+                .set_pipeline_layout(sprite_pl)
+                .set_shaders(sprite_vert_sm, sprite_frag_sm)
+                .set_input_topology(VK_PRIMITIVE_TOPOLOGY_TRIANGLE_LIST)
+                .set_polygon_mode(VK_POLYGON_MODE_FILL)
+                .set_cull_mode(VK_CULL_MODE_NONE, VK_FRONT_FACE_CLOCKWISE) // flipped sprites are mirrored quads
+                .set_multisampling_none()
+                .enabled_blending_alphablend()
+                .disable_depth_test()
+                .set_color_attachment_format(SCENE_IMAGE_FORMAT);
+
+            builder.build(&device)?
+        };
+
+        device.destroy_shader_module(sprite_vert_sm);
+        device.destroy_shader_module(sprite_frag_sm);
+
+        // Text Pipelines
+        //   Alpha blended glyph quads over the tonemapped image, like the sprites. World text is tested against the
+        //   scene depth without writing to it, so labels are hidden behind geometry but never hide each other.
+
+        let text_vert_sm = load_shader_module(&device, "text", ShaderStage::Vertex)?;
+        let text_frag_sm = load_shader_module(&device, "text", ShaderStage::Fragment)?;
+
+        let text_pl = {
+            let descriptors:    [VkDescriptorSetLayout; 1] = [ bindless.layout ];
+            let push_constants: [VkPushConstantRange;   1] = [
+                make_push_constant_range(0, std::mem::size_of::<TextPushConstants>() as u32, VK_SHADER_STAGE_VERTEX_BIT | VK_SHADER_STAGE_FRAGMENT_BIT),
+            ];
+
+            device.create_pipeline_layout(descriptors.as_slice(), push_constants.as_slice())?
+        };
+
+        let create_text_pipeline = |depth_test: bool| -> Result<VkPipeline, RenderError> {
+            let mut builder = GraphicsPipelineBuilder::new();
+            builder
+                .set_pipeline_layout(text_pl)
+                .set_shaders(text_vert_sm, text_frag_sm)
+                .set_input_topology(VK_PRIMITIVE_TOPOLOGY_TRIANGLE_LIST)
+                .set_polygon_mode(VK_POLYGON_MODE_FILL)
+                .set_cull_mode(VK_CULL_MODE_NONE, VK_FRONT_FACE_CLOCKWISE) // world text is readable from behind
+                .set_multisampling_none()
+                .enabled_blending_alphablend()
+                .set_color_attachment_format(SCENE_IMAGE_FORMAT)
+                .set_depth_format(device.get_depth_format());
+
+            if depth_test {
+                builder.enable_depth_test(false, VK_COMPARE_OP_LESS_OR_EQUAL);
+            } else {
+                builder.disable_depth_test();
+            }
+
+            return builder.build(&device);
+        };
+
+        let text_p       = create_text_pipeline(false)?;
+        let text_world_p = create_text_pipeline(true)?;
+
+        device.destroy_shader_module(text_vert_sm);
+        device.destroy_shader_module(text_frag_sm);
+
+        // Shadow Depth Pipeline
+        //   Renders the sun's shadow cascades. Depth only, and the depth bias is set per frame from the
+        //   ShadowSettings so it can be tuned at runtime. Meshes with Mask materials use the alpha tested variants,
+        //   which read the material's albedo alpha from the bindless set.
+
+        let shadow_depth_vert_sm                    = load_shader_module(&device, "shadow_depth", ShaderStage::Vertex)?;
+        let skinned_shadow_depth_vert_sm            = load_shader_module(&device, "skinned_shadow_depth", ShaderStage::Vertex)?;
+        let shadow_depth_alpha_test_vert_sm         = load_shader_module(&device, "shadow_depth_alpha_test", ShaderStage::Vertex)?;
+        let skinned_shadow_depth_alpha_test_vert_sm = load_shader_module(&device, "skinned_shadow_depth_alpha_test", ShaderStage::Vertex)?;
+        let shadow_alpha_test_frag_sm               = load_shader_module(&device, "shadow_alpha_test", ShaderStage::Fragment)?;
+
+        let shadow_depth_pl = {
+            let descriptors:    [VkDescriptorSetLayout; 1] = [ bindless.layout ];
+            let push_constants: [VkPushConstantRange;   1] = [
+                make_push_constant_range(0, std::mem::size_of::<GpuDrawPushConstants>() as u32, VK_SHADER_STAGE_VERTEX_BIT | VK_SHADER_STAGE_FRAGMENT_BIT),
+            ];
+
+            device.create_pipeline_layout(descriptors.as_slice(), push_constants.as_slice())?
+        };
+
+        let create_shadow_depth_pipeline = |vertex_shader: VkShaderModule, alpha_test_shader: Option<VkShaderModule>| -> Result<VkPipeline, RenderError> {
+            let mut builder = GraphicsPipelineBuilder::new();
+
+            match alpha_test_shader {
+                Some(fragment_shader) => builder.set_shaders(vertex_shader, fragment_shader),
+                None                  => builder.set_vertex_shader(vertex_shader),
+            };
+
+            builder
+                .set_pipeline_layout(shadow_depth_pl)
+                .set_input_topology(VK_PRIMITIVE_TOPOLOGY_TRIANGLE_LIST)
+                .set_polygon_mode(VK_POLYGON_MODE_FILL)
+                .set_cull_mode(VK_CULL_MODE_BACK_BIT, VK_FRONT_FACE_CLOCKWISE)
+                .enable_dynamic_depth_bias()
+                .set_multisampling_none()
+                .enable_depth_test(true, VK_COMPARE_OP_LESS_OR_EQUAL)
+                .set_no_color_attachment()
+                .set_depth_format(SHADOW_MAP_FORMAT);
+
+            return builder.build(&device);
+        };
+
+        let shadow_depth_p                    = create_shadow_depth_pipeline(shadow_depth_vert_sm, None)?;
+        let skinned_shadow_depth_p            = create_shadow_depth_pipeline(skinned_shadow_depth_vert_sm, None)?;
+        let shadow_depth_alpha_test_p         = create_shadow_depth_pipeline(shadow_depth_alpha_test_vert_sm, Some(shadow_alpha_test_frag_sm))?;
+        let skinned_shadow_depth_alpha_test_p = create_shadow_depth_pipeline(skinned_shadow_depth_alpha_test_vert_sm, Some(shadow_alpha_test_frag_sm))?;
+
+        device.destroy_shader_module(shadow_depth_vert_sm);
+        device.destroy_shader_module(skinned_shadow_depth_vert_sm);
+        device.destroy_shader_module(shadow_depth_alpha_test_vert_sm);
+        device.destroy_shader_module(skinned_shadow_depth_alpha_test_vert_sm);
+        device.destroy_shader_module(shadow_alpha_test_frag_sm);
+
+        // Some Default samplers
+        //
+
+        let nearest_sampler = device.create_sampler(VK_FILTER_NEAREST, VK_FILTER_NEAREST)?;
+        let linear_sampler  = device.create_sampler(VK_FILTER_LINEAR,  VK_FILTER_LINEAR)?;
+        let shadow_sampler  = device.create_shadow_sampler()?;
+        let post_sampler    = device.create_clamped_sampler(VK_FILTER_LINEAR)?;
+
+        bindless.write_samplers(&device, [linear_sampler, nearest_sampler]);
+
+        // Default lighting, a warm sun coming in at an angle. The sky gradient of make_default_environment() lights the
+        // scene until the engine sends an environment.
+        let mut scene_data = GlobalSceneData::default();
+        scene_data.ambient_color  = Float4::new(0.3, 0.3, 0.3, 1.0);
+        scene_data.sunlight_dir   = Float4::new(-0.4, -1.0, -0.3, 0.0);
+        scene_data.sunlight_color = Float4::new(1.0, 0.95, 0.85, 1.0);
+
+        // Setup imgui
+        //
+        //let editor_data = device.create_imgui_editor(swapchain.get_image_count() as u32);
+
+        let mut result = RenderSystem{
+            create_info,
+            device,
+            swapchain,
+            frame_data,
+            frame_index: 0,
+            recording_workers,
+            transient_images: RefCell::new(TransientImagePool::default()),
+            scene_data,
+            global_scene_dl: gpu_global_scene_dl,
+            imm_fence,
+            imm_command_pool,
+            imm_command_buffer,
+            //editor_data,
+            environment:              GpuEnvironment::default(),
+            retained_environment:     EnvironmentCubes::default(),
+            environment_loader,
+            skybox_settings:          SkyboxSettings::default(),
+            skybox_dl,
+            skybox_pl,
+            skybox_p,
+            cube_sampler,
+            mesh_pl,
+            mesh_p,
+            skinned_mesh_p,
+            deferred_settings:        DeferredSettings::default(),
+            gbuffer_mesh_p,
+            gbuffer_skinned_mesh_p,
+            deferred_lighting_dl,
+            deferred_lighting_pl,
+            deferred_lighting_p,
+            transparency_settings:    TransparencySettings::default(),
+            transparent_mesh_p,
+            transparent_skinned_mesh_p,
+            oit_mesh_p,
+            oit_skinned_mesh_p,
+            oit_composite_dl,
+            oit_composite_pl,
+            oit_composite_p,
+            culling_settings:         GpuCullingSettings::default(),
+            culling_stats:            Cell::new(CullingStats::default()),
+            draw_cull_dl,
+            draw_cull_pl,
+            draw_cull_p,
+            hiz_build_p,
+            hiz,
+            msaa_samples,
+            requested_msaa_samples:   DEFAULT_MSAA_SAMPLES,
+            ssao_settings:            SsaoSettings::default(),
+            ssao_p,
+            ssao_blur_p,
+            ssao_apply_dl,
+            ssao_apply_pl,
+            ssao_apply_p,
+            shadow_settings:          ShadowSettings::default(),
+            shadow_depth_pl,
+            shadow_depth_p,
+            skinned_shadow_depth_p,
+            shadow_depth_alpha_test_p,
+            skinned_shadow_depth_alpha_test_p,
+            shadow_sampler,
+            point_lights:             Vec::new(),
+            spot_lights:              Vec::new(),
+            light_cull_dl,
+            light_cull_pl,
+            light_cull_p,
+            light_grid,
+            post_settings:            PostProcessSettings::default(),
+            post_process_dl,
+            tonemap_dl,
+            post_process_pl,
+            tonemap_pl,
+            bloom_downsample_p,
+            bloom_upsample_p,
+            tonemap_p,
+            fxaa_p,
+            post_sampler,
+            color_grading_lut:        AllocatedImage::default(),
+            retained_lut:             ColorGradingLut::identity(DEFAULT_LUT_SIZE),
+            particle_emitters:        Vec::new(),
+            particle_settings:        ParticleSettings::default(),
+            particle_clock:           None,
+            particle_sim_pl,
+            particle_sim_p,
+            particle_pl,
+            particle_additive_p,
+            particle_alpha_p,
+            sprites:                  Vec::new(),
+            sprite_camera:            Float4x4::identity(),
+            sprite_settings:          SpriteSettings::default(),
+            sprite_pl,
+            sprite_p,
+            text:                     Vec::new(),
+            text_pl,
+            text_p,
+            text_world_p,
+            debug_lines:              DebugLines::default(),
+            debug_line_pl,
+            debug_line_p,
+            debug_line_overlay_p,
+            meshes:                   [GpuMeshBuffers::default(); MAX_LOADED_MESHES],
+            mesh_count:               0,
+            retained_meshes:          Vec::new(),
+            lod_settings:             LodSettings::default(),
+            instanced_meshes:          Vec::new(),
+            retained_instanced_meshes: Vec::new(),
+            default_instance_buffer:   AllocatedBuffer::default(),
+            default_instance_address:  0,
+            skinned_meshes:            Vec::new(),
+            retained_skinned_meshes:   Vec::new(),
+            white_image:              AllocatedImage::default(),
+            black_image:              AllocatedImage::default(),
+            grey_image:               AllocatedImage::default(),
+            error_checkerboard_image: AllocatedImage::default(),
+            flat_normal_image:        AllocatedImage::default(),
+            textures:                 Vec::new(),
+            retained_textures:        Vec::new(),
+            white_texture:            TextureId::default(),
+            flat_normal_texture:      TextureId::default(),
+            error_texture:            TextureId::default(),
+            default_sampler_linear:   linear_sampler,
+            default_sampler_nearest:  nearest_sampler,
+            bindless,
+            materials:                Vec::new(),
+            retained_materials:       Vec::new(),
+            view_matrix:              Float4x4::identity(),
+            perspective_matrix:       Float4x4::identity(),
+            outgoing_commands:        RenderCommandBuffer::default(),
+            simulate_device_lost:     false,
+            is_destroyed:             false,
+            render_graph_dump_path:   None,
+        };
+
+        // Let's create some test images
+        //
+
+        let white_image = {
+            let packed_color = Float4::one().pack_unorm_u32();
+            let packed_ptr = (&packed_color as *const u32) as *const u8;
+
+            result.upload_image(packed_ptr, VkExtent3D{ width: 1, height: 1, depth: 1 }, VK_FORMAT_R8G8B8A8_UNORM, VK_IMAGE_USAGE_SAMPLED_BIT, true)?
+        };
+
+        let grey_image = {
+            let packed_color = Float4::new(0.66, 0.66, 0.66, 1.0).pack_unorm_u32();
+            let packed_ptr = (&packed_color as *const u32) as *const u8;
+
+            result.upload_image(packed_ptr, VkExtent3D{ width: 1, height: 1, depth: 1 }, VK_FORMAT_R8G8B8A8_UNORM, VK_IMAGE_USAGE_SAMPLED_BIT, true)?
+        };
+
+        let black_image = {
+            let packed_color = Float4::zero().pack_unorm_u32();
+            let packed_ptr = (&packed_color as *const u32) as *const u8;
+
+            result.upload_image(packed_ptr, VkExtent3D{ width: 1, height: 1, depth: 1 }, VK_FORMAT_R8G8B8A8_UNORM, VK_IMAGE_USAGE_SAMPLED_BIT, true)?
+        };
+
+        let checkerboard = {
+            let packed_magenta = Float4::new(1.0, 0.0, 1.0, 1.0).pack_unorm_u32();
+            let packed_black   = Float4::zero().pack_unorm_u32();
+
+            let mut pixels: [u32; 16*16] = [0; 16*16];
+            for x in 0..16 {
+          		for y in 0..16 {
+         			pixels[y*16 + x] = if ((x % 2) ^ (y % 2) > 0) { packed_magenta } else { packed_black };
+          		}
+            }
+
+            result.upload_image(pixels.as_ptr() as *const u8, VkExtent3D{ width: 16, height: 16, depth: 1 }, VK_FORMAT_R8G8B8A8_UNORM, VK_IMAGE_USAGE_SAMPLED_BIT, true)?
+        };
+
+        // Tangent-space normal pointing straight out of the surface
+        let flat_normal_image = {
+            let packed_normal = Float4::new(0.5, 0.5, 1.0, 1.0).pack_unorm_u32();
+            let packed_ptr = (&packed_normal as *const u32) as *const u8;
+
+            result.upload_image(packed_ptr, VkExtent3D{ width: 1, height: 1, depth: 1 }, VK_FORMAT_R8G8B8A8_UNORM, VK_IMAGE_USAGE_SAMPLED_BIT, true)?
+        };
+
+        result.white_image = white_image;
+        result.grey_image  = grey_image;
+        result.black_image = black_image;
+        result.error_checkerboard_image = checkerboard;
+        result.flat_normal_image        = flat_normal_image;
+
+        // The texture array starts out empty, so these always fit
+        result.white_texture       = result.bindless.add_texture(&result.device, result.white_image.view).unwrap();
+        result.flat_normal_texture = result.bindless.add_texture(&result.device, result.flat_normal_image.view).unwrap();
+        result.error_texture       = result.bindless.add_texture(&result.device, result.error_checkerboard_image.view).unwrap();
+
+        let (default_instance_buffer, default_instance_address) = result.upload_instances(&[MeshInstance::default()])?;
+        result.default_instance_buffer  = default_instance_buffer;
+        result.default_instance_address = default_instance_address;
+
+        let identity_lut = ColorGradingLut::identity(DEFAULT_LUT_SIZE);
+        result.color_grading_lut = result.upload_color_grading_lut(&identity_lut)?;
+
+        // The default sky is built on the loader, the placeholder lights the first frames
+        result.set_environment(build_environment(&make_placeholder_environment()))?;
+        result.environment_loader.request(make_default_environment());
+
+        // The default material, used by meshes without one
+        let default_material = result.create_material(&CreateMaterialInfo::default()).expect("the material buffer starts out empty");
+        result.materials.push(default_material);
+
+        return Ok(result);
+    }
+
+    fn get_frame_data(&self) -> Rc<PerFrameData> {
+        self.frame_data[self.swapchain.frame_index].clone()
+    }
+
+    /// Uploads this frame's scene data to a transient uniform buffer and allocates the scene set of the lit mesh
+    /// pipelines around it.
+    fn allocate_scene_set(&self, shadow_map: GraphImage, lights: VkBuffer) -> Result<VkDescriptorSet, RenderError> {
+        // This is, like, definately not how I want to do this.
+        let frame_data = self.get_frame_data();
+        let mut deletion_queues = frame_data.deletion_queues.borrow_mut();
+        let mut dyn_descriptors = frame_data.dynamic_descriptors.borrow_mut();
+
+        let scene_data = self.device.create_buffer(std::mem::size_of::<GlobalSceneData>(), VK_BUFFER_USAGE_UNIFORM_BUFFER_BIT, VMA_MEMORY_USAGE_CPU_TO_GPU)?;
+        deletion_queues.buffer_deletion_queue.push_back(scene_data);
+
+        // Copy the scene data to the gpu buffer
+        let mut memory = scene_data.get_allocation();
+        assert!(memory != ptr::null_mut());
+
+        let mut memory_as_scene = memory as *mut GlobalSceneData;
+        unsafe { std::ptr::copy(&self.scene_data, memory_as_scene, 1) };
+
+        //create a descriptor set that binds that buffer and update it
+        let global_ds = dyn_descriptors.allocate(&self.device, self.global_scene_dl)?;
+
+        let mut writer = DescriptorWriter::new();
+        writer.write_buffer(0, scene_data.buffer, std::mem::size_of::<GlobalSceneData>() as u64, 0, VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER);
+        writer.write_combined_image_sampler(1, shadow_map.view, self.shadow_sampler, VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL);
+        writer.write_buffer(2, lights, VK_WHOLE_SIZE as VkDeviceSize, 0, VK_DESCRIPTOR_TYPE_STORAGE_BUFFER);
+        writer.write_buffer(3, self.light_grid.buffer, VK_WHOLE_SIZE as VkDeviceSize, 0, VK_DESCRIPTOR_TYPE_STORAGE_BUFFER);
+        writer.write_combined_image_sampler(4, self.environment.irradiance.view, self.cube_sampler, VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL);
+        writer.write_combined_image_sampler(5, self.environment.specular.view, self.cube_sampler, VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL);
+        writer.update_set(&self.device, global_ds);
+
+        return Ok(global_ds);
+    }
+
+    /// Renders every opaque mesh into `color_image` and `depth_image`. The color is cleared to transparent black, so the
+    /// alpha holds the coverage and the background can be composited underneath. With MSAA, the targets are
+    /// multisampled and are resolved into `resolve_images` (color, depth). `ambient_images` is the ambient target and,
+    /// with MSAA, its resolve target. Without them, SSAO is off and the ambient output is dropped.
+    fn draw_geometry(&self, cmd_buffer: &mut CommandBuffer, color_image: GraphImage, depth_image: GraphImage, resolve_images: Option<(GraphImage, GraphImage)>, ambient_images: Option<(GraphImage, Option<GraphImage>)>, shadow_map: GraphImage, lights: VkBuffer, draw_buffers: Option<&GpuDrawBuffers>) -> Result<(), RenderError> {
+        let global_ds = self.allocate_scene_set(shadow_map, lights)?;
+
+        let clear_color = VkClearValue{ color: VkClearColorValue{ float32: [0.0, 0.0, 0.0, 0.0] } };
+
+        let mut color_attachment = make_color_attachment_info(color_image.view, Some(clear_color), VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL);
+       	let mut depth_attachment = make_depth_attachment_info(depth_image.view, VK_IMAGE_LAYOUT_DEPTH_ATTACHMENT_OPTIMAL);
+
+        if let Some((color_resolve, depth_resolve)) = resolve_images {
+            // Depth can't be averaged, and sample zero is the only depth resolve every device supports
+            color_attachment = make_resolve_attachment_info(color_attachment, color_resolve.view, VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL, VK_RESOLVE_MODE_AVERAGE_BIT);
+            depth_attachment = make_resolve_attachment_info(depth_attachment, depth_resolve.view, VK_IMAGE_LAYOUT_DEPTH_ATTACHMENT_OPTIMAL, VK_RESOLVE_MODE_SAMPLE_ZERO_BIT);
+        }
+
+        // A null view discards the writes to the ambient attachment
+        let mut ambient_attachment = match ambient_images {
+            Some((ambient_image, _)) => make_color_attachment_info(ambient_image.view, Some(clear_color), VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL),
+            None                     => make_color_attachment_info(ptr::null_mut(), None, VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL),
+        };
+
+        if let Some((_, Some(ambient_resolve))) = ambient_images {
+            ambient_attachment = make_resolve_attachment_info(ambient_attachment, ambient_resolve.view, VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL, VK_RESOLVE_MODE_AVERAGE_BIT);
+        }
+
+        let color_attachments: [VkRenderingAttachmentInfo; 2] = [color_attachment, ambient_attachment];
+
+        let mut render_info = make_rendering_info(color_image.get_extent_2d(), color_attachments.as_ptr(), &depth_attachment);
+        render_info.colorAttachmentCount = color_attachments.len() as u32;
+
+        // Attachments with a null view must be inherited with an undefined format
+        let ambient_format = ambient_images.map_or(VK_FORMAT_UNDEFINED, |(ambient_image, _)| ambient_image.format);
+        let color_formats: [VkFormat; 2] = [color_image.format, ambient_format];
+        let inheritance = RenderingInheritance{
+            color_formats: &color_formats,
+            depth_format:  depth_image.format,
+            samples:       self.msaa_samples,
+        };
+
+        return self.record_geometry(cmd_buffer, render_info, &inheritance, (self.mesh_p, self.skinned_mesh_p), global_ds, draw_buffers);
+    }
+
+    /// Renders every opaque mesh into the G-buffer of the deferred path: the emissive light and coverage into
+    /// `color_image`, the surfaces into `gbuffer_images` (albedo, normals, material) and the depth into `depth_image`.
+    /// Always one sample per pixel, see deferred.rs
+    fn draw_gbuffer(&self, cmd_buffer: &mut CommandBuffer, color_image: GraphImage, gbuffer_images: (GraphImage, GraphImage, GraphImage), depth_image: GraphImage, shadow_map: GraphImage, lights: VkBuffer, draw_buffers: Option<&GpuDrawBuffers>) -> Result<(), RenderError> {
+        // The G-buffer shaders only read the scene data, but they share the lit meshes' layout
+        let global_ds = self.allocate_scene_set(shadow_map, lights)?;
+
+        let (albedo_image, normal_image, material_image) = gbuffer_images;
+
+        let clear_color = VkClearValue{ color: VkClearColorValue{ float32: [0.0, 0.0, 0.0, 0.0] } };
+
+        let color_attachments: [VkRenderingAttachmentInfo; 4] = [
+            make_color_attachment_info(color_image.view,    Some(clear_color), VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL),
+            make_color_attachment_info(albedo_image.view,   Some(clear_color), VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL),
+            make_color_attachment_info(normal_image.view,   Some(clear_color), VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL),
+            make_color_attachment_info(material_image.view, Some(clear_color), VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL),
+        ];
+        let depth_attachment = make_depth_attachment_info(depth_image.view, VK_IMAGE_LAYOUT_DEPTH_ATTACHMENT_OPTIMAL);
+
+        let mut render_info = make_rendering_info(color_image.get_extent_2d(), color_attachments.as_ptr(), &depth_attachment);
+        render_info.colorAttachmentCount = color_attachments.len() as u32;
+
+        let color_formats: [VkFormat; 4] = [color_image.format, albedo_image.format, normal_image.format, material_image.format];
+        let inheritance = RenderingInheritance{
+            color_formats: &color_formats,
+            depth_format:  depth_image.format,
+            samples:       VK_SAMPLE_COUNT_1_BIT,
+        };
+
+        return self.record_geometry(cmd_buffer, render_info, &inheritance, (self.gbuffer_mesh_p, self.gbuffer_skinned_mesh_p), global_ds, draw_buffers);
+    }
+
+    /// Records the opaque meshes into the rendering described by `render_info`, with `pipelines` (regular, skinned).
+    /// With `draw_buffers`, the meshes are drawn from the indirect commands written by the draw cull pass instead of
+    /// the CPU draw list. Large CPU draw lists are split across the recording workers, whose secondary command
+    /// buffers are created with `inheritance`.
+    fn record_geometry(&self, cmd_buffer: &mut CommandBuffer, mut render_info: VkRenderingInfo, inheritance: &RenderingInheritance, pipelines: (VkPipeline, VkPipeline), scene_set: VkDescriptorSet, draw_buffers: Option<&GpuDrawBuffers>) -> Result<(), RenderError> {
+        let meshes = &self.meshes[0..self.mesh_count];
+
+        // GPU-driven draws are culled by the draw cull pass, their stats are read back once the frame is done
+        let visible_draws = match draw_buffers {
+            Some(_) => Vec::new(),
+            None    => {
+                let (visible_draws, culling_stats) = cull_draw_list(self.scene_data.view_proj, &self.materials, meshes, &self.instanced_meshes);
+                self.culling_stats.set(culling_stats);
+                visible_draws
+            },
+        };
+
+        let draw_context = GeometryDrawContext{
+            pipeline:          pipelines.0,
+            skinned_pipeline:  pipelines.1,
+            layout:            self.mesh_pl,
+            scene_set,
+            draw_extent:       render_info.renderArea.extent,
+            meshes,
+            instanced_meshes:  &self.instanced_meshes,
+            skinned_meshes:    &self.skinned_meshes,
+            draws:             &visible_draws,
+            default_instances: self.default_instance_address,
+            bindless_set:      self.bindless.set,
+            materials:         &self.materials,
+        };
+
+        if let Some(draw_buffers) = draw_buffers {
+            // A handful of draws per mesh, not worth splitting across workers
+            cmd_buffer.begin_rendering(render_info);
+            draw_context.record_indirect(cmd_buffer, draw_buffers);
+            cmd_buffer.end_rendering();
+
+            return Ok(());
+        }
+
+        let draw_count   = draw_context.get_draw_count();
+        let worker_count = self.recording_workers.get_worker_count().min(draw_count / MIN_DRAWS_PER_WORKER);
+
+        if worker_count > 1 {
+            let secondary_command_buffers = self.record_draws_in_parallel(&draw_context, worker_count, inheritance)?;
+
+            render_info.flags = VK_RENDERING_CONTENTS_SECONDARY_COMMAND_BUFFERS_BIT as VkRenderingFlags;
+
+            cmd_buffer.begin_rendering(render_info);
+            cmd_buffer.execute_commands(&secondary_command_buffers);
+            cmd_buffer.end_rendering();
+        } else {
+            cmd_buffer.begin_rendering(render_info);
+            draw_context.record(cmd_buffer, 0..draw_count);
+            cmd_buffer.end_rendering();
+        }
+
+        return Ok(());
+    }
+
+    /// Renders every mesh into each of the sun's shadow cascades. The cascades are tiles of a single atlas, so
+    /// they share one render pass and only the viewport changes between them. Meshes with Mask materials are drawn
+    /// with the alpha tested pipelines, so their cut out texels don't cast shadows.
+    fn draw_shadows(&self, cmd_buffer: &mut CommandBuffer, shadow_map: GraphImage) -> Result<(), RenderError> {
+        let depth_attachment = make_depth_attachment_info(shadow_map.view, VK_IMAGE_LAYOUT_DEPTH_ATTACHMENT_OPTIMAL);
+        let render_info      = make_rendering_info(shadow_map.get_extent_2d(), ptr::null(), &depth_attachment);
+
+        let settings   = &self.shadow_settings;
+        let resolution = settings.cascade_resolution;
+        let stages     = VK_SHADER_STAGE_VERTEX_BIT | VK_SHADER_STAGE_FRAGMENT_BIT;
+
+        cmd_buffer.begin_rendering(render_info);
+        cmd_buffer.bind_graphics_pipeline(self.shadow_depth_p);
+        cmd_buffer.set_depth_bias(settings.depth_bias_constant, 0.0, settings.depth_bias_slope);
+
+        let sets: [VkDescriptorSet; 1] = [self.bindless.set];
+        cmd_buffer.bind_graphics_descriptor_sets(self.shadow_depth_pl, 0, &sets);
+
+        // Alpha tested and regular draws can alternate, the pipelines share a layout
+        let mut is_alpha_tested_bound = false;
+
+        for cascade in 0..settings.cascade_count as usize {
+            let (offset_x, offset_y) = settings.get_cascade_offset(cascade);
+            cmd_buffer.set_viewport(resolution as i32, resolution as i32, offset_x, offset_y);
+            cmd_buffer.set_scissor_rect(offset_x as i32, offset_y as i32, resolution, resolution);
+
+            let cascade_view_proj = self.scene_data.cascade_view_proj[cascade];
+
+            let meshes    = self.meshes[0..self.mesh_count].iter().map(|mesh| (mesh, self.default_instance_address, 1));
+            let instanced = self.instanced_meshes.iter().map(|instanced| (&instanced.mesh, instanced.instance_buffer_address, instanced.instance_count));
+
+            for (mesh, instance_buffer, instance_count) in meshes.chain(instanced) {
+                if instance_count == 0 {
+                    continue;
+                }
+
+                let is_alpha_tested = self.materials[mesh.material_index].is_alpha_tested();
+                if is_alpha_tested != is_alpha_tested_bound {
+                    is_alpha_tested_bound = is_alpha_tested;
+                    cmd_buffer.bind_graphics_pipeline(if is_alpha_tested { self.shadow_depth_alpha_test_p } else { self.shadow_depth_p });
+                }
+
+                let push_consts = GpuDrawPushConstants {
+                    world_matrix:   mul_rh(cascade_view_proj, mesh.transform),
+                    vertex_buffer:  mesh.vertex_buffer_address,
+                    instance_buffer,
+                    material_index: mesh.material_index as u32, // only read by the alpha tested pipeline
+                    _pad:           0,
+                    skin_buffer:    0,
+                    joint_buffer:   0,
+                };
+
+                cmd_buffer.bind_push_constants(self.shadow_depth_pl, stages, push_consts, 0);
+                cmd_buffer.bind_index_buffer(&mesh.index_buffer);
+                let lod = mesh.get_lod();
+                cmd_buffer.draw_indexed(lod.index_count, instance_count, lod.first_index, 0, 0);
+            }
+        }
+
+        if !self.skinned_meshes.is_empty() {
+            cmd_buffer.bind_graphics_pipeline(self.skinned_shadow_depth_p);
+            is_alpha_tested_bound = false;
+
+            for cascade in 0..settings.cascade_count as usize {
+                let (offset_x, offset_y) = settings.get_cascade_offset(cascade);
+                cmd_buffer.set_viewport(resolution as i32, resolution as i32, offset_x, offset_y);
+                cmd_buffer.set_scissor_rect(offset_x as i32, offset_y as i32, resolution, resolution);
+
+                let cascade_view_proj = self.scene_data.cascade_view_proj[cascade];
+
+                for skinned in &self.skinned_meshes {
+                    let mesh = &skinned.mesh;
+
+                    let is_alpha_tested = self.materials[mesh.material_index].is_alpha_tested();
+                    if is_alpha_tested != is_alpha_tested_bound {
+                        is_alpha_tested_bound = is_alpha_tested;
+                        cmd_buffer.bind_graphics_pipeline(if is_alpha_tested { self.skinned_shadow_depth_alpha_test_p } else { self.skinned_shadow_depth_p });
+                    }
+
+                    let push_consts = GpuDrawPushConstants {
+                        world_matrix:    mul_rh(cascade_view_proj, mesh.transform),
+                        vertex_buffer:   mesh.vertex_buffer_address,
+                        instance_buffer: self.default_instance_address,
+                        material_index:  mesh.material_index as u32, // only read by the alpha tested pipeline
+                        _pad:            0,
+                        skin_buffer:     skinned.skin_buffer_address,
+                        joint_buffer:    skinned.joint_buffer_address,
+                    };
+
+                    cmd_buffer.bind_push_constants(self.shadow_depth_pl, stages, push_consts, 0);
+                    cmd_buffer.bind_index_buffer(&mesh.index_buffer);
+                    let lod = mesh.get_lod();
+                    cmd_buffer.draw_indexed(lod.index_count, 1, lod.first_index, 0, 0);
+                }
+            }
+        }
+
+        cmd_buffer.end_rendering();
+        return Ok(());
+    }
+
+    /// Copies this frame's point and spot lights into a transient storage buffer. The buffer always has room for at
+    /// least one light, so it can be bound when there are no lights.
+    fn upload_lights(&self) -> Result<(AllocatedBuffer, usize), RenderError> {
+        let lights = pack_lights(&self.point_lights, &self.spot_lights);
+
+        let buffer_size = lights.len().max(1) * std::mem::size_of::<GpuLight>();
+        let buffer      = self.device.create_buffer(buffer_size, VK_BUFFER_USAGE_STORAGE_BUFFER_BIT, VMA_MEMORY_USAGE_CPU_TO_GPU)?;
+
+        let frame_data = self.get_frame_data();
+        frame_data.deletion_queues.borrow_mut().buffer_deletion_queue.push_back(buffer);
+
+        let memory = buffer.get_allocation();
+        assert!(memory != ptr::null_mut());
+        unsafe { std::ptr::copy(lights.as_ptr(), memory as *mut GpuLight, lights.len()) };
+
+        return Ok((buffer, lights.len()));
+    }
+
+    /// Assigns the lights to the clusters of the view frustum, one invocation per cluster.
+    fn cull_lights(&self, cmd_buffer: &mut CommandBuffer, lights: VkBuffer, light_count: usize) -> Result<(), RenderError> {
+        let cull_ds = {
+            let frame_data = self.get_frame_data();
+            let mut dyn_descriptors = frame_data.dynamic_descriptors.borrow_mut();
+
+            let ds = dyn_descriptors.allocate(&self.device, self.light_cull_dl)?;
+
+            let mut writer = DescriptorWriter::new();
+            writer.write_buffer(0, lights, VK_WHOLE_SIZE as VkDeviceSize, 0, VK_DESCRIPTOR_TYPE_STORAGE_BUFFER);
+            writer.write_buffer(1, self.light_grid.buffer, VK_WHOLE_SIZE as VkDeviceSize, 0, VK_DESCRIPTOR_TYPE_STORAGE_BUFFER);
+            writer.update_set(&self.device, ds);
+
+            ds
+        };
+
+        let push_consts = make_light_cull_constants(self.view_matrix, self.perspective_matrix, light_count);
+
+        cmd_buffer.bind_compute_pipeline(self.light_cull_p);
+
+        let descriptors: [VkDescriptorSet; 1] = [ cull_ds ];
+        cmd_buffer.bind_compute_descriptor_sets(self.light_cull_pl, 0, descriptors.as_slice());
+        cmd_buffer.bind_push_constants(self.light_cull_pl, VK_SHADER_STAGE_COMPUTE_BIT, push_consts, 0);
+
+        // A workgroup covers one depth slice of the grid
+        cmd_buffer.dispatch_compute(1, 1, CLUSTER_GRID_Z);
+
+        return Ok(());
+    }
+
+    /// Copies `data` into a transient buffer the CPU writes and the GPU reads, released with the frame. The buffer
+    /// always has room for at least one element.
+    fn upload_frame_buffer<T>(&self, data: &[T], usage: VkBufferUsageFlags) -> Result<AllocatedBuffer, RenderError> {
+        let buffer_size = data.len().max(1) * std::mem::size_of::<T>();
+        let buffer      = self.device.create_buffer(buffer_size, usage, VMA_MEMORY_USAGE_CPU_TO_GPU)?;
+
+        let frame_data = self.get_frame_data();
+        frame_data.deletion_queues.borrow_mut().buffer_deletion_queue.push_back(buffer);
+
+        let memory = buffer.get_allocation();
+        assert!(memory != ptr::null_mut());
+        unsafe { std::ptr::copy(data.as_ptr(), memory as *mut T, data.len()) };
+
+        return Ok(buffer);
+    }
+
+    /// Creates the storage buffer an emitter's particles are simulated in on the GPU. The buffer starts out with
+    /// garbage, the first simulation pass clears it.
+    fn create_particle_buffer(&self, emitter: &ParticleEmitter) -> Result<(AllocatedBuffer, VkDeviceAddress), RenderError> {
+        let buffer_size  = emitter.get_capacity() as usize * std::mem::size_of::<GpuParticle>();
+        let buffer_flags = VK_BUFFER_USAGE_STORAGE_BUFFER_BIT | VK_BUFFER_USAGE_SHADER_DEVICE_ADDRESS_BIT;
+
+        let buffer = self.device.create_buffer(buffer_size, buffer_flags, VMA_MEMORY_USAGE_GPU_ONLY)?;
+        return Ok((buffer, self.device.get_buffer_device_address(&buffer)));
+    }
+
+    fn add_particle_emitter(&mut self, emitter: &ParticleEmitter) -> Result<(), RenderError> {
+        let (buffer, buffer_address) = self.create_particle_buffer(emitter)?;
+        self.particle_emitters.push(ParticleEmitterState::new(*emitter, buffer, buffer_address));
+
+        return Ok(());
+    }
+
+    /// Advances every emitter's spawner by the time since the last frame. CPU simulated emitters are simulated and
+    /// copied into transient buffers, GPU simulated emitters get their step for the simulation pass. Returns None
+    /// when there are no emitters.
+    fn update_particles(&mut self) -> Result<Option<ParticleDrawList>, RenderError> {
+        let now        = Instant::now();
+        let delta_time = match self.particle_clock {
+            Some(last_update) => now.duration_since(last_update).as_secs_f32(),
+            None              => 0.0,
+        };
+        self.particle_clock = Some(now);
+
+        if self.particle_emitters.is_empty() {
+            return Ok(None);
+        }
+
+        let simulation = self.particle_settings.simulation;
+
+        let mut gpu_emitters = Vec::<GpuParticleEmitter>::with_capacity(self.particle_emitters.len());
+        let mut draws        = Vec::<ParticleDraw>::with_capacity(self.particle_emitters.len());
+
+        for emitter_index in 0..self.particle_emitters.len() {
+            let state   = &mut self.particle_emitters[emitter_index];
+            let emitter = state.emitter;
+            let step    = state.spawner.advance(&emitter, delta_time);
+
+            let gpu_step = match simulation {
+                ParticleSimulation::Gpu => {
+                    let reset = state.needs_reset;
+                    state.needs_reset = false;
+
+                    Some((step, reset))
+                },
+                ParticleSimulation::Cpu => {
+                    simulate_particles(&mut state.cpu_particles, &emitter, &step);
+
+                    // The GPU buffer falls behind, so it starts over if the simulation moves back to the GPU
+                    state.needs_reset = true;
+                    None
+                },
+            };
+
+            let particle_buffer = if gpu_step.is_some() {
+                state.buffer_address
+            } else {
+                let particles = pack_particles(&state.cpu_particles);
+                let buffer    = self.upload_frame_buffer(&particles, VK_BUFFER_USAGE_STORAGE_BUFFER_BIT | VK_BUFFER_USAGE_SHADER_DEVICE_ADDRESS_BIT)?;
+
+                self.device.get_buffer_device_address(&buffer)
+            };
+
+            let texture_index = match emitter.texture_sheet {
+                Some(sheet) => {
+                    let texture_id = match self.textures.iter().find(|texture| texture.engine_id == sheet.texture) {
+                        Some(texture) => texture.id,
+                        None          => self.error_texture,
+                    };
+
+                    texture_id.get_index()
+                },
+                None => NO_PARTICLE_TEXTURE,
+            };
+
+            gpu_emitters.push(pack_particle_emitter(&emitter));
+            draws.push(ParticleDraw{
+                emitter_index:   emitter_index as u32,
+                particle_buffer,
+                capacity:        emitter.get_capacity(),
+                texture_index,
+                blend:           emitter.blend,
+                gpu_step,
+            });
+        }
+
+        let emitter_buffer = self.upload_frame_buffer(&gpu_emitters, VK_BUFFER_USAGE_STORAGE_BUFFER_BIT | VK_BUFFER_USAGE_SHADER_DEVICE_ADDRESS_BIT)?;
+
+        return Ok(Some(ParticleDrawList{
+            emitter_buffer: self.device.get_buffer_device_address(&emitter_buffer),
+            draws,
+        }));
+    }
+
+    /// Batches this frame's sprites and copies them into a transient buffer. Returns None when there are no sprites
+    /// to draw.
+    fn upload_sprites(&self) -> Result<Option<SpriteDrawList>, RenderError> {
+        if self.sprites.is_empty() {
+            return Ok(None);
+        }
+
+        let get_texture_index = |engine_id: u64| -> u32 {
+            let texture_id = match self.textures.iter().find(|texture| texture.engine_id == engine_id) {
+                Some(texture) => texture.id,
+                None          => self.error_texture,
+            };
+
+            return texture_id.get_index();
+        };
+
+        let (sprites, batches) = build_sprite_batches(&self.sprites, get_texture_index);
+
+        let buffer = self.upload_frame_buffer(&sprites, VK_BUFFER_USAGE_STORAGE_BUFFER_BIT | VK_BUFFER_USAGE_SHADER_DEVICE_ADDRESS_BIT)?;
+
+        return Ok(Some(SpriteDrawList{
+            sprite_buffer: self.device.get_buffer_device_address(&buffer),
+            batches,
+        }));
+    }
+
+    /// Batches this frame's text and copies the glyphs into a transient buffer. Returns None when there is no text
+    /// to draw.
+    fn upload_text(&self) -> Result<Option<TextDrawList>, RenderError> {
+        if self.text.iter().all(|draw| draw.glyphs.is_empty()) {
+            return Ok(None);
+        }
+
+        let get_texture_index = |engine_id: u64| -> u32 {
+            let texture_id = match self.textures.iter().find(|texture| texture.engine_id == engine_id) {
+                Some(texture) => texture.id,
+                None          => self.error_texture,
+            };
+
+            return texture_id.get_index();
+        };
+
+        let (glyphs, batches) = build_text_batches(&self.text, get_texture_index);
+
+        let buffer = self.upload_frame_buffer(&glyphs, VK_BUFFER_USAGE_STORAGE_BUFFER_BIT | VK_BUFFER_USAGE_SHADER_DEVICE_ADDRESS_BIT)?;
+
+        return Ok(Some(TextDrawList{
+            glyph_buffer: self.device.get_buffer_device_address(&buffer),
+            batches,
+        }));
+    }
+
+    /// Copies this frame's debug lines into a transient buffer, the depth tested lines first. Returns the buffer's
+    /// address, or None when there are no lines to draw.
+    fn upload_debug_lines(&self) -> Result<Option<VkDeviceAddress>, RenderError> {
+        if self.debug_lines.is_empty() {
+            return Ok(None);
+        }
+
+        let mut vertices = Vec::with_capacity(self.debug_lines.depth_tested.len() + self.debug_lines.overlay.len());
+        vertices.extend_from_slice(&self.debug_lines.depth_tested);
+        vertices.extend_from_slice(&self.debug_lines.overlay);
+
+        let buffer = self.upload_frame_buffer(&vertices, VK_BUFFER_USAGE_STORAGE_BUFFER_BIT | VK_BUFFER_USAGE_SHADER_DEVICE_ADDRESS_BIT)?;
+
+        return Ok(Some(self.device.get_buffer_device_address(&buffer)));
+    }
+
+    /// Whether this frame's geometry is culled and drawn on the GPU, see culling.rs
+    fn is_gpu_driven(&self) -> bool {
+        return self.culling_settings.enabled && self.device.supports_gpu_driven_rendering();
+    }
+
+    /// Packs this frame's draw list and uploads it for the draw cull pass. Returns None when there is nothing to
+    /// draw, the geometry pass then has nothing to wait on.
+    fn upload_draw_list(&self) -> Result<Option<GpuDrawBuffers>, RenderError> {
+        let list = build_draw_list(&self.materials, &self.meshes[0..self.mesh_count], &self.instanced_meshes, &self.retained_instanced_meshes);
+        if list.get_object_count() == 0 {
+            return Ok(None);
+        }
+
+        let hiz_mip_count = if self.culling_settings.occlusion_culling && self.hiz.is_valid { self.hiz.get_mip_count() } else { 0 };
+
+        let cull_data = [DrawCullData{
+            view_proj:     self.scene_data.view_proj,
+            hiz_view_proj: self.hiz.view_proj,
+            params:        Float4::new(list.get_object_count() as f32, self.hiz.image.dims.width as f32, self.hiz.image.dims.height as f32, hiz_mip_count as f32),
+        }];
+
+        let instances = self.upload_frame_buffer(&list.instances, VK_BUFFER_USAGE_STORAGE_BUFFER_BIT | VK_BUFFER_USAGE_SHADER_DEVICE_ADDRESS_BIT)?;
+        let objects   = self.upload_frame_buffer(&list.objects, VK_BUFFER_USAGE_STORAGE_BUFFER_BIT)?;
+        let batches   = self.upload_frame_buffer(&list.gpu_batches, VK_BUFFER_USAGE_STORAGE_BUFFER_BIT)?;
+        let cull_data = self.upload_frame_buffer(&cull_data, VK_BUFFER_USAGE_UNIFORM_BUFFER_BIT)?;
+
+        // Written by the draw cull pass
+        let indirect_flags = VK_BUFFER_USAGE_STORAGE_BUFFER_BIT | VK_BUFFER_USAGE_INDIRECT_BUFFER_BIT | VK_BUFFER_USAGE_TRANSFER_DST_BIT;
+
+        let commands = self.device.create_buffer(list.get_object_count() * DRAW_COMMAND_STRIDE, indirect_flags, VMA_MEMORY_USAGE_GPU_ONLY)?;
+        self.get_frame_data().deletion_queues.borrow_mut().buffer_deletion_queue.push_back(commands);
+
+        let counts_size = list.batches.len() * std::mem::size_of::<u32>();
+
+        let counts = self.device.create_buffer(counts_size, indirect_flags | VK_BUFFER_USAGE_TRANSFER_SRC_BIT, VMA_MEMORY_USAGE_GPU_ONLY)?;
+        self.get_frame_data().deletion_queues.borrow_mut().buffer_deletion_queue.push_back(counts);
+
+        let counts_readback = self.device.create_buffer(counts_size, VK_BUFFER_USAGE_TRANSFER_DST_BIT, VMA_MEMORY_USAGE_GPU_TO_CPU)?;
+        self.get_frame_data().deletion_queues.borrow_mut().buffer_deletion_queue.push_back(counts_readback);
+
+        return Ok(Some(GpuDrawBuffers{
+            instance_address: self.device.get_buffer_device_address(&instances),
+            list,
+            instances,
+            objects,
+            batches,
+            commands,
+            counts,
+            counts_readback,
+            cull_data,
+        }));
+    }
+
+    /// Tests every object against the frustum and the Hi-Z pyramid, and writes the draw commands of the visible
+    /// ones. One invocation per object.
+    fn cull_draws(&self, cmd_buffer: &mut CommandBuffer, draw_buffers: &GpuDrawBuffers, hiz: GraphImage) -> Result<(), RenderError> {
+        let cull_ds = {
+            let frame_data = self.get_frame_data();
+            let mut dyn_descriptors = frame_data.dynamic_descriptors.borrow_mut();
+
+            let ds = dyn_descriptors.allocate(&self.device, self.draw_cull_dl)?;
+
+            let mut writer = DescriptorWriter::new();
+            writer.write_buffer(0, draw_buffers.cull_data.buffer, std::mem::size_of::<DrawCullData>() as u64, 0, VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER);
+            writer.write_buffer(1, draw_buffers.objects.buffer,  VK_WHOLE_SIZE as VkDeviceSize, 0, VK_DESCRIPTOR_TYPE_STORAGE_BUFFER);
+            writer.write_buffer(2, draw_buffers.batches.buffer,  VK_WHOLE_SIZE as VkDeviceSize, 0, VK_DESCRIPTOR_TYPE_STORAGE_BUFFER);
+            writer.write_buffer(3, draw_buffers.commands.buffer, VK_WHOLE_SIZE as VkDeviceSize, 0, VK_DESCRIPTOR_TYPE_STORAGE_BUFFER);
+            writer.write_buffer(4, draw_buffers.counts.buffer,   VK_WHOLE_SIZE as VkDeviceSize, 0, VK_DESCRIPTOR_TYPE_STORAGE_BUFFER);
+            writer.write_combined_image_sampler(5, hiz.view, self.default_sampler_nearest, VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL);
+            writer.update_set(&self.device, ds);
+
+            ds
+        };
+
+        // Every batch starts the frame with no visible objects
+        cmd_buffer.fill_buffer(draw_buffers.counts.buffer, 0, VK_WHOLE_SIZE as VkDeviceSize, 0);
+        cmd_buffer.buffer_barrier(draw_buffers.counts.buffer,
+            VK_PIPELINE_STAGE_2_TRANSFER_BIT,       VK_ACCESS_2_TRANSFER_WRITE_BIT,
+            VK_PIPELINE_STAGE_2_COMPUTE_SHADER_BIT, VK_ACCESS_2_SHADER_STORAGE_READ_BIT | VK_ACCESS_2_SHADER_STORAGE_WRITE_BIT);
+
+        cmd_buffer.bind_compute_pipeline(self.draw_cull_p);
+
+        let descriptors: [VkDescriptorSet; 1] = [ cull_ds ];
+        cmd_buffer.bind_compute_descriptor_sets(self.draw_cull_pl, 0, descriptors.as_slice());
+
+        let object_count = draw_buffers.list.get_object_count() as u32;
+        cmd_buffer.dispatch_compute(object_count.div_ceil(DRAW_CULL_GROUP_SIZE), 1, 1);
+
+        // The counts are copied back for the culling stats, read once the GPU is done with the frame
+        let counts_size = (draw_buffers.list.batches.len() * std::mem::size_of::<u32>()) as VkDeviceSize;
+
+        cmd_buffer.buffer_barrier(draw_buffers.counts.buffer,
+            VK_PIPELINE_STAGE_2_COMPUTE_SHADER_BIT, VK_ACCESS_2_SHADER_STORAGE_WRITE_BIT,
+            VK_PIPELINE_STAGE_2_TRANSFER_BIT,       VK_ACCESS_2_TRANSFER_READ_BIT);
+        cmd_buffer.copy_buffer(&draw_buffers.counts_readback, 0, &draw_buffers.counts, 0, counts_size);
+        cmd_buffer.buffer_barrier(draw_buffers.counts_readback.buffer,
+            VK_PIPELINE_STAGE_2_TRANSFER_BIT, VK_ACCESS_2_TRANSFER_WRITE_BIT,
+            VK_PIPELINE_STAGE_2_HOST_BIT,     VK_ACCESS_2_HOST_READ_BIT);
+
+        return Ok(());
+    }
+
+    /// Draws the skybox into the scene wherever the geometry didn't fully cover a pixel.
+    fn draw_skybox(&self, cmd_buffer: &mut CommandBuffer, scene: GraphImage) -> Result<(), RenderError> {
+        // The scene image can change from frame to frame, so the descriptor is written every frame.
+        let skybox_ds = {
+            let frame_data = self.get_frame_data();
+            let mut dyn_descriptors = frame_data.dynamic_descriptors.borrow_mut();
+
+            let ds = dyn_descriptors.allocate(&self.device, self.skybox_dl)?;
+
+            let mut writer = DescriptorWriter::new();
+            writer.write_storage_image(0, scene.view, VK_IMAGE_LAYOUT_GENERAL);
+            writer.write_combined_image_sampler(1, self.environment.skybox.view, self.cube_sampler, VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL);
+            writer.update_set(&self.device, ds);
+
+            ds
+        };
+
+        let max_lod     = self.retained_environment.skybox.len().saturating_sub(1) as f32;
+        let push_consts = SkyboxPushConstants{
+            inverse_view_proj: self.scene_data.view_proj.invert(),
+            camera_pos:        self.scene_data.camera_pos,
+            params:            Float4::new(self.skybox_settings.intensity, self.skybox_settings.blur * max_lod, 0.0, 0.0),
+        };
+
+        cmd_buffer.bind_compute_pipeline(self.skybox_p);
+
+        let descriptors: [VkDescriptorSet; 1] = [ skybox_ds ];
+        cmd_buffer.bind_compute_descriptor_sets(self.skybox_pl, 0, descriptors.as_slice());
+        cmd_buffer.bind_push_constants(self.skybox_pl, VK_SHADER_STAGE_COMPUTE_BIT, push_consts, 0);
+        cmd_buffer.dispatch_compute(scene.extent.width.div_ceil(SKYBOX_GROUP_SIZE), scene.extent.height.div_ceil(SKYBOX_GROUP_SIZE), 1);
+
+        return Ok(());
+    }
+
+    /// Computes the half-resolution AO from the resolved scene `depth`, see ssao.comp.
+    fn compute_ssao(&self, cmd_buffer: &mut CommandBuffer, depth: GraphImage, target: GraphImage) -> Result<(), RenderError> {
+        let settings = &self.ssao_settings;
+
+        let push_consts = ComputePushConstants{
+            data1: get_projection_terms(self.scene_data.proj),
+            data2: Float4::new(settings.radius, settings.intensity, settings.sample_count as f32, 0.0),
+            data3: Float4::new(depth.extent.width as f32, depth.extent.height as f32, 0.0, 0.0),
+            data4: Float4::zero(),
+        };
+
+        return self.dispatch_post_process(cmd_buffer, self.ssao_p, depth, target, push_consts);
+    }
+
+    /// Blurs the AO along `direction`, (1, 0) or (0, 1), see ssao_blur.comp.
+    fn blur_ssao(&self, cmd_buffer: &mut CommandBuffer, source: GraphImage, target: GraphImage, direction: (f32, f32)) -> Result<(), RenderError> {
+        let push_consts = ComputePushConstants{
+            data1: Float4::new(direction.0, direction.1, 0.0, 0.0),
+            data2: Float4::zero(),
+            data3: Float4::zero(),
+            data4: Float4::zero(),
+        };
+
+        return self.dispatch_post_process(cmd_buffer, self.ssao_blur_p, source, target, push_consts);
+    }
+
+    /// Takes the occluded ambient light out of the scene, or replaces the scene with the AO for the debug view.
+    /// `ssao_images` is the ambient target, the blurred AO and the scene depth.
+    fn apply_ssao(&self, cmd_buffer: &mut CommandBuffer, scene: GraphImage, ssao_images: (GraphImage, GraphImage, GraphImage)) -> Result<(), RenderError> {
+        let (ambient, ao, depth) = ssao_images;
+
+        let apply_ds = {
+            let frame_data = self.get_frame_data();
+            let mut dyn_descriptors = frame_data.dynamic_descriptors.borrow_mut();
+
+            let ds = dyn_descriptors.allocate(&self.device, self.ssao_apply_dl)?;
+
+            let mut writer = DescriptorWriter::new();
+            writer.write_storage_image(0, scene.view, VK_IMAGE_LAYOUT_GENERAL);
+            writer.write_combined_image_sampler(1, ambient.view, self.default_sampler_nearest, VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL);
+            writer.write_combined_image_sampler(2, ao.view,      self.default_sampler_nearest, VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL);
+            writer.write_combined_image_sampler(3, depth.view,   self.default_sampler_nearest, VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL);
+            writer.update_set(&self.device, ds);
+
+            ds
+        };
+
+        let proj        = get_projection_terms(self.scene_data.proj);
+        let push_consts = ComputePushConstants{
+            data1: Float4::new(proj.z, proj.w, if self.ssao_settings.debug_view { 1.0 } else { 0.0 }, 0.0),
+            data2: Float4::zero(),
+            data3: Float4::zero(),
+            data4: Float4::zero(),
+        };
+
+        cmd_buffer.bind_compute_pipeline(self.ssao_apply_p);
+
+        let descriptors: [VkDescriptorSet; 1] = [ apply_ds ];
+        cmd_buffer.bind_compute_descriptor_sets(self.ssao_apply_pl, 0, descriptors.as_slice());
+        cmd_buffer.bind_push_constants(self.ssao_apply_pl, VK_SHADER_STAGE_COMPUTE_BIT, push_consts, 0);
+        cmd_buffer.dispatch_compute(scene.extent.width.div_ceil(SSAO_GROUP_SIZE), scene.extent.height.div_ceil(SSAO_GROUP_SIZE), 1);
+
+        return Ok(());
+    }
+
+    /// Lights the G-buffer into the scene, or replaces the scene with the G-buffer debug view, see
+    /// deferred_lighting.comp. `gbuffer_images` is the albedo, normals and material. Without `ssao`, the white image
+    /// stands in for an AO buffer that doesn't occlude anything.
+    fn light_gbuffer(&self, cmd_buffer: &mut CommandBuffer, scene: GraphImage, gbuffer_images: (GraphImage, GraphImage, GraphImage), depth: GraphImage, ssao: Option<GraphImage>, shadow_map: GraphImage, lights: VkBuffer) -> Result<(), RenderError> {
+        let global_ds = self.allocate_scene_set(shadow_map, lights)?;
+
+        let (albedo, normals, material) = gbuffer_images;
+        let ao_view = ssao.map_or(self.white_image.view, |ao| ao.view);
+
+        let gbuffer_ds = {
+            let frame_data = self.get_frame_data();
+            let mut dyn_descriptors = frame_data.dynamic_descriptors.borrow_mut();
+
+            let ds = dyn_descriptors.allocate(&self.device, self.deferred_lighting_dl)?;
+
+            let mut writer = DescriptorWriter::new();
+            writer.write_storage_image(0, scene.view, VK_IMAGE_LAYOUT_GENERAL);
+            writer.write_combined_image_sampler(1, albedo.view,   self.default_sampler_nearest, VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL);
+            writer.write_combined_image_sampler(2, normals.view,  self.default_sampler_nearest, VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL);
+            writer.write_combined_image_sampler(3, material.view, self.default_sampler_nearest, VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL);
+            writer.write_combined_image_sampler(4, depth.view,    self.default_sampler_nearest, VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL);
+            writer.write_combined_image_sampler(5, ao_view,       self.default_sampler_nearest, VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL);
+            writer.update_set(&self.device, ds);
+
+            ds
+        };
+
+        // The G-buffer views take precedence over the SSAO one, which has no ambient target to read on this path
+        let debug_view = if self.deferred_settings.debug_view != GBufferDebugView::Lit {
+            self.deferred_settings.debug_view.get_shader_value()
+        } else if ssao.is_some() && self.ssao_settings.debug_view {
+            SSAO_DEBUG_VIEW
+        } else {
+            GBufferDebugView::Lit.get_shader_value()
+        };
+
+        let push_consts = DeferredLightingPushConstants{
+            inverse_view_proj: self.scene_data.view_proj.invert(),
+            params:            Float4::new(debug_view as f32, 0.0, 0.0, 0.0),
+        };
+
+        cmd_buffer.bind_compute_pipeline(self.deferred_lighting_p);
+
+        let descriptors: [VkDescriptorSet; 2] = [ global_ds, gbuffer_ds ];
+        cmd_buffer.bind_compute_descriptor_sets(self.deferred_lighting_pl, 0, descriptors.as_slice());
+        cmd_buffer.bind_push_constants(self.deferred_lighting_pl, VK_SHADER_STAGE_COMPUTE_BIT, push_consts, 0);
+        cmd_buffer.dispatch_compute(scene.extent.width.div_ceil(DEFERRED_LIGHTING_GROUP_SIZE), scene.extent.height.div_ceil(DEFERRED_LIGHTING_GROUP_SIZE), 1);
+
+        return Ok(());
+    }
+
+    /// Draws the meshes with blended materials in the order of `transparent_draws`, tested against the resolved depth.
+    /// `color_targets` is the scene, or the accumulation and revealage targets with weighted blended OIT.
+    fn draw_transparent(&self, cmd_buffer: &mut CommandBuffer, color_targets: &[GraphImage], depth: GraphImage, shadow_map: GraphImage, lights: VkBuffer, transparent_draws: &[TransparentDraw]) -> Result<(), RenderError> {
+        let global_ds    = self.allocate_scene_set(shadow_map, lights)?;
+        let weighted_oit = color_targets.len() == 2;
+
+        let color_attachments: Vec<VkRenderingAttachmentInfo> = if weighted_oit {
+            // Nothing accumulated, and the scene fully revealed
+            let accum_clear     = VkClearValue{ color: VkClearColorValue{ float32: [0.0, 0.0, 0.0, 0.0] } };
+            let revealage_clear = VkClearValue{ color: VkClearColorValue{ float32: [1.0, 0.0, 0.0, 0.0] } };
+
+            vec![
+                make_color_attachment_info(color_targets[0].view, Some(accum_clear),     VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL),
+                make_color_attachment_info(color_targets[1].view, Some(revealage_clear), VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL),
+            ]
+        } else {
+            vec![make_color_attachment_info(color_targets[0].view, None, VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL)]
+        };
+
+        let mut depth_attachment = make_depth_attachment_info(depth.view, VK_IMAGE_LAYOUT_DEPTH_READ_ONLY_OPTIMAL);
+        depth_attachment.loadOp  = VK_ATTACHMENT_LOAD_OP_LOAD;
+        depth_attachment.storeOp = VK_ATTACHMENT_STORE_OP_NONE;
+
+        let draw_extent     = depth.get_extent_2d();
+        let mut render_info = make_rendering_info(draw_extent, color_attachments.as_ptr(), &depth_attachment);
+        render_info.colorAttachmentCount = color_attachments.len() as u32;
+
+        let (pipeline, skinned_pipeline) = if weighted_oit {
+            (self.oit_mesh_p, self.oit_skinned_mesh_p)
+        } else {
+            (self.transparent_mesh_p, self.transparent_skinned_mesh_p)
+        };
+
+        cmd_buffer.begin_rendering(render_info);
+        cmd_buffer.bind_graphics_pipeline(pipeline);
+        cmd_buffer.set_viewport(draw_extent.width as i32, draw_extent.height as i32, 0, 0);
+        cmd_buffer.set_scissor(draw_extent.width, draw_extent.height);
+
+        let sets: [VkDescriptorSet; 2] = [global_ds, self.bindless.set];
+        cmd_buffer.bind_graphics_descriptor_sets(self.mesh_pl, 0, &sets);
+
+        // Sorted draws can alternate between skinned and regular meshes, the pipelines share a layout
+        let mut is_skinned_bound = false;
+
+        for draw in transparent_draws {
+            if draw.is_skinned() != is_skinned_bound {
+                is_skinned_bound = draw.is_skinned();
+                cmd_buffer.bind_graphics_pipeline(if is_skinned_bound { skinned_pipeline } else { pipeline });
+            }
+
+            let mesh = &draw.mesh;
+
+            let push_consts = GpuDrawPushConstants {
+                world_matrix:    mesh.transform,
+                vertex_buffer:   mesh.vertex_buffer_address,
+                instance_buffer: draw.instance_buffer,
+                material_index:  mesh.material_index as u32,
+                _pad:            0,
+                skin_buffer:     draw.skin_buffer,
+                joint_buffer:    draw.joint_buffer,
+            };
+
+            cmd_buffer.bind_push_constants(self.mesh_pl, VK_SHADER_STAGE_VERTEX_BIT | VK_SHADER_STAGE_FRAGMENT_BIT, push_consts, 0);
+            cmd_buffer.bind_index_buffer(&mesh.index_buffer);
+            let lod = mesh.get_lod();
+            cmd_buffer.draw_indexed(lod.index_count, draw.instance_count, lod.first_index, 0, 0);
+        }
+
+        cmd_buffer.end_rendering();
+        return Ok(());
+    }
+
+    /// Blends the weighted average of the transparent surfaces over the scene, see oit_composite.comp.
+    fn composite_oit(&self, cmd_buffer: &mut CommandBuffer, scene: GraphImage, accum: GraphImage, revealage: GraphImage) -> Result<(), RenderError> {
+        let composite_ds = {
+            let frame_data = self.get_frame_data();
+            let mut dyn_descriptors = frame_data.dynamic_descriptors.borrow_mut();
+
+            let ds = dyn_descriptors.allocate(&self.device, self.oit_composite_dl)?;
+
+            let mut writer = DescriptorWriter::new();
+            writer.write_storage_image(0, scene.view, VK_IMAGE_LAYOUT_GENERAL);
+            writer.write_combined_image_sampler(1, accum.view,     self.default_sampler_nearest, VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL);
+            writer.write_combined_image_sampler(2, revealage.view, self.default_sampler_nearest, VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL);
+            writer.update_set(&self.device, ds);
+
+            ds
+        };
+
+        cmd_buffer.bind_compute_pipeline(self.oit_composite_p);
+
+        let descriptors: [VkDescriptorSet; 1] = [ composite_ds ];
+        cmd_buffer.bind_compute_descriptor_sets(self.oit_composite_pl, 0, descriptors.as_slice());
+        cmd_buffer.dispatch_compute(scene.extent.width.div_ceil(OIT_COMPOSITE_GROUP_SIZE), scene.extent.height.div_ceil(OIT_COMPOSITE_GROUP_SIZE), 1);
+
+        return Ok(());
+    }
+
+    /// Reduces the scene depth into the Hi-Z pyramid, one mip at a time. Each mip reads the one before it, so
+    /// there is a barrier between the dispatches.
+    fn build_hiz(&self, cmd_buffer: &mut CommandBuffer, depth: GraphImage, hiz: GraphImage) -> Result<(), RenderError> {
+        cmd_buffer.bind_compute_pipeline(self.hiz_build_p);
+
+        for mip in 0..self.hiz.get_mip_count() {
+            let (source_view, source_layout, source_extent) = if mip == 0 {
+                (depth.view, VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL, depth.get_extent_2d())
+            } else {
+                (self.hiz.mip_views[mip as usize - 1], VK_IMAGE_LAYOUT_GENERAL, get_hiz_mip_extent(hiz.extent, mip - 1))
+            };
+
+            let target_extent = get_hiz_mip_extent(hiz.extent, mip);
+
+            let hiz_ds = {
+                let frame_data = self.get_frame_data();
+                let mut dyn_descriptors = frame_data.dynamic_descriptors.borrow_mut();
+
+                let ds = dyn_descriptors.allocate(&self.device, self.post_process_dl)?;
+
+                let mut writer = DescriptorWriter::new();
+                writer.write_combined_image_sampler(0, source_view, self.default_sampler_nearest, source_layout);
+                writer.write_storage_image(1, self.hiz.mip_views[mip as usize], VK_IMAGE_LAYOUT_GENERAL);
+                writer.update_set(&self.device, ds);
+
+                ds
+            };
+
+            let push_consts = ComputePushConstants{
+                data1: Float4::new(source_extent.width as f32, source_extent.height as f32, target_extent.width as f32, target_extent.height as f32),
+                data2: Float4::zero(),
+                data3: Float4::zero(),
+                data4: Float4::zero(),
+            };
+
+            let descriptors: [VkDescriptorSet; 1] = [ hiz_ds ];
+            cmd_buffer.bind_compute_descriptor_sets(self.post_process_pl, 0, descriptors.as_slice());
+            cmd_buffer.bind_push_constants(self.post_process_pl, VK_SHADER_STAGE_COMPUTE_BIT, push_consts, 0);
+            cmd_buffer.dispatch_compute(target_extent.width.div_ceil(HIZ_GROUP_SIZE), target_extent.height.div_ceil(HIZ_GROUP_SIZE), 1);
+
+            cmd_buffer.image_mip_barrier(hiz.image, mip, VK_IMAGE_LAYOUT_GENERAL,
+                VK_PIPELINE_STAGE_2_COMPUTE_SHADER_BIT, VK_ACCESS_2_SHADER_STORAGE_WRITE_BIT,
+                VK_PIPELINE_STAGE_2_COMPUTE_SHADER_BIT, VK_ACCESS_2_SHADER_SAMPLED_READ_BIT);
+        }
+
+        return Ok(());
+    }
+
+    /// Runs a post-processing pipeline that samples `source` and writes every texel of `target`. Used by the bloom
+    /// and FXAA passes, which share a pipeline layout.
+    fn dispatch_post_process(&self, cmd_buffer: &mut CommandBuffer, pipeline: VkPipeline, source: GraphImage, target: GraphImage, push_consts: ComputePushConstants) -> Result<(), RenderError> {
+        let post_ds = {
+            let frame_data = self.get_frame_data();
+            let mut dyn_descriptors = frame_data.dynamic_descriptors.borrow_mut();
+
+            let ds = dyn_descriptors.allocate(&self.device, self.post_process_dl)?;
+
+            let mut writer = DescriptorWriter::new();
+            writer.write_combined_image_sampler(0, source.view, self.post_sampler, VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL);
+            writer.write_storage_image(1, target.view, VK_IMAGE_LAYOUT_GENERAL);
+            writer.update_set(&self.device, ds);
+
+            ds
+        };
+
+        cmd_buffer.bind_compute_pipeline(pipeline);
+
+        let descriptors: [VkDescriptorSet; 1] = [ post_ds ];
+        cmd_buffer.bind_compute_descriptor_sets(self.post_process_pl, 0, descriptors.as_slice());
+        cmd_buffer.bind_push_constants(self.post_process_pl, VK_SHADER_STAGE_COMPUTE_BIT, push_consts, 0);
+
+        let group_x = (target.extent.width  + POST_PROCESS_GROUP_SIZE - 1) / POST_PROCESS_GROUP_SIZE;
+        let group_y = (target.extent.height + POST_PROCESS_GROUP_SIZE - 1) / POST_PROCESS_GROUP_SIZE;
+        cmd_buffer.dispatch_compute(group_x, group_y, 1);
+
+        return Ok(());
+    }
+
+    /// Downsamples `source` into the next bloom mip. The first downsample reads the scene and applies the threshold.
+    fn downsample_bloom(&self, cmd_buffer: &mut CommandBuffer, source: GraphImage, target: GraphImage, is_first_mip: bool) -> Result<(), RenderError> {
+        let push_consts = ComputePushConstants{
+            data1: Float4::new(1.0 / source.extent.width as f32, 1.0 / source.extent.height as f32, if is_first_mip { 1.0 } else { 0.0 }, 0.0),
+            data2: Float4::new(self.post_settings.bloom_threshold, self.post_settings.bloom_knee, 0.0, 0.0),
+            data3: Float4::zero(),
+            data4: Float4::zero(),
+        };
+
+        return self.dispatch_post_process(cmd_buffer, self.bloom_downsample_p, source, target, push_consts);
+    }
+
+    /// Blurs the smaller bloom mip `source` and adds it onto `target`.
+    fn upsample_bloom(&self, cmd_buffer: &mut CommandBuffer, source: GraphImage, target: GraphImage) -> Result<(), RenderError> {
+        let push_consts = ComputePushConstants{
+            data1: Float4::new(1.0 / source.extent.width as f32, 1.0 / source.extent.height as f32, 1.0, 0.0),
+            data2: Float4::zero(),
+            data3: Float4::zero(),
+            data4: Float4::zero(),
+        };
+
+        return self.dispatch_post_process(cmd_buffer, self.bloom_upsample_p, source, target, push_consts);
+    }
+
+    /// Tonemaps the HDR scene into `target`, encoded for the swapchain. `bloom` is the first bloom mip, or a black
+    /// image when bloom is disabled.
+    fn tonemap(&self, cmd_buffer: &mut CommandBuffer, scene: GraphImage, bloom: VkImageView, target: GraphImage) -> Result<(), RenderError> {
+        let tonemap_ds = {
+            let frame_data = self.get_frame_data();
+            let mut dyn_descriptors = frame_data.dynamic_descriptors.borrow_mut();
+
+            let ds = dyn_descriptors.allocate(&self.device, self.tonemap_dl)?;
+
+            let mut writer = DescriptorWriter::new();
+            writer.write_combined_image_sampler(0, scene.view, self.post_sampler, VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL);
+            writer.write_combined_image_sampler(1, bloom, self.post_sampler, VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL);
+            writer.write_combined_image_sampler(2, self.color_grading_lut.view, self.post_sampler, VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL);
+            writer.write_storage_image(3, target.view, VK_IMAGE_LAYOUT_GENERAL);
+            writer.update_set(&self.device, ds);
+
+            ds
+        };
+
+        let settings = &self.post_settings;
+        let is_hdr10 = self.swapchain.surface_format.colorSpace == VK_COLOR_SPACE_HDR10_ST2084_EXT;
+
+        // Every bloom mip is added up on the way back up the chain, so the intensity is spread over the mips
+        let bloom_intensity    = if settings.bloom_enabled         { settings.bloom_intensity / settings.bloom_mip_count as f32 } else { 0.0 };
+        let lut_strength       = if settings.color_grading_enabled { settings.color_grading_strength } else { 0.0 };
+        let vignette_intensity = if settings.vignette_enabled      { settings.vignette_intensity     } else { 0.0 };
+
+        let push_consts = ComputePushConstants{
+            data1: Float4::new(settings.get_exposure(), bloom_intensity, settings.tonemapper.get_shader_id(), if is_hdr10 { 1.0 } else { 0.0 }),
+            data2: Float4::new(lut_strength, self.retained_lut.size as f32, vignette_intensity, settings.vignette_radius),
+            data3: Float4::new(settings.hdr_paper_white_nits, settings.hdr_peak_nits, 0.0, 0.0),
+            data4: Float4::zero(),
+        };
+
+        cmd_buffer.bind_compute_pipeline(self.tonemap_p);
+
+        let descriptors: [VkDescriptorSet; 1] = [ tonemap_ds ];
+        cmd_buffer.bind_compute_descriptor_sets(self.tonemap_pl, 0, descriptors.as_slice());
+        cmd_buffer.bind_push_constants(self.tonemap_pl, VK_SHADER_STAGE_COMPUTE_BIT, push_consts, 0);
+
+        let group_x = (target.extent.width  + POST_PROCESS_GROUP_SIZE - 1) / POST_PROCESS_GROUP_SIZE;
+        let group_y = (target.extent.height + POST_PROCESS_GROUP_SIZE - 1) / POST_PROCESS_GROUP_SIZE;
+        cmd_buffer.dispatch_compute(group_x, group_y, 1);
+
+        return Ok(());
+    }
+
+    fn apply_fxaa(&self, cmd_buffer: &mut CommandBuffer, source: GraphImage, target: GraphImage) -> Result<(), RenderError> {
+        let push_consts = ComputePushConstants{
+            data1: Float4::new(1.0 / source.extent.width as f32, 1.0 / source.extent.height as f32, 0.0, 0.0),
+            data2: Float4::zero(),
+            data3: Float4::zero(),
+            data4: Float4::zero(),
+        };
+
+        return self.dispatch_post_process(cmd_buffer, self.fxaa_p, source, target, push_consts);
+    }
+
+    /// Runs the simulation of every GPU simulated emitter, one dispatch per emitter. The emitters write separate
+    /// buffers, so the dispatches don't wait on each other.
+    fn simulate_particles(&self, cmd_buffer: &mut CommandBuffer, particle_draws: &ParticleDrawList) -> Result<(), RenderError> {
+        cmd_buffer.bind_compute_pipeline(self.particle_sim_p);
+
+        for draw in &particle_draws.draws {
+            let Some((step, reset)) = draw.gpu_step else {
+                continue;
+            };
+
+            let push_consts = ParticleSimPushConstants{
+                particle_buffer: draw.particle_buffer,
+                emitter_buffer:  particle_draws.emitter_buffer,
+                emitter_index:   draw.emitter_index,
+                capacity:        draw.capacity,
+                spawn_start:     step.spawn_start,
+                spawn_count:     step.spawn_count,
+                seed:            step.seed,
+                reset:           if reset { 1 } else { 0 },
+                delta_time:      step.delta_time,
+                _pad:            0,
+            };
+
+            cmd_buffer.bind_push_constants(self.particle_sim_pl, VK_SHADER_STAGE_COMPUTE_BIT, push_consts, 0);
+            cmd_buffer.dispatch_compute(draw.capacity.div_ceil(64), 1, 1);
+        }
+
+        return Ok(());
+    }
+
+    /// Draws every emitter's particles into `target`, the HDR scene, as billboards facing the camera. The particles
+    /// are tested against `depth` without writing to it.
+    fn draw_particles(&self, cmd_buffer: &mut CommandBuffer, target: GraphImage, depth: GraphImage, particle_draws: &ParticleDrawList) -> Result<(), RenderError> {
+        let color_attachment = make_color_attachment_info(target.view, None, VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL);
+
+        let mut depth_attachment = make_depth_attachment_info(depth.view, VK_IMAGE_LAYOUT_DEPTH_READ_ONLY_OPTIMAL);
+        depth_attachment.loadOp  = VK_ATTACHMENT_LOAD_OP_LOAD;
+        depth_attachment.storeOp = VK_ATTACHMENT_STORE_OP_NONE;
+
+        let draw_extent = target.get_extent_2d();
+        let render_info = make_rendering_info(draw_extent, &color_attachment, &depth_attachment);
+
+        // The camera's axes in world space, the columns of the inverse view
+        let camera_to_world = self.view_matrix.invert();
+        let camera_right    = camera_to_world.translate_point(Float4::new(1.0, 0.0, 0.0, 0.0));
+        let camera_up       = camera_to_world.translate_point(Float4::new(0.0, 1.0, 0.0, 0.0));
+
+        cmd_buffer.begin_rendering(render_info);
+        cmd_buffer.set_viewport(draw_extent.width as i32, draw_extent.height as i32, 0, 0);
+        cmd_buffer.set_scissor(draw_extent.width, draw_extent.height);
+
+        let sets: [VkDescriptorSet; 1] = [self.bindless.set];
+
+        for draw in &particle_draws.draws {
+            let pipeline = match draw.blend {
+                ParticleBlend::Additive   => self.particle_additive_p,
+                ParticleBlend::AlphaBlend => self.particle_alpha_p,
+            };
+
+            cmd_buffer.bind_graphics_pipeline(pipeline);
+            cmd_buffer.bind_graphics_descriptor_sets(self.particle_pl, 0, &sets);
+
+            let push_consts = ParticleDrawPushConstants{
+                view_proj:       self.scene_data.view_proj,
+                camera_right,
+                camera_up,
+                particle_buffer: draw.particle_buffer,
+                emitter_buffer:  particle_draws.emitter_buffer,
+                emitter_index:   draw.emitter_index,
+                texture_index:   draw.texture_index,
+            };
+
+            // One instance per slot, dead slots are discarded in the vertex shader
+            cmd_buffer.bind_push_constants(self.particle_pl, VK_SHADER_STAGE_VERTEX_BIT | VK_SHADER_STAGE_FRAGMENT_BIT, push_consts, 0);
+            cmd_buffer.draw(6, draw.capacity, 0, 0);
+        }
+
+        cmd_buffer.end_rendering();
+        return Ok(());
+    }
+
+    /// Draws this frame's sprite batches into `target`, which holds display colors. One instanced draw per batch.
+    fn draw_sprites(&self, cmd_buffer: &mut CommandBuffer, target: GraphImage, sprite_draws: &SpriteDrawList) -> Result<(), RenderError> {
+        let color_attachment = make_color_attachment_info(target.view, None, VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL);
+
+        let draw_extent = target.get_extent_2d();
+        let render_info = make_rendering_info(draw_extent, &color_attachment, ptr::null());
+
+        let sampler_index = match self.sprite_settings.filter {
+            SpriteFilter::Nearest => BINDLESS_SAMPLER_NEAREST,
+            SpriteFilter::Linear  => BINDLESS_SAMPLER_LINEAR,
+        };
+
+        let is_hdr10 = self.swapchain.surface_format.colorSpace == VK_COLOR_SPACE_HDR10_ST2084_EXT;
+        let output   = Float4::new(if is_hdr10 { 1.0 } else { 0.0 }, self.post_settings.hdr_paper_white_nits, 0.0, 0.0);
+
+        cmd_buffer.begin_rendering(render_info);
+        cmd_buffer.bind_graphics_pipeline(self.sprite_p);
+        cmd_buffer.set_viewport(draw_extent.width as i32, draw_extent.height as i32, 0, 0);
+        cmd_buffer.set_scissor(draw_extent.width, draw_extent.height);
+
+        let sets: [VkDescriptorSet; 1] = [self.bindless.set];
+        cmd_buffer.bind_graphics_descriptor_sets(self.sprite_pl, 0, &sets);
+
+        for batch in &sprite_draws.batches {
+            let push_consts = SpritePushConstants{
+                view_proj:     self.sprite_camera,
+                sprite_buffer: sprite_draws.sprite_buffer,
+                texture_index: batch.texture_index,
+                sampler_index,
+                output,
+            };
+
+            cmd_buffer.bind_push_constants(self.sprite_pl, VK_SHADER_STAGE_VERTEX_BIT | VK_SHADER_STAGE_FRAGMENT_BIT, push_consts, 0);
+            cmd_buffer.draw(6, batch.instance_count, 0, batch.first_instance);
+        }
+
+        cmd_buffer.end_rendering();
+        return Ok(());
+    }
+
+    /// Draws this frame's text batches into `target`, which holds display colors. World text is tested against
+    /// `depth` without writing to it, screen text is placed in pixels of `target`.
+    fn draw_text(&self, cmd_buffer: &mut CommandBuffer, target: GraphImage, depth: GraphImage, text_draws: &TextDrawList) -> Result<(), RenderError> {
+        let color_attachment = make_color_attachment_info(target.view, None, VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL);
+
+        let mut depth_attachment = make_depth_attachment_info(depth.view, VK_IMAGE_LAYOUT_DEPTH_READ_ONLY_OPTIMAL);
+        depth_attachment.loadOp  = VK_ATTACHMENT_LOAD_OP_LOAD;
+        depth_attachment.storeOp = VK_ATTACHMENT_STORE_OP_NONE;
+
+        let draw_extent = target.get_extent_2d();
+        let render_info = make_rendering_info(draw_extent, &color_attachment, &depth_attachment);
+
+        // Screen text is in pixels from the top-left of the target
+        let screen_view_proj = Float4x4::get_orthographic_matrix(0.0, draw_extent.width as f32, 0.0, draw_extent.height as f32, -1.0, 1.0);
+
+        let is_hdr10 = self.swapchain.surface_format.colorSpace == VK_COLOR_SPACE_HDR10_ST2084_EXT;
+        let output   = Float4::new(if is_hdr10 { 1.0 } else { 0.0 }, self.post_settings.hdr_paper_white_nits, 0.0, 0.0);
+
+        cmd_buffer.begin_rendering(render_info);
+        cmd_buffer.set_viewport(draw_extent.width as i32, draw_extent.height as i32, 0, 0);
+        cmd_buffer.set_scissor(draw_extent.width, draw_extent.height);
+
+        let sets: [VkDescriptorSet; 1] = [self.bindless.set];
+
+        let mut bound_space = None;
+        for batch in &text_draws.batches {
+            if bound_space != Some(batch.space) {
+                let pipeline = match batch.space {
+                    TextSpace::World  => self.text_world_p,
+                    TextSpace::Screen => self.text_p,
+                };
+
+                cmd_buffer.bind_graphics_pipeline(pipeline);
+                cmd_buffer.bind_graphics_descriptor_sets(self.text_pl, 0, &sets);
+                bound_space = Some(batch.space);
+            }
+
+            let push_consts = TextPushConstants{
+                view_proj:     if batch.space == TextSpace::World { self.scene_data.view_proj } else { screen_view_proj },
+                glyph_buffer:  text_draws.glyph_buffer,
+                texture_index: batch.texture_index,
+                mode:          if batch.is_sdf { 1 } else { 0 },
+                output,
+            };
+
+            cmd_buffer.bind_push_constants(self.text_pl, VK_SHADER_STAGE_VERTEX_BIT | VK_SHADER_STAGE_FRAGMENT_BIT, push_consts, 0);
+            cmd_buffer.draw(6, batch.instance_count, 0, batch.first_instance);
+        }
+
+        cmd_buffer.end_rendering();
+        return Ok(());
+    }
+
+    /// Draws this frame's debug lines into `target`, which holds display colors. The depth tested lines are tested
+    /// against `depth` without writing to it.
+    fn draw_debug_lines(&self, cmd_buffer: &mut CommandBuffer, target: GraphImage, depth: GraphImage, vertex_buffer: VkDeviceAddress) -> Result<(), RenderError> {
+        let color_attachment = make_color_attachment_info(target.view, None, VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL);
+
+        let mut depth_attachment = make_depth_attachment_info(depth.view, VK_IMAGE_LAYOUT_DEPTH_READ_ONLY_OPTIMAL);
+        depth_attachment.loadOp  = VK_ATTACHMENT_LOAD_OP_LOAD;
+        depth_attachment.storeOp = VK_ATTACHMENT_STORE_OP_NONE;
+
+        let draw_extent = target.get_extent_2d();
+        let render_info = make_rendering_info(draw_extent, &color_attachment, &depth_attachment);
+
+        let is_hdr10    = self.swapchain.surface_format.colorSpace == VK_COLOR_SPACE_HDR10_ST2084_EXT;
+        let push_consts = DebugLinePushConstants{
+            view_proj:     self.scene_data.view_proj,
+            vertex_buffer,
+            _pad:          0,
+            output:        Float4::new(if is_hdr10 { 1.0 } else { 0.0 }, self.post_settings.hdr_paper_white_nits, 0.0, 0.0),
+        };
+
+        let depth_tested_count = self.debug_lines.depth_tested.len() as u32;
+        let overlay_count      = self.debug_lines.overlay.len() as u32;
+
+        cmd_buffer.begin_rendering(render_info);
+        cmd_buffer.set_viewport(draw_extent.width as i32, draw_extent.height as i32, 0, 0);
+        cmd_buffer.set_scissor(draw_extent.width, draw_extent.height);
+
+        if depth_tested_count > 0 {
+            cmd_buffer.bind_graphics_pipeline(self.debug_line_p);
+            cmd_buffer.bind_push_constants(self.debug_line_pl, VK_SHADER_STAGE_VERTEX_BIT | VK_SHADER_STAGE_FRAGMENT_BIT, push_consts, 0);
+            cmd_buffer.draw(depth_tested_count, 1, 0, 0);
+        }
+
+        if overlay_count > 0 {
+            cmd_buffer.bind_graphics_pipeline(self.debug_line_overlay_p);
+            cmd_buffer.bind_push_constants(self.debug_line_pl, VK_SHADER_STAGE_VERTEX_BIT | VK_SHADER_STAGE_FRAGMENT_BIT, push_consts, 0);
+            cmd_buffer.draw(overlay_count, 1, depth_tested_count, 0);
+        }
+
+        cmd_buffer.end_rendering();
+        return Ok(());
+    }
+
+    /// Fills in the camera and shadow cascade parts of the scene data for this frame.
+    fn update_scene_data(&mut self) {
+        let sun_dir  = self.scene_data.sunlight_dir;
+        let cascades = compute_shadow_cascades(&self.shadow_settings, self.view_matrix, self.perspective_matrix, Float3::new(sun_dir.x, sun_dir.y, sun_dir.z));
+
+        let atlas_extent = self.shadow_settings.get_atlas_extent();
+
+        self.scene_data.view       = self.view_matrix;
+        self.scene_data.proj       = self.perspective_matrix;
+        self.scene_data.view_proj  = mul_rh(self.perspective_matrix, self.view_matrix);
+        self.scene_data.camera_pos = self.view_matrix.invert().translate_point(Float4::new(0.0, 0.0, 0.0, 1.0));
+
+        self.scene_data.cascade_view_proj   = cascades.view_proj;
+        self.scene_data.cascade_splits      = Float4::new(cascades.split_depths[0], cascades.split_depths[1], cascades.split_depths[2], cascades.split_depths[3]);
+        self.scene_data.cascade_texel_sizes = Float4::new(cascades.texel_sizes[0], cascades.texel_sizes[1], cascades.texel_sizes[2], cascades.texel_sizes[3]);
+        self.scene_data.shadow_params       = Float4::new(
+            cascades.count as f32,
+            self.shadow_settings.normal_bias,
+            self.shadow_settings.pcf_radius as f32,
+            1.0 / atlas_extent.width as f32,
+        );
+
+        // Lets the lit shader find the light cluster of a fragment, see get_fragment_cluster() in mesh.frag
+        let (near, far)      = get_clip_planes(self.perspective_matrix);
+        let swapchain_extent = self.swapchain.get_extent();
+        let slice_scale      = CLUSTER_GRID_Z as f32 / (far / near).ln();
+
+        let light_count = (self.point_lights.len() + self.spot_lights.len()).min(MAX_LIGHTS);
+
+        self.scene_data.light_params   = Float4::new(light_count as f32, near, far, 0.0);
+        self.scene_data.cluster_params = Float4::new(
+            swapchain_extent.width  as f32 / CLUSTER_GRID_X as f32,
+            swapchain_extent.height as f32 / CLUSTER_GRID_Y as f32,
+            slice_scale,
+            -near.ln() * slice_scale,
+        );
+    }
+
+    /// Picks the LOD every mesh is drawn with this frame from its screen size, see lod.rs. Instanced meshes use the
+    /// LOD of their largest instance on screen, skinned meshes only have LOD 0. Must run after update_scene_data().
+    fn update_mesh_lods(&mut self) {
+        let settings         = self.lod_settings;
+        let camera_pos       = Float3::new(self.scene_data.camera_pos.x, self.scene_data.camera_pos.y, self.scene_data.camera_pos.z);
+        let projection_scale = get_projection_terms(self.perspective_matrix).y;
+
+        let pick_lod = |mesh: &GpuMeshBuffers, screen_size: f32| -> u32 {
+            let lod = if let Some(forced) = settings.forced_lod {
+                (forced as usize).min(mesh.get_lods().len() - 1)
+            } else if settings.enabled {
+                select_lod(mesh.get_lods(), screen_size * settings.lod_bias, mesh.lod as usize, settings.hysteresis)
+            } else {
+                0
+            };
+
+            return lod as u32;
+        };
+
+        for mesh in self.meshes[0..self.mesh_count].iter_mut() {
+            let sphere = transform_bounding_sphere(mesh.transform, mesh.bounds);
+            mesh.lod = pick_lod(mesh, get_screen_size(sphere, camera_pos, projection_scale));
+        }
+
+        for (instanced, retained) in self.instanced_meshes.iter_mut().zip(self.retained_instanced_meshes.iter()) {
+            let mesh = &mut instanced.mesh;
+
+            let screen_size = retained.instances.iter()
+                .map(|instance| get_screen_size(transform_bounding_sphere(mul_rh(mesh.transform, instance.transform), mesh.bounds), camera_pos, projection_scale))
+                .fold(0.0, f32::max);
+
+            mesh.lod = pick_lod(mesh, screen_size);
+        }
+    }
+
+    /// Splits the draw list into one contiguous range per worker, and records each range into a secondary command
+    /// buffer on its own recording worker. Returns the secondary command buffers in draw order.
+    fn record_draws_in_parallel(&self, draw_context: &GeometryDrawContext, worker_count: usize, inheritance: &RenderingInheritance) -> Result<Vec<VkCommandBuffer>, RenderError> {
+        let frame_data = self.get_frame_data();
+        let mut worker_pools = frame_data.worker_command_pools.borrow_mut();
+
+        // Command buffers are allocated on the render thread, so the device is never shared with the workers.
+        let mut command_buffers = Vec::<&mut CommandBuffer>::with_capacity(worker_count);
+        for worker_pool in worker_pools.iter_mut().take(worker_count) {
+            command_buffers.push(worker_pool.acquire(&self.device)?);
+        }
+
+        let draw_count    = draw_context.get_draw_count();
+        let draws_per_job = (draw_count + worker_count - 1) / worker_count;
+
+        type RecordJob<'j> = Box<dyn FnOnce() -> Result<(), RenderError> + Send + 'j>;
+
+        let jobs: Vec<RecordJob> = command_buffers.iter_mut().enumerate().map(|(job_index, command_buffer)| {
+            let first_draw = (job_index * draws_per_job).min(draw_count);
+            let last_draw  = ((job_index + 1) * draws_per_job).min(draw_count);
+
+            Box::new(move || -> Result<(), RenderError> {
+                command_buffer.begin_secondary_recording(inheritance)?;
+                draw_context.record(command_buffer, first_draw..last_draw);
+                return command_buffer.end_recording();
+            }) as RecordJob
+        }).collect();
+
+        for result in self.recording_workers.run(jobs) {
+            match result {
+                Ok(recorded) => recorded?,
+                Err(failed)  => return Err(RenderError::RecordingWorkerPanicked(failed.0)),
+            }
+        }
+
+        return Ok(command_buffers.iter().map(|command_buffer| command_buffer.handle).collect());
+    }
+
+    // A function which takes the closure: fn func(cmd_buffer: &CommandBuffer)
+    fn immediate_submit<F>(&mut self, f: F) -> Result<(), RenderError> where
+        F: Fn(&CommandBuffer)
+    {
+        self.device.reset_fences(&self.imm_fence)?;
+        self.imm_command_buffer.reset()?;
+        self.imm_command_buffer.begin_recording()?;
+
+        // execute the function
+        f(&self.imm_command_buffer);
+
+        self.imm_command_buffer.end_recording()?;
+
+        let cmd_buffer_si = self.imm_command_buffer.get_submit_info();
+        let submit = make_submit_info(cmd_buffer_si, None, None);
+
+        self.device.queue_submit(QueueType::Graphics, submit, self.imm_fence)?;
+        self.device.wait_for_fences(self.imm_fence)?;
+
+        return Ok(());
+    }
+
+    pub fn render_editor(&mut self, command_buffer: &mut CommandBuffer, image_view: VkImageView) {
+        use crate::util::ffi::*;
+
+        call!(vendor::imgui::igRender);
+
+        let swapchain_extent = self.swapchain.get_extent();
+        let draw_extent      = VkExtent2D{ width: swapchain_extent.width, height: swapchain_extent.height };
+        let color_attachment = make_color_attachment_info(image_view, None, VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL);
+        let render_info = make_rendering_info(draw_extent, &color_attachment, std::ptr::null());
+
+        command_buffer.begin_rendering(render_info);
+        vendor::imgui::ig_vulkan_render_draw_data(command_buffer.handle, ptr::null_mut());
+        command_buffer.end_rendering();
+    }
+
+    pub fn on_editor_update(&mut self) {
+        use std::{ffi::CString, os::raw};
+
+        let window_name = CString::new("Skybox").expect("Failed to convert name to CString.");
+        let mut window_open = true;
+
+        if call!(igBegin, window_name.as_ptr(), &mut window_open, 0) {
+            let intensity_name = CString::new("Intensity").expect("Failed to convert name to CString.");
+            let blur_name      = CString::new("Blur").expect("Failed to convert name to CString.");
+
+			call!(igDragFloat, intensity_name.as_ptr(), &mut self.skybox_settings.intensity, 0.05, 0.0, 10.0, ptr::null(), 0);
+			call!(igDragFloat, blur_name.as_ptr(), &mut self.skybox_settings.blur, 0.01, 0.0, 1.0, ptr::null(), 0);
+        }
+
+        call!(igEnd);
+    }
+
+    fn process_render_command(&mut self, command: &RenderCommand) -> Result<(), RenderError> {
+        match command {
+            RenderCommand::UpdateCamera(camera) => {
+                self.view_matrix        = camera.view_matrix;
+                self.perspective_matrix = camera.perspective_matrix;
+            }
+
+            RenderCommand::CreateMesh(mesh_info) => {
+                assert!(self.mesh_count < MAX_LOADED_MESHES - 1);
+
+                let vertices = unsafe { std::slice::from_raw_parts(mesh_info.vertices, mesh_info.vertex_count) };
+                let indices  = unsafe { std::slice::from_raw_parts(mesh_info.indices,  mesh_info.index_count)  };
+
+                // Retain the mesh before uploading it. If the upload loses the device, recovery uploads it instead.
+                self.retained_meshes.push(RetainedMesh{
+                    vertices:    vertices.to_vec(),
+                    indices:     indices.to_vec(),
+                    lods:        mesh_info.lods.clone(),
+                    transform:   mesh_info.transform,
+                    material_id: mesh_info.material_id,
+                    engine_id:   mesh_info.engine_id,
+                });
+
+                //note: this will evventually be deferred.
+                let mut mesh = match self.upload_mesh(indices, vertices, &mesh_info.lods) {
+                    Ok(mesh)                       => mesh,
+                    Err(RenderError::DeviceLost)   => return Err(RenderError::DeviceLost),
+                    Err(error)                     => {
+                        self.retained_meshes.pop();
+                        return Err(error);
+                    },
+                };
+                mesh.transform      = mesh_info.transform;
+                mesh.material_index = self.find_material_index(mesh_info.material_id);
+
+                self.add_mesh(mesh, mesh_info.engine_id);
+            },
+
+            RenderCommand::CreateInstancedMesh(mesh_info) => {
+                let vertices = unsafe { std::slice::from_raw_parts(mesh_info.vertices, mesh_info.vertex_count) };
+                let indices  = unsafe { std::slice::from_raw_parts(mesh_info.indices,  mesh_info.index_count)  };
+
+                let retained = RetainedInstancedMesh{
+                    vertices:    vertices.to_vec(),
+                    indices:     indices.to_vec(),
+                    lods:        mesh_info.lods.clone(),
+                    instances:   mesh_info.instances.clone(),
+                    material_id: mesh_info.material_id,
+                    engine_id:   mesh_info.engine_id,
+                };
+
+                // If the upload loses the device, the retained copy is uploaded by the recovery instead.
+                match self.upload_instanced_mesh(&retained) {
+                    Ok(instanced_mesh)           => self.instanced_meshes.push(instanced_mesh),
+                    Err(RenderError::DeviceLost) => {
+                        self.retained_instanced_meshes.push(retained);
+                        return Err(RenderError::DeviceLost);
+                    },
+                    Err(error)                   => return Err(error),
+                }
+
+                self.retained_instanced_meshes.push(retained);
+            },
+
+            RenderCommand::CreateSkinnedMesh(mesh_info) => {
+                let vertices = unsafe { std::slice::from_raw_parts(mesh_info.vertices, mesh_info.vertex_count) };
+                let skin     = unsafe { std::slice::from_raw_parts(mesh_info.skin,     mesh_info.vertex_count) };
+                let indices  = unsafe { std::slice::from_raw_parts(mesh_info.indices,  mesh_info.index_count)  };
+
+                // Starts out in the bind pose
+                let retained = RetainedSkinnedMesh{
+                    vertices:       vertices.to_vec(),
+                    skin:           skin.to_vec(),
+                    indices:        indices.to_vec(),
+                    joint_matrices: vec![Float4x4::identity(); mesh_info.joint_count.max(1)],
+                    transform:      mesh_info.transform,
+                    material_id:    mesh_info.material_id,
+                    engine_id:      mesh_info.engine_id,
+                };
+
+                // If the upload loses the device, the retained copy is uploaded by the recovery instead.
+                match self.upload_skinned_mesh(&retained) {
+                    Ok(skinned_mesh)             => self.skinned_meshes.push(skinned_mesh),
+                    Err(RenderError::DeviceLost) => {
+                        self.retained_skinned_meshes.push(retained);
+                        return Err(RenderError::DeviceLost);
+                    },
+                    Err(error)                   => return Err(error),
+                }
+
+                self.retained_skinned_meshes.push(retained);
+            },
+
+            RenderCommand::UpdateJointMatrices(engine_id, joint_matrices) => {
+                let Some(index) = self.skinned_meshes.iter().position(|mesh| mesh.engine_id == *engine_id) else {
+                    println!("[WARN] :: RenderSystem :: Ignoring joint matrices for skinned mesh {}, which hasn't been created.", engine_id);
+                    return Ok(());
+                };
+
+                let joint_count = self.skinned_meshes[index].joint_count as usize;
+                if joint_matrices.len() != joint_count {
+                    println!("[WARN] :: RenderSystem :: Skinned mesh {} has {} joints, ignoring a palette of {}.", engine_id, joint_count, joint_matrices.len());
+                    return Ok(());
+                }
+
+                self.retained_skinned_meshes[index].joint_matrices = joint_matrices.clone();
+
+                let (joint_buffer, joint_buffer_address) = self.create_joint_buffer(joint_matrices)?;
+
+                let skinned_mesh = &mut self.skinned_meshes[index];
+                let old_buffer   = std::mem::replace(&mut skinned_mesh.joint_buffer, joint_buffer);
+                skinned_mesh.joint_buffer_address = joint_buffer_address;
+
+                self.retire_buffer(old_buffer);
+            },
+
+            RenderCommand::UpdateMeshInstances(engine_id, instances) => {
+                let Some(index) = self.instanced_meshes.iter().position(|mesh| mesh.engine_id == *engine_id) else {
+                    println!("[WARN] :: RenderSystem :: Ignoring instances for instanced mesh {}, which hasn't been created.", engine_id);
+                    return Ok(());
+                };
+
+                self.retained_instanced_meshes[index].instances = instances.clone();
+
+                let (instance_buffer, instance_buffer_address) = self.upload_instances(instances)?;
+
+                let instanced_mesh = &mut self.instanced_meshes[index];
+                let old_buffer     = std::mem::replace(&mut instanced_mesh.instance_buffer, instance_buffer);
+
+                instanced_mesh.instance_buffer_address = instance_buffer_address;
+                instanced_mesh.instance_count          = instances.len() as u32;
+                instanced_mesh.instance_bounds         = compute_instance_bounds(&instanced_mesh.mesh.aabb, instances);
+
+                self.retire_buffer(old_buffer);
+            },
+
+            RenderCommand::CreateTexture(texture_info) => {
+                let byte_count = (texture_info.width * texture_info.height * 4) as usize;
+                let pixels     = unsafe { std::slice::from_raw_parts(texture_info.pixels, byte_count) };
+
+                let retained = RetainedTexture{
+                    pixels:    pixels.to_vec(),
+                    width:     texture_info.width,
+                    height:    texture_info.height,
+                    format:    texture_info.format,
+                    engine_id: texture_info.engine_id,
+                };
+
+                // If the upload loses the device, the retained copy is uploaded by the recovery instead.
+                match self.upload_texture(&retained) {
+                    Ok(texture)                  => self.textures.push(texture),
+                    Err(RenderError::DeviceLost) => {
+                        self.retained_textures.push(retained);
+                        return Err(RenderError::DeviceLost);
+                    },
+                    Err(error)                   => return Err(error),
+                }
+
+                self.retained_textures.push(retained);
+            },
+
+            RenderCommand::CreateMaterial(material_info) => {
+                if let Some(material) = self.create_material(material_info) {
+                    self.materials.push(material);
+                }
+
+                self.retained_materials.push(*material_info);
+            },
+
+            RenderCommand::UpdateEnvironment(environment) => {
+                if environment.is_valid() {
+                    self.environment_loader.request(environment.clone());
+                } else {
+                    println!("[WARN] :: RenderSystem :: Ignoring an environment map whose pixels don't cover the map.");
+                }
+            },
+
+            RenderCommand::UpdateSkyboxSettings(settings) => {
+                self.skybox_settings = settings.sanitize();
+            },
+
+            RenderCommand::UpdateSsaoSettings(settings) => {
+                self.ssao_settings = settings.sanitize();
+            },
+
+            RenderCommand::UpdateTransparencySettings(settings) => {
+                self.transparency_settings = *settings;
+            },
+
+            RenderCommand::UpdateShadowSettings(settings) => {
+                self.shadow_settings = settings.sanitize();
+            },
+
+            RenderCommand::UpdatePointLights(lights) => {
+                self.point_lights = lights.clone();
+            },
+
+            RenderCommand::UpdateSpotLights(lights) => {
+                self.spot_lights = lights.clone();
+            },
+
+            RenderCommand::UpdateMsaaSampleCount(sample_count) => {
+                // Applied with the next resize, which recreates the geometry targets and pipelines
+                self.requested_msaa_samples = *sample_count;
+                self.swapchain.invalidate();
+            },
+
+            RenderCommand::UpdateGpuCullingSettings(settings) => {
+                self.culling_settings = *settings;
+            },
+
+            RenderCommand::UpdateDeferredSettings(settings) => {
+                self.deferred_settings = *settings;
+            },
+
+            RenderCommand::UpdateLodSettings(settings) => {
+                self.lod_settings = settings.sanitize();
+            },
+
+            RenderCommand::UpdatePostProcessSettings(settings) => {
+                self.post_settings = settings.sanitize();
+            },
+
+            RenderCommand::UpdateColorGradingLut(lut) => {
+                if lut.is_valid() {
+                    self.set_color_grading_lut(lut.clone())?;
+                } else {
+                    println!("[WARN] :: RenderSystem :: Ignoring a color grading LUT of size {} with {} bytes.", lut.size, lut.pixels.len());
+                }
+            },
+
+            RenderCommand::DebugSimulateDeviceLost => {
+                println!("[DEBUG] :: RenderSystem :: Simulating a lost device on the next frame.");
+                self.simulate_device_lost = true;
+            },
+
+            RenderCommand::DebugDumpRenderGraph(path) => {
+                self.render_graph_dump_path = Some(path.clone());
+            },
+
+            RenderCommand::CreateParticleEmitter(emitter) => {
+                if self.particle_emitters.iter().any(|state| state.emitter.engine_id == emitter.engine_id) {
+                    println!("[WARN] :: RenderSystem :: Ignoring particle emitter {}, which already exists.", emitter.engine_id);
+                    return Ok(());
+                }
+
+                self.add_particle_emitter(emitter)?;
+            },
+
+            RenderCommand::UpdateParticleEmitter(emitter) => {
+                let Some(index) = self.particle_emitters.iter().position(|state| state.emitter.engine_id == emitter.engine_id) else {
+                    println!("[WARN] :: RenderSystem :: Ignoring particle emitter {}, which hasn't been created.", emitter.engine_id);
+                    return Ok(());
+                };
+
+                // The particles survive the update, unless the ring has to change size
+                if emitter.get_capacity() != self.particle_emitters[index].emitter.get_capacity() {
+                    let (buffer, buffer_address) = self.create_particle_buffer(emitter)?;
+
+                    let state      = &mut self.particle_emitters[index];
+                    let old_buffer = std::mem::replace(&mut state.buffer, buffer);
+
+                    state.buffer_address = buffer_address;
+                    state.emitter        = emitter.get_validated();
+                    state.reset();
+
+                    self.retire_buffer(old_buffer);
+                } else {
+                    self.particle_emitters[index].emitter = emitter.get_validated();
+                }
+            },
+
+            RenderCommand::DestroyParticleEmitter(engine_id) => {
+                let Some(index) = self.particle_emitters.iter().position(|state| state.emitter.engine_id == *engine_id) else {
+                    println!("[WARN] :: RenderSystem :: Ignoring particle emitter {}, which hasn't been created.", engine_id);
+                    return Ok(());
+                };
+
+                let state = self.particle_emitters.remove(index);
+                self.retire_buffer(state.buffer);
+            },
+
+            RenderCommand::UpdateParticleSettings(settings) => {
+                if settings.simulation != self.particle_settings.simulation {
+                    for state in &mut self.particle_emitters {
+                        state.reset();
+                    }
+                }
+
+                self.particle_settings = *settings;
+            },
+
+            RenderCommand::UpdateSprites(sprites) => {
+                self.sprites = sprites.clone();
+            },
+
+            RenderCommand::UpdateSpriteCamera(view_proj) => {
+                self.sprite_camera = *view_proj;
+            },
+
+            RenderCommand::UpdateSpriteSettings(settings) => {
+                self.sprite_settings = *settings;
+            },
+
+            RenderCommand::UpdateText(text) => {
+                self.text = text.clone();
+            },
+
+            RenderCommand::UpdateDebugLines(lines) => {
+                self.debug_lines = lines.clone();
+
+                let max_vertices = MAX_DEBUG_LINES * 2;
+                if self.debug_lines.depth_tested.len() + self.debug_lines.overlay.len() > max_vertices {
+                    println!("[WARN] :: RenderSystem :: More than {} debug lines this frame, the rest are dropped.", MAX_DEBUG_LINES);
+
+                    self.debug_lines.depth_tested.truncate(max_vertices);
+                    self.debug_lines.overlay.truncate(max_vertices - self.debug_lines.depth_tested.len());
+                }
+            },
+
+            default => {},
+        }
+
+        return Ok(());
+    }
+
+    fn process_render_commands(&mut self, command_buffer: &RenderCommandBuffer) -> Result<(), RenderError> {
+        let mut device_lost = false;
+
+        for command in &command_buffer.commands {
+            match self.process_render_command(command) {
+                Ok(_) => {},
+                Err(RenderError::DeviceLost) => {
+                    // Recover immediately so the rest of the commands still apply to the new device.
+                    self.recover_from_device_lost()?;
+                    device_lost = true;
+                },
+                Err(error) => return Err(error),
+            }
+        }
+
+        if device_lost {
+            return Err(RenderError::DeviceLost);
+        }
+
+        return Ok(());
+    }
+
+    fn upload_texture(&mut self, texture: &RetainedTexture) -> Result<GpuTexture, RenderError> {
+        let extent = VkExtent3D{ width: texture.width, height: texture.height, depth: 1 };
+        let image  = self.upload_image(texture.pixels.as_ptr(), extent, texture.format.get_vk_format(), VK_IMAGE_USAGE_SAMPLED_BIT, true)?;
+
+        // Out of bindless slots, the texture is still uploaded so it can be destroyed with the rest
+        let id = match self.bindless.add_texture(&self.device, image.view) {
+            Some(id) => id,
+            None     => {
+                println!("[WARN] :: RenderSystem :: The bindless texture array is full ({} textures), texture {} shows up as the error texture.", MAX_BINDLESS_TEXTURES, texture.engine_id);
+                self.error_texture
+            },
+        };
+
+        return Ok(GpuTexture{ image, id, engine_id: texture.engine_id });
+    }
+
+    /// Returns the bindless id of the texture with the engine id, or `fallback` if the material doesn't use one.
+    /// Textures that haven't been created show up as the error checkerboard.
+    fn find_texture_id(&self, engine_id: Option<u64>, fallback: TextureId) -> TextureId {
+        let Some(engine_id) = engine_id else {
+            return fallback;
+        };
+
+        return match self.textures.iter().find(|texture| texture.engine_id == engine_id) {
+            Some(texture) => texture.id,
+            None          => {
+                println!("[WARN] :: RenderSystem :: A material uses texture {}, which hasn't been created.", engine_id);
+                self.error_texture
+            },
+        };
+    }
+
+    /// Meshes without a material, or with one that hasn't been created, use the default material.
+    fn find_material_index(&self, engine_id: Option<u64>) -> usize {
+        let Some(engine_id) = engine_id else {
+            return 0;
+        };
+
+        // Skip the default material, its engine id doesn't belong to the engine
+        return match self.materials.iter().skip(1).position(|material| material.engine_id == engine_id) {
+            Some(index) => index + 1,
+            None        => {
+                println!("[WARN] :: RenderSystem :: A mesh uses material {}, which hasn't been created.", engine_id);
+                0
+            },
+        };
+    }
+
+    /// Writes the material's constants into the next slot of the bindless material buffer. Returns None when the
+    /// buffer is full, meshes using the material fall back to the default material.
+    fn create_material(&mut self, info: &CreateMaterialInfo) -> Option<GpuMaterial> {
+        let index = self.materials.len() as u32;
+        if index >= MAX_BINDLESS_MATERIALS {
+            println!("[WARN] :: RenderSystem :: The bindless material buffer is full ({} materials), dropping material {}.", MAX_BINDLESS_MATERIALS, info.engine_id);
+            return None;
+        }
+
+        let constants = MaterialConstants{
+            base_color_factor:          info.base_color_factor,
+            emissive_factor:            Float4::new(info.emissive_factor.x, info.emissive_factor.y, info.emissive_factor.z, 0.0),
+            metallic_factor:            info.metallic_factor,
+            roughness_factor:           info.roughness_factor,
+            normal_scale:               info.normal_scale,
+            occlusion_strength:         info.occlusion_strength,
+            albedo_texture:             self.find_texture_id(info.albedo_texture,             self.white_texture).get_index(),
+            normal_texture:             self.find_texture_id(info.normal_texture,             self.flat_normal_texture).get_index(),
+            metallic_roughness_texture: self.find_texture_id(info.metallic_roughness_texture, self.white_texture).get_index(),
+            occlusion_texture:          self.find_texture_id(info.occlusion_texture,          self.white_texture).get_index(),
+            emissive_texture:           self.find_texture_id(info.emissive_texture,           self.white_texture).get_index(),
+            sampler:                    BINDLESS_SAMPLER_LINEAR,
+            alpha_cutoff:               info.alpha_cutoff,
+            alpha_mode:                 info.alpha_mode.get_shader_value(),
+        };
+
+        // New slots aren't read by frames in flight, so the buffer can be written right away
+        self.bindless.write_material(index, &constants);
+
+        return Some(GpuMaterial{ engine_id: info.engine_id, alpha_mode: info.alpha_mode });
+    }
+
+    fn upload_color_grading_lut(&mut self, lut: &ColorGradingLut) -> Result<AllocatedImage, RenderError> {
+        return self.upload_image(lut.pixels.as_ptr(), lut.get_extent(), VK_FORMAT_R8G8B8A8_UNORM, VK_IMAGE_USAGE_SAMPLED_BIT, false);
+    }
+
+    /// Replaces the color grading LUT. Frames in flight may still sample the previous LUT, so this waits for the GPU
+    /// before releasing it. LUTs are rarely replaced, so the stall is not a concern.
+    fn set_color_grading_lut(&mut self, lut: ColorGradingLut) -> Result<(), RenderError> {
+        let image = match self.upload_color_grading_lut(&lut) {
+            Ok(image)                    => image,
+            Err(RenderError::DeviceLost) => {
+                // The recovery uploads the retained copy instead
+                self.retained_lut = lut;
+                return Err(RenderError::DeviceLost);
+            },
+            Err(error)                   => return Err(error),
+        };
+
+        self.device.wait_idle();
+        self.device.destroy_image_memory(&mut self.color_grading_lut);
+
+        self.color_grading_lut = image;
+        self.retained_lut      = lut;
+
+        return Ok(());
+    }
+
+    /// Uploads the mips of a cubemap into an RGBA16F cube image.
+    fn upload_cube_image(&mut self, mips: &[CubeMap]) -> Result<AllocatedImage, RenderError> {
+        let texels    = pack_cube_mips(mips);
+        let data_size = texels.len() * std::mem::size_of::<[u16; 4]>();
+
+        let mut upload_buffer = self.device.create_buffer(data_size, VK_BUFFER_USAGE_TRANSFER_SRC_BIT, VMA_MEMORY_USAGE_CPU_TO_GPU)?;
+
+        let upload_memory = upload_buffer.get_allocation();
+        assert!(upload_memory != ptr::null_mut());
+
+        unsafe { std::ptr::copy(texels.as_ptr() as *const u8, upload_memory as *mut u8, data_size) };
+
+        let result = match self.device.allocate_cube_image_memory(mips[0].size, VK_FORMAT_R16G16B16A16_SFLOAT,
+            VK_IMAGE_USAGE_SAMPLED_BIT | VK_IMAGE_USAGE_TRANSFER_DST_BIT, mips.len() as u32) {
+            Ok(image)  => image,
+            Err(error) => {
+                self.device.destroy_buffer(&mut upload_buffer);
+                return Err(error);
+            },
+        };
+
+        let submit_result = self.immediate_submit(
+            |command_buffer: &CommandBuffer| {
+                command_buffer.transition_image(result.image, VK_IMAGE_LAYOUT_UNDEFINED, VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL);
+
+                let mut offset = 0;
+                for (level, mip) in mips.iter().enumerate() {
+                    let extent = VkExtent3D{ width: mip.size, height: mip.size, depth: 1 };
+                    command_buffer.copy_buffer_to_image_mip(&upload_buffer, offset as VkDeviceSize, &result, level as u32, 6, extent);
+
+                    offset += mip.texels.len() * std::mem::size_of::<[u16; 4]>();
+                }
+
+                command_buffer.transition_image(result.image, VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL, VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL);
+            }
+        );
+
+        self.device.destroy_buffer(&mut upload_buffer);
+        if let Err(error) = submit_result {
+            let mut result = result;
+            self.device.destroy_image_memory(&mut result);
+            return Err(error);
+        }
+
+        return Ok(result);
+    }
+
+    fn upload_environment(&mut self, cubes: &EnvironmentCubes) -> Result<GpuEnvironment, RenderError> {
+        let mut environment = GpuEnvironment::default();
+
+        let result = (|| -> Result<(), RenderError> {
+            environment.skybox     = self.upload_cube_image(&cubes.skybox)?;
+            environment.irradiance = self.upload_cube_image(&cubes.irradiance)?;
+            environment.specular   = self.upload_cube_image(&cubes.specular)?;
+            Ok(())
+        })();
+
+        if let Err(error) = result {
+            environment.destroy(&self.device);
+            return Err(error);
+        }
+
+        return Ok(environment);
+    }
+
+    /// Replaces the skybox and the image-based lighting. Like the color grading LUT, this waits for the GPU before
+    /// releasing the previous maps.
+    fn set_environment(&mut self, cubes: EnvironmentCubes) -> Result<(), RenderError> {
+        let environment = match self.upload_environment(&cubes) {
+            Ok(environment)              => environment,
+            Err(RenderError::DeviceLost) => {
+                // The recovery uploads the retained copy instead
+                self.retained_environment = cubes;
+                return Err(RenderError::DeviceLost);
+            },
+            Err(error)                   => return Err(error),
+        };
+
+        self.device.wait_idle();
+        self.environment.destroy(&self.device);
+
+        self.environment          = environment;
+        self.retained_environment = cubes;
+
+        return Ok(());
+    }
+
+    // Stores an uploaded mesh in the next free slot and lets the engine know it is ready.
+    fn add_mesh(&mut self, mesh: GpuMeshBuffers, engine_id: u64) {
+        let mesh_id = self.mesh_count;
+
+        self.meshes[self.mesh_count] = mesh;
+        self.mesh_count += 1;
+
+        let response = ReadyMeshInfo{
+            engine_id,
+            render_mesh_id: mesh_id as u64,
+        };
+
+        self.outgoing_commands.commands.push_back(RenderCommand::ReadyMesh(response));
+    }
+
+    /// Uploads the instances to a storage buffer the vertex shaders index with gl_InstanceIndex. The buffer always
+    /// has room for at least one instance, so it can be created for an empty instance list.
+    fn upload_instances(&mut self, instances: &[MeshInstance]) -> Result<(AllocatedBuffer, VkDeviceAddress), RenderError> {
+        let gpu_instances = pack_instances(instances);
 
-        let triangle_p = {
-            let mut builder = GraphicsPipelineBuilder::new();
+        let instance_buffer_size  = gpu_instances.len() * std::mem::size_of::<GpuInstance>();
+        let instance_buffer_flags = VK_BUFFER_USAGE_STORAGE_BUFFER_BIT | VK_BUFFER_USAGE_TRANSFER_DST_BIT | VK_BUFFER_USAGE_SHADER_DEVICE_ADDRESS_BIT;
 
-            //use the triangle layout we created
-            builder
-                .set_pipeline_layout(triangle_pl)
-            //connecting the vertex and pixel shaders to the pipeline
-                .set_shaders(colored_tri_vert_sm, colored_tri_frag_sm)
-            //it will draw triangles
-                .set_input_topology(VK_PRIMITIVE_TOPOLOGY_TRIANGLE_LIST)
-            //filled triangles
-                .set_polygon_mode(VK_POLYGON_MODE_FILL)
-            //no backface culling
-                .set_cull_mode(VK_CULL_MODE_BACK_BIT, VK_FRONT_FACE_CLOCKWISE)
-            //no multisampling
-                .set_multisampling_none()
-            //no blending
-                .disable_blending()
-            // additive blending
-                //.enabled_blending_additive()
-            // alpha blending
-                //.enabled_blending_alphablend()
-            //no depth testing
-                //.disable_depth_test()
-            // enabled depth testing
-                .enable_depth_test(true, VK_COMPARE_OP_LESS_OR_EQUAL)
-            //connect the image format we will draw into, from draw image
-                .set_color_attachment_format(scene_image.format)
-                .set_depth_format(depth_image.format);
+        let mut instance_buffer = self.device.create_buffer(instance_buffer_size.max(std::mem::size_of::<GpuInstance>()), instance_buffer_flags, VMA_MEMORY_USAGE_GPU_ONLY)?;
+        let instance_address    = self.device.get_buffer_device_address(&instance_buffer);
+
+        if gpu_instances.is_empty() {
+            return Ok((instance_buffer, instance_address));
+        }
 
-            //finally build the pipeline
-            builder.build(&device)
+        let mut staging_buffer = match self.device.create_buffer(instance_buffer_size, VK_BUFFER_USAGE_TRANSFER_SRC_BIT, VMA_MEMORY_USAGE_CPU_ONLY) {
+            Ok(buffer) => buffer,
+            Err(error) => {
+                self.device.destroy_buffer(&mut instance_buffer);
+                return Err(error);
+            },
         };
 
-        device.destroy_shader_module(colored_tri_vert_sm);
-        device.destroy_shader_module(colored_tri_frag_sm);
+        let memory = staging_buffer.info.pMappedData;
+        assert!(memory != ptr::null_mut());
+        unsafe { std::ptr::copy(gpu_instances.as_ptr(), memory as *mut GpuInstance, gpu_instances.len()) };
 
-        // Some Default samplers
-        //
+        let submit_result = self.immediate_submit(
+            |command_buffer: &CommandBuffer| {
+                command_buffer.copy_buffer(&instance_buffer, 0, &staging_buffer, 0, instance_buffer_size as VkDeviceSize);
+            }
+        );
 
-        let nearest_sampler = device.create_sampler(VK_FILTER_NEAREST, VK_FILTER_NEAREST);
-        let linear_sampler  = device.create_sampler(VK_FILTER_LINEAR,  VK_FILTER_LINEAR);
+        self.device.destroy_buffer(&mut staging_buffer);
 
-        // Setup imgui
-        //
-        //let editor_data = device.create_imgui_editor(swapchain.get_image_count() as u32);
+        if let Err(error) = submit_result {
+            self.device.destroy_buffer(&mut instance_buffer);
+            return Err(error);
+        }
 
-        let mut result = RenderSystem{
-            device,
-            swapchain,
-            scene_image,
-            depth_image,
-            frame_data,
-            frame_index: 0,
-            global_da,
-            draw_image_dl,
-            draw_image_ds,
-            scene_data:      GlobalSceneData::default(),
-            global_scene_dl: gpu_global_scene_dl,
-            imm_fence,
-            imm_command_pool,
-            imm_command_buffer,
-            //editor_data,
-            gradient_pl,
-            gradient_p,
-            compute_effects:        vec![compute_effect_gradient, sky_effect],
-            current_compute_effect: 1,
-            single_image_dl,
-            triangle_pl,
-            triangle_p,
-            meshes:                   [GpuMeshBuffers::default(); MAX_LOADED_MESHES],
-            mesh_count:               0,
-            white_image:              AllocatedImage::default(),
-            black_image:              AllocatedImage::default(),
-            grey_image:               AllocatedImage::default(),
-            error_checkerboard_image: AllocatedImage::default(),
-            default_sampler_linear:   linear_sampler,
-            default_sampler_nearest:  nearest_sampler,
-            view_matrix:              Float4x4::identity(),
-            perspective_matrix:       Float4x4::identity(),
-            outgoing_commands:        RenderCommandBuffer::default(),
+        return Ok((instance_buffer, instance_address));
+    }
+
+    fn upload_instanced_mesh(&mut self, retained: &RetainedInstancedMesh) -> Result<GpuInstancedMesh, RenderError> {
+        let mut mesh = self.upload_mesh(&retained.indices, &retained.vertices, &retained.lods)?;
+        mesh.material_index = self.find_material_index(retained.material_id);
+
+        let (instance_buffer, instance_buffer_address) = match self.upload_instances(&retained.instances) {
+            Ok(result) => result,
+            Err(error) => {
+                self.device.destroy_buffer(&mut mesh.index_buffer);
+                self.device.destroy_buffer(&mut mesh.vertex_buffer);
+                return Err(error);
+            },
         };
 
-        // Let's create some test images
-        //
+        return Ok(GpuInstancedMesh{
+            mesh,
+            instance_buffer,
+            instance_buffer_address,
+            instance_count:  retained.instances.len() as u32,
+            instance_bounds: compute_instance_bounds(&mesh.aabb, &retained.instances),
+            engine_id:       retained.engine_id,
+        });
+    }
 
-        let white_image = {
-            let packed_color = Float4::one().pack_unorm_u32();
-            let packed_ptr = (&packed_color as *const u32) as *const u8;
+    /// Copies a joint palette into a host visible storage buffer the skinned vertex shaders read.
+    fn create_joint_buffer(&self, joint_matrices: &[Float4x4]) -> Result<(AllocatedBuffer, VkDeviceAddress), RenderError> {
+        let buffer_size  = joint_matrices.len().max(1) * std::mem::size_of::<Float4x4>();
+        let buffer_flags = VK_BUFFER_USAGE_STORAGE_BUFFER_BIT | VK_BUFFER_USAGE_SHADER_DEVICE_ADDRESS_BIT;
 
-            result.upload_image(packed_ptr, VkExtent3D{ width: 1, height: 1, depth: 1 }, VK_FORMAT_R8G8B8A8_UNORM, VK_IMAGE_USAGE_SAMPLED_BIT, true)
-        };
+        let buffer = self.device.create_buffer(buffer_size, buffer_flags, VMA_MEMORY_USAGE_CPU_TO_GPU)?;
 
-        let grey_image = {
-            let packed_color = Float4::new(0.66, 0.66, 0.66, 1.0).pack_unorm_u32();
-            let packed_ptr = (&packed_color as *const u32) as *const u8;
+        let memory = buffer.get_allocation();
+        assert!(memory != ptr::null_mut());
+        unsafe { std::ptr::copy(joint_matrices.as_ptr(), memory as *mut Float4x4, joint_matrices.len()) };
+
+        return Ok((buffer, self.device.get_buffer_device_address(&buffer)));
+    }
+
+    fn upload_skinned_mesh(&mut self, retained: &RetainedSkinnedMesh) -> Result<GpuSkinnedMesh, RenderError> {
+        // Skinned meshes only have LOD 0
+        let mut mesh = self.upload_mesh(&retained.indices, &retained.vertices, &[])?;
+        mesh.transform      = retained.transform;
+        mesh.material_index = self.find_material_index(retained.material_id);
 
-            result.upload_image(packed_ptr, VkExtent3D{ width: 1, height: 1, depth: 1 }, VK_FORMAT_R8G8B8A8_UNORM, VK_IMAGE_USAGE_SAMPLED_BIT, true)
+        // The skin stream goes through the same staging path as the instances
+        let skin_buffer_size  = retained.skin.len().max(1) * std::mem::size_of::<SkinVertex>();
+        let skin_buffer_flags = VK_BUFFER_USAGE_STORAGE_BUFFER_BIT | VK_BUFFER_USAGE_TRANSFER_DST_BIT | VK_BUFFER_USAGE_SHADER_DEVICE_ADDRESS_BIT;
+
+        let destroy_mesh = |device: &Device, mesh: &mut GpuMeshBuffers| {
+            device.destroy_buffer(&mut mesh.index_buffer);
+            device.destroy_buffer(&mut mesh.vertex_buffer);
         };
 
-        let black_image = {
-            let packed_color = Float4::zero().pack_unorm_u32();
-            let packed_ptr = (&packed_color as *const u32) as *const u8;
+        let mut skin_buffer = match self.device.create_buffer(skin_buffer_size, skin_buffer_flags, VMA_MEMORY_USAGE_GPU_ONLY) {
+            Ok(buffer) => buffer,
+            Err(error) => {
+                destroy_mesh(&self.device, &mut mesh);
+                return Err(error);
+            },
+        };
 
-            result.upload_image(packed_ptr, VkExtent3D{ width: 1, height: 1, depth: 1 }, VK_FORMAT_R8G8B8A8_UNORM, VK_IMAGE_USAGE_SAMPLED_BIT, true)
+        let mut staging_buffer = match self.device.create_buffer(skin_buffer_size, VK_BUFFER_USAGE_TRANSFER_SRC_BIT, VMA_MEMORY_USAGE_CPU_ONLY) {
+            Ok(buffer) => buffer,
+            Err(error) => {
+                destroy_mesh(&self.device, &mut mesh);
+                self.device.destroy_buffer(&mut skin_buffer);
+                return Err(error);
+            },
         };
 
-        let checkerboard = {
-            let packed_magenta = Float4::new(1.0, 0.0, 1.0, 1.0).pack_unorm_u32();
-            let packed_black   = Float4::zero().pack_unorm_u32();
+        let memory = staging_buffer.info.pMappedData;
+        assert!(memory != ptr::null_mut());
+        unsafe { std::ptr::copy(retained.skin.as_ptr(), memory as *mut SkinVertex, retained.skin.len()) };
 
-            let mut pixels: [u32; 16*16] = [0; 16*16];
-            for x in 0..16 {
-          		for y in 0..16 {
-         			pixels[y*16 + x] = if ((x % 2) ^ (y % 2) > 0) { packed_magenta } else { packed_black };
-          		}
+        let submit_result = self.immediate_submit(
+            |command_buffer: &CommandBuffer| {
+                command_buffer.copy_buffer(&skin_buffer, 0, &staging_buffer, 0, skin_buffer_size as VkDeviceSize);
             }
+        );
 
-            result.upload_image(pixels.as_ptr() as *const u8, VkExtent3D{ width: 16, height: 16, depth: 1 }, VK_FORMAT_R8G8B8A8_UNORM, VK_IMAGE_USAGE_SAMPLED_BIT, true)
+        self.device.destroy_buffer(&mut staging_buffer);
+
+        let joint_result = submit_result.and_then(|_| self.create_joint_buffer(&retained.joint_matrices));
+        let (joint_buffer, joint_buffer_address) = match joint_result {
+            Ok(result) => result,
+            Err(error) => {
+                destroy_mesh(&self.device, &mut mesh);
+                self.device.destroy_buffer(&mut skin_buffer);
+                return Err(error);
+            },
         };
 
-        result.white_image = white_image;
-        result.grey_image  = grey_image;
-        result.black_image = black_image;
-        result.error_checkerboard_image = checkerboard;
+        return Ok(GpuSkinnedMesh{
+            mesh,
+            skin_buffer,
+            skin_buffer_address: self.device.get_buffer_device_address(&skin_buffer),
+            joint_buffer,
+            joint_buffer_address,
+            joint_count:         retained.joint_matrices.len() as u32,
+            engine_id:           retained.engine_id,
+        });
+    }
 
-        return result;
+    /// Releases a buffer that frames in flight may still read. Commands are processed before the current frame
+    /// slot is rendered, and the slot before it is the last one to be waited on, so its deletion queue is only
+    /// flushed once every frame that could use the buffer has finished.
+    fn retire_buffer(&self, buffer: AllocatedBuffer) {
+        let frame_count = self.frame_data.len();
+        let last_slot   = (self.swapchain.frame_index + frame_count - 1) % frame_count;
+
+        self.frame_data[last_slot].deletion_queues.borrow_mut().buffer_deletion_queue.push_back(buffer);
     }
 
-    fn get_frame_data(&self) -> Rc<PerFrameData> {
-        self.frame_data[self.swapchain.frame_index].clone()
+    /// Returns RenderError::DeviceLost if the device was lost while processing the commands. In that case the
+    /// device has already been recreated and the commands have been applied to the new device.
+    pub fn submit_render_commands(&mut self, render_command_buffer: RenderCommandBuffer) -> Result<(), RenderError> {
+        if self.is_destroyed {
+            return Err(RenderError::DeviceRecoveryFailed("The renderer has no device".to_string()));
+        }
+
+        return self.process_render_commands(&render_command_buffer);
     }
 
-    fn draw_geometry(&self, cmd_buffer: &mut CommandBuffer) {
-        if false { // Example of dynamically allocating descriptors / transient buffer memory
-            // This is, like, definately not how I want to do this.
-            let frame_data = self.get_frame_data();
-            let mut deletion_queues = frame_data.deletion_queues.borrow_mut();
-            let mut dyn_descriptors = frame_data.dynamic_descriptors.borrow_mut();
+    /// Returns RenderError::DeviceLost if the device was lost during the frame. In that case the device has
+    /// already been recreated, the frame is dropped and rendering resumes on the next frame.
+    pub fn render(&mut self) -> Result<(), RenderError> {
+        if self.is_destroyed {
+            return Err(RenderError::DeviceRecoveryFailed("The renderer has no device".to_string()));
+        }
 
-            let scene_data = self.device.create_buffer(std::mem::size_of::<GlobalSceneData>(), VK_BUFFER_USAGE_UNIFORM_BUFFER_BIT, VMA_MEMORY_USAGE_CPU_TO_GPU);
-            deletion_queues.buffer_deletion_queue.push_back(scene_data);
+        match self.render_frame() {
+            Err(RenderError::DeviceLost) => {
+                self.recover_from_device_lost()?;
+                return Err(RenderError::DeviceLost);
+            },
+            result => return result,
+        }
+    }
 
-            // Copy the scene data to the gpu buffer
-            let mut memory = scene_data.get_allocation();
-            assert!(memory != ptr::null_mut());
+    /// Tears down the device and everything created from it, then creates a new device and re-uploads every
+    /// resource from the retained CPU copies. Camera state, the render settings and the swapchain size carry
+    /// over to the new device. The MSAA sample count is applied by the resize on the next frame.
+    fn recover_from_device_lost(&mut self) -> Result<(), RenderError> {
+        println!("[WARN] :: RenderSystem :: The GPU device was lost. Recreating the device and its resources.");
 
-            let mut memory_as_scene = memory as *mut GlobalSceneData;
-            unsafe { std::ptr::copy(&self.scene_data, memory_as_scene, 1) };
+        // Destroying objects created from a lost device is allowed, it just releases their memory.
+        self.destroy();
 
-            //create a descriptor set that binds that buffer and update it
-           	let global_ds = dyn_descriptors.allocate(&self.device, self.global_scene_dl);
+        let mut recovered = match RenderSystem::new(self.create_info) {
+            Ok(system) => system,
+            Err(error) => return Err(RenderError::DeviceRecoveryFailed(error.to_string())),
+        };
 
-           	let mut writer = DescriptorWriter::new();
-           	writer.write_buffer(0, scene_data.buffer, std::mem::size_of::<GlobalSceneData>() as u64, 0, VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER);
-           	writer.update_set(&self.device, global_ds);
+        // Carry over the CPU-side state that doesn't depend on the device
+        recovered.view_matrix            = self.view_matrix;
+        recovered.perspective_matrix     = self.perspective_matrix;
+        recovered.shadow_settings        = self.shadow_settings;
+        recovered.skybox_settings        = self.skybox_settings;
+        recovered.transparency_settings  = self.transparency_settings;
+        recovered.deferred_settings      = self.deferred_settings;
+        recovered.lod_settings           = self.lod_settings;
+        recovered.point_lights           = std::mem::take(&mut self.point_lights);
+        recovered.spot_lights            = std::mem::take(&mut self.spot_lights);
+        recovered.post_settings          = self.post_settings;
+        recovered.ssao_settings          = self.ssao_settings;
+        recovered.requested_msaa_samples = self.requested_msaa_samples;
+        recovered.culling_settings       = self.culling_settings;
+        recovered.particle_settings      = self.particle_settings;
+        recovered.sprites                = std::mem::take(&mut self.sprites);
+        recovered.sprite_camera          = self.sprite_camera;
+        recovered.sprite_settings        = self.sprite_settings;
+        recovered.text                   = std::mem::take(&mut self.text);
+
+        // The new swapchain is created at the surface's current extent. Resize it to the last known window size
+        // on the next frame, for platforms where the surface doesn't report an extent.
+        recovered.swapchain.on_resize(self.swapchain.cached_width, self.swapchain.cached_height);
+
+        std::mem::swap(&mut recovered.outgoing_commands, &mut self.outgoing_commands);
+
+        // The recording workers and the environment loader don't depend on the device, so the running ones are kept,
+        // along with the environment being built
+        std::mem::swap(&mut recovered.recording_workers, &mut self.recording_workers);
+        std::mem::swap(&mut recovered.environment_loader, &mut self.environment_loader);
+
+        // Re-upload every texture, then every material, so the meshes can find their materials again.
+        let retained_textures = std::mem::take(&mut self.retained_textures);
+        for retained in &retained_textures {
+            match recovered.upload_texture(retained) {
+                Ok(texture) => recovered.textures.push(texture),
+                Err(error)  => {
+                    recovered.destroy();
+                    return Err(RenderError::DeviceRecoveryFailed(error.to_string()));
+                },
+            }
         }
+        recovered.retained_textures = retained_textures;
 
-        let color_attachment = make_color_attachment_info(self.scene_image.view, None, VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL);
-       	let depth_attachment = make_depth_attachment_info(self.depth_image.view, VK_IMAGE_LAYOUT_DEPTH_ATTACHMENT_OPTIMAL);
+        let retained_materials = std::mem::take(&mut self.retained_materials);
+        for retained in &retained_materials {
+            if let Some(material) = recovered.create_material(retained) {
+                recovered.materials.push(material);
+            }
+        }
+        recovered.retained_materials = retained_materials;
 
-        let draw_extent = VkExtent2D{ width: self.scene_image.dims.width, height: self.scene_image.dims.height };
-        let render_info = make_rendering_info(draw_extent, &color_attachment, &depth_attachment);
+        if let Err(error) = recovered.set_color_grading_lut(self.retained_lut.clone()) {
+            recovered.destroy();
+            return Err(RenderError::DeviceRecoveryFailed(error.to_string()));
+        }
 
-        cmd_buffer.begin_rendering(render_info);
+        if let Err(error) = recovered.set_environment(std::mem::take(&mut self.retained_environment)) {
+            recovered.destroy();
+            return Err(RenderError::DeviceRecoveryFailed(error.to_string()));
+        }
 
-        cmd_buffer.bind_graphics_pipeline(self.triangle_p);
-        cmd_buffer.set_viewport(draw_extent.width as i32, (draw_extent.height  as i32), 0, 0);
-        cmd_buffer.set_scissor(draw_extent.width, draw_extent.height);
+        // Re-upload every mesh. Mesh ids are stable since meshes are uploaded in the same order as before.
+        let previous_mesh_count = self.mesh_count;
+        let retained_meshes     = std::mem::take(&mut self.retained_meshes);
 
-        // let's bind a texture!
-        {
-            let frame_data = self.get_frame_data();
-            let mut dyn_descriptors = frame_data.dynamic_descriptors.borrow_mut();
+        for retained in &retained_meshes {
+            let mut mesh = match recovered.upload_mesh(&retained.indices, &retained.vertices, &retained.lods) {
+                Ok(mesh)   => mesh,
+                Err(error) => {
+                    recovered.destroy();
+                    return Err(RenderError::DeviceRecoveryFailed(error.to_string()));
+                },
+            };
+            mesh.transform      = retained.transform;
+            mesh.material_index = recovered.find_material_index(retained.material_id);
+
+            if recovered.mesh_count < previous_mesh_count {
+                recovered.meshes[recovered.mesh_count] = mesh;
+                recovered.mesh_count += 1;
+            } else {
+                // This mesh was being uploaded when the device was lost, so the engine hasn't heard about it yet.
+                recovered.add_mesh(mesh, retained.engine_id);
+            }
+        }
 
-           	let image_set = dyn_descriptors.allocate(&self.device, self.single_image_dl);
+        recovered.retained_meshes = retained_meshes;
 
-            let mut writer = DescriptorWriter::new();
-           	writer.write_combined_image_sampler(0, self.error_checkerboard_image.view, self.default_sampler_nearest, VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL);
-           	writer.update_set(&self.device, image_set);
+        let retained_instanced_meshes = std::mem::take(&mut self.retained_instanced_meshes);
+        for retained in &retained_instanced_meshes {
+            match recovered.upload_instanced_mesh(retained) {
+                Ok(instanced_mesh) => recovered.instanced_meshes.push(instanced_mesh),
+                Err(error)         => {
+                    recovered.destroy();
+                    return Err(RenderError::DeviceRecoveryFailed(error.to_string()));
+                },
+            }
+        }
+        recovered.retained_instanced_meshes = retained_instanced_meshes;
+
+        let retained_skinned_meshes = std::mem::take(&mut self.retained_skinned_meshes);
+        for retained in &retained_skinned_meshes {
+            match recovered.upload_skinned_mesh(retained) {
+                Ok(skinned_mesh) => recovered.skinned_meshes.push(skinned_mesh),
+                Err(error)       => {
+                    recovered.destroy();
+                    return Err(RenderError::DeviceRecoveryFailed(error.to_string()));
+                },
+            }
+        }
+        recovered.retained_skinned_meshes = retained_skinned_meshes;
 
-            let sets: [VkDescriptorSet; 1] = [image_set];
-            cmd_buffer.bind_graphics_descriptor_sets(self.triangle_pl, 0, &sets);
+        // The emitters start over, their particles were on the lost device
+        for state in &self.particle_emitters {
+            if let Err(error) = recovered.add_particle_emitter(&state.emitter) {
+                recovered.destroy();
+                return Err(RenderError::DeviceRecoveryFailed(error.to_string()));
+            }
         }
 
-        let persp_view = mul_rh(self.perspective_matrix, self.view_matrix);
+        *self = recovered;
 
-        for i in 0..self.mesh_count {
-            let mesh = &self.meshes[i];
+        println!("[INFO] :: RenderSystem :: Recovered from a lost device.");
+        return Ok(());
+    }
 
-            let push_consts = GpuDrawPushConstants {
-                world_matrix:  mul_rh(persp_view, mesh.transform),
-                vertex_buffer: mesh.vertex_buffer_address,
-            };
+    /// Describes the frame: the sun's shadow cascades, the light culling, the geometry pass, the skybox,
+    /// the particles, the post-processing chain and the copy into the swapchain. With `draw_buffers`, the geometry is culled and
+    /// drawn on the GPU, and the Hi-Z pyramid is rebuilt from the frame's depth for the next frame.
+    fn build_render_graph<'a>(&'a self, frame_lights: &AllocatedBuffer, light_count: usize, draw_buffers: Option<&'a GpuDrawBuffers>, transparent_draws: Option<&'a Vec<TransparentDraw>>, particle_draws: Option<&'a ParticleDrawList>, sprite_draws: Option<&'a SpriteDrawList>, text_draws: Option<&'a TextDrawList>, debug_line_buffer: Option<VkDeviceAddress>) -> RenderGraph<'a> {
+        let mut graph = RenderGraph::new();
+
+        let swapchain_extent = self.swapchain.get_extent();
+
+        let swapchain_image = graph.import_image("swapchain", GraphImage{
+            image:  self.swapchain.get_swapchain_image(),
+            view:   self.swapchain.get_swapchain_image_view(),
+            extent: swapchain_extent,
+            format: self.swapchain.get_format(),
+        }, VK_IMAGE_LAYOUT_UNDEFINED, Some(VK_IMAGE_LAYOUT_PRESENT_SRC_KHR));
+
+        let scene_image = graph.create_image("scene", ImageDesc{ extent: swapchain_extent, format: SCENE_IMAGE_FORMAT, samples: VK_SAMPLE_COUNT_1_BIT });
+        let depth_image = graph.create_image("depth", ImageDesc{ extent: swapchain_extent, format: self.device.get_depth_format(), samples: VK_SAMPLE_COUNT_1_BIT });
+        let shadow_map  = graph.create_image("shadow_map", ImageDesc{ extent: self.shadow_settings.get_atlas_extent(), format: SHADOW_MAP_FORMAT, samples: VK_SAMPLE_COUNT_1_BIT });
+
+        let lights     = graph.import_buffer("lights", frame_lights);
+        let light_grid = graph.import_buffer("light_grid", &self.light_grid);
+
+        // Draw the sun's shadow cascades
+        graph.add_pass("shadows")
+            .write_image(shadow_map, ImageAccess::DepthAttachment)
+            .execute(move |command_buffer, resources| {
+                return self.draw_shadows(command_buffer, resources.get_image(shadow_map));
+            });
+
+        // Assign the point and spot lights to clusters
+        graph.add_pass("light_cull")
+            .read_buffer(lights, BufferAccess::ComputeShaderRead)
+            .write_buffer(light_grid, BufferAccess::ComputeShaderWrite)
+            .execute(move |command_buffer, resources| {
+                return self.cull_lights(command_buffer, resources.get_buffer(lights), light_count);
+            });
+
+        // Cull the draw list on the GPU, against the frustum and last frame's Hi-Z pyramid
+        let gpu_draws = if let Some(buffers) = draw_buffers {
+            let hiz_layout = if self.hiz.is_valid { VK_IMAGE_LAYOUT_GENERAL } else { VK_IMAGE_LAYOUT_UNDEFINED };
+            let hiz_image  = graph.import_image("hiz", GraphImage::from_allocated(&self.hiz.image), hiz_layout, Some(VK_IMAGE_LAYOUT_GENERAL));
+
+            let draw_objects  = graph.import_buffer("draw_objects",  &buffers.objects);
+            let draw_batches  = graph.import_buffer("draw_batches",  &buffers.batches);
+            let draw_commands = graph.import_buffer("draw_commands", &buffers.commands);
+            let draw_counts   = graph.import_buffer("draw_counts",   &buffers.counts);
+
+            graph.add_pass("draw_cull")
+                .read_image(hiz_image, ImageAccess::ComputeSampled)
+                .read_buffer(draw_objects, BufferAccess::ComputeShaderRead)
+                .read_buffer(draw_batches, BufferAccess::ComputeShaderRead)
+                .write_buffer(draw_commands, BufferAccess::ComputeShaderWrite)
+                .write_buffer(draw_counts, BufferAccess::ComputeShaderWrite)
+                .execute(move |command_buffer, resources| {
+                    return self.cull_draws(command_buffer, buffers, resources.get_image(hiz_image));
+                });
+
+            Some((hiz_image, draw_commands, draw_counts))
+        } else {
+            None
+        };
 
-            cmd_buffer.bind_push_constants(self.triangle_pl, VK_SHADER_STAGE_VERTEX_BIT, push_consts, 0);
-            cmd_buffer.bind_index_buffer(&mesh.index_buffer);
-            cmd_buffer.draw_indexed(mesh.index_count, 1, 0, 0, 0);
-        }
+        let is_deferred = self.create_info.render_path == RenderPath::Deferred;
 
-        cmd_buffer.end_rendering();
-    }
+        // The ambient light the forward geometry adds to the scene, for SSAO to occlude. The deferred lighting reads
+        // the AO itself.
+        let ambient_image = if self.ssao_settings.enabled && !is_deferred {
+            Some(graph.create_image("ambient", ImageDesc{ extent: swapchain_extent, format: AMBIENT_IMAGE_FORMAT, samples: VK_SAMPLE_COUNT_1_BIT }))
+        } else {
+            None
+        };
 
-    // A function which takes the closure: fn func(cmd_buffer: &CommandBuffer)
-    fn immediate_submit<F>(&mut self, f: F) where
-        F: Fn(&CommandBuffer)
-    {
-        self.device.reset_fences(&self.imm_fence);
-        self.imm_command_buffer.reset();
-        self.imm_command_buffer.begin_recording();
+        // The surfaces the deferred lighting pass reads: albedo, normals and material
+        let gbuffer_images = if is_deferred {
+            Some((
+                graph.create_image("gbuffer_albedo",   ImageDesc{ extent: swapchain_extent, format: GBUFFER_ALBEDO_FORMAT,   samples: VK_SAMPLE_COUNT_1_BIT }),
+                graph.create_image("gbuffer_normal",   ImageDesc{ extent: swapchain_extent, format: GBUFFER_NORMAL_FORMAT,   samples: VK_SAMPLE_COUNT_1_BIT }),
+                graph.create_image("gbuffer_material", ImageDesc{ extent: swapchain_extent, format: GBUFFER_MATERIAL_FORMAT, samples: VK_SAMPLE_COUNT_1_BIT }),
+            ))
+        } else {
+            None
+        };
 
-        // execute the function
-        f(&self.imm_command_buffer);
+        // Draw geometry
+        //   On the deferred path the geometry only fills the G-buffer, it is lit after SSAO.
+        //   With MSAA the geometry is drawn into multisampled targets, which are resolved into the scene color and
+        //   depth. The depth is resolved as well, so later passes always find the scene depth in depth_image.
+        if let Some((albedo_image, normal_image, material_image)) = gbuffer_images {
+            // The shadow map isn't sampled, but it is bound through the scene set shared with the lit meshes
+            let mut geometry_pass = graph.add_pass("geometry")
+                .read_image(shadow_map, ImageAccess::FragmentSampled)
+                .write_image(scene_image, ImageAccess::ColorAttachment)
+                .write_image(albedo_image, ImageAccess::ColorAttachment)
+                .write_image(normal_image, ImageAccess::ColorAttachment)
+                .write_image(material_image, ImageAccess::ColorAttachment)
+                .write_image(depth_image, ImageAccess::DepthAttachment);
+
+            if let Some((_, draw_commands, draw_counts)) = gpu_draws {
+                geometry_pass = geometry_pass
+                    .read_buffer(draw_commands, BufferAccess::IndirectRead)
+                    .read_buffer(draw_counts, BufferAccess::IndirectRead);
+            }
 
-        self.imm_command_buffer.end_recording();
+            geometry_pass.execute(move |command_buffer, resources| {
+                let gbuffer = (resources.get_image(albedo_image), resources.get_image(normal_image), resources.get_image(material_image));
+                let lights  = resources.get_buffer(lights);
+
+                return self.draw_gbuffer(command_buffer, resources.get_image(scene_image), gbuffer, resources.get_image(depth_image), resources.get_image(shadow_map), lights, draw_buffers);
+            });
+        } else if self.msaa_samples != VK_SAMPLE_COUNT_1_BIT {
+            let msaa_scene_image = graph.create_image("scene_msaa", ImageDesc{ extent: swapchain_extent, format: SCENE_IMAGE_FORMAT, samples: self.msaa_samples });
+            let msaa_depth_image = graph.create_image("depth_msaa", ImageDesc{ extent: swapchain_extent, format: self.device.get_depth_format(), samples: self.msaa_samples });
+
+            let msaa_ambient_image = ambient_image.map(|_| {
+                graph.create_image("ambient_msaa", ImageDesc{ extent: swapchain_extent, format: AMBIENT_IMAGE_FORMAT, samples: self.msaa_samples })
+            });
+
+            let mut geometry_pass = graph.add_pass("geometry")
+                .read_image(shadow_map, ImageAccess::FragmentSampled)
+                .read_buffer(lights, BufferAccess::FragmentShaderRead)
+                .read_buffer(light_grid, BufferAccess::FragmentShaderRead)
+                .write_image(msaa_scene_image, ImageAccess::ColorAttachment)
+                .write_image(msaa_depth_image, ImageAccess::DepthAttachment)
+                .write_image(scene_image, ImageAccess::ColorResolve)
+                .write_image(depth_image, ImageAccess::DepthResolve);
+
+            if let (Some(ambient_image), Some(msaa_ambient_image)) = (ambient_image, msaa_ambient_image) {
+                geometry_pass = geometry_pass
+                    .write_image(msaa_ambient_image, ImageAccess::ColorAttachment)
+                    .write_image(ambient_image, ImageAccess::ColorResolve);
+            }
 
-        let cmd_buffer_si = self.imm_command_buffer.get_submit_info();
-        let submit = make_submit_info(cmd_buffer_si, None, None);
+            if let Some((_, draw_commands, draw_counts)) = gpu_draws {
+                geometry_pass = geometry_pass
+                    .read_buffer(draw_commands, BufferAccess::IndirectRead)
+                    .read_buffer(draw_counts, BufferAccess::IndirectRead);
+            }
 
-        self.device.queue_submit(QueueType::Graphics, submit, self.imm_fence);
-        self.device.wait_for_fences(self.imm_fence);
-    }
+            geometry_pass.execute(move |command_buffer, resources| {
+                let resolve_images = Some((resources.get_image(scene_image), resources.get_image(depth_image)));
+                let ambient_images = msaa_ambient_image.zip(ambient_image).map(|(msaa_ambient, ambient)| (resources.get_image(msaa_ambient), Some(resources.get_image(ambient))));
+                let lights         = resources.get_buffer(lights);
 
-    pub fn render_editor(&mut self, command_buffer: &mut CommandBuffer, image_view: VkImageView) {
-        use crate::util::ffi::*;
+                return self.draw_geometry(command_buffer, resources.get_image(msaa_scene_image), resources.get_image(msaa_depth_image), resolve_images, ambient_images, resources.get_image(shadow_map), lights, draw_buffers);
+            });
+        } else {
+            let mut geometry_pass = graph.add_pass("geometry")
+                .read_image(shadow_map, ImageAccess::FragmentSampled)
+                .read_buffer(lights, BufferAccess::FragmentShaderRead)
+                .read_buffer(light_grid, BufferAccess::FragmentShaderRead)
+                .write_image(scene_image, ImageAccess::ColorAttachment)
+                .write_image(depth_image, ImageAccess::DepthAttachment);
+
+            if let Some(ambient_image) = ambient_image {
+                geometry_pass = geometry_pass.write_image(ambient_image, ImageAccess::ColorAttachment);
+            }
 
-        call!(vendor::imgui::igRender);
+            if let Some((_, draw_commands, draw_counts)) = gpu_draws {
+                geometry_pass = geometry_pass
+                    .read_buffer(draw_commands, BufferAccess::IndirectRead)
+                    .read_buffer(draw_counts, BufferAccess::IndirectRead);
+            }
 
-        let draw_extent = VkExtent2D{ width: self.scene_image.dims.width, height: self.scene_image.dims.height };
-        let color_attachment = make_color_attachment_info(image_view, None, VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL);
-        let render_info = make_rendering_info(draw_extent, &color_attachment, std::ptr::null());
+            geometry_pass.execute(move |command_buffer, resources| {
+                let ambient_images = ambient_image.map(|ambient| (resources.get_image(ambient), None));
+                let lights         = resources.get_buffer(lights);
 
-        command_buffer.begin_rendering(render_info);
-        vendor::imgui::ig_vulkan_render_draw_data(command_buffer.handle, ptr::null_mut());
-        command_buffer.end_rendering();
-    }
+                return self.draw_geometry(command_buffer, resources.get_image(scene_image), resources.get_image(depth_image), None, ambient_images, resources.get_image(shadow_map), lights, draw_buffers);
+            });
+        }
 
-    pub fn on_editor_update(&mut self) {
-        use std::{ffi::CString, os::raw};
+        // Build the Hi-Z pyramid from this frame's depth, for next frame's draw cull
+        if let Some((hiz_image, _, _)) = gpu_draws {
+            graph.add_pass("hiz_build")
+                .read_image(depth_image, ImageAccess::ComputeSampled)
+                .write_image(hiz_image, ImageAccess::ComputeStorageWrite)
+                .execute(move |command_buffer, resources| {
+                    return self.build_hiz(command_buffer, resources.get_image(depth_image), resources.get_image(hiz_image));
+                });
+        }
 
-        let window_name = CString::new("Background").expect("Failed to convert name to CString.");
-        let mut window_open = true;
+        // Occlude the ambient light of the geometry, from the resolved scene depth
+        let ssao_image = if self.ssao_settings.enabled {
+            let ssao_extent = get_ssao_extent(swapchain_extent);
+            let ao_image    = graph.create_image("ssao",        ImageDesc{ extent: ssao_extent, format: SSAO_IMAGE_FORMAT, samples: VK_SAMPLE_COUNT_1_BIT });
+            let blur_image  = graph.create_image("ssao_blur_x", ImageDesc{ extent: ssao_extent, format: SSAO_IMAGE_FORMAT, samples: VK_SAMPLE_COUNT_1_BIT });
+            let final_image = graph.create_image("ssao_blur_y", ImageDesc{ extent: ssao_extent, format: SSAO_IMAGE_FORMAT, samples: VK_SAMPLE_COUNT_1_BIT });
+
+            graph.add_pass("ssao")
+                .read_image(depth_image, ImageAccess::ComputeSampled)
+                .write_image(ao_image, ImageAccess::ComputeStorageWrite)
+                .execute(move |command_buffer, resources| {
+                    return self.compute_ssao(command_buffer, resources.get_image(depth_image), resources.get_image(ao_image));
+                });
+
+            for (pass_name, source, target, direction) in [("ssao_blur_x", ao_image, blur_image, (1.0, 0.0)), ("ssao_blur_y", blur_image, final_image, (0.0, 1.0))] {
+                graph.add_pass(pass_name)
+                    .read_image(source, ImageAccess::ComputeSampled)
+                    .write_image(target, ImageAccess::ComputeStorageWrite)
+                    .execute(move |command_buffer, resources| {
+                        return self.blur_ssao(command_buffer, resources.get_image(source), resources.get_image(target), direction);
+                    });
+            }
 
-        if call!(igBegin, window_name.as_ptr(), &mut window_open, 0) {
-            let max_effects = (self.compute_effects.len() - 1) as i32;
+            Some(final_image)
+        } else {
+            None
+        };
 
-            let compute_effect = &mut self.compute_effects[self.current_compute_effect];
+        // On the forward path, take the occluded ambient light back out of the lit scene
+        if let (Some(ambient_image), Some(final_image)) = (ambient_image, ssao_image) {
+            graph.add_pass("ssao_apply")
+                .read_image(ambient_image, ImageAccess::ComputeSampled)
+                .read_image(final_image, ImageAccess::ComputeSampled)
+                .read_image(depth_image, ImageAccess::ComputeSampled)
+                .write_image(scene_image, ImageAccess::ComputeStorageWrite)
+                .execute(move |command_buffer, resources| {
+                    let ssao_images = (resources.get_image(ambient_image), resources.get_image(final_image), resources.get_image(depth_image));
+                    return self.apply_ssao(command_buffer, resources.get_image(scene_image), ssao_images);
+                });
+        }
 
-            let select_name     = CString::new(format!("Selected Name: {}", compute_effect.name)).expect("Failed to convert name to CString.");
-            let effect_idx_name = CString::new("Effect Index").expect("Failed to convert name to CString.");
-            let data1_name      = CString::new("Data 1").expect("Failed to convert name to CString.");
-            let data2_name      = CString::new("Data 2").expect("Failed to convert name to CString.");
-            let data3_name      = CString::new("Data 3").expect("Failed to convert name to CString.");
-            let data4_name      = CString::new("Data 4").expect("Failed to convert name to CString.");
+        // Light the G-buffer into the scene, occluded by the AO
+        if let Some((albedo_image, normal_image, material_image)) = gbuffer_images {
+            let mut lighting_pass = graph.add_pass("deferred_lighting")
+                .read_image(albedo_image, ImageAccess::ComputeSampled)
+                .read_image(normal_image, ImageAccess::ComputeSampled)
+                .read_image(material_image, ImageAccess::ComputeSampled)
+                .read_image(depth_image, ImageAccess::ComputeSampled)
+                .read_image(shadow_map, ImageAccess::ComputeSampled)
+                .read_buffer(lights, BufferAccess::ComputeShaderRead)
+                .read_buffer(light_grid, BufferAccess::ComputeShaderRead)
+                .write_image(scene_image, ImageAccess::ComputeStorageWrite);
+
+            if let Some(ssao_image) = ssao_image {
+                lighting_pass = lighting_pass.read_image(ssao_image, ImageAccess::ComputeSampled);
+            }
 
-			call!(igText, select_name.as_ptr());
-			call!(igSliderInt, effect_idx_name.as_ptr(), &mut self.current_compute_effect as *mut usize as *mut i32,
-			    0, max_effects as i32, ptr::null(), 0);
+            lighting_pass.execute(move |command_buffer, resources| {
+                let gbuffer = (resources.get_image(albedo_image), resources.get_image(normal_image), resources.get_image(material_image));
+                let ssao    = ssao_image.map(|ao| resources.get_image(ao));
+                let lights  = resources.get_buffer(lights);
 
-			call!(igDragFloat4, data1_name.as_ptr(), &mut compute_effect.push_data.data1.x, 0.1, 0.0, 1.0, ptr::null(), 0);
-			call!(igDragFloat4, data2_name.as_ptr(), &mut compute_effect.push_data.data2.x, 0.1, 0.0, 1.0, ptr::null(), 0);
-			call!(igDragFloat4, data3_name.as_ptr(), &mut compute_effect.push_data.data3.x, 0.1, 0.0, 1.0, ptr::null(), 0);
-			call!(igDragFloat4, data4_name.as_ptr(), &mut compute_effect.push_data.data4.x, 0.1, 0.0, 1.0, ptr::null(), 0);
+                return self.light_gbuffer(command_buffer, resources.get_image(scene_image), gbuffer, resources.get_image(depth_image), ssao, resources.get_image(shadow_map), lights);
+            });
         }
 
-        call!(igEnd);
-    }
+        // Draw the skybox underneath the geometry
+        graph.add_pass("background")
+            .write_image(scene_image, ImageAccess::ComputeStorageWrite)
+            .execute(move |command_buffer, resources| {
+                return self.draw_skybox(command_buffer, resources.get_image(scene_image));
+            });
+
+        // Draw the meshes with blended materials over the scene, tested against the resolved scene depth
+        if let Some(transparent_draws) = transparent_draws.filter(|draws| !draws.is_empty()) {
+            match self.transparency_settings.mode {
+                TransparencyMode::Sorted => {
+                    graph.add_pass("transparent")
+                        .read_image(shadow_map, ImageAccess::FragmentSampled)
+                        .read_buffer(lights, BufferAccess::FragmentShaderRead)
+                        .read_buffer(light_grid, BufferAccess::FragmentShaderRead)
+                        .read_image(depth_image, ImageAccess::DepthAttachmentRead)
+                        .write_image(scene_image, ImageAccess::ColorAttachment)
+                        .execute(move |command_buffer, resources| {
+                            let targets = [resources.get_image(scene_image)];
+                            let lights  = resources.get_buffer(lights);
+
+                            return self.draw_transparent(command_buffer, &targets, resources.get_image(depth_image), resources.get_image(shadow_map), lights, transparent_draws);
+                        });
+                },
+                TransparencyMode::WeightedBlended => {
+                    let accum_image     = graph.create_image("oit_accum",     ImageDesc{ extent: swapchain_extent, format: OIT_ACCUM_FORMAT,     samples: VK_SAMPLE_COUNT_1_BIT });
+                    let revealage_image = graph.create_image("oit_revealage", ImageDesc{ extent: swapchain_extent, format: OIT_REVEALAGE_FORMAT, samples: VK_SAMPLE_COUNT_1_BIT });
+
+                    graph.add_pass("transparent")
+                        .read_image(shadow_map, ImageAccess::FragmentSampled)
+                        .read_buffer(lights, BufferAccess::FragmentShaderRead)
+                        .read_buffer(light_grid, BufferAccess::FragmentShaderRead)
+                        .read_image(depth_image, ImageAccess::DepthAttachmentRead)
+                        .write_image(accum_image, ImageAccess::ColorAttachment)
+                        .write_image(revealage_image, ImageAccess::ColorAttachment)
+                        .execute(move |command_buffer, resources| {
+                            let targets = [resources.get_image(accum_image), resources.get_image(revealage_image)];
+                            let lights  = resources.get_buffer(lights);
+
+                            return self.draw_transparent(command_buffer, &targets, resources.get_image(depth_image), resources.get_image(shadow_map), lights, transparent_draws);
+                        });
+
+                    graph.add_pass("oit_composite")
+                        .read_image(accum_image, ImageAccess::ComputeSampled)
+                        .read_image(revealage_image, ImageAccess::ComputeSampled)
+                        .write_image(scene_image, ImageAccess::ComputeStorageWrite)
+                        .execute(move |command_buffer, resources| {
+                            return self.composite_oit(command_buffer, resources.get_image(scene_image), resources.get_image(accum_image), resources.get_image(revealage_image));
+                        });
+                },
+            }
+        }
 
-    fn process_render_commands(&mut self, command_buffer: &RenderCommandBuffer) {
-        for command in &command_buffer.commands {
-            match command {
-                RenderCommand::UpdateCamera(camera) => {
-                    self.view_matrix        = camera.view_matrix;
-                    self.perspective_matrix = camera.perspective_matrix;
+        // Simulate the particles and draw them into the scene, tested against the resolved scene depth
+        if let Some(particle_draws) = particle_draws {
+            let particle_buffers: Vec<BufferHandle> = particle_draws.draws.iter()
+                .filter(|draw| draw.gpu_step.is_some())
+                .map(|draw| graph.import_buffer("particles", &self.particle_emitters[draw.emitter_index as usize].buffer))
+                .collect();
+
+            if !particle_buffers.is_empty() {
+                let mut sim_pass = graph.add_pass("particle_sim");
+                for buffer in &particle_buffers {
+                    sim_pass = sim_pass.write_buffer(*buffer, BufferAccess::ComputeShaderWrite);
                 }
 
-                RenderCommand::CreateMesh(mesh_info) => {
-                    assert!(self.mesh_count < MAX_LOADED_MESHES - 1);
+                sim_pass.execute(move |command_buffer, _| {
+                    return self.simulate_particles(command_buffer, particle_draws);
+                });
+            }
 
-                    let vertices = unsafe { std::slice::from_raw_parts(mesh_info.vertices, mesh_info.vertex_count) };
-                    let indices  = unsafe { std::slice::from_raw_parts(mesh_info.indices,  mesh_info.index_count)  };
+            let mut draw_pass = graph.add_pass("particles")
+                .read_image(depth_image, ImageAccess::DepthAttachmentRead)
+                .write_image(scene_image, ImageAccess::ColorAttachment);
 
-                    //note: this will evventually be deferred.
-                    let mut mesh = self.upload_mesh(indices, vertices);
-                    mesh.transform = mesh_info.transform;
+            for buffer in &particle_buffers {
+                draw_pass = draw_pass.read_buffer(*buffer, BufferAccess::VertexShaderRead);
+            }
 
-                    let mesh_id = self.mesh_count;
+            draw_pass.execute(move |command_buffer, resources| {
+                return self.draw_particles(command_buffer, resources.get_image(scene_image), resources.get_image(depth_image), particle_draws);
+            });
+        }
 
-                    self.meshes[self.mesh_count] = mesh;
-                    self.mesh_count += 1;
+        // Bloom, downsample the scene into a chain of mips then accumulate them back up into the first mip
+        let bloom_image = if self.post_settings.bloom_enabled {
+            let mip_count = self.post_settings.bloom_mip_count as usize;
 
-                    let response = ReadyMeshInfo{
-                        engine_id:      mesh_info.engine_id,
-                        render_mesh_id: mesh_id as u64,
-                    };
+            let mut bloom_mips = Vec::<ImageHandle>::with_capacity(mip_count);
+            for mip in 0..mip_count {
+                let extent = get_bloom_extent(swapchain_extent, mip);
+                bloom_mips.push(graph.create_image(&format!("bloom_mip{}", mip), ImageDesc{ extent, format: SCENE_IMAGE_FORMAT, samples: VK_SAMPLE_COUNT_1_BIT }));
+            }
 
-                    self.outgoing_commands.commands.push_back(RenderCommand::ReadyMesh(response));
-                },
+            for mip in 0..mip_count {
+                let source = if mip == 0 { scene_image } else { bloom_mips[mip - 1] };
+                let target = bloom_mips[mip];
+
+                graph.add_pass(&format!("bloom_downsample{}", mip))
+                    .read_image(source, ImageAccess::ComputeSampled)
+                    .write_image(target, ImageAccess::ComputeStorageWrite)
+                    .execute(move |command_buffer, resources| {
+                        return self.downsample_bloom(command_buffer, resources.get_image(source), resources.get_image(target), mip == 0);
+                    });
+            }
 
-                default => {},
+            for mip in (0..mip_count - 1).rev() {
+                let source = bloom_mips[mip + 1];
+                let target = bloom_mips[mip];
+
+                graph.add_pass(&format!("bloom_upsample{}", mip))
+                    .read_image(source, ImageAccess::ComputeSampled)
+                    .write_image(target, ImageAccess::ComputeStorageWrite)
+                    .execute(move |command_buffer, resources| {
+                        return self.upsample_bloom(command_buffer, resources.get_image(source), resources.get_image(target));
+                    });
             }
+
+            Some(bloom_mips[0])
+        } else {
+            None
+        };
+
+        // Tonemap the scene into a displayable image
+        let post_image = graph.create_image("post", ImageDesc{ extent: swapchain_extent, format: SCENE_IMAGE_FORMAT, samples: VK_SAMPLE_COUNT_1_BIT });
+
+        let mut tonemap_pass = graph.add_pass("tonemap")
+            .read_image(scene_image, ImageAccess::ComputeSampled);
+
+        if let Some(bloom) = bloom_image {
+            tonemap_pass = tonemap_pass.read_image(bloom, ImageAccess::ComputeSampled);
         }
-    }
 
-    pub fn submit_render_commands(&mut self, render_command_buffer: RenderCommandBuffer) {
-        self.process_render_commands(&render_command_buffer);
+        tonemap_pass
+            .write_image(post_image, ImageAccess::ComputeStorageWrite)
+            .execute(move |command_buffer, resources| {
+                let bloom_view = match bloom_image {
+                    Some(bloom) => resources.get_image(bloom).view,
+                    None        => self.black_image.view,
+                };
+
+                return self.tonemap(command_buffer, resources.get_image(scene_image), bloom_view, resources.get_image(post_image));
+            });
+
+        // Anti-alias the tonemapped image
+        let final_image = if self.post_settings.fxaa_enabled {
+            let aa_image = graph.create_image("post_aa", ImageDesc{ extent: swapchain_extent, format: SCENE_IMAGE_FORMAT, samples: VK_SAMPLE_COUNT_1_BIT });
+
+            graph.add_pass("fxaa")
+                .read_image(post_image, ImageAccess::ComputeSampled)
+                .write_image(aa_image, ImageAccess::ComputeStorageWrite)
+                .execute(move |command_buffer, resources| {
+                    return self.apply_fxaa(command_buffer, resources.get_image(post_image), resources.get_image(aa_image));
+                });
+
+            aa_image
+        } else {
+            post_image
+        };
+
+        // Draw the sprites over the anti-aliased image
+        if let Some(sprite_draws) = sprite_draws {
+            graph.add_pass("sprites")
+                .write_image(final_image, ImageAccess::ColorAttachment)
+                .execute(move |command_buffer, resources| {
+                    return self.draw_sprites(command_buffer, resources.get_image(final_image), sprite_draws);
+                });
+        }
+
+        // Draw the text over the sprites, world text tested against the resolved scene depth
+        if let Some(text_draws) = text_draws {
+            graph.add_pass("text")
+                .read_image(depth_image, ImageAccess::DepthAttachmentRead)
+                .write_image(final_image, ImageAccess::ColorAttachment)
+                .execute(move |command_buffer, resources| {
+                    return self.draw_text(command_buffer, resources.get_image(final_image), resources.get_image(depth_image), text_draws);
+                });
+        }
+
+        // Draw the debug lines over the anti-aliased image, tested against the resolved scene depth
+        if let Some(vertex_buffer) = debug_line_buffer {
+            graph.add_pass("debug_lines")
+                .read_image(depth_image, ImageAccess::DepthAttachmentRead)
+                .write_image(final_image, ImageAccess::ColorAttachment)
+                .execute(move |command_buffer, resources| {
+                    return self.draw_debug_lines(command_buffer, resources.get_image(final_image), resources.get_image(depth_image), vertex_buffer);
+                });
+        }
+
+        // Now, copy the final image to the swapchain
+        graph.add_pass("copy_to_swapchain")
+            .read_image(final_image, ImageAccess::TransferSrc)
+            .write_image(swapchain_image, ImageAccess::TransferDst)
+            .execute(move |command_buffer, resources| {
+                let source    = resources.get_image(final_image);
+                let swapchain = resources.get_image(swapchain_image);
+
+                command_buffer.copy_image_to_image(source.image, source.get_extent_2d(), swapchain.image, swapchain.get_extent_2d());
+                return Ok(());
+            });
+
+        // Render Imgui directly into the swapchain image.
+        //   note: it is likely I will want to render into a rgba 8bit target and composite with the scene image
+        //         before copying into the swapchain buffer. vkguide does this, so I am going to do this for now.
+        //graph.add_pass("editor")
+        //    .write_image(swapchain_image, ImageAccess::ColorAttachment)
+        //    .execute(move |command_buffer, resources| {
+        //        self.render_editor(command_buffer, resources.get_image(swapchain_image).view);
+        //        return Ok(());
+        //    });
+
+        return graph;
     }
 
-    pub fn render(&mut self) {
-        //if render_command_buffer.commands.len() > 0 {
-        //    self.process_render_commands(&render_command_buffer);
-        //}
+    fn render_frame(&mut self) -> Result<(), RenderError> {
+        // Swap in the environment the loader finished building, if there is one
+        if let Some(cubes) = self.environment_loader.take_built() {
+            self.set_environment(cubes)?;
+        }
 
         // If the swapchain has been invalidated, recreate it. Will usually happen when we need to resize.
         if !self.swapchain.is_valid()
         {
-            self.resize_device_resources();
-            return; //don't render this frame...
+            return self.resize_device_resources(); //don't render this frame...
         }
 
-        if !self.swapchain.acquire_frame(&self.device) {
-            return; // try again next frame
+        if !self.swapchain.acquire_frame(&self.device)? {
+            return Ok(()); // try again next frame
         }
 
         let frame_data  = self.get_frame_data();
@@ -697,6 +4179,22 @@ impl RenderSystem {
             dyn_descriptors.clear_pools(&self.device);
         }
 
+        for worker_pool in frame_data.worker_command_pools.borrow_mut().iter_mut() {
+            worker_pool.begin_frame();
+        }
+
+        // The GPU is done with the last frame that used this frame data, its draw counts can be read before the
+        // readback buffer is released with the rest of the frame's buffers
+        if let Some((readback, batch_count)) = frame_data.culling_readback.take() {
+            self.device.invalidate_buffer(&readback)?;
+
+            let memory = readback.get_allocation();
+            assert!(memory != ptr::null_mut());
+            let visible_counts = unsafe { std::slice::from_raw_parts(memory as *const u32, batch_count) };
+
+            self.culling_stats.set(count_visible_batches(visible_counts));
+        }
+
         {
             let mut deletion_queues = frame_data.deletion_queues.borrow_mut();
             for buffer in &mut deletion_queues.buffer_deletion_queue {
@@ -717,72 +4215,56 @@ impl RenderSystem {
         let mut command_buffer_state = frame_data.command_buffer.borrow_mut();
         let mut command_buffer = &mut command_buffer_state.handle;
 
-        command_buffer.reset();
-        command_buffer.begin_recording();
-
-        command_buffer.transition_image(self.scene_image.image, VK_IMAGE_LAYOUT_UNDEFINED, VK_IMAGE_LAYOUT_GENERAL);
-
-        if false { // Draw background, simple
-            //command_buffer.clear_color_image(self.scene_image.image, &clear_value);
+        command_buffer.reset()?;
+        command_buffer.begin_recording()?;
 
-            command_buffer.bind_compute_pipeline(self.gradient_p);
+        self.update_scene_data();
+        self.update_mesh_lods();
 
-            let descriptors: [VkDescriptorSet; 1] = [ self.draw_image_ds ];
-            command_buffer.bind_compute_descriptor_sets(self.gradient_pl, 0, descriptors.as_slice());
+        let (frame_lights, light_count) = self.upload_lights()?;
 
-            let group_x = self.scene_image.dims.width  as f32 / 16.0;
-            let group_y = self.scene_image.dims.height as f32 / 16.0;
+        let draw_buffers = if self.is_gpu_driven() { self.upload_draw_list()? } else { None };
 
-            command_buffer.dispatch_compute(group_x.ceil() as u32, group_y.ceil() as u32, 1);
+        // Meshes with blended materials are sorted for the transparent pass, see transparency.rs
+        let transparent_draws = if self.materials.iter().any(|material| material.is_blended()) {
+            let camera_pos = self.scene_data.camera_pos;
+            Some(build_transparent_draws(
+                self.scene_data.view_proj, Float3::new(camera_pos.x, camera_pos.y, camera_pos.z), &self.materials,
+                &self.meshes[0..self.mesh_count], &self.instanced_meshes, &self.skinned_meshes, self.default_instance_address))
         } else {
-            let compute_effect = &self.compute_effects[self.current_compute_effect];
-
-            command_buffer.bind_compute_pipeline(compute_effect.pipeline);
+            None
+        };
 
-            let descriptors: [VkDescriptorSet; 1] = [ self.draw_image_ds ];
-            command_buffer.bind_compute_descriptor_sets(compute_effect.layout, 0, descriptors.as_slice());
+        let particle_draws    = self.update_particles()?;
+        let sprite_draws      = self.upload_sprites()?;
+        let text_draws        = self.upload_text()?;
+        let debug_line_buffer = self.upload_debug_lines()?;
 
-            command_buffer.bind_push_constants(compute_effect.layout, VK_SHADER_STAGE_COMPUTE_BIT, compute_effect.push_data, 0);
+        let render_graph_dot = {
+            let mut graph = self.build_render_graph(&frame_lights, light_count, draw_buffers.as_ref(), transparent_draws.as_ref(), particle_draws.as_ref(), sprite_draws.as_ref(), text_draws.as_ref(), debug_line_buffer);
+            graph.compile(&self.device, &mut self.transient_images.borrow_mut(), self.frame_data.len())?;
+            graph.execute(&mut command_buffer)?;
 
-            let group_x = self.scene_image.dims.width  as f32 / 16.0;
-            let group_y = self.scene_image.dims.height as f32 / 16.0;
+            if self.render_graph_dump_path.is_some() { Some(graph.to_dot()) } else { None }
+        };
 
-            command_buffer.dispatch_compute(group_x.ceil() as u32, group_y.ceil() as u32, 1);
+        // The pyramid now holds this frame's depth, seen from this frame's camera
+        if draw_buffers.is_some() {
+            self.hiz.view_proj = self.scene_data.view_proj;
+            self.hiz.is_valid  = true;
         }
 
-        { // Draw geometry
-            command_buffer.transition_image(self.scene_image.image, VK_IMAGE_LAYOUT_GENERAL,   VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL);
-            command_buffer.transition_image(self.depth_image.image, VK_IMAGE_LAYOUT_UNDEFINED, VK_IMAGE_LAYOUT_DEPTH_ATTACHMENT_OPTIMAL);
-
-            self.draw_geometry(&mut command_buffer);
+        if let (Some(dot), Some(path)) = (render_graph_dot, self.render_graph_dump_path.take()) {
+            match std::fs::write(&path, dot) {
+                Ok(_)  => println!("[INFO] :: RenderSystem :: Wrote the render graph to {}", path.display()),
+                Err(e) => println!("[WARN] :: RenderSystem :: Failed to write the render graph to {}: {}", path.display(), e),
+            }
         }
 
-        // Now, copy the scene framebuffer to the swapchain
-        let swapchain_image      = self.swapchain.get_swapchain_image();
-        let swapchain_image_view = self.swapchain.get_swapchain_image_view();
-        let swapchain_extent     = self.swapchain.get_extent();
-
-        command_buffer.transition_image(self.scene_image.image, VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL, VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL);
-        command_buffer.transition_image(swapchain_image,        VK_IMAGE_LAYOUT_UNDEFINED,                VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL);
-
-        let src_extent = VkExtent2D{ width: self.scene_image.dims.width, height: self.scene_image.dims.height };
-        let dst_extent = VkExtent2D{ width: swapchain_extent.width,      height: swapchain_extent.height      };
-
-        command_buffer.copy_image_to_image(self.scene_image.image, src_extent, swapchain_image, dst_extent);
-
-        // Render Imgui directly into the swapchain image.
-        //   note: it is likely I will want to render into a rgba 8bit target and composite with the scene image
-        //         before copying into the swapchain buffer. vkguide does this, so I am going to do this for now.
-        command_buffer.transition_image(swapchain_image, VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL, VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL);
-        //self.render_editor(&mut command_buffer, swapchain_image_view);
-
-        // Transition the swapchain image to present mode
-        command_buffer.transition_image(swapchain_image, VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL, VK_IMAGE_LAYOUT_PRESENT_SRC_KHR);
-
         // End the Frame
         //
 
-        command_buffer.end_recording();
+        command_buffer.end_recording()?;
 
         let cmd_buffer_si = command_buffer.get_submit_info();
 
@@ -800,19 +4282,36 @@ impl RenderSystem {
         //   renderFence will now block until the graphic commands finish execution
         let graphics_queue = self.device.get_queue(QueueType::Graphics);
 
+        if self.simulate_device_lost {
+            self.simulate_device_lost = false;
+            return Err(RenderError::DeviceLost);
+        }
+
         call_throw!(self.device.fns.queue_submit2, graphics_queue, 1, &submit, self.swapchain.get_render_fence());
 
+        frame_data.culling_readback.set(draw_buffers.as_ref().map(|buffers| (buffers.counts_readback, buffers.list.batches.len())));
+        if self.is_gpu_driven() && draw_buffers.is_none() {
+            self.culling_stats.set(CullingStats::default()); // nothing to draw
+        }
+
         // todo: grab an "empty" command buffer to wait on currentFrameData->mPresentSemaphore
         // vkAcquireImageKHR will signal this semaphore when we are ready to render into this image.
         // In a real graphics pipeline, we might want to do this in the compositing step when we render
         // directly into the swapchain Framebuffer.
 
-        self.swapchain.present_frame(&self.device);
+        self.swapchain.present_frame(&self.device)?;
 
         self.frame_index = (self.frame_index + 1) % consts::MAX_BUFFERED_FRAMES;
+
+        return Ok(());
     }
 
     pub fn destroy(&mut self) {
+        if self.is_destroyed {
+            return; // already released, usually after a failed device recovery
+        }
+
+        self.is_destroyed = true;
         self.device.wait_idle();
 
         for i in 0..self.mesh_count {
@@ -821,29 +4320,117 @@ impl RenderSystem {
             self.device.destroy_buffer(&mut mesh.vertex_buffer);
         }
 
+        for instanced_mesh in &mut self.instanced_meshes {
+            self.device.destroy_buffer(&mut instanced_mesh.mesh.index_buffer);
+            self.device.destroy_buffer(&mut instanced_mesh.mesh.vertex_buffer);
+            self.device.destroy_buffer(&mut instanced_mesh.instance_buffer);
+        }
+
+        for skinned_mesh in &mut self.skinned_meshes {
+            self.device.destroy_buffer(&mut skinned_mesh.mesh.index_buffer);
+            self.device.destroy_buffer(&mut skinned_mesh.mesh.vertex_buffer);
+            self.device.destroy_buffer(&mut skinned_mesh.skin_buffer);
+            self.device.destroy_buffer(&mut skinned_mesh.joint_buffer);
+        }
+
+        self.device.destroy_buffer(&mut self.default_instance_buffer);
+
+        for state in &mut self.particle_emitters {
+            self.device.destroy_buffer(&mut state.buffer);
+        }
+
         self.device.destroy_image_memory(&mut self.white_image);
         self.device.destroy_image_memory(&mut self.black_image);
         self.device.destroy_image_memory(&mut self.grey_image);
         self.device.destroy_image_memory(&mut self.error_checkerboard_image);
+        self.device.destroy_image_memory(&mut self.flat_normal_image);
+
+        for texture in &mut self.textures {
+            self.device.destroy_image_memory(&mut texture.image);
+        }
+
+        self.bindless.destroy(&self.device);
+
         self.device.destroy_sampler(self.default_sampler_linear);
         self.device.destroy_sampler(self.default_sampler_nearest);
+        self.device.destroy_sampler(self.shadow_sampler);
+        self.device.destroy_sampler(self.post_sampler);
+        self.device.destroy_sampler(self.cube_sampler);
+        self.device.destroy_buffer(&mut self.light_grid);
+        self.device.destroy_image_memory(&mut self.color_grading_lut);
+        self.environment.destroy(&self.device);
 
         //self.device.destroy_imgui_editor(&mut self.editor_data);
 
-        self.device.destroy_pipeline(self.triangle_p);
-        self.device.destroy_pipeline_layout(self.triangle_pl);
-        self.device.destroy_descriptor_set_layout(self.single_image_dl);
+        self.device.destroy_pipeline(self.mesh_p);
+        self.device.destroy_pipeline(self.skinned_mesh_p);
+        self.device.destroy_pipeline(self.transparent_mesh_p);
+        self.device.destroy_pipeline(self.transparent_skinned_mesh_p);
+        self.device.destroy_pipeline(self.oit_mesh_p);
+        self.device.destroy_pipeline(self.oit_skinned_mesh_p);
+        self.device.destroy_pipeline(self.gbuffer_mesh_p);
+        self.device.destroy_pipeline(self.gbuffer_skinned_mesh_p);
+        self.device.destroy_pipeline_layout(self.mesh_pl);
+
+        self.device.destroy_pipeline(self.deferred_lighting_p);
+        self.device.destroy_pipeline_layout(self.deferred_lighting_pl);
+        self.device.destroy_descriptor_set_layout(self.deferred_lighting_dl);
+
+        self.device.destroy_pipeline(self.oit_composite_p);
+        self.device.destroy_pipeline_layout(self.oit_composite_pl);
+        self.device.destroy_descriptor_set_layout(self.oit_composite_dl);
+
+        self.device.destroy_pipeline(self.shadow_depth_p);
+        self.device.destroy_pipeline(self.skinned_shadow_depth_p);
+        self.device.destroy_pipeline(self.shadow_depth_alpha_test_p);
+        self.device.destroy_pipeline(self.skinned_shadow_depth_alpha_test_p);
+        self.device.destroy_pipeline_layout(self.shadow_depth_pl);
+
+        self.device.destroy_pipeline(self.light_cull_p);
+        self.device.destroy_pipeline_layout(self.light_cull_pl);
+        self.device.destroy_descriptor_set_layout(self.light_cull_dl);
+
+        self.device.destroy_pipeline(self.draw_cull_p);
+        self.device.destroy_pipeline(self.hiz_build_p);
+        self.device.destroy_pipeline_layout(self.draw_cull_pl);
+        self.device.destroy_descriptor_set_layout(self.draw_cull_dl);
+        self.hiz.destroy(&self.device);
+
+        self.device.destroy_pipeline(self.bloom_downsample_p);
+        self.device.destroy_pipeline(self.bloom_upsample_p);
+        self.device.destroy_pipeline(self.tonemap_p);
+        self.device.destroy_pipeline(self.fxaa_p);
+        self.device.destroy_pipeline(self.ssao_p);
+        self.device.destroy_pipeline(self.ssao_blur_p);
+        self.device.destroy_pipeline(self.ssao_apply_p);
+        self.device.destroy_pipeline_layout(self.ssao_apply_pl);
+        self.device.destroy_descriptor_set_layout(self.ssao_apply_dl);
+        self.device.destroy_pipeline_layout(self.post_process_pl);
+        self.device.destroy_pipeline_layout(self.tonemap_pl);
+        self.device.destroy_descriptor_set_layout(self.post_process_dl);
+        self.device.destroy_descriptor_set_layout(self.tonemap_dl);
+
+        self.device.destroy_pipeline(self.particle_sim_p);
+        self.device.destroy_pipeline(self.particle_additive_p);
+        self.device.destroy_pipeline(self.particle_alpha_p);
+        self.device.destroy_pipeline_layout(self.particle_sim_pl);
+        self.device.destroy_pipeline_layout(self.particle_pl);
+
+        self.device.destroy_pipeline(self.sprite_p);
+        self.device.destroy_pipeline_layout(self.sprite_pl);
+
+        self.device.destroy_pipeline(self.text_p);
+        self.device.destroy_pipeline(self.text_world_p);
+        self.device.destroy_pipeline_layout(self.text_pl);
+
+        self.device.destroy_pipeline(self.debug_line_p);
+        self.device.destroy_pipeline(self.debug_line_overlay_p);
+        self.device.destroy_pipeline_layout(self.debug_line_pl);
+
+        self.device.destroy_pipeline(self.skybox_p);
+        self.device.destroy_pipeline_layout(self.skybox_pl);
+        self.device.destroy_descriptor_set_layout(self.skybox_dl);
 
-        self.device.destroy_pipeline(self.gradient_p);
-        self.device.destroy_pipeline_layout(self.gradient_pl);
-
-        for effect in &self.compute_effects {
-            self.device.destroy_pipeline(effect.pipeline);
-            self.device.destroy_pipeline_layout(effect.layout);
-        }
-
-        self.device.destroy_descriptor_allocator(&mut self.global_da);
-        self.device.destroy_descriptor_set_layout(self.draw_image_dl);
         self.device.destroy_descriptor_set_layout(self.global_scene_dl);
 
         self.device.destroy_fence(&mut self.imm_fence);
@@ -852,6 +4439,10 @@ impl RenderSystem {
         for frame_data in &self.frame_data{
             self.device.destroy_command_pool(&mut frame_data.command_buffer.borrow_mut().pool);
 
+            for worker_pool in frame_data.worker_command_pools.borrow_mut().iter_mut() {
+                self.device.destroy_command_pool(&mut worker_pool.pool);
+            }
+
             {
                 let mut dyn_descriptors = frame_data.dynamic_descriptors.borrow_mut();
                 dyn_descriptors.destroy(&self.device);
@@ -872,18 +4463,24 @@ impl RenderSystem {
             }
         }
 
-        self.device.destroy_image_memory(&mut self.depth_image);
-        self.device.destroy_image_memory(&mut self.scene_image);
+        self.transient_images.borrow_mut().destroy(&self.device);
         self.device.destroy_swapchain(&mut self.swapchain);
         self.device.destroy();
     }
 
+    /// Meshes drawn and culled by the last frame's CPU draw loop.
+    pub fn get_culling_stats(&self) -> CullingStats {
+        return self.culling_stats.get();
+    }
+
     pub fn on_resize(&mut self, width: u32, height: u32)
     {
         self.swapchain.on_resize(width, height);
     }
 
-    fn upload_mesh(&mut self, indices: &[u32], vertices: &[Vertex]) -> GpuMeshBuffers {
+    /// Uploads a mesh with the LODs `lods`, ranges of `indices`. Without LODs, or with LODs that don't fit in the
+    /// indices, the whole index buffer is LOD 0.
+    fn upload_mesh(&mut self, indices: &[u32], vertices: &[Vertex], lods: &[MeshLod]) -> Result<GpuMeshBuffers, RenderError> {
         let vertex_buffer_size = vertices.len() * std::mem::size_of::<Vertex>();
         let index_buffer_size  = indices.len()  * std::mem::size_of::<u32>();
 
@@ -891,12 +4488,32 @@ impl RenderSystem {
         let index_buffer_flags  = VK_BUFFER_USAGE_INDEX_BUFFER_BIT | VK_BUFFER_USAGE_TRANSFER_DST_BIT;
 
         let mut result = GpuMeshBuffers::default();
-        result.index_buffer          = self.device.create_buffer(index_buffer_size, index_buffer_flags, VMA_MEMORY_USAGE_GPU_ONLY);
-        result.vertex_buffer         = self.device.create_buffer(vertex_buffer_size, vertex_buffer_flags, VMA_MEMORY_USAGE_GPU_ONLY);
+        result.index_buffer          = self.device.create_buffer(index_buffer_size, index_buffer_flags, VMA_MEMORY_USAGE_GPU_ONLY)?;
+        result.vertex_buffer         = self.device.create_buffer(vertex_buffer_size, vertex_buffer_flags, VMA_MEMORY_USAGE_GPU_ONLY)?;
         result.vertex_buffer_address = self.device.get_buffer_device_address(&result.vertex_buffer);
-        result.index_count           = indices.len() as u32;
+        result.lods[0]               = MeshLod{ first_index: 0, index_count: indices.len() as u32, screen_size: f32::MAX };
+        result.lod_count             = 1;
+
+        let bounds = compute_bounds(vertices, indices);
+        result.bounds                = bounds.sphere;
+        result.aabb                  = bounds.aabb;
+
+        // select_lod() expects the finest LOD first, with the screen sizes shrinking from there
+        let mut sorted_lods = lods.to_vec();
+        sorted_lods.sort_by(|a, b| b.screen_size.total_cmp(&a.screen_size));
+
+        let fits       = |lod: &MeshLod| -> bool { lod.first_index as usize + lod.index_count as usize <= indices.len() };
+        let is_coarser = |pair: &[MeshLod]| -> bool { pair[1].index_count <= pair[0].index_count };
+        if sorted_lods.len() > MAX_MESH_LODS || !sorted_lods.iter().all(fits) {
+            println!("[WARN] :: RenderSystem :: Ignoring the LODs of a mesh with {} indices, they don't fit (or there are more than {}).", indices.len(), MAX_MESH_LODS);
+        } else if !sorted_lods.windows(2).all(is_coarser) {
+            println!("[WARN] :: RenderSystem :: Ignoring the LODs of a mesh with {} indices, the LODs drawn smaller have more indices.", indices.len());
+        } else if !sorted_lods.is_empty() {
+            result.lods[0..sorted_lods.len()].copy_from_slice(&sorted_lods);
+            result.lod_count = sorted_lods.len() as u32;
+        }
 
-       	let mut staging_buffer = self.device.create_buffer(vertex_buffer_size + index_buffer_size, VK_BUFFER_USAGE_TRANSFER_SRC_BIT, VMA_MEMORY_USAGE_CPU_ONLY);
+       	let mut staging_buffer = self.device.create_buffer(vertex_buffer_size + index_buffer_size, VK_BUFFER_USAGE_TRANSFER_SRC_BIT, VMA_MEMORY_USAGE_CPU_ONLY)?;
 
         let mut memory = staging_buffer.info.pMappedData;
         assert!(memory != ptr::null_mut());
@@ -909,7 +4526,7 @@ impl RenderSystem {
         let mut memory_as_index = unsafe { memory.add(vertex_buffer_size) } as *mut u32;
         unsafe { std::ptr::copy(indices.as_ptr(), memory_as_index, indices.len()) };
 
-        self.immediate_submit(
+        let submit_result = self.immediate_submit(
             |command_buffer: &CommandBuffer| {
                 // Copy to the final vertex buffer
                 command_buffer.copy_buffer(&result.vertex_buffer, 0, &staging_buffer, 0, vertex_buffer_size as VkDeviceSize);
@@ -919,15 +4536,16 @@ impl RenderSystem {
         );
 
         self.device.destroy_buffer(&mut staging_buffer);
+        submit_result?;
 
-        return result;
+        return Ok(result);
     }
 
-    fn upload_image(&mut self, data: *const u8, size: VkExtent3D, format: VkFormat, usage: VkImageUsageFlags, mipmapped: bool) -> AllocatedImage {
+    fn upload_image(&mut self, data: *const u8, size: VkExtent3D, format: VkFormat, usage: VkImageUsageFlags, mipmapped: bool) -> Result<AllocatedImage, RenderError> {
         let bytes_per_pixel = 4; //todo: determine based on VkFormat
         let data_size = size.width * size.height * size.depth * bytes_per_pixel;
 
-       	let mut upload_buffer = self.device.create_buffer(data_size as usize, VK_BUFFER_USAGE_TRANSFER_SRC_BIT, VMA_MEMORY_USAGE_CPU_TO_GPU);
+       	let mut upload_buffer = self.device.create_buffer(data_size as usize, VK_BUFFER_USAGE_TRANSFER_SRC_BIT, VMA_MEMORY_USAGE_CPU_TO_GPU)?;
 
         // copy the pixels into the upload buffer
         let upload_memory = upload_buffer.get_allocation();
@@ -938,9 +4556,9 @@ impl RenderSystem {
 
         let result = self.device.allocate_image_memory(
             size, format, usage | VK_IMAGE_USAGE_TRANSFER_DST_BIT | VK_IMAGE_USAGE_TRANSFER_SRC_BIT,
-            VMA_MEMORY_USAGE_GPU_ONLY, VK_MEMORY_PROPERTY_DEVICE_LOCAL_BIT, mipmapped);
+            VMA_MEMORY_USAGE_GPU_ONLY, VK_MEMORY_PROPERTY_DEVICE_LOCAL_BIT, mipmapped, VK_SAMPLE_COUNT_1_BIT)?;
 
-        self.immediate_submit(
+        let submit_result = self.immediate_submit(
             |command_buffer: &CommandBuffer| {
           		command_buffer.transition_image(result.image, VK_IMAGE_LAYOUT_UNDEFINED, VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL);
                 command_buffer.copy_buffer_to_image(&upload_buffer, &result, size);
@@ -954,8 +4572,9 @@ impl RenderSystem {
         );
 
         self.device.destroy_buffer(&mut upload_buffer);
+        submit_result?;
 
-        return result;
+        return Ok(result);
     }
 
     fn destroy_image(&mut self, mut image: &mut AllocatedImage) {
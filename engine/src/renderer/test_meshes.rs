@@ -0,0 +1,64 @@
+use crate::math::{ float3::*, float4::* };
+
+use super::mesh::Vertex;
+use super::mesh_processing::deduplicate_vertices;
+
+//
+// Test Meshes
+//
+// The testbed's OBJ files, loaded for the unit tests of the mesh processing and LOD modules. Only compiled for tests.
+//
+
+const GEOMETRY_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../demos/testbed/assets/geometry/");
+
+/// Loads an OBJ file from the testbed's assets the way its converter does, one vertex per corner. Faces are
+/// triangulated as fans, corners without a normal get a zero normal.
+pub(crate) fn load_obj(name: &str) -> (Vec<Vertex>, Vec<u32>) {
+    let file = std::fs::read_to_string(format!("{}{}", GEOMETRY_PATH, name)).expect("Failed to read a test mesh.");
+
+    let mut positions  = Vec::<Float3>::new();
+    let mut normals    = Vec::<Float3>::new();
+    let mut tex_coords = Vec::<(f32, f32)>::new();
+    let mut vertices   = Vec::<Vertex>::new();
+
+    for line in file.lines() {
+        let mut tokens = line.split_whitespace();
+        let numbers    = |tokens: std::str::SplitWhitespace| -> Vec<f32> { tokens.map(|token| token.parse::<f32>().unwrap()).collect() };
+
+        match tokens.next() {
+            Some("v")  => { let v = numbers(tokens); positions.push(Float3::new(v[0], v[1], v[2])); },
+            Some("vn") => { let v = numbers(tokens); normals.push(Float3::new(v[0], v[1], v[2])); },
+            Some("vt") => { let v = numbers(tokens); tex_coords.push((v[0], v[1])); },
+            Some("f")  => {
+                let corners = tokens.map(|corner| {
+                    let mut parts = corner.split('/').map(|part| part.parse::<usize>().ok().map(|index| index - 1));
+
+                    let mut vertex = Vertex::new();
+                    vertex.position = positions[parts.next().flatten().unwrap()];
+                    if let Some(uv) = parts.next().flatten() {
+                        (vertex.uv_x, vertex.uv_y) = tex_coords[uv];
+                    }
+                    if let Some(normal) = parts.next().flatten() {
+                        vertex.normal = normals[normal];
+                    }
+                    vertex.color = Float4::one();
+                    vertex
+                }).collect::<Vec<Vertex>>();
+
+                for corner in 1..corners.len() - 1 {
+                    vertices.extend_from_slice(&[corners[0], corners[corner], corners[corner + 1]]);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    let indices = (0..vertices.len() as u32).collect();
+    return (vertices, indices);
+}
+
+pub(crate) fn load_indexed_obj(name: &str) -> (Vec<Vertex>, Vec<u32>) {
+    let (mut vertices, mut indices) = load_obj(name);
+    deduplicate_vertices(&mut vertices, &mut indices);
+    return (vertices, indices);
+}
@@ -0,0 +1,145 @@
+use crate::font::atlas::*;
+use crate::math::{ float2::*, float3::*, float4::* };
+
+use super::shader::GpuGlyph;
+
+use vendor::vulkan::VkDeviceAddress;
+
+//
+// Text
+//
+// Text laid out with a font::atlas::FontAtlas, drawn as one quad per glyph. The engine sends the frame's text with
+// RenderCommand::UpdateText, and the atlas texture is created like any other texture, with TextureFormat::Rgba8Unorm.
+//   - Screen: in pixels from the top-left of the window, drawn over everything. For HUDs and overlays.
+//   - World:  on a plane in the scene, depth tested against the scene but not writing depth. For labels.
+//
+// Both are drawn after the sprites and before the debug lines, over the tonemapped image, so the text colors are the
+// colors on screen. World text is drawn first, so screen text always ends up on top.
+//
+
+/// Glyphs past this count are dropped
+pub const MAX_TEXT_GLYPHS: usize = 65536;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TextSpace {
+    Screen,
+    World,
+}
+
+/// A block of laid out text, ready to be sent with RenderCommand::UpdateText.
+#[derive(Clone, Debug)]
+pub struct TextDraw {
+    pub texture:       u64, // CreateTextureInfo::engine_id of the atlas' texture
+    pub rasterization: GlyphRasterization,
+    pub space:         TextSpace,
+    pub origin:        Float3, // where the top-left of the text ends up
+    pub axis_x:        Float3, // one text space pixel to the right
+    pub axis_y:        Float3, // one text space pixel down
+    pub color:         Float4, // alpha blended
+    pub glyphs:        Vec<GlyphQuad>,
+}
+
+impl TextDraw {
+    /// Text with its top-left corner at `position`, in pixels from the top-left of the window. `pixel_size` is the
+    /// font's em size on screen.
+    pub fn screen(atlas: &FontAtlas, texture: u64, text: &str, position: Float2, pixel_size: f32, color: Float4) -> Self {
+        let scale = pixel_size / atlas.pixel_size;
+
+        return Self{
+            texture,
+            rasterization: atlas.rasterization,
+            space:         TextSpace::Screen,
+            origin:        Float3::new(position.x, position.y, 0.0),
+            axis_x:        Float3::new(scale, 0.0, 0.0),
+            axis_y:        Float3::new(0.0, scale, 0.0),
+            color,
+            glyphs:        atlas.layout_text(text),
+        };
+    }
+
+    /// Text on the plane spanned by `right` and `up`, with its top-left corner at `position`. `height` is the
+    /// font's em size in world units.
+    pub fn world(atlas: &FontAtlas, texture: u64, text: &str, position: Float3, right: Float3, up: Float3, height: f32, color: Float4) -> Self {
+        let scale = height / atlas.pixel_size;
+
+        return Self{
+            texture,
+            rasterization: atlas.rasterization,
+            space:         TextSpace::World,
+            origin:        position,
+            axis_x:        right.unit() * scale,
+            axis_y:        -(up.unit() * scale),
+            color,
+            glyphs:        atlas.layout_text(text),
+        };
+    }
+}
+
+/// One instanced draw of glyphs sharing an atlas and a space
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) struct TextBatch {
+    pub space:          TextSpace,
+    pub texture_index:  u32,  // into the bindless texture array
+    pub is_sdf:         bool,
+    pub first_instance: u32,  // into the GpuGlyph buffer
+    pub instance_count: u32,
+}
+
+/// A frame's text, uploaded and ready to draw
+pub(crate) struct TextDrawList {
+    pub glyph_buffer: VkDeviceAddress, // GpuGlyph array
+    pub batches:      Vec<TextBatch>,
+}
+
+/// Packs the glyphs of every draw for text.vert, world text first. `get_texture_index` maps an atlas texture to its
+/// slot in the bindless texture array. Returns the packed glyphs and the draws that cover them.
+pub(crate) fn build_text_batches(draws: &[TextDraw], get_texture_index: impl Fn(u64) -> u32) -> (Vec<GpuGlyph>, Vec<TextBatch>) {
+    let mut instances = Vec::<GpuGlyph>::new();
+    let mut batches   = Vec::<TextBatch>::new();
+    let mut dropped   = 0;
+
+    for space in [TextSpace::World, TextSpace::Screen] {
+        for draw in draws.iter().filter(|draw| draw.space == space) {
+            let texture_index = get_texture_index(draw.texture);
+            let is_sdf        = matches!(draw.rasterization, GlyphRasterization::Sdf{ .. });
+
+            for glyph in &draw.glyphs {
+                if instances.len() >= MAX_TEXT_GLYPHS {
+                    dropped += 1;
+                    continue;
+                }
+
+                let width  = glyph.max.x - glyph.min.x;
+                let height = glyph.max.y - glyph.min.y;
+                let corner = draw.origin + draw.axis_x * glyph.min.x + draw.axis_y * glyph.min.y;
+                let axis_x = draw.axis_x * width;
+                let axis_y = draw.axis_y * height;
+
+                instances.push(GpuGlyph{
+                    corner:  Float4::new(corner.x, corner.y, corner.z, 1.0),
+                    axis_x:  Float4::new(axis_x.x, axis_x.y, axis_x.z, 0.0),
+                    axis_y:  Float4::new(axis_y.x, axis_y.y, axis_y.z, 0.0),
+                    uv_rect: Float4::new(glyph.uv_min.x, glyph.uv_min.y, glyph.uv_max.x, glyph.uv_max.y),
+                    color:   draw.color,
+                });
+
+                match batches.last_mut() {
+                    Some(batch) if batch.space == space && batch.texture_index == texture_index && batch.is_sdf == is_sdf => batch.instance_count += 1,
+                    _ => batches.push(TextBatch{
+                        space,
+                        texture_index,
+                        is_sdf,
+                        first_instance: (instances.len() - 1) as u32,
+                        instance_count: 1,
+                    }),
+                }
+            }
+        }
+    }
+
+    if dropped > 0 {
+        println!("[WARN] :: Text :: {} glyphs were submitted, only the first {} are rendered.", instances.len() + dropped, MAX_TEXT_GLYPHS);
+    }
+
+    return (instances, batches);
+}
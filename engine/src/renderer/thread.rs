@@ -7,6 +7,8 @@ use std::cell::RefCell;
 
 use super::system::*;
 use super::command_buffer::*;
+use super::culling::CullingStats;
+use super::error::RenderError;
 
 const MAX_QUEUED_FRAMES: usize = 3;
 
@@ -60,9 +62,11 @@ pub struct RtrCommandList {}
 
 #[derive(PartialEq)]
 pub enum RenderThreadResponse {
-    RenderFrameDone,
+    RendererReady,
+    RenderFrameDone(CullingStats),
     RendererShutdown,
     SubmitCommandList,
+    RendererError(RenderError),
 }
 
 struct FrameSync {
@@ -85,15 +89,21 @@ fn process_render_command(render_system: &mut RenderSystem, command: RenderThrea
         },
 
         RenderThreadCommand::SubmitCommandList(rtc_submit_command_list) => {
-            render_system.submit_render_commands(rtc_submit_command_list.cmd_buffer);
+            if let Err(error) = render_system.submit_render_commands(rtc_submit_command_list.cmd_buffer) {
+                return Some(RenderThreadResponse::RendererError(error));
+            }
+
             return None; //todo: we'll probably want to send a response buffer...
         },
 
         RenderThreadCommand::RenderFrame(fence) => {
-            render_system.render();
-            fence.signal(); // let the main thread know we have finished this frame.
+            let result = render_system.render();
+            fence.signal(); // let the main thread know we have finished this frame, even if it failed.
 
-            return Some(RenderThreadResponse::RenderFrameDone);
+            return match result {
+                Ok(_)      => Some(RenderThreadResponse::RenderFrameDone(render_system.get_culling_stats())),
+                Err(error) => Some(RenderThreadResponse::RendererError(error)),
+            };
         },
 
         RenderThreadCommand::Resize(width, height) => {
@@ -107,7 +117,9 @@ fn process_render_command(render_system: &mut RenderSystem, command: RenderThrea
 // but it is not a real concern for this, so I am going to, uh, sneak this in there.
 unsafe impl Send for RendererCreateInfo {}
 
-pub fn create_render_thread(create_info: RendererCreateInfo) -> RenderThread {
+/// Spawns the render thread and blocks until the RenderSystem has been created on it. If the renderer fails to
+/// initialize, the thread exits and the error is returned.
+pub fn create_render_thread(create_info: RendererCreateInfo) -> Result<RenderThread, RenderError> {
     let (main_thread_sender,   main_thread_reciever)   = mpsc::channel();
     let (render_thread_sender, render_thread_reciever) = mpsc::channel();
 
@@ -117,7 +129,17 @@ pub fn create_render_thread(create_info: RendererCreateInfo) -> RenderThread {
         let local_reciever: mpsc::Receiver<RenderThreadCommand> = render_thread_reciever;
 
         // the render thread will own the render system.
-        let mut render_system = RenderSystem::new(create_info);
+        let mut render_system = match RenderSystem::new(create_info) {
+            Ok(system) => system,
+            Err(error) => {
+                let _ = local_sender.send(RenderThreadResponse::RendererError(error));
+                return;
+            }
+        };
+
+        if local_sender.send(RenderThreadResponse::RendererReady).is_err() {
+            return; // the main thread has gone away, nothing left to render for.
+        }
 
         'thread_loop: loop {
             if let Ok(msg) = local_reciever.recv() {
@@ -127,7 +149,7 @@ pub fn create_render_thread(create_info: RendererCreateInfo) -> RenderThread {
 
                     match local_sender.send(response) {
                         Ok(_)  => {},
-                        Err(e) => println!("Failed to send a message to the main thread from the render thread."),
+                        Err(_) => println!("Failed to send a message to the main thread from the render thread, it has disconnected."),
                     }
 
                     if is_shutdown {
@@ -138,7 +160,17 @@ pub fn create_render_thread(create_info: RendererCreateInfo) -> RenderThread {
         }
     });
 
-    RenderThread{
+    // Wait for the renderer to finish initializing
+    match main_thread_reciever.recv() {
+        Ok(RenderThreadResponse::RendererReady)        => {},
+        Ok(RenderThreadResponse::RendererError(error)) => {
+            let _ = thread_handler.join();
+            return Err(error);
+        },
+        _ => return Err(RenderError::RenderThreadDisconnected),
+    }
+
+    Ok(RenderThread{
         handle:              thread_handler,
         render_thread_queue: render_thread_sender,
         main_thread_queue:   main_thread_reciever,
@@ -150,38 +182,33 @@ pub fn create_render_thread(create_info: RendererCreateInfo) -> RenderThread {
             ],
             fence_index: 0,
         }),
-    }
+    })
 }
 
 impl RenderThread {
-    fn send_message(&self, cmd: RenderThreadCommand) {
+    fn send_message(&self, cmd: RenderThreadCommand) -> Result<(), RenderError> {
         match self.render_thread_queue.send(cmd) {
-            Ok(_) => {},
-            Err(e) => {
-                panic!("Failed to send a message to the render thread: {:?}", e);
+            Ok(_) => return Ok(()),
+            Err(_) => {
+                println!("Failed to send a message to the render thread, it has disconnected.");
+                return Err(RenderError::RenderThreadDisconnected);
             },
         }
     }
 
-    pub fn recieve_message(&self, blocking: bool) -> Option<RenderThreadResponse> {
+    pub fn recieve_message(&self, blocking: bool) -> Result<Option<RenderThreadResponse>, RenderError> {
         if blocking {
             match self.main_thread_queue.recv() {
-                Ok(msg) => return Some(msg),
-                Err(e)  => panic!("Failed to recv a message from the render thread: {:?}", e),
+                Ok(msg) => return Ok(Some(msg)),
+                Err(_)  => return Err(RenderError::RenderThreadDisconnected),
             }
         } else {
             match self.main_thread_queue.try_recv() {
-                Ok(msg) => return Some(msg),
-                Err(e)  => {
-                    match e {
-                        TryRecvError::Empty        => return None,
-                        TryRecvError::Disconnected => panic!("Failed to recv a message from the render thread: {:?}", e),
-                    }
-                },
+                Ok(msg)                         => return Ok(Some(msg)),
+                Err(TryRecvError::Empty)        => return Ok(None),
+                Err(TryRecvError::Disconnected) => return Err(RenderError::RenderThreadDisconnected),
             }
         }
-
-        None
     }
 
     fn get_fence(&self) -> Arc<ThreadFence> {
@@ -201,31 +228,33 @@ impl RenderThread {
         return fence;
     }
 
-    pub fn submit_command_buffer(&self, cmd_buffer: RenderCommandBuffer) {
-        self.send_message(RenderThreadCommand::SubmitCommandList(RtcSubmitCommandList { cmd_buffer }));
+    pub fn submit_command_buffer(&self, cmd_buffer: RenderCommandBuffer) -> Result<(), RenderError> {
+        return self.send_message(RenderThreadCommand::SubmitCommandList(RtcSubmitCommandList { cmd_buffer }));
     }
 
     // this will block the calling thread until the frame is ready to be rendered
     //   todo: determine if I want to hide this from the caller.
-    pub fn render_frame(&self, frame: usize) {
+    pub fn render_frame(&self, frame: usize) -> Result<(), RenderError> {
         let fence = self.acquire_frame();
-        self.send_message(RenderThreadCommand::RenderFrame(fence));
+        return self.send_message(RenderThreadCommand::RenderFrame(fence));
     }
 
-    pub fn on_resize(&self, width: u32, height: u32) {
-        self.send_message(RenderThreadCommand::Resize(width, height));
+    pub fn on_resize(&self, width: u32, height: u32) -> Result<(), RenderError> {
+        return self.send_message(RenderThreadCommand::Resize(width, height));
     }
 
-    pub fn destroy(&self) {
-        self.send_message(RenderThreadCommand::DestroyRenderer);
+    pub fn destroy(&self) -> Result<(), RenderError> {
+        self.send_message(RenderThreadCommand::DestroyRenderer)?;
 
         'msg_loop: loop {
-            // block until the renderer has fully shutdown
-            if let Some(response) = self.recieve_message(true) {
+            // block until the renderer has fully shutdown, any errors reported before shutdown are dropped.
+            if let Some(response) = self.recieve_message(true)? {
                 if response == RenderThreadResponse::RendererShutdown {
                     break 'msg_loop;
                 }
             }
         }
+
+        return Ok(());
     }
 }
@@ -0,0 +1,137 @@
+use crate::math::{ float3::*, float4x4::* };
+
+use super::culling::{ is_box_in_frustum, is_sphere_in_frustum, transform_bounding_sphere };
+use super::material_system::GpuMaterial;
+use super::mesh::{ GpuMeshBuffers, GpuInstancedMesh, GpuSkinnedMesh };
+
+use vendor::vulkan::*;
+
+//
+// Transparency
+//
+// Meshes with an AlphaMode::Blend material skip the geometry pass. They are drawn in the transparent pass, after the
+// skybox and before the particles, into the resolved scene and tested against the resolved depth without writing to
+// it. The pass has one of two modes:
+//   1. Sorted          - meshes are sorted back to front by the view distance of their bounds and alpha blended over
+//                        the scene. Sorting is per mesh: the triangles of a mesh, and the instances of an instanced
+//                        mesh, are drawn in the order they were given, so intersecting or concave transparent meshes
+//                        can blend in the wrong order.
+//   2. WeightedBlended - order-independent (McGuire and Bavoil, "Weighted Blended Order-Independent Transparency").
+//                        Every surface adds its premultiplied color, weighted by its depth and alpha, to an
+//                        accumulation target and multiplies its transparency into a revealage target. A compute pass
+//                        (oit_composite.comp) then blends the weighted average color over the scene. No sorting, but
+//                        the result only approximates the correct order, most visibly with several opaque-ish layers.
+//
+// Like the particles, transparent meshes are drawn at one sample per pixel even when the geometry pass uses MSAA.
+// They still cast shadows as if they were opaque.
+//
+
+// Must match oit_composite.comp
+pub(crate) const OIT_COMPOSITE_GROUP_SIZE: u32 = 16;
+
+pub(crate) const OIT_ACCUM_FORMAT:     VkFormat = VK_FORMAT_R16G16B16A16_SFLOAT;
+pub(crate) const OIT_REVEALAGE_FORMAT: VkFormat = VK_FORMAT_R16_SFLOAT;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TransparencyMode {
+    Sorted,          // back to front, alpha blended
+    WeightedBlended, // order independent, approximate
+}
+
+/// How meshes with blended materials are drawn. Can be changed at runtime with
+/// RenderCommand::UpdateTransparencySettings.
+#[derive(Clone, Copy, Debug)]
+pub struct TransparencySettings {
+    pub mode: TransparencyMode,
+}
+
+impl Default for TransparencySettings {
+    fn default() -> Self {
+        Self{
+            mode: TransparencyMode::Sorted,
+        }
+    }
+}
+
+/// A mesh of the transparent pass, with everything needed to record its draw.
+pub(crate) struct TransparentDraw {
+    pub mesh:            GpuMeshBuffers,
+    pub instance_buffer: VkDeviceAddress,
+    pub instance_count:  u32,
+    pub skin_buffer:     VkDeviceAddress, // 0 for meshes that aren't skinned
+    pub joint_buffer:    VkDeviceAddress,
+    pub view_distance:   f32,             // from the camera to the center of the mesh's world-space bounds
+}
+
+impl TransparentDraw {
+    pub fn is_skinned(&self) -> bool {
+        return self.skin_buffer != 0;
+    }
+}
+
+/// Gathers the visible meshes with blended materials, sorted back to front. Regular and instanced meshes are frustum
+/// culled like the CPU draw list, skinned meshes are always drawn since their bounds change with the pose.
+pub(crate) fn build_transparent_draws(
+    view_proj:         Float4x4,
+    camera_pos:        Float3,
+    materials:         &[GpuMaterial],
+    meshes:            &[GpuMeshBuffers],
+    instanced_meshes:  &[GpuInstancedMesh],
+    skinned_meshes:    &[GpuSkinnedMesh],
+    default_instances: VkDeviceAddress,
+) -> Vec<TransparentDraw> {
+    let planes      = view_proj.get_frustum_planes();
+    let is_blended  = |mesh: &GpuMeshBuffers| -> bool { materials[mesh.material_index].is_blended() };
+    let distance_to = |center: Float3| -> f32 { (center - camera_pos).length() };
+
+    let mut draws = Vec::<TransparentDraw>::new();
+
+    for mesh in meshes.iter().filter(|mesh| is_blended(mesh)) {
+        let sphere = transform_bounding_sphere(mesh.transform, mesh.bounds);
+        if !is_sphere_in_frustum(&planes, sphere) || !is_box_in_frustum(&planes, &mesh.aabb.transform(mesh.transform)) {
+            continue;
+        }
+
+        draws.push(TransparentDraw{
+            mesh:            *mesh,
+            instance_buffer: default_instances,
+            instance_count:  1,
+            skin_buffer:     0,
+            joint_buffer:    0,
+            view_distance:   distance_to(Float3::new(sphere.x, sphere.y, sphere.z)),
+        });
+    }
+
+    for instanced in instanced_meshes.iter().filter(|instanced| is_blended(&instanced.mesh)) {
+        if instanced.instance_count == 0 || !is_box_in_frustum(&planes, &instanced.instance_bounds) {
+            continue;
+        }
+
+        draws.push(TransparentDraw{
+            mesh:            instanced.mesh,
+            instance_buffer: instanced.instance_buffer_address,
+            instance_count:  instanced.instance_count,
+            skin_buffer:     0,
+            joint_buffer:    0,
+            view_distance:   distance_to(instanced.instance_bounds.get_center()),
+        });
+    }
+
+    for skinned in skinned_meshes.iter().filter(|skinned| is_blended(&skinned.mesh)) {
+        let sphere = transform_bounding_sphere(skinned.mesh.transform, skinned.mesh.bounds);
+
+        draws.push(TransparentDraw{
+            mesh:            skinned.mesh,
+            instance_buffer: default_instances,
+            instance_count:  1,
+            skin_buffer:     skinned.skin_buffer_address,
+            joint_buffer:    skinned.joint_buffer_address,
+            view_distance:   distance_to(Float3::new(sphere.x, sphere.y, sphere.z)),
+        });
+    }
+
+    // Farthest first
+    draws.sort_by(|a, b| b.view_distance.total_cmp(&a.view_distance));
+
+    return draws;
+}
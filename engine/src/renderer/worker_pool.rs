@@ -0,0 +1,195 @@
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::thread;
+
+//
+// Worker Pool
+//
+// A fixed set of threads, spawned once with the renderer, that run jobs handed to them by the render thread. Jobs
+// may borrow from the caller: WorkerPool::run doesn't return until every job it was given has finished or been
+// dropped, the same guarantee std::thread::scope gives, without creating and joining threads every frame.
+//
+// A job that panics doesn't take its worker down. The panic is caught on the worker and returned to the caller.
+//
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+struct Worker {
+    sender: Option<mpsc::Sender<Job>>, // taken on drop, which tells the worker to exit
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+pub struct WorkerPool {
+    workers: Vec<Worker>,
+}
+
+/// The job panicked, or its worker was gone before the job could run. Holds the panic message, if there was one.
+#[derive(Debug)]
+pub struct JobFailed(pub String);
+
+fn get_panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        return String::from(*message);
+    }
+
+    if let Some(message) = payload.downcast_ref::<String>() {
+        return message.clone();
+    }
+
+    return String::from("unknown panic");
+}
+
+impl WorkerPool {
+    pub fn new(name: &str, worker_count: usize) -> std::io::Result<WorkerPool> {
+        let mut workers = Vec::<Worker>::with_capacity(worker_count);
+
+        for worker_index in 0..worker_count {
+            let (sender, receiver) = mpsc::channel::<Job>();
+
+            let handle = thread::Builder::new()
+                .name(format!("{} {}", name, worker_index))
+                .spawn(move || {
+                    while let Ok(job) = receiver.recv() {
+                        job();
+                    }
+                })?;
+
+            workers.push(Worker{ sender: Some(sender), handle: Some(handle) });
+        }
+
+        return Ok(WorkerPool{ workers });
+    }
+
+    pub fn get_worker_count(&self) -> usize {
+        return self.workers.len();
+    }
+
+    /// Runs job `i` on worker `i % worker_count` and waits for all of them. A pool without workers runs the jobs on
+    /// the calling thread instead. Returns the result of each job, in the order the jobs were given.
+    pub fn run<'scope, R>(&self, jobs: Vec<Box<dyn FnOnce() -> R + Send + 'scope>>) -> Vec<Result<R, JobFailed>>
+        where R: Send + 'static
+    {
+        let job_count = jobs.len();
+        let (done_sender, done_receiver) = mpsc::channel::<(usize, Result<R, JobFailed>)>();
+
+        for (job_index, job) in jobs.into_iter().enumerate() {
+            let done_sender = done_sender.clone();
+
+            let wrapped: Box<dyn FnOnce() + Send + 'scope> = Box::new(move || {
+                // The job is consumed by the call, so everything it borrowed is released before the result is sent.
+                let result = panic::catch_unwind(AssertUnwindSafe(job))
+                    .map_err(|payload| JobFailed(get_panic_message(payload.as_ref())));
+
+                let _ = done_sender.send((job_index, result));
+            });
+
+            if self.workers.is_empty() {
+                wrapped();
+                continue;
+            }
+
+            // SAFETY: the job only outlives 'scope if this function returns before the job has finished. The loop
+            // below waits for every job to report back, and only stops early once every job has been dropped,
+            // which also means none of them are running.
+            let wrapped: Job = unsafe { std::mem::transmute::<Box<dyn FnOnce() + Send + 'scope>, Job>(wrapped) };
+
+            let worker = &self.workers[job_index % self.workers.len()];
+            if let Some(sender) = &worker.sender {
+                // A worker that has gone away hands the job back, which drops it here.
+                let _ = sender.send(wrapped);
+            }
+        }
+
+        // Only the jobs hold a sender now, so the channel disconnects once they have all finished or been dropped.
+        drop(done_sender);
+
+        let mut results: Vec<Option<Result<R, JobFailed>>> = (0..job_count).map(|_| None).collect();
+        while let Ok((job_index, result)) = done_receiver.recv() {
+            results[job_index] = Some(result);
+        }
+
+        return results.into_iter()
+            .map(|result| result.unwrap_or_else(|| Err(JobFailed(String::from("the worker exited before running the job")))))
+            .collect();
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        // Disconnect every worker first, so they all exit in parallel
+        for worker in &mut self.workers {
+            worker.sender = None;
+        }
+
+        for worker in &mut self.workers {
+            if let Some(handle) = worker.handle.take() {
+                let _ = handle.join();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jobs_can_borrow_and_return_in_order() {
+        let pool = WorkerPool::new("test worker", 3).unwrap();
+
+        let mut ranges = vec![0u32; 8];
+        let values: Vec<u32> = (0..64).collect();
+
+        let jobs: Vec<Box<dyn FnOnce() -> u32 + Send + '_>> = ranges.iter_mut().enumerate()
+            .map(|(index, sum)| {
+                let values = &values;
+                Box::new(move || {
+                    *sum = values[index * 8..(index + 1) * 8].iter().sum();
+                    return index as u32;
+                }) as Box<dyn FnOnce() -> u32 + Send + '_>
+            })
+            .collect();
+
+        // More jobs than workers, each worker runs several
+        let results: Vec<u32> = pool.run(jobs).into_iter().map(|result| result.unwrap()).collect();
+        assert_eq!(results, (0..8).collect::<Vec<u32>>());
+        assert_eq!(ranges.iter().sum::<u32>(), values.iter().sum::<u32>());
+        assert_eq!(ranges[1], (8..16).sum::<u32>());
+    }
+
+    #[test]
+    fn a_panicking_job_is_reported_and_the_worker_survives() {
+        let pool = WorkerPool::new("test worker", 1).unwrap();
+
+        let jobs: Vec<Box<dyn FnOnce() -> u32 + Send>> = vec![
+            Box::new(|| 1),
+            Box::new(|| panic!("recording failed")),
+            Box::new(|| 3),
+        ];
+
+        let results = pool.run(jobs);
+        assert_eq!(results[0].as_ref().unwrap(), &1);
+        assert_eq!(results[1].as_ref().unwrap_err().0, "recording failed");
+        assert_eq!(results[2].as_ref().unwrap(), &3);
+
+        let jobs: Vec<Box<dyn FnOnce() -> u32 + Send>> = vec![Box::new(|| 4)];
+        assert_eq!(pool.run(jobs)[0].as_ref().unwrap(), &4);
+    }
+
+    #[test]
+    fn a_pool_without_workers_runs_jobs_inline() {
+        let pool = WorkerPool::new("test worker", 0).unwrap();
+
+        let jobs: Vec<Box<dyn FnOnce() -> u32 + Send>> = vec![
+            Box::new(|| 1),
+            Box::new(|| panic!("recording failed")),
+            Box::new(|| 3),
+        ];
+
+        let results = pool.run(jobs);
+        assert_eq!(results[0].as_ref().unwrap(), &1);
+        assert_eq!(results[1].as_ref().unwrap_err().0, "recording failed");
+        assert_eq!(results[2].as_ref().unwrap(), &3);
+    }
+}